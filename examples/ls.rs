@@ -33,9 +33,10 @@ fn main() -> io::Result<()> {
     };
     for r in dir.iter() {
         let e = r?;
-        let modified = DateTime::<Local>::from(e.modified())
-            .format("%Y-%m-%d %H:%M:%S")
-            .to_string();
+        let modified = e.modification_time().map_or_else(
+            || "-".to_string(),
+            |t| DateTime::<Local>::from(t).format("%Y-%m-%d %H:%M:%S").to_string(),
+        );
         println!("{:4}  {}  {}", format_file_size(e.len()), modified, e.file_name());
     }
     Ok(())
@@ -0,0 +1,242 @@
+//! A tiny shell operating on a FAT filesystem image.
+//!
+//! Usage: `cargo run --example shell -- IMAGE [--script]`
+//!
+//! In the script mode commands are read from the standard input, echoed to the output and files are created with
+//! fixed timestamps so the output is deterministic.
+
+use std::env;
+use std::fs::OpenOptions;
+use std::io::{self, prelude::*};
+
+use fatfs::{Date, DateTime, DirEntry, FileSystem, FsOptions, OemCpConverter, StdIoWrapper, Time, TimeProvider};
+use fscommon::BufStream;
+
+const HELP: &str = "\
+commands:
+  ls [DIR]            list directory
+  cat FILE            print file contents
+  write FILE TEXT     write a line of text to a file
+  mkdir DIR           create directory
+  rm PATH             remove file or empty directory
+  mv SRC DST          move or rename file or directory
+  stat PATH           print file metadata
+  df                  print filesystem usage
+  help                print this help
+  exit                exit the shell
+";
+
+/// Time provider returning a fixed date and time (2020-01-01 12:00:00) used in the script mode.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FixedTimeProvider;
+
+impl TimeProvider for FixedTimeProvider {
+    fn get_current_date(&self) -> Date {
+        Date::new(2020, 1, 1)
+    }
+
+    fn get_current_date_time(&self) -> DateTime {
+        DateTime::new(Date::new(2020, 1, 1), Time::new(12, 0, 0, 0))
+    }
+}
+
+fn format_date(date: Date) -> String {
+    format!("{:04}-{:02}-{:02}", date.year, date.month, date.day)
+}
+
+fn format_date_time(date_time: DateTime) -> String {
+    let time = date_time.time;
+    format!(
+        "{} {:02}:{:02}:{:02}",
+        format_date(date_time.date),
+        time.hour,
+        time.min,
+        time.sec
+    )
+}
+
+fn format_opt<T>(value: Option<T>, format: fn(T) -> String) -> String {
+    value.map_or_else(|| "-".to_string(), format)
+}
+
+fn split_parent(path: &str) -> (&str, &str) {
+    let path = path.trim_matches('/');
+    path.rfind('/').map_or(("", path), |n| (&path[..n], &path[n + 1..]))
+}
+
+fn find_entry<'a, T, TP, OCC>(
+    fs: &'a FileSystem<StdIoWrapper<T>, TP, OCC>,
+    path: &str,
+) -> io::Result<DirEntry<'a, StdIoWrapper<T>, TP, OCC>>
+where
+    T: Read + Write + Seek,
+    TP: TimeProvider,
+    OCC: OemCpConverter,
+{
+    let (parent, name) = split_parent(path);
+    let dir = if parent.is_empty() {
+        fs.root_dir()
+    } else {
+        fs.root_dir().open_dir(parent)?
+    };
+    for r in dir.iter() {
+        let e = r?;
+        if e.file_name().eq_ignore_ascii_case(name) {
+            return Ok(e);
+        }
+    }
+    Err(io::Error::new(io::ErrorKind::NotFound, "No such file or directory"))
+}
+
+fn run_command<T, TP, OCC, W>(fs: &FileSystem<StdIoWrapper<T>, TP, OCC>, line: &str, out: &mut W) -> io::Result<()>
+where
+    T: Read + Write + Seek,
+    TP: TimeProvider,
+    OCC: OemCpConverter,
+    W: Write,
+{
+    let mut args = line.splitn(3, ' ').filter(|s| !s.is_empty());
+    let cmd = args.next().unwrap_or("");
+    let arg1 = args.next();
+    let arg2 = args.next();
+    let missing_arg = || io::Error::new(io::ErrorKind::InvalidInput, "missing argument");
+    let root_dir = fs.root_dir();
+    match cmd {
+        "ls" => {
+            let dir = match arg1 {
+                Some(path) => root_dir.open_dir(path)?,
+                None => root_dir,
+            };
+            for r in dir.iter() {
+                let e = r?;
+                let size = if e.is_dir() {
+                    "<DIR>".to_string()
+                } else {
+                    e.len().to_string()
+                };
+                writeln!(
+                    out,
+                    "{:>10}  {}  {}",
+                    size,
+                    format_opt(e.modification_time(), format_date_time),
+                    e.file_name()
+                )?;
+            }
+        }
+        "cat" => {
+            let mut file = root_dir.open_file(arg1.ok_or_else(missing_arg)?)?;
+            let mut buf = Vec::new();
+            file.read_to_end(&mut buf)?;
+            out.write_all(&buf)?;
+        }
+        "write" => {
+            let mut file = root_dir.create_file(arg1.ok_or_else(missing_arg)?)?;
+            file.truncate()?;
+            file.write_all(arg2.unwrap_or("").as_bytes())?;
+            file.write_all(b"\n")?;
+        }
+        "mkdir" => {
+            root_dir.create_dir(arg1.ok_or_else(missing_arg)?)?;
+        }
+        "rm" => {
+            root_dir.remove(arg1.ok_or_else(missing_arg)?)?;
+        }
+        "mv" => {
+            let src = arg1.ok_or_else(missing_arg)?;
+            let dst = arg2.ok_or_else(missing_arg)?;
+            root_dir.rename(src, &root_dir, dst)?;
+        }
+        "stat" => {
+            let e = find_entry(fs, arg1.ok_or_else(missing_arg)?)?;
+            writeln!(out, "name: {}", e.file_name())?;
+            writeln!(out, "short name: {}", e.short_file_name())?;
+            writeln!(out, "type: {}", if e.is_dir() { "directory" } else { "file" })?;
+            writeln!(out, "size: {}", e.len())?;
+            writeln!(out, "attributes: {:?}", e.attributes())?;
+            writeln!(out, "created: {}", format_opt(e.creation_time(), format_date_time))?;
+            writeln!(out, "modified: {}", format_opt(e.modification_time(), format_date_time))?;
+            writeln!(out, "accessed: {}", format_opt(e.access_date(), format_date))?;
+        }
+        "df" => {
+            let stats = fs.stats()?;
+            let cluster_size = u64::from(stats.cluster_size());
+            writeln!(out, "type: {:?}", fs.fat_type())?;
+            writeln!(out, "cluster size: {}", cluster_size)?;
+            writeln!(out, "total: {}", u64::from(stats.total_clusters()) * cluster_size)?;
+            writeln!(out, "free: {}", u64::from(stats.free_clusters()) * cluster_size)?;
+        }
+        "help" => out.write_all(HELP.as_bytes())?,
+        _ => writeln!(out, "unknown command: {}", cmd)?,
+    }
+    Ok(())
+}
+
+/// Executes commands read from `input` until the end of input or `exit` command.
+///
+/// In the script mode each command is echoed, otherwise a prompt is printed before reading a command.
+pub fn run<T, TP, OCC, R, W>(
+    fs: &FileSystem<StdIoWrapper<T>, TP, OCC>,
+    input: R,
+    out: &mut W,
+    script: bool,
+) -> io::Result<()>
+where
+    T: Read + Write + Seek,
+    TP: TimeProvider,
+    OCC: OemCpConverter,
+    R: BufRead,
+    W: Write,
+{
+    let mut lines = input.lines();
+    loop {
+        if !script {
+            write!(out, "> ")?;
+            out.flush()?;
+        }
+        let line = match lines.next() {
+            Some(line) => line?,
+            None => break,
+        };
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if script {
+            writeln!(out, "> {}", line)?;
+        }
+        if line == "exit" {
+            break;
+        }
+        if let Err(err) = run_command(fs, line, out) {
+            writeln!(out, "error: {}", err)?;
+        }
+    }
+    Ok(())
+}
+
+fn main() -> io::Result<()> {
+    let mut args = env::args().skip(1);
+    let image_path = match args.next() {
+        Some(path) => path,
+        None => {
+            eprintln!("usage: shell IMAGE [--script]");
+            std::process::exit(1);
+        }
+    };
+    let script = args.next().as_deref() == Some("--script");
+    let img_file = OpenOptions::new().read(true).write(true).open(image_path)?;
+    let buf_stream = BufStream::new(img_file);
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    if script {
+        let options = FsOptions::new().time_provider(FixedTimeProvider);
+        let fs = FileSystem::new(buf_stream, options)?;
+        run(&fs, stdin.lock(), &mut stdout.lock(), true)?;
+        fs.unmount()?;
+    } else {
+        let fs = FileSystem::new(buf_stream, FsOptions::new())?;
+        run(&fs, stdin.lock(), &mut stdout.lock(), false)?;
+        fs.unmount()?;
+    }
+    Ok(())
+}
@@ -214,6 +214,14 @@ impl BiosParameterBlock {
                 self.fats
             );
         }
+        if self.active_fat() >= u16::from(self.fats) {
+            error!(
+                "invalid active FAT in BPB: {} (number of FATs: {})",
+                self.active_fat(),
+                self.fats
+            );
+            return Err(Error::CorruptedFileSystem);
+        }
         Ok(())
     }
 
@@ -383,6 +391,12 @@ impl BiosParameterBlock {
         data_sectors / u32::from(self.sectors_per_cluster)
     }
 
+    /// Returns the storage length needed to mount the filesystem: the reserved region, FATs, the root directory and
+    /// the first data cluster.
+    pub(crate) fn min_storage_len(&self) -> u64 {
+        self.bytes_from_sectors(self.first_data_sector()) + u64::from(self.cluster_size())
+    }
+
     pub(crate) fn bytes_from_sectors(&self, sectors: u32) -> u64 {
         // Note: total number of sectors is a 32 bit number so offsets have to be 64 bit
         u64::from(sectors) * u64::from(self.bytes_per_sector)
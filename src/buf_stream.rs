@@ -0,0 +1,273 @@
+#[cfg(all(not(feature = "std"), feature = "alloc"))]
+use alloc::vec;
+#[cfg(all(not(feature = "std"), feature = "alloc"))]
+use alloc::vec::Vec;
+use core::cmp;
+use core::convert::TryFrom;
+
+use crate::error::IoError;
+use crate::fs::ReadWriteSeek;
+use crate::io::{IoBase, Read, Seek, SeekFrom, Write};
+
+/// Default buffer size used by `BufStream::new`.
+#[cfg(feature = "alloc")]
+pub const DEFAULT_BUF_STREAM_CAPACITY: usize = 512;
+
+/// A stream caching one block of an underlying stream.
+///
+/// The block has the size of the buffer (e.g. one or more sectors) and starts at an offset being a multiple of
+/// the buffer size, so every access to the underlying stream is block aligned. Reads and writes within the cached
+/// block do not access the underlying stream. Modified data is written back when another block is loaded, on
+/// `flush` and when the stream is dropped. Seeks within the cached block do not access the underlying stream
+/// either. Reads and writes of at least the buffer size bypass the buffer.
+///
+/// The buffer can be provided by the caller (e.g. a mutable slice in `no_std` environments without allocator):
+///
+/// ```
+/// # fn main() -> Result<(), fatfs::Error<std::io::Error>> {
+/// # let img = std::fs::read("resources/fat16.img")?;
+/// # let storage = fatfs::StdIoWrapper::new(std::io::Cursor::new(img));
+/// let mut buf = [0_u8; 2048];
+/// let stream = fatfs::BufStream::with_buffer(storage, &mut buf[..]);
+/// let fs = fatfs::FileSystem::new(stream, fatfs::FsOptions::new())?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct BufStream<T: ReadWriteSeek, B: AsMut<[u8]>> {
+    // taken by `into_inner` so the destructor does not write the buffer back again
+    inner: Option<T>,
+    buf: B,
+    capacity: usize,
+    // offset of the cached block in the underlying stream
+    block_start: Option<u64>,
+    // number of valid bytes in the buffer - it is smaller than the buffer if the block is at the end of the stream
+    block_len: usize,
+    dirty: bool,
+    pos: u64,
+}
+
+#[cfg(feature = "alloc")]
+impl<T: ReadWriteSeek> BufStream<T, Vec<u8>> {
+    /// Creates a stream caching `inner` using an allocated buffer of `DEFAULT_BUF_STREAM_CAPACITY` bytes.
+    pub fn new(inner: T) -> Self {
+        Self::with_capacity(inner, DEFAULT_BUF_STREAM_CAPACITY)
+    }
+
+    /// Creates a stream caching `inner` using an allocated buffer of `capacity` bytes.
+    ///
+    /// # Panics
+    ///
+    /// Will panic if `capacity` is zero.
+    pub fn with_capacity(inner: T, capacity: usize) -> Self {
+        Self::with_buffer(inner, vec![0; capacity])
+    }
+}
+
+impl<T: ReadWriteSeek, B: AsMut<[u8]>> BufStream<T, B> {
+    /// Creates a stream caching `inner` using a caller-provided buffer.
+    ///
+    /// The size of `buf` is the size of the cached block. The initial position is zero regardless of the position
+    /// of `inner`.
+    ///
+    /// # Panics
+    ///
+    /// Will panic if `buf` is empty.
+    pub fn with_buffer(inner: T, mut buf: B) -> Self {
+        let capacity = buf.as_mut().len();
+        assert!(capacity > 0, "buffer cannot be empty");
+        Self {
+            inner: Some(inner),
+            buf,
+            capacity,
+            block_start: None,
+            block_len: 0,
+            dirty: false,
+            pos: 0,
+        }
+    }
+
+    /// Returns the size of the buffer.
+    #[must_use]
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Writes back modified data and returns the underlying stream.
+    ///
+    /// # Errors
+    ///
+    /// Errors returned by the underlying stream are passed through. The modified data is lost in that case.
+    pub fn into_inner(mut self) -> Result<T, T::Error> {
+        self.flush_buf()?;
+        Ok(self.take_inner())
+    }
+
+    fn inner(&mut self) -> &mut T {
+        expect_inner(&mut self.inner)
+    }
+
+    fn take_inner(&mut self) -> T {
+        self.inner.take().expect("inner stream is only taken by into_inner")
+    }
+
+    fn block_of(&self, pos: u64) -> u64 {
+        pos - pos % self.capacity as u64
+    }
+
+    /// Returns the end of the cached data.
+    fn buffered_end(&self) -> Option<u64> {
+        self.block_start.map(|start| start + self.block_len as u64)
+    }
+
+    /// Writes the cached block back if it has been modified.
+    fn flush_buf(&mut self) -> Result<(), T::Error> {
+        // there is nothing to write back after `into_inner`
+        if let (true, Some(start), Some(inner)) = (self.dirty, self.block_start, self.inner.as_mut()) {
+            trace!("BufStream: writing back block {}", start);
+            inner.seek(SeekFrom::Start(start))?;
+            inner.write_all(&self.buf.as_mut()[..self.block_len])?;
+            self.dirty = false;
+        }
+        Ok(())
+    }
+
+    /// Writes the cached block back if needed and loads the block starting at `start`.
+    fn load_block(&mut self, start: u64) -> Result<(), T::Error> {
+        self.flush_buf()?;
+        self.block_start = None;
+        trace!("BufStream: loading block {}", start);
+        let inner = expect_inner(&mut self.inner);
+        inner.seek(SeekFrom::Start(start))?;
+        let buf = self.buf.as_mut();
+        let mut len = 0;
+        while len < buf.len() {
+            match inner.read(&mut buf[len..]) {
+                Ok(0) => break,
+                Ok(n) => len += n,
+                Err(ref e) if e.is_interrupted() => {}
+                Err(e) => return Err(e),
+            }
+        }
+        self.block_start = Some(start);
+        self.block_len = len;
+        Ok(())
+    }
+}
+
+fn expect_inner<T>(inner: &mut Option<T>) -> &mut T {
+    inner.as_mut().expect("inner stream is only taken by into_inner")
+}
+
+impl<T: ReadWriteSeek, B: AsMut<[u8]>> IoBase for BufStream<T, B> {
+    type Error = T::Error;
+}
+
+impl<T: ReadWriteSeek, B: AsMut<[u8]>> Read for BufStream<T, B> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        if buf.len() >= self.capacity {
+            // the read may cover the cached block so it must be written back first
+            self.flush_buf()?;
+            let pos = self.pos;
+            self.inner().seek(SeekFrom::Start(pos))?;
+            let n = self.inner().read(buf)?;
+            self.pos += n as u64;
+            return Ok(n);
+        }
+        let block = self.block_of(self.pos);
+        if self.block_start != Some(block) {
+            self.load_block(block)?;
+        }
+        let offset = (self.pos - block) as usize;
+        if offset >= self.block_len {
+            return Ok(0);
+        }
+        let n = cmp::min(buf.len(), self.block_len - offset);
+        buf[..n].copy_from_slice(&self.buf.as_mut()[offset..offset + n]);
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl<T: ReadWriteSeek, B: AsMut<[u8]>> Write for BufStream<T, B> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        if buf.len() >= self.capacity {
+            // the write may cover the cached block so it is dropped
+            self.flush_buf()?;
+            self.block_start = None;
+            let pos = self.pos;
+            self.inner().seek(SeekFrom::Start(pos))?;
+            let n = self.inner().write(buf)?;
+            self.pos += n as u64;
+            return Ok(n);
+        }
+        let block = self.block_of(self.pos);
+        if self.block_start != Some(block) {
+            self.load_block(block)?;
+        }
+        let offset = (self.pos - block) as usize;
+        let block_buf = self.buf.as_mut();
+        if offset > self.block_len {
+            // the stream is extended - fill the gap the same way as a write beyond the end of a file does
+            for b in &mut block_buf[self.block_len..offset] {
+                *b = 0;
+            }
+        }
+        let n = cmp::min(buf.len(), block_buf.len() - offset);
+        block_buf[offset..offset + n].copy_from_slice(&buf[..n]);
+        self.block_len = cmp::max(self.block_len, offset + n);
+        self.dirty = true;
+        self.pos += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        self.flush_buf()?;
+        self.inner().flush()
+    }
+}
+
+impl<T: ReadWriteSeek, B: AsMut<[u8]>> Seek for BufStream<T, B> {
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64, Self::Error> {
+        let buffered_end = self.buffered_end();
+        let new_pos_opt: Option<u64> = match pos {
+            SeekFrom::Current(x) => i64::try_from(self.pos)
+                .ok()
+                .and_then(|n| n.checked_add(x))
+                .and_then(|n| u64::try_from(n).ok()),
+            SeekFrom::Start(x) => Some(x),
+            SeekFrom::End(o) => {
+                // the cached block can extend the underlying stream
+                let len = cmp::max(self.inner().seek(SeekFrom::End(0))?, buffered_end.unwrap_or(0));
+                i64::try_from(len)
+                    .ok()
+                    .and_then(|len| len.checked_add(o))
+                    .and_then(|n| u64::try_from(n).ok())
+            }
+        };
+        let new_pos = new_pos_opt.ok_or_else(|| {
+            debug!("invalid seek {:?}", pos);
+            T::Error::new_invalid_input_error()
+        })?;
+        let is_buffered = matches!(buffered_end, Some(end) if new_pos <= end);
+        if !is_buffered {
+            // let the underlying stream validate the position
+            self.inner().seek(SeekFrom::Start(new_pos))?;
+        }
+        self.pos = new_pos;
+        Ok(new_pos)
+    }
+}
+
+impl<T: ReadWriteSeek, B: AsMut<[u8]>> Drop for BufStream<T, B> {
+    fn drop(&mut self) {
+        if let Err(err) = self.flush_buf() {
+            error!("failed to write back buffered data {:?}", err);
+        }
+    }
+}
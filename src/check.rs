@@ -0,0 +1,825 @@
+#[cfg(not(feature = "std"))]
+use alloc::collections::{BTreeMap, BTreeSet};
+#[cfg(not(feature = "std"))]
+use alloc::string::String;
+#[cfg(not(feature = "std"))]
+use alloc::vec;
+#[cfg(not(feature = "std"))]
+use alloc::vec::Vec;
+
+#[cfg(feature = "std")]
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::dir::{Dir, DirIter};
+use crate::dir_entry::{char_to_uppercase, DirEntry};
+use crate::error::Error;
+use crate::fs::{FatType, FileSystem, OemCpConverter, ReadWriteSeek};
+use crate::io::{Read, ReadLeExt, Write, WriteLeExt};
+use crate::table::{FatValue, RESERVED_FAT_ENTRIES};
+use crate::time::TimeProvider;
+
+const STATE_MAGIC: u32 = 0x4B43_4846; // "FHCK"
+const STATE_VERSION: u32 = 1;
+
+/// A problem found by the filesystem consistency check.
+///
+/// Paths are relative to the root directory. The root directory itself has an empty path.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum CheckFinding {
+    /// Cluster is used by more than one file or directory or a cluster chain contains a loop. `path` is the
+    /// entry whose chain was found to reuse the cluster.
+    CrossLinkedCluster { path: String, cluster: u32 },
+    /// Cluster chain of the entry contains a free, bad or out of range cluster. `cluster` is the last valid
+    /// cluster of the chain (0 if the first cluster is invalid or a directory has no cluster).
+    BrokenChain { path: String, cluster: u32 },
+    /// File size does not match the number of clusters in its chain.
+    SizeMismatch { path: String, size: u32, clusters: u32 },
+    /// A run of clusters marked as used in the FAT but not referenced by any file or directory.
+    LostClusters { first_cluster: u32, count: u32 },
+    /// Entry has the same long or short name as an earlier entry in the same directory so lookups by that name
+    /// return the earlier entry. `entry_pos` identifies the shadowed entry - see `remove_entry_at` method on `Dir`.
+    DuplicateName { path: String, entry_pos: u64 },
+}
+
+impl CheckFinding {
+    fn serialize<W: Write>(&self, wrt: &mut W) -> Result<(), W::Error> {
+        match self {
+            CheckFinding::CrossLinkedCluster { path, cluster } => {
+                wrt.write_u8(0)?;
+                write_str(wrt, path)?;
+                wrt.write_u32_le(*cluster)
+            }
+            CheckFinding::BrokenChain { path, cluster } => {
+                wrt.write_u8(1)?;
+                write_str(wrt, path)?;
+                wrt.write_u32_le(*cluster)
+            }
+            CheckFinding::SizeMismatch { path, size, clusters } => {
+                wrt.write_u8(2)?;
+                write_str(wrt, path)?;
+                wrt.write_u32_le(*size)?;
+                wrt.write_u32_le(*clusters)
+            }
+            CheckFinding::LostClusters { first_cluster, count } => {
+                wrt.write_u8(3)?;
+                wrt.write_u32_le(*first_cluster)?;
+                wrt.write_u32_le(*count)
+            }
+            CheckFinding::DuplicateName { path, entry_pos } => {
+                wrt.write_u8(4)?;
+                write_str(wrt, path)?;
+                write_u64(wrt, *entry_pos)
+            }
+        }
+    }
+
+    fn deserialize<R: Read>(rdr: &mut R) -> Result<Self, Error<R::Error>> {
+        Ok(match rdr.read_u8()? {
+            0 => CheckFinding::CrossLinkedCluster {
+                path: read_str(rdr)?,
+                cluster: rdr.read_u32_le()?,
+            },
+            1 => CheckFinding::BrokenChain {
+                path: read_str(rdr)?,
+                cluster: rdr.read_u32_le()?,
+            },
+            2 => CheckFinding::SizeMismatch {
+                path: read_str(rdr)?,
+                size: rdr.read_u32_le()?,
+                clusters: rdr.read_u32_le()?,
+            },
+            3 => CheckFinding::LostClusters {
+                first_cluster: rdr.read_u32_le()?,
+                count: rdr.read_u32_le()?,
+            },
+            4 => CheckFinding::DuplicateName {
+                path: read_str(rdr)?,
+                entry_pos: read_u64(rdr)?,
+            },
+            _ => return Err(Error::InvalidInput),
+        })
+    }
+}
+
+/// A result of the filesystem consistency check.
+///
+/// It is returned by the `check` method on `FileSystem` (or the last call of `check_incremental`).
+#[derive(Clone, Eq, PartialEq, Debug, Default)]
+pub struct CheckReport {
+    findings: Vec<CheckFinding>,
+    files: u64,
+    dirs: u64,
+}
+
+impl CheckReport {
+    /// Problems found in the order of detection.
+    #[must_use]
+    pub fn findings(&self) -> &[CheckFinding] {
+        &self.findings
+    }
+
+    /// Returns true if no problems were found.
+    #[must_use]
+    pub fn is_clean(&self) -> bool {
+        self.findings.is_empty()
+    }
+
+    /// Number of checked files
+    #[must_use]
+    pub fn files(&self) -> u64 {
+        self.files
+    }
+
+    /// Number of checked directories excluding the root directory
+    #[must_use]
+    pub fn dirs(&self) -> u64 {
+        self.dirs
+    }
+}
+
+/// Limits of the work done by a single `check_incremental` call.
+///
+/// At least one unit of work is done by every call so the check always makes progress.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct CheckBudget {
+    max_entries: u32,
+    max_clusters: u32,
+}
+
+impl CheckBudget {
+    /// Creates a budget without limits.
+    #[must_use]
+    pub fn unlimited() -> Self {
+        Self {
+            max_entries: u32::MAX,
+            max_clusters: u32::MAX,
+        }
+    }
+
+    /// Sets the maximal number of directory entries read by a call.
+    #[must_use]
+    pub fn max_entries(mut self, max_entries: u32) -> Self {
+        self.max_entries = max_entries;
+        self
+    }
+
+    /// Sets the maximal number of clusters processed by a call.
+    ///
+    /// A cluster is processed when it is visited while following a cluster chain and when its FAT entry is checked
+    /// while looking for lost clusters.
+    #[must_use]
+    pub fn max_clusters(mut self, max_clusters: u32) -> Self {
+        self.max_clusters = max_clusters;
+        self
+    }
+
+    fn is_exhausted(self) -> bool {
+        self.max_entries == 0 || self.max_clusters == 0
+    }
+}
+
+impl Default for CheckBudget {
+    fn default() -> Self {
+        Self::unlimited()
+    }
+}
+
+/// A progress of the incremental filesystem check.
+#[derive(Clone, PartialEq, Debug)]
+pub enum CheckProgress {
+    /// Check is not finished. `fraction` is an estimate of the completed part in range 0 to 1.
+    InProgress { fraction: f32 },
+    /// Check is finished.
+    Done(CheckReport),
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+enum CheckPhase {
+    Start,
+    Directories,
+    Fat,
+    Done,
+}
+
+impl CheckPhase {
+    fn to_u8(self) -> u8 {
+        match self {
+            CheckPhase::Start => 0,
+            CheckPhase::Directories => 1,
+            CheckPhase::Fat => 2,
+            CheckPhase::Done => 3,
+        }
+    }
+
+    fn from_u8(n: u8) -> Option<Self> {
+        match n {
+            0 => Some(CheckPhase::Start),
+            1 => Some(CheckPhase::Directories),
+            2 => Some(CheckPhase::Fat),
+            3 => Some(CheckPhase::Done),
+            _ => None,
+        }
+    }
+}
+
+/// A directory waiting for its entries to be checked.
+#[derive(Clone, Eq, PartialEq, Debug)]
+struct PendingDir {
+    // None for the root directory
+    cluster: Option<u32>,
+    path: String,
+    // offset of the next entry to read
+    offset: u32,
+}
+
+/// A cluster chain being followed.
+#[derive(Clone, Eq, PartialEq, Debug)]
+struct ChainCursor {
+    path: String,
+    first_cluster: u32,
+    // next cluster to visit
+    cluster: u32,
+    clusters: u32,
+    // None for directories
+    size: Option<u32>,
+}
+
+impl ChainCursor {
+    fn serialize<W: Write>(&self, wrt: &mut W) -> Result<(), W::Error> {
+        write_str(wrt, &self.path)?;
+        wrt.write_u32_le(self.first_cluster)?;
+        wrt.write_u32_le(self.cluster)?;
+        wrt.write_u32_le(self.clusters)?;
+        // directories have no size - u32::MAX is not a valid file size
+        wrt.write_u32_le(self.size.unwrap_or(u32::MAX))
+    }
+
+    fn deserialize<R: Read>(rdr: &mut R) -> Result<Self, Error<R::Error>> {
+        Ok(Self {
+            path: read_str(rdr)?,
+            first_cluster: rdr.read_u32_le()?,
+            cluster: rdr.read_u32_le()?,
+            clusters: rdr.read_u32_le()?,
+            size: match rdr.read_u32_le()? {
+                u32::MAX => None,
+                n => Some(n),
+            },
+        })
+    }
+}
+
+/// A resumable state of the incremental filesystem check.
+///
+/// A new state is created by `CheckState::new` and passed to the `check_incremental` method on `FileSystem` until
+/// the check is done. The state contains a cluster usage bitmap (one bit per cluster) kept in memory. It can be
+/// saved between calls by `serialize` and restored by `deserialize`, e.g. to continue the check after a reboot.
+///
+/// The state is only valid as long as the filesystem is not modified.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct CheckState {
+    phase: CheckPhase,
+    total_clusters: u32,
+    used_clusters_estimate: u32,
+    bitmap: Vec<u32>,
+    visited_clusters: u32,
+    pending_dirs: Vec<PendingDir>,
+    chain: Option<ChainCursor>,
+    // next cluster to check for being lost
+    fat_cluster: u32,
+    lost_run: Option<(u32, u32)>,
+    report: CheckReport,
+}
+
+impl CheckState {
+    /// Creates a state of a check which has not been started yet.
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            phase: CheckPhase::Start,
+            total_clusters: 0,
+            used_clusters_estimate: 0,
+            bitmap: Vec::new(),
+            visited_clusters: 0,
+            pending_dirs: Vec::new(),
+            chain: None,
+            fat_cluster: RESERVED_FAT_ENTRIES,
+            lost_run: None,
+            report: CheckReport::default(),
+        }
+    }
+
+    /// Returns true if the check is finished.
+    #[must_use]
+    pub fn is_done(&self) -> bool {
+        self.phase == CheckPhase::Done
+    }
+
+    pub(crate) fn is_started(&self) -> bool {
+        self.phase != CheckPhase::Start
+    }
+
+    /// Writes the state in a binary form which can be read by `deserialize`.
+    ///
+    /// # Errors
+    ///
+    /// Errors returned by the writer are passed through.
+    pub fn serialize<W: Write>(&self, wrt: &mut W) -> Result<(), W::Error> {
+        wrt.write_u32_le(STATE_MAGIC)?;
+        wrt.write_u32_le(STATE_VERSION)?;
+        wrt.write_u8(self.phase.to_u8())?;
+        wrt.write_u32_le(self.total_clusters)?;
+        wrt.write_u32_le(self.used_clusters_estimate)?;
+        wrt.write_u32_le(self.visited_clusters)?;
+        wrt.write_u32_le(self.fat_cluster)?;
+        let (lost_first_cluster, lost_count) = self.lost_run.unwrap_or((0, 0));
+        wrt.write_u32_le(lost_first_cluster)?;
+        wrt.write_u32_le(lost_count)?;
+        wrt.write_u32_le(self.bitmap.len() as u32)?;
+        for &word in &self.bitmap {
+            wrt.write_u32_le(word)?;
+        }
+        wrt.write_u32_le(self.pending_dirs.len() as u32)?;
+        for dir in &self.pending_dirs {
+            wrt.write_u32_le(dir.cluster.unwrap_or(0))?;
+            write_str(wrt, &dir.path)?;
+            wrt.write_u32_le(dir.offset)?;
+        }
+        if let Some(ref chain) = self.chain {
+            wrt.write_u8(1)?;
+            chain.serialize(wrt)?;
+        } else {
+            wrt.write_u8(0)?;
+        }
+        write_u64(wrt, self.report.files)?;
+        write_u64(wrt, self.report.dirs)?;
+        wrt.write_u32_le(self.report.findings.len() as u32)?;
+        for finding in &self.report.findings {
+            finding.serialize(wrt)?;
+        }
+        Ok(())
+    }
+
+    /// Reads a state written by `serialize`.
+    ///
+    /// # Errors
+    ///
+    /// Errors that can be returned:
+    ///
+    /// * `Error::InvalidInput` will be returned if the data does not contain a valid state.
+    /// * `Error::Io` will be returned if the reader returned an error.
+    pub fn deserialize<R: Read>(rdr: &mut R) -> Result<Self, Error<R::Error>> {
+        if rdr.read_u32_le()? != STATE_MAGIC || rdr.read_u32_le()? != STATE_VERSION {
+            error!("invalid check state signature");
+            return Err(Error::InvalidInput);
+        }
+        let phase = CheckPhase::from_u8(rdr.read_u8()?).ok_or(Error::InvalidInput)?;
+        let total_clusters = rdr.read_u32_le()?;
+        if total_clusters > FatType::Fat32.max_clusters() {
+            error!("invalid cluster count in check state");
+            return Err(Error::InvalidInput);
+        }
+        let used_clusters_estimate = rdr.read_u32_le()?;
+        let visited_clusters = rdr.read_u32_le()?;
+        let fat_cluster = rdr.read_u32_le()?;
+        let lost_first_cluster = rdr.read_u32_le()?;
+        let lost_count = rdr.read_u32_le()?;
+        let lost_run = if lost_count > 0 {
+            Some((lost_first_cluster, lost_count))
+        } else {
+            None
+        };
+        let bitmap_len = rdr.read_u32_le()?;
+        let expected_bitmap_len = if phase == CheckPhase::Start {
+            0
+        } else {
+            bitmap_words(total_clusters)
+        };
+        if bitmap_len != expected_bitmap_len {
+            error!("invalid check state bitmap length");
+            return Err(Error::InvalidInput);
+        }
+        let mut bitmap = Vec::with_capacity(bitmap_len as usize);
+        for _ in 0..bitmap_len {
+            bitmap.push(rdr.read_u32_le()?);
+        }
+        let pending_dirs_len = rdr.read_u32_le()?;
+        let mut pending_dirs = Vec::new();
+        for _ in 0..pending_dirs_len {
+            let cluster = match rdr.read_u32_le()? {
+                0 => None,
+                n => Some(n),
+            };
+            let path = read_str(rdr)?;
+            let offset = rdr.read_u32_le()?;
+            pending_dirs.push(PendingDir { cluster, path, offset });
+        }
+        let chain = match rdr.read_u8()? {
+            0 => None,
+            1 => Some(ChainCursor::deserialize(rdr)?),
+            _ => return Err(Error::InvalidInput),
+        };
+        let files = read_u64(rdr)?;
+        let dirs = read_u64(rdr)?;
+        let findings_len = rdr.read_u32_le()?;
+        let mut findings = Vec::new();
+        for _ in 0..findings_len {
+            let finding = CheckFinding::deserialize(rdr)?;
+            findings.push(finding);
+        }
+        let state = Self {
+            phase,
+            total_clusters,
+            used_clusters_estimate,
+            bitmap,
+            visited_clusters,
+            pending_dirs,
+            chain,
+            fat_cluster,
+            lost_run,
+            report: CheckReport { findings, files, dirs },
+        };
+        // clusters are used as bitmap indices
+        let end_cluster = total_clusters + RESERVED_FAT_ENTRIES;
+        let chain_valid = match state.chain {
+            Some(ref chain) => state.is_valid_cluster(chain.cluster),
+            None => true,
+        };
+        if fat_cluster > end_cluster || !chain_valid {
+            error!("invalid check state");
+            return Err(Error::InvalidInput);
+        }
+        Ok(state)
+    }
+
+    pub(crate) fn step<IO: ReadWriteSeek, TP: TimeProvider, OCC: OemCpConverter>(
+        &mut self,
+        fs: &FileSystem<IO, TP, OCC>,
+        mut budget: CheckBudget,
+    ) -> Result<CheckProgress, Error<IO::Error>> {
+        // make sure every call makes progress
+        budget.max_entries = budget.max_entries.max(1);
+        budget.max_clusters = budget.max_clusters.max(1);
+        if self.phase != CheckPhase::Start && self.total_clusters != fs.total_clusters() {
+            error!("check state does not match the filesystem");
+            return Err(Error::InvalidInput);
+        }
+        // directory iterator is kept between entries of the same directory
+        let mut dir_iter: Option<DirIter<IO, TP, OCC>> = None;
+        loop {
+            match self.phase {
+                CheckPhase::Start => self.start(fs),
+                CheckPhase::Done => return Ok(CheckProgress::Done(self.report.clone())),
+                _ if budget.is_exhausted() => {
+                    return Ok(CheckProgress::InProgress {
+                        fraction: self.fraction(),
+                    })
+                }
+                CheckPhase::Directories => {
+                    if self.chain.is_some() {
+                        let pending_dirs_len = self.pending_dirs.len();
+                        self.follow_chain(fs)?;
+                        // a subdirectory is checked before the rest of the current directory
+                        if self.pending_dirs.len() != pending_dirs_len {
+                            dir_iter = None;
+                        }
+                        budget.max_clusters -= 1;
+                    } else if let Some(dir) = self.pending_dirs.last() {
+                        if dir_iter.is_none() {
+                            let dir_obj = Dir::from_cluster(dir.cluster, fs);
+                            dir_iter = Some(dir_obj.iter_from(u64::from(dir.offset))?);
+                        }
+                        if let Some(r) = dir_iter.as_mut().and_then(Iterator::next) {
+                            self.check_dir_entry(fs, &r?);
+                            budget.max_entries -= 1;
+                        } else {
+                            self.check_duplicate_names(fs)?;
+                            self.pending_dirs.pop();
+                            dir_iter = None;
+                        }
+                    } else {
+                        self.phase = CheckPhase::Fat;
+                    }
+                }
+                CheckPhase::Fat => {
+                    self.check_fat_entry(fs)?;
+                    budget.max_clusters -= 1;
+                }
+            }
+        }
+    }
+
+    fn check_dir_entry<IO: ReadWriteSeek, TP, OCC: OemCpConverter>(
+        &mut self,
+        fs: &FileSystem<IO, TP, OCC>,
+        e: &DirEntry<IO, TP, OCC>,
+    ) {
+        let dir = self.pending_dirs.last_mut().unwrap();
+        dir.offset = e.offset_range.1 as u32;
+        let name = e.short_file_name_as_bytes();
+        // ignore special entries "." and ".."
+        if name == b"." || name == b".." {
+            return;
+        }
+        let path = join_path(&dir.path, &e.file_name());
+        let size = if e.is_dir() {
+            self.report.dirs += 1;
+            None
+        } else {
+            self.report.files += 1;
+            Some(e.len() as u32)
+        };
+        self.start_chain(fs, path, e.first_cluster(), size);
+    }
+
+    /// Reports entries shadowed by an earlier entry with the same name in the last pending directory.
+    ///
+    /// The directory is read again when all its entries have been checked so duplicates are found even if the
+    /// check was interrupted in the middle of the directory.
+    fn check_duplicate_names<IO: ReadWriteSeek, TP: TimeProvider, OCC: OemCpConverter>(
+        &mut self,
+        fs: &FileSystem<IO, TP, OCC>,
+    ) -> Result<(), Error<IO::Error>> {
+        let dir = self.pending_dirs.last().unwrap();
+        let mut names = DirNames::new(fs.options.case_sensitive_lookup);
+        for r in Dir::from_cluster(dir.cluster, fs).iter() {
+            let e = r?;
+            if e.is_dot_entry() {
+                continue;
+            }
+            let long_name = e.file_name();
+            let short_name = e.short_file_name();
+            if names.contains(&long_name) || names.contains(&short_name) {
+                warn!("duplicate name {} in directory {}", long_name, dir.path);
+                self.report.findings.push(CheckFinding::DuplicateName {
+                    path: join_path(&dir.path, &long_name),
+                    entry_pos: e.entry_pos(),
+                });
+            }
+            names.insert(&long_name, &short_name, e.has_lfn());
+        }
+        Ok(())
+    }
+
+    fn start<IO: ReadWriteSeek, TP, OCC>(&mut self, fs: &FileSystem<IO, TP, OCC>) {
+        self.total_clusters = fs.total_clusters();
+        self.used_clusters_estimate = fs
+            .free_clusters_hint()
+            .map_or(self.total_clusters, |n| self.total_clusters - n);
+        self.bitmap = vec![0; bitmap_words(self.total_clusters) as usize];
+        self.phase = CheckPhase::Directories;
+        // FAT32 root directory is stored in a cluster chain
+        match Dir::from_cluster(None, fs).first_cluster() {
+            Some(n) => self.start_chain(fs, String::new(), Some(n), None),
+            None => self.pending_dirs.push(PendingDir {
+                cluster: None,
+                path: String::new(),
+                offset: 0,
+            }),
+        }
+    }
+
+    fn start_chain<IO: ReadWriteSeek, TP, OCC>(
+        &mut self,
+        fs: &FileSystem<IO, TP, OCC>,
+        path: String,
+        first_cluster: Option<u32>,
+        size: Option<u32>,
+    ) {
+        match first_cluster {
+            Some(n) if self.is_valid_cluster(n) => {
+                self.chain = Some(ChainCursor {
+                    path,
+                    first_cluster: n,
+                    cluster: n,
+                    clusters: 0,
+                    size,
+                });
+            }
+            Some(_) => self
+                .report
+                .findings
+                .push(CheckFinding::BrokenChain { path, cluster: 0 }),
+            None => match size {
+                Some(0) => {}
+                Some(size) => self.check_size(fs, path, size, 0),
+                None => self
+                    .report
+                    .findings
+                    .push(CheckFinding::BrokenChain { path, cluster: 0 }),
+            },
+        }
+    }
+
+    fn follow_chain<IO: ReadWriteSeek, TP, OCC>(
+        &mut self,
+        fs: &FileSystem<IO, TP, OCC>,
+    ) -> Result<(), Error<IO::Error>> {
+        let mut chain = self.chain.take().unwrap();
+        let cluster = chain.cluster;
+        if self.is_visited(cluster) {
+            self.report.findings.push(CheckFinding::CrossLinkedCluster {
+                path: chain.path,
+                cluster,
+            });
+            return Ok(());
+        }
+        self.mark_visited(cluster);
+        chain.clusters += 1;
+        match fs.fat_entry(cluster)? {
+            FatValue::Data(n) if self.is_valid_cluster(n) => {
+                chain.cluster = n;
+                self.chain = Some(chain);
+            }
+            FatValue::EndOfChain => match chain.size {
+                Some(size) => self.check_size(fs, chain.path, size, chain.clusters),
+                None => self.pending_dirs.push(PendingDir {
+                    cluster: Some(chain.first_cluster),
+                    path: chain.path,
+                    offset: 0,
+                }),
+            },
+            _ => self.report.findings.push(CheckFinding::BrokenChain {
+                path: chain.path,
+                cluster,
+            }),
+        }
+        Ok(())
+    }
+
+    fn check_size<IO: ReadWriteSeek, TP, OCC>(
+        &mut self,
+        fs: &FileSystem<IO, TP, OCC>,
+        path: String,
+        size: u32,
+        clusters: u32,
+    ) {
+        if fs.clusters_from_bytes(u64::from(size)) != clusters {
+            self.report
+                .findings
+                .push(CheckFinding::SizeMismatch { path, size, clusters });
+        }
+    }
+
+    fn check_fat_entry<IO: ReadWriteSeek, TP, OCC>(
+        &mut self,
+        fs: &FileSystem<IO, TP, OCC>,
+    ) -> Result<(), Error<IO::Error>> {
+        let cluster = self.fat_cluster;
+        if cluster >= self.total_clusters + RESERVED_FAT_ENTRIES {
+            self.end_lost_run();
+            self.phase = CheckPhase::Done;
+            return Ok(());
+        }
+        let used = match fs.fat_entry(cluster)? {
+            FatValue::Data(_) | FatValue::EndOfChain => true,
+            FatValue::Free | FatValue::Bad => false,
+        };
+        if used && !self.is_visited(cluster) {
+            match self.lost_run {
+                Some((_, ref mut count)) => *count += 1,
+                None => self.lost_run = Some((cluster, 1)),
+            }
+        } else {
+            self.end_lost_run();
+        }
+        self.fat_cluster += 1;
+        Ok(())
+    }
+
+    fn end_lost_run(&mut self) {
+        if let Some((first_cluster, count)) = self.lost_run.take() {
+            self.report
+                .findings
+                .push(CheckFinding::LostClusters { first_cluster, count });
+        }
+    }
+
+    fn is_valid_cluster(&self, cluster: u32) -> bool {
+        cluster >= RESERVED_FAT_ENTRIES && cluster < self.total_clusters + RESERVED_FAT_ENTRIES
+    }
+
+    fn is_visited(&self, cluster: u32) -> bool {
+        self.bitmap[(cluster / 32) as usize] & (1 << (cluster % 32)) != 0
+    }
+
+    fn mark_visited(&mut self, cluster: u32) {
+        self.bitmap[(cluster / 32) as usize] |= 1 << (cluster % 32);
+        self.visited_clusters += 1;
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    fn fraction(&self) -> f32 {
+        // directories and the FAT scan are counted as halves of the work
+        let total = self.total_clusters.max(1) as f32;
+        match self.phase {
+            CheckPhase::Start => 0.0,
+            CheckPhase::Directories => {
+                let used = self.used_clusters_estimate.max(1) as f32;
+                0.5 * (self.visited_clusters as f32 / used).min(1.0)
+            }
+            CheckPhase::Fat => 0.5 + 0.5 * ((self.fat_cluster - RESERVED_FAT_ENTRIES) as f32 / total).min(1.0),
+            CheckPhase::Done => 1.0,
+        }
+    }
+}
+
+impl Default for CheckState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Returns number of 32-bit words in the cluster usage bitmap.
+fn bitmap_words(total_clusters: u32) -> u32 {
+    (total_clusters + RESERVED_FAT_ENTRIES + 31) / 32
+}
+
+// Paths stored in the state are limited by the maximal directory depth and name length
+const MAX_STATE_STR_LEN: u32 = 1024 * 1024;
+
+/// Names of entries in a directory matched the same way as in lookups.
+struct DirNames {
+    case_sensitive: bool,
+    /// Uppercase long and short names (case-insensitive lookup) or exact long names (case-sensitive lookup)
+    names: BTreeSet<String>,
+    /// Uppercase short names with uppercase long names of their entries (case-sensitive lookup)
+    short_names: BTreeMap<String, Option<String>>,
+}
+
+impl DirNames {
+    fn new(case_sensitive: bool) -> Self {
+        Self {
+            case_sensitive,
+            names: BTreeSet::new(),
+            short_names: BTreeMap::new(),
+        }
+    }
+
+    /// Checks if a lookup of `name` would return one of the inserted entries.
+    fn contains(&self, name: &str) -> bool {
+        if !self.case_sensitive {
+            return self.names.contains(&to_uppercase(name));
+        }
+        if self.names.contains(name) {
+            return true;
+        }
+        // a short name does not match if the long name differs from `name` only in case
+        let uppercase_name = to_uppercase(name);
+        match self.short_names.get(&uppercase_name) {
+            Some(long_name) => long_name.as_ref() != Some(&uppercase_name),
+            None => false,
+        }
+    }
+
+    fn insert(&mut self, long_name: &str, short_name: &str, has_lfn: bool) {
+        if self.case_sensitive {
+            if has_lfn {
+                self.names.insert(long_name.into());
+            }
+            let uppercase_long_name = if has_lfn { Some(to_uppercase(long_name)) } else { None };
+            self.short_names.insert(to_uppercase(short_name), uppercase_long_name);
+        } else {
+            self.names.insert(to_uppercase(long_name));
+            self.names.insert(to_uppercase(short_name));
+        }
+    }
+}
+
+fn join_path(parent: &str, name: &str) -> String {
+    if parent.is_empty() {
+        name.into()
+    } else {
+        [parent, name].join("/")
+    }
+}
+
+fn to_uppercase(name: &str) -> String {
+    name.chars().flat_map(char_to_uppercase).collect()
+}
+
+fn write_str<W: Write>(wrt: &mut W, s: &str) -> Result<(), W::Error> {
+    wrt.write_u32_le(s.len() as u32)?;
+    wrt.write_all(s.as_bytes())
+}
+
+fn read_str<R: Read>(rdr: &mut R) -> Result<String, Error<R::Error>> {
+    let len = rdr.read_u32_le()?;
+    if len > MAX_STATE_STR_LEN {
+        error!("invalid string length in check state");
+        return Err(Error::InvalidInput);
+    }
+    let mut buf = vec![0_u8; len as usize];
+    rdr.read_exact(&mut buf)?;
+    String::from_utf8(buf).map_err(|_| Error::InvalidInput)
+}
+
+fn write_u64<W: Write>(wrt: &mut W, n: u64) -> Result<(), W::Error> {
+    wrt.write_u32_le(n as u32)?;
+    wrt.write_u32_le((n >> 32) as u32)
+}
+
+fn read_u64<R: Read>(rdr: &mut R) -> Result<u64, Error<R::Error>> {
+    let low = rdr.read_u32_le()?;
+    let high = rdr.read_u32_le()?;
+    Ok(u64::from(low) | (u64::from(high) << 32))
+}
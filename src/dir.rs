@@ -1,26 +1,55 @@
-#[cfg(all(not(feature = "std"), feature = "alloc", feature = "lfn"))]
+#[cfg(all(not(feature = "std"), feature = "alloc"))]
+use alloc::rc::Rc;
+#[cfg(all(not(feature = "std"), feature = "alloc"))]
+use alloc::string::String;
+#[cfg(all(not(feature = "std"), feature = "alloc"))]
+use alloc::vec;
+#[cfg(all(not(feature = "std"), feature = "alloc"))]
 use alloc::vec::Vec;
 use core::char;
 use core::cmp;
+use core::fmt;
 use core::num;
 use core::str;
 #[cfg(feature = "lfn")]
 use core::{iter, slice};
+#[cfg(feature = "std")]
+use std::rc::Rc;
 
+#[cfg(feature = "lfn")]
+use crate::dir_entry::{decode_lfn_lossy, LFN_ENTRY_LAST_FLAG, LFN_PART_LEN};
 use crate::dir_entry::{
-    DirEntry, DirEntryData, DirFileEntryData, DirLfnEntryData, FileAttributes, ShortName, DIR_ENTRY_SIZE,
+    DeletedEntryInfo, DirEntry, DirEntryData, DirFileEntryData, DirLfnEntryData, FileAttributes, ShortName,
+    DIR_ENTRY_DELETED_FLAG, DIR_ENTRY_END_MARKER, DIR_ENTRY_SIZE,
 };
-#[cfg(feature = "lfn")]
-use crate::dir_entry::{LFN_ENTRY_LAST_FLAG, LFN_PART_LEN};
 use crate::dir_entry::{SFN_PADDING, SFN_SIZE};
-use crate::error::{Error, IoError};
-use crate::file::File;
-use crate::fs::{DiskSlice, FileSystem, FsIoAdapter, OemCpConverter, ReadWriteSeek};
+use crate::error::{Error, IoError, WindowsNameRule};
+use crate::file::{File, OpenOptions};
+use crate::fs::{
+    DiskSlice, FatType, FileSystem, FilenamePolicy, FsIoAdapter, OemCpConverter, ReadWriteSeek, ShortNameAlgorithm,
+    UsageSummary,
+};
 use crate::io::{self, IoBase, Read, Seek, SeekFrom, Write};
+#[cfg(feature = "alloc")]
+use crate::scoped_dir::ScopedDir;
 use crate::time::TimeProvider;
 
 const LFN_PADDING: u16 = 0xFFFF;
 
+// Directory size is limited to 65536 entries by the FAT specification
+pub(crate) const MAX_DIR_ENTRIES: u32 = 65536;
+
+// Directory tree walking without the `alloc` feature and recursive removal are recursive - limit the depth to protect
+// the stack from corrupted (looped) directories
+const MAX_DIR_DEPTH: u32 = 256;
+
+// Largest numeric tail of a generated short name - it leaves one character for the prefix (e.g. `T~999999`)
+const MAX_NUMERIC_TAIL: u32 = 999_999;
+
+/// Callback invoked for every subdirectory found by `Dir::add_usage`.
+pub(crate) type SubdirVisitor<'a, 'b, IO, TP, OCC> =
+    dyn FnMut(&mut UsageSummary, &DirEntry<'a, IO, TP, OCC>) -> Result<(), Error<<IO as IoBase>::Error>> + 'b;
+
 pub(crate) enum DirRawStream<'a, IO: ReadWriteSeek, TP, OCC> {
     File(File<'a, IO, TP, OCC>),
     Root(DiskSlice<FsIoAdapter<'a, IO, TP, OCC>, FsIoAdapter<'a, IO, TP, OCC>>),
@@ -40,6 +69,14 @@ impl<IO: ReadWriteSeek, TP, OCC> DirRawStream<'_, IO, TP, OCC> {
             DirRawStream::Root(_) => None,
         }
     }
+
+    /// Returns the maximal size of a directory that cannot grow (FAT12/FAT16 root directory).
+    fn fixed_size(&self) -> Option<u64> {
+        match self {
+            DirRawStream::File(_) => None,
+            DirRawStream::Root(slice) => Some(slice.size()),
+        }
+    }
 }
 
 // Note: derive cannot be used because of invalid bounds. See: https://github.com/rust-lang/rust/issues/26925
@@ -69,7 +106,10 @@ impl<IO: ReadWriteSeek, TP: TimeProvider, OCC> Write for DirRawStream<'_, IO, TP
     fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
         match self {
             DirRawStream::File(file) => file.write(buf),
-            DirRawStream::Root(raw) => raw.write(buf),
+            DirRawStream::Root(raw) => {
+                let fs = raw.inner().fs();
+                raw.write(buf).map_err(|e| fs.handle_write_error(e))
+            }
         }
     }
     fn flush(&mut self) -> Result<(), Self::Error> {
@@ -89,72 +129,359 @@ impl<IO: ReadWriteSeek, TP, OCC> Seek for DirRawStream<'_, IO, TP, OCC> {
     }
 }
 
-fn split_path(path: &str) -> (&str, Option<&str>) {
-    let trimmed_path = path.trim_matches('/');
-    trimmed_path.find('/').map_or((trimmed_path, None), |n| {
+fn split_path<'b>(path: &'b str, separators: &[char]) -> (&'b str, Option<&'b str>) {
+    let trimmed_path = path.trim_matches(separators);
+    trimmed_path.find(separators).map_or((trimmed_path, None), |n| {
         (&trimmed_path[..n], Some(&trimmed_path[n + 1..]))
     })
 }
 
+/// Returns true if `path` refers to the directory it is relative to (e.g. `""`, `"/"` or `"."`).
+fn is_self_path(path: &str, separators: &[char]) -> bool {
+    let trimmed_path = path.trim_matches(separators);
+    trimmed_path.is_empty() || trimmed_path == "."
+}
+
+/// Returns true if `name` is a `.` or `..` path component.
+fn is_dot_component(name: &str) -> bool {
+    name == "." || name == ".."
+}
+
+#[allow(clippy::large_enum_variant)]
 enum DirEntryOrShortName<'a, IO: ReadWriteSeek, TP, OCC> {
     DirEntry(DirEntry<'a, IO, TP, OCC>),
-    ShortName([u8; SFN_SIZE]),
+    // free entries found by the same directory scan are remembered so the directory is not scanned again
+    ShortName([u8; SFN_SIZE], Option<FreeEntries>),
 }
 
 /// A FAT filesystem directory.
 ///
 /// This struct is created by the `open_dir` or `create_dir` methods on `Dir`.
 /// The root directory is returned by the `root_dir` method on `FileSystem`.
+///
+/// Names are compared case-insensitively against both the long and the short name of an entry. A consistent
+/// filesystem never contains two matching entries in one directory but a corrupted one can. Methods taking a path
+/// always use the first matching entry in directory order, the shadowed entries are reported by the `check` method
+/// on `FileSystem` and can be accessed with `find_all` and removed with `remove_entry_at`.
 pub struct Dir<'a, IO: ReadWriteSeek, TP, OCC> {
     stream: DirRawStream<'a, IO, TP, OCC>,
     fs: &'a FileSystem<IO, TP, OCC>,
+    // `None` for the root directory and directories opened without a known path
+    #[cfg(feature = "alloc")]
+    path: Option<Rc<str>>,
 }
 
 impl<'a, IO: ReadWriteSeek, TP, OCC> Dir<'a, IO, TP, OCC> {
     pub(crate) fn new(stream: DirRawStream<'a, IO, TP, OCC>, fs: &'a FileSystem<IO, TP, OCC>) -> Self {
-        Dir { stream, fs }
+        Dir {
+            stream,
+            fs,
+            #[cfg(feature = "alloc")]
+            path: None,
+        }
+    }
+
+    /// Sets the path this directory has been opened through.
+    #[cfg(feature = "alloc")]
+    pub(crate) fn with_path(mut self, path: &str) -> Self {
+        self.path = if path.is_empty() { None } else { Some(path.into()) };
+        self
+    }
+
+    /// Returns the '/' separated path of this directory relative to the root directory.
+    ///
+    /// The path is built from the names used to open the directory (`.` and `..` components are resolved). The root
+    /// directory has an empty path. Renaming or moving the directory does not change the path of existing `Dir`
+    /// objects.
+    #[cfg(feature = "alloc")]
+    #[must_use]
+    pub fn path(&self) -> &str {
+        self.path.as_deref().unwrap_or("")
+    }
+
+    /// Creates an iterator reading `stream` of this directory. Returned entries know the path of this directory.
+    fn dir_iter(&self, stream: DirRawStream<'a, IO, TP, OCC>, skip_volume: bool) -> DirIter<'a, IO, TP, OCC> {
+        let iter = DirIter::new(stream, self.fs, skip_volume);
+        #[cfg(feature = "alloc")]
+        let iter = DirIter {
+            dir_path: self.path.clone(),
+            ..iter
+        };
+        iter
+    }
+
+    /// Opens the directory starting at `cluster` (`None` means the root directory).
+    pub(crate) fn from_cluster(cluster: Option<u32>, fs: &'a FileSystem<IO, TP, OCC>) -> Self {
+        match cluster {
+            Some(n) => Dir::new(DirRawStream::File(File::new(Some(n), None, fs)), fs),
+            None => fs.root_dir(),
+        }
+    }
+
+    pub(crate) fn first_cluster(&self) -> Option<u32> {
+        self.stream.first_cluster()
     }
 
     /// Creates directory entries iterator.
     #[must_use]
     pub fn iter(&self) -> DirIter<'a, IO, TP, OCC> {
-        DirIter::new(self.stream.clone(), self.fs, true)
+        self.dir_iter(self.stream.clone(), true)
+    }
+
+    /// Creates directory entries iterator that also yields volume label entries.
+    ///
+    /// Volume label entries are normally skipped by `iter`. They are present only in the root directory and can be
+    /// recognized by `DirEntry::is_volume`. Their raw name is available through `DirEntry::raw_short_name`.
+    #[must_use]
+    pub fn iter_with_volume_labels(&self) -> DirIter<'a, IO, TP, OCC> {
+        self.dir_iter(self.stream.clone(), false)
+    }
+
+    /// Creates raw directory entries iterator that also yields deleted entries.
+    ///
+    /// Deleted entries are returned as `RawDirEntry::Deleted` in the order they are stored on the disk. Long name
+    /// entries preceding a deleted short name entry are attached to it if their checksums match. Volume label entries
+    /// are returned like in `iter_with_volume_labels`. The iterator ends with `RawDirEntry::End`.
+    #[must_use]
+    pub fn iter_raw(&self) -> RawDirIter<'a, IO, TP, OCC> {
+        let mut inner = self.dir_iter(self.stream.clone(), false);
+        inner.with_deleted = true;
+        RawDirIter { inner, ended: false }
+    }
+
+    /// Creates directory entries iterator returning only entries accepted by `filter`.
+    ///
+    /// Filtering is done while reading the directory so no long names are decoded for skipped entries.
+    #[must_use]
+    pub fn iter_filtered(&self, filter: EntryFilter) -> DirIter<'a, IO, TP, OCC> {
+        let mut iter = self.iter();
+        iter.filter = Some(filter);
+        iter
+    }
+
+    /// Creates iterator over files in the directory.
+    ///
+    /// Hidden files are skipped. See `iter_filtered`.
+    #[must_use]
+    pub fn files(&self) -> DirIter<'a, IO, TP, OCC> {
+        self.iter_filtered(EntryFilter::files())
+    }
+
+    /// Creates iterator over subdirectories of the directory.
+    ///
+    /// Hidden directories are skipped but "." and ".." entries are returned. See `iter_filtered`.
+    #[must_use]
+    pub fn dirs(&self) -> DirIter<'a, IO, TP, OCC> {
+        self.iter_filtered(EntryFilter::dirs())
+    }
+
+    /// Creates directory entries iterator starting at the given offset in the directory.
+    pub(crate) fn iter_from(&self, offset: u64) -> Result<DirIter<'a, IO, TP, OCC>, Error<IO::Error>> {
+        let mut stream = self.stream.clone();
+        stream.seek(SeekFrom::Start(offset))?;
+        Ok(self.dir_iter(stream, true))
     }
 }
 
 impl<'a, IO: ReadWriteSeek, TP: TimeProvider, OCC: OemCpConverter> Dir<'a, IO, TP, OCC> {
+    /// Returns `dir` opened through `path` relative to self with its path set.
+    #[cfg(feature = "alloc")]
+    fn opened_through(&self, dir: Self, path: &str) -> Self {
+        dir.with_path(&self.join_path(path))
+    }
+
+    #[cfg(not(feature = "alloc"))]
+    fn opened_through(&self, dir: Self, _path: &str) -> Self {
+        dir
+    }
+
+    /// Appends `path` relative to self to the path of self resolving `.` and `..` components.
+    #[cfg(feature = "alloc")]
+    fn join_path(&self, path: &str) -> String {
+        let mut joined = String::from(self.path());
+        for name in path.split(self.fs.options.path_separators()) {
+            match name {
+                "" | "." => {}
+                ".." => {
+                    let len = parent_path(&joined).len();
+                    joined.truncate(len);
+                }
+                _ => {
+                    if !joined.is_empty() {
+                        joined.push('/');
+                    }
+                    joined.push_str(name);
+                }
+            }
+        }
+        joined
+    }
+
     fn find_entry(
         &self,
         name: &str,
         is_dir: Option<bool>,
-        mut short_name_gen: Option<&mut ShortNameGenerator>,
+        short_name_gen: Option<&mut ShortNameGenerator>,
     ) -> Result<DirEntry<'a, IO, TP, OCC>, Error<IO::Error>> {
-        for r in self.iter() {
+        match self.lookup_entry(name, short_name_gen, None)? {
+            Some(e) => Self::check_entry_type(e, is_dir),
+            None => Err(Error::not_found(name)),
+        }
+    }
+
+    /// Finds entry `name` without building an error if it does not exist.
+    ///
+    /// A miss does not allocate (`Error::NotFound` carries the missing path component) so this is used by lookups
+    /// where a missing entry is an expected outcome. If `free_entries` is given it is fed with all entries read, so
+    /// after a miss it knows where a new entry can be written.
+    #[allow(clippy::type_complexity)]
+    fn lookup_entry(
+        &self,
+        name: &str,
+        mut short_name_gen: Option<&mut ShortNameGenerator>,
+        free_entries: Option<&mut FreeEntriesFinder>,
+    ) -> Result<Option<DirEntry<'a, IO, TP, OCC>>, Error<IO::Error>> {
+        let dir_cluster = self.stream.first_cluster().unwrap_or(0);
+        if let Some(e) = self.find_cached_entry(dir_cluster, name) {
+            return Ok(Some(e));
+        }
+        let mut iter = self.dir_iter(self.stream.clone(), false);
+        iter.free_entries = free_entries.as_ref().map(|finder| **finder);
+        for r in iter.by_ref() {
             let e = r?;
+            // never match the volume label but remember it for conflicts detection
+            if e.data.is_volume() {
+                if let Some(ref mut gen) = short_name_gen {
+                    gen.add_volume_label(e.raw_short_name());
+                }
+                continue;
+            }
             // compare name ignoring case
             if e.eq_name(name) {
-                // check if file or directory is expected
-                if is_dir.is_some() && Some(e.is_dir()) != is_dir {
-                    if e.is_dir() {
-                        error!("Is a directory");
-                    } else {
-                        error!("Not a directory");
-                    }
-                    return Err(Error::InvalidInput);
-                }
-                return Ok(e);
+                self.fs.dir_cache_insert(dir_cluster, name, e.offset_range.0);
+                return Ok(Some(e));
             }
             // update short name generator state
             if let Some(ref mut gen) = short_name_gen {
                 gen.add_existing(e.raw_short_name());
             }
         }
-        Err(Error::NotFound) //("No such file or directory"))
+        if let (Some(finder), Some(result)) = (free_entries, iter.free_entries) {
+            *finder = result;
+        }
+        Ok(None)
+    }
+
+    /// Returns entry `name` if the directory lookup cache knows its position and it is still there.
+    fn find_cached_entry(&self, dir_cluster: u32, name: &str) -> Option<DirEntry<'a, IO, TP, OCC>> {
+        let offset = self.fs.dir_cache_get(dir_cluster, name)?;
+        let mut stream = self.stream.clone();
+        stream.seek(SeekFrom::Start(offset)).ok()?;
+        // cached offset is only a hint - errors are ignored and lookup falls back to the directory scan
+        let e = self.dir_iter(stream, true).next()?.ok()?;
+        if e.offset_range.0 == offset && e.eq_name(name) {
+            Some(e)
+        } else {
+            trace!("stale directory cache entry {}", name);
+            None
+        }
+    }
+
+    fn check_entry_type(
+        e: DirEntry<'a, IO, TP, OCC>,
+        is_dir: Option<bool>,
+    ) -> Result<DirEntry<'a, IO, TP, OCC>, Error<IO::Error>> {
+        // check if file or directory is expected
+        if is_dir.is_some() && Some(e.is_dir()) != is_dir {
+            if e.is_dir() {
+                error!("Is a directory");
+            } else {
+                error!("Not a directory");
+            }
+            return Err(Error::InvalidInput);
+        }
+        Ok(e)
+    }
+
+    fn split_path_checked<'b>(&self, path: &'b str) -> Result<(&'b str, Option<&'b str>), Error<IO::Error>> {
+        // number of directories traversed before reaching the last path component (repeated slashes are collapsed)
+        let separators = self.path_separators();
+        let depth = path
+            .split(separators)
+            .filter(|c| !c.is_empty())
+            .count()
+            .saturating_sub(1);
+        if depth as u64 > u64::from(self.fs.options.limits.max_dir_depth) {
+            error!("path {} exceeds the directory depth limit", path);
+            return Err(Error::LimitExceeded);
+        }
+        Ok(split_path(path, separators))
+    }
+
+    pub(crate) fn path_separators(&self) -> &'static [char] {
+        self.fs.options.path_separators()
+    }
+
+    /// Makes sure `path` points to an entry inside a directory and not to a directory itself.
+    ///
+    /// The last component of `path` cannot be `.` or `..`.
+    fn check_entry_path(&self, path: &str) -> Result<(), Error<IO::Error>> {
+        let separators = self.path_separators();
+        let last_component = path.trim_matches(separators).rsplit(separators).next().unwrap_or("");
+        if last_component.is_empty() || is_dot_component(last_component) {
+            error!("path {:?} does not point to a directory entry", path);
+            return Err(Error::InvalidInput);
+        }
+        Ok(())
+    }
+
+    /// Opens the directory containing the last component of `path` and returns it with the last component.
+    fn open_parent_dir<'b>(&self, path: &'b str) -> Result<(Self, &'b str), Error<IO::Error>> {
+        let (mut name, mut rest_opt) = self.split_path_checked(path)?;
+        let mut dir = self.clone();
+        while let Some(rest) = rest_opt {
+            dir = dir.open_path_component(path, name)?;
+            let (next_name, next_rest_opt) = split_path(rest, self.path_separators());
+            name = next_name;
+            rest_opt = next_rest_opt;
+        }
+        Ok((dir, name))
+    }
+
+    /// Opens directory `name` being a component of `path`.
+    ///
+    /// `.` is resolved to self and `..` to the parent directory. `Error::InvalidInput` is returned for `..` in the
+    /// root directory.
+    fn open_path_component(&self, path: &str, name: &str) -> Result<Self, Error<IO::Error>> {
+        match name {
+            "." => Ok(self.clone()),
+            ".." => self.parent()?.ok_or_else(|| {
+                error!("path {} goes above the root directory", path);
+                Error::InvalidInput
+            }),
+            _ => Ok(self.find_path_component(path, name, Some(true))?.to_dir_without_path()),
+        }
+    }
+
+    /// Finds entry `name` being a component of `path`.
+    ///
+    /// If the entry does not exist the returned error contains the part of `path` before `name`.
+    fn find_path_component(
+        &self,
+        path: &str,
+        name: &str,
+        is_dir: Option<bool>,
+    ) -> Result<DirEntry<'a, IO, TP, OCC>, Error<IO::Error>> {
+        self.find_entry(name, is_dir, None).map_err(|err| {
+            // `name` is a slice of `path`
+            let name_offset = name.as_ptr() as usize - path.as_ptr() as usize;
+            err.with_resolved_prefix(path[..name_offset].trim_matches(self.path_separators()))
+        })
     }
 
     #[allow(clippy::type_complexity)]
     pub(crate) fn find_volume_entry(&self) -> Result<Option<DirEntry<'a, IO, TP, OCC>>, Error<IO::Error>> {
-        for r in DirIter::new(self.stream.clone(), self.fs, false) {
+        for r in self.dir_iter(self.stream.clone(), false) {
             let e = r?;
             if e.data.is_volume() {
                 return Ok(Some(e));
@@ -163,52 +490,192 @@ impl<'a, IO: ReadWriteSeek, TP: TimeProvider, OCC: OemCpConverter> Dir<'a, IO, T
         Ok(None)
     }
 
+    /// Sets the name of the volume entry to `label` or removes the entry if `label` is `None`.
+    ///
+    /// The entry is created if it does not exist.
+    pub(crate) fn set_volume_entry(&self, label: Option<[u8; SFN_SIZE]>) -> Result<(), Error<IO::Error>> {
+        match (self.find_volume_entry()?, label) {
+            (Some(e), Some(label)) => {
+                let mut raw_entry = e.data.renamed(label);
+                raw_entry.set_modified(self.fs.options.time_provider.get_current_date_time());
+                let mut stream = self.stream.clone();
+                stream.seek(SeekFrom::Start(e.offset_range.1 - u64::from(DIR_ENTRY_SIZE)))?;
+                raw_entry.serialize(&mut stream)
+            }
+            (Some(e), None) => self.free_entry_slots(&e),
+            (None, Some(label)) => {
+                let raw_entry = self.create_sfn_entry(label, FileAttributes::VOLUME_ID, None);
+                let (mut stream, at_end) = self.find_free_entries(1)?;
+                raw_entry.serialize(&mut stream)?;
+                if at_end {
+                    let end_pos = stream.seek(SeekFrom::Current(0))?;
+                    self.write_end_marker(end_pos)?;
+                }
+                Ok(())
+            }
+            (None, None) => Ok(()),
+        }
+    }
+
     fn check_for_existence(
         &self,
         name: &str,
         is_dir: Option<bool>,
     ) -> Result<DirEntryOrShortName<'a, IO, TP, OCC>, Error<IO::Error>> {
-        let mut short_name_gen = ShortNameGenerator::new(name);
+        // validate the name before the caller starts modifying the filesystem (e.g. removes the old entry on rename)
+        self.validate_new_name(name)?;
+        let mut short_name_gen = ShortNameGenerator::new(name, self.fs.options.short_name_algorithm);
+        // free entries are searched for during the first scan assuming the short name is not changed by collisions
+        let mut free_entries = FreeEntriesFinder::new(Self::num_entries_for_name(name, &short_name_gen.short_name));
+        let mut first_scan = true;
         loop {
-            // find matching entry
-            let r = self.find_entry(name, is_dir, Some(&mut short_name_gen));
-            match r {
-                // file not found - continue with short name generation
-                Err(Error::NotFound) => {}
-                // unexpected error - return it
-                Err(err) => return Err(err),
-                // directory already exists - return it
-                Ok(e) => return Ok(DirEntryOrShortName::DirEntry(e)),
-            };
+            // find matching entry - a miss is the common case so it must not build an error
+            let free_entries_opt = if first_scan { Some(&mut free_entries) } else { None };
+            first_scan = false;
+            if let Some(e) = self.lookup_entry(name, Some(&mut short_name_gen), free_entries_opt)? {
+                // entry already exists - return it
+                return Self::check_entry_type(e, is_dir).map(DirEntryOrShortName::DirEntry);
+            }
             // try to generate short name
-            if let Ok(name) = short_name_gen.generate() {
-                return Ok(DirEntryOrShortName::ShortName(name));
+            if let Ok(short_name) = short_name_gen.generate() {
+                if short_name_gen.is_volume_label(&short_name) {
+                    if self.fs.options.strict {
+                        error!("short name of {} conflicts with the volume label", name);
+                        return Err(Error::AlreadyExists);
+                    }
+                    warn!("short name of {} conflicts with the volume label", name);
+                }
+                return Ok(DirEntryOrShortName::ShortName(short_name, free_entries.found));
             }
             // there were too many collisions in short name generation
-            // try different checksum in the next iteration
+            // try next numeric tails in the next iteration
             short_name_gen.next_iteration();
+            if short_name_gen.is_exhausted() {
+                error!("cannot generate unique short name for {}", name);
+                return Err(Error::AlreadyExists);
+            }
         }
     }
 
     /// Opens existing subdirectory.
     ///
     /// `path` is a '/' separated directory path relative to self directory.
+    /// `Error::NotFound` returned by this and other methods taking a path contains the first path component that
+    /// has not been found (if the `alloc` feature is enabled).
+    /// An empty path, `/` and `.` refer to self directory - a copy of it is returned. Other methods taking a path
+    /// return `Error::InvalidInput` for such paths and for paths ending with a `.` or `..` component.
+    /// In all methods taking a path repeated slashes are collapsed, a `.` component refers to the current directory
+    /// and a `..` component to its parent (found using the `..` entry, see `parent`).
+    /// A `\` is a separator too if `FsOptions::backslash_separator` is enabled.
     ///
     /// # Errors
     ///
     /// Errors that can be returned:
     ///
     /// * `Error::NotFound` will be returned if `path` does not point to any existing directory entry.
-    /// * `Error::InvalidInput` will be returned if `path` points to a file that is not a directory.
+    /// * `Error::InvalidInput` will be returned if `path` points to a file that is not a directory or if a `..`
+    ///   component goes above the root directory.
     /// * `Error::Io` will be returned if the underlying storage object returned an I/O error.
     pub fn open_dir(&self, path: &str) -> Result<Self, Error<IO::Error>> {
         trace!("Dir::open_dir {}", path);
-        let (name, rest_opt) = split_path(path);
-        let e = self.find_entry(name, Some(true), None)?;
-        match rest_opt {
-            Some(rest) => e.to_dir().open_dir(rest),
-            None => Ok(e.to_dir()),
+        if is_self_path(path, self.path_separators()) {
+            return Ok(self.clone());
+        }
+        let (dir, name) = self.open_parent_dir(path)?;
+        let dir = dir.open_path_component(path, name)?;
+        Ok(self.opened_through(dir, path))
+    }
+
+    /// Returns the directory entry of an existing file or directory.
+    ///
+    /// `path` is a '/' separated path relative to self directory. Unlike `open_file` and `open_dir` it does not
+    /// require the entry type to be known, so it can be used to read metadata (e.g. size or attributes) of any entry.
+    ///
+    /// # Errors
+    ///
+    /// Errors that can be returned:
+    ///
+    /// * `Error::NotFound` will be returned if `path` points to a non-existing directory entry.
+    /// * `Error::InvalidInput` will be returned if a component of `path` other than the last one is a file or if
+    ///   `path` is empty or refers to self directory (e.g. `/` or `.`).
+    /// * `Error::LimitExceeded` will be returned if `path` exceeds the configured directory depth limit.
+    /// * `Error::Io` will be returned if the underlying storage object returned an I/O error.
+    pub fn entry(&self, path: &str) -> Result<DirEntry<'a, IO, TP, OCC>, Error<IO::Error>> {
+        trace!("Dir::entry {}", path);
+        self.check_entry_path(path)?;
+        let (dir, name) = self.open_parent_dir(path)?;
+        // `name` is a slice of `path`
+        let name_offset = name.as_ptr() as usize - path.as_ptr() as usize;
+        let dir = self.opened_through(dir, &path[..name_offset]);
+        dir.find_path_component(path, name, None)
+    }
+
+    /// Returns the directory entry with the long name consisting of UCS-2 units `name`.
+    ///
+    /// Long names written by other systems can contain unpaired surrogates. `DirEntry::file_name` replaces them with
+    /// the replacement character and methods taking a path match them against it, so such a name is reachable but not
+    /// necessarily unique. This method looks up an entry in `self` directory (`name` is not a path) whose long name
+    /// is exactly `name` (as returned by `DirEntry::long_file_name_as_ucs2_units`). If there is no such entry and
+    /// `name` is valid UTF-16 it is looked up like a name passed to `entry` (e.g. ignoring case).
+    ///
+    /// # Errors
+    ///
+    /// Errors that can be returned:
+    ///
+    /// * `Error::NotFound` will be returned if there is no entry with this name.
+    /// * `Error::InvalidFileNameLength` will be returned if `name` is empty or too long.
+    /// * `Error::Io` will be returned if the underlying storage object returned an I/O error.
+    #[cfg(feature = "lfn")]
+    pub fn entry_by_ucs2_name(&self, name: &[u16]) -> Result<DirEntry<'a, IO, TP, OCC>, Error<IO::Error>> {
+        trace!("Dir::entry_by_ucs2_name {:?}", name);
+        if name.is_empty() || name.len() > MAX_LONG_NAME_LEN {
+            return Err(Error::InvalidFileNameLength);
+        }
+        for r in self.iter() {
+            let e = r?;
+            if e.long_file_name_as_ucs2_units() == Some(name) {
+                return Ok(e);
+            }
+        }
+        let mut name_buf = [0_u8; LONG_NAME_BUFFER_LEN * 3];
+        let decoded_name = decode_ucs2(name, &mut name_buf);
+        if char::decode_utf16(name.iter().copied()).any(|r| r.is_err()) {
+            return Err(Error::not_found(decoded_name));
+        }
+        self.find_entry(decoded_name, None, None)
+    }
+
+    /// Returns the parent directory or `None` if this is the root directory.
+    ///
+    /// The parent directory is found using the `..` entry, so the path used to open this directory is not needed.
+    ///
+    /// # Errors
+    ///
+    /// Errors that can be returned:
+    ///
+    /// * `Error::CorruptedFileSystem` will be returned if this directory has no `..` entry.
+    /// * `Error::Io` will be returned if the underlying storage object returned an I/O error.
+    pub fn parent(&self) -> Result<Option<Self>, Error<IO::Error>> {
+        trace!("Dir::parent");
+        let root_cluster = self.fs.root_dir().first_cluster();
+        if self.first_cluster() == root_cluster {
+            return Ok(None);
         }
+        // `..` is the second entry of a directory
+        for r in self.iter().take(2) {
+            let e = r?;
+            if e.short_file_name_as_bytes() == b".." {
+                // `..` entry contains 0 if the parent is the root directory (even on FAT32), but the root directory
+                // cluster is accepted too
+                let cluster = e.first_cluster().filter(|&n| Some(n) != root_cluster);
+                let parent = Self::from_cluster(cluster, self.fs);
+                #[cfg(feature = "alloc")]
+                let parent = parent.with_path(parent_path(self.path()));
+                return Ok(Some(parent));
+            }
+        }
+        error!("directory has no .. entry");
+        Err(Error::CorruptedFileSystem)
     }
 
     /// Opens existing file.
@@ -220,21 +687,63 @@ impl<'a, IO: ReadWriteSeek, TP: TimeProvider, OCC: OemCpConverter> Dir<'a, IO, T
     /// Errors that can be returned:
     ///
     /// * `Error::NotFound` will be returned if `path` points to a non-existing directory entry.
-    /// * `Error::InvalidInput` will be returned if `path` points to a file that is a directory.
+    /// * `Error::InvalidInput` will be returned if `path` points to a file that is a directory or if `path` is empty
+    ///   or refers to self directory (e.g. `/` or `.`).
     /// * `Error::Io` will be returned if the underlying storage object returned an I/O error.
     pub fn open_file(&self, path: &str) -> Result<File<'a, IO, TP, OCC>, Error<IO::Error>> {
         trace!("Dir::open_file {}", path);
+        self.check_entry_path(path)?;
         // traverse path
-        let (name, rest_opt) = split_path(path);
-        if let Some(rest) = rest_opt {
-            let e = self.find_entry(name, Some(true), None)?;
-            return e.to_dir().open_file(rest);
-        }
+        let (dir, name) = self.open_parent_dir(path)?;
         // convert entry to a file
-        let e = self.find_entry(name, Some(false), None)?;
+        let e = dir.find_path_component(path, name, Some(false))?;
         Ok(e.to_file())
     }
 
+    /// Checks if `path` points to an existing file or directory.
+    ///
+    /// `path` is a '/' separated path relative to self directory. An empty path, `/` and `.` refer to self directory
+    /// so `true` is returned for them. `false` is returned if any component of `path` does not exist or a component
+    /// other than the last one is a file.
+    /// Unlike `open_file` and `open_dir` this method does not allocate memory if the entry does not exist so it is
+    /// suitable for probing many paths.
+    ///
+    /// # Errors
+    ///
+    /// Errors that can be returned:
+    ///
+    /// * `Error::LimitExceeded` will be returned if `path` exceeds the configured directory depth limit.
+    /// * `Error::Io` will be returned if the underlying storage object returned an I/O error.
+    pub fn exists(&self, path: &str) -> Result<bool, Error<IO::Error>> {
+        trace!("Dir::exists {}", path);
+        if is_self_path(path, self.path_separators()) {
+            return Ok(true);
+        }
+        let (mut name, mut rest_opt) = self.split_path_checked(path)?;
+        let mut dir = self.clone();
+        while let Some(rest) = rest_opt {
+            dir = match name {
+                "." => dir,
+                ".." => match dir.parent()? {
+                    Some(parent) => parent,
+                    None => return Ok(false),
+                },
+                _ => match dir.lookup_entry(name, None, None)? {
+                    Some(e) if e.is_dir() => e.to_dir_without_path(),
+                    _ => return Ok(false),
+                },
+            };
+            let (next_name, next_rest_opt) = split_path(rest, self.path_separators());
+            name = next_name;
+            rest_opt = next_rest_opt;
+        }
+        match name {
+            "." => Ok(true),
+            ".." => Ok(dir.parent()?.is_some()),
+            _ => Ok(dir.lookup_entry(name, None, None)?.is_some()),
+        }
+    }
+
     /// Creates new or opens existing file=.
     ///
     /// `path` is a '/' separated file path relative to `self` directory.
@@ -244,123 +753,785 @@ impl<'a, IO: ReadWriteSeek, TP: TimeProvider, OCC: OemCpConverter> Dir<'a, IO, T
     ///
     /// Errors that can be returned:
     ///
-    /// * `Error::InvalidInput` will be returned if `path` points to an existing file that is a directory.
+    /// * `Error::NotFound` will be returned if a parent directory in `path` does not exist. The missing parent is
+    ///   reported, not the last component.
+    /// * `Error::InvalidInput` will be returned if `path` points to an existing file that is a directory or if `path`
+    ///   is empty or refers to self directory (e.g. `/` or `.`).
     /// * `Error::InvalidFileNameLength` will be returned if the file name is empty or if it is too long.
-    /// * `Error::UnsupportedFileNameCharacter` will be returned if the file name contains an invalid character.
+    /// * `Error::UnsupportedFileNameCharacter` will be returned if the file name contains a character not allowed by
+    ///   `FsOptions::filename_policy`.
+    /// * `Error::UnsupportedWindowsFileName` will be returned if the file name cannot be used on Windows (see
+    ///   `FsOptions::windows_name_rules`).
     /// * `Error::NotEnoughSpace` will be returned if there is not enough free space to create a new file.
+    /// * `Error::PermissionDenied` will be returned if the filesystem is mounted read-only.
     /// * `Error::Io` will be returned if the underlying storage object returned an I/O error.
     pub fn create_file(&self, path: &str) -> Result<File<'a, IO, TP, OCC>, Error<IO::Error>> {
-        trace!("Dir::create_file {}", path);
-        // traverse path
-        let (name, rest_opt) = split_path(path);
-        if let Some(rest) = rest_opt {
-            return self.find_entry(name, Some(true), None)?.to_dir().create_file(rest);
+        self.create_file_with_attrs(path, FileAttributes::empty())
+    }
+
+    /// Creates new file with attributes `attrs` or opens existing file.
+    ///
+    /// Works like `create_file` but the new entry gets `attrs` right away, so e.g. a hidden file is never visible
+    /// without the `HIDDEN` attribute. Attributes of an existing file are not changed.
+    ///
+    /// # Errors
+    ///
+    /// Errors are the same as for `create_file`. `Error::InvalidInput` is also returned if `attrs` contain
+    /// `DIRECTORY` or `VOLUME_ID`.
+    pub fn create_file_with_attrs(
+        &self,
+        path: &str,
+        attrs: FileAttributes,
+    ) -> Result<File<'a, IO, TP, OCC>, Error<IO::Error>> {
+        trace!("Dir::create_file_with_attrs {} {:?}", path, attrs);
+        self.create_file_internal(path, attrs, false)
+    }
+
+    /// Creates new file failing if an entry with the same name already exists.
+    ///
+    /// `path` is a '/' separated file path relative to `self` directory. It works like `create_file` but an existing
+    /// entry is never opened (similar to `std::fs::OpenOptions::create_new`). The name is compared with existing long
+    /// and short names the same way as in `open_file` (case-insensitively by default), so e.g. `FOO.TXT` prevents
+    /// creation of `foo.txt`. The check and the creation are done in a single call, so there is no separate lookup
+    /// that could get outdated.
+    ///
+    /// # Errors
+    ///
+    /// Errors are the same as for `create_file`. `Error::AlreadyExists` is also returned if an entry (a file or a
+    /// directory) with this name exists.
+    pub fn create_new_file(&self, path: &str) -> Result<File<'a, IO, TP, OCC>, Error<IO::Error>> {
+        trace!("Dir::create_new_file {}", path);
+        self.create_file_internal(path, FileAttributes::empty(), true)
+    }
+
+    /// Returns a builder used to open a file in `self` directory with options similar to `std::fs::OpenOptions`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), fatfs::Error<std::io::Error>> {
+    /// # let img = std::fs::read("resources/fat16.img")?;
+    /// # let fs = fatfs::FileSystem::new(std::io::Cursor::new(img), fatfs::FsOptions::new())?;
+    /// use fatfs::Write;
+    /// let root_dir = fs.root_dir();
+    /// let mut file = root_dir.open_options().write(true).truncate(true).open("short.txt")?;
+    /// file.write_all(b"new content")?;
+    /// let mut file = root_dir.open_options().append(true).open("short.txt")?;
+    /// file.write_all(b" appended")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[must_use]
+    pub fn open_options(&self) -> OpenOptions<'a, IO, TP, OCC> {
+        OpenOptions::new(self)
+    }
+
+    fn create_file_internal(
+        &self,
+        path: &str,
+        attrs: FileAttributes,
+        exclusive: bool,
+    ) -> Result<File<'a, IO, TP, OCC>, Error<IO::Error>> {
+        if attrs.intersects(FileAttributes::DIRECTORY | FileAttributes::VOLUME_ID) {
+            error!("invalid attributes of a new file: {:?}", attrs);
+            return Err(Error::InvalidInput);
         }
-        // this is final filename in the path
-        let r = self.check_for_existence(name, Some(false))?;
+        // an existing file would be opened with write access
+        self.fs.check_writable()?;
+        self.check_entry_path(path)?;
+        // traverse path
+        let (dir, name) = self.open_parent_dir(path)?;
+        // this is final filename in the path - an existing directory is reported as AlreadyExists if exclusive
+        let is_dir = if exclusive { None } else { Some(false) };
+        let r = dir.check_for_existence(name, is_dir)?;
         match r {
             // file does not exist - create it
-            DirEntryOrShortName::ShortName(short_name) => {
-                let sfn_entry = self.create_sfn_entry(short_name, FileAttributes::from_bits_truncate(0), None);
-                Ok(self.write_entry(name, sfn_entry)?.to_file())
+            DirEntryOrShortName::ShortName(short_name, free_entries) => {
+                let sfn_entry = dir.create_sfn_entry(short_name, attrs, None);
+                Ok(dir.write_entry(name, sfn_entry, free_entries)?.to_file())
+            }
+            DirEntryOrShortName::DirEntry(e) => {
+                if exclusive {
+                    error!("{} already exists", path);
+                    return Err(Error::AlreadyExists);
+                }
+                // file already exists - return it
+                Ok(e.to_file())
             }
-            // file already exists - return it
-            DirEntryOrShortName::DirEntry(e) => Ok(e.to_file()),
         }
     }
 
     /// Creates new directory or opens existing.
     ///
     /// `path` is a '/' separated path relative to self directory.
+    /// If the directory exists its `.` and `..` entries are validated. Entries missing because of an interrupted
+    /// `create_dir` call are added, so retrying a failed call is safe.
     ///
     /// # Errors
     ///
     /// Errors that can be returned:
     ///
-    /// * `Error::InvalidInput` will be returned if `path` points to an existing file that is not a directory.
+    /// * `Error::NotFound` will be returned if a parent directory in `path` does not exist.
+    /// * `Error::InvalidInput` will be returned if `path` points to an existing file that is not a directory or if
+    ///   `path` is empty or refers to self directory (e.g. `/` or `.`).
     /// * `Error::InvalidFileNameLength` will be returned if the file name is empty or if it is too long.
-    /// * `Error::UnsupportedFileNameCharacter` will be returned if the file name contains an invalid character.
+    /// * `Error::UnsupportedFileNameCharacter` will be returned if the file name contains a character not allowed by
+    ///   `FsOptions::filename_policy`.
+    /// * `Error::UnsupportedWindowsFileName` will be returned if the file name cannot be used on Windows (see
+    ///   `FsOptions::windows_name_rules`).
     /// * `Error::NotEnoughSpace` will be returned if there is not enough free space to create a new directory.
+    /// * `Error::CorruptedFileSystem` will be returned if the existing directory has invalid `.` or `..` entries and
+    ///   repair mode is disabled (see `FsOptions::repair`).
     /// * `Error::Io` will be returned if the underlying storage object returned an I/O error.
     pub fn create_dir(&self, path: &str) -> Result<Self, Error<IO::Error>> {
-        trace!("Dir::create_dir {}", path);
+        self.create_dir_with_attrs(path, FileAttributes::empty())
+    }
+
+    /// Creates new directory with attributes `attrs` or opens existing directory.
+    ///
+    /// Works like `create_dir` but the new entry gets `attrs` right away. The `DIRECTORY` attribute is always set.
+    /// `.` and `..` entries of the new directory get only the `DIRECTORY` attribute. Attributes of an existing
+    /// directory are not changed.
+    ///
+    /// # Errors
+    ///
+    /// Errors are the same as for `create_dir`. `Error::InvalidInput` is also returned if `attrs` contain
+    /// `VOLUME_ID`.
+    pub fn create_dir_with_attrs(&self, path: &str, attrs: FileAttributes) -> Result<Self, Error<IO::Error>> {
+        trace!("Dir::create_dir_with_attrs {} {:?}", path, attrs);
+        let dir = self.create_dir_internal(path, attrs, false)?;
+        Ok(self.opened_through(dir, path))
+    }
+
+    /// Creates new directory failing if an entry with the same name already exists.
+    ///
+    /// `path` is a '/' separated path relative to self directory. It works like `create_dir` (parent directories
+    /// are traversed the same way and the new directory gets the same `.` and `..` entries) but an existing entry is
+    /// never opened, so e.g. an old directory tree is not silently reused.
+    ///
+    /// # Errors
+    ///
+    /// Errors are the same as for `create_dir`. `Error::AlreadyExists` is also returned if an entry (a file or a
+    /// directory) with the name of the last path component exists.
+    pub fn create_new_dir(&self, path: &str) -> Result<Self, Error<IO::Error>> {
+        trace!("Dir::create_new_dir {}", path);
+        let dir = self.create_dir_internal(path, FileAttributes::empty(), true)?;
+        Ok(self.opened_through(dir, path))
+    }
+
+    fn create_dir_internal(
+        &self,
+        path: &str,
+        attrs: FileAttributes,
+        exclusive: bool,
+    ) -> Result<Self, Error<IO::Error>> {
+        if attrs.contains(FileAttributes::VOLUME_ID) {
+            error!("invalid attributes of a new directory: {:?}", attrs);
+            return Err(Error::InvalidInput);
+        }
+        self.check_entry_path(path)?;
         // traverse path
-        let (name, rest_opt) = split_path(path);
-        if let Some(rest) = rest_opt {
-            return self.find_entry(name, Some(true), None)?.to_dir().create_dir(rest);
+        let (name, rest_opt) = self.split_path_checked(path)?;
+        if rest_opt.is_some() {
+            let (dir, name) = self.open_parent_dir(path)?;
+            return dir.create_dir_internal(name, attrs, exclusive);
         }
-        // this is final filename in the path
-        let r = self.check_for_existence(name, Some(true))?;
+        // this is final filename in the path - an existing file is reported as AlreadyExists if exclusive
+        let is_dir = if exclusive { None } else { Some(true) };
+        let r = self.check_for_existence(name, is_dir)?;
         match r {
             // directory does not exist - create it
-            DirEntryOrShortName::ShortName(short_name) => {
+            DirEntryOrShortName::ShortName(short_name, free_entries) => {
                 // alloc cluster for directory data
                 let cluster = self.fs.alloc_cluster(None, true)?;
                 // create entry in parent directory
-                let sfn_entry = self.create_sfn_entry(short_name, FileAttributes::DIRECTORY, Some(cluster));
-                let entry = self.write_entry(name, sfn_entry)?;
-                let dir = entry.to_dir();
-                // create special entries "." and ".."
-                let dot_sfn = ShortNameGenerator::generate_dot();
-                let sfn_entry = self.create_sfn_entry(dot_sfn, FileAttributes::DIRECTORY, entry.first_cluster());
-                dir.write_entry(".", sfn_entry)?;
-                let dotdot_sfn = ShortNameGenerator::generate_dotdot();
-                let sfn_entry =
-                    self.create_sfn_entry(dotdot_sfn, FileAttributes::DIRECTORY, self.stream.first_cluster());
-                dir.write_entry("..", sfn_entry)?;
+                let sfn_entry = self.create_sfn_entry(short_name, attrs | FileAttributes::DIRECTORY, Some(cluster));
+                let entry = match self.write_entry(name, sfn_entry, free_entries) {
+                    Ok(entry) => entry,
+                    Err(err) => {
+                        // do not leak the cluster if there is no space for the entry (e.g. in a full root directory)
+                        self.fs.free_cluster_chain(cluster)?;
+                        return Err(err);
+                    }
+                };
+                let dir = entry.to_dir_without_path();
+                // create special entries "." and ".." at the beginning of the zeroed cluster - they are plain short
+                // entries without LFN entries and the end marker is already in place after them
+                let mut stream = dir.stream.clone();
+                stream.seek(SeekFrom::Start(0))?;
+                for dot_entry in &self.create_dot_entries(&entry.data) {
+                    dot_entry.serialize(&mut stream)?;
+                }
                 Ok(dir)
             }
-            // directory already exists - return it
-            DirEntryOrShortName::DirEntry(e) => Ok(e.to_dir()),
-        }
-    }
-
-    fn is_empty(&self) -> Result<bool, Error<IO::Error>> {
-        trace!("Dir::is_empty");
-        // check if directory contains no files
-        for r in self.iter() {
-            let e = r?;
-            let name = e.short_file_name_as_bytes();
-            // ignore special entries "." and ".."
-            if name != b"." && name != b".." {
-                return Ok(false);
+            DirEntryOrShortName::DirEntry(e) => {
+                if exclusive {
+                    error!("{} already exists", path);
+                    return Err(Error::AlreadyExists);
+                }
+                // directory already exists - make sure it has been fully created and return it
+                self.validate_dot_entries(&e)?;
+                Ok(e.to_dir_without_path())
             }
         }
-        Ok(true)
     }
 
-    /// Removes existing file or directory.
+    /// Creates new directory together with all missing parent directories or opens existing.
     ///
-    /// `path` is a '/' separated file path relative to self directory.
-    /// Make sure there is no reference to this file (no File instance) or filesystem corruption
-    /// can happen.
+    /// `path` is a '/' separated path relative to self directory. It works like `create_dir` called for every
+    /// component of `path` in turn (similar to `std::fs::create_dir_all`), so every created directory gets `.` and
+    /// `..` entries and a zeroed cluster. Directories created before an error are not removed.
     ///
     /// # Errors
     ///
     /// Errors that can be returned:
     ///
-    /// * `Error::NotFound` will be returned if `path` points to a non-existing directory entry.
-    /// * `Error::InvalidInput` will be returned if `path` points to a file that is not a directory.
-    /// * `Error::DirectoryIsNotEmpty` will be returned if the specified directory is not empty.
-    /// * `Error::Io` will be returned if the underlying storage object returned an I/O error.
-    pub fn remove(&self, path: &str) -> Result<(), Error<IO::Error>> {
-        trace!("Dir::remove {}", path);
-        // traverse path
-        let (name, rest_opt) = split_path(path);
-        if let Some(rest) = rest_opt {
-            let e = self.find_entry(name, Some(true), None)?;
-            return e.to_dir().remove(rest);
+    /// * `Error::InvalidInput` will be returned if any component of `path` points to an existing file that is not a
+    ///   directory or if `path` is empty or refers to self directory (e.g. `/` or `.`). Nothing is created after
+    ///   that component.
+    /// * `Error::LimitExceeded` will be returned if `path` exceeds the configured directory depth limit.
+    /// * Other errors are the same as for `create_dir`.
+    pub fn create_dir_all(&self, path: &str) -> Result<Self, Error<IO::Error>> {
+        trace!("Dir::create_dir_all {}", path);
+        self.check_entry_path(path)?;
+        let (mut name, mut rest_opt) = self.split_path_checked(path)?;
+        let mut dir = self.clone();
+        loop {
+            dir = if is_self_path(name, self.path_separators()) || name == ".." {
+                dir.open_dir(name)?
+            } else {
+                dir.create_dir(name)?
+            };
+            if let Some(rest) = rest_opt {
+                let (next_name, next_rest_opt) = split_path(rest, self.path_separators());
+                name = next_name;
+                rest_opt = next_rest_opt;
+            } else {
+                return Ok(dir);
+            }
+        }
+    }
+
+    /// Creates `.` and `..` entries of a subdirectory of self described by `dir_entry`.
+    ///
+    /// Timestamps are copied from the subdirectory entry. `..` entry of a subdirectory of the root directory points
+    /// to cluster 0 (also on FAT32).
+    fn create_dot_entries(&self, dir_entry: &DirFileEntryData) -> [DirFileEntryData; 2] {
+        let fat_type = self.fs.fat_type();
+        let mut dot = dir_entry.renamed(ShortNameGenerator::generate_dot());
+        // attributes like HIDDEN apply to the directory entry only
+        dot.set_attributes(FileAttributes::DIRECTORY);
+        let mut dotdot = dot.renamed(ShortNameGenerator::generate_dotdot());
+        dotdot.set_first_cluster(self.dotdot_cluster(), fat_type);
+        [dot, dotdot]
+    }
+
+    /// Returns the cluster stored in `..` entries of subdirectories of self.
+    fn dotdot_cluster(&self) -> Option<u32> {
+        let parent_cluster = self.stream.first_cluster();
+        if parent_cluster == self.fs.root_dir().first_cluster() {
+            None
+        } else {
+            parent_cluster
+        }
+    }
+
+    /// Updates the `..` entry of subdirectory `dir` so it points to self.
+    fn set_parent_of(&self, dir: &Dir<IO, TP, OCC>) -> Result<(), Error<IO::Error>> {
+        let mut stream = dir.stream.clone();
+        // `..` is the second entry of a directory
+        for _ in 0..2 {
+            let raw_entry = DirEntryData::deserialize(&mut stream)?;
+            if let DirEntryData::File(mut data) = raw_entry {
+                if *data.name() == ShortNameGenerator::generate_dotdot() {
+                    data.set_first_cluster(self.dotdot_cluster(), self.fs.fat_type());
+                    stream.seek(SeekFrom::Current(-i64::from(DIR_ENTRY_SIZE)))?;
+                    data.serialize(&mut stream)?;
+                    return Ok(());
+                }
+            }
+        }
+        error!("directory has no .. entry");
+        Err(Error::CorruptedFileSystem)
+    }
+
+    /// Writes a short name entry without LFN entries into the first free slot.
+    fn write_short_entry(&self, raw_entry: &DirFileEntryData) -> Result<(), Error<IO::Error>> {
+        let (mut stream, at_end) = self.find_free_entries(1)?;
+        raw_entry.serialize(&mut stream)?;
+        if at_end {
+            let end_pos = stream.seek(SeekFrom::Current(0))?;
+            self.write_end_marker(end_pos)?;
+        }
+        Ok(())
+    }
+
+    /// Makes sure subdirectory `e` of self starts with valid `.` and `..` entries.
+    ///
+    /// Missing entries (left by a `create_dir` call interrupted by an error) are written. Entries pointing to wrong
+    /// clusters and duplicated entries are fixed in repair mode, otherwise `Error::CorruptedFileSystem` is returned.
+    fn validate_dot_entries(&self, e: &DirEntry<'a, IO, TP, OCC>) -> Result<(), Error<IO::Error>> {
+        let fat_type = self.fs.fat_type();
+        let dir = e.to_dir_without_path();
+        let dir_cluster = dir.stream.first_cluster();
+        if dir_cluster.is_none() {
+            error!("directory entry without a cluster");
+            return Err(Error::CorruptedFileSystem);
+        }
+        let names = [".", ".."];
+        let short_names = [
+            ShortNameGenerator::generate_dot(),
+            ShortNameGenerator::generate_dotdot(),
+        ];
+        let dot_entries = self.create_dot_entries(&e.data);
+        let clusters = [dir_cluster, dot_entries[1].first_cluster(fat_type)];
+        let repair = self.fs.options.repair;
+        let mut stream = dir.stream.clone();
+        let mut num_entries = 0;
+        loop {
+            let pos = stream.seek(SeekFrom::Current(0))?;
+            let raw_entry = DirEntryData::deserialize(&mut stream)?;
+            if raw_entry.is_end() {
+                break;
+            }
+            // LFN entries are not checked, readers ignore them if the short entry gets deleted
+            let mut data = if let DirEntryData::File(data) = raw_entry {
+                data
+            } else {
+                continue;
+            };
+            if data.is_deleted() {
+                continue;
+            }
+            let index = num_entries;
+            num_entries += 1;
+            let dot_index = short_names.iter().position(|n| n == data.name());
+            if index < 2 {
+                if dot_index != Some(index) {
+                    error!("directory entry {} is not {}", index, names[index]);
+                    return Err(Error::CorruptedFileSystem);
+                }
+                let cluster = data.first_cluster(fat_type);
+                // ".." entry in a subdirectory of the FAT32 root directory can contain either 0 or the root cluster
+                let is_valid = cluster == clusters[index] || (index == 1 && cluster == self.stream.first_cluster());
+                if is_valid {
+                    continue;
+                }
+                if !repair {
+                    error!("{} entry points to invalid cluster {:?}", names[index], cluster);
+                    return Err(Error::CorruptedFileSystem);
+                }
+                warn!(
+                    "fixing {} entry pointing to invalid cluster {:?}",
+                    names[index], cluster
+                );
+                data.set_first_cluster(clusters[index], fat_type);
+            } else if let Some(i) = dot_index {
+                if !repair {
+                    error!("duplicated {} entry", names[i]);
+                    return Err(Error::CorruptedFileSystem);
+                }
+                warn!("removing duplicated {} entry", names[i]);
+                data.set_deleted();
+            } else {
+                continue;
+            }
+            stream.seek(SeekFrom::Start(pos))?;
+            data.serialize(&mut stream)?;
+            stream.seek(SeekFrom::Start(pos + u64::from(DIR_ENTRY_SIZE)))?;
+        }
+        // finish creation of the directory
+        for index in num_entries..2 {
+            warn!("directory is missing {} entry - adding it", names[index]);
+            dir.write_short_entry(&dot_entries[index])?;
+        }
+        Ok(())
+    }
+
+    /// Adds files in this directory to the summary and calls `visit_subdir` for every subdirectory.
+    pub(crate) fn add_usage(
+        &self,
+        summary: &mut UsageSummary,
+        depth: u32,
+        visit_subdir: &mut SubdirVisitor<'a, '_, IO, TP, OCC>,
+    ) -> Result<(), Error<IO::Error>> {
+        if let Some(n) = self.stream.first_cluster() {
+            summary.allocated_bytes += self.fs.bytes_from_clusters(self.fs.count_cluster_chain(n)?);
+        }
+        for r in self.iter() {
+            let e = r?;
+            let name = e.short_file_name_as_bytes();
+            // ignore special entries "." and ".."
+            if name == b"." || name == b".." {
+                continue;
+            }
+            summary.max_depth = cmp::max(summary.max_depth, depth + 1);
+            if e.is_dir() {
+                summary.dirs += 1;
+                visit_subdir(summary, &e)?;
+            } else {
+                summary.files += 1;
+                summary.logical_bytes += e.len();
+                summary.max_file_size = cmp::max(summary.max_file_size, e.len() as u32);
+                if let Some(n) = e.first_cluster() {
+                    summary.allocated_bytes += self.fs.bytes_from_clusters(self.fs.count_cluster_chain(n)?);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds this directory and all its subdirectories to the summary using recursion.
+    ///
+    /// It is used if the `alloc` feature is disabled - `DirQueue` is used otherwise.
+    #[cfg(not(feature = "alloc"))]
+    pub(crate) fn add_usage_recursive(&self, summary: &mut UsageSummary, depth: u32) -> Result<(), Error<IO::Error>> {
+        if depth > self.fs.options.limits.max_dir_depth {
+            error!("directory tree exceeds the depth limit");
+            return Err(Error::LimitExceeded);
+        }
+        if depth > MAX_DIR_DEPTH {
+            error!("directory tree is too deep");
+            return Err(Error::CorruptedFileSystem);
+        }
+        self.add_usage(summary, depth, &mut |summary, e| match e.first_cluster() {
+            Some(_) => e.to_dir_without_path().add_usage_recursive(summary, depth + 1),
+            None => {
+                error!("directory entry without a cluster");
+                Err(Error::CorruptedFileSystem)
+            }
+        })
+    }
+
+    /// Counts files and subdirectories in this directory.
+    ///
+    /// Returns a tuple `(files, dirs)`. Raw directory entries are scanned without building `DirEntry` objects so
+    /// long names are not decoded and nothing is allocated. Deleted entries, long name entries and volume labels are
+    /// not counted. "." and ".." entries are not counted either.
+    ///
+    /// # Errors
+    ///
+    /// Errors that can be returned:
+    ///
+    /// * `Error::LimitExceeded` will be returned if the directory exceeds the entries limit from `FsLimits`.
+    /// * `Error::Io` will be returned if the underlying storage object returned an I/O error.
+    pub fn entry_count(&self) -> Result<(usize, usize), Error<IO::Error>> {
+        trace!("Dir::entry_count");
+        let mut files = 0;
+        let mut dirs = 0;
+        self.scan_entries(|data| {
+            if data.is_dir() {
+                dirs += 1;
+            } else {
+                files += 1;
+            }
+            true
+        })?;
+        Ok((files, dirs))
+    }
+
+    /// Checks if this directory contains no files and subdirectories.
+    ///
+    /// "." and ".." entries, deleted entries and volume labels are ignored. The root directory has no "." and ".."
+    /// entries so it is empty if it contains nothing but the volume label. Like `entry_count` it scans raw directory
+    /// entries without decoding long names but it stops at the first file or subdirectory.
+    ///
+    /// # Errors
+    ///
+    /// Errors that can be returned:
+    ///
+    /// * `Error::LimitExceeded` will be returned if the directory exceeds the entries limit from `FsLimits`.
+    /// * `Error::Io` will be returned if the underlying storage object returned an I/O error.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # fn main() -> std::io::Result<()> {
+    /// # let img = std::fs::read("resources/fat16.img")?;
+    /// # let fs = fatfs::FileSystem::new(std::io::Cursor::new(img), fatfs::FsOptions::new())?;
+    /// let dir = fs.root_dir().create_dir("logs")?;
+    /// assert!(dir.is_empty()?);
+    /// dir.create_file("today.log")?;
+    /// assert!(!dir.is_empty()?);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn is_empty(&self) -> Result<bool, Error<IO::Error>> {
+        trace!("Dir::is_empty");
+        let mut empty = true;
+        self.scan_entries(|_| {
+            empty = false;
+            false
+        })?;
+        Ok(empty)
+    }
+
+    /// Calls `f` for short name entries of files and subdirectories until it returns `false`.
+    ///
+    /// Deleted entries, long name entries, volume labels and "." and ".." entries are skipped.
+    fn scan_entries(&self, mut f: impl FnMut(&DirFileEntryData) -> bool) -> Result<(), Error<IO::Error>> {
+        let mut stream = self.stream.clone();
+        stream.seek(SeekFrom::Start(0))?;
+        let dot_sfn = ShortNameGenerator::generate_dot();
+        let dotdot_sfn = ShortNameGenerator::generate_dotdot();
+        for _ in 0..self.fs.options.limits.max_dir_entries {
+            // a directory filling all of its clusters has no end marker - deserialize returns an end entry then
+            let raw_entry = DirEntryData::deserialize(&mut stream)?;
+            if raw_entry.is_end() {
+                return Ok(());
+            }
+            let data = match raw_entry {
+                DirEntryData::File(data) => data,
+                DirEntryData::Lfn(_) => continue,
+            };
+            if data.is_deleted() || data.is_volume() || *data.name() == dot_sfn || *data.name() == dotdot_sfn {
+                continue;
+            }
+            if !f(&data) {
+                return Ok(());
+            }
+        }
+        error!("directory exceeds the entries limit");
+        Err(Error::LimitExceeded)
+    }
+
+    /// Removes existing file or directory.
+    ///
+    /// `path` is a '/' separated file path relative to self directory.
+    /// Make sure there is no reference to this file (no File instance) or filesystem corruption
+    /// can happen.
+    ///
+    /// # Errors
+    ///
+    /// Errors that can be returned:
+    ///
+    /// * `Error::NotFound` will be returned if `path` points to a non-existing directory entry.
+    /// * `Error::InvalidInput` will be returned if `path` is empty or refers to self directory (e.g. `/` or `.`).
+    /// * `Error::DirectoryIsNotEmpty` will be returned if the specified directory is not empty.
+    /// * `Error::PermissionDenied` will be returned if the filesystem is mounted read-only.
+    /// * `Error::Io` will be returned if the underlying storage object returned an I/O error.
+    pub fn remove(&self, path: &str) -> Result<(), Error<IO::Error>> {
+        trace!("Dir::remove {}", path);
+        self.remove_path(path, None)
+    }
+
+    /// Removes existing file.
+    ///
+    /// `path` is a '/' separated file path relative to self directory. Unlike `remove` it never removes a directory.
+    /// Make sure there is no reference to this file (no File instance) or filesystem corruption can happen.
+    ///
+    /// # Errors
+    ///
+    /// Errors that can be returned:
+    ///
+    /// * `Error::NotFound` will be returned if `path` points to a non-existing directory entry.
+    /// * `Error::InvalidInput` will be returned if `path` is empty, refers to self directory (e.g. `/` or `.`) or
+    ///   points to a directory.
+    /// * `Error::Io` will be returned if the underlying storage object returned an I/O error.
+    pub fn remove_file(&self, path: &str) -> Result<(), Error<IO::Error>> {
+        trace!("Dir::remove_file {}", path);
+        self.remove_path(path, Some(false))
+    }
+
+    /// Removes existing empty directory.
+    ///
+    /// `path` is a '/' separated directory path relative to self directory. Unlike `remove` it never removes a file.
+    ///
+    /// # Errors
+    ///
+    /// Errors that can be returned:
+    ///
+    /// * `Error::NotFound` will be returned if `path` points to a non-existing directory entry.
+    /// * `Error::InvalidInput` will be returned if `path` is empty, refers to self directory (e.g. `/` or `.`) or
+    ///   points to a file.
+    /// * `Error::DirectoryIsNotEmpty` will be returned if the specified directory is not empty.
+    /// * `Error::Io` will be returned if the underlying storage object returned an I/O error.
+    pub fn remove_dir(&self, path: &str) -> Result<(), Error<IO::Error>> {
+        trace!("Dir::remove_dir {}", path);
+        self.remove_path(path, Some(true))
+    }
+
+    fn remove_path(&self, path: &str, is_dir: Option<bool>) -> Result<(), Error<IO::Error>> {
+        self.fs.check_writable()?;
+        self.check_entry_path(path)?;
+        // traverse path
+        let (dir, name) = self.open_parent_dir(path)?;
+        let e = dir.find_path_component(path, name, is_dir)?;
+        dir.remove_entry(&e)
+    }
+
+    /// Removes existing file or directory including all its content.
+    ///
+    /// `path` is a '/' separated path relative to self directory. The directory tree is walked depth-first: files are
+    /// removed first and then the directories which became empty, freeing their clusters. `.` and `..` entries are
+    /// never followed. Subdirectories with the `SYSTEM` attribute (e.g. "System Volume Information") are skipped
+    /// together with their content, so directories containing them are not removed either - use
+    /// `remove_recursive_forced` to remove them too. The directory pointed to by `path` is never skipped.
+    /// Make sure there is no reference to any of the removed files (no File instance) or filesystem corruption
+    /// can happen.
+    ///
+    /// Returns the number of removed files and directories.
+    ///
+    /// # Errors
+    ///
+    /// Errors that can be returned:
+    ///
+    /// * `Error::NotFound` will be returned if `path` points to a non-existing directory entry.
+    /// * `Error::InvalidInput` will be returned if `path` is empty or refers to self directory (e.g. `/` or `.`).
+    /// * `Error::LimitExceeded` will be returned if directories are nested deeper than allowed by
+    ///   `FsLimits::max_dir_depth`.
+    /// * `Error::CorruptedFileSystem` will be returned if a directory entry has no cluster or directories are nested
+    ///   too deep (e.g. because of a directory loop).
+    /// * `Error::Io` will be returned if the underlying storage object returned an I/O error.
+    ///
+    /// Removal stops at the first error. Entries removed before the error stay removed.
+    pub fn remove_recursive(&self, path: &str) -> Result<u64, Error<IO::Error>> {
+        trace!("Dir::remove_recursive {}", path);
+        self.remove_tree(path, false)
+    }
+
+    /// Removes existing file or directory including all its content without skipping `SYSTEM` directories.
+    ///
+    /// It works like `remove_recursive` but subdirectories with the `SYSTEM` attribute are removed too.
+    ///
+    /// # Errors
+    ///
+    /// Errors are the same as for `remove_recursive`.
+    pub fn remove_recursive_forced(&self, path: &str) -> Result<u64, Error<IO::Error>> {
+        trace!("Dir::remove_recursive_forced {}", path);
+        self.remove_tree(path, true)
+    }
+
+    fn remove_tree(&self, path: &str, force: bool) -> Result<u64, Error<IO::Error>> {
+        self.fs.check_writable()?;
+        self.check_entry_path(path)?;
+        let (dir, name) = self.open_parent_dir(path)?;
+        let e = dir.find_path_component(path, name, None)?;
+        let mut removed = 0;
+        if e.is_dir() && !Self::subdir(&e)?.remove_contents(force, 1, &mut removed)? {
+            // a skipped system directory is left inside
+            return Ok(removed);
+        }
+        dir.remove_entry(&e)?;
+        Ok(removed + 1)
+    }
+
+    /// Removes all entries of this directory (except skipped system directories) and adds their number to
+    /// `removed`.
+    ///
+    /// Returns true if the directory is empty afterwards.
+    fn remove_contents(&self, force: bool, depth: u32, removed: &mut u64) -> Result<bool, Error<IO::Error>> {
+        if depth > self.fs.options.limits.max_dir_depth {
+            error!("directory tree exceeds the depth limit");
+            return Err(Error::LimitExceeded);
+        }
+        if depth > MAX_DIR_DEPTH {
+            error!("directory tree is too deep");
+            return Err(Error::CorruptedFileSystem);
         }
+        let mut is_empty = true;
+        for r in self.iter() {
+            let e = r?;
+            if e.is_dot_entry() {
+                continue;
+            }
+            if e.is_dir() {
+                if !force && e.attributes().contains(FileAttributes::SYSTEM) {
+                    debug!("skipping system directory at {}", e.entry_pos());
+                    is_empty = false;
+                    continue;
+                }
+                if !Self::subdir(&e)?.remove_contents(force, depth + 1, removed)? {
+                    is_empty = false;
+                    continue;
+                }
+            }
+            self.remove_entry(&e)?;
+            *removed += 1;
+        }
+        Ok(is_empty)
+    }
+
+    /// Returns the directory of `e` making sure it is not the root directory because of a missing cluster.
+    fn subdir(e: &DirEntry<'a, IO, TP, OCC>) -> Result<Self, Error<IO::Error>> {
+        if e.first_cluster().is_none() {
+            error!("directory entry without a cluster");
+            return Err(Error::CorruptedFileSystem);
+        }
+        Ok(e.to_dir_without_path())
+    }
+
+    /// Returns all entries of this directory matching `name` in directory order.
+    ///
+    /// A consistent filesystem contains at most one matching entry. More entries are returned only if the directory
+    /// is corrupted - the first one is the entry used by lookups, e.g. in `open_file` and `remove`. Use
+    /// `remove_entry_at` to remove a specific one.
+    ///
+    /// # Errors
+    ///
+    /// `Error::Io` will be returned if the underlying storage object returned an I/O error.
+    #[cfg(feature = "alloc")]
+    #[allow(clippy::type_complexity)]
+    pub fn find_all(&self, name: &str) -> Result<Vec<DirEntry<'a, IO, TP, OCC>>, Error<IO::Error>> {
+        trace!("Dir::find_all {}", name);
+        let mut entries = Vec::new();
+        for r in self.iter() {
+            let e = r?;
+            if e.eq_name(name) {
+                entries.push(e);
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Removes the entry of this directory whose short name entry is stored at `entry_pos`.
+    ///
+    /// The position is returned by the `entry_pos` method on `DirEntry`. Unlike `remove` this method can remove an
+    /// entry shadowed by an earlier entry with the same name (see `find_all`).
+    /// Make sure there is no reference to this file (no File instance) or filesystem corruption
+    /// can happen.
+    ///
+    /// # Errors
+    ///
+    /// Errors that can be returned:
+    ///
+    /// * `Error::InvalidInput` will be returned if there is no entry at `entry_pos` in this directory or it is
+    ///   a `.` or `..` entry.
+    /// * `Error::DirectoryIsNotEmpty` will be returned if the entry is a directory which is not empty.
+    /// * `Error::Io` will be returned if the underlying storage object returned an I/O error.
+    pub fn remove_entry_at(&self, entry_pos: u64) -> Result<(), Error<IO::Error>> {
+        trace!("Dir::remove_entry_at {}", entry_pos);
+        for r in self.iter() {
+            let e = r?;
+            if e.entry_pos() != entry_pos {
+                continue;
+            }
+            if e.is_dot_entry() {
+                error!("cannot remove . or .. entry");
+                return Err(Error::InvalidInput);
+            }
+            return self.remove_entry(&e);
+        }
+        error!("no directory entry at position {}", entry_pos);
+        Err(Error::InvalidInput)
+    }
+
+    fn remove_entry(&self, e: &DirEntry<IO, TP, OCC>) -> Result<(), Error<IO::Error>> {
         // in case of directory check if it is empty
-        let e = self.find_entry(name, None, None)?;
-        if e.is_dir() && !e.to_dir().is_empty()? {
+        if e.is_dir() && !e.to_dir_without_path().is_empty()? {
             return Err(Error::DirectoryIsNotEmpty);
         }
         // free data
         if let Some(n) = e.first_cluster() {
             self.fs.free_cluster_chain(n)?;
         }
-        // free long and short name entries
+        self.free_entry_slots(e)
+    }
+
+    /// Marks long and short name entries of `e` as deleted.
+    fn free_entry_slots(&self, e: &DirEntry<IO, TP, OCC>) -> Result<(), Error<IO::Error>> {
+        // a file created later in the same slot must not share the state of handles of this one
+        self.fs.open_files.borrow_mut().forget(e.entry_pos());
         let mut stream = self.stream.clone();
         stream.seek(SeekFrom::Start(e.offset_range.0))?;
         let num = ((e.offset_range.1 - e.offset_range.0) / u64::from(DIR_ENTRY_SIZE)) as usize;
@@ -374,11 +1545,151 @@ impl<'a, IO: ReadWriteSeek, TP: TimeProvider, OCC: OemCpConverter> Dir<'a, IO, T
         Ok(())
     }
 
+    /// Restores a deleted entry returned by `iter_raw`.
+    ///
+    /// `first_char` replaces the first byte of the short name overwritten by the deletion (it can be recovered from
+    /// long name entries - see `DeletedEntryInfo::recovered_first_byte`). Long name entries attached to `deleted` are
+    /// restored only if their checksum matches the short name starting with `first_char`.
+    /// Clusters of a file are assumed to be contiguous: consecutive clusters starting from the stored first cluster
+    /// and covering the stored size are allocated again as a cluster chain. Only the first cluster of a directory is
+    /// restored - it is enough for an empty directory and `remove` deletes only empty directories.
+    ///
+    /// # Errors
+    ///
+    /// Errors that can be returned:
+    ///
+    /// * `Error::InvalidInput` will be returned if `first_char` cannot start a short name or if `deleted` does not
+    ///   describe a deleted entry currently stored in this directory (e.g. the slots have been reused).
+    /// * `Error::AlreadyExists` will be returned if an entry with the restored short name or long name exists.
+    /// * `Error::ClusterInUse` will be returned if any of the needed clusters has been allocated again since the
+    ///   deletion. Nothing is modified in this case.
+    /// * `Error::CorruptedFileSystem` will be returned if the stored clusters do not fit in the volume.
+    /// * `Error::Io` will be returned if the underlying storage object returned an I/O error.
+    pub fn undelete(
+        &self,
+        deleted: &DeletedEntryInfo,
+        first_char: u8,
+    ) -> Result<DirEntry<'a, IO, TP, OCC>, Error<IO::Error>> {
+        trace!("Dir::undelete {:?}", deleted);
+        if first_char == DIR_ENTRY_END_MARKER || first_char == SFN_PADDING || first_char == DIR_ENTRY_DELETED_FLAG {
+            error!("invalid first short name character {:#x}", first_char);
+            return Err(Error::InvalidInput);
+        }
+        let (begin_offset, end_offset) = deleted.offset_range;
+        let sfn_offset = end_offset - u64::from(DIR_ENTRY_SIZE);
+        // make sure the short name entry has not been changed since it was read
+        let mut stream = self.stream.clone();
+        stream.seek(SeekFrom::Start(sfn_offset))?;
+        let unchanged = match DirEntryData::deserialize(&mut stream)? {
+            DirEntryData::File(data) => data.to_bytes() == deleted.data.to_bytes(),
+            DirEntryData::Lfn(_) => false,
+        };
+        let entry_pos = stream.abs_pos().map(|pos| pos - u64::from(DIR_ENTRY_SIZE));
+        if !unchanged || entry_pos != Some(deleted.entry_pos) {
+            error!("deleted entry is not stored at position {}", deleted.entry_pos);
+            return Err(Error::InvalidInput);
+        }
+        let mut data = deleted.data.clone();
+        data.set_undeleted(first_char);
+        #[cfg(feature = "lfn")]
+        let lfn_utf16 = if deleted.first_name_byte == Some(first_char) {
+            self.check_deleted_lfn_entries(begin_offset, sfn_offset, &deleted.lfn_utf16, data.name())?
+        } else {
+            LfnBuffer::new()
+        };
+        #[cfg(feature = "lfn")]
+        let restore_lfn = lfn_utf16.len() > 0;
+        #[cfg(not(feature = "lfn"))]
+        let restore_lfn = false;
+        // check for name conflicts
+        #[cfg(feature = "lfn")]
+        let mut name_buf = [0_u8; LONG_NAME_BUFFER_LEN * 3];
+        #[cfg(feature = "lfn")]
+        let lfn = decode_ucs2(lfn_utf16.as_ucs2_units(), &mut name_buf);
+        for r in self.iter() {
+            let e = r?;
+            #[cfg(feature = "lfn")]
+            let lfn_conflict = restore_lfn && e.eq_name(lfn);
+            #[cfg(not(feature = "lfn"))]
+            let lfn_conflict = false;
+            if e.raw_short_name() == data.name() || lfn_conflict {
+                error!("undeleted entry conflicts with {:?}", e);
+                return Err(Error::AlreadyExists);
+            }
+        }
+        // restore the cluster chain first - it can fail without modifying anything
+        if let Some(first_cluster) = deleted.first_cluster {
+            // the size of a directory is unknown
+            let size = data.size().unwrap_or(0);
+            let num_clusters = cmp::max(self.fs.clusters_from_bytes(u64::from(size)), 1);
+            self.fs.alloc_cluster_range(None, first_cluster, num_clusters)?;
+        }
+        let begin_offset = if restore_lfn {
+            #[cfg(feature = "lfn")]
+            {
+                stream.seek(SeekFrom::Start(begin_offset))?;
+                for lfn_entry in LfnEntriesGenerator::new(lfn_utf16.as_ucs2_units(), lfn_checksum(data.name())) {
+                    lfn_entry.serialize(&mut stream)?;
+                }
+            }
+            begin_offset
+        } else {
+            sfn_offset
+        };
+        stream.seek(SeekFrom::Start(sfn_offset))?;
+        data.serialize(&mut stream)?;
+        Ok(DirEntry {
+            short_name: ShortName::new(data.name()),
+            data,
+            #[cfg(feature = "lfn")]
+            lfn_utf16,
+            fs: self.fs,
+            #[cfg(feature = "alloc")]
+            parent_path: self.path.clone(),
+            entry_pos: deleted.entry_pos,
+            offset_range: (begin_offset, end_offset),
+        })
+    }
+
+    /// Returns the long name stored in deleted long name entries in `begin_offset..sfn_offset` if they can be restored.
+    #[cfg(feature = "lfn")]
+    fn check_deleted_lfn_entries(
+        &self,
+        begin_offset: u64,
+        sfn_offset: u64,
+        lfn_utf16: &LfnBuffer,
+        short_name: &[u8; SFN_SIZE],
+    ) -> Result<LfnBuffer, Error<IO::Error>> {
+        let num_entries = (sfn_offset - begin_offset) / u64::from(DIR_ENTRY_SIZE);
+        let chksum = lfn_checksum(short_name);
+        let mut stream = self.stream.clone();
+        stream.seek(SeekFrom::Start(begin_offset))?;
+        for _ in 0..num_entries {
+            match DirEntryData::deserialize(&mut stream)? {
+                DirEntryData::Lfn(data) if data.is_deleted() && data.checksum() == chksum => {}
+                _ => {
+                    error!("deleted long name entries have been changed");
+                    return Err(Error::InvalidInput);
+                }
+            }
+        }
+        // entries are generated again from the name so their number must not change
+        if LfnEntriesGenerator::new(lfn_utf16.as_ucs2_units(), chksum).len() as u64 == num_entries {
+            Ok(lfn_utf16.clone())
+        } else {
+            warn!("long name does not match the number of deleted entries");
+            Ok(LfnBuffer::new())
+        }
+    }
+
     /// Renames or moves existing file or directory.
     ///
     /// `src_path` is a '/' separated source file path relative to self directory.
     /// `dst_path` is a '/' separated destination file path relative to `dst_dir`.
     /// `dst_dir` can be set to self directory if rename operation without moving is needed.
+    /// Renaming an entry to a name differing only in case (e.g. `README.md` to `readme.md`) changes the case of its
+    /// long name and keeps its short name. The `..` entry of a directory moved to another parent directory is updated
+    /// to point to the new parent.
     /// Make sure there is no reference to this file (no File instance) or filesystem corruption
     /// can happen.
     ///
@@ -389,102 +1700,459 @@ impl<'a, IO: ReadWriteSeek, TP: TimeProvider, OCC: OemCpConverter> Dir<'a, IO, T
     /// * `Error::NotFound` will be returned if `src_path` points to a non-existing directory entry or if `dst_path`
     ///   stripped from the last component does not point to an existing directory.
     /// * `Error::AlreadyExists` will be returned if `dst_path` points to an existing directory entry.
+    /// * `Error::InvalidInput` will be returned if `src_path` or `dst_path` is empty or refers to the directory it is
+    ///   relative to (e.g. `/` or `.`).
+    /// * `Error::InvalidFileNameLength`, `Error::UnsupportedFileNameCharacter` or `Error::UnsupportedWindowsFileName`
+    ///   will be returned if the destination name is invalid (see `FsOptions::filename_policy` and
+    ///   `FsOptions::windows_name_rules`). The source name is never validated so an entry with an
+    ///   invalid name created by another implementation can be renamed.
+    /// * `Error::NotEnoughSpace` will be returned if there is no space for the new entry in the destination
+    ///   directory. The source entry is left untouched in that case.
     /// * `Error::Io` will be returned if the underlying storage object returned an I/O error.
     pub fn rename(&self, src_path: &str, dst_dir: &Dir<IO, TP, OCC>, dst_path: &str) -> Result<(), Error<IO::Error>> {
         trace!("Dir::rename {} {}", src_path, dst_path);
-        // traverse source path
-        let (src_name, src_rest_opt) = split_path(src_path);
-        if let Some(rest) = src_rest_opt {
-            let e = self.find_entry(src_name, Some(true), None)?;
-            return e.to_dir().rename(rest, dst_dir, dst_path);
-        }
-        // traverse destination path
-        let (dst_name, dst_rest_opt) = split_path(dst_path);
-        if let Some(rest) = dst_rest_opt {
-            let e = dst_dir.find_entry(dst_name, Some(true), None)?;
-            return self.rename(src_path, &e.to_dir(), rest);
-        }
+        self.check_entry_path(src_path)?;
+        self.check_entry_path(dst_path)?;
+        // traverse source and destination paths
+        let (src_dir, src_name) = self.open_parent_dir(src_path)?;
+        let (dst_dir, dst_name) = dst_dir.open_parent_dir(dst_path)?;
+        // find existing file
+        let e = src_dir.find_path_component(src_path, src_name, None)?;
         // move/rename file
-        self.rename_internal(src_path, dst_dir, dst_path)
+        src_dir.rename_internal(&e, &dst_dir, dst_name, false)
+    }
+
+    /// Renames or moves existing file or directory replacing the destination if it exists.
+    ///
+    /// It works like `rename` but if `dst_path` points to an existing file, the file is removed (its clusters and
+    /// directory entries are freed) before the new entry is written. A directory can only replace an empty
+    /// directory. The destination is not restored if writing the new entry fails.
+    /// Make sure there is no reference to the source and destination files (no File instance) or filesystem
+    /// corruption can happen.
+    ///
+    /// # Errors
+    ///
+    /// Errors that can be returned:
+    ///
+    /// * `Error::InvalidInput` will be returned if a file would replace a directory or a directory would replace a
+    ///   file.
+    /// * `Error::DirectoryIsNotEmpty` will be returned if `dst_path` points to a directory which is not empty.
+    /// * Other errors are the same as for `rename` except `Error::AlreadyExists`.
+    pub fn rename_replace(
+        &self,
+        src_path: &str,
+        dst_dir: &Dir<IO, TP, OCC>,
+        dst_path: &str,
+    ) -> Result<(), Error<IO::Error>> {
+        trace!("Dir::rename_replace {} {}", src_path, dst_path);
+        self.check_entry_path(src_path)?;
+        self.check_entry_path(dst_path)?;
+        let (src_dir, src_name) = self.open_parent_dir(src_path)?;
+        let (dst_dir, dst_name) = dst_dir.open_parent_dir(dst_path)?;
+        let e = src_dir.find_path_component(src_path, src_name, None)?;
+        src_dir.rename_internal(&e, &dst_dir, dst_name, true)
+    }
+
+    /// Moves all files and subdirectories of this directory into `dst_dir`.
+    ///
+    /// Entries keep their names (short names are generated again) and `..` entries of moved subdirectories are
+    /// updated to point to `dst_dir`. All entries are checked before the first one is moved, so if any of them
+    /// cannot be moved because of a name collision nothing is changed. This directory is left empty.
+    /// Make sure there is no reference to files in this directory (no File instance) or filesystem corruption can
+    /// happen.
+    ///
+    /// Returns the number of moved entries.
+    ///
+    /// # Errors
+    ///
+    /// Errors that can be returned:
+    ///
+    /// * `Error::AlreadyExists` will be returned if an entry with the same name as one of the moved entries exists in
+    ///   `dst_dir`.
+    /// * `Error::InvalidInput` will be returned if `dst_dir` is this directory or its subdirectory.
+    /// * `Error::InvalidFileNameLength`, `Error::UnsupportedFileNameCharacter` or `Error::UnsupportedWindowsFileName`
+    ///   will be returned if a name of a moved entry cannot be created (see `FsOptions::filename_policy` and
+    ///   `FsOptions::windows_name_rules`).
+    /// * `Error::NotEnoughSpace` will be returned if there is no space for new entries in `dst_dir`. Entries moved
+    ///   before stay in `dst_dir`.
+    /// * `Error::Io` will be returned if the underlying storage object returned an I/O error.
+    #[cfg(feature = "alloc")]
+    pub fn move_contents(&self, dst_dir: &Dir<IO, TP, OCC>) -> Result<usize, Error<IO::Error>> {
+        trace!("Dir::move_contents");
+        self.move_contents_internal(dst_dir, false)
+    }
+
+    /// Moves files and subdirectories of this directory into `dst_dir` skipping names existing in `dst_dir`.
+    ///
+    /// It works like `move_contents` but entries colliding with an entry in `dst_dir` are left in this directory
+    /// instead of failing. Returns the number of moved entries (skipped entries are not counted).
+    ///
+    /// # Errors
+    ///
+    /// Errors are the same as for `move_contents` except `Error::AlreadyExists`.
+    #[cfg(feature = "alloc")]
+    pub fn move_contents_skip_existing(&self, dst_dir: &Dir<IO, TP, OCC>) -> Result<usize, Error<IO::Error>> {
+        trace!("Dir::move_contents_skip_existing");
+        self.move_contents_internal(dst_dir, true)
+    }
+
+    #[cfg(feature = "alloc")]
+    fn move_contents_internal(
+        &self,
+        dst_dir: &Dir<IO, TP, OCC>,
+        skip_existing: bool,
+    ) -> Result<usize, Error<IO::Error>> {
+        // moving a directory into itself would detach it from the tree
+        let mut ancestor = Some(dst_dir.clone());
+        let mut depth = 0;
+        while let Some(dir) = ancestor {
+            if dir.first_cluster() == self.first_cluster() {
+                error!("cannot move directory contents into the directory itself or its subdirectory");
+                return Err(Error::InvalidInput);
+            }
+            depth += 1;
+            if depth > MAX_DIR_DEPTH {
+                error!("directory tree is too deep");
+                return Err(Error::CorruptedFileSystem);
+            }
+            ancestor = dir.parent()?;
+        }
+        // collect entries first - the directory is modified while moving them
+        let mut entries = Vec::new();
+        for r in self.iter() {
+            let e = r?;
+            if e.is_dot_entry() {
+                continue;
+            }
+            let name = e.file_name();
+            if dst_dir.lookup_entry(&name, None, None)?.is_some() {
+                if skip_existing {
+                    continue;
+                }
+                error!("cannot move {}: destination entry exists", name);
+                return Err(Error::AlreadyExists);
+            }
+            dst_dir.validate_new_name(&name)?;
+            entries.push((e, name));
+        }
+        for (e, name) in &entries {
+            self.rename_internal(e, dst_dir, name, false)?;
+        }
+        Ok(entries.len())
+    }
+
+    /// Moves entries of this directory to its beginning, reclaiming slots of deleted entries, and frees clusters
+    /// that are no longer used.
+    ///
+    /// Entries keep their order and long name entries stay in front of their short name entries, so `.` and `..`
+    /// entries of a subdirectory remain the first two entries. Slots following the last entry are zeroed. A
+    /// directory always keeps its first cluster and the fixed size root directory of FAT12/FAT16 is never shrunk.
+    /// Positions of entries change so `DirEntry` instances (and values returned by `DirEntry::entry_pos`) obtained
+    /// before the call must not be used after it.
+    /// Make sure there is no reference to files or subdirectories of this directory (no File or Dir instance) or
+    /// filesystem corruption can happen - they would update their entries at the old positions.
+    ///
+    /// # Errors
+    ///
+    /// Errors that can be returned:
+    ///
+    /// * `Error::CorruptedFileSystem` will be returned if the cluster chain of the directory is shorter than its
+    ///   entries.
+    /// * `Error::Io` will be returned if the underlying storage object returned an I/O error.
+    pub fn compact(&self) -> Result<(), Error<IO::Error>> {
+        trace!("Dir::compact");
+        let mut iter = self.dir_iter(self.stream.clone(), false);
+        let mut stream = self.stream.clone();
+        let mut buf = [0_u8; DIR_ENTRY_SIZE as usize];
+        // position where the next entry is moved to
+        let mut write_pos: u64 = 0;
+        for r in iter.by_ref() {
+            let e = r?;
+            let (start, end) = e.offset_range;
+            if start != write_pos {
+                trace!("moving entry from {} to {}", start, write_pos);
+                // entries are only moved towards the beginning so every slot is read before it is overwritten
+                for offset in (0..end - start).step_by(DIR_ENTRY_SIZE as usize) {
+                    stream.seek(SeekFrom::Start(start + offset))?;
+                    stream.read_exact(&mut buf)?;
+                    stream.seek(SeekFrom::Start(write_pos + offset))?;
+                    stream.write_all(&buf)?;
+                }
+            }
+            write_pos += end - start;
+        }
+        // the iterator stopped just after the end marker or at the end of the directory
+        let old_end = iter.stream.seek(SeekFrom::Current(0))?;
+        let new_size = self.free_clusters_after(write_pos)?;
+        // zero moved and deleted entries left after the last entry - the first zeroed slot is the end marker
+        buf = [0_u8; DIR_ENTRY_SIZE as usize];
+        stream.seek(SeekFrom::Start(write_pos))?;
+        for _ in (write_pos..cmp::min(old_end, new_size)).step_by(DIR_ENTRY_SIZE as usize) {
+            stream.write_all(&buf)?;
+        }
+        Ok(())
+    }
+
+    /// Frees clusters of this directory not needed for `size` bytes of entries and returns the new size of the
+    /// directory (`u64::MAX` for the fixed size root directory).
+    fn free_clusters_after(&self, size: u64) -> Result<u64, Error<IO::Error>> {
+        if let Some(first_cluster) = self.stream.first_cluster() {
+            // a directory always keeps its first cluster
+            let num_clusters = cmp::max(self.fs.clusters_from_bytes(size), 1);
+            let last_cluster = if num_clusters == 1 {
+                first_cluster
+            } else {
+                let last_cluster_opt = self.fs.cluster_iter(first_cluster).nth((num_clusters - 2) as usize);
+                last_cluster_opt.ok_or_else(|| {
+                    error!("directory cluster chain is shorter than its entries");
+                    Error::CorruptedFileSystem
+                })??
+            };
+            if self.fs.cluster_iter(last_cluster).next().is_some() {
+                trace!("freeing directory clusters after {}", last_cluster);
+                self.fs.truncate_cluster_chain(last_cluster)?;
+            }
+            Ok(u64::from(num_clusters) * u64::from(self.fs.cluster_size()))
+        } else {
+            Ok(u64::MAX)
+        }
+    }
+
+    /// Copies existing file to a new file.
+    ///
+    /// `src_path` is a '/' separated source file path relative to self directory.
+    /// `dst_path` is a '/' separated destination file path relative to `dst_dir`.
+    /// Data is copied cluster by cluster (if the `alloc` feature is enabled, otherwise in 512 bytes chunks). If
+    /// copying fails the partially written destination file is removed.
+    ///
+    /// Returns the number of copied bytes.
+    ///
+    /// # Errors
+    ///
+    /// Errors that can be returned:
+    ///
+    /// * `Error::NotFound` will be returned if `src_path` points to a non-existing directory entry or if `dst_path`
+    ///   stripped from the last component does not point to an existing directory.
+    /// * `Error::AlreadyExists` will be returned if `dst_path` points to an existing directory entry.
+    /// * `Error::InvalidInput` will be returned if `src_path` points to a directory or if `src_path` or `dst_path` is
+    ///   empty or refers to the directory it is relative to (e.g. `/` or `.`).
+    /// * `Error::InvalidFileNameLength`, `Error::UnsupportedFileNameCharacter` or `Error::UnsupportedWindowsFileName`
+    ///   will be returned if the destination name is invalid (see `FsOptions::filename_policy` and
+    ///   `FsOptions::windows_name_rules`).
+    /// * `Error::NotEnoughSpace` will be returned if there is not enough free space for the copy.
+    /// * `Error::Io` will be returned if the underlying storage object returned an I/O error.
+    pub fn copy_file(
+        &self,
+        src_path: &str,
+        dst_dir: &Dir<IO, TP, OCC>,
+        dst_path: &str,
+    ) -> Result<u64, Error<IO::Error>> {
+        trace!("Dir::copy_file {} {}", src_path, dst_path);
+        let mut src = self.open_file(src_path)?;
+        self.check_entry_path(dst_path)?;
+        let (dst_dir, dst_name) = dst_dir.open_parent_dir(dst_path)?;
+        if dst_dir.lookup_entry(dst_name, None, None)?.is_some() {
+            return Err(Error::AlreadyExists);
+        }
+        let mut dst = dst_dir.create_file(dst_name)?;
+        match self.copy_data(&mut src, &mut dst) {
+            Ok(n) => Ok(n),
+            Err(err) => {
+                drop(dst);
+                if let Err(remove_err) = dst_dir.remove(dst_name) {
+                    warn!("failed to remove partially copied file: {:?}", remove_err);
+                }
+                Err(err)
+            }
+        }
+    }
+
+    fn copy_data(&self, src: &mut File<IO, TP, OCC>, dst: &mut File<IO, TP, OCC>) -> Result<u64, Error<IO::Error>> {
+        #[cfg(feature = "alloc")]
+        let mut buf = vec![0_u8; self.fs.cluster_size() as usize];
+        #[cfg(not(feature = "alloc"))]
+        let mut buf = [0_u8; 512];
+        let mut copied = 0;
+        loop {
+            let n = src.read(&mut buf)?;
+            if n == 0 {
+                break;
+            }
+            dst.write_all(&buf[..n])?;
+            copied += n as u64;
+        }
+        dst.flush()?;
+        Ok(copied)
+    }
+
+    /// Converts this directory into a handle confined to it.
+    ///
+    /// Paths passed to the returned `ScopedDir` cannot leave this directory - see `ScopedDir` for details.
+    #[cfg(feature = "alloc")]
+    #[must_use]
+    pub fn into_scoped(self) -> ScopedDir<'a, IO, TP, OCC> {
+        ScopedDir::new(self)
     }
 
     fn rename_internal(
         &self,
-        src_name: &str,
+        e: &DirEntry<'a, IO, TP, OCC>,
         dst_dir: &Dir<IO, TP, OCC>,
         dst_name: &str,
+        replace: bool,
     ) -> Result<(), Error<IO::Error>> {
-        trace!("Dir::rename_internal {} {}", src_name, dst_name);
-        // find existing file
-        let e = self.find_entry(src_name, None, None)?;
+        trace!("Dir::rename_internal {}", dst_name);
         // check if destionation filename is unused
-        let r = dst_dir.check_for_existence(dst_name, None)?;
-        let short_name = match r {
-            // destination file already exist
-            DirEntryOrShortName::DirEntry(ref dst_e) => {
-                // check if source and destination entry is the same
-                if e.is_same_entry(dst_e) {
-                    // nothing to do
-                    return Ok(());
+        let mut r = dst_dir.check_for_existence(dst_name, None)?;
+        if let DirEntryOrShortName::DirEntry(ref dst_e) = r {
+            // check if source and destination entry is the same
+            if e.is_same_entry(dst_e) {
+                // long names preserve case so a case-only rename rewrites the entry keeping its short name
+                if cfg!(feature = "lfn") && !e.eq_name_exact(dst_name) {
+                    dst_dir.write_entry(dst_name, e.data.clone(), None)?;
+                    self.free_entry_slots(e)?;
                 }
-                // destination file exists and it is not the same as source file - fail
+                return Ok(());
+            }
+            // destination file exists and it is not the same as source file - fail
+            if !replace {
+                return Err(Error::AlreadyExists);
+            }
+            if e.is_dir() != dst_e.is_dir() {
+                error!("cannot replace a file with a directory or a directory with a file");
+                return Err(Error::InvalidInput);
+            }
+            dst_dir.remove_entry(dst_e)?;
+            // the short name of the removed entry could be reused but a new one is generated like for a new entry
+            r = dst_dir.check_for_existence(dst_name, None)?;
+        }
+        let (short_name, free_entries) = match r {
+            DirEntryOrShortName::DirEntry(_) => {
+                // another entry with the same name shadowed by the removed one
+                error!("destination name is still used after removing the replaced entry");
                 return Err(Error::AlreadyExists);
             }
             // destionation file does not exist, short name has been generated
-            DirEntryOrShortName::ShortName(short_name) => short_name,
+            DirEntryOrShortName::ShortName(short_name, free_entries) => (short_name, free_entries),
         };
+        // save new directory entry before freeing the old one, so if writing fails (e.g. there is no space for new
+        // entries) the data is still reachable through the source entry
+        let sfn_entry = e.data.renamed(short_name);
+        dst_dir.write_entry(dst_name, sfn_entry, free_entries)?;
+        // a directory moved to another parent must point to it by its `..` entry
+        if e.is_dir() && dst_dir.first_cluster() != self.first_cluster() {
+            if let Some(cluster) = e.first_cluster() {
+                // the directory is opened without its entry - the old entry is deleted below
+                dst_dir.set_parent_of(&Dir::from_cluster(Some(cluster), self.fs))?;
+            }
+        }
         // free long and short name entries
-        let mut stream = self.stream.clone();
-        stream.seek(SeekFrom::Start(e.offset_range.0))?;
-        let num = ((e.offset_range.1 - e.offset_range.0) / u64::from(DIR_ENTRY_SIZE)) as usize;
-        for _ in 0..num {
-            let mut data = DirEntryData::deserialize(&mut stream)?;
-            trace!("removing LFN entry {:?}", data);
-            data.set_deleted();
-            stream.seek(SeekFrom::Current(-i64::from(DIR_ENTRY_SIZE)))?;
-            data.serialize(&mut stream)?;
+        self.free_entry_slots(e)?;
+        Ok(())
+    }
+
+    /// Makes sure the first `size` bytes of this directory are backed by allocated clusters.
+    ///
+    /// Missing clusters are allocated, zeroed and linked at the end of the cluster chain before any entry is written
+    /// into them, so stale data is never interpreted as directory entries. The fixed size root directory is not
+    /// affected.
+    fn grow(&self, size: u64) -> Result<(), Error<IO::Error>> {
+        if let Some(first_cluster) = self.stream.first_cluster() {
+            let cluster_size = u64::from(self.fs.cluster_size());
+            let mut last_cluster = first_cluster;
+            let mut allocated = cluster_size;
+            // the chain is followed only up to `size` so a loop after it does not matter
+            let mut iter = self.fs.cluster_iter(first_cluster);
+            while allocated < size {
+                last_cluster = if let Some(r) = iter.next() {
+                    r?
+                } else {
+                    trace!("growing directory by a cluster");
+                    self.fs.alloc_cluster(Some(last_cluster), true)?
+                };
+                allocated += cluster_size;
+            }
         }
-        // save new directory entry
-        let sfn_entry = e.data.renamed(short_name);
-        dst_dir.write_entry(dst_name, sfn_entry)?;
         Ok(())
     }
 
-    fn find_free_entries(&self, num_entries: u32) -> Result<DirRawStream<'a, IO, TP, OCC>, Error<IO::Error>> {
+    /// Finds space for `num_entries` entries and returns a stream positioned at its start.
+    ///
+    /// The returned flag is set if the space starts at the end marker (after the last used entry), so the end marker
+    /// has to be moved after the new entries.
+    #[allow(clippy::type_complexity)]
+    fn find_free_entries(&self, num_entries: u32) -> Result<(DirRawStream<'a, IO, TP, OCC>, bool), Error<IO::Error>> {
         let mut stream = self.stream.clone();
-        let mut first_free: u32 = 0;
-        let mut num_free: u32 = 0;
+        let mut finder = FreeEntriesFinder::new(num_entries);
         let mut i: u32 = 0;
+        let max_entries = self.fs.options.limits.max_dir_entries;
         loop {
+            if i >= max_entries {
+                error!("directory exceeds the entries limit");
+                return Err(Error::LimitExceeded);
+            }
             let raw_entry = DirEntryData::deserialize(&mut stream)?;
-            if raw_entry.is_end() {
-                // first unused entry - all remaining space can be used
-                if num_free == 0 {
-                    first_free = i;
-                }
-                let pos = u64::from(first_free * DIR_ENTRY_SIZE);
-                stream.seek(io::SeekFrom::Start(pos))?;
-                return Ok(stream);
-            } else if raw_entry.is_deleted() {
-                // free entry - calculate number of free entries in a row
-                if num_free == 0 {
-                    first_free = i;
-                }
-                num_free += 1;
-                if num_free == num_entries {
-                    // enough space for new file
-                    let pos = u64::from(first_free * DIR_ENTRY_SIZE);
-                    stream.seek(io::SeekFrom::Start(pos))?;
-                    return Ok(stream);
-                }
-            } else {
-                // used entry - start counting from 0
-                num_free = 0;
+            finder.process(i, &raw_entry);
+            if let Some(free_entries) = finder.found {
+                return self.open_free_entries(free_entries);
             }
             i += 1;
         }
     }
 
+    /// Returns a stream positioned at `free_entries` and a flag telling if they are at the end of the directory.
+    ///
+    /// Free entries at the end of the directory are checked against the entries limit and the directory is grown
+    /// if needed.
+    #[allow(clippy::type_complexity)]
+    fn open_free_entries(
+        &self,
+        free_entries: FreeEntries,
+    ) -> Result<(DirRawStream<'a, IO, TP, OCC>, bool), Error<IO::Error>> {
+        let mut stream = self.stream.clone();
+        if free_entries.at_end {
+            let max_entries = self.fs.options.limits.max_dir_entries;
+            if u64::from(free_entries.first) + u64::from(free_entries.num) > u64::from(max_entries) {
+                error!("directory exceeds the entries limit");
+                return Err(Error::LimitExceeded);
+            }
+            // make sure all entries fit so a fixed size root directory is not left with a partial entries run
+            let end_pos = u64::from(free_entries.first + free_entries.num) * u64::from(DIR_ENTRY_SIZE);
+            if stream.fixed_size().unwrap_or(u64::MAX) < end_pos {
+                error!("not enough space in the root directory");
+                return Err(Error::NotEnoughSpace);
+            }
+            self.grow(end_pos)?;
+        }
+        let pos = u64::from(free_entries.first * DIR_ENTRY_SIZE);
+        stream.seek(io::SeekFrom::Start(pos))?;
+        Ok((stream, free_entries.at_end))
+    }
+
+    /// Makes sure the directory end marker is placed at `offset` (just after the last used entry).
+    ///
+    /// Slots following the end marker are unused by definition but they can contain stale data (e.g. left by other
+    /// implementations), so when entries are appended the marker must be written explicitly - otherwise the stale
+    /// entries would become visible. Used entries are never overwritten and nothing is written if the directory
+    /// ends at `offset`.
+    fn write_end_marker(&self, offset: u64) -> Result<(), Error<IO::Error>> {
+        let mut stream = self.stream.clone();
+        stream.seek(SeekFrom::Start(offset))?;
+        let mut first_byte = [0_u8; 1];
+        match stream.read_exact(&mut first_byte) {
+            // directory is full - no marker is needed
+            Err(Error::UnexpectedEof) => return Ok(()),
+            Err(err) => return Err(err),
+            Ok(()) => {}
+        }
+        if first_byte[0] != DIR_ENTRY_END_MARKER {
+            trace!("writing end marker at {}", offset);
+            stream.seek(SeekFrom::Start(offset))?;
+            stream.write_all(&[DIR_ENTRY_END_MARKER])?;
+        }
+        Ok(())
+    }
+
     fn create_sfn_entry(
         &self,
         short_name: [u8; SFN_SIZE],
@@ -514,38 +2182,75 @@ impl<'a, IO: ReadWriteSeek, TP: TimeProvider, OCC: OemCpConverter> Dir<'a, IO, T
         &self,
         lfn_utf16: &LfnBuffer,
         short_name: &[u8; SFN_SIZE],
-    ) -> Result<(DirRawStream<'a, IO, TP, OCC>, u64), Error<IO::Error>> {
+        free_entries: Option<FreeEntries>,
+    ) -> Result<(DirRawStream<'a, IO, TP, OCC>, u64, bool), Error<IO::Error>> {
         // get short name checksum
         let lfn_chsum = lfn_checksum(short_name);
         // create LFN entries generator
         let lfn_iter = LfnEntriesGenerator::new(lfn_utf16.as_ucs2_units(), lfn_chsum);
-        // find space for new entries (multiple LFN entries and 1 SFN entry)
+        // find space for new entries (multiple LFN entries and 1 SFN entry) unless the caller already found it
         let num_entries = lfn_iter.len() as u32 + 1;
-        let mut stream = self.find_free_entries(num_entries)?;
+        let (mut stream, at_end) = match free_entries {
+            Some(free_entries) if free_entries.num == num_entries => self.open_free_entries(free_entries)?,
+            _ => self.find_free_entries(num_entries)?,
+        };
         let start_pos = stream.seek(io::SeekFrom::Current(0))?;
         // write LFN entries before SFN entry
         for lfn_entry in lfn_iter {
             lfn_entry.serialize(&mut stream)?;
         }
-        Ok((stream, start_pos))
+        Ok((stream, start_pos, at_end))
     }
 
+    fn validate_new_name(&self, name: &str) -> Result<(), Error<IO::Error>> {
+        // check if name doesn't contain unsupported characters
+        validate_long_name(name, self.fs.options.filename_policy)?;
+        if self.fs.options.windows_name_rules {
+            validate_windows_name(name)?;
+        }
+        if name.encode_utf16().count() > self.fs.options.limits.max_name_len {
+            error!("name {} exceeds the length limit", name);
+            return Err(Error::LimitExceeded);
+        }
+        Ok(())
+    }
+
+    /// Returns the number of entries (LFN entries and the SFN entry) needed by a new entry `name` with `short_name`.
+    fn num_entries_for_name(name: &str, short_name: &[u8; SFN_SIZE]) -> u32 {
+        if short_name_case_flags(name, short_name).is_some() {
+            return 1;
+        }
+        let lfn_utf16 = Self::encode_lfn_utf16(name);
+        LfnEntriesGenerator::new(lfn_utf16.as_ucs2_units(), 0).len() as u32 + 1
+    }
+
+    /// Writes a new entry `name` using free entries found by the caller if they are given and still match the
+    /// number of needed entries.
     fn write_entry(
         &self,
         name: &str,
         raw_entry: DirFileEntryData,
+        free_entries: Option<FreeEntries>,
     ) -> Result<DirEntry<'a, IO, TP, OCC>, Error<IO::Error>> {
         trace!("Dir::write_entry {}", name);
-        // check if name doesn't contain unsupported characters
-        validate_long_name(name)?;
-        // convert long name to UTF-16
-        let lfn_utf16 = Self::encode_lfn_utf16(name);
+        self.validate_new_name(name)?;
+        // a name differing from the short name only by case of its parts is stored using the lowercase flags
+        let mut raw_entry = raw_entry;
+        let case_flags = short_name_case_flags(name, raw_entry.name());
+        let (lowercase_basename, lowercase_ext) = case_flags.unwrap_or((false, false));
+        raw_entry.set_lowercase_flags(lowercase_basename, lowercase_ext);
+        // convert long name to UTF-16 - it is not needed if the short name is enough
+        let lfn_utf16 = Self::encode_lfn_utf16(if case_flags.is_some() { "" } else { name });
         // write LFN entries
-        let (mut stream, start_pos) = self.alloc_and_write_lfn_entries(&lfn_utf16, raw_entry.name())?;
+        let (mut stream, start_pos, at_end) =
+            self.alloc_and_write_lfn_entries(&lfn_utf16, raw_entry.name(), free_entries)?;
         // write short name entry
         raw_entry.serialize(&mut stream)?;
         // Get position directory stream after entries were written
         let end_pos = stream.seek(io::SeekFrom::Current(0))?;
+        if at_end {
+            self.write_end_marker(end_pos)?;
+        }
         // Get current absolute position on the storage
         // Unwrapping is safe because abs_pos() returns None only if stream is at position 0. This is not
         // the case because an entry was just written
@@ -562,29 +2267,170 @@ impl<'a, IO: ReadWriteSeek, TP: TimeProvider, OCC: OemCpConverter> Dir<'a, IO, T
             #[cfg(feature = "lfn")]
             lfn_utf16,
             fs: self.fs,
+            #[cfg(feature = "alloc")]
+            parent_path: self.path.clone(),
             entry_pos: start_abs_pos,
             offset_range: (start_pos, end_pos),
         })
     }
-}
+}
+
+// Note: derive cannot be used because of invalid bounds. See: https://github.com/rust-lang/rust/issues/26925
+impl<IO: ReadWriteSeek, TP: TimeProvider, OCC: OemCpConverter> Clone for Dir<'_, IO, TP, OCC> {
+    fn clone(&self) -> Self {
+        Self {
+            stream: self.stream.clone(),
+            fs: self.fs,
+            #[cfg(feature = "alloc")]
+            path: self.path.clone(),
+        }
+    }
+}
+
+/// A run of free directory entries where a new entry can be written.
+#[derive(Copy, Clone, Debug)]
+struct FreeEntries {
+    // index of the first entry
+    first: u32,
+    num: u32,
+    // the run starts at the end marker - the directory may need to grow
+    at_end: bool,
+}
+
+/// Finds the first run of free entries long enough for a new entry while the directory is read.
+#[derive(Copy, Clone, Debug)]
+struct FreeEntriesFinder {
+    num_entries: u32,
+    first_free: u32,
+    num_free: u32,
+    found: Option<FreeEntries>,
+}
+
+impl FreeEntriesFinder {
+    fn new(num_entries: u32) -> Self {
+        Self {
+            num_entries,
+            first_free: 0,
+            num_free: 0,
+            found: None,
+        }
+    }
+
+    /// Processes raw entry at index `i` - entries must be passed in order starting from the first one.
+    fn process(&mut self, i: u32, raw_entry: &DirEntryData) {
+        if self.found.is_some() {
+            return;
+        }
+        if raw_entry.is_end() {
+            // first unused entry - all remaining space can be used
+            if self.num_free == 0 {
+                self.first_free = i;
+            }
+            self.found = Some(FreeEntries {
+                first: self.first_free,
+                num: self.num_entries,
+                at_end: true,
+            });
+        } else if raw_entry.is_deleted() {
+            // free entry - calculate number of free entries in a row
+            if self.num_free == 0 {
+                self.first_free = i;
+            }
+            self.num_free += 1;
+            if self.num_free == self.num_entries {
+                // enough space for new file
+                self.found = Some(FreeEntries {
+                    first: self.first_free,
+                    num: self.num_entries,
+                    at_end: false,
+                });
+            }
+        } else {
+            // used entry - start counting from 0
+            self.num_free = 0;
+        }
+    }
+}
+
+/// A filter of entries returned by `Dir::iter_filtered`.
+///
+/// Entries with the `HIDDEN` attribute (both files and directories) are skipped unless `include_hidden` is used.
+/// Deleted entries and volume labels are always skipped.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct EntryFilter {
+    files: bool,
+    dirs: bool,
+    hidden: bool,
+}
+
+impl EntryFilter {
+    /// Creates a filter accepting files and directories.
+    #[must_use]
+    pub fn all() -> Self {
+        Self {
+            files: true,
+            dirs: true,
+            hidden: false,
+        }
+    }
 
-// Note: derive cannot be used because of invalid bounds. See: https://github.com/rust-lang/rust/issues/26925
-impl<IO: ReadWriteSeek, TP: TimeProvider, OCC: OemCpConverter> Clone for Dir<'_, IO, TP, OCC> {
-    fn clone(&self) -> Self {
+    /// Creates a filter accepting only files.
+    #[must_use]
+    pub fn files() -> Self {
         Self {
-            stream: self.stream.clone(),
-            fs: self.fs,
+            dirs: false,
+            ..Self::all()
+        }
+    }
+
+    /// Creates a filter accepting only directories.
+    ///
+    /// Note: "." and ".." entries are directories and are accepted too.
+    #[must_use]
+    pub fn dirs() -> Self {
+        Self {
+            files: false,
+            ..Self::all()
+        }
+    }
+
+    /// Sets if entries with the `HIDDEN` attribute are accepted.
+    #[must_use]
+    pub fn include_hidden(mut self, enabled: bool) -> Self {
+        self.hidden = enabled;
+        self
+    }
+
+    fn matches(self, attrs: FileAttributes) -> bool {
+        if !self.hidden && attrs.contains(FileAttributes::HIDDEN) {
+            return false;
+        }
+        if attrs.contains(FileAttributes::DIRECTORY) {
+            self.dirs
+        } else {
+            self.files
         }
     }
 }
 
+impl Default for EntryFilter {
+    fn default() -> Self {
+        Self::all()
+    }
+}
+
 /// An iterator over the directory entries.
 ///
-/// This struct is created by the `iter` method on `Dir`.
+/// This struct is created by the `iter` method on `Dir` and by its variants (e.g. `iter_filtered`).
 pub struct DirIter<'a, IO: ReadWriteSeek, TP, OCC> {
     stream: DirRawStream<'a, IO, TP, OCC>,
     fs: &'a FileSystem<IO, TP, OCC>,
     skip_volume: bool,
+    filter: Option<EntryFilter>,
+    free_entries: Option<FreeEntriesFinder>,
+    with_deleted: bool,
+    #[cfg(feature = "alloc")]
+    dir_path: Option<Rc<str>>,
     err: bool,
 }
 
@@ -594,6 +2440,11 @@ impl<'a, IO: ReadWriteSeek, TP, OCC> DirIter<'a, IO, TP, OCC> {
             stream,
             fs,
             skip_volume,
+            filter: None,
+            free_entries: None,
+            with_deleted: false,
+            #[cfg(feature = "alloc")]
+            dir_path: None,
             err: false,
         }
     }
@@ -610,18 +2461,67 @@ impl<'a, IO: ReadWriteSeek, TP: TimeProvider, OCC> DirIter<'a, IO, TP, OCC> {
         }
     }
 
+    fn accepts(&self, data: &DirFileEntryData) -> bool {
+        match self.filter {
+            Some(filter) => !data.is_volume() && filter.matches(data.attributes()),
+            None => true,
+        }
+    }
+
     #[allow(clippy::type_complexity)]
     fn read_dir_entry(&mut self) -> Result<Option<DirEntry<'a, IO, TP, OCC>>, Error<IO::Error>> {
         trace!("DirIter::read_dir_entry");
+        match self.read_raw_entry()? {
+            RawDirEntry::Live(e) => Ok(Some(e)),
+            RawDirEntry::Deleted(_) | RawDirEntry::End => Ok(None),
+        }
+    }
+
+    fn read_raw_entry(&mut self) -> Result<RawDirEntry<'a, IO, TP, OCC>, Error<IO::Error>> {
         let mut lfn_builder = LongNameBuilder::new();
+        // The builder is big so it is created only when deleted entries are returned
+        let mut deleted_lfn_builder = if self.with_deleted {
+            Some(DeletedLongNameBuilder::new())
+        } else {
+            None
+        };
         let mut offset = self.stream.seek(SeekFrom::Current(0))?;
         let mut begin_offset = offset;
+        let max_offset = u64::from(self.fs.options.limits.max_dir_entries) * u64::from(DIR_ENTRY_SIZE);
         loop {
+            if offset >= max_offset {
+                error!("directory exceeds the entries limit");
+                return Err(Error::LimitExceeded);
+            }
             let raw_entry = DirEntryData::deserialize(&mut self.stream)?;
+            if let Some(ref mut finder) = self.free_entries {
+                finder.process((offset / u64::from(DIR_ENTRY_SIZE)) as u32, &raw_entry);
+            }
             offset += u64::from(DIR_ENTRY_SIZE);
             // Check if this is end of dir
             if raw_entry.is_end() {
-                return Ok(None);
+                return Ok(RawDirEntry::End);
+            }
+            if let Some(ref mut deleted_lfn_builder) = deleted_lfn_builder {
+                if raw_entry.is_deleted() {
+                    lfn_builder.clear();
+                    begin_offset = offset;
+                    match raw_entry {
+                        DirEntryData::File(data) => {
+                            trace!("deleted file entry");
+                            let abs_pos = self.stream.abs_pos().unwrap() - u64::from(DIR_ENTRY_SIZE);
+                            let fat_type = self.fs.fat_type();
+                            let info = deleted_lfn_builder.finish(data, abs_pos, offset, fat_type);
+                            return Ok(RawDirEntry::Deleted(info));
+                        }
+                        DirEntryData::Lfn(data) => {
+                            trace!("deleted lfn entry");
+                            deleted_lfn_builder.process(&data, offset - u64::from(DIR_ENTRY_SIZE));
+                            continue;
+                        }
+                    }
+                }
+                deleted_lfn_builder.clear();
             }
             // Check if this is deleted or volume ID entry
             if self.should_ship_entry(&raw_entry) {
@@ -631,6 +2531,12 @@ impl<'a, IO: ReadWriteSeek, TP: TimeProvider, OCC> DirIter<'a, IO, TP, OCC> {
                 continue;
             }
             match raw_entry {
+                DirEntryData::File(ref data) if !self.accepts(data) => {
+                    // Skip the entry before its long name is validated and moved out of the builder
+                    trace!("entry filtered out");
+                    lfn_builder.clear();
+                    begin_offset = offset;
+                }
                 DirEntryData::File(data) => {
                     // Get current absolute position on the storage
                     // Unwrapping is safe because abs_pos() returns None only if stream is at position 0. This is not
@@ -642,15 +2548,26 @@ impl<'a, IO: ReadWriteSeek, TP: TimeProvider, OCC> DirIter<'a, IO, TP, OCC> {
                     let abs_pos = end_abs_pos - u64::from(DIR_ENTRY_SIZE);
                     // Check if LFN checksum is valid
                     lfn_builder.validate_chksum(data.name());
+                    #[cfg(feature = "lfn")]
+                    let lfn_utf16 = lfn_builder.into_buf();
+                    #[cfg(feature = "lfn")]
+                    {
+                        if lfn_utf16.len() > self.fs.options.limits.max_name_len {
+                            error!("long name exceeds the length limit");
+                            return Err(Error::LimitExceeded);
+                        }
+                    }
                     // Return directory entry
                     let short_name = ShortName::new(data.name());
                     trace!("file entry {:?}", data.name());
-                    return Ok(Some(DirEntry {
+                    return Ok(RawDirEntry::Live(DirEntry {
                         data,
                         short_name,
                         #[cfg(feature = "lfn")]
-                        lfn_utf16: lfn_builder.into_buf(),
+                        lfn_utf16,
                         fs: self.fs,
+                        #[cfg(feature = "alloc")]
+                        parent_path: self.dir_path.clone(),
                         entry_pos: abs_pos,
                         offset_range: (begin_offset, offset),
                     }));
@@ -673,6 +2590,11 @@ impl<IO: ReadWriteSeek, TP, OCC> Clone for DirIter<'_, IO, TP, OCC> {
             fs: self.fs,
             err: self.err,
             skip_volume: self.skip_volume,
+            filter: self.filter,
+            free_entries: self.free_entries,
+            with_deleted: self.with_deleted,
+            #[cfg(feature = "alloc")]
+            dir_path: self.dir_path.clone(),
         }
     }
 }
@@ -696,16 +2618,67 @@ impl<'a, IO: ReadWriteSeek, TP: TimeProvider, OCC> Iterator for DirIter<'a, IO,
     }
 }
 
+/// A directory entry returned by `RawDirIter`.
+pub enum RawDirEntry<'a, IO: ReadWriteSeek, TP, OCC> {
+    /// An entry visible in the directory.
+    Live(DirEntry<'a, IO, TP, OCC>),
+    /// A deleted entry with fields preserved from before the deletion.
+    Deleted(DeletedEntryInfo),
+    /// The end of the directory.
+    End,
+}
+
+impl<IO: ReadWriteSeek, TP, OCC> fmt::Debug for RawDirEntry<'_, IO, TP, OCC> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RawDirEntry::Live(e) => f.debug_tuple("Live").field(e).finish(),
+            RawDirEntry::Deleted(info) => f.debug_tuple("Deleted").field(info).finish(),
+            RawDirEntry::End => f.write_str("End"),
+        }
+    }
+}
+
+/// An iterator over the directory entries including deleted ones.
+///
+/// This struct is created by the `iter_raw` method on `Dir`. The last item is `RawDirEntry::End` unless an error
+/// occurs.
+pub struct RawDirIter<'a, IO: ReadWriteSeek, TP, OCC> {
+    inner: DirIter<'a, IO, TP, OCC>,
+    ended: bool,
+}
+
+impl<'a, IO: ReadWriteSeek, TP: TimeProvider, OCC> Iterator for RawDirIter<'a, IO, TP, OCC> {
+    type Item = Result<RawDirEntry<'a, IO, TP, OCC>, Error<IO::Error>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.ended {
+            return None;
+        }
+        let r = self.inner.read_raw_entry();
+        if let Ok(RawDirEntry::End) | Err(_) = r {
+            self.ended = true;
+        }
+        Some(r)
+    }
+}
+
 #[rustfmt::skip]
-fn validate_long_name<E: IoError>(name: &str) -> Result<(), Error<E>> {
+fn validate_long_name<E: IoError>(name: &str, policy: FilenamePolicy) -> Result<(), Error<E>> {
     // check if length is valid
     if name.is_empty() {
         return Err(Error::InvalidFileNameLength);
     }
-    if name.len() > MAX_LONG_NAME_LEN {
+    // the limit applies to UTF-16 code units stored in LFN entries (not UTF-8 bytes)
+    if name.encode_utf16().count() > MAX_LONG_NAME_LEN {
         return Err(Error::InvalidFileNameLength);
     }
     // check if there are only valid characters
+    if policy == FilenamePolicy::Relaxed {
+        if name.chars().any(|c| c.is_control() || c == '/') {
+            return Err(Error::UnsupportedFileNameCharacter);
+        }
+        return Ok(());
+    }
     for c in name.chars() {
         match c {
             'a'..='z' | 'A'..='Z' | '0'..='9'
@@ -718,6 +2691,91 @@ fn validate_long_name<E: IoError>(name: &str) -> Result<(), Error<E>> {
     Ok(())
 }
 
+fn validate_windows_name<E: IoError>(name: &str) -> Result<(), Error<E>> {
+    const DEVICE_NAMES: [&str; 4] = ["CON", "PRN", "AUX", "NUL"];
+    const NUMBERED_DEVICE_NAMES: [&str; 2] = ["COM", "LPT"];
+    if name.ends_with('.') || name.ends_with(' ') {
+        error!("name {:?} ends with a dot or a space", name);
+        return Err(Error::UnsupportedWindowsFileName {
+            rule: WindowsNameRule::TrailingDotOrSpace,
+        });
+    }
+    // the extension and spaces before it are ignored by Windows when checking for a device name
+    let base_name = name.split('.').next().unwrap_or("").trim_end_matches(' ');
+    let bytes = base_name.as_bytes();
+    let is_device_name = match bytes.len() {
+        3 => DEVICE_NAMES.iter().any(|n| bytes.eq_ignore_ascii_case(n.as_bytes())),
+        4 => {
+            NUMBERED_DEVICE_NAMES
+                .iter()
+                .any(|n| bytes[..3].eq_ignore_ascii_case(n.as_bytes()))
+                && (b'1'..=b'9').contains(&bytes[3])
+        }
+        _ => false,
+    };
+    if is_device_name {
+        error!("name {} is a reserved device name", name);
+        return Err(Error::UnsupportedWindowsFileName {
+            rule: WindowsNameRule::ReservedDeviceName,
+        });
+    }
+    Ok(())
+}
+
+/// Checks if `name` can be stored as `short_name` with the NT lowercase flags instead of a long name.
+///
+/// It is possible if both parts of the name (basename and extension) are equal to the short name parts ignoring case
+/// and letters in each part are either all lowercase or all uppercase. Returns the lowercase flags of the basename and
+/// the extension.
+fn short_name_case_flags(name: &str, short_name: &[u8; SFN_SIZE]) -> Option<(bool, bool)> {
+    fn part_case(part: &str, short_part: &[u8]) -> Option<bool> {
+        let short_len = short_part
+            .iter()
+            .rposition(|&c| c != SFN_PADDING)
+            .map_or(0, |pos| pos + 1);
+        if !part.as_bytes().eq_ignore_ascii_case(&short_part[..short_len]) {
+            return None;
+        }
+        let has_lower = part.bytes().any(|c| c.is_ascii_lowercase());
+        let has_upper = part.bytes().any(|c| c.is_ascii_uppercase());
+        if has_lower && has_upper {
+            None
+        } else {
+            Some(has_lower)
+        }
+    }
+    let (basename, ext) = match name.rfind('.') {
+        // a trailing dot is not a part of the short name
+        Some(dot_index) if dot_index + 1 == name.len() => return None,
+        Some(dot_index) => (&name[..dot_index], &name[dot_index + 1..]),
+        None => (name, ""),
+    };
+    if basename.is_empty() {
+        return None;
+    }
+    Some((
+        part_case(basename, &short_name[..8])?,
+        part_case(ext, &short_name[8..])?,
+    ))
+}
+
+/// Returns `path` without its last component.
+#[cfg(feature = "alloc")]
+pub(crate) fn parent_path(path: &str) -> &str {
+    path.rfind('/').map_or("", |i| &path[..i])
+}
+
+/// Decodes a long name into `buf` replacing invalid surrogates with the replacement character.
+#[cfg(feature = "lfn")]
+fn decode_ucs2<'b>(ucs2_units: &[u16], buf: &'b mut [u8; LONG_NAME_BUFFER_LEN * 3]) -> &'b str {
+    let mut len = 0;
+    for c in decode_lfn_lossy(ucs2_units) {
+        len += c.encode_utf8(&mut buf[len..]).len();
+    }
+    // only complete characters have been encoded
+    str::from_utf8(&buf[..len]).unwrap()
+}
+
 fn lfn_checksum(short_name: &[u8; SFN_SIZE]) -> u8 {
     let mut chksum = num::Wrapping(0_u8);
     for b in short_name {
@@ -726,59 +2784,23 @@ fn lfn_checksum(short_name: &[u8; SFN_SIZE]) -> u8 {
     chksum.0
 }
 
-#[cfg(all(feature = "lfn", feature = "alloc"))]
-#[derive(Clone)]
-pub(crate) struct LfnBuffer {
-    ucs2_units: Vec<u16>,
-}
-
-const MAX_LONG_NAME_LEN: usize = 255;
+pub(crate) const MAX_LONG_NAME_LEN: usize = 255;
 
 #[cfg(feature = "lfn")]
 const MAX_LONG_DIR_ENTRIES: usize = (MAX_LONG_NAME_LEN + LFN_PART_LEN - 1) / LFN_PART_LEN;
 
-#[cfg(all(feature = "lfn", not(feature = "alloc")))]
+#[cfg(feature = "lfn")]
 const LONG_NAME_BUFFER_LEN: usize = MAX_LONG_DIR_ENTRIES * LFN_PART_LEN;
 
-#[cfg(all(feature = "lfn", not(feature = "alloc")))]
+// Fixed size buffer is used even if the `alloc` feature is enabled so reading directory entries does not allocate
+#[cfg(feature = "lfn")]
 #[derive(Clone)]
 pub(crate) struct LfnBuffer {
     ucs2_units: [u16; LONG_NAME_BUFFER_LEN],
     len: usize,
 }
 
-#[cfg(all(feature = "lfn", feature = "alloc"))]
-impl LfnBuffer {
-    fn new() -> Self {
-        Self {
-            ucs2_units: Vec::<u16>::new(),
-        }
-    }
-
-    fn from_ucs2_units<I: Iterator<Item = u16>>(usc2_units: I) -> Self {
-        Self {
-            ucs2_units: usc2_units.collect(),
-        }
-    }
-
-    fn clear(&mut self) {
-        self.ucs2_units.clear();
-    }
-
-    pub(crate) fn len(&self) -> usize {
-        self.ucs2_units.len()
-    }
-
-    fn set_len(&mut self, len: usize) {
-        self.ucs2_units.resize(len, 0_u16);
-    }
-
-    pub(crate) fn as_ucs2_units(&self) -> &[u16] {
-        &self.ucs2_units
-    }
-}
-
-#[cfg(all(feature = "lfn", not(feature = "alloc")))]
+#[cfg(feature = "lfn")]
 impl LfnBuffer {
     fn new() -> Self {
         Self {
@@ -939,6 +2961,121 @@ impl LongNameBuilder {
     fn validate_chksum(&mut self, _short_name: &[u8; SFN_SIZE]) {}
 }
 
+/// Collects long name entries preceding a deleted short name entry.
+///
+/// The order field of deleted entries is overwritten so the entries are kept in the disk order and only their
+/// checksums are compared.
+#[cfg(feature = "lfn")]
+struct DeletedLongNameBuilder {
+    ucs2_units: [u16; LONG_NAME_BUFFER_LEN],
+    num: usize,
+    chksum: u8,
+    begin_offset: u64,
+}
+
+#[cfg(feature = "lfn")]
+impl DeletedLongNameBuilder {
+    fn new() -> Self {
+        Self {
+            ucs2_units: [0_u16; LONG_NAME_BUFFER_LEN],
+            num: 0,
+            chksum: 0,
+            begin_offset: 0,
+        }
+    }
+
+    fn clear(&mut self) {
+        self.num = 0;
+    }
+
+    fn process(&mut self, data: &DirLfnEntryData, offset: u64) {
+        if self.num > 0 && (data.checksum() != self.chksum || self.num == MAX_LONG_DIR_ENTRIES) {
+            // Entries belong to another deleted name
+            self.num = 0;
+        }
+        if self.num == 0 {
+            self.chksum = data.checksum();
+            self.begin_offset = offset;
+        }
+        let pos = self.num * LFN_PART_LEN;
+        data.copy_name_to_slice(&mut self.ucs2_units[pos..pos + LFN_PART_LEN]);
+        self.num += 1;
+    }
+
+    fn recover_first_byte(&self, short_name: &[u8; SFN_SIZE]) -> Option<u8> {
+        // The checksum depends on the first byte bijectively so at most one byte matches
+        let mut name = *short_name;
+        (0x21..=0xFF_u8).filter(|&b| b != DIR_ENTRY_DELETED_FLAG).find(|&b| {
+            name[0] = b;
+            lfn_checksum(&name) == self.chksum
+        })
+    }
+
+    fn finish(
+        &mut self,
+        data: DirFileEntryData,
+        entry_pos: u64,
+        end_offset: u64,
+        fat_type: FatType,
+    ) -> DeletedEntryInfo {
+        let first_name_byte = if self.num > 0 {
+            self.recover_first_byte(data.name())
+        } else {
+            None
+        };
+        let (lfn_utf16, begin_offset) = if first_name_byte.is_some() {
+            // The first entry on the disk contains the last part of the name
+            let parts = self.ucs2_units[..self.num * LFN_PART_LEN].chunks(LFN_PART_LEN).rev();
+            let mut lfn = LfnBuffer::from_ucs2_units(parts.flatten().copied());
+            let new_len = lfn
+                .as_ucs2_units()
+                .iter()
+                .rposition(|c| *c != LFN_PADDING && *c != 0)
+                .map_or(0, |n| n + 1);
+            lfn.set_len(new_len);
+            (lfn, self.begin_offset)
+        } else {
+            (LfnBuffer::new(), end_offset - u64::from(DIR_ENTRY_SIZE))
+        };
+        self.num = 0;
+        DeletedEntryInfo {
+            first_cluster: data.first_cluster(fat_type),
+            data,
+            lfn_utf16,
+            first_name_byte,
+            entry_pos,
+            offset_range: (begin_offset, end_offset),
+        }
+    }
+}
+
+// Dummy implementation for non-alloc build
+#[cfg(not(feature = "lfn"))]
+struct DeletedLongNameBuilder {}
+#[cfg(not(feature = "lfn"))]
+impl DeletedLongNameBuilder {
+    fn new() -> Self {
+        DeletedLongNameBuilder {}
+    }
+    fn clear(&mut self) {}
+    fn process(&mut self, _data: &DirLfnEntryData, _offset: u64) {}
+    fn finish(
+        &mut self,
+        data: DirFileEntryData,
+        entry_pos: u64,
+        end_offset: u64,
+        fat_type: FatType,
+    ) -> DeletedEntryInfo {
+        DeletedEntryInfo {
+            first_cluster: data.first_cluster(fat_type),
+            data,
+            first_name_byte: None,
+            entry_pos,
+            offset_range: (end_offset - u64::from(DIR_ENTRY_SIZE), end_offset),
+        }
+    }
+}
+
 #[cfg(feature = "lfn")]
 struct LfnEntriesGenerator<'a> {
     name_parts_iter: iter::Rev<slice::Chunks<'a, u16>>,
@@ -1032,25 +3169,44 @@ impl Iterator for LfnEntriesGenerator {
 #[cfg(not(feature = "lfn"))]
 impl ExactSizeIterator for LfnEntriesGenerator {}
 
+/// Generates a short name for a new entry based on its long name and short names of existing entries.
+///
+/// Existing names are only used to build sets of taken candidates (`add_existing` does not depend on the names
+/// passed before), so the result of `generate` depends only on the set of existing names, not on the order they
+/// were found in the directory. Candidates are checked in a fixed order:
+///
+/// 1. the name converted to 8.3 form if the conversion was lossless (e.g. `FOO.TXT`),
+/// 2. a 6-character prefix with numeric tails 1-4 (e.g. `TEXTFI~1.TXT`),
+/// 3. a 2-character prefix with a checksum of the long name and numeric tails 1-9 (e.g. `TE527D~1.TXT`), the checksum
+///    algorithm depends on `ShortNameAlgorithm` and this step is skipped for `ShortNameAlgorithm::NumericTail`,
+/// 4. a prefix with numeric tails starting from 5, shortened when the tail gets longer (e.g. `TEXTFI~5.TXT`,
+///    `TEXTF~10.TXT`, `TEXT~100.TXT`).
+///
+/// Numeric tails are tracked in windows of 64 numbers. If all candidates in the current window are taken
+/// `next_iteration` moves to the next window and the directory has to be scanned again.
 #[derive(Default, Debug, Clone)]
 struct ShortNameGenerator {
     chksum: u16,
-    long_prefix_bitmap: u16,
+    algorithm: ShortNameAlgorithm,
+    tail_base: u32,
+    tail_bitmap: u64,
     prefix_chksum_bitmap: u16,
     name_fits: bool,
     lossy_conv: bool,
     exact_match: bool,
     basename_len: usize,
     short_name: [u8; SFN_SIZE],
+    volume_label: Option<[u8; SFN_SIZE]>,
 }
 
 impl ShortNameGenerator {
-    fn new(name: &str) -> Self {
+    fn new(name: &str, algorithm: ShortNameAlgorithm) -> Self {
         // padded by ' '
         let mut short_name = [SFN_PADDING; SFN_SIZE];
         // find extension after last dot
         // Note: short file name cannot start with the extension
-        let dot_index_opt = name[1..].rfind('.').map(|index| index + 1);
+        let first_char_len = name.chars().next().map_or(0, char::len_utf8);
+        let dot_index_opt = name[first_char_len..].rfind('.').map(|index| index + first_char_len);
         // copy basename (part of filename before a dot)
         let basename_src = dot_index_opt.map_or(name, |dot_index| &name[..dot_index]);
         let (basename_len, basename_fits, basename_lossy) =
@@ -1060,9 +3216,13 @@ impl ShortNameGenerator {
             let (_, ext_fits, ext_lossy) = Self::copy_short_name_part(&mut short_name[8..11], &name[dot_index + 1..]);
             (basename_fits && ext_fits, basename_lossy || ext_lossy)
         });
-        let chksum = Self::checksum(name);
+        let chksum = match algorithm {
+            ShortNameAlgorithm::Windows => Self::windows_checksum(name),
+            _ => Self::checksum(name),
+        };
         Self {
             chksum,
+            algorithm,
             name_fits,
             lossy_conv,
             basename_len,
@@ -1117,6 +3277,7 @@ impl ShortNameGenerator {
     }
 
     fn add_existing(&mut self, short_name: &[u8; SFN_SIZE]) {
+        // Note: only flags and bitmap bits are set here so the order of calls does not matter
         // check for exact match collision
         if short_name == &self.short_name {
             self.exact_match = true;
@@ -1128,18 +3289,47 @@ impl ShortNameGenerator {
         self.check_for_short_prefix_collision(short_name);
     }
 
+    fn add_volume_label(&mut self, volume_label: &[u8; SFN_SIZE]) {
+        // volume label does not collide with file names so it is only remembered
+        self.volume_label = Some(*volume_label);
+    }
+
+    fn is_volume_label(&self, short_name: &[u8; SFN_SIZE]) -> bool {
+        self.volume_label.as_ref() == Some(short_name)
+    }
+
     fn check_for_long_prefix_collision(&mut self, short_name: &[u8; SFN_SIZE]) {
-        // check for long prefix form collision (TEXTFI~1.TXT)
-        let long_prefix_len = cmp::min(self.basename_len, 6);
-        if short_name[long_prefix_len] != b'~' {
+        // check for long prefix form collision (TEXTFI~1.TXT, TEXTF~10.TXT)
+        let basename = &short_name[..8];
+        let basename_len = basename
+            .iter()
+            .rposition(|&c| c != SFN_PADDING)
+            .map_or(0, |pos| pos + 1);
+        // numeric tail is a sequence of digits not starting with zero and preceded by a tilde
+        let digits_len = basename[..basename_len]
+            .iter()
+            .rev()
+            .take_while(|c| c.is_ascii_digit())
+            .count();
+        if digits_len == 0 || digits_len >= basename_len {
             return;
         }
-        if let Some(num_suffix) = char::from(short_name[long_prefix_len + 1]).to_digit(10) {
-            let long_prefix_matches = short_name[..long_prefix_len] == self.short_name[..long_prefix_len];
-            let ext_matches = short_name[8..] == self.short_name[8..];
-            if long_prefix_matches && ext_matches {
-                self.long_prefix_bitmap |= 1 << num_suffix;
-            }
+        let tilde_pos = basename_len - digits_len - 1;
+        let digits = &basename[tilde_pos + 1..basename_len];
+        if basename[tilde_pos] != b'~' || digits[0] == b'0' {
+            return;
+        }
+        // prefix is shortened so the name and its tail fit in 8 characters
+        let long_prefix_len = cmp::min(self.basename_len, 7 - digits.len());
+        if tilde_pos != long_prefix_len
+            || short_name[..long_prefix_len] != self.short_name[..long_prefix_len]
+            || short_name[8..] != self.short_name[8..]
+        {
+            return;
+        }
+        let num = digits.iter().fold(0, |acc, &c| acc * 10 + u32::from(c - b'0'));
+        if num >= self.tail_base && num - self.tail_base < 64 {
+            self.tail_bitmap |= 1 << (num - self.tail_base);
         }
     }
 
@@ -1171,22 +3361,51 @@ impl ShortNameGenerator {
         chksum.0
     }
 
+    fn windows_checksum(name: &str) -> u16 {
+        // hash used by Windows NT and later (reverse engineered), computed over UTF-16 code units of the name
+        let mut chksum = num::Wrapping(0_i32);
+        for c in name.encode_utf16() {
+            chksum = chksum * num::Wrapping(0x25) + num::Wrapping(i32::from(c));
+        }
+        let mut temp = (chksum * num::Wrapping(314_159_269)).0.wrapping_abs();
+        let quotient = ((i64::from(temp) * 1_152_921_497) >> 60 & 0xF) as i32;
+        temp = temp.wrapping_sub(quotient.wrapping_mul(1_000_000_007));
+        // reverse order of nibbles
+        let bytes = temp.to_le_bytes();
+        let x = u16::from_le_bytes([bytes[0], bytes[1]]);
+        (x >> 12) | ((x >> 4) & 0x00F0) | ((x << 4) & 0x0F00) | (x << 12)
+    }
+
     fn generate(&self) -> Result<[u8; SFN_SIZE], Error<()>> {
         if !self.lossy_conv && self.name_fits && !self.exact_match {
             // If there was no lossy conversion and name fits into
             // 8.3 convention and there is no collision return it as is
             return Ok(self.short_name);
         }
-        // Try using long 6-characters prefix
-        for i in 1..5 {
-            if self.long_prefix_bitmap & (1 << i) == 0 {
-                return Ok(self.build_prefixed_name(i, false));
+        let first_tail = if self.tail_base == 0 && self.algorithm == ShortNameAlgorithm::NumericTail {
+            1
+        } else if self.tail_base == 0 {
+            // Try using long 6-characters prefix
+            for i in 1..5 {
+                if self.tail_bitmap & (1 << i) == 0 {
+                    return Ok(self.build_prefixed_name(i, false));
+                }
             }
-        }
-        // Try prefix with checksum
-        for i in 1..10 {
-            if self.prefix_chksum_bitmap & (1 << i) == 0 {
-                return Ok(self.build_prefixed_name(i, true));
+            // Try prefix with checksum
+            for i in 1..10 {
+                if self.prefix_chksum_bitmap & (1 << i) == 0 {
+                    return Ok(self.build_prefixed_name(i, true));
+                }
+            }
+            5
+        } else {
+            self.tail_base
+        };
+        // Try remaining numeric tails in the current window
+        let last_tail = cmp::min(self.tail_base + 63, MAX_NUMERIC_TAIL);
+        for num in first_tail..=last_tail {
+            if self.tail_bitmap & (1 << (num - self.tail_base)) == 0 {
+                return Ok(self.build_prefixed_name(num, false));
             }
         }
         // Too many collisions - fail
@@ -1194,13 +3413,17 @@ impl ShortNameGenerator {
     }
 
     fn next_iteration(&mut self) {
-        // Try different checksum in next iteration
-        self.chksum = (num::Wrapping(self.chksum) + num::Wrapping(1)).0;
+        // Try next window of numeric tails in next iteration
+        self.tail_base += 64;
         // Zero bitmaps
-        self.long_prefix_bitmap = 0;
+        self.tail_bitmap = 0;
         self.prefix_chksum_bitmap = 0;
     }
 
+    fn is_exhausted(&self) -> bool {
+        self.tail_base > MAX_NUMERIC_TAIL
+    }
+
     fn build_prefixed_name(&self, num: u32, with_chksum: bool) -> [u8; SFN_SIZE] {
         let mut buf = [SFN_PADDING; SFN_SIZE];
         let prefix_len = if with_chksum {
@@ -1209,16 +3432,31 @@ impl ShortNameGenerator {
             buf[prefix_len..prefix_len + 4].copy_from_slice(&Self::u16_to_hex(self.chksum));
             prefix_len + 4
         } else {
-            let prefix_len = cmp::min(self.basename_len, 6);
+            let prefix_len = cmp::min(self.basename_len, 7 - Self::num_digits(num));
             buf[..prefix_len].copy_from_slice(&self.short_name[..prefix_len]);
             prefix_len
         };
         buf[prefix_len] = b'~';
-        buf[prefix_len + 1] = char::from_digit(num, 10).unwrap() as u8; // SAFE: num is in range [1, 9]
+        let tail_end = prefix_len + 1 + Self::num_digits(num);
+        let mut rest = num;
+        for c in buf[prefix_len + 1..tail_end].iter_mut().rev() {
+            *c = b'0' + (rest % 10) as u8; // SAFE: remainder is in range [0, 9]
+            rest /= 10;
+        }
         buf[8..].copy_from_slice(&self.short_name[8..]);
         buf
     }
 
+    fn num_digits(num: u32) -> usize {
+        let mut digits = 1;
+        let mut rest = num / 10;
+        while rest > 0 {
+            digits += 1;
+            rest /= 10;
+        }
+        digits
+    }
+
     fn u16_to_hex(x: u16) -> [u8; 4] {
         // Unwrapping below is safe because each line takes 4 bits of `x` and shifts them to the right so they form
         // a number in range [0, 15]
@@ -1240,32 +3478,72 @@ mod tests {
 
     #[test]
     fn test_split_path() {
-        assert_eq!(split_path("aaa/bbb/ccc"), ("aaa", Some("bbb/ccc")));
-        assert_eq!(split_path("aaa/bbb"), ("aaa", Some("bbb")));
-        assert_eq!(split_path("aaa"), ("aaa", None));
+        let slash: &[char] = &['/'];
+        assert_eq!(split_path("aaa/bbb/ccc", slash), ("aaa", Some("bbb/ccc")));
+        assert_eq!(split_path("aaa/bbb", slash), ("aaa", Some("bbb")));
+        assert_eq!(split_path("aaa", slash), ("aaa", None));
+        assert_eq!(split_path("aaa//bbb", slash), ("aaa", Some("/bbb")));
+        assert_eq!(split_path("/bbb", slash), ("bbb", None));
+        assert_eq!(split_path("aaa\\bbb", slash), ("aaa\\bbb", None));
+    }
+
+    #[test]
+    fn test_split_path_with_backslash() {
+        let both: &[char] = &['/', '\\'];
+        assert_eq!(split_path("aaa\\bbb/ccc", both), ("aaa", Some("bbb/ccc")));
+        assert_eq!(split_path("bbb/ccc", both), ("bbb", Some("ccc")));
+        assert_eq!(split_path("\\aaa\\", both), ("aaa", None));
+        assert_eq!(split_path("/\\aaa\\/bbb\\", both), ("aaa", Some("/bbb")));
+        assert_eq!(split_path("\\", both), ("", None));
+        assert!(is_self_path("\\", both));
+        assert!(is_self_path("\\.\\", both));
+        assert!(!is_self_path("\\", &['/']));
     }
 
     #[test]
     fn test_generate_short_name() {
-        assert_eq!(ShortNameGenerator::new("Foo").generate().ok(), Some(*b"FOO        "));
-        assert_eq!(ShortNameGenerator::new("Foo.b").generate().ok(), Some(*b"FOO     B  "));
         assert_eq!(
-            ShortNameGenerator::new("Foo.baR").generate().ok(),
+            ShortNameGenerator::new("Foo", ShortNameAlgorithm::Checksum)
+                .generate()
+                .ok(),
+            Some(*b"FOO        ")
+        );
+        assert_eq!(
+            ShortNameGenerator::new("Foo.b", ShortNameAlgorithm::Checksum)
+                .generate()
+                .ok(),
+            Some(*b"FOO     B  ")
+        );
+        assert_eq!(
+            ShortNameGenerator::new("Foo.baR", ShortNameAlgorithm::Checksum)
+                .generate()
+                .ok(),
             Some(*b"FOO     BAR")
         );
         assert_eq!(
-            ShortNameGenerator::new("Foo+1.baR").generate().ok(),
+            ShortNameGenerator::new("Foo+1.baR", ShortNameAlgorithm::Checksum)
+                .generate()
+                .ok(),
             Some(*b"FOO_1~1 BAR")
         );
         assert_eq!(
-            ShortNameGenerator::new("ver +1.2.text").generate().ok(),
+            ShortNameGenerator::new("ver +1.2.text", ShortNameAlgorithm::Checksum)
+                .generate()
+                .ok(),
             Some(*b"VER_12~1TEX")
         );
         assert_eq!(
-            ShortNameGenerator::new(".bashrc.swp").generate().ok(),
+            ShortNameGenerator::new(".bashrc.swp", ShortNameAlgorithm::Checksum)
+                .generate()
+                .ok(),
             Some(*b"BASHRC~1SWP")
         );
-        assert_eq!(ShortNameGenerator::new(".foo").generate().ok(), Some(*b"FOO~1      "));
+        assert_eq!(
+            ShortNameGenerator::new(".foo", ShortNameAlgorithm::Checksum)
+                .generate()
+                .ok(),
+            Some(*b"FOO~1      ")
+        );
     }
 
     #[test]
@@ -1273,6 +3551,24 @@ mod tests {
         ShortNameGenerator::checksum("\u{FF5A}\u{FF5A}\u{FF5A}\u{FF5A}");
     }
 
+    #[test]
+    fn test_short_name_case_flags() {
+        assert_eq!(short_name_case_flags("readme.txt", b"README  TXT"), Some((true, true)));
+        assert_eq!(short_name_case_flags("README.txt", b"README  TXT"), Some((false, true)));
+        assert_eq!(short_name_case_flags("readme.TXT", b"README  TXT"), Some((true, false)));
+        assert_eq!(
+            short_name_case_flags("README.TXT", b"README  TXT"),
+            Some((false, false))
+        );
+        assert_eq!(short_name_case_flags("a-1", b"A-1        "), Some((true, false)));
+        assert_eq!(short_name_case_flags("ReadMe.txt", b"README  TXT"), None);
+        assert_eq!(short_name_case_flags("readme.Txt", b"README  TXT"), None);
+        assert_eq!(short_name_case_flags("readme", b"README  TXT"), None);
+        assert_eq!(short_name_case_flags("readme.", b"README     "), None);
+        assert_eq!(short_name_case_flags("read me.txt", b"README~1TXT"), None);
+        assert_eq!(short_name_case_flags(".txt", b"TXT~1      "), None);
+    }
+
     #[test]
     fn test_lfn_checksum_overflow() {
         lfn_checksum(&[0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF]);
@@ -1281,7 +3577,7 @@ mod tests {
     #[test]
     fn test_generate_short_name_collisions_long() {
         let mut buf: [u8; SFN_SIZE];
-        let mut gen = ShortNameGenerator::new("TextFile.Mine.txt");
+        let mut gen = ShortNameGenerator::new("TextFile.Mine.txt", ShortNameAlgorithm::Checksum);
         buf = gen.generate().unwrap();
         assert_eq!(&buf, b"TEXTFI~1TXT");
         gen.add_existing(&buf);
@@ -1305,20 +3601,116 @@ mod tests {
             assert_eq!(&buf, format!("TE527D~{}TXT", i).as_bytes());
         }
         gen.add_existing(&buf);
+        buf = gen.generate().unwrap();
+        assert_eq!(&buf, b"TEXTFI~5TXT");
+        let mut names = Vec::new();
+        for _ in 6..64 {
+            gen.add_existing(&buf);
+            buf = gen.generate().unwrap();
+            names.push(buf);
+        }
+        assert_eq!(&names[3], b"TEXTFI~9TXT");
+        assert_eq!(&names[4], b"TEXTF~10TXT");
+        assert_eq!(&names[57], b"TEXTF~63TXT");
+        gen.add_existing(&buf);
         assert!(gen.generate().is_err());
         gen.next_iteration();
-        for _i in 0..4 {
-            buf = gen.generate().unwrap();
+        buf = gen.generate().unwrap();
+        assert_eq!(&buf, b"TEXTF~64TXT");
+        gen.next_iteration();
+        buf = gen.generate().unwrap();
+        assert_eq!(&buf, b"TEXT~128TXT");
+    }
+
+    #[test]
+    fn test_generate_short_name_multi_digit_tails() {
+        let mut gen = ShortNameGenerator::new("x.txt", ShortNameAlgorithm::Checksum);
+        gen.add_existing(b"X       TXT");
+        for name in &[b"X~1     TXT", b"X~2     TXT", b"X~3     TXT", b"X~4     TXT"] {
+            gen.add_existing(name);
+        }
+        for i in 1..10 {
+            let name = gen.build_prefixed_name(i, true);
+            gen.add_existing(&name);
+        }
+        // tails with leading zeros or a wrong prefix length do not collide
+        gen.add_existing(b"X~05    TXT");
+        gen.add_existing(b"X~5     DAT");
+        assert_eq!(&gen.generate().unwrap(), b"X~5     TXT");
+        for i in 5..64 {
+            let name = gen.build_prefixed_name(i, false);
+            gen.add_existing(&name);
+        }
+        assert!(gen.generate().is_err());
+        while gen.tail_base < 999_936 {
+            gen.next_iteration();
+        }
+        assert_eq!(&gen.generate().unwrap(), b"X~999936TXT");
+        gen.add_existing(b"X~999999TXT");
+        for i in 999_936..999_999 {
+            let name = gen.build_prefixed_name(i, false);
+            gen.add_existing(&name);
+        }
+        assert!(gen.generate().is_err());
+        gen.next_iteration();
+        assert!(gen.is_exhausted());
+    }
+
+    #[test]
+    fn test_generate_short_name_shortens_prefix_for_long_tails() {
+        let mut gen = ShortNameGenerator::new("TextFile.Mine.txt", ShortNameAlgorithm::Checksum);
+        gen.tail_base = 960;
+        assert_eq!(&gen.generate().unwrap(), b"TEXT~960TXT");
+        gen.next_iteration();
+        assert_eq!(&gen.generate().unwrap(), b"TEX~1024TXT");
+        // a name with a shorter prefix taken from a different long name does not collide
+        gen.add_existing(b"TE~1024 TXT");
+        assert_eq!(&gen.generate().unwrap(), b"TEX~1024TXT");
+        gen.add_existing(b"TEX~1024TXT");
+        assert_eq!(&gen.generate().unwrap(), b"TEX~1025TXT");
+    }
+
+    #[test]
+    fn test_generate_short_name_windows_algorithm() {
+        // the same long name as in `test_generate_short_name_collisions_long` gets a different hash
+        assert_eq!(ShortNameGenerator::windows_checksum("TextFile.Mine.txt"), 0x2AF5);
+        assert_eq!(ShortNameGenerator::windows_checksum("x.txt"), 0x6C05);
+        let mut gen = ShortNameGenerator::new("TextFile.Mine.txt", ShortNameAlgorithm::Windows);
+        let mut names = Vec::new();
+        for _ in 0..14 {
+            let buf = gen.generate().unwrap();
             gen.add_existing(&buf);
+            names.push(buf);
         }
-        buf = gen.generate().unwrap();
-        assert_eq!(&buf, b"TE527E~1TXT");
+        assert_eq!(&names[0], b"TEXTFI~1TXT");
+        assert_eq!(&names[3], b"TEXTFI~4TXT");
+        assert_eq!(&names[4], b"TE2AF5~1TXT");
+        assert_eq!(&names[12], b"TE2AF5~9TXT");
+        assert_eq!(&names[13], b"TEXTFI~5TXT");
+    }
+
+    #[test]
+    fn test_generate_short_name_numeric_tail_algorithm() {
+        let mut gen = ShortNameGenerator::new("TextFile.Mine.txt", ShortNameAlgorithm::NumericTail);
+        let mut names = Vec::new();
+        for _ in 0..12 {
+            let buf = gen.generate().unwrap();
+            gen.add_existing(&buf);
+            names.push(buf);
+        }
+        assert_eq!(&names[0], b"TEXTFI~1TXT");
+        assert_eq!(&names[8], b"TEXTFI~9TXT");
+        assert_eq!(&names[9], b"TEXTF~10TXT");
+        assert_eq!(&names[11], b"TEXTF~12TXT");
+        // a lossless name is used as is
+        let gen = ShortNameGenerator::new("Foo.baR", ShortNameAlgorithm::NumericTail);
+        assert_eq!(&gen.generate().unwrap(), b"FOO     BAR");
     }
 
     #[test]
     fn test_generate_short_name_collisions_short() {
         let mut buf: [u8; SFN_SIZE];
-        let mut gen = ShortNameGenerator::new("x.txt");
+        let mut gen = ShortNameGenerator::new("x.txt", ShortNameAlgorithm::Checksum);
         buf = gen.generate().unwrap();
         assert_eq!(&buf, b"X       TXT");
         gen.add_existing(&buf);
@@ -1340,4 +3732,51 @@ mod tests {
         buf = gen.generate().unwrap();
         assert_eq!(&buf, b"X40DA~2 TXT");
     }
+
+    #[test]
+    fn test_generate_short_name_independent_of_existing_names_order() {
+        let name = "TextFile.Mine.txt";
+        let mut existing: Vec<[u8; SFN_SIZE]> = Vec::new();
+        let mut gen = ShortNameGenerator::new(name, ShortNameAlgorithm::Checksum);
+        // take all 6-character prefix candidates and some of checksum candidates
+        for _ in 0..7 {
+            let short_name = gen.generate().unwrap();
+            gen.add_existing(&short_name);
+            existing.push(short_name);
+        }
+        // add unrelated and almost colliding names
+        for i in 0..93_u32 {
+            let mut short_name = [SFN_PADDING; SFN_SIZE];
+            let s = match i % 4 {
+                0 => format!("FILE{:04}TXT", i),
+                1 => format!("TEXTFI~{}DAT", i % 10),
+                2 => format!("TE{:04X}~{}TXT", i, i % 10),
+                _ => format!("TEXTF~{}TXT", i % 10),
+            };
+            short_name[..s.len()].copy_from_slice(s.as_bytes());
+            existing.push(short_name);
+        }
+        assert_eq!(existing.len(), 100);
+
+        let generate = |names: &[[u8; SFN_SIZE]]| {
+            let mut gen = ShortNameGenerator::new(name, ShortNameAlgorithm::Checksum);
+            for short_name in names {
+                gen.add_existing(short_name);
+            }
+            gen.generate().unwrap()
+        };
+        let expected = generate(&existing);
+        assert_eq!(&expected, b"TE527D~4TXT");
+        // shuffle using a simple xorshift generator to avoid test dependencies
+        let mut seed = 0x2545_F491_u32;
+        for _ in 0..20 {
+            for i in (1..existing.len()).rev() {
+                seed ^= seed << 13;
+                seed ^= seed >> 17;
+                seed ^= seed << 5;
+                existing.swap(i, seed as usize % (i + 1));
+            }
+            assert_eq!(generate(&existing), expected);
+        }
+    }
 }
@@ -0,0 +1,194 @@
+#[cfg(all(not(feature = "std"), feature = "alloc"))]
+use alloc::string::String;
+#[cfg(all(not(feature = "std"), feature = "alloc"))]
+use alloc::vec::Vec;
+use core::fmt::Debug;
+
+/// A storage of the directory lookup cache.
+///
+/// The cache maps a directory (identified by its first cluster, 0 is used for the FAT12/FAT16 root directory) and
+/// a name used for the lookup to the offset of the entry in the directory. Cached offsets are only hints - entry
+/// found at the cached offset is verified before it is used, so a storage is free to drop entries at any time.
+///
+/// A storage is enabled by the `set_dir_cache` method on `FileSystem`. Users can provide their own implementation.
+/// The filesystem owns the storage as a boxed trait object, so the cache is only available with the `alloc` feature.
+pub trait DirCacheStorage: Debug {
+    /// Returns the cached offset of entry `name` in directory `dir_cluster`.
+    fn get(&self, dir_cluster: u32, name: &str) -> Option<u64>;
+
+    /// Stores the offset of entry `name` in directory `dir_cluster`.
+    ///
+    /// If the storage is full it can evict other entries or ignore the new entry.
+    fn insert(&mut self, dir_cluster: u32, name: &str, offset: u64);
+
+    /// Removes all entries.
+    fn clear(&mut self);
+}
+
+/// A `DirCacheStorage` implementation using a vector searched linearly.
+///
+/// It is small and deterministic. When the capacity is reached the oldest entry is evicted.
+#[cfg(feature = "alloc")]
+#[derive(Debug, Clone)]
+pub struct LinearDirCache {
+    entries: Vec<(u32, String, u64)>,
+    capacity: usize,
+}
+
+#[cfg(feature = "alloc")]
+impl LinearDirCache {
+    /// Default number of cached entries.
+    pub const DEFAULT_CAPACITY: usize = 64;
+
+    /// Creates a cache storing up to `capacity` entries.
+    #[must_use]
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: Vec::new(),
+            capacity,
+        }
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl Default for LinearDirCache {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_CAPACITY)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl DirCacheStorage for LinearDirCache {
+    fn get(&self, dir_cluster: u32, name: &str) -> Option<u64> {
+        self.entries
+            .iter()
+            .find(|(cluster, entry_name, _)| *cluster == dir_cluster && entry_name == name)
+            .map(|(_, _, offset)| *offset)
+    }
+
+    fn insert(&mut self, dir_cluster: u32, name: &str, offset: u64) {
+        if let Some(entry) = self
+            .entries
+            .iter_mut()
+            .find(|(cluster, entry_name, _)| *cluster == dir_cluster && entry_name == name)
+        {
+            entry.2 = offset;
+            return;
+        }
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.len() == self.capacity {
+            self.entries.remove(0);
+        }
+        self.entries.push((dir_cluster, name.into(), offset));
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+/// A `DirCacheStorage` implementation using a hash map from the `hashbrown` crate.
+///
+/// It is faster than `LinearDirCache` for big capacities. When the capacity is reached all entries are dropped.
+#[cfg(all(feature = "alloc", feature = "hashbrown"))]
+#[derive(Debug, Clone)]
+pub struct HashbrownDirCache {
+    dirs: hashbrown::HashMap<u32, hashbrown::HashMap<String, u64>>,
+    len: usize,
+    capacity: usize,
+}
+
+#[cfg(all(feature = "alloc", feature = "hashbrown"))]
+impl HashbrownDirCache {
+    /// Default number of cached entries.
+    pub const DEFAULT_CAPACITY: usize = 1024;
+
+    /// Creates a cache storing up to `capacity` entries.
+    #[must_use]
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            dirs: hashbrown::HashMap::new(),
+            len: 0,
+            capacity,
+        }
+    }
+}
+
+#[cfg(all(feature = "alloc", feature = "hashbrown"))]
+impl Default for HashbrownDirCache {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_CAPACITY)
+    }
+}
+
+#[cfg(all(feature = "alloc", feature = "hashbrown"))]
+impl DirCacheStorage for HashbrownDirCache {
+    fn get(&self, dir_cluster: u32, name: &str) -> Option<u64> {
+        self.dirs.get(&dir_cluster)?.get(name).copied()
+    }
+
+    fn insert(&mut self, dir_cluster: u32, name: &str, offset: u64) {
+        if let Some(cached_offset) = self.dirs.get_mut(&dir_cluster).and_then(|dir| dir.get_mut(name)) {
+            *cached_offset = offset;
+            return;
+        }
+        if self.capacity == 0 {
+            return;
+        }
+        if self.len == self.capacity {
+            self.clear();
+        }
+        self.dirs.entry(dir_cluster).or_default().insert(name.into(), offset);
+        self.len += 1;
+    }
+
+    fn clear(&mut self) {
+        self.dirs.clear();
+        self.len = 0;
+    }
+}
+
+#[cfg(all(test, feature = "alloc"))]
+mod tests {
+    use super::*;
+
+    fn check_storage<S: DirCacheStorage>(mut storage: S) {
+        assert_eq!(storage.get(0, "a"), None);
+        storage.insert(0, "a", 32);
+        storage.insert(5, "a", 64);
+        storage.insert(5, "b", 96);
+        assert_eq!(storage.get(0, "a"), Some(32));
+        assert_eq!(storage.get(5, "a"), Some(64));
+        assert_eq!(storage.get(5, "b"), Some(96));
+        assert_eq!(storage.get(5, "c"), None);
+        storage.insert(5, "a", 128);
+        assert_eq!(storage.get(5, "a"), Some(128));
+        storage.clear();
+        assert_eq!(storage.get(0, "a"), None);
+    }
+
+    #[test]
+    fn linear_storage() {
+        check_storage(LinearDirCache::default());
+        let mut storage = LinearDirCache::new(2);
+        storage.insert(0, "a", 0);
+        storage.insert(0, "b", 32);
+        storage.insert(0, "c", 64);
+        assert_eq!(storage.get(0, "a"), None);
+        assert_eq!(storage.get(0, "c"), Some(64));
+    }
+
+    #[cfg(feature = "hashbrown")]
+    #[test]
+    fn hashbrown_storage() {
+        check_storage(HashbrownDirCache::default());
+        let mut storage = HashbrownDirCache::new(2);
+        storage.insert(0, "a", 0);
+        storage.insert(0, "b", 32);
+        storage.insert(0, "c", 64);
+        assert_eq!(storage.get(0, "c"), Some(64));
+        assert_eq!(storage.len, 1);
+    }
+}
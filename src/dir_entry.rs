@@ -1,4 +1,6 @@
 #[cfg(all(not(feature = "std"), feature = "alloc"))]
+use alloc::rc::Rc;
+#[cfg(all(not(feature = "std"), feature = "alloc"))]
 use alloc::string::String;
 use bitflags::bitflags;
 use core::char;
@@ -7,18 +9,34 @@ use core::fmt;
 #[cfg(not(feature = "unicode"))]
 use core::iter;
 use core::str;
+#[cfg(feature = "std")]
+use std::rc::Rc;
 
+#[cfg(feature = "alloc")]
+use crate::dir::parent_path;
 #[cfg(feature = "lfn")]
 use crate::dir::LfnBuffer;
 use crate::dir::{Dir, DirRawStream};
 use crate::error::{Error, IoError};
 use crate::file::File;
 use crate::fs::{FatType, FileSystem, OemCpConverter, ReadWriteSeek};
-use crate::io::{self, Read, ReadLeExt, Write, WriteLeExt};
+use crate::io::{Read, ReadLeExt, Write};
+#[cfg(feature = "repair")]
+use crate::time::TimeProvider;
 use crate::time::{Date, DateTime};
 
 bitflags! {
     /// A FAT file attributes.
+    ///
+    /// Attributes of directories have the following meaning in this crate:
+    ///
+    /// * `HIDDEN` hides a directory the same way it hides a file - it is not listed by iterators skipping hidden
+    ///   entries (`Dir::files`, `Dir::dirs` and `Dir::iter_filtered`).
+    /// * `SYSTEM` marks a directory managed by the operating system (e.g. "System Volume Information").
+    ///   `Dir::remove_recursive` skips such directories, `Dir::remove_recursive_forced` removes them too.
+    /// * `ARCHIVE` is not used for directories. It is never set automatically on a directory (e.g. when an entry
+    ///   inside it is created or modified) but it is preserved if it is already set.
+    /// * `READ_ONLY` is preserved but not enforced.
     #[derive(Default)]
     pub struct FileAttributes: u8 {
         const READ_ONLY  = 0x01;
@@ -38,6 +56,8 @@ pub(crate) const DIR_ENTRY_SIZE: u32 = 32;
 // Directory entry flags available in first byte of the short name
 pub(crate) const DIR_ENTRY_DELETED_FLAG: u8 = 0xE5;
 pub(crate) const DIR_ENTRY_REALLY_E5_FLAG: u8 = 0x05;
+// Marks the first free entry after the last used entry in a directory
+pub(crate) const DIR_ENTRY_END_MARKER: u8 = 0x00;
 
 // Short file name field size in bytes (besically 8 + 3)
 pub(crate) const SFN_SIZE: usize = 11;
@@ -54,14 +74,20 @@ pub(crate) const LFN_ENTRY_LAST_FLAG: u8 = 0x40;
 
 // Character to upper case conversion which supports Unicode only if `unicode` feature is enabled
 #[cfg(feature = "unicode")]
-fn char_to_uppercase(c: char) -> char::ToUppercase {
+pub(crate) fn char_to_uppercase(c: char) -> char::ToUppercase {
     c.to_uppercase()
 }
 #[cfg(not(feature = "unicode"))]
-fn char_to_uppercase(c: char) -> iter::Once<char> {
+pub(crate) fn char_to_uppercase(c: char) -> iter::Once<char> {
     iter::once(c.to_ascii_uppercase())
 }
 
+// Decodes a long name replacing unpaired surrogates with the replacement character (like `String::from_utf16_lossy`)
+#[cfg(feature = "lfn")]
+pub(crate) fn decode_lfn_lossy(lfn: &[u16]) -> impl Iterator<Item = char> + '_ {
+    char::decode_utf16(lfn.iter().copied()).map(|r| r.unwrap_or(char::REPLACEMENT_CHARACTER))
+}
+
 /// Decoded file short name
 #[derive(Clone, Debug, Default)]
 pub(crate) struct ShortName {
@@ -162,7 +188,6 @@ impl DirFileEntryData {
         &self.name
     }
 
-    #[cfg(feature = "alloc")]
     fn lowercase_name(&self) -> ShortName {
         let mut name_copy: [u8; SFN_SIZE] = self.name;
         if self.lowercase_basename() {
@@ -208,6 +233,14 @@ impl DirFileEntryData {
         self.size = size;
     }
 
+    pub(crate) fn attributes(&self) -> FileAttributes {
+        self.attrs
+    }
+
+    pub(crate) fn set_attributes(&mut self, attrs: FileAttributes) {
+        self.attrs = attrs;
+    }
+
     pub(crate) fn is_dir(&self) -> bool {
         self.attrs.contains(FileAttributes::DIRECTORY)
     }
@@ -224,6 +257,16 @@ impl DirFileEntryData {
         self.reserved_0 & (1 << 4) != 0
     }
 
+    pub(crate) fn set_lowercase_flags(&mut self, lowercase_basename: bool, lowercase_ext: bool) {
+        self.reserved_0 &= !((1 << 3) | (1 << 4));
+        if lowercase_basename {
+            self.reserved_0 |= 1 << 3;
+        }
+        if lowercase_ext {
+            self.reserved_0 |= 1 << 4;
+        }
+    }
+
     fn created(&self) -> DateTime {
         DateTime::decode(self.create_date, self.create_time_1, self.create_time_0)
     }
@@ -253,19 +296,26 @@ impl DirFileEntryData {
     }
 
     pub(crate) fn serialize<W: Write>(&self, wrt: &mut W) -> Result<(), W::Error> {
-        wrt.write_all(&self.name)?;
-        wrt.write_u8(self.attrs.bits())?;
-        wrt.write_u8(self.reserved_0)?;
-        wrt.write_u8(self.create_time_0)?;
-        wrt.write_u16_le(self.create_time_1)?;
-        wrt.write_u16_le(self.create_date)?;
-        wrt.write_u16_le(self.access_date)?;
-        wrt.write_u16_le(self.first_cluster_hi)?;
-        wrt.write_u16_le(self.modify_time)?;
-        wrt.write_u16_le(self.modify_date)?;
-        wrt.write_u16_le(self.first_cluster_lo)?;
-        wrt.write_u32_le(self.size)?;
-        Ok(())
+        // Entry is written using a single call so an interrupted operation cannot leave a partially written entry
+        wrt.write_all(&self.to_bytes())
+    }
+
+    /// Returns on-disk image of the entry containing all fields.
+    pub(crate) fn to_bytes(&self) -> [u8; DIR_ENTRY_SIZE as usize] {
+        let mut buf = [0_u8; DIR_ENTRY_SIZE as usize];
+        buf[0..11].copy_from_slice(&self.name);
+        buf[11] = self.attrs.bits();
+        buf[12] = self.reserved_0;
+        buf[13] = self.create_time_0;
+        buf[14..16].copy_from_slice(&self.create_time_1.to_le_bytes());
+        buf[16..18].copy_from_slice(&self.create_date.to_le_bytes());
+        buf[18..20].copy_from_slice(&self.access_date.to_le_bytes());
+        buf[20..22].copy_from_slice(&self.first_cluster_hi.to_le_bytes());
+        buf[22..24].copy_from_slice(&self.modify_time.to_le_bytes());
+        buf[24..26].copy_from_slice(&self.modify_date.to_le_bytes());
+        buf[26..28].copy_from_slice(&self.first_cluster_lo.to_le_bytes());
+        buf[28..32].copy_from_slice(&self.size.to_le_bytes());
+        buf
     }
 
     pub(crate) fn is_deleted(&self) -> bool {
@@ -276,8 +326,12 @@ impl DirFileEntryData {
         self.name[0] = DIR_ENTRY_DELETED_FLAG;
     }
 
+    pub(crate) fn set_undeleted(&mut self, first_char: u8) {
+        self.name[0] = first_char;
+    }
+
     pub(crate) fn is_end(&self) -> bool {
-        self.name[0] == 0
+        self.name[0] == DIR_ENTRY_END_MARKER
     }
 
     pub(crate) fn is_volume(&self) -> bool {
@@ -322,21 +376,22 @@ impl DirLfnEntryData {
     }
 
     pub(crate) fn serialize<W: Write>(&self, wrt: &mut W) -> Result<(), W::Error> {
-        wrt.write_u8(self.order)?;
-        for ch in &self.name_0 {
-            wrt.write_u16_le(*ch)?;
-        }
-        wrt.write_u8(self.attrs.bits())?;
-        wrt.write_u8(self.entry_type)?;
-        wrt.write_u8(self.checksum)?;
-        for ch in &self.name_1 {
-            wrt.write_u16_le(*ch)?;
-        }
-        wrt.write_u16_le(self.reserved_0)?;
-        for ch in &self.name_2 {
-            wrt.write_u16_le(*ch)?;
+        fn copy_ucs2_le(dst: &mut [u8], src: &[u16]) {
+            for (dst_ch, ch) in dst.chunks_exact_mut(2).zip(src) {
+                dst_ch.copy_from_slice(&ch.to_le_bytes());
+            }
         }
-        Ok(())
+        // Entry is written using a single call so an interrupted operation cannot leave a partially written entry
+        let mut buf = [0_u8; DIR_ENTRY_SIZE as usize];
+        buf[0] = self.order;
+        copy_ucs2_le(&mut buf[1..11], &self.name_0);
+        buf[11] = self.attrs.bits();
+        buf[12] = self.entry_type;
+        buf[13] = self.checksum;
+        copy_ucs2_le(&mut buf[14..26], &self.name_1);
+        buf[26..28].copy_from_slice(&self.reserved_0.to_le_bytes());
+        copy_ucs2_le(&mut buf[28..32], &self.name_2);
+        wrt.write_all(&buf)
     }
 
     pub(crate) fn order(&self) -> u8 {
@@ -356,7 +411,7 @@ impl DirLfnEntryData {
     }
 
     pub(crate) fn is_end(&self) -> bool {
-        self.order == 0
+        self.order == DIR_ENTRY_END_MARKER
     }
 }
 
@@ -459,7 +514,10 @@ impl DirEntryData {
 pub(crate) struct DirEntryEditor {
     data: DirFileEntryData,
     pos: u64,
+    // Set by setters only if a field value actually changes so reading a file never causes a write of its entry
     dirty: bool,
+    // The data cannot be reached without the first cluster so it is written back by `flush_first_cluster` too
+    first_cluster_dirty: bool,
 }
 
 impl DirEntryEditor {
@@ -468,6 +526,7 @@ impl DirEntryEditor {
             data,
             pos,
             dirty: false,
+            first_cluster_dirty: false,
         }
     }
 
@@ -475,10 +534,23 @@ impl DirEntryEditor {
         &self.data
     }
 
+    pub(crate) fn pos(&self) -> u64 {
+        self.pos
+    }
+
+    /// Updates the size and the first cluster changed through another handle of the file.
+    ///
+    /// The entry is not marked dirty - the handle that made the change is responsible for writing it back.
+    pub(crate) fn set_shared_state(&mut self, size: u32, first_cluster: Option<u32>, fat_type: FatType) {
+        self.data.set_size(size);
+        self.data.set_first_cluster(first_cluster, fat_type);
+    }
+
     pub(crate) fn set_first_cluster(&mut self, first_cluster: Option<u32>, fat_type: FatType) {
         if first_cluster != self.data.first_cluster(fat_type) {
             self.data.set_first_cluster(first_cluster, fat_type);
             self.dirty = true;
+            self.first_cluster_dirty = true;
         }
     }
 
@@ -492,6 +564,14 @@ impl DirEntryEditor {
         }
     }
 
+    /// Sets the `ARCHIVE` attribute of a modified file. Directories never get it.
+    pub(crate) fn set_archive(&mut self) {
+        if !self.data.is_dir() && !self.data.attrs.contains(FileAttributes::ARCHIVE) {
+            self.data.attrs |= FileAttributes::ARCHIVE;
+            self.dirty = true;
+        }
+    }
+
     pub(crate) fn set_created(&mut self, date_time: DateTime) {
         if date_time != self.data.created() {
             self.data.set_created(date_time);
@@ -513,18 +593,34 @@ impl DirEntryEditor {
         }
     }
 
-    pub(crate) fn flush<IO: ReadWriteSeek, TP, OCC>(&mut self, fs: &FileSystem<IO, TP, OCC>) -> Result<(), IO::Error> {
+    pub(crate) fn flush<IO: ReadWriteSeek, TP, OCC>(
+        &mut self,
+        fs: &FileSystem<IO, TP, OCC>,
+    ) -> Result<(), Error<IO::Error>> {
         if self.dirty {
             self.write(fs)?;
             self.dirty = false;
+            self.first_cluster_dirty = false;
         }
         Ok(())
     }
 
-    fn write<IO: ReadWriteSeek, TP, OCC>(&self, fs: &FileSystem<IO, TP, OCC>) -> Result<(), IO::Error> {
-        let mut disk = fs.disk.borrow_mut();
-        disk.seek(io::SeekFrom::Start(self.pos))?;
-        self.data.serialize(&mut *disk)
+    /// Writes the entry back only if its first cluster has changed.
+    pub(crate) fn flush_first_cluster<IO: ReadWriteSeek, TP, OCC>(
+        &mut self,
+        fs: &FileSystem<IO, TP, OCC>,
+    ) -> Result<(), Error<IO::Error>> {
+        if self.first_cluster_dirty {
+            self.flush(fs)?;
+        }
+        Ok(())
+    }
+
+    fn write<IO: ReadWriteSeek, TP, OCC>(&self, fs: &FileSystem<IO, TP, OCC>) -> Result<(), Error<IO::Error>> {
+        // All changed fields (e.g. size and modification time) are written together using a single write call so
+        // they stay consistent if the operation is interrupted
+        let entry_bytes = self.data.to_bytes();
+        fs.write_all_at(self.pos, &entry_bytes, fs.options.verify_writes.verify_metadata())
     }
 }
 
@@ -540,6 +636,9 @@ pub struct DirEntry<'a, IO: ReadWriteSeek, TP, OCC> {
     pub(crate) entry_pos: u64,
     pub(crate) offset_range: (u64, u64),
     pub(crate) fs: &'a FileSystem<IO, TP, OCC>,
+    // path of the directory containing this entry
+    #[cfg(feature = "alloc")]
+    pub(crate) parent_path: Option<Rc<str>>,
 }
 
 #[allow(clippy::len_without_is_empty)]
@@ -575,6 +674,10 @@ impl<'a, IO: ReadWriteSeek, TP, OCC: OemCpConverter> DirEntry<'a, IO, TP, OCC> {
     }
 
     /// Returns long file name or if it doesn't exist fallbacks to short file name.
+    ///
+    /// Unpaired surrogates in the long name are replaced with `U+FFFD REPLACEMENT CHARACTER`. The returned name can
+    /// still be used to open the entry but it may be ambiguous - use `long_file_name_as_ucs2_units` and
+    /// `Dir::entry_by_ucs2_name` to match such names exactly.
     #[cfg(feature = "alloc")]
     #[must_use]
     pub fn file_name(&self) -> String {
@@ -589,6 +692,63 @@ impl<'a, IO: ReadWriteSeek, TP, OCC: OemCpConverter> DirEntry<'a, IO, TP, OCC> {
         self.data.lowercase_name().to_string(&self.fs.options.oem_cp_converter)
     }
 
+    /// Returns the '/' separated path of this entry relative to the root directory.
+    ///
+    /// The path is built from the path of the directory this entry has been read from (see `Dir::path`) and
+    /// `file_name`. `.` and `..` entries are resolved, so for them the path of the directory itself or its parent is
+    /// returned.
+    #[cfg(feature = "alloc")]
+    #[must_use]
+    pub fn path(&self) -> String {
+        let dir_path = self.parent_path.as_deref().unwrap_or("");
+        let name = self.file_name();
+        match name.as_str() {
+            "." => dir_path.into(),
+            ".." => parent_path(dir_path).into(),
+            _ if dir_path.is_empty() => name,
+            _ => {
+                let mut path = String::from(dir_path);
+                path.push('/');
+                path.push_str(&name);
+                path
+            }
+        }
+    }
+
+    /// Returns number of directory entry slots occupied by this entry.
+    ///
+    /// The number includes LFN entries preceding the short name entry and the short name entry itself.
+    #[must_use]
+    pub fn slot_count(&self) -> u32 {
+        ((self.offset_range.1 - self.offset_range.0) / u64::from(DIR_ENTRY_SIZE)) as u32
+    }
+
+    /// Checks if this entry is preceded by LFN entries.
+    ///
+    /// Note: LFN entries are taken into account even if the long name was discarded because of an invalid checksum.
+    /// In that case `long_file_name_as_ucs2_units` returns `None`.
+    #[must_use]
+    pub fn has_lfn(&self) -> bool {
+        self.slot_count() > 1
+    }
+
+    /// Checks if long file name is exactly the same as short file name.
+    ///
+    /// Short name lowercase flags are honored and comparison is case-sensitive. Returns `true` if entry has no long
+    /// file name.
+    #[cfg(feature = "lfn")]
+    #[must_use]
+    pub fn lfn_matches_sfn(&self) -> bool {
+        let lfn = match self.long_file_name_as_ucs2_units() {
+            Some(lfn) => lfn,
+            None => return true,
+        };
+        let short_name = self.data.lowercase_name();
+        let oem_cp_converter = &self.fs.options.oem_cp_converter;
+        let sfn_iter = short_name.as_bytes().iter().map(|c| Some(oem_cp_converter.decode(*c)));
+        char::decode_utf16(lfn.iter().copied()).map(Result::ok).eq(sfn_iter)
+    }
+
     /// Returns file attributes.
     #[must_use]
     pub fn attributes(&self) -> FileAttributes {
@@ -602,15 +762,40 @@ impl<'a, IO: ReadWriteSeek, TP, OCC: OemCpConverter> DirEntry<'a, IO, TP, OCC> {
     }
 
     /// Checks if entry belongs to regular file.
+    ///
+    /// Returns `false` for volume label entries.
     #[must_use]
     pub fn is_file(&self) -> bool {
-        self.data.is_file()
+        self.data.is_file() && !self.data.is_volume()
+    }
+
+    /// Checks if this is a volume label entry.
+    ///
+    /// Volume label entries are returned only by `Dir::iter_with_volume_labels`.
+    #[must_use]
+    pub fn is_volume(&self) -> bool {
+        self.data.is_volume()
     }
 
     pub(crate) fn first_cluster(&self) -> Option<u32> {
         self.data.first_cluster(self.fs.fat_type())
     }
 
+    /// Checks if this is a "." or ".." entry.
+    pub(crate) fn is_dot_entry(&self) -> bool {
+        let name = self.short_name.as_bytes();
+        name == b"." || name == b".."
+    }
+
+    /// Returns position of the short name entry on the storage in bytes.
+    ///
+    /// The position identifies the entry even if another entry in the same directory has the same name. It can be
+    /// passed to `remove_entry_at` method on `Dir`.
+    #[must_use]
+    pub fn entry_pos(&self) -> u64 {
+        self.entry_pos
+    }
+
     fn editor(&self) -> DirEntryEditor {
         DirEntryEditor::new(self.data.clone(), self.entry_pos)
     }
@@ -637,6 +822,14 @@ impl<'a, IO: ReadWriteSeek, TP, OCC: OemCpConverter> DirEntry<'a, IO, TP, OCC> {
     /// Will panic if this is not a directory.
     #[must_use]
     pub fn to_dir(&self) -> Dir<'a, IO, TP, OCC> {
+        let dir = self.to_dir_without_path();
+        #[cfg(feature = "alloc")]
+        let dir = dir.with_path(&self.path());
+        dir
+    }
+
+    /// Returns `Dir` struct for this entry without its path to avoid an allocation.
+    pub(crate) fn to_dir_without_path(&self) -> Dir<'a, IO, TP, OCC> {
         assert!(self.is_dir(), "Not a directory entry");
         match self.first_cluster() {
             Some(n) => {
@@ -653,15 +846,60 @@ impl<'a, IO: ReadWriteSeek, TP, OCC: OemCpConverter> DirEntry<'a, IO, TP, OCC> {
         u64::from(self.data.size)
     }
 
+    /// Returns file creation date and time or `None` if it is not set.
+    ///
+    /// Creation time is optional in FAT and many implementations leave it zeroed - an entry with a zero creation date
+    /// is treated as having no creation time. Resolution of the time field is 1/100s. Invalid stored values (e.g.
+    /// month 0 or hour 31) are moved into valid ranges.
+    #[must_use]
+    pub fn creation_time(&self) -> Option<DateTime> {
+        if self.data.create_date == 0 {
+            None
+        } else {
+            Some(self.data.created().clamped())
+        }
+    }
+
+    /// Returns file last access date or `None` if it is not set.
+    ///
+    /// Access date is optional in FAT and many implementations leave it zeroed - a zero access date is treated as not
+    /// set. An invalid stored date (e.g. month 0) is moved into valid ranges.
+    #[must_use]
+    pub fn access_date(&self) -> Option<Date> {
+        if self.data.access_date == 0 {
+            None
+        } else {
+            Some(self.data.accessed().clamped())
+        }
+    }
+
+    /// Returns file last modification date and time or `None` if it is not set.
+    ///
+    /// An entry with a zero modification date is treated as having no modification time. Resolution of the time
+    /// field is 2s. Invalid stored values (e.g. month 0 or hour 31) are moved into valid ranges.
+    #[must_use]
+    pub fn modification_time(&self) -> Option<DateTime> {
+        if self.data.modify_date == 0 {
+            None
+        } else {
+            Some(self.data.modified().clamped())
+        }
+    }
+
     /// Returns file creation date and time.
     ///
     /// Resolution of the time field is 1/100s.
+    /// Deprecated: a zeroed field is returned as an invalid date (month and day equal to 0) - use `creation_time`.
+    #[deprecated]
     #[must_use]
     pub fn created(&self) -> DateTime {
         self.data.created()
     }
 
     /// Returns file last access date.
+    ///
+    /// Deprecated: a zeroed field is returned as an invalid date (month and day equal to 0) - use `access_date`.
+    #[deprecated]
     #[must_use]
     pub fn accessed(&self) -> Date {
         self.data.accessed()
@@ -670,31 +908,128 @@ impl<'a, IO: ReadWriteSeek, TP, OCC: OemCpConverter> DirEntry<'a, IO, TP, OCC> {
     /// Returns file last modification date and time.
     ///
     /// Resolution of the time field is 2s.
+    /// Deprecated: a zeroed field is returned as an invalid date (month and day equal to 0) - use
+    /// `modification_time`.
+    #[deprecated]
     #[must_use]
     pub fn modified(&self) -> DateTime {
         self.data.modified()
     }
 
-    pub(crate) fn raw_short_name(&self) -> &[u8; SFN_SIZE] {
+    /// Sets attributes of this entry.
+    ///
+    /// Only the short name entry is written (at `entry_pos`), see `set_created`. `DIRECTORY` and `VOLUME_ID`
+    /// attributes define the type of the entry so they cannot be changed - they must be the same as in the current
+    /// attributes.
+    ///
+    /// # Errors
+    ///
+    /// Errors that can be returned:
+    ///
+    /// * `Error::InvalidInput` will be returned if `attrs` would change the `DIRECTORY` or `VOLUME_ID` attribute.
+    /// * `Error::Io` will be returned if the underlying storage object returned an I/O error.
+    pub fn set_attributes(&mut self, attrs: FileAttributes) -> Result<(), Error<IO::Error>> {
+        let type_attrs = FileAttributes::DIRECTORY | FileAttributes::VOLUME_ID;
+        if attrs & type_attrs != self.data.attrs & type_attrs {
+            error!(
+                "cannot change type attributes from {:?} to {:?}",
+                self.data.attrs, attrs
+            );
+            return Err(Error::InvalidInput);
+        }
+        let mut raw_entry = self.data.clone();
+        raw_entry.set_attributes(attrs);
+        self.write_data(raw_entry)
+    }
+
+    /// Sets date and time of creation of this entry.
+    ///
+    /// Only the short name entry is written (at `entry_pos`), long name entries are not touched. The time is stored
+    /// with 1/100s resolution.
+    /// Note: an open `File` or `Dir` of this entry keeps its own copy of the entry and writes it back when it is
+    /// flushed after a change (e.g. a write updating the modification time), so set timestamps after closing it.
+    ///
+    /// # Errors
+    ///
+    /// Errors that can be returned:
+    ///
+    /// * `Error::InvalidInput` will be returned if a field of `date_time` is out of the range accepted by
+    ///   `Date::new` and `Time::new` (e.g. the year is before 1980 or after 2107).
+    /// * `Error::Io` will be returned if the underlying storage object returned an I/O error.
+    pub fn set_created(&mut self, date_time: DateTime) -> Result<(), Error<IO::Error>> {
+        Self::validate_date_time(date_time)?;
+        let mut raw_entry = self.data.clone();
+        raw_entry.set_created(date_time);
+        self.write_data(raw_entry)
+    }
+
+    /// Sets date of last access of this entry.
+    ///
+    /// Only the short name entry is written, see `set_created`.
+    ///
+    /// # Errors
+    ///
+    /// Errors are the same as for `set_created`.
+    pub fn set_accessed(&mut self, date: Date) -> Result<(), Error<IO::Error>> {
+        if !date.is_valid() {
+            error!("invalid access date {:?}", date);
+            return Err(Error::InvalidInput);
+        }
+        let mut raw_entry = self.data.clone();
+        raw_entry.set_accessed(date);
+        self.write_data(raw_entry)
+    }
+
+    /// Sets date and time of last modification of this entry.
+    ///
+    /// Only the short name entry is written, see `set_created`. The time is stored with 2s resolution (odd seconds
+    /// and milliseconds are dropped).
+    ///
+    /// # Errors
+    ///
+    /// Errors are the same as for `set_created`.
+    pub fn set_modified(&mut self, date_time: DateTime) -> Result<(), Error<IO::Error>> {
+        Self::validate_date_time(date_time)?;
+        let mut raw_entry = self.data.clone();
+        raw_entry.set_modified(date_time);
+        self.write_data(raw_entry)
+    }
+
+    fn validate_date_time(date_time: DateTime) -> Result<(), Error<IO::Error>> {
+        if date_time.is_valid() {
+            Ok(())
+        } else {
+            error!("invalid date and time {:?}", date_time);
+            Err(Error::InvalidInput)
+        }
+    }
+
+    /// Writes the short name entry `raw_entry` at `entry_pos` and updates this entry on success.
+    fn write_data(&mut self, raw_entry: DirFileEntryData) -> Result<(), Error<IO::Error>> {
+        DirEntryEditor::new(raw_entry.clone(), self.entry_pos).write(self.fs)?;
+        self.data = raw_entry;
+        Ok(())
+    }
+
+    /// Returns the raw 11-byte name stored in the short name entry.
+    ///
+    /// The name is space-padded and has no dot separating the extension. For volume label entries this is the
+    /// volume label.
+    #[must_use]
+    pub fn raw_short_name(&self) -> &[u8; SFN_SIZE] {
         &self.data.name
     }
 
     #[cfg(feature = "lfn")]
     fn eq_name_lfn(&self, name: &str) -> bool {
         if let Some(lfn) = self.long_file_name_as_ucs2_units() {
-            let self_decode_iter = char::decode_utf16(lfn.iter().copied());
             let mut other_uppercase_iter = name.chars().flat_map(char_to_uppercase);
-            for decode_result in self_decode_iter {
-                if let Ok(self_char) = decode_result {
-                    for self_uppercase_char in char_to_uppercase(self_char) {
-                        // compare each character in uppercase
-                        if Some(self_uppercase_char) != other_uppercase_iter.next() {
-                            return false;
-                        }
+            for self_char in decode_lfn_lossy(lfn) {
+                for self_uppercase_char in char_to_uppercase(self_char) {
+                    // compare each character in uppercase
+                    if Some(self_uppercase_char) != other_uppercase_iter.next() {
+                        return false;
                     }
-                } else {
-                    // decoding failed
-                    return false;
                 }
             }
             // both iterators should be at the end here
@@ -708,13 +1043,236 @@ impl<'a, IO: ReadWriteSeek, TP, OCC: OemCpConverter> DirEntry<'a, IO, TP, OCC> {
     pub(crate) fn eq_name(&self, name: &str) -> bool {
         #[cfg(feature = "lfn")]
         {
-            if self.eq_name_lfn(name) {
+            if self.fs.options.case_sensitive_lookup {
+                if let Some(lfn) = self.long_file_name_as_ucs2_units() {
+                    if decode_lfn_lossy(lfn).eq(name.chars()) {
+                        return true;
+                    }
+                    // a long name differing only in case is a different name - do not fall back to the short name
+                    if self.eq_name_lfn(name) {
+                        return false;
+                    }
+                }
+            } else if self.eq_name_lfn(name) {
                 return true;
             }
         }
 
         self.short_name.eq_ignore_case(name, &self.fs.options.oem_cp_converter)
     }
+
+    /// Checks if `name` is exactly the same as the name of this entry including case.
+    pub(crate) fn eq_name_exact(&self, name: &str) -> bool {
+        #[cfg(feature = "lfn")]
+        {
+            if let Some(lfn) = self.long_file_name_as_ucs2_units() {
+                return lfn.iter().copied().eq(name.encode_utf16());
+            }
+        }
+        let short_name = self.data.lowercase_name();
+        let oem_cp_converter = &self.fs.options.oem_cp_converter;
+        let sfn_iter = short_name.as_bytes().iter().map(|c| oem_cp_converter.decode(*c));
+        sfn_iter.eq(name.chars())
+    }
+}
+
+/// Repair primitives fixing a damaged `DIRECTORY` attribute.
+///
+/// Both methods write only the attribute byte of the short name entry and refuse conversions that are obviously wrong.
+/// They are meant for salvaging data from a damaged filesystem and should not be used on a consistent one.
+#[cfg(feature = "repair")]
+impl<'a, IO: ReadWriteSeek, TP: TimeProvider, OCC: OemCpConverter> DirEntry<'a, IO, TP, OCC> {
+    /// Clears the `DIRECTORY` attribute of an entry whose cluster chain contains file data.
+    ///
+    /// Returns the updated entry. File size is taken from the entry so its data can be read using `to_file` if the
+    /// size field is not damaged.
+    ///
+    /// # Errors
+    ///
+    /// Errors that can be returned:
+    ///
+    /// * `Error::InvalidInput` will be returned if this is not a directory entry, it is a "." or ".." entry or its
+    ///   first cluster starts with valid "." and ".." entries.
+    /// * `Error::Io` will be returned if the underlying storage object returned an I/O error.
+    pub fn clear_directory_attribute(&self) -> Result<Self, Error<IO::Error>> {
+        if !self.is_dir() || self.is_dot_entry() || self.has_dot_entries()? {
+            error!(
+                "refusing to convert directory {:?} to a file",
+                self.short_name.as_bytes()
+            );
+            return Err(Error::InvalidInput);
+        }
+        self.write_attributes(self.data.attrs - FileAttributes::DIRECTORY)
+    }
+
+    /// Sets the `DIRECTORY` attribute of an entry whose cluster chain contains a directory.
+    ///
+    /// Returns the updated entry. Its content can be accessed using `to_dir`.
+    ///
+    /// # Errors
+    ///
+    /// Errors that can be returned:
+    ///
+    /// * `Error::InvalidInput` will be returned if this is not a file entry or its first cluster does not start with
+    ///   valid "." and ".." entries.
+    /// * `Error::Io` will be returned if the underlying storage object returned an I/O error.
+    pub fn set_directory_attribute(&self) -> Result<Self, Error<IO::Error>> {
+        if !self.is_file() || !self.has_dot_entries()? {
+            error!(
+                "refusing to convert file {:?} to a directory",
+                self.short_name.as_bytes()
+            );
+            return Err(Error::InvalidInput);
+        }
+        self.write_attributes(self.data.attrs | FileAttributes::DIRECTORY)
+    }
+
+    /// Checks if the first cluster starts with "." entry pointing to it followed by ".." entry.
+    fn has_dot_entries(&self) -> Result<bool, Error<IO::Error>> {
+        let cluster = match self.first_cluster() {
+            Some(n) => n,
+            None => return Ok(false),
+        };
+        let mut iter = Dir::from_cluster(Some(cluster), self.fs).iter_from(0)?;
+        let dot = match iter.next().transpose()? {
+            Some(e) => e.is_dir() && e.short_name.as_bytes() == b"." && e.first_cluster() == Some(cluster),
+            None => false,
+        };
+        let dot_dot = match iter.next().transpose()? {
+            Some(e) => e.is_dir() && e.short_name.as_bytes() == b"..",
+            None => false,
+        };
+        Ok(dot && dot_dot)
+    }
+
+    fn write_attributes(&self, attrs: FileAttributes) -> Result<Self, Error<IO::Error>> {
+        // attributes follow the short name
+        let verify = self.fs.options.verify_writes.verify_metadata();
+        self.fs
+            .write_all_at(self.entry_pos + SFN_SIZE as u64, &[attrs.bits()], verify)?;
+        let mut data = self.data.clone();
+        data.attrs = attrs;
+        Ok(Self {
+            data,
+            short_name: self.short_name.clone(),
+            #[cfg(feature = "lfn")]
+            lfn_utf16: self.lfn_utf16.clone(),
+            entry_pos: self.entry_pos,
+            offset_range: self.offset_range,
+            fs: self.fs,
+            #[cfg(feature = "alloc")]
+            parent_path: self.parent_path.clone(),
+        })
+    }
+}
+
+/// A deleted directory entry returned by `Dir::iter_raw`.
+///
+/// Deletion overwrites only the first byte of the short name - other fields keep their values from before the
+/// deletion. Clusters of a deleted entry are free so they could have been reused by other files since then.
+#[derive(Clone)]
+pub struct DeletedEntryInfo {
+    pub(crate) data: DirFileEntryData,
+    #[cfg(feature = "lfn")]
+    pub(crate) lfn_utf16: LfnBuffer,
+    pub(crate) first_name_byte: Option<u8>,
+    pub(crate) first_cluster: Option<u32>,
+    pub(crate) entry_pos: u64,
+    pub(crate) offset_range: (u64, u64),
+}
+
+impl DeletedEntryInfo {
+    /// Returns the last 10 bytes of the raw short name (the first byte is overwritten by the deletion marker).
+    ///
+    /// The name is space-padded and has no dot separating the extension.
+    #[must_use]
+    pub fn short_name_tail(&self) -> [u8; SFN_SIZE - 1] {
+        let mut tail = [0_u8; SFN_SIZE - 1];
+        tail.copy_from_slice(&self.data.name[1..]);
+        tail
+    }
+
+    /// Returns the first byte of the raw short name recovered from the checksum stored in long name entries.
+    ///
+    /// `None` is returned if the entry has no long name attached.
+    #[must_use]
+    pub fn recovered_first_byte(&self) -> Option<u8> {
+        self.first_name_byte
+    }
+
+    /// Returns the long name if long name entries preceding the short name entry are still present.
+    ///
+    /// Long name entries are attached only if all of them have the same checksum and it matches the short name
+    /// with a valid first character.
+    #[cfg(feature = "lfn")]
+    #[must_use]
+    pub fn long_file_name_as_ucs2_units(&self) -> Option<&[u16]> {
+        if self.lfn_utf16.len() > 0 {
+            Some(self.lfn_utf16.as_ucs2_units())
+        } else {
+            None
+        }
+    }
+
+    /// Returns attributes of the deleted entry.
+    #[must_use]
+    pub fn attributes(&self) -> FileAttributes {
+        self.data.attrs
+    }
+
+    /// Checks if the deleted entry was a directory.
+    #[must_use]
+    pub fn is_dir(&self) -> bool {
+        self.data.is_dir()
+    }
+
+    /// Returns the file size stored in the entry or 0 for a directory.
+    #[must_use]
+    pub fn len(&self) -> u64 {
+        u64::from(self.data.size)
+    }
+
+    /// Checks if the stored size is 0.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.data.size == 0
+    }
+
+    /// Returns the first cluster stored in the entry or `None` if it is 0.
+    #[must_use]
+    pub fn first_cluster(&self) -> Option<u32> {
+        self.first_cluster
+    }
+
+    /// Returns the last modification date and time or `None` if it is not set.
+    ///
+    /// Invalid stored values are moved into valid ranges like in `DirEntry::modification_time`.
+    #[must_use]
+    pub fn modification_time(&self) -> Option<DateTime> {
+        if self.data.modify_date == 0 {
+            None
+        } else {
+            Some(self.data.modified().clamped())
+        }
+    }
+
+    /// Returns position of the short name entry on the storage in bytes.
+    #[must_use]
+    pub fn entry_pos(&self) -> u64 {
+        self.entry_pos
+    }
+
+    /// Returns number of directory entry slots occupied by this entry including the attached long name entries.
+    #[must_use]
+    pub fn slot_count(&self) -> u32 {
+        ((self.offset_range.1 - self.offset_range.0) / u64::from(DIR_ENTRY_SIZE)) as u32
+    }
+}
+
+impl fmt::Debug for DeletedEntryInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        self.data.fmt(f)
+    }
 }
 
 impl<IO: ReadWriteSeek, TP, OCC> fmt::Debug for DirEntry<'_, IO, TP, OCC> {
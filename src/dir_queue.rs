@@ -0,0 +1,188 @@
+#[cfg(not(feature = "std"))]
+use alloc::collections::BTreeSet;
+#[cfg(not(feature = "std"))]
+use alloc::format;
+#[cfg(not(feature = "std"))]
+use alloc::string::String;
+#[cfg(not(feature = "std"))]
+use alloc::vec;
+#[cfg(not(feature = "std"))]
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::collections::BTreeSet;
+
+use crate::dir::Dir;
+use crate::dir_entry::DirEntry;
+use crate::error::Error;
+use crate::fs::{FileSystem, OemCpConverter, ReadWriteSeek};
+use crate::time::TimeProvider;
+
+/// A directory waiting in the `DirQueue`.
+struct PendingDir {
+    /// First cluster of the directory (`None` for the FAT12/FAT16 root directory)
+    cluster: Option<u32>,
+    path: String,
+    depth: u32,
+}
+
+/// A work queue used to traverse the directory tree without recursion.
+///
+/// Directories are visited in the depth-first order. Memory usage depends on the number of directories waiting in the
+/// queue instead of the depth of the tree. A directory is queued at most once even if it is referenced by multiple
+/// entries, so directory loops in a corrupted filesystem are skipped.
+pub(crate) struct DirQueue<'a, IO: ReadWriteSeek, TP, OCC> {
+    pending: Vec<PendingDir>,
+    visited: BTreeSet<u32>,
+    fs: &'a FileSystem<IO, TP, OCC>,
+}
+
+impl<'a, IO: ReadWriteSeek, TP: TimeProvider, OCC: OemCpConverter> DirQueue<'a, IO, TP, OCC> {
+    /// Creates a queue containing the root directory.
+    pub(crate) fn new(fs: &'a FileSystem<IO, TP, OCC>) -> Self {
+        let root_cluster = fs.root_dir().first_cluster();
+        let mut visited = BTreeSet::new();
+        visited.extend(root_cluster);
+        let pending = vec![PendingDir {
+            cluster: root_cluster,
+            path: String::new(),
+            depth: 0,
+        }];
+        Self { pending, visited, fs }
+    }
+
+    /// Removes the next directory from the queue.
+    pub(crate) fn pop(&mut self) -> Option<DirQueueEntry<'a, IO, TP, OCC>> {
+        let pending = self.pending.pop()?;
+        Some(DirQueueEntry {
+            dir: Dir::from_cluster(pending.cluster, self.fs).with_path(&pending.path),
+            path: pending.path,
+            depth: pending.depth,
+        })
+    }
+
+    /// Queues the subdirectory `entry` of the directory `parent`.
+    ///
+    /// A directory that has already been queued is skipped.
+    pub(crate) fn push(
+        &mut self,
+        parent: &DirQueueEntry<'a, IO, TP, OCC>,
+        entry: &DirEntry<'a, IO, TP, OCC>,
+    ) -> Result<(), Error<IO::Error>> {
+        let cluster = if let Some(n) = entry.first_cluster() {
+            n
+        } else {
+            error!("directory entry without a cluster");
+            return Err(Error::CorruptedFileSystem);
+        };
+        let depth = parent.depth + 1;
+        if depth > self.fs.options.limits.max_dir_depth {
+            error!("directory tree exceeds the depth limit");
+            return Err(Error::LimitExceeded);
+        }
+        if !self.visited.insert(cluster) {
+            warn!(
+                "directory at cluster {} is referenced more than once - skipping it",
+                cluster
+            );
+            return Ok(());
+        }
+        let path = if parent.path.is_empty() {
+            entry.file_name()
+        } else {
+            format!("{}/{}", parent.path, entry.file_name())
+        };
+        self.pending.push(PendingDir {
+            cluster: Some(cluster),
+            path,
+            depth,
+        });
+        Ok(())
+    }
+}
+
+/// A directory returned by `DirQueueIter`.
+pub struct DirQueueEntry<'a, IO: ReadWriteSeek, TP, OCC> {
+    dir: Dir<'a, IO, TP, OCC>,
+    path: String,
+    depth: u32,
+}
+
+impl<'a, IO: ReadWriteSeek, TP, OCC> DirQueueEntry<'a, IO, TP, OCC> {
+    /// Returns the directory.
+    #[must_use]
+    pub fn dir(&self) -> &Dir<'a, IO, TP, OCC> {
+        &self.dir
+    }
+
+    /// Returns the directory path relative to the root directory. The root directory has an empty path.
+    #[must_use]
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Returns the number of directories between the root directory and this directory (0 for the root directory).
+    #[must_use]
+    pub fn depth(&self) -> u32 {
+        self.depth
+    }
+
+    /// Returns the directory consuming this entry.
+    #[must_use]
+    pub fn into_dir(self) -> Dir<'a, IO, TP, OCC> {
+        self.dir
+    }
+}
+
+/// An iterator over all directories on the volume.
+///
+/// It is returned by the `dir_queue_iter` method on `FileSystem`. Directories are returned in the depth-first order
+/// starting with the root directory. Subdirectories of a directory are found when the directory is returned, so
+/// changes made to it afterwards are not taken into account.
+///
+/// The iterator uses a queue allocated on the heap instead of recursion so deeply nested directories do not grow the
+/// stack. A directory referenced by more than one entry (e.g. a directory loop in a corrupted filesystem) is returned
+/// only once.
+pub struct DirQueueIter<'a, IO: ReadWriteSeek, TP, OCC> {
+    queue: DirQueue<'a, IO, TP, OCC>,
+    err: bool,
+}
+
+impl<'a, IO: ReadWriteSeek, TP: TimeProvider, OCC: OemCpConverter> DirQueueIter<'a, IO, TP, OCC> {
+    pub(crate) fn new(fs: &'a FileSystem<IO, TP, OCC>) -> Self {
+        Self {
+            queue: DirQueue::new(fs),
+            err: false,
+        }
+    }
+
+    fn queue_subdirs(&mut self, parent: &DirQueueEntry<'a, IO, TP, OCC>) -> Result<(), Error<IO::Error>> {
+        let start = self.queue.pending.len();
+        for r in parent.dir.iter() {
+            let e = r?;
+            let name = e.short_file_name_as_bytes();
+            // ignore special entries "." and ".."
+            if e.is_dir() && name != b"." && name != b".." {
+                self.queue.push(parent, &e)?;
+            }
+        }
+        // keep the order of subdirectories
+        self.queue.pending[start..].reverse();
+        Ok(())
+    }
+}
+
+impl<'a, IO: ReadWriteSeek, TP: TimeProvider, OCC: OemCpConverter> Iterator for DirQueueIter<'a, IO, TP, OCC> {
+    type Item = Result<DirQueueEntry<'a, IO, TP, OCC>, Error<IO::Error>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.err {
+            return None;
+        }
+        let entry = self.queue.pop()?;
+        if let Err(err) = self.queue_subdirs(&entry) {
+            self.err = true;
+            return Some(Err(err));
+        }
+        Some(Ok(entry))
+    }
+}
@@ -1,3 +1,6 @@
+#[cfg(all(not(feature = "std"), feature = "alloc"))]
+use alloc::string::String;
+
 /// Error enum with all errors that can be returned by functions from this crate
 ///
 /// Generic parameter `T` is a type of external error returned by the user provided storage
@@ -13,10 +16,23 @@ pub enum Error<T> {
     /// A parameter was incorrect.
     InvalidInput,
     /// A requested file or directory has not been found.
-    NotFound,
+    ///
+    /// Fields are only available if the `alloc` feature is enabled - use `Error::NotFound { .. }` pattern to match
+    /// this error in code independent of enabled features.
+    NotFound {
+        /// Path component that has not been found.
+        #[cfg(feature = "alloc")]
+        missing_component: String,
+        /// Path components found before the missing one joined by '/' (empty if the first component is missing).
+        #[cfg(feature = "alloc")]
+        resolved_prefix: String,
+    },
     /// A file or a directory with the same name already exists.
     AlreadyExists,
     /// An operation cannot be finished because a directory is not empty.
+    ///
+    /// It is returned e.g. by `Dir::remove` and is converted to `std::io::Error` of kind `InvalidInput` with
+    /// "Directory is not empty" message, so it can be distinguished from a missing path (`NotFound` kind).
     DirectoryIsNotEmpty,
     /// File system internal structures are corrupted/invalid.
     CorruptedFileSystem,
@@ -26,6 +42,89 @@ pub enum Error<T> {
     InvalidFileNameLength,
     /// The provided file name contains an invalid character.
     UnsupportedFileNameCharacter,
+    /// The provided file name cannot be used on Windows (see `FsOptions::windows_name_rules`).
+    UnsupportedWindowsFileName {
+        /// The violated rule.
+        rule: WindowsNameRule,
+    },
+    /// Data read back after a write differs from the written data (see `FsOptions::verify_writes`).
+    WriteVerificationFailed {
+        /// Offset of the first mismatching byte relative to the storage start.
+        offset: u64,
+    },
+    /// The storage size differs from the size observed when the filesystem was mounted (see
+    /// `FileSystem::refresh_geometry`).
+    StorageSizeChanged,
+    /// An operation exceeds one of the limits configured by `FsOptions::limits`.
+    LimitExceeded,
+    /// A file would become larger than the maximal file size supported by FAT (4 GiB minus 1 byte).
+    FileTooLarge,
+    /// The storage is too small to contain the filesystem structures needed to mount it.
+    StorageTooSmall {
+        /// Storage length in bytes.
+        len: u64,
+        /// Minimal storage length in bytes required by the filesystem geometry (or the boot sector size if the
+        /// geometry cannot be read).
+        min_len: u64,
+    },
+    /// A cluster needed to restore a deleted entry has been allocated again since the deletion.
+    ClusterInUse {
+        /// The allocated cluster.
+        cluster: u32,
+    },
+    /// An operation is not allowed by the options used to open a file (e.g. a write to a file opened without write
+    /// access, see `OpenOptions`).
+    PermissionDenied,
+    /// The filesystem is already in use by an operation that has not finished yet.
+    ///
+    /// This error is caused by a reentrant access, e.g. from a callback invoked by the filesystem. It does not
+    /// detect use from multiple threads - with the `unsound_sync` feature enabled such use is a data race (undefined
+    /// behavior) and may not result in this error.
+    Busy,
+}
+
+/// A rule for names of created files and directories enforced for compatibility with Windows.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+#[non_exhaustive]
+pub enum WindowsNameRule {
+    /// The name (ignoring the extension) is a reserved DOS device name, e.g. `CON` or `lpt1.txt`.
+    ReservedDeviceName,
+    /// The name ends with a dot or a space.
+    TrailingDotOrSpace,
+}
+
+impl core::fmt::Display for WindowsNameRule {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            WindowsNameRule::ReservedDeviceName => write!(f, "name is a reserved device name"),
+            WindowsNameRule::TrailingDotOrSpace => write!(f, "name ends with a dot or a space"),
+        }
+    }
+}
+
+impl<T> Error<T> {
+    #[cfg_attr(not(feature = "alloc"), allow(unused_variables))]
+    pub(crate) fn not_found(name: &str) -> Self {
+        Error::NotFound {
+            #[cfg(feature = "alloc")]
+            missing_component: name.into(),
+            #[cfg(feature = "alloc")]
+            resolved_prefix: String::new(),
+        }
+    }
+
+    /// Sets path components found before the missing one in the `NotFound` error. Other errors are not changed.
+    #[cfg_attr(not(feature = "alloc"), allow(unused_variables))]
+    pub(crate) fn with_resolved_prefix(self, resolved_prefix: &str) -> Self {
+        match self {
+            #[cfg(feature = "alloc")]
+            Error::NotFound { missing_component, .. } => Error::NotFound {
+                missing_component,
+                resolved_prefix: resolved_prefix.into(),
+            },
+            _ => self,
+        }
+    }
 }
 
 impl<T: IoError> From<T> for Error<T> {
@@ -39,15 +138,25 @@ impl From<Error<std::io::Error>> for std::io::Error {
     fn from(error: Error<Self>) -> Self {
         match error {
             Error::Io(io_error) => io_error,
-            Error::UnexpectedEof | Error::NotEnoughSpace => Self::new(std::io::ErrorKind::UnexpectedEof, error),
+            Error::UnexpectedEof | Error::NotEnoughSpace | Error::StorageTooSmall { .. } => {
+                Self::new(std::io::ErrorKind::UnexpectedEof, error)
+            }
             Error::WriteZero => Self::new(std::io::ErrorKind::WriteZero, error),
             Error::InvalidInput
             | Error::InvalidFileNameLength
             | Error::UnsupportedFileNameCharacter
+            | Error::UnsupportedWindowsFileName { .. }
             | Error::DirectoryIsNotEmpty => Self::new(std::io::ErrorKind::InvalidInput, error),
-            Error::NotFound => Self::new(std::io::ErrorKind::NotFound, error),
+            Error::NotFound { .. } => Self::new(std::io::ErrorKind::NotFound, error),
             Error::AlreadyExists => Self::new(std::io::ErrorKind::AlreadyExists, error),
+            Error::PermissionDenied => Self::new(std::io::ErrorKind::PermissionDenied, error),
             Error::CorruptedFileSystem => Self::new(std::io::ErrorKind::InvalidData, error),
+            Error::WriteVerificationFailed { .. }
+            | Error::StorageSizeChanged
+            | Error::LimitExceeded
+            | Error::FileTooLarge
+            | Error::ClusterInUse { .. }
+            | Error::Busy => Self::new(std::io::ErrorKind::Other, error),
         }
     }
 }
@@ -62,10 +171,43 @@ impl<T: core::fmt::Display> core::fmt::Display for Error<T> {
             Error::InvalidInput => write!(f, "Invalid input"),
             Error::InvalidFileNameLength => write!(f, "Invalid file name length"),
             Error::UnsupportedFileNameCharacter => write!(f, "Unsupported file name character"),
+            Error::UnsupportedWindowsFileName { rule } => {
+                f.write_str("Unsupported file name: ")?;
+                core::fmt::Display::fmt(rule, f)
+            }
             Error::DirectoryIsNotEmpty => write!(f, "Directory is not empty"),
-            Error::NotFound => write!(f, "No such file or directory"),
+            #[cfg(feature = "alloc")]
+            Error::NotFound {
+                missing_component,
+                resolved_prefix,
+            } if !missing_component.is_empty() => {
+                if resolved_prefix.is_empty() {
+                    write!(f, "No such file or directory: {}", missing_component)
+                } else {
+                    write!(
+                        f,
+                        "No such file or directory: {}/{}",
+                        resolved_prefix, missing_component
+                    )
+                }
+            }
+            Error::NotFound { .. } => write!(f, "No such file or directory"),
             Error::AlreadyExists => write!(f, "File or directory already exists"),
             Error::CorruptedFileSystem => write!(f, "Corrupted file system"),
+            Error::WriteVerificationFailed { offset } => {
+                write!(f, "Write verification failed at offset {:#x}", offset)
+            }
+            Error::StorageSizeChanged => write!(f, "Storage size has changed"),
+            Error::LimitExceeded => write!(f, "Configured limit exceeded"),
+            Error::FileTooLarge => write!(f, "File too large"),
+            Error::StorageTooSmall { len, min_len } => write!(
+                f,
+                "Storage is too small: {} bytes long but at least {} bytes are required",
+                len, min_len
+            ),
+            Error::ClusterInUse { cluster } => write!(f, "Cluster {} is in use", *cluster),
+            Error::PermissionDenied => write!(f, "Permission denied"),
+            Error::Busy => write!(f, "Filesystem is busy - reentrant access detected"),
         }
     }
 }
@@ -88,6 +230,17 @@ pub trait IoError: core::fmt::Debug {
     fn is_interrupted(&self) -> bool;
     fn new_unexpected_eof_error() -> Self;
     fn new_write_zero_error() -> Self;
+
+    /// Creates an error returned when an invalid argument is passed (e.g. seek to a negative offset).
+    ///
+    /// The default implementation returns `new_unexpected_eof_error()` for compatibility with older implementations.
+    #[must_use]
+    fn new_invalid_input_error() -> Self
+    where
+        Self: Sized,
+    {
+        Self::new_unexpected_eof_error()
+    }
 }
 
 impl<T: core::fmt::Debug + IoError> IoError for Error<T> {
@@ -105,6 +258,10 @@ impl<T: core::fmt::Debug + IoError> IoError for Error<T> {
     fn new_write_zero_error() -> Self {
         Error::<T>::WriteZero
     }
+
+    fn new_invalid_input_error() -> Self {
+        Error::<T>::InvalidInput
+    }
 }
 
 impl IoError for () {
@@ -119,6 +276,10 @@ impl IoError for () {
     fn new_write_zero_error() -> Self {
         // empty
     }
+
+    fn new_invalid_input_error() -> Self {
+        // empty
+    }
 }
 
 #[cfg(feature = "std")]
@@ -134,4 +295,8 @@ impl IoError for std::io::Error {
     fn new_write_zero_error() -> Self {
         Self::new(std::io::ErrorKind::WriteZero, "failed to write whole buffer")
     }
+
+    fn new_invalid_input_error() -> Self {
+        Self::new(std::io::ErrorKind::InvalidInput, "invalid input")
+    }
 }
@@ -1,17 +1,24 @@
 use core::cmp;
 use core::convert::TryFrom;
 
+use crate::dir::Dir;
 use crate::dir_entry::DirEntryEditor;
 use crate::error::Error;
-use crate::fs::{FileSystem, ReadWriteSeek};
+use crate::fs::{FileSystem, OemCpConverter, ReadWriteSeek};
 use crate::io::{IoBase, Read, Seek, SeekFrom, Write};
+use crate::open_files::SharedFileState;
 use crate::time::{Date, DateTime, TimeProvider};
 
-const MAX_FILE_SIZE: u32 = core::u32::MAX;
+pub(crate) const MAX_FILE_SIZE: u32 = core::u32::MAX;
 
 /// A FAT filesystem file object used for reading and writing data.
 ///
 /// This struct is created by the `open_file` or `create_file` methods on `Dir`.
+///
+/// Multiple handles of the same file (opened more than once or cloned) share the file size and the first cluster,
+/// so data written through one of them is visible through the others and the directory entry written back by the
+/// last flush is never outdated. Handles positioned beyond the end of a file truncated through another handle are
+/// moved to the new end. Sharing requires the `alloc` feature.
 pub struct File<'a, IO: ReadWriteSeek, TP, OCC> {
     // Note first_cluster is None if file is empty
     first_cluster: Option<u32>,
@@ -21,33 +28,261 @@ pub struct File<'a, IO: ReadWriteSeek, TP, OCC> {
     offset: u32,
     // file dir entry editor - None for root dir
     entry: Option<DirEntryEditor>,
+    // detects loops in the cluster chain followed by read and write
+    loop_detector: ClusterLoopDetector,
+    // operations allowed by the options used to open the file
+    access: FileAccess,
+    // registration in the table of open files - None for directories
+    open_file_id: Option<u32>,
+    // number of truncations done through all handles of the file known to this handle
+    truncations: u32,
     // file-system reference
     fs: &'a FileSystem<IO, TP, OCC>,
 }
 
+/// Operations allowed on a file handle.
+#[derive(Clone, Copy, Debug)]
+struct FileAccess {
+    read: bool,
+    write: bool,
+    // every write is done at the end of the file
+    append: bool,
+}
+
+impl FileAccess {
+    const READ: Self = Self {
+        read: true,
+        write: false,
+        append: false,
+    };
+    const READ_WRITE: Self = Self {
+        read: true,
+        write: true,
+        append: false,
+    };
+}
+
+/// Options and flags which can be used to configure how a file is opened.
+///
+/// This builder is created by the `open_options` method on `Dir` and works like `std::fs::OpenOptions`. All flags
+/// are initially `false`, so at least one of `read`, `write` and `append` must be set before calling `open`.
+#[allow(clippy::struct_excessive_bools)]
+pub struct OpenOptions<'a, IO: ReadWriteSeek, TP, OCC> {
+    dir: Dir<'a, IO, TP, OCC>,
+    read: bool,
+    write: bool,
+    append: bool,
+    truncate: bool,
+    create: bool,
+    create_new: bool,
+}
+
+/// Detects a loop in a cluster chain followed one cluster at a time (Brent's algorithm).
+///
+/// A loop in a corrupted FAT would make a directory infinite. The detector uses constant memory and finds the loop
+/// after following at most a few times more clusters than the loop and the part of the chain before it contain.
+#[derive(Clone, Copy, Debug)]
+struct ClusterLoopDetector {
+    saved_cluster: Option<u32>,
+    steps: u32,
+    power: u32,
+    // offset of the last visited cluster - visiting it again (e.g. when an I/O operation is retried) is not a step
+    last_offset: Option<u32>,
+}
+
+impl ClusterLoopDetector {
+    fn new(start_cluster: Option<u32>) -> Self {
+        Self {
+            saved_cluster: start_cluster,
+            steps: 0,
+            power: 1,
+            last_offset: None,
+        }
+    }
+
+    /// Returns false if `cluster` starting at `offset` in the file closes a loop.
+    fn visit(&mut self, cluster: u32, offset: u32) -> bool {
+        if self.last_offset == Some(offset) {
+            return true;
+        }
+        self.last_offset = Some(offset);
+        if self.saved_cluster == Some(cluster) {
+            return false;
+        }
+        self.steps += 1;
+        if self.steps == self.power {
+            self.saved_cluster = Some(cluster);
+            self.power = self.power.saturating_mul(2);
+            self.steps = 0;
+        }
+        true
+    }
+}
+
+/// Position of a file handle saved by the positional I/O methods.
+#[derive(Clone, Copy, Debug)]
+struct FilePosition {
+    offset: u32,
+    current_cluster: Option<u32>,
+    loop_detector: ClusterLoopDetector,
+    truncations: u32,
+}
+
+/// Returns the error reported when a file would become `size` bytes long exceeding the maximal file size.
+fn file_size_error<E>(size: u64) -> Error<E> {
+    if size > u64::from(MAX_FILE_SIZE) {
+        error!("file size would exceed the maximal file size supported by FAT");
+        Error::FileTooLarge
+    } else {
+        error!("file size would exceed the configured limit");
+        Error::LimitExceeded
+    }
+}
+
+/// Merges consecutive clusters of a chain into runs returned as the first cluster and the number of clusters.
+struct ClusterRuns<I> {
+    clusters: I,
+    run: Option<(u32, u32)>,
+}
+
+impl<I> ClusterRuns<I> {
+    fn new(clusters: I) -> Self {
+        Self { clusters, run: None }
+    }
+}
+
+impl<E, I: Iterator<Item = Result<u32, E>>> Iterator for ClusterRuns<I> {
+    type Item = Result<(u32, u32), E>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.clusters.next() {
+                Some(Ok(cluster)) => match self.run {
+                    Some((first, count)) if first + count == cluster => self.run = Some((first, count + 1)),
+                    Some(run) => {
+                        self.run = Some((cluster, 1));
+                        return Some(Ok(run));
+                    }
+                    None => self.run = Some((cluster, 1)),
+                },
+                Some(Err(err)) => return Some(Err(err)),
+                None => return self.run.take().map(Ok),
+            }
+        }
+    }
+}
+
 /// An extent containing a file's data on disk.
 ///
-/// This is created by the `extents` method on `File`, and represents
-/// a byte range on the disk that contains a file's data. All values
-/// are in bytes.
-#[derive(Clone, Debug)]
+/// This is created by the `extents` method on `File`, and represents a maximal run of physically contiguous clusters
+/// containing a file's data. All values are in bytes. The offset is relative to the beginning of the storage used by
+/// the `FileSystem` - divide it by the sector size to get the sector number.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct Extent {
     pub offset: u64,
-    pub size: u32,
+    pub len: u64,
+}
+
+/// Fragmentation statistics of a file.
+///
+/// This is created by the `fragmentation` method on `File`. An extent is a run of contiguous clusters allocated for
+/// the file. Clusters allocated beyond the file size (e.g. preallocated by a write) are included, so extent sizes are
+/// multiples of the cluster size.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default)]
+pub struct FragInfo {
+    pub(crate) extents: u32,
+    pub(crate) largest_extent: u64,
+    pub(crate) smallest_extent: u64,
+    pub(crate) allocated_bytes: u64,
+}
+
+impl FragInfo {
+    fn add_extent(&mut self, size: u64) {
+        self.smallest_extent = if self.extents == 0 {
+            size
+        } else {
+            cmp::min(self.smallest_extent, size)
+        };
+        self.largest_extent = cmp::max(self.largest_extent, size);
+        self.allocated_bytes += size;
+        self.extents += 1;
+    }
+
+    /// Number of extents (0 for an empty file, 1 for a file that is not fragmented)
+    #[must_use]
+    pub fn extents(&self) -> u32 {
+        self.extents
+    }
+
+    /// Size of the largest extent in bytes (0 for an empty file)
+    #[must_use]
+    pub fn largest_extent(&self) -> u64 {
+        self.largest_extent
+    }
+
+    /// Size of the smallest extent in bytes (0 for an empty file)
+    #[must_use]
+    pub fn smallest_extent(&self) -> u64 {
+        self.smallest_extent
+    }
+
+    /// Size of all clusters allocated for the file in bytes
+    #[must_use]
+    pub fn allocated_bytes(&self) -> u64 {
+        self.allocated_bytes
+    }
+
+    /// Fragmentation score - number of extents per MiB of allocated space (0 for an empty file)
+    ///
+    /// Files with a higher score need more seeks to be read.
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn score(&self) -> f64 {
+        if self.allocated_bytes == 0 {
+            0.0
+        } else {
+            f64::from(self.extents) * 1_048_576.0 / self.allocated_bytes as f64
+        }
+    }
 }
 
 impl<'a, IO: ReadWriteSeek, TP, OCC> File<'a, IO, TP, OCC> {
     pub(crate) fn new(
-        first_cluster: Option<u32>,
-        entry: Option<DirEntryEditor>,
+        mut first_cluster: Option<u32>,
+        mut entry: Option<DirEntryEditor>,
         fs: &'a FileSystem<IO, TP, OCC>,
     ) -> Self {
+        let mut open_file_id = None;
+        let mut truncations = 0;
+        if let Some(ref mut e) = entry {
+            if let Some(size) = e.inner().size() {
+                // the entry read from the directory can be outdated if the file is already open
+                let state = SharedFileState {
+                    size,
+                    first_cluster,
+                    truncations,
+                };
+                let (id, state) = fs.open_files.borrow_mut().open(e.pos(), state);
+                e.set_shared_state(state.size, state.first_cluster, fs.fat_type());
+                first_cluster = state.first_cluster;
+                truncations = state.truncations;
+                open_file_id = id;
+            }
+        }
         File {
             first_cluster,
             entry,
             fs,
             current_cluster: None, // cluster before first one
             offset: 0,
+            loop_detector: ClusterLoopDetector::new(first_cluster),
+            access: if fs.options.read_only {
+                FileAccess::READ
+            } else {
+                FileAccess::READ_WRITE
+            },
+            open_file_id,
+            truncations,
         }
     }
 
@@ -55,14 +290,26 @@ impl<'a, IO: ReadWriteSeek, TP, OCC> File<'a, IO, TP, OCC> {
     ///
     /// # Errors
     ///
-    /// `Error::Io` will be returned if the underlying storage object returned an I/O error.
+    /// Errors that can be returned:
+    ///
+    /// * `Error::PermissionDenied` will be returned if the file has been opened without write access (see
+    ///   `OpenOptions`).
+    /// * `Error::Io` will be returned if the underlying storage object returned an I/O error.
     ///
     /// # Panics
     ///
     /// Will panic if this is the root directory.
     pub fn truncate(&mut self) -> Result<(), Error<IO::Error>> {
         trace!("File::truncate");
+        if !self.access.write {
+            error!("file has been opened without write access");
+            return Err(Error::PermissionDenied);
+        }
+        self.refresh_shared_state()?;
         if let Some(ref mut e) = self.entry {
+            if self.fs.options.update_archive_attribute && e.inner().size() != Some(self.offset) {
+                e.set_archive();
+            }
             e.set_size(self.offset);
             if self.offset == 0 {
                 e.set_first_cluster(None, self.fs.fat_type());
@@ -74,48 +321,99 @@ impl<'a, IO: ReadWriteSeek, TP, OCC> File<'a, IO, TP, OCC> {
         if let Some(current_cluster) = self.current_cluster {
             // current cluster is none only if offset is 0
             debug_assert!(self.offset > 0);
-            self.fs.truncate_cluster_chain(current_cluster)
+            self.fs.truncate_cluster_chain(current_cluster)?;
         } else {
             debug_assert!(self.offset == 0);
             if let Some(n) = self.first_cluster {
                 self.fs.free_cluster_chain(n)?;
                 self.first_cluster = None;
             }
-            Ok(())
         }
+        // other handles can be positioned in the freed clusters
+        self.truncations = self.truncations.wrapping_add(1);
+        self.store_shared_state();
+        Ok(())
     }
 
-    /// Get the extents of a file on disk.
+    /// Enables or disables the append mode of this handle.
     ///
-    /// This returns an iterator over the byte ranges on-disk occupied by
-    /// this file.
-    pub fn extents(&mut self) -> impl Iterator<Item=Result<Extent, Error<IO::Error>>> + 'a {
+    /// In the append mode every write is done at the current end of the file (including data written through other
+    /// handles) and leaves the position there. Seeks affect only reads. Unlike `OpenOptions::append` it does not grant
+    /// write access to a handle opened without it.
+    pub fn set_append(&mut self, append: bool) {
+        self.access.append = append;
+    }
 
+    /// Returns the extents of a file on disk.
+    ///
+    /// Each extent is a maximal run of physically contiguous clusters, found by following the cluster chain of the
+    /// file. The last extent is clipped to the file size, so the data of the file can be read by concatenating the
+    /// extents (e.g. by a DMA engine). Clusters allocated beyond the file size are not included.
+    ///
+    /// Errors that can be returned by the iterator:
+    ///
+    /// * `Error::CorruptedFileSystem` will be returned if the cluster chain is invalid.
+    /// * `Error::Io` will be returned if the underlying storage object returned an I/O error.
+    pub fn extents(&self) -> impl Iterator<Item = Result<Extent, Error<IO::Error>>> + 'a {
         let fs = self.fs;
-        let cluster_size = fs.cluster_size();
-        let mut bytes_left = match self.size() {
-            Some(s) => s,
-            None => return None.into_iter().flatten(),
-        };
-        let first = match self.first_cluster {
-            Some(f) => f,
-            None => return None.into_iter().flatten(),
-        };
+        let cluster_size = u64::from(fs.cluster_size());
+        let (size, first_cluster) = self.current_size_and_first_cluster();
+        // directories have no size
+        let bytes_left = size.map_or(u64::MAX, u64::from);
+        self.cluster_runs(first_cluster)
+            .scan(bytes_left, move |bytes_left, r| match r {
+                Ok(_) if *bytes_left == 0 => None,
+                Ok((first, count)) => {
+                    let len = cmp::min(u64::from(count) * cluster_size, *bytes_left);
+                    *bytes_left -= len;
+                    Some(Ok(Extent {
+                        offset: fs.offset_from_cluster(first),
+                        len,
+                    }))
+                }
+                Err(err) => Some(Err(err)),
+            })
+    }
 
-        Some(core::iter::once(Ok(first)).chain(fs.cluster_iter(first))
-             .map(move |cluster_err| {
-                 match cluster_err {
-                     Ok(cluster) => {
-                         let size = cluster_size.min(bytes_left);
-                         bytes_left -= size;
-                         Ok(Extent {
-                             offset: fs.offset_from_cluster(cluster),
-                             size: size,
-                         })
-                     },
-                     Err(e) => Err(e),
-                 }
-             })).into_iter().flatten()
+    /// Returns fragmentation statistics of this file.
+    ///
+    /// The whole cluster chain is followed so it can take a while for big files.
+    ///
+    /// # Errors
+    ///
+    /// Errors that can be returned:
+    ///
+    /// * `Error::CorruptedFileSystem` will be returned if the cluster chain is invalid.
+    /// * `Error::Io` will be returned if the underlying storage object returned an I/O error.
+    pub fn fragmentation(&self) -> Result<FragInfo, Error<IO::Error>> {
+        let cluster_size = u64::from(self.fs.cluster_size());
+        let mut info = FragInfo::default();
+        for r in self.cluster_runs(self.current_size_and_first_cluster().1) {
+            let (_, count) = r?;
+            info.add_extent(u64::from(count) * cluster_size);
+        }
+        Ok(info)
+    }
+
+    // Returns the size (None for a directory) and the first cluster including changes made through other handles
+    fn current_size_and_first_cluster(&self) -> (Option<u32>, Option<u32>) {
+        match self.open_file_id.and_then(|id| self.fs.open_files.borrow().get(id)) {
+            Some(state) => (Some(state.size), state.first_cluster),
+            None => (self.size(), self.first_cluster),
+        }
+    }
+
+    // Returns runs of contiguous clusters of the whole chain starting at `first_cluster`
+    fn cluster_runs(
+        &self,
+        first_cluster: Option<u32>,
+    ) -> impl Iterator<Item = Result<(u32, u32), Error<IO::Error>>> + 'a {
+        let fs = self.fs;
+        let clusters = first_cluster
+            .map(|first| core::iter::once(Ok(first)).chain(fs.cluster_iter(first)))
+            .into_iter()
+            .flatten();
+        ClusterRuns::new(clusters)
     }
 
     pub(crate) fn abs_pos(&self) -> Option<u64> {
@@ -139,13 +437,110 @@ impl<'a, IO: ReadWriteSeek, TP, OCC> File<'a, IO, TP, OCC> {
         }
     }
 
+    /// Returns the cluster following the current cluster (the first cluster if the position is 0).
+    fn next_cluster(&mut self) -> Result<Option<u32>, Error<IO::Error>> {
+        if let Some(current_cluster) = self.current_cluster {
+            match self.fs.cluster_iter(current_cluster).next() {
+                Some(Err(err)) => Err(err),
+                // a chain looping back to its first cluster is detected right away
+                Some(Ok(n)) if Some(n) == self.first_cluster || !self.loop_detector.visit(n, self.offset) => {
+                    error!(
+                        "cluster chain contains a loop (cluster {} follows cluster {})",
+                        n, current_cluster
+                    );
+                    Err(Error::CorruptedFileSystem)
+                }
+                Some(Ok(n)) => Ok(Some(n)),
+                None => Ok(None),
+            }
+        } else {
+            Ok(self.first_cluster)
+        }
+    }
+
+    // Returns the number of bytes that can be transferred in one storage operation starting at `offset_in_cluster`
+    // of `cluster`, limited by `max_size` and the run of clusters following each other on disk.
+    // Note: the chain is not checked for loops here - a run is strictly increasing and the size of a file limits reads
+    // and writes anyway. Errors are left for `next_cluster` to report when the cluster is really needed.
+    fn contiguous_bytes(&self, cluster: u32, offset_in_cluster: u32, max_size: usize) -> usize {
+        let cluster_size = self.fs.cluster_size() as usize;
+        let mut size = cluster_size - offset_in_cluster as usize;
+        if max_size <= size || self.size().is_none() {
+            return cmp::min(size, max_size);
+        }
+        let mut prev_cluster = cluster;
+        for r in self.fs.cluster_iter(cluster) {
+            match r {
+                Ok(n) if n == prev_cluster + 1 && Some(n) != self.first_cluster => {
+                    prev_cluster = n;
+                    size += cluster_size;
+                    if size >= max_size {
+                        return max_size;
+                    }
+                }
+                _ => break,
+            }
+        }
+        size
+    }
+
+    // Writes the directory entry back only if the size, the first cluster, the attributes or a timestamp has changed
     fn flush_dir_entry(&mut self) -> Result<(), Error<IO::Error>> {
+        self.load_shared_state();
         if let Some(ref mut e) = self.entry {
             e.flush(self.fs)?;
         }
         Ok(())
     }
 
+    fn shared_state(&self) -> SharedFileState {
+        SharedFileState {
+            size: self.size().unwrap_or(0),
+            first_cluster: self.first_cluster,
+            truncations: self.truncations,
+        }
+    }
+
+    // Picks up the size and the first cluster changed through other handles of the same file
+    fn load_shared_state(&mut self) {
+        if let Some(state) = self.open_file_id.and_then(|id| self.fs.open_files.borrow().get(id)) {
+            if state.size == self.size().unwrap_or(0) && state.first_cluster == self.first_cluster {
+                return;
+            }
+            if let Some(ref mut e) = self.entry {
+                e.set_shared_state(state.size, state.first_cluster, self.fs.fat_type());
+            }
+            self.first_cluster = state.first_cluster;
+            if self.current_cluster.is_none() {
+                self.loop_detector = ClusterLoopDetector::new(self.first_cluster);
+            }
+        }
+    }
+
+    // Like `load_shared_state` but also finds the position again if clusters of the file have been freed through
+    // another handle, because the current cluster may not belong to the file anymore
+    fn refresh_shared_state(&mut self) -> Result<(), Error<IO::Error>> {
+        self.load_shared_state();
+        if let Some(state) = self.open_file_id.and_then(|id| self.fs.open_files.borrow().get(id)) {
+            if state.truncations != self.truncations {
+                self.truncations = state.truncations;
+                let offset = cmp::min(self.offset, state.size);
+                self.offset = 0;
+                self.current_cluster = None;
+                self.loop_detector = ClusterLoopDetector::new(self.first_cluster);
+                self.seek(SeekFrom::Start(u64::from(offset)))?;
+            }
+        }
+        Ok(())
+    }
+
+    // Makes the size and the first cluster changed through this handle visible to other handles of the file
+    fn store_shared_state(&self) {
+        if let Some(id) = self.open_file_id {
+            self.fs.open_files.borrow_mut().set(id, self.shared_state());
+        }
+    }
+
     /// Sets date and time of creation for this file.
     ///
     /// Note: it is set to a value from the `TimeProvider` when creating a file.
@@ -170,7 +565,8 @@ impl<'a, IO: ReadWriteSeek, TP, OCC> File<'a, IO, TP, OCC> {
 
     /// Sets date and time of last modification for this file.
     ///
-    /// Note: it is overwritten by a value from the `TimeProvider` on every file write operation.
+    /// Note: it is overwritten by a value from the `TimeProvider` on every file write operation unless
+    /// `FsOptions::update_modified_time` is disabled.
     /// Deprecated: if needed implement a custom `TimeProvider`.
     #[deprecated]
     pub fn set_modified(&mut self, date_time: DateTime) {
@@ -193,6 +589,21 @@ impl<'a, IO: ReadWriteSeek, TP, OCC> File<'a, IO, TP, OCC> {
         }
     }
 
+    fn should_verify_writes(&self) -> bool {
+        let policy = self.fs.options.verify_writes;
+        // Note: only the root directory has no entry
+        let is_metadata = self.entry.is_none() || self.is_dir();
+        policy.verify_data() || (is_metadata && policy.verify_metadata())
+    }
+
+    /// Returns `size` if it does not exceed the maximal file size.
+    fn check_new_size(&self, size: u64) -> Result<u32, Error<IO::Error>> {
+        match u32::try_from(size) {
+            Ok(n) if n <= self.fs.options.limits.max_file_size => Ok(n),
+            _ => Err(file_size_error(size)),
+        }
+    }
+
     fn bytes_left_in_file(&self) -> Option<usize> {
         // Note: seeking beyond end of file is not allowed so overflow is impossible
         self.size().map(|s| (s - self.offset) as usize)
@@ -209,20 +620,349 @@ impl<'a, IO: ReadWriteSeek, TP, OCC> File<'a, IO, TP, OCC> {
         self.first_cluster
     }
 
-    fn flush(&mut self) -> Result<(), Error<IO::Error>> {
+    /// Flushes the file data to the storage without updating the directory entry if possible.
+    ///
+    /// Data and FAT changes are written to the storage when a write call returns so this function only flushes the
+    /// storage object. The directory entry is written back only if the file got its first cluster (or lost it)
+    /// since the last flush, because the data could not be found without it. Changes of the size and timestamps
+    /// are kept in memory until `sync_all` or `flush` is called or the file is dropped.
+    ///
+    /// This is useful for files that are often appended to (e.g. logs) - the directory sector is not rewritten after
+    /// every write. If the power is lost after calling only this function the size stored in the directory entry can
+    /// be smaller than the amount of data written to the file. The data beyond that size is not visible and
+    /// `FileSystem::check` reports a `SizeMismatch` for the file if clusters were allocated for it.
+    ///
+    /// # Errors
+    ///
+    /// `Error::Io` will be returned if the underlying storage object returned an I/O error.
+    pub fn sync_data(&mut self) -> Result<(), Error<IO::Error>> {
+        trace!("File::sync_data");
+        self.load_shared_state();
+        if let Some(ref mut e) = self.entry {
+            e.flush_first_cluster(self.fs)?;
+        }
+        self.fs.disk_mut()?.flush()?;
+        Ok(())
+    }
+
+    /// Flushes the file data and writes back the directory entry if it has changed.
+    ///
+    /// After this function returns the size and timestamps stored on the storage match the file. It is called by
+    /// `flush` and when the file is dropped.
+    ///
+    /// # Errors
+    ///
+    /// `Error::Io` will be returned if the underlying storage object returned an I/O error.
+    pub fn sync_all(&mut self) -> Result<(), Error<IO::Error>> {
+        trace!("File::sync_all");
         self.flush_dir_entry()?;
-        let mut disk = self.fs.disk.borrow_mut();
-        disk.flush()?;
+        self.fs.disk_mut()?.flush()?;
         Ok(())
     }
+
+    /// Flushes the file and closes it.
+    ///
+    /// It is the same as dropping the file but errors are reported instead of being only logged, e.g. if the
+    /// directory entry cannot be written because of an I/O error. Calling it after `flush` or `sync_all` does not
+    /// write anything again. The handle is closed even if an error is returned - changes that could not be written are
+    /// not retried.
+    ///
+    /// # Errors
+    ///
+    /// `Error::Io` will be returned if the underlying storage object returned an I/O error.
+    pub fn close(mut self) -> Result<(), Error<IO::Error>> {
+        trace!("File::close");
+        let result = self.sync_all();
+        // `Drop` must not write the entry again
+        self.entry = None;
+        result
+    }
 }
 
 impl<IO: ReadWriteSeek, TP: TimeProvider, OCC> File<'_, IO, TP, OCC> {
+    /// Sets the length of the file to `len` bytes.
+    ///
+    /// If `len` is smaller than the current length clusters beyond the new end are freed (the partially used last
+    /// cluster is kept). If it is larger the file is extended with zeros, allocating clusters as needed. If the
+    /// extension fails (e.g. because there is not enough free space) the clusters allocated by it are freed, so the
+    /// file keeps its previous length. The current position is not changed unless it is beyond the new end - then it
+    /// is moved to the end.
+    ///
+    /// # Errors
+    ///
+    /// Errors that can be returned:
+    ///
+    /// * `Error::PermissionDenied` will be returned if the file has been opened without write access (see
+    ///   `OpenOptions`).
+    /// * `Error::InvalidInput` will be returned if this file is a directory.
+    /// * `Error::FileTooLarge` will be returned if `len` exceeds the maximal file size supported by FAT (4 GiB minus
+    ///   1 byte).
+    /// * `Error::LimitExceeded` will be returned if `len` exceeds the maximal file size configured by
+    ///   `FsOptions::limits`.
+    /// * `Error::NotEnoughSpace` will be returned if there is not enough free space to extend the file.
+    /// * `Error::Io` will be returned if the underlying storage object returned an I/O error.
+    ///
+    /// # Panics
+    ///
+    /// Will panic if this is the root directory.
+    pub fn set_len(&mut self, len: u64) -> Result<(), Error<IO::Error>> {
+        trace!("File::set_len {}", len);
+        if !self.access.write {
+            error!("file has been opened without write access");
+            return Err(Error::PermissionDenied);
+        }
+        self.refresh_shared_state()?;
+        let size = match self.size() {
+            Some(s) if !self.is_dir() => s,
+            _ => {
+                error!("length of a directory cannot be set");
+                return Err(Error::InvalidInput);
+            }
+        };
+        let len = self.check_new_size(len)?;
+        let offset = self.offset;
+        if len < size {
+            self.seek(SeekFrom::Start(u64::from(len)))?;
+            self.truncate()?;
+        } else if len > size {
+            self.seek(SeekFrom::Start(u64::from(size)))?;
+            if let Err(err) = self.write_zeros(len - size) {
+                // free clusters allocated for the extension
+                self.seek(SeekFrom::Start(u64::from(size)))?;
+                self.truncate()?;
+                self.seek(SeekFrom::Start(u64::from(offset)))?;
+                return Err(err);
+            }
+        }
+        self.seek(SeekFrom::Start(u64::from(cmp::min(offset, len))))?;
+        Ok(())
+    }
+
+    /// Reads exactly `buf.len()` bytes starting at `offset` in the file without changing the current position.
+    ///
+    /// It works like seeking to `offset` and calling `read_exact` but the position is restored without following the
+    /// cluster chain again. A seek forward follows the chain from the current cluster, so accessing records in
+    /// ascending order does not walk the chain from the start every time.
+    ///
+    /// # Errors
+    ///
+    /// Errors that can be returned:
+    ///
+    /// * `Error::UnexpectedEof` will be returned if the file ends before the buffer is filled.
+    /// * `Error::PermissionDenied` will be returned if the file has been opened without read access.
+    /// * `Error::CorruptedFileSystem` will be returned if the cluster chain is invalid.
+    /// * `Error::Io` will be returned if the underlying storage object returned an I/O error.
+    pub fn read_exact_at(&mut self, offset: u64, buf: &mut [u8]) -> Result<(), Error<IO::Error>> {
+        trace!("File::read_exact_at {}", offset);
+        let position = self.position();
+        let result = self.seek_within_file(offset).and_then(|pos| {
+            if pos < offset && !buf.is_empty() {
+                error!("offset is beyond the end of file");
+                Err(Error::UnexpectedEof)
+            } else {
+                self.read_exact(buf)
+            }
+        });
+        self.set_position(position);
+        result
+    }
+
+    /// Writes the whole buffer starting at `offset` in the file without changing the current position.
+    ///
+    /// It works like seeking to `offset` and calling `write_all`, also in the append mode. If `offset` is beyond the
+    /// end of the file the gap is filled with zeros.
+    ///
+    /// # Errors
+    ///
+    /// Errors that can be returned:
+    ///
+    /// * `Error::FileTooLarge` will be returned if the file would exceed the maximal file size supported by FAT.
+    /// * `Error::LimitExceeded` will be returned if the file would exceed the limit set by `FsLimits`.
+    /// * `Error::PermissionDenied` will be returned if the file has been opened without write access.
+    /// * `Error::NotEnoughSpace` will be returned if there is not enough free space.
+    /// * `Error::Io` will be returned if the underlying storage object returned an I/O error.
+    pub fn write_all_at(&mut self, offset: u64, buf: &[u8]) -> Result<(), Error<IO::Error>> {
+        trace!("File::write_all_at {}", offset);
+        if buf.is_empty() {
+            // like `write_all` of an empty buffer - the file is not extended up to `offset`
+            return Ok(());
+        }
+        // an offset close to `u64::MAX` is beyond the maximal file size anyway
+        let end = offset
+            .checked_add(buf.len() as u64)
+            .ok_or_else(|| file_size_error(u64::MAX))?;
+        self.check_new_size(end)?;
+        let position = self.position();
+        let append = self.access.append;
+        self.access.append = false;
+        let result = self.seek_within_file(offset).and_then(|pos| {
+            if pos < offset {
+                self.write_zeros((offset - pos) as u32)?;
+            }
+            self.write_all(buf)
+        });
+        self.access.append = append;
+        self.set_position(position);
+        result
+    }
+
+    // Seeks to `offset` or to the end of file if it is smaller and returns the new position
+    fn seek_within_file(&mut self, offset: u64) -> Result<u64, Error<IO::Error>> {
+        self.seek(SeekFrom::Start(cmp::min(offset, u64::from(MAX_FILE_SIZE))))
+    }
+
+    fn position(&self) -> FilePosition {
+        FilePosition {
+            offset: self.offset,
+            current_cluster: self.current_cluster,
+            loop_detector: self.loop_detector,
+            truncations: self.truncations,
+        }
+    }
+
+    fn set_position(&mut self, position: FilePosition) {
+        self.offset = position.offset;
+        self.current_cluster = position.current_cluster;
+        self.loop_detector = position.loop_detector;
+        // if the file has been truncated through another handle in the meantime the next operation finds the
+        // restored position again
+        self.truncations = position.truncations;
+    }
+
+    /// Allocates clusters needed to store `size` bytes in the file in advance.
+    ///
+    /// Missing clusters are appended to the cluster chain of the file. A run of consecutive free clusters is preferred
+    /// (starting right after the last cluster of the file if possible), so data written later is not fragmented. If
+    /// there is no such run the clusters are allocated one by one. Writes use the preallocated clusters instead of
+    /// allocating new ones.
+    ///
+    /// If `update_len` is `false` the file length is not changed. Clusters not used by writes stay allocated until
+    /// the file is truncated (e.g. using `truncate` or `set_len`), so `FileSystem::check` reports a size mismatch for
+    /// the file until then. If `update_len` is `true` and the file is shorter than `size` bytes its length is set to
+    /// `size`. Note: in that case the new part of the file is not initialized - it contains data previously stored
+    /// in the allocated clusters. Use `set_len` to extend a file with zeros.
+    ///
+    /// Returns `true` if the new clusters have been allocated as a single contiguous run (`true` is returned if no
+    /// cluster was needed too).
+    ///
+    /// # Errors
+    ///
+    /// Errors that can be returned:
+    ///
+    /// * `Error::PermissionDenied` will be returned if the file has been opened without write access (see
+    ///   `OpenOptions`).
+    /// * `Error::InvalidInput` will be returned if this file is a directory.
+    /// * `Error::FileTooLarge` will be returned if `size` exceeds the maximal file size supported by FAT (4 GiB minus
+    ///   1 byte).
+    /// * `Error::LimitExceeded` will be returned if `size` exceeds the maximal file size configured by
+    ///   `FsOptions::limits`.
+    /// * `Error::NotEnoughSpace` will be returned if there is not enough free space. Clusters allocated by this call
+    ///   are freed in that case.
+    /// * `Error::CorruptedFileSystem` will be returned if the cluster chain of the file is invalid.
+    /// * `Error::Io` will be returned if the underlying storage object returned an I/O error.
+    pub fn preallocate(&mut self, size: u64, update_len: bool) -> Result<bool, Error<IO::Error>> {
+        trace!("File::preallocate {} {}", size, update_len);
+        if !self.access.write {
+            error!("file has been opened without write access");
+            return Err(Error::PermissionDenied);
+        }
+        self.refresh_shared_state()?;
+        let old_size = match self.size() {
+            Some(s) if !self.is_dir() => s,
+            _ => {
+                error!("directory cannot be preallocated");
+                return Err(Error::InvalidInput);
+            }
+        };
+        let size = self.check_new_size(size)?;
+        let needed_clusters = self.fs.clusters_from_bytes(u64::from(size));
+        // find the last cluster of the file - the chain is followed only up to the needed length
+        let mut num_clusters = 0;
+        let mut last_cluster = self.first_cluster;
+        if let Some(first_cluster) = self.first_cluster {
+            num_clusters = 1;
+            let mut iter = self.fs.cluster_iter(first_cluster);
+            while num_clusters < needed_clusters {
+                match iter.next() {
+                    Some(r) => last_cluster = Some(r?),
+                    None => break,
+                }
+                num_clusters += 1;
+            }
+        }
+        let mut contiguous = true;
+        if needed_clusters > num_clusters {
+            self.fs.set_dirty_flag(true)?;
+            let count = needed_clusters - num_clusters;
+            let preferred_cluster = last_cluster.map(|n| n + 1);
+            let first_new_cluster = if let Some(first) = self.fs.find_free_cluster_run(preferred_cluster, count)? {
+                self.fs.alloc_cluster_range(last_cluster, first, count)?;
+                first
+            } else {
+                contiguous = false;
+                self.alloc_clusters_one_by_one(last_cluster, count)?
+            };
+            if self.first_cluster.is_none() {
+                self.set_first_cluster(first_new_cluster);
+                self.loop_detector = ClusterLoopDetector::new(self.first_cluster);
+            }
+        }
+        if update_len && size > old_size {
+            if let Some(ref mut e) = self.entry {
+                e.set_size(size);
+                if self.fs.options.update_modified_time {
+                    e.set_modified(self.fs.options.time_provider.get_current_date_time());
+                }
+                if self.fs.options.update_archive_attribute {
+                    e.set_archive();
+                }
+            }
+        }
+        self.store_shared_state();
+        Ok(contiguous)
+    }
+
+    /// Allocates `count` clusters appending them to `prev_cluster` and returns the first of them.
+    ///
+    /// If the allocation fails clusters allocated by this call are freed.
+    fn alloc_clusters_one_by_one(&self, prev_cluster: Option<u32>, count: u32) -> Result<u32, Error<IO::Error>> {
+        let first_cluster = self.fs.alloc_cluster(prev_cluster, false)?;
+        let mut cluster = first_cluster;
+        for _ in 1..count {
+            cluster = match self.fs.alloc_cluster(Some(cluster), false) {
+                Ok(n) => n,
+                Err(err) => {
+                    match prev_cluster {
+                        Some(n) => self.fs.truncate_cluster_chain(n)?,
+                        None => self.fs.free_cluster_chain(first_cluster)?,
+                    }
+                    return Err(err);
+                }
+            };
+        }
+        Ok(first_cluster)
+    }
+
+    fn write_zeros(&mut self, mut len: u32) -> Result<(), Error<IO::Error>> {
+        const ZEROS: [u8; 512] = [0_u8; 512];
+        while len > 0 {
+            let write_size = cmp::min(len, ZEROS.len() as u32);
+            self.write_all(&ZEROS[..write_size as usize])?;
+            len -= write_size;
+        }
+        Ok(())
+    }
+
     fn update_dir_entry_after_write(&mut self) {
         let offset = self.offset;
         if let Some(ref mut e) = self.entry {
-            let now = self.fs.options.time_provider.get_current_date_time();
-            e.set_modified(now);
+            if self.fs.options.update_modified_time {
+                let now = self.fs.options.time_provider.get_current_date_time();
+                e.set_modified(now);
+            }
+            if self.fs.options.update_archive_attribute {
+                e.set_archive();
+            }
             if e.inner().size().map_or(false, |s| offset > s) {
                 e.set_size(offset);
             }
@@ -232,20 +972,30 @@ impl<IO: ReadWriteSeek, TP: TimeProvider, OCC> File<'_, IO, TP, OCC> {
 
 impl<IO: ReadWriteSeek, TP, OCC> Drop for File<'_, IO, TP, OCC> {
     fn drop(&mut self) {
-        if let Err(err) = self.flush() {
+        if let Err(err) = self.sync_all() {
             error!("flush failed {:?}", err);
         }
+        if let Some(id) = self.open_file_id {
+            self.fs.open_files.borrow_mut().close(id);
+        }
     }
 }
 
 // Note: derive cannot be used because of invalid bounds. See: https://github.com/rust-lang/rust/issues/26925
 impl<IO: ReadWriteSeek, TP, OCC> Clone for File<'_, IO, TP, OCC> {
     fn clone(&self) -> Self {
+        if let Some(id) = self.open_file_id {
+            self.fs.open_files.borrow_mut().reopen(id);
+        }
         File {
             first_cluster: self.first_cluster,
             current_cluster: self.current_cluster,
             offset: self.offset,
             entry: self.entry.clone(),
+            loop_detector: self.loop_detector,
+            access: self.access,
+            open_file_id: self.open_file_id,
+            truncations: self.truncations,
             fs: self.fs,
         }
     }
@@ -258,20 +1008,19 @@ impl<IO: ReadWriteSeek, TP, OCC> IoBase for File<'_, IO, TP, OCC> {
 impl<IO: ReadWriteSeek, TP: TimeProvider, OCC> Read for File<'_, IO, TP, OCC> {
     fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
         trace!("File::read");
+        if !self.access.read {
+            error!("file has been opened without read access");
+            return Err(Error::PermissionDenied);
+        }
+        self.refresh_shared_state()?;
+        if self.size().unwrap_or(0) > self.fs.options.limits.max_file_size {
+            error!("file size exceeds the configured limit");
+            return Err(Error::LimitExceeded);
+        }
         let cluster_size = self.fs.cluster_size();
         let current_cluster_opt = if self.offset % cluster_size == 0 {
             // next cluster
-            match self.current_cluster {
-                None => self.first_cluster,
-                Some(n) => {
-                    let r = self.fs.cluster_iter(n).next();
-                    match r {
-                        Some(Err(err)) => return Err(err),
-                        Some(Ok(n)) => Some(n),
-                        None => None,
-                    }
-                }
-            }
+            self.next_cluster()?
         } else {
             self.current_cluster
         };
@@ -282,25 +1031,23 @@ impl<IO: ReadWriteSeek, TP: TimeProvider, OCC> Read for File<'_, IO, TP, OCC> {
         let offset_in_cluster = self.offset % cluster_size;
         let bytes_left_in_cluster = (cluster_size - offset_in_cluster) as usize;
         let bytes_left_in_file = self.bytes_left_in_file().unwrap_or(bytes_left_in_cluster);
-        let read_size = cmp::min(cmp::min(buf.len(), bytes_left_in_cluster), bytes_left_in_file);
-        if read_size == 0 {
+        let max_read_size = cmp::min(buf.len(), bytes_left_in_file);
+        if max_read_size == 0 {
             return Ok(0);
         }
+        // read following clusters in the same call if they are contiguous
+        let read_size = self.contiguous_bytes(current_cluster, offset_in_cluster, max_read_size);
         trace!("read {} bytes in cluster {}", read_size, current_cluster);
         let offset_in_fs = self.fs.offset_from_cluster(current_cluster) + u64::from(offset_in_cluster);
-        let read_bytes = {
-            let mut disk = self.fs.disk.borrow_mut();
-            disk.seek(SeekFrom::Start(offset_in_fs))?;
-            disk.read(&mut buf[..read_size])?
-        };
+        let read_bytes = self.fs.read_at(offset_in_fs, &mut buf[..read_size])?;
         if read_bytes == 0 {
             return Ok(0);
         }
         self.offset += read_bytes as u32;
-        self.current_cluster = Some(current_cluster);
+        self.current_cluster = Some(current_cluster + (offset_in_cluster + read_bytes as u32 - 1) / cluster_size);
 
         if let Some(ref mut e) = self.entry {
-            if self.fs.options.update_accessed_date {
+            if self.fs.options.update_accessed_date && !self.fs.options.read_only {
                 let now = self.fs.options.time_provider.get_current_date();
                 e.set_accessed(now);
             }
@@ -322,14 +1069,24 @@ where
 impl<IO: ReadWriteSeek, TP: TimeProvider, OCC> Write for File<'_, IO, TP, OCC> {
     fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
         trace!("File::write");
+        if !self.access.write {
+            error!("file has been opened without write access");
+            return Err(Error::PermissionDenied);
+        }
+        self.refresh_shared_state()?;
+        if self.access.append {
+            self.seek(SeekFrom::End(0))?;
+        }
         let cluster_size = self.fs.cluster_size();
         let offset_in_cluster = self.offset % cluster_size;
-        let bytes_left_in_cluster = (cluster_size - offset_in_cluster) as usize;
-        let bytes_left_until_max_file_size = (MAX_FILE_SIZE - self.offset) as usize;
-        let write_size = cmp::min(buf.len(), bytes_left_in_cluster);
-        let write_size = cmp::min(write_size, bytes_left_until_max_file_size);
+        let max_file_size = self.fs.options.limits.max_file_size;
+        let bytes_left_until_max_file_size = max_file_size.saturating_sub(self.offset) as usize;
+        let max_write_size = cmp::min(buf.len(), bytes_left_until_max_file_size);
         // Exit early if we are going to write no data
-        if write_size == 0 {
+        if max_write_size == 0 {
+            if !buf.is_empty() {
+                return Err(file_size_error(u64::from(max_file_size) + 1));
+            }
             return Ok(0);
         }
         // Mark the volume 'dirty'
@@ -337,17 +1094,7 @@ impl<IO: ReadWriteSeek, TP: TimeProvider, OCC> Write for File<'_, IO, TP, OCC> {
         // Get cluster for write possibly allocating new one
         let current_cluster = if self.offset % cluster_size == 0 {
             // next cluster
-            let next_cluster = match self.current_cluster {
-                None => self.first_cluster,
-                Some(n) => {
-                    let r = self.fs.cluster_iter(n).next();
-                    match r {
-                        Some(Err(err)) => return Err(err),
-                        Some(Ok(n)) => Some(n),
-                        None => None,
-                    }
-                }
-            };
+            let next_cluster = self.next_cluster()?;
             if let Some(n) = next_cluster {
                 n
             } else {
@@ -366,25 +1113,26 @@ impl<IO: ReadWriteSeek, TP: TimeProvider, OCC> Write for File<'_, IO, TP, OCC> {
                 None => panic!("Offset inside cluster but no cluster allocated"),
             }
         };
+        // write to following clusters in the same call if they are already allocated and contiguous
+        let write_size = self.contiguous_bytes(current_cluster, offset_in_cluster, max_write_size);
         trace!("write {} bytes in cluster {}", write_size, current_cluster);
         let offset_in_fs = self.fs.offset_from_cluster(current_cluster) + u64::from(offset_in_cluster);
-        let written_bytes = {
-            let mut disk = self.fs.disk.borrow_mut();
-            disk.seek(SeekFrom::Start(offset_in_fs))?;
-            disk.write(&buf[..write_size])?
-        };
+        let written_bytes = self
+            .fs
+            .write_at(offset_in_fs, &buf[..write_size], self.should_verify_writes())?;
         if written_bytes == 0 {
             return Ok(0);
         }
         // some bytes were writter - update position and optionally size
         self.offset += written_bytes as u32;
-        self.current_cluster = Some(current_cluster);
+        self.current_cluster = Some(current_cluster + (offset_in_cluster + written_bytes as u32 - 1) / cluster_size);
         self.update_dir_entry_after_write();
+        self.store_shared_state();
         Ok(written_bytes)
     }
 
     fn flush(&mut self) -> Result<(), Self::Error> {
-        Self::flush(self)
+        self.sync_all()
     }
 }
 
@@ -409,6 +1157,7 @@ where
 impl<IO: ReadWriteSeek, TP, OCC> Seek for File<'_, IO, TP, OCC> {
     fn seek(&mut self, pos: SeekFrom) -> Result<u64, Self::Error> {
         trace!("File::seek");
+        self.refresh_shared_state()?;
         let size_opt = self.size();
         let new_offset_opt: Option<u32> = match pos {
             SeekFrom::Current(x) => i64::from(self.offset)
@@ -447,10 +1196,15 @@ impl<IO: ReadWriteSeek, TP, OCC> Seek for File<'_, IO, TP, OCC> {
             // return the previous cluster if the offset points to the cluster boundary
             // Note: new_offset_in_clusters cannot be 0 here because new_offset is not 0
             debug_assert!(new_offset_in_clusters > 0);
-            let clusters_to_skip = new_offset_in_clusters - 1;
-            let mut cluster = first_cluster;
-            let mut iter = self.fs.cluster_iter(first_cluster);
-            for i in 0..clusters_to_skip {
+            // when seeking forward continue from the current cluster - its index is old_offset_in_clusters - 1
+            let (start_index, mut cluster) = match self.current_cluster {
+                Some(n) if old_offset_in_clusters > 0 && new_offset_in_clusters > old_offset_in_clusters => {
+                    (old_offset_in_clusters - 1, n)
+                }
+                _ => (0, first_cluster),
+            };
+            let mut iter = self.fs.cluster_iter(cluster);
+            for i in start_index..new_offset_in_clusters - 1 {
                 cluster = if let Some(r) = iter.next() {
                     r?
                 } else {
@@ -467,6 +1221,7 @@ impl<IO: ReadWriteSeek, TP, OCC> Seek for File<'_, IO, TP, OCC> {
         };
         self.offset = new_offset;
         self.current_cluster = new_cluster;
+        self.loop_detector = ClusterLoopDetector::new(new_cluster.or(self.first_cluster));
         Ok(u64::from(self.offset))
     }
 }
@@ -480,3 +1235,126 @@ where
         Ok(Seek::seek(self, pos.into())?)
     }
 }
+
+impl<'a, IO: ReadWriteSeek, TP: TimeProvider, OCC: OemCpConverter> OpenOptions<'a, IO, TP, OCC> {
+    pub(crate) fn new(dir: &Dir<'a, IO, TP, OCC>) -> Self {
+        OpenOptions {
+            dir: dir.clone(),
+            read: false,
+            write: false,
+            append: false,
+            truncate: false,
+            create: false,
+            create_new: false,
+        }
+    }
+
+    /// Sets the option for read access.
+    ///
+    /// Reading from a file opened without read access returns `Error::PermissionDenied`.
+    #[must_use]
+    pub fn read(mut self, read: bool) -> Self {
+        self.read = read;
+        self
+    }
+
+    /// Sets the option for write access.
+    ///
+    /// Writing to or truncating a file opened without write access returns `Error::PermissionDenied`.
+    #[must_use]
+    pub fn write(mut self, write: bool) -> Self {
+        self.write = write;
+        self
+    }
+
+    /// Sets the option for the append mode.
+    ///
+    /// Every write is done at the end of the file, regardless of the position set by `seek`. It implies write
+    /// access.
+    #[must_use]
+    pub fn append(mut self, append: bool) -> Self {
+        self.append = append;
+        self
+    }
+
+    /// Sets the option for truncating an existing file.
+    ///
+    /// The file length is set to 0 and its clusters are freed when the file is opened. It requires write access
+    /// and cannot be combined with `append`.
+    #[must_use]
+    pub fn truncate(mut self, truncate: bool) -> Self {
+        self.truncate = truncate;
+        self
+    }
+
+    /// Sets the option to create a new file or open it if it already exists.
+    ///
+    /// It requires write or append access.
+    #[must_use]
+    pub fn create(mut self, create: bool) -> Self {
+        self.create = create;
+        self
+    }
+
+    /// Sets the option to create a new file failing if an entry with the same name already exists.
+    ///
+    /// It works like `Dir::create_new_file`, requires write or append access and takes precedence over `create` and
+    /// `truncate`.
+    #[must_use]
+    pub fn create_new(mut self, create_new: bool) -> Self {
+        self.create_new = create_new;
+        self
+    }
+
+    /// Opens a file at `path` with the options specified by `self`.
+    ///
+    /// `path` is a '/' separated file path relative to the directory this builder has been created for.
+    ///
+    /// # Errors
+    ///
+    /// Errors that can be returned:
+    ///
+    /// * `Error::InvalidInput` will be returned if the combination of options is invalid: none of `read`, `write`
+    ///   and `append` is set, `truncate`, `create` or `create_new` is set without write access or `truncate` is
+    ///   combined with `append`.
+    /// * `Error::NotFound` will be returned if the file does not exist and neither `create` nor `create_new` is set.
+    /// * `Error::AlreadyExists` will be returned if `create_new` is set and an entry with the same name exists.
+    /// * `Error::PermissionDenied` will be returned if write or append access is requested and the filesystem is
+    ///   mounted read-only.
+    /// * Other errors are the same as for `Dir::open_file` and `Dir::create_file`.
+    pub fn open(&self, path: &str) -> Result<File<'a, IO, TP, OCC>, Error<IO::Error>> {
+        trace!("OpenOptions::open {}", path);
+        let write = self.write || self.append;
+        if !self.read && !write {
+            error!("file must be opened with read, write or append access");
+            return Err(Error::InvalidInput);
+        }
+        if (self.truncate || self.create || self.create_new) && !write {
+            error!("creating or truncating a file requires write access");
+            return Err(Error::InvalidInput);
+        }
+        if self.truncate && self.append {
+            error!("truncate cannot be combined with append");
+            return Err(Error::InvalidInput);
+        }
+        let mut file = if self.create_new {
+            self.dir.create_new_file(path)?
+        } else if self.create {
+            self.dir.create_file(path)?
+        } else {
+            self.dir.open_file(path)?
+        };
+        if write {
+            file.fs.check_writable()?;
+        }
+        if self.truncate && !self.create_new {
+            file.truncate()?;
+        }
+        file.access = FileAccess {
+            read: self.read,
+            write,
+            append: self.append,
+        };
+        Ok(file)
+    }
+}
@@ -0,0 +1,132 @@
+//! Formatting helpers for user interfaces.
+//!
+//! All helpers write directly to the `Formatter` and can be used without the `alloc` feature.
+
+use core::fmt::{self, Write};
+use core::str;
+
+const UNITS: [&str; 7] = ["B", "KiB", "MiB", "GiB", "TiB", "PiB", "EiB"];
+
+/// A size in bytes formatted using binary prefixes.
+///
+/// Sizes below 1000 bytes are formatted as an integer number of bytes (e.g. `999 B`). Bigger sizes are formatted
+/// with one decimal digit using the smallest unit that keeps the integer part below 1000 (e.g. `1.0 KiB` for 1000
+/// bytes and `1.5 MiB` for 1572864 bytes). The value is rounded half up. Width, fill and alignment of the formatter
+/// are honored.
+///
+/// # Examples
+///
+/// ```rust
+/// use fatfs::fmt::HumanSize;
+///
+/// assert_eq!(format!("{}", HumanSize(1536)), "1.5 KiB");
+/// assert_eq!(format!("{:>9}", HumanSize(12)), "     12 B");
+/// ```
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct HumanSize(pub u64);
+
+impl fmt::Display for HumanSize {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // output is at most 9 bytes long (e.g. "999.9 KiB") - format it into a stack buffer so padding can be applied
+        let mut buf = StackBuf::new();
+        if self.0 < 1000 {
+            write!(buf, "{} {}", self.0, UNITS[0])?;
+        } else {
+            let (tenths, unit) = scale(self.0);
+            write!(buf, "{}.{} {}", tenths / 10, tenths % 10, unit)?;
+        }
+        f.pad(buf.as_str())
+    }
+}
+
+/// Returns the size in tenths of the selected unit.
+fn scale(size: u64) -> (u128, &'static str) {
+    let mut divisor = 1_u128;
+    for unit in &UNITS[1..] {
+        divisor *= 1024;
+        let tenths = (u128::from(size) * 10 + divisor / 2) / divisor;
+        if tenths < 10000 {
+            return (tenths, unit);
+        }
+    }
+    unreachable!("u64 sizes are below 1000 EiB")
+}
+
+/// A fixed-size buffer implementing `fmt::Write`.
+struct StackBuf {
+    data: [u8; 16],
+    len: usize,
+}
+
+impl StackBuf {
+    fn new() -> Self {
+        Self { data: [0; 16], len: 0 }
+    }
+
+    fn as_str(&self) -> &str {
+        // only complete `str` slices are written
+        str::from_utf8(&self.data[..self.len]).unwrap_or_default()
+    }
+}
+
+impl Write for StackBuf {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let end = self.len + s.len();
+        if end > self.data.len() {
+            return Err(fmt::Error);
+        }
+        self.data[self.len..end].copy_from_slice(s.as_bytes());
+        self.len = end;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fmt(size: u64) -> String {
+        format!("{}", HumanSize(size))
+    }
+
+    #[test]
+    fn bytes() {
+        assert_eq!(fmt(0), "0 B");
+        assert_eq!(fmt(1), "1 B");
+        assert_eq!(fmt(999), "999 B");
+    }
+
+    #[test]
+    fn unit_boundaries() {
+        assert_eq!(fmt(1000), "1.0 KiB");
+        assert_eq!(fmt(1023), "1.0 KiB");
+        assert_eq!(fmt(1024), "1.0 KiB");
+        assert_eq!(fmt(1075), "1.0 KiB");
+        assert_eq!(fmt(1076), "1.1 KiB");
+        assert_eq!(fmt(1536), "1.5 KiB");
+        assert_eq!(fmt(999 * 1024 + 972), "999.9 KiB");
+        assert_eq!(fmt(999 * 1024 + 973), "1.0 MiB");
+        assert_eq!(fmt(1024 * 1024), "1.0 MiB");
+        assert_eq!(fmt(1_572_864), "1.5 MiB");
+        assert_eq!(fmt(1 << 30), "1.0 GiB");
+        assert_eq!(fmt(1 << 40), "1.0 TiB");
+        assert_eq!(fmt(1 << 50), "1.0 PiB");
+        assert_eq!(fmt(1 << 60), "1.0 EiB");
+        assert_eq!(fmt(u64::MAX), "16.0 EiB");
+    }
+
+    #[test]
+    fn typical_volume_sizes() {
+        // 1.44 MB floppy
+        assert_eq!(fmt(1_474_560), "1.4 MiB");
+        // FAT32 limit of a file size
+        assert_eq!(fmt(u64::from(u32::MAX)), "4.0 GiB");
+    }
+
+    #[test]
+    fn padding() {
+        assert_eq!(format!("{:>8}", HumanSize(5)), "     5 B");
+        assert_eq!(format!("{:<8}|", HumanSize(2048)), "2.0 KiB |");
+        assert_eq!(format!("{:*^9}", HumanSize(2048)), "*2.0 KiB*");
+    }
+}
@@ -0,0 +1,149 @@
+#[cfg(not(feature = "std"))]
+use alloc::collections::BinaryHeap;
+#[cfg(not(feature = "std"))]
+use alloc::format;
+#[cfg(not(feature = "std"))]
+use alloc::string::String;
+#[cfg(not(feature = "std"))]
+use alloc::vec::Vec;
+use core::cmp::{Ordering, Reverse};
+#[cfg(feature = "std")]
+use std::collections::BinaryHeap;
+
+use crate::error::Error;
+use crate::file::FragInfo;
+use crate::fs::{FileSystem, OemCpConverter, ReadWriteSeek};
+use crate::time::TimeProvider;
+
+/// A file included in the `FragmentationReport`.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct FragmentedFile {
+    path: String,
+    info: FragInfo,
+}
+
+impl FragmentedFile {
+    /// Path of the file relative to the root directory
+    #[must_use]
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Fragmentation statistics of the file
+    #[must_use]
+    pub fn info(&self) -> &FragInfo {
+        &self.info
+    }
+}
+
+/// Orders files from the least to the most fragmented one. Files with the same number of extents are ordered by the
+/// path in reverse so the report lists them alphabetically.
+impl Ord for FragmentedFile {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.info
+            .extents()
+            .cmp(&other.info.extents())
+            .then_with(|| other.path.cmp(&self.path))
+    }
+}
+
+impl PartialOrd for FragmentedFile {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Fragmentation statistics of all files on the volume.
+///
+/// It is computed by the `fragmentation_report` method on `FileSystem`.
+#[derive(Clone, Eq, PartialEq, Debug, Default)]
+pub struct FragmentationReport {
+    files: u64,
+    fragmented_files: u64,
+    extents: u64,
+    allocated_bytes: u64,
+    most_fragmented: Vec<FragmentedFile>,
+}
+
+impl FragmentationReport {
+    /// Number of regular files
+    #[must_use]
+    pub fn files(&self) -> u64 {
+        self.files
+    }
+
+    /// Number of files consisting of more than one extent
+    #[must_use]
+    pub fn fragmented_files(&self) -> u64 {
+        self.fragmented_files
+    }
+
+    /// Total number of extents of all files
+    #[must_use]
+    pub fn extents(&self) -> u64 {
+        self.extents
+    }
+
+    /// Size of all clusters allocated for files in bytes
+    #[must_use]
+    pub fn allocated_bytes(&self) -> u64 {
+        self.allocated_bytes
+    }
+
+    /// Volume fragmentation score - number of extents per MiB of space allocated for files (0 if no space is
+    /// allocated)
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn score(&self) -> f64 {
+        if self.allocated_bytes == 0 {
+            0.0
+        } else {
+            self.extents as f64 * 1_048_576.0 / self.allocated_bytes as f64
+        }
+    }
+
+    /// Most fragmented files ordered by the number of extents (descending) and path
+    #[must_use]
+    pub fn most_fragmented(&self) -> &[FragmentedFile] {
+        &self.most_fragmented
+    }
+}
+
+pub(crate) fn fragmentation_report<IO: ReadWriteSeek, TP: TimeProvider, OCC: OemCpConverter>(
+    fs: &FileSystem<IO, TP, OCC>,
+    top_n: usize,
+) -> Result<FragmentationReport, Error<IO::Error>> {
+    let mut report = FragmentationReport::default();
+    // min-heap keeping at most `top_n` most fragmented files
+    let mut top = BinaryHeap::with_capacity(top_n + 1);
+    for r in fs.dir_queue_iter() {
+        let dir_entry = r?;
+        for r in dir_entry.dir().iter() {
+            let e = r?;
+            if e.is_dir() {
+                continue;
+            }
+            let info = e.to_file().fragmentation()?;
+            report.files += 1;
+            report.extents += u64::from(info.extents());
+            report.allocated_bytes += info.allocated_bytes();
+            if info.extents() > 1 {
+                report.fragmented_files += 1;
+            }
+            if top_n == 0 {
+                continue;
+            }
+            let path = if dir_entry.path().is_empty() {
+                e.file_name()
+            } else {
+                format!("{}/{}", dir_entry.path(), e.file_name())
+            };
+            top.push(Reverse(FragmentedFile { path, info }));
+            if top.len() > top_n {
+                top.pop();
+            }
+        }
+    }
+    report.most_fragmented = top.into_sorted_vec().into_iter().map(|Reverse(f)| f).collect();
+    Ok(report)
+}
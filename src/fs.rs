@@ -1,22 +1,37 @@
 #[cfg(all(not(feature = "std"), feature = "alloc"))]
+use alloc::boxed::Box;
+#[cfg(all(not(feature = "std"), feature = "alloc"))]
 use alloc::string::String;
+#[cfg(all(not(feature = "std"), feature = "alloc"))]
+use alloc::{vec, vec::Vec};
 use core::borrow::BorrowMut;
-use core::cell::{Cell, RefCell};
+use core::cell::{Cell, RefCell, RefMut};
 use core::char;
 use core::cmp;
 use core::convert::TryFrom;
-use core::fmt::Debug;
+use core::fmt::{self, Debug};
 use core::marker::PhantomData;
 use core::u32;
 
 use crate::boot_sector::{format_boot_sector, BiosParameterBlock, BootSector};
-use crate::dir::{Dir, DirRawStream};
+#[cfg(feature = "alloc")]
+use crate::check::{CheckBudget, CheckProgress, CheckReport, CheckState};
+use crate::dir::{Dir, DirRawStream, MAX_DIR_ENTRIES, MAX_LONG_NAME_LEN};
+use crate::dir_cache::DirCacheStorage;
 use crate::dir_entry::{DirFileEntryData, FileAttributes, SFN_PADDING, SFN_SIZE};
-use crate::error::Error;
-use crate::file::File;
+#[cfg(feature = "alloc")]
+use crate::dir_queue::{DirQueue, DirQueueIter};
+use crate::error::{Error, IoError};
+use crate::file::{File, MAX_FILE_SIZE};
+#[cfg(feature = "alloc")]
+use crate::fragmentation::{self, FragmentationReport};
 use crate::io::{self, IoBase, Read, ReadLeExt, Seek, SeekFrom, Write, WriteLeExt};
+use crate::open_files::OpenFiles;
+#[cfg(feature = "alloc")]
+use crate::scoped_dir::ScopedDir;
 use crate::table::{
-    alloc_cluster, count_free_clusters, format_fat, read_fat_flags, ClusterIterator, RESERVED_FAT_ENTRIES,
+    alloc_cluster, alloc_cluster_range, count_free_clusters, encode_fat_flags, find_free_cluster_run, format_fat,
+    read_fat, read_fat_flags, ClusterIterator, FatValue, RESERVED_FAT_ENTRIES,
 };
 use crate::time::{DefaultTimeProvider, TimeProvider};
 
@@ -116,6 +131,87 @@ impl FsStatusFlags {
             io_error: flags & 2 != 0,
         }
     }
+
+    pub(crate) fn union(self, other: Self) -> Self {
+        Self {
+            dirty: self.dirty || other.dirty,
+            io_error: self.io_error || other.io_error,
+        }
+    }
+}
+
+/// Status flags stored in each of the locations used by FAT implementations.
+///
+/// It is returned by the `read_status_flag_sources` method on `FileSystem`.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct FsStatusFlagSources {
+    boot_sector: FsStatusFlags,
+    fat: Option<FsStatusFlags>,
+}
+
+impl FsStatusFlagSources {
+    /// Flags stored in the Boot Sector (`BS_Reserved1` field - offset 0x25 on FAT12/FAT16 and 0x41 on FAT32)
+    ///
+    /// This location is used by Windows NT and Linux.
+    #[must_use]
+    pub fn boot_sector(&self) -> FsStatusFlags {
+        self.boot_sector
+    }
+
+    /// Flags stored in the high bits of the second FAT entry (`None` on FAT12 which has no such bits)
+    ///
+    /// This location is described by the FAT specification and used by Windows 95 and later.
+    #[must_use]
+    pub fn fat(&self) -> Option<FsStatusFlags> {
+        self.fat
+    }
+}
+
+/// A volume label returned by the `read_volume_label` method on `FileSystem`.
+///
+/// The label is stored without trailing padding. The `Display` implementation uses characters decoded from the OEM
+/// codepage by the `OemCpConverter` of the filesystem.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct VolumeLabel {
+    bytes: [u8; SFN_SIZE],
+    chars: [char; SFN_SIZE],
+    len: usize,
+}
+
+impl VolumeLabel {
+    fn new<OCC: OemCpConverter>(raw: &[u8; SFN_SIZE], converter: &OCC) -> Self {
+        let len = raw.iter().rposition(|b| *b != SFN_PADDING).map_or(0, |p| p + 1);
+        let mut chars = [' '; SFN_SIZE];
+        for (c, b) in chars.iter_mut().zip(&raw[..len]) {
+            *c = converter.decode(*b);
+        }
+        Self {
+            bytes: *raw,
+            chars,
+            len,
+        }
+    }
+
+    /// Returns the label encoded in the OEM codepage.
+    #[must_use]
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..self.len]
+    }
+
+    /// Returns `true` if the label is empty.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl fmt::Display for VolumeLabel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for c in &self.chars[..self.len] {
+            fmt::Write::write_char(f, *c)?;
+        }
+        Ok(())
+    }
 }
 
 /// A sum of `Read` and `Seek` traits.
@@ -131,6 +227,8 @@ struct FsInfoSector {
     free_cluster_count: Option<u32>,
     next_free_cluster: Option<u32>,
     dirty: bool,
+    // false if the sector has not been read successfully - it is never written then
+    valid: bool,
 }
 
 impl FsInfoSector {
@@ -176,10 +274,11 @@ impl FsInfoSector {
             free_cluster_count,
             next_free_cluster,
             dirty: false,
+            valid: true,
         })
     }
 
-    fn serialize<W: Write>(&self, wrt: &mut W) -> Result<(), Error<W::Error>> {
+    fn serialize<W: Write>(&self, wrt: &mut W) -> Result<(), W::Error> {
         wrt.write_u32_le(Self::LEAD_SIG)?;
         let reserved = [0_u8; 480];
         wrt.write_all(&reserved)?;
@@ -206,7 +305,7 @@ impl FsInfoSector {
         if let Some(n) = self.next_free_cluster {
             if n > max_valid_cluster_number {
                 warn!(
-                    "invalid free_cluster_count ({}) in fs_info exceeds maximum cluster number ({})",
+                    "invalid next_free_cluster ({}) in fs_info exceeds maximum cluster number ({})",
                     n, max_valid_cluster_number
                 );
                 self.next_free_cluster = None;
@@ -214,11 +313,21 @@ impl FsInfoSector {
         }
     }
 
-    fn map_free_clusters(&mut self, map_fn: impl Fn(u32) -> u32) {
+    /// Updates the free cluster count if it is known.
+    ///
+    /// Returns false if the count turns out to be wrong (the result does not fit in range `0..=total_clusters`). The
+    /// count is not modified in that case.
+    fn map_free_clusters(&mut self, map_fn: impl Fn(u32) -> Option<u32>, total_clusters: u32) -> bool {
         if let Some(n) = self.free_cluster_count {
-            self.free_cluster_count = Some(map_fn(n));
-            self.dirty = true;
+            match map_fn(n) {
+                Some(n) if n <= total_clusters => {
+                    self.free_cluster_count = Some(n);
+                    self.dirty = true;
+                }
+                _ => return false,
+            }
         }
+        true
     }
 
     fn set_next_free_cluster(&mut self, cluster: u32) {
@@ -232,12 +341,137 @@ impl FsInfoSector {
     }
 }
 
+/// A write verification policy.
+///
+/// Controls which writes are read back from the storage and compared with the written data. Verification is specified
+/// by the `verify_writes` property in `FsOptions` struct.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum VerifyPolicy {
+    /// Written data is never read back.
+    None,
+    /// File Allocation Table, directory entries, FS Information Sector and Boot Sector writes (volume label and status
+    /// flags) are verified.
+    MetadataOnly,
+    /// All writes are verified, including file contents.
+    All,
+}
+
+impl VerifyPolicy {
+    pub(crate) fn verify_metadata(self) -> bool {
+        self != VerifyPolicy::None
+    }
+
+    pub(crate) fn verify_data(self) -> bool {
+        self == VerifyPolicy::All
+    }
+}
+
+impl Default for VerifyPolicy {
+    fn default() -> Self {
+        VerifyPolicy::None
+    }
+}
+
+/// A policy of characters allowed in names of created files and directories.
+///
+/// The policy is specified by the `filename_policy` property in `FsOptions` struct. It only applies to new names -
+/// existing entries can always be opened, renamed and removed regardless of characters in their names.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum FilenamePolicy {
+    /// Only characters allowed in long names by the FAT specification are accepted.
+    Strict,
+    /// All characters except control characters and `/` are accepted. This is the character set Linux and other
+    /// POSIX systems allow, so names created by such systems can be recreated. Names using characters outside of the
+    /// `Strict` set may be inaccessible on other systems (e.g. Windows).
+    Relaxed,
+}
+
+impl Default for FilenamePolicy {
+    fn default() -> Self {
+        FilenamePolicy::Strict
+    }
+}
+
+/// An algorithm generating short names of created files and directories.
+///
+/// The algorithm is specified by the `short_name_algorithm` property in `FsOptions` struct. It is only used when a long
+/// name cannot be stored as a short name without a loss, e.g. `Long File Name.txt`. In such case the name is converted
+/// to a short name with a numeric tail (`LONGFI~1.TXT`) and if that name is taken, the algorithm decides what
+/// candidates are tried next. All algorithms fall back to multi-digit numeric tails with a shortened prefix
+/// (`LONGF~10.TXT`) when other candidates are exhausted.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum ShortNameAlgorithm {
+    /// Numeric tails `~1`-`~4` are followed by a 2-character prefix with a BSD checksum of the long name and numeric
+    /// tails `~1`-`~9` (e.g. `LO1BEC~1.TXT`).
+    Checksum,
+    /// Numeric tails `~1`-`~4` are followed by a 2-character prefix with a hash of the long name and numeric tails
+    /// `~1`-`~9` (e.g. `LO8FE5~1.TXT`). The hash is the (reverse engineered) hash used by Windows NT and later, so
+    /// names created in the same order as on Windows get the same short names.
+    Windows,
+    /// Only numeric tails are used (`~1`, `~2` and so on) like in the Linux `vfat` driver.
+    NumericTail,
+}
+
+impl Default for ShortNameAlgorithm {
+    fn default() -> Self {
+        ShortNameAlgorithm::Checksum
+    }
+}
+
+/// Limits enforced by the filesystem in addition to the limits of the FAT format.
+///
+/// Limits are useful when processing untrusted images. An operation exceeding a limit fails with
+/// `Error::LimitExceeded`. Limits are specified by the `limits` property in `FsOptions` struct. Default limits are equal
+/// to the FAT format limits.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct FsLimits {
+    /// Maximal size of a file in bytes. Reading a bigger file and writing past this size fails.
+    ///
+    /// Files larger than the maximal size supported by FAT (the default value) cannot be created - writing past it
+    /// fails with `Error::FileTooLarge` instead of `Error::LimitExceeded`.
+    pub max_file_size: u32,
+    /// Maximal number of 32-byte entries (including deleted and LFN entries) in a directory.
+    pub max_dir_entries: u32,
+    /// Maximal length of a long file name in UTF-16 code units. Applies to created and read entries.
+    pub max_name_len: usize,
+    /// Maximal number of directories traversed by a path or a recursive directory tree walk.
+    pub max_dir_depth: u32,
+}
+
+impl Default for FsLimits {
+    fn default() -> Self {
+        Self {
+            max_file_size: MAX_FILE_SIZE,
+            max_dir_entries: MAX_DIR_ENTRIES,
+            max_name_len: MAX_LONG_NAME_LEN,
+            max_dir_depth: u32::MAX,
+        }
+    }
+}
+
 /// A FAT filesystem mount options.
 ///
 /// Options are specified as an argument for `FileSystem::new` method.
-#[derive(Copy, Clone, Debug, Default)]
+#[derive(Copy, Clone, Debug)]
+#[allow(clippy::struct_excessive_bools)]
 pub struct FsOptions<TP, OCC> {
     pub(crate) update_accessed_date: bool,
+    pub(crate) update_modified_time: bool,
+    pub(crate) update_archive_attribute: bool,
+    pub(crate) verify_writes: VerifyPolicy,
+    pub(crate) strict: bool,
+    pub(crate) repair: bool,
+    pub(crate) read_only: bool,
+    pub(crate) filename_policy: FilenamePolicy,
+    pub(crate) case_sensitive_lookup: bool,
+    pub(crate) backslash_separator: bool,
+    pub(crate) windows_name_rules: bool,
+    pub(crate) short_name_algorithm: ShortNameAlgorithm,
+    pub(crate) limits: FsLimits,
+    #[cfg(feature = "alloc")]
+    pub(crate) prefetch_fat: bool,
+    #[cfg(feature = "alloc")]
+    pub(crate) fat_cache_budget: usize,
     pub(crate) oem_cp_converter: OCC,
     pub(crate) time_provider: TP,
 }
@@ -246,10 +480,32 @@ impl FsOptions<DefaultTimeProvider, LossyOemCpConverter> {
     /// Creates a `FsOptions` struct with default options.
     #[must_use]
     pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<TP: Default, OCC: Default> Default for FsOptions<TP, OCC> {
+    fn default() -> Self {
         Self {
             update_accessed_date: false,
-            oem_cp_converter: LossyOemCpConverter::new(),
-            time_provider: DefaultTimeProvider::new(),
+            update_modified_time: true,
+            update_archive_attribute: true,
+            verify_writes: VerifyPolicy::None,
+            strict: false,
+            repair: false,
+            read_only: false,
+            filename_policy: FilenamePolicy::Strict,
+            case_sensitive_lookup: false,
+            backslash_separator: false,
+            windows_name_rules: true,
+            short_name_algorithm: ShortNameAlgorithm::Checksum,
+            limits: FsLimits::default(),
+            #[cfg(feature = "alloc")]
+            prefetch_fat: false,
+            #[cfg(feature = "alloc")]
+            fat_cache_budget: usize::MAX,
+            oem_cp_converter: OCC::default(),
+            time_provider: TP::default(),
         }
     }
 }
@@ -261,10 +517,202 @@ impl<TP: TimeProvider, OCC: OemCpConverter> FsOptions<TP, OCC> {
         self
     }
 
+    /// If enabled the modification date and time of a file is updated when its content is changed by a write.
+    ///
+    /// Updating the modification time makes every flush after a write rewrite the directory entry of the file, even
+    /// if the size has not changed. Disabling it reduces writes to directory sectors (e.g. wear of flash media caused
+    /// by small appends or in-place updates) - the entry is still written when the size or the first cluster changes.
+    /// To avoid rewriting the entry of a file modified in place disable `update_archive_attribute` too.
+    /// Default is `true`.
+    #[must_use]
+    pub fn update_modified_time(mut self, enabled: bool) -> Self {
+        self.update_modified_time = enabled;
+        self
+    }
+
+    /// If enabled the `ARCHIVE` attribute of a file is set when its content is changed by a write or a truncation.
+    ///
+    /// Backup tools use the attribute to find modified files. The attribute is written together with the size and
+    /// the modification time so it does not need an additional write. Directories never get the attribute.
+    /// Disabling it avoids changing directory entries of files modified in place if only the data matters (e.g. on
+    /// flash media when the modification time is not updated either).
+    /// Default is `true`.
+    #[must_use]
+    pub fn update_archive_attribute(mut self, enabled: bool) -> Self {
+        self.update_archive_attribute = enabled;
+        self
+    }
+
+    /// Selects which writes are read back and compared with the written data.
+    ///
+    /// A mismatch is reported as `Error::WriteVerificationFailed` and the volume is marked with the IO Error flag.
+    /// Verification is useful for unreliable media (e.g. counterfeit SD cards silently dropping writes). It reads data
+    /// back through the storage object so it is only meaningful if the storage does not cache written data.
+    /// Default is `VerifyPolicy::None`.
+    #[must_use]
+    pub fn verify_writes(mut self, policy: VerifyPolicy) -> Self {
+        self.verify_writes = policy;
+        self
+    }
+
+    /// If enabled operations creating valid but questionable filesystem structures fail instead of logging a warning.
+    ///
+    /// For example creating a file in the root directory with a short name equal to the volume label is rejected
+    /// with `Error::AlreadyExists`.
+    /// Default is `false`.
+    #[must_use]
+    pub fn strict(mut self, enabled: bool) -> Self {
+        self.strict = enabled;
+        self
+    }
+
+    /// If enabled inconsistencies found in filesystem structures during normal operations are fixed when possible
+    /// instead of being reported as `Error::CorruptedFileSystem`.
+    ///
+    /// For example `Dir::create_dir` called for an existing directory fixes its `.` and `..` entries if they point
+    /// to wrong clusters or are duplicated.
+    /// Default is `false`.
+    #[must_use]
+    pub fn repair(mut self, enabled: bool) -> Self {
+        self.repair = enabled;
+        self
+    }
+
+    /// If enabled the filesystem is mounted read-only and nothing is ever written to the storage.
+    ///
+    /// Operations modifying the filesystem (e.g. creating, removing, renaming or writing a file and setting the volume
+    /// label) fail with `Error::PermissionDenied` before touching the storage. The dirty flag is never set, the FS
+    /// Information Sector is not updated and accessed dates are not updated. Reading files and iterating directories
+    /// work as usual. It is useful for write-protected media and images that must stay unchanged.
+    /// Default is `false`.
+    #[must_use]
+    pub fn read_only(mut self, enabled: bool) -> Self {
+        self.read_only = enabled;
+        self
+    }
+
+    /// Selects characters allowed in names of created files and directories (see `FilenamePolicy`).
+    ///
+    /// Default is `FilenamePolicy::Strict`.
+    #[must_use]
+    pub fn filename_policy(mut self, policy: FilenamePolicy) -> Self {
+        self.filename_policy = policy;
+        self
+    }
+
+    /// If enabled long names are compared case-sensitively when looking up a path.
+    ///
+    /// This is not standard FAT behavior - other implementations treat names differing only in case as the same
+    /// name. It is useful for images used to exchange files with tools creating such names. A long name of an
+    /// entry must match exactly, e.g. `Foo.txt` does not match `foo.txt` and `create_file("foo.txt")` creates a new
+    /// file next to `Foo.txt`. Short names (including short names of entries with a long name) are still compared
+    /// case-insensitively unless the long name differs from the looked up name only in case. Short names generated
+    /// for new entries never collide with existing ones regardless of case. The option has no effect if the `lfn`
+    /// feature is disabled.
+    /// Default is `false`.
+    #[must_use]
+    pub fn case_sensitive_lookup(mut self, enabled: bool) -> Self {
+        self.case_sensitive_lookup = enabled;
+        self
+    }
+
+    /// If enabled `\` is a path separator equivalent to `/` in all methods taking a path.
+    ///
+    /// It is useful for paths coming from DOS or Windows software. Leading, trailing and repeated separators of both
+    /// kinds are handled the same way, e.g. `\a\b/c/` refers to the same entry as `a/b/c`. When disabled `\` is
+    /// treated as a part of a name (it is rejected by the default filename policy).
+    /// Default is `false`.
+    #[must_use]
+    pub fn backslash_separator(mut self, enabled: bool) -> Self {
+        self.backslash_separator = enabled;
+        self
+    }
+
+    /// If enabled names of created files and directories that cannot be used on Windows are rejected.
+    ///
+    /// Rejected names are reserved DOS device names (`CON`, `PRN`, `AUX`, `NUL`, `COM1`-`COM9` and `LPT1`-`LPT9`)
+    /// regardless of case and extension, e.g. `aux.txt`, and names ending with a dot or a space. Windows cannot open
+    /// or remove files with such names. Creating such a name fails with `Error::UnsupportedWindowsFileName` containing
+    /// the violated rule. The rules apply to `create_file`, `create_dir`, `rename` and other methods creating a name,
+    /// existing entries can always be opened, renamed and removed.
+    /// Default is `true`.
+    #[must_use]
+    pub fn windows_name_rules(mut self, enabled: bool) -> Self {
+        self.windows_name_rules = enabled;
+        self
+    }
+
+    /// Sets an algorithm generating short names of created files and directories (see `ShortNameAlgorithm`).
+    ///
+    /// It applies to `create_file`, `create_dir`, `rename` and other methods creating a name.
+    /// Default is `ShortNameAlgorithm::Checksum`.
+    #[must_use]
+    pub fn short_name_algorithm(mut self, algorithm: ShortNameAlgorithm) -> Self {
+        self.short_name_algorithm = algorithm;
+        self
+    }
+
+    /// Sets limits enforced by the filesystem (see `FsLimits`).
+    ///
+    /// Default limits are equal to the FAT format limits.
+    #[must_use]
+    pub fn limits(mut self, limits: FsLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// If enabled the FAT is read into memory by `FileSystem::prefetch_fat` when `check`, `check_incremental` or
+    /// `usage_summary` starts, unless it has been prefetched before.
+    ///
+    /// Traversals of deep directory trees alternate directory reads with FAT reads scattered across the FAT region.
+    /// Prefetching replaces them with a few big sequential reads. Default is `false`.
+    #[cfg(feature = "alloc")]
+    #[must_use]
+    pub fn prefetch_fat(mut self, enabled: bool) -> Self {
+        self.prefetch_fat = enabled;
+        self
+    }
+
+    /// Sets the maximal number of bytes of the FAT kept in memory by `FileSystem::prefetch_fat`.
+    ///
+    /// Only the beginning of the FAT is prefetched if it is bigger than the budget. The rest is read from the storage
+    /// when needed. Default is `usize::MAX` - the whole FAT is prefetched.
+    #[cfg(feature = "alloc")]
+    #[must_use]
+    pub fn fat_cache_budget(mut self, max_bytes: usize) -> Self {
+        self.fat_cache_budget = max_bytes;
+        self
+    }
+
+    /// Returns characters separating path components.
+    pub(crate) fn path_separators(&self) -> &'static [char] {
+        if self.backslash_separator {
+            &['/', '\\']
+        } else {
+            &['/']
+        }
+    }
+
     /// Changes default OEM code page encoder-decoder.
     pub fn oem_cp_converter<OCC2: OemCpConverter>(self, oem_cp_converter: OCC2) -> FsOptions<TP, OCC2> {
         FsOptions::<TP, OCC2> {
             update_accessed_date: self.update_accessed_date,
+            update_modified_time: self.update_modified_time,
+            update_archive_attribute: self.update_archive_attribute,
+            verify_writes: self.verify_writes,
+            strict: self.strict,
+            repair: self.repair,
+            read_only: self.read_only,
+            filename_policy: self.filename_policy,
+            case_sensitive_lookup: self.case_sensitive_lookup,
+            backslash_separator: self.backslash_separator,
+            windows_name_rules: self.windows_name_rules,
+            short_name_algorithm: self.short_name_algorithm,
+            limits: self.limits,
+            #[cfg(feature = "alloc")]
+            prefetch_fat: self.prefetch_fat,
+            #[cfg(feature = "alloc")]
+            fat_cache_budget: self.fat_cache_budget,
             oem_cp_converter,
             time_provider: self.time_provider,
         }
@@ -274,6 +722,22 @@ impl<TP: TimeProvider, OCC: OemCpConverter> FsOptions<TP, OCC> {
     pub fn time_provider<TP2: TimeProvider>(self, time_provider: TP2) -> FsOptions<TP2, OCC> {
         FsOptions::<TP2, OCC> {
             update_accessed_date: self.update_accessed_date,
+            update_modified_time: self.update_modified_time,
+            update_archive_attribute: self.update_archive_attribute,
+            verify_writes: self.verify_writes,
+            strict: self.strict,
+            repair: self.repair,
+            read_only: self.read_only,
+            filename_policy: self.filename_policy,
+            case_sensitive_lookup: self.case_sensitive_lookup,
+            backslash_separator: self.backslash_separator,
+            windows_name_rules: self.windows_name_rules,
+            short_name_algorithm: self.short_name_algorithm,
+            limits: self.limits,
+            #[cfg(feature = "alloc")]
+            prefetch_fat: self.prefetch_fat,
+            #[cfg(feature = "alloc")]
+            fat_cache_budget: self.fat_cache_budget,
             oem_cp_converter: self.oem_cp_converter,
             time_provider,
         }
@@ -306,11 +770,93 @@ impl FileSystemStats {
     pub fn free_clusters(&self) -> u32 {
         self.free_clusters
     }
+
+    /// Total size in bytes of clusters usable for file allocation
+    #[must_use]
+    pub fn total_bytes(&self) -> u64 {
+        u64::from(self.total_clusters) * u64::from(self.cluster_size)
+    }
+
+    /// Size of free clusters in bytes
+    #[must_use]
+    pub fn free_bytes(&self) -> u64 {
+        u64::from(self.free_clusters) * u64::from(self.cluster_size)
+    }
+}
+
+/// A summary of the volume usage.
+///
+/// It is computed by the `usage_summary` method on `FileSystem` which walks the entire directory tree.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default)]
+pub struct UsageSummary {
+    pub(crate) files: u64,
+    pub(crate) dirs: u64,
+    pub(crate) logical_bytes: u64,
+    pub(crate) allocated_bytes: u64,
+    pub(crate) max_depth: u32,
+    pub(crate) max_file_size: u32,
+}
+
+impl UsageSummary {
+    /// Number of regular files
+    #[must_use]
+    pub fn files(&self) -> u64 {
+        self.files
+    }
+
+    /// Number of directories excluding the root directory
+    #[must_use]
+    pub fn dirs(&self) -> u64 {
+        self.dirs
+    }
+
+    /// Sum of all file sizes in bytes
+    #[must_use]
+    pub fn logical_bytes(&self) -> u64 {
+        self.logical_bytes
+    }
+
+    /// Size of all clusters allocated for files and directories in bytes
+    #[must_use]
+    pub fn allocated_bytes(&self) -> u64 {
+        self.allocated_bytes
+    }
+
+    /// Number of components in the longest path (0 if the root directory is empty)
+    #[must_use]
+    pub fn max_depth(&self) -> u32 {
+        self.max_depth
+    }
+
+    /// Size of the biggest file in bytes
+    #[must_use]
+    pub fn max_file_size(&self) -> u32 {
+        self.max_file_size
+    }
+
+    /// Mean file size in bytes rounded down (0 if there are no files)
+    #[must_use]
+    pub fn mean_file_size(&self) -> u64 {
+        self.logical_bytes.checked_div(self.files).unwrap_or(0)
+    }
 }
 
 /// A FAT filesystem object.
 ///
 /// `FileSystem` struct is representing a state of a mounted FAT volume.
+///
+/// `FileSystem` uses interior mutability without locking so it is not `Sync` - it can be moved to another thread
+/// (if the storage is `Send`) but it cannot be shared by multiple threads:
+///
+/// ```compile_fail
+/// fn assert_sync<T: Sync>(_: &T) {}
+/// let img = std::io::Cursor::new(std::fs::read("resources/fat16.img").unwrap());
+/// let fs = fatfs::FileSystem::new(img, fatfs::FsOptions::new()).unwrap();
+/// assert_sync(&fs);
+/// ```
+///
+/// The `unsound_sync` feature makes it `Sync` for users that serialize all access by other means. It is unsound -
+/// using the filesystem from multiple threads at the same time is undefined behavior.
 pub struct FileSystem<IO: ReadWriteSeek, TP, OCC> {
     pub(crate) disk: RefCell<IO>,
     pub(crate) options: FsOptions<TP, OCC>,
@@ -320,9 +866,27 @@ pub struct FileSystem<IO: ReadWriteSeek, TP, OCC> {
     root_dir_sectors: u32,
     total_clusters: u32,
     fs_info: RefCell<FsInfoSector>,
+    // label stored in the BPB block - it is changed by `set_volume_label`
+    bpb_volume_label: Cell<[u8; SFN_SIZE]>,
+    // flags from both locations read on mount - they are never cleared
+    mount_status_flags: FsStatusFlags,
     current_status_flags: Cell<FsStatusFlags>,
+    // false if flags in the boot sector and in the FAT differ and have not been written yet
+    status_flags_synced: Cell<bool>,
+    storage_len: Cell<u64>,
+    // size and first cluster of files shared by their handles
+    pub(crate) open_files: RefCell<OpenFiles>,
+    #[cfg(feature = "alloc")]
+    fat_cache: RefCell<Vec<u8>>,
+    #[cfg(feature = "alloc")]
+    dir_cache: RefCell<Option<Box<dyn DirCacheStorage + Send>>>,
 }
 
+// Safety: this is not actually safe - users enabling the `unsound_sync` feature are responsible for making sure the
+// filesystem is never used by multiple threads at the same time.
+#[cfg(feature = "unsound_sync")]
+unsafe impl<IO: ReadWriteSeek + Send, TP: Sync, OCC: Sync> Sync for FileSystem<IO, TP, OCC> {}
+
 pub trait IntoStorage<T: Read + Write + Seek> {
     fn into_storage(self) -> T;
 }
@@ -343,9 +907,12 @@ impl<T: std::io::Read + std::io::Write + std::io::Seek> IntoStorage<io::StdIoWra
 impl<IO: Read + Write + Seek, TP, OCC> FileSystem<IO, TP, OCC> {
     /// Creates a new filesystem object instance.
     ///
-    /// Supplied `storage` parameter cannot be seeked. If there is a need to read a fragment of disk
-    /// image (e.g. partition) library user should wrap the file struct in a struct limiting
-    /// access to partition bytes only e.g. `fscommon::StreamSlice`.
+    /// Supplied `storage` parameter cannot be seeked. To mount a fragment of a disk image (e.g. a partition) wrap the
+    /// storage in `StreamSlice` limiting access to the partition bytes, i.e.
+    /// `FileSystem::new(StreamSlice::new(disk, start, end)?, options)`.
+    ///
+    /// A mutable reference to a storage object can be passed instead of an owned object. It allows to mount
+    /// a filesystem for a limited scope and use the storage object directly after the filesystem is dropped.
     ///
     /// Note: creating multiple filesystem objects with a single underlying storage can
     /// cause a filesystem corruption.
@@ -367,6 +934,9 @@ impl<IO: Read + Write + Seek, TP, OCC> FileSystem<IO, TP, OCC> {
         trace!("FileSystem::new");
         debug_assert!(disk.seek(SeekFrom::Current(0))? == 0);
 
+        // remember storage size so it can be detected if the storage is resized behind our back
+        let storage_len = storage_len(&mut disk)?;
+
         // read boot sector
         let bpb = {
             let boot = BootSector::deserialize(&mut disk)?;
@@ -374,6 +944,18 @@ impl<IO: Read + Write + Seek, TP, OCC> FileSystem<IO, TP, OCC> {
             boot.bpb
         };
 
+        let min_storage_len = bpb.min_storage_len();
+        if storage_len < min_storage_len {
+            error!(
+                "storage is too small for the filesystem geometry: {} bytes long but at least {} bytes are required",
+                storage_len, min_storage_len
+            );
+            return Err(Error::StorageTooSmall {
+                len: storage_len,
+                min_len: min_storage_len,
+            });
+        }
+
         let root_dir_sectors = bpb.root_dir_sectors();
         let first_data_sector = bpb.first_data_sector();
         let total_clusters = bpb.total_clusters();
@@ -382,13 +964,25 @@ impl<IO: Read + Write + Seek, TP, OCC> FileSystem<IO, TP, OCC> {
         // read FSInfo sector if this is FAT32
         let mut fs_info = if fat_type == FatType::Fat32 {
             disk.seek(SeekFrom::Start(bpb.bytes_from_sectors(bpb.fs_info_sector())))?;
-            FsInfoSector::deserialize(&mut disk)?
+            match FsInfoSector::deserialize(&mut disk) {
+                // the sector only holds hints - free clusters are counted when needed
+                Err(Error::CorruptedFileSystem) => {
+                    warn!("ignoring corrupted FsInfo sector");
+                    FsInfoSector::default()
+                }
+                r => r?,
+            }
         } else {
             FsInfoSector::default()
         };
 
+        // status flags can be stored in the boot sector and in the second FAT entry - use both
+        let fat_status_flags = read_fat_flags(&mut fat_slice::<IO, _>(&mut disk, &bpb), fat_type)?;
+        let status_flags = bpb.status_flags().union(fat_status_flags);
+        let status_flags_synced = fat_type == FatType::Fat12 || bpb.status_flags() == fat_status_flags;
+
         // if dirty flag is set completly ignore free_cluster_count in FSInfo
-        if bpb.status_flags().dirty {
+        if status_flags.dirty {
             fs_info.free_cluster_count = None;
         }
 
@@ -396,18 +990,26 @@ impl<IO: Read + Write + Seek, TP, OCC> FileSystem<IO, TP, OCC> {
         fs_info.validate_and_fix(total_clusters);
 
         // return FileSystem struct
-        let status_flags = bpb.status_flags();
         trace!("FileSystem::new end");
         Ok(Self {
             disk: RefCell::new(disk),
             options,
             fat_type,
-            bpb,
             first_data_sector,
             root_dir_sectors,
             total_clusters,
             fs_info: RefCell::new(fs_info),
+            bpb_volume_label: Cell::new(bpb.volume_label),
+            bpb,
+            mount_status_flags: status_flags,
             current_status_flags: Cell::new(status_flags),
+            status_flags_synced: Cell::new(status_flags_synced),
+            storage_len: Cell::new(storage_len),
+            open_files: RefCell::new(OpenFiles::default()),
+            #[cfg(feature = "alloc")]
+            fat_cache: RefCell::new(Vec::new()),
+            #[cfg(feature = "alloc")]
+            dir_cache: RefCell::new(None),
         })
     }
 
@@ -416,6 +1018,12 @@ impl<IO: Read + Write + Seek, TP, OCC> FileSystem<IO, TP, OCC> {
         self.fat_type
     }
 
+    /// Returns `true` if the filesystem has been mounted with the `read_only` option.
+    #[must_use]
+    pub fn is_read_only(&self) -> bool {
+        self.options.read_only
+    }
+
     /// Returns a volume identifier read from BPB in the Boot Sector.
     pub fn volume_id(&self) -> u32 {
         self.bpb.volume_id
@@ -424,8 +1032,8 @@ impl<IO: Read + Write + Seek, TP, OCC> FileSystem<IO, TP, OCC> {
     /// Returns a volume label from BPB in the Boot Sector as byte array slice.
     ///
     /// Label is encoded in the OEM codepage.
-    /// Note: This function returns label stored in the BPB block. Use `read_volume_label_from_root_dir_as_bytes` to
-    /// read label from the root directory.
+    /// Note: This function returns label stored in the BPB block when the filesystem was mounted. Use
+    /// `read_volume_label` to read the current label preferably from the root directory.
     pub fn volume_label_as_bytes(&self) -> &[u8] {
         let full_label_slice = &self.bpb.volume_label;
         let len = full_label_slice
@@ -451,6 +1059,36 @@ impl<IO: Read + Write + Seek, TP, OCC> FileSystem<IO, TP, OCC> {
         self.offset_from_sector(self.sector_from_cluster(cluser))
     }
 
+    /// Makes sure the storage has not been resized before accessing data ending at `end` offset.
+    ///
+    /// The storage size is only queried if `end` lies beyond the size observed previously so the check is cheap.
+    pub(crate) fn check_storage_access(&self, disk: &mut IO, end: u64) -> Result<(), Error<IO::Error>> {
+        if end <= self.storage_len.get() {
+            return Ok(());
+        }
+        self.check_storage_len(disk)
+    }
+
+    /// Compares the current storage size with the size observed previously.
+    ///
+    /// Used after a read hit an unexpected end of the storage. Storage position is not preserved.
+    pub(crate) fn check_storage_len(&self, disk: &mut IO) -> Result<(), Error<IO::Error>> {
+        let len = disk.seek(SeekFrom::End(0))?;
+        let expected_len = self.storage_len.get();
+        if len != expected_len {
+            error!("storage size changed from {} to {} bytes", expected_len, len);
+            return Err(Error::StorageSizeChanged);
+        }
+        Ok(())
+    }
+
+    /// Records that the storage has been extended by a write ending at `end` offset.
+    pub(crate) fn storage_written(&self, end: u64) {
+        if end > self.storage_len.get() {
+            self.storage_len.set(end);
+        }
+    }
+
     pub(crate) fn bytes_from_clusters(&self, clusters: u32) -> u64 {
         self.bpb.bytes_from_sectors(self.bpb.sectors_from_clusters(clusters))
     }
@@ -459,9 +1097,25 @@ impl<IO: Read + Write + Seek, TP, OCC> FileSystem<IO, TP, OCC> {
         self.bpb.clusters_from_bytes(bytes)
     }
 
+    pub(crate) fn total_clusters(&self) -> u32 {
+        self.total_clusters
+    }
+
+    /// Returns the free cluster count if it is known without scanning the FAT.
+    pub(crate) fn free_clusters_hint(&self) -> Option<u32> {
+        self.fs_info.borrow().free_cluster_count
+    }
+
+    pub(crate) fn fat_entry(&self, cluster: u32) -> Result<FatValue, Error<IO::Error>> {
+        read_fat(&mut self.fat_slice(), self.fat_type, cluster)
+    }
+
     fn fat_slice(&self) -> impl ReadWriteSeek<Error = Error<IO::Error>> + '_ {
         let io = FsIoAdapter { fs: self };
-        fat_slice(io, &self.bpb)
+        let slice = fat_slice(io, &self.bpb).with_verification(self.options.verify_writes.verify_metadata());
+        #[cfg(feature = "alloc")]
+        let slice = FatCacheSlice::new(slice, &self.fat_cache);
+        slice
     }
 
     pub(crate) fn cluster_iter(
@@ -473,56 +1127,270 @@ impl<IO: Read + Write + Seek, TP, OCC> FileSystem<IO, TP, OCC> {
     }
 
     pub(crate) fn truncate_cluster_chain(&self, cluster: u32) -> Result<(), Error<IO::Error>> {
+        self.check_writable()?;
         let mut iter = self.cluster_iter(cluster);
-        let num_free = iter.truncate()?;
-        let mut fs_info = self.fs_info.borrow_mut();
-        fs_info.map_free_clusters(|n| n + num_free);
-        Ok(())
+        let num_free = iter.truncate().map_err(|e| self.handle_write_error(e))?;
+        self.update_free_clusters(|n| n.checked_add(num_free))
     }
 
     pub(crate) fn free_cluster_chain(&self, cluster: u32) -> Result<(), Error<IO::Error>> {
+        self.check_writable()?;
         let mut iter = self.cluster_iter(cluster);
-        let num_free = iter.free()?;
-        let mut fs_info = self.fs_info.borrow_mut();
-        fs_info.map_free_clusters(|n| n + num_free);
+        let num_free = iter.free().map_err(|e| self.handle_write_error(e))?;
+        self.update_free_clusters(|n| n.checked_add(num_free))
+    }
+
+    /// Updates the free cluster count from the FS Information Sector.
+    ///
+    /// The stored count can be stale (e.g. after the volume was modified by an implementation not updating it). If
+    /// the update makes it obviously wrong free clusters are counted again.
+    fn update_free_clusters(&self, map_fn: impl Fn(u32) -> Option<u32>) -> Result<(), Error<IO::Error>> {
+        let valid = self.fs_info.borrow_mut().map_free_clusters(map_fn, self.total_clusters);
+        if !valid {
+            warn!("free_cluster_count in fs_info is not valid - recalculating");
+            self.recalc_free_clusters()?;
+        }
         Ok(())
     }
 
+    pub(crate) fn count_cluster_chain(&self, first_cluster: u32) -> Result<u32, Error<IO::Error>> {
+        let mut count = 1;
+        for r in self.cluster_iter(first_cluster) {
+            r?;
+            count += 1;
+            if count > self.total_clusters {
+                error!("cluster chain starting at {} is longer than the volume", first_cluster);
+                return Err(Error::CorruptedFileSystem);
+            }
+        }
+        Ok(count)
+    }
+
     pub(crate) fn alloc_cluster(&self, prev_cluster: Option<u32>, zero: bool) -> Result<u32, Error<IO::Error>> {
         trace!("alloc_cluster");
+        // The hint is advisory: it is range checked by `alloc_cluster` and the search for a free cluster starts there,
+        // so a hint pointing to an allocated cluster only makes the search longer.
         let hint = self.fs_info.borrow().next_free_cluster;
-        let cluster = {
+        // FAT writes are batched - the flags entry must be updated before it can be buffered with other entries
+        self.set_dirty_flag(true)?;
+        let result = {
             let mut fat = self.fat_slice();
-            alloc_cluster(&mut fat, self.fat_type, prev_cluster, hint, self.total_clusters)?
+            alloc_cluster(&mut fat, self.fat_type, prev_cluster, hint, self.total_clusters)
+        };
+        let cluster = match result {
+            Ok(cluster) => cluster,
+            Err(Error::NotEnoughSpace) => {
+                // the volume is full so the stored free cluster count must be zero
+                self.update_free_clusters(|n| if n == 0 { Some(0) } else { None })?;
+                return Err(Error::NotEnoughSpace);
+            }
+            Err(e) => return Err(self.handle_write_error(e)),
         };
         if zero {
-            let mut disk = self.disk.borrow_mut();
-            disk.seek(SeekFrom::Start(self.offset_from_cluster(cluster)))?;
-            write_zeros(&mut *disk, u64::from(self.cluster_size()))?;
+            self.write_zeros_at(self.offset_from_cluster(cluster), u64::from(self.cluster_size()))?;
         }
-        let mut fs_info = self.fs_info.borrow_mut();
-        fs_info.set_next_free_cluster(cluster + 1);
-        fs_info.map_free_clusters(|n| n - 1);
+        self.fs_info.borrow_mut().set_next_free_cluster(cluster + 1);
+        self.update_free_clusters(|n| n.checked_sub(1))?;
         Ok(cluster)
     }
 
+    /// Allocates `count` consecutive free clusters starting at `first_cluster` as a single cluster chain.
+    ///
+    /// If `prev_cluster` is given the chain is appended to it.
+    pub(crate) fn alloc_cluster_range(
+        &self,
+        prev_cluster: Option<u32>,
+        first_cluster: u32,
+        count: u32,
+    ) -> Result<(), Error<IO::Error>> {
+        trace!("alloc_cluster_range {} {}", first_cluster, count);
+        // see `alloc_cluster`
+        self.set_dirty_flag(true)?;
+        {
+            let mut fat = self.fat_slice();
+            alloc_cluster_range(
+                &mut fat,
+                self.fat_type,
+                prev_cluster,
+                first_cluster,
+                count,
+                self.total_clusters,
+            )
+            .map_err(|e| self.handle_write_error(e))?;
+        }
+        self.fs_info.borrow_mut().set_next_free_cluster(first_cluster + count);
+        self.update_free_clusters(|n| n.checked_sub(count))
+    }
+
+    /// Finds `count` consecutive free clusters preferring the run starting at `preferred_cluster`.
+    ///
+    /// Other runs are searched starting at the next free cluster hint (see `alloc_cluster`).
+    pub(crate) fn find_free_cluster_run(
+        &self,
+        preferred_cluster: Option<u32>,
+        count: u32,
+    ) -> Result<Option<u32>, Error<IO::Error>> {
+        let hint = self.fs_info.borrow().next_free_cluster;
+        let mut fat = self.fat_slice();
+        find_free_cluster_run(
+            &mut fat,
+            self.fat_type,
+            preferred_cluster,
+            hint,
+            count,
+            self.total_clusters,
+        )
+    }
+
     /// Returns status flags for this volume.
     ///
+    /// Flags are combined from the Boot Sector and the second FAT entry (see `read_status_flag_sources`).
+    ///
     /// # Errors
     ///
     /// `Error::Io` will be returned if the underlying storage object returned an I/O error.
     pub fn read_status_flags(&self) -> Result<FsStatusFlags, Error<IO::Error>> {
-        let bpb_status = self.bpb.status_flags();
-        let fat_status = read_fat_flags(&mut self.fat_slice(), self.fat_type)?;
+        let sources = self.read_status_flag_sources()?;
+        let flags = sources
+            .fat
+            .map_or(sources.boot_sector, |fat| sources.boot_sector.union(fat));
+        // IO Error flag can be set during this session by a failed write verification
+        let session_io_error = self.current_status_flags.get().io_error;
         Ok(FsStatusFlags {
-            dirty: bpb_status.dirty || fat_status.dirty,
-            io_error: bpb_status.io_error || fat_status.io_error,
+            dirty: flags.dirty,
+            io_error: flags.io_error || session_io_error,
         })
     }
 
+    /// Returns status flags stored in each location separately.
+    ///
+    /// FAT implementations store the dirty and IO Error flags either in the Boot Sector or in the second FAT entry.
+    /// `read_status_flags` combines both. When this crate changes flags it writes both locations.
+    ///
+    /// # Errors
+    ///
+    /// `Error::Io` will be returned if the underlying storage object returned an I/O error.
+    pub fn read_status_flag_sources(&self) -> Result<FsStatusFlagSources, Error<IO::Error>> {
+        let boot_sector = {
+            let mut disk = self.disk_mut()?;
+            disk.seek(SeekFrom::Start(self.status_flags_offset()))?;
+            FsStatusFlags::decode(disk.read_u8()?)
+        };
+        let fat = if self.fat_type == FatType::Fat12 {
+            None
+        } else {
+            Some(read_fat_flags(&mut self.fat_slice(), self.fat_type)?)
+        };
+        Ok(FsStatusFlagSources { boot_sector, fat })
+    }
+
+    /// Accepts the current size of the underlying storage object.
+    ///
+    /// The storage size is remembered when the filesystem is mounted. If the storage is later resized (e.g. a host
+    /// file is truncated or extended by another process) the change is detected lazily: reads hitting the end of the
+    /// storage and accesses past the remembered size fail with `Error::StorageSizeChanged` instead of returning an
+    /// unexpected end of file. Other writes do not query the storage size. This method can be used to explicitly
+    /// accept a storage that has grown, or shrunk without cutting off any part of the volume.
+    ///
+    /// # Errors
+    ///
+    /// Errors that can be returned:
+    ///
+    /// * `Error::StorageSizeChanged` will be returned if the storage has shrunk and is now smaller than the volume.
+    /// * `Error::Io` will be returned if the underlying storage object returned an I/O error.
+    pub fn refresh_geometry(&self) -> Result<(), Error<IO::Error>> {
+        let len = self.disk_mut()?.seek(SeekFrom::End(0))?;
+        let old_len = self.storage_len.get();
+        let volume_len = self.bpb.bytes_from_sectors(self.bpb.total_sectors());
+        if len < old_len && len < volume_len {
+            error!(
+                "storage shrunk to {} bytes but the volume needs {} bytes",
+                len, volume_len
+            );
+            return Err(Error::StorageSizeChanged);
+        }
+        if len != old_len {
+            info!("storage size changed from {} to {} bytes", old_len, len);
+        }
+        self.storage_len.set(len);
+        Ok(())
+    }
+
+    /// Reads the active File Allocation Table into memory.
+    ///
+    /// The table is read using a few big sequential reads and is kept in memory until the filesystem is unmounted.
+    /// All later FAT reads are served from memory and FAT writes update both the storage and the in-memory copy. It
+    /// speeds up operations following many cluster chains (e.g. `usage_summary` or a walk through a big directory
+    /// tree) when the storage is slow for small random reads. Memory usage equals the size of a single FAT copy
+    /// limited by `FsOptions::fat_cache_budget`. Calling it again reads the table again. See `FsOptions::prefetch_fat`
+    /// for prefetching done automatically.
+    ///
+    /// # Errors
+    ///
+    /// `Error::Io` will be returned if the underlying storage object returned an I/O error.
+    #[cfg(feature = "alloc")]
+    pub fn prefetch_fat(&self) -> Result<(), Error<IO::Error>> {
+        const CHUNK_SIZE: usize = 64 * 1024;
+        let fat_size = cmp::min(
+            self.bpb.bytes_from_sectors(self.bpb.sectors_per_fat()),
+            self.options.fat_cache_budget as u64,
+        ) as usize;
+        let mut data = vec![0_u8; fat_size];
+        let mut fat = self.fat_slice();
+        fat.seek(SeekFrom::Start(0))?;
+        for chunk in data.chunks_mut(CHUNK_SIZE) {
+            fat.read_exact(chunk)?;
+        }
+        trace!("prefetched {} bytes of FAT", fat_size);
+        *self.fat_cache.borrow_mut() = data;
+        Ok(())
+    }
+
+    /// Calls `prefetch_fat` if it is enabled by `FsOptions::prefetch_fat` and the FAT has not been prefetched yet.
+    #[cfg(feature = "alloc")]
+    fn auto_prefetch_fat(&self) -> Result<(), Error<IO::Error>> {
+        if self.options.prefetch_fat && self.fat_cache.borrow().is_empty() {
+            self.prefetch_fat()?;
+        }
+        Ok(())
+    }
+
+    /// Enables the directory lookup cache using the given storage or disables it if `None` is passed.
+    ///
+    /// The cache remembers positions of entries found by name (e.g. when a path is opened) so next lookups of the
+    /// same name do not have to read the directory from the beginning. See `LinearDirCache` for the default storage.
+    #[cfg(feature = "alloc")]
+    pub fn set_dir_cache(&self, storage: Option<Box<dyn DirCacheStorage + Send>>) {
+        *self.dir_cache.borrow_mut() = storage;
+    }
+
+    #[cfg(feature = "alloc")]
+    pub(crate) fn dir_cache_get(&self, dir_cluster: u32, name: &str) -> Option<u64> {
+        // cache is only a hint so it is skipped in case of a reentrant access
+        self.dir_cache.try_borrow().ok()?.as_ref()?.get(dir_cluster, name)
+    }
+
+    #[cfg(not(feature = "alloc"))]
+    pub(crate) fn dir_cache_get(&self, _dir_cluster: u32, _name: &str) -> Option<u64> {
+        None
+    }
+
+    #[cfg(feature = "alloc")]
+    pub(crate) fn dir_cache_insert(&self, dir_cluster: u32, name: &str, offset: u64) {
+        if let Ok(mut storage_opt) = self.dir_cache.try_borrow_mut() {
+            if let Some(storage) = storage_opt.as_mut() {
+                storage.insert(dir_cluster, name, offset);
+            }
+        }
+    }
+
+    #[cfg(not(feature = "alloc"))]
+    pub(crate) fn dir_cache_insert(&self, _dir_cluster: u32, _name: &str, _offset: u64) {}
+
     /// Returns filesystem statistics like number of total and free clusters.
     ///
-    /// For FAT32 volumes number of free clusters from the FS Information Sector is returned (may be incorrect).
+    /// For FAT32 volumes number of free clusters from the FS Information Sector is returned (may be incorrect). The
+    /// number is counted again if an allocation proves it wrong.
     /// For other FAT variants number is computed on the first call to this method and cached for later use.
     ///
     /// # Errors
@@ -550,6 +1418,21 @@ impl<IO: Read + Write + Seek, TP, OCC> FileSystem<IO, TP, OCC> {
         Ok(free_cluster_count)
     }
 
+    /// Writes the FS Information Sector if needed and flushes the storage.
+    ///
+    /// The free cluster count and the next free cluster hint are kept in memory and written to the FS Information
+    /// Sector only by this method and on unmount. Unlike `unmount` this method does not clear the dirty flag so the
+    /// filesystem can be used afterwards. A FS Information Sector with invalid signatures is never written.
+    ///
+    /// # Errors
+    ///
+    /// `Error::Io` will be returned if the underlying storage object returned an I/O error.
+    pub fn flush(&self) -> Result<(), Error<IO::Error>> {
+        self.flush_fs_info()?;
+        self.disk_mut()?.flush()?;
+        Ok(())
+    }
+
     /// Unmounts the filesystem.
     ///
     /// Updates the FS Information Sector if needed.
@@ -569,38 +1452,240 @@ impl<IO: Read + Write + Seek, TP, OCC> FileSystem<IO, TP, OCC> {
 
     fn flush_fs_info(&self) -> Result<(), Error<IO::Error>> {
         let mut fs_info = self.fs_info.borrow_mut();
-        if self.fat_type == FatType::Fat32 && fs_info.dirty {
-            let mut disk = self.disk.borrow_mut();
+        // a free cluster count calculated on a read-only volume stays in memory
+        if self.fat_type == FatType::Fat32 && fs_info.valid && fs_info.dirty && !self.options.read_only {
+            let mut disk = self.disk_mut()?;
             let fs_info_sector_offset = self.offset_from_sector(u32::from(self.bpb.fs_info_sector));
             disk.seek(SeekFrom::Start(fs_info_sector_offset))?;
-            fs_info.serialize(&mut *disk)?;
+            let verify = self.options.verify_writes.verify_metadata();
+            let result = fs_info.serialize(&mut VerifyingWriter::new(&mut *disk, verify));
+            result.map_err(|e| self.handle_write_error_with_disk(&mut *disk, e))?;
             fs_info.dirty = false;
         }
         Ok(())
     }
 
-    pub(crate) fn set_dirty_flag(&self, dirty: bool) -> Result<(), IO::Error> {
-        // Do not overwrite flags read from BPB on mount
-        let mut flags = self.bpb.status_flags();
+    /// Writes the label field in the Boot Sector and its backup copy.
+    fn write_bpb_volume_label(&self, label: [u8; SFN_SIZE]) -> Result<(), Error<IO::Error>> {
+        // the field is not present without the extended boot signature
+        if self.bpb.ext_sig != 0x29 {
+            return Ok(());
+        }
+        let field_offset = if self.fat_type == FatType::Fat32 { 0x047 } else { 0x02B };
+        // only FAT32 volumes have a backup boot sector - zero means there is none
+        let backup_sector = if self.fat_type == FatType::Fat32 {
+            self.bpb.backup_boot_sector()
+        } else {
+            0
+        };
+        let sectors = [0, backup_sector];
+        let num_sectors = if backup_sector == 0 { 1 } else { 2 };
+        let mut disk = self.disk_mut()?;
+        let verify = self.options.verify_writes.verify_metadata();
+        for sector in &sectors[..num_sectors] {
+            disk.seek(SeekFrom::Start(self.offset_from_sector(*sector) + field_offset))?;
+            let result = VerifyingWriter::new(&mut *disk, verify).write_all(&label);
+            result.map_err(|e| self.handle_write_error_with_disk(&mut *disk, e))?;
+        }
+        self.bpb_volume_label.set(label);
+        Ok(())
+    }
+
+    pub(crate) fn set_dirty_flag(&self, dirty: bool) -> Result<(), Error<IO::Error>> {
+        if self.options.read_only {
+            // the flag is never set so there is nothing to clear
+            return if dirty { self.check_writable() } else { Ok(()) };
+        }
+        self.set_dirty_flag_with_disk(&mut *self.disk_mut()?, dirty)
+    }
+
+    fn set_dirty_flag_with_disk(&self, disk: &mut IO, dirty: bool) -> Result<(), Error<IO::Error>> {
+        // Do not overwrite flags read on mount
+        let mut flags = self.mount_status_flags;
         flags.dirty |= dirty;
+        // IO Error flag set during this session must be kept
+        flags.io_error |= self.current_status_flags.get().io_error;
+        // Both locations are synchronized when the volume is modified, read-only sessions do not write anything
+        self.write_status_flags(disk, flags, dirty)
+            .map_err(|e| self.handle_write_error_with_disk(disk, e))
+    }
+
+    fn write_status_flags(&self, disk: &mut IO, flags: FsStatusFlags, sync: bool) -> Result<(), Error<IO::Error>> {
         // Check if flags has changed
         let current_flags = self.current_status_flags.get();
-        if flags == current_flags {
+        if flags == current_flags && (self.status_flags_synced.get() || !sync) {
             // Nothing to do
             return Ok(());
         }
-        let encoded = flags.encode();
         // Note: only one field is written to avoid rewriting entire boot-sector which could be dangerous
-        // Compute reserver_1 field offset and write new flags
-        let offset = if self.fat_type() == FatType::Fat32 {
+        disk.seek(io::SeekFrom::Start(self.status_flags_offset()))?;
+        let verify = self.options.verify_writes.verify_metadata();
+        VerifyingWriter::new(&mut *disk, verify).write_u8(flags.encode())?;
+        // Other implementations only read flags from the FAT - keep them in sync
+        self.write_fat_status_flags(disk, flags)?;
+        self.current_status_flags.set(flags);
+        self.status_flags_synced.set(true);
+        Ok(())
+    }
+
+    /// Returns offset of the `BS_Reserved1` field containing status flags in the Boot Sector.
+    fn status_flags_offset(&self) -> u64 {
+        if self.fat_type() == FatType::Fat32 {
             0x041
         } else {
             0x025
+        }
+    }
+
+    /// Writes status flags to the second entry of every FAT.
+    ///
+    /// The storage is accessed directly because this function is called when writing through the FAT slice.
+    fn write_fat_status_flags(&self, disk: &mut IO, flags: FsStatusFlags) -> Result<(), Error<IO::Error>> {
+        let entry_size: u64 = match self.fat_type {
+            FatType::Fat12 => return Ok(()),
+            FatType::Fat16 => 2,
+            FatType::Fat32 => 4,
         };
-        let mut disk = self.disk.borrow_mut();
-        disk.seek(io::SeekFrom::Start(offset))?;
-        disk.write_u8(encoded)?;
-        self.current_status_flags.set(flags);
+        let sectors_per_fat = self.bpb.sectors_per_fat();
+        let (first_fat, fats) = if self.bpb.mirroring_enabled() {
+            (0, u32::from(self.bpb.fats))
+        } else {
+            (u32::from(self.bpb.active_fat()), 1)
+        };
+        let mut first_raw = None;
+        for fat in first_fat..first_fat + fats {
+            let fat_offset = self.offset_from_sector(self.bpb.reserved_sectors() + fat * sectors_per_fat);
+            let offset = fat_offset + entry_size;
+            disk.seek(SeekFrom::Start(offset))?;
+            let old_raw = if entry_size == 2 {
+                u32::from(disk.read_u16_le()?)
+            } else {
+                disk.read_u32_le()?
+            };
+            let new_raw = encode_fat_flags(self.fat_type, old_raw, flags);
+            first_raw.get_or_insert(new_raw);
+            if new_raw == old_raw {
+                continue;
+            }
+            disk.seek(SeekFrom::Start(offset))?;
+            let mut writer = VerifyingWriter::new(&mut *disk, self.options.verify_writes.verify_metadata());
+            if entry_size == 2 {
+                writer.write_u16_le(new_raw as u16)?;
+            } else {
+                writer.write_u32_le(new_raw)?;
+            }
+        }
+        #[cfg(feature = "alloc")]
+        if let Some(raw) = first_raw {
+            // keep the in-memory FAT copy in sync
+            let bytes = raw.to_le_bytes();
+            let range = entry_size as usize..2 * entry_size as usize;
+            if let Some(cached) = self.fat_cache.borrow_mut().get_mut(range.clone()) {
+                cached.copy_from_slice(&bytes[..range.len()]);
+            }
+        }
+        Ok(())
+    }
+
+    /// Marks the volume with the IO Error flag if `error` is a write verification failure.
+    ///
+    /// The error is returned unchanged so this function can be used in `map_err`.
+    pub(crate) fn handle_write_error(&self, error: Error<IO::Error>) -> Error<IO::Error> {
+        match self.disk_mut() {
+            Ok(mut disk) => self.handle_write_error_with_disk(&mut *disk, error),
+            Err(_) => error,
+        }
+    }
+
+    /// Returns `Error::PermissionDenied` if the filesystem is mounted read-only.
+    pub(crate) fn check_writable(&self) -> Result<(), Error<IO::Error>> {
+        if self.options.read_only {
+            error!("filesystem is mounted read-only");
+            return Err(Error::PermissionDenied);
+        }
+        Ok(())
+    }
+
+    /// Borrows the storage.
+    ///
+    /// `Error::Busy` is returned instead of panicking if the storage is already borrowed, i.e. if the filesystem is
+    /// accessed from a callback invoked while an operation is in progress. `RefCell` is not thread-safe so use from
+    /// multiple threads (possible only with the `unsound_sync` feature) is undefined behavior and is not detected.
+    pub(crate) fn disk_mut(&self) -> Result<RefMut<'_, IO>, Error<IO::Error>> {
+        self.disk.try_borrow_mut().map_err(|_| {
+            error!("filesystem is busy - reentrant access detected");
+            Error::Busy
+        })
+    }
+
+    pub(crate) fn handle_write_error_with_disk(&self, disk: &mut IO, error: Error<IO::Error>) -> Error<IO::Error> {
+        if let Error::WriteVerificationFailed { .. } = error {
+            let mut flags = self.current_status_flags.get();
+            flags.io_error = true;
+            if let Err(err) = self.write_status_flags(disk, flags, true) {
+                error!("failed to set IO Error flag {:?}", err);
+            }
+        }
+        error
+    }
+
+    /// Reads data from the storage starting at `offset`.
+    ///
+    /// This is the storage access path shared by cluster IO, the FAT12/FAT16 root directory region and FAT reads not
+    /// served from the prefetched copy. Storage size checks are done here.
+    pub(crate) fn read_at(&self, offset: u64, buf: &mut [u8]) -> Result<usize, Error<IO::Error>> {
+        let mut disk = self.disk_mut()?;
+        self.check_storage_access(&mut *disk, offset + buf.len() as u64)?;
+        disk.seek(SeekFrom::Start(offset))?;
+        let size = disk.read(buf)?;
+        if size == 0 && !buf.is_empty() {
+            // data lies beyond the end of the storage - check if it has been truncated
+            self.check_storage_len(&mut *disk)?;
+        }
+        Ok(size)
+    }
+
+    /// Writes data to the storage starting at `offset` and marks the volume dirty.
+    ///
+    /// This is the write counterpart of `read_at` used by all writes outside of the reserved region. If `verify` is
+    /// set the written data is read back and the IO Error flag is set if it differs.
+    pub(crate) fn write_at(&self, offset: u64, buf: &[u8], verify: bool) -> Result<usize, Error<IO::Error>> {
+        self.check_writable()?;
+        let mut disk = self.disk_mut()?;
+        self.set_dirty_flag_with_disk(&mut *disk, true)?;
+        self.check_storage_access(&mut *disk, offset + buf.len() as u64)?;
+        disk.seek(SeekFrom::Start(offset))?;
+        let size = disk.write(buf)?;
+        self.storage_written(offset + size as u64);
+        if verify {
+            verify_written(&mut *disk, offset, &buf[..size])
+                .map_err(|e| self.handle_write_error_with_disk(&mut *disk, e))?;
+        }
+        Ok(size)
+    }
+
+    /// Writes the whole buffer using `write_at`.
+    pub(crate) fn write_all_at(&self, mut offset: u64, mut buf: &[u8], verify: bool) -> Result<(), Error<IO::Error>> {
+        while !buf.is_empty() {
+            let size = self.write_at(offset, buf, verify)?;
+            if size == 0 {
+                error!("failed to write whole buffer");
+                return Err(Error::WriteZero);
+            }
+            offset += size as u64;
+            buf = &buf[size..];
+        }
+        Ok(())
+    }
+
+    fn write_zeros_at(&self, mut offset: u64, mut len: u64) -> Result<(), Error<IO::Error>> {
+        const ZEROS: [u8; 512] = [0_u8; 512];
+        while len > 0 {
+            let write_size = cmp::min(len, ZEROS.len() as u64) as usize;
+            self.write_all_at(offset, &ZEROS[..write_size], false)?;
+            offset += write_size as u64;
+            len -= write_size as u64;
+        }
         Ok(())
     }
 
@@ -609,13 +1694,16 @@ impl<IO: Read + Write + Seek, TP, OCC> FileSystem<IO, TP, OCC> {
         trace!("root_dir");
         let root_rdr = {
             match self.fat_type {
-                FatType::Fat12 | FatType::Fat16 => DirRawStream::Root(DiskSlice::from_sectors(
-                    self.first_data_sector - self.root_dir_sectors,
-                    self.root_dir_sectors,
-                    1,
-                    &self.bpb,
-                    FsIoAdapter { fs: self },
-                )),
+                FatType::Fat12 | FatType::Fat16 => DirRawStream::Root(
+                    DiskSlice::from_sectors(
+                        self.first_data_sector - self.root_dir_sectors,
+                        self.root_dir_sectors,
+                        1,
+                        &self.bpb,
+                        FsIoAdapter { fs: self },
+                    )
+                    .with_verification(self.options.verify_writes.verify_metadata()),
+                ),
                 FatType::Fat32 => DirRawStream::File(File::new(Some(self.bpb.root_dir_first_cluster), None, self)),
             }
         };
@@ -627,12 +1715,13 @@ impl<IO: ReadWriteSeek, TP, OCC: OemCpConverter> FileSystem<IO, TP, OCC> {
     /// Returns a volume label from BPB in the Boot Sector as `String`.
     ///
     /// Non-ASCII characters are replaced by the replacement character (U+FFFD).
-    /// Note: This function returns label stored in the BPB block. Use `read_volume_label_from_root_dir` to read label
+    /// Note: This function returns label stored in the BPB block. Use `read_volume_label` to read the label preferably
     /// from the root directory.
     #[cfg(feature = "alloc")]
     pub fn volume_label(&self) -> String {
         // Decode volume label from OEM codepage
-        let volume_label_iter = self.volume_label_as_bytes().iter().copied();
+        let volume_label = VolumeLabel::new(&self.bpb_volume_label.get(), &self.options.oem_cp_converter);
+        let volume_label_iter = volume_label.as_bytes().iter().copied();
         let char_iter = volume_label_iter.map(|c| self.options.oem_cp_converter.decode(c));
         // Build string from character iterator
         char_iter.collect()
@@ -640,6 +1729,81 @@ impl<IO: ReadWriteSeek, TP, OCC: OemCpConverter> FileSystem<IO, TP, OCC> {
 }
 
 impl<IO: ReadWriteSeek, TP: TimeProvider, OCC: OemCpConverter> FileSystem<IO, TP, OCC> {
+    /// Returns the volume label.
+    ///
+    /// The label is read from the entry with `VOLUME_ID` attribute in the root directory. The label stored in the BPB
+    /// block is returned if there is no such entry. Windows updates only the root directory entry when changing the
+    /// label so the BPB block is used just as a fallback.
+    ///
+    /// # Errors
+    ///
+    /// `Error::Io` will be returned if the underlying storage object returned an I/O error.
+    pub fn read_volume_label(&self) -> Result<VolumeLabel, Error<IO::Error>> {
+        let raw = self
+            .read_volume_label_from_root_dir_as_bytes()?
+            .unwrap_or_else(|| self.bpb_volume_label.get());
+        Ok(VolumeLabel::new(&raw, &self.options.oem_cp_converter))
+    }
+
+    /// Sets the volume label.
+    ///
+    /// The label is converted to uppercase and stored in the entry with `VOLUME_ID` attribute in the root directory
+    /// (the entry is created if needed) and in the BPB block (including the backup boot sector on FAT32). An empty
+    /// label removes the root directory entry and sets the BPB label to `NO NAME`.
+    ///
+    /// # Errors
+    ///
+    /// Errors that can be returned:
+    ///
+    /// * `Error::InvalidInput` will be returned if `label` is longer than 11 characters, starts with a space or
+    ///   contains a character which is not allowed in short names or cannot be encoded in the OEM codepage.
+    /// * `Error::NotEnoughSpace` will be returned if there is no space for a new entry in the root directory.
+    /// * `Error::PermissionDenied` will be returned if the filesystem is mounted read-only.
+    /// * `Error::Io` will be returned if the underlying storage object returned an I/O error.
+    pub fn set_volume_label(&self, label: &str) -> Result<(), Error<IO::Error>> {
+        trace!("FileSystem::set_volume_label {}", label);
+        self.check_writable()?;
+        let raw_label = self.encode_volume_label(label)?;
+        if label.is_empty() {
+            self.root_dir().set_volume_entry(None)?;
+            self.write_bpb_volume_label(*b"NO NAME    ")
+        } else {
+            self.root_dir().set_volume_entry(Some(raw_label))?;
+            self.write_bpb_volume_label(raw_label)
+        }
+    }
+
+    fn encode_volume_label(&self, label: &str) -> Result<[u8; SFN_SIZE], Error<IO::Error>> {
+        let mut raw_label = [SFN_PADDING; SFN_SIZE];
+        let mut len = 0;
+        for c in label.chars() {
+            if len == SFN_SIZE {
+                error!("volume label {} is too long", label);
+                return Err(Error::InvalidInput);
+            }
+            let mut upper = c.to_uppercase();
+            let c = if upper.len() == 1 { upper.next().unwrap_or(c) } else { c };
+            let is_invalid = c.is_control() || "\"*+,./:;<=>?[\\]|".contains(c) || (len == 0 && c == ' ');
+            let encoded = if is_invalid {
+                None
+            } else {
+                self.options.oem_cp_converter.encode(c)
+            };
+            if let Some(b) = encoded {
+                raw_label[len] = b;
+                len += 1;
+            } else {
+                error!("invalid character {:?} in volume label {}", c, label);
+                return Err(Error::InvalidInput);
+            }
+        }
+        // 0xE5 would mark the entry as deleted - 0x05 is used instead like in short names
+        if raw_label[0] == 0xE5 {
+            raw_label[0] = 0x05;
+        }
+        Ok(raw_label)
+    }
+
     /// Returns a volume label from root directory as `String`.
     ///
     /// It finds file with `VOLUME_ID` attribute and returns its short name.
@@ -667,6 +1831,139 @@ impl<IO: ReadWriteSeek, TP: TimeProvider, OCC: OemCpConverter> FileSystem<IO, TP
         })
     }
 
+    /// Returns number of files and directories, their total size and other statistics.
+    ///
+    /// Unlike `stats` this function walks the entire directory tree and the cluster chain of every file so it can take
+    /// a while for big volumes.
+    ///
+    /// # Errors
+    ///
+    /// Errors that can be returned:
+    ///
+    /// * `Error::CorruptedFileSystem` will be returned if directory structure or a cluster chain is invalid (e.g.
+    ///   a cluster chain contains a loop). Without the `alloc` feature it is also returned if directories are nested
+    ///   too deeply. With the `alloc` feature a directory referenced more than once (e.g. a directory loop) is counted
+    ///   only once.
+    /// * `Error::LimitExceeded` will be returned if directories are nested deeper than allowed by
+    ///   `FsLimits::max_dir_depth`.
+    /// * `Error::Io` will be returned if the underlying storage object returned an I/O error.
+    pub fn usage_summary(&self) -> Result<UsageSummary, Error<IO::Error>> {
+        let mut summary = UsageSummary::default();
+        #[cfg(feature = "alloc")]
+        {
+            self.auto_prefetch_fat()?;
+            let mut queue = DirQueue::new(self);
+            while let Some(entry) = queue.pop() {
+                entry
+                    .dir()
+                    .add_usage(&mut summary, entry.depth(), &mut |_, e| queue.push(&entry, e))?;
+            }
+        }
+        #[cfg(not(feature = "alloc"))]
+        self.root_dir().add_usage_recursive(&mut summary, 0)?;
+        Ok(summary)
+    }
+
+    /// Returns an iterator over all directories on the volume including the root directory.
+    ///
+    /// Directories are returned in the depth-first order. The traversal does not use recursion so it is safe for
+    /// deeply nested directory trees, and every directory is returned at most once even if the directory structure
+    /// contains a loop. See `DirQueueIter` for details.
+    ///
+    /// # Errors
+    ///
+    /// Errors that can be returned by the iterator:
+    ///
+    /// * `Error::CorruptedFileSystem` will be returned if a directory entry has no cluster.
+    /// * `Error::LimitExceeded` will be returned if directories are nested deeper than allowed by
+    ///   `FsLimits::max_dir_depth`.
+    /// * `Error::Io` will be returned if the underlying storage object returned an I/O error.
+    ///
+    /// The iteration ends after the first error.
+    #[cfg(feature = "alloc")]
+    #[must_use]
+    pub fn dir_queue_iter(&self) -> DirQueueIter<'_, IO, TP, OCC> {
+        DirQueueIter::new(self)
+    }
+
+    /// Returns fragmentation statistics of all files on the volume.
+    ///
+    /// The report includes up to `top_n` most fragmented files. Directories are traversed using `dir_queue_iter` and
+    /// only `top_n` files are kept in memory at once. The cluster chain of every file is followed so it can take a
+    /// while for big volumes.
+    ///
+    /// # Errors
+    ///
+    /// Errors that can be returned:
+    ///
+    /// * `Error::CorruptedFileSystem` will be returned if directory structure or a cluster chain is invalid.
+    /// * `Error::LimitExceeded` will be returned if directories are nested deeper than allowed by
+    ///   `FsLimits::max_dir_depth`.
+    /// * `Error::Io` will be returned if the underlying storage object returned an I/O error.
+    #[cfg(feature = "alloc")]
+    pub fn fragmentation_report(&self, top_n: usize) -> Result<FragmentationReport, Error<IO::Error>> {
+        fragmentation::fragmentation_report(self, top_n)
+    }
+
+    /// Opens the directory `path` as a handle confined to it.
+    ///
+    /// `path` is relative to the root directory. The returned `ScopedDir` can be passed to code that should not access
+    /// anything outside of this directory - paths passed to it are resolved like in `chroot`.
+    ///
+    /// # Errors
+    ///
+    /// Errors are the same as for `Dir::open_dir`.
+    #[cfg(feature = "alloc")]
+    pub fn scoped_root(&self, path: &str) -> Result<ScopedDir<'_, IO, TP, OCC>, Error<IO::Error>> {
+        Ok(self.root_dir().open_dir(path)?.into_scoped())
+    }
+
+    /// Checks consistency of the filesystem.
+    ///
+    /// All cluster chains reachable from the root directory are followed and compared with file sizes. Clusters used
+    /// by more than one chain and clusters marked as used in the FAT but not reachable are reported. The filesystem is
+    /// not modified. It can take a while for big volumes - see `check_incremental` for a version splitting the work.
+    ///
+    /// # Errors
+    ///
+    /// Errors that can be returned:
+    ///
+    /// * `Error::CorruptedFileSystem` will be returned if a directory cannot be read.
+    /// * `Error::Io` will be returned if the underlying storage object returned an I/O error.
+    #[cfg(feature = "alloc")]
+    pub fn check(&self) -> Result<CheckReport, Error<IO::Error>> {
+        let mut state = CheckState::new();
+        loop {
+            if let CheckProgress::Done(report) = self.check_incremental(&mut state, CheckBudget::unlimited())? {
+                return Ok(report);
+            }
+        }
+    }
+
+    /// Performs a part of the filesystem consistency check limited by `budget`.
+    ///
+    /// The check is resumed from `state` and the state is updated so the next call continues the work. The call
+    /// finishing the check returns the same report as `check`. The filesystem must not be modified between calls.
+    ///
+    /// # Errors
+    ///
+    /// Errors that can be returned:
+    ///
+    /// * `Error::InvalidInput` will be returned if `state` was created for a different filesystem.
+    /// * `Error::CorruptedFileSystem` will be returned if a directory cannot be read.
+    /// * `Error::Io` will be returned if the underlying storage object returned an I/O error.
+    #[cfg(feature = "alloc")]
+    pub fn check_incremental(
+        &self,
+        state: &mut CheckState,
+        budget: CheckBudget,
+    ) -> Result<CheckProgress, Error<IO::Error>> {
+        if !state.is_started() {
+            self.auto_prefetch_fat()?;
+        }
+        state.step(self, budget)
+    }
+
     /// Returns a volume label from root directory as byte array.
     ///
     /// Label is encoded in the OEM codepage.
@@ -694,47 +1991,161 @@ pub(crate) struct FsIoAdapter<'a, IO: ReadWriteSeek, TP, OCC> {
     fs: &'a FileSystem<IO, TP, OCC>,
 }
 
-impl<IO: ReadWriteSeek, TP, OCC> IoBase for FsIoAdapter<'_, IO, TP, OCC> {
+impl<IO: ReadWriteSeek, TP, OCC> StorageIo for FsIoAdapter<'_, IO, TP, OCC> {
     type Error = IO::Error;
+
+    fn read_at(&mut self, offset: u64, buf: &mut [u8]) -> Result<usize, Error<Self::Error>> {
+        self.fs.read_at(offset, buf)
+    }
+
+    fn write_at(&mut self, offset: u64, buf: &[u8], verify: bool) -> Result<usize, Error<Self::Error>> {
+        self.fs.write_at(offset, buf, verify)
+    }
+
+    fn flush(&mut self) -> Result<(), Error<Self::Error>> {
+        Ok(self.fs.disk_mut()?.flush()?)
+    }
 }
 
-impl<IO: ReadWriteSeek, TP, OCC> Read for FsIoAdapter<'_, IO, TP, OCC> {
-    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
-        self.fs.disk.borrow_mut().read(buf)
+impl<'a, IO: ReadWriteSeek, TP, OCC> FsIoAdapter<'a, IO, TP, OCC> {
+    pub(crate) fn fs(&self) -> &'a FileSystem<IO, TP, OCC> {
+        self.fs
+    }
+}
+
+// Note: derive cannot be used because of invalid bounds. See: https://github.com/rust-lang/rust/issues/26925
+impl<IO: ReadWriteSeek, TP, OCC> Clone for FsIoAdapter<'_, IO, TP, OCC> {
+    fn clone(&self) -> Self {
+        FsIoAdapter { fs: self.fs }
+    }
+}
+
+/// Reads back `expected.len()` bytes starting at `offset` and compares them with `expected`.
+///
+/// Stream position is left right after the verified range.
+pub(crate) fn verify_written<S: Read + Seek>(
+    storage: &mut S,
+    offset: u64,
+    expected: &[u8],
+) -> Result<(), Error<S::Error>> {
+    let mut buf = [0_u8; 512];
+    storage.seek(SeekFrom::Start(offset))?;
+    let mut chunk_offset = offset;
+    for chunk in expected.chunks(buf.len()) {
+        let read_buf = &mut buf[..chunk.len()];
+        storage.read_exact(read_buf)?;
+        if let Some(pos) = read_buf.iter().zip(chunk).position(|(a, b)| a != b) {
+            let offset = chunk_offset + pos as u64;
+            error!("write verification failed at offset {}", offset);
+            return Err(Error::WriteVerificationFailed { offset });
+        }
+        chunk_offset += chunk.len() as u64;
+    }
+    Ok(())
+}
+
+/// A writer verifying every write using `verify_written` if enabled.
+pub(crate) struct VerifyingWriter<'a, S> {
+    inner: &'a mut S,
+    enabled: bool,
+}
+
+impl<'a, S: ReadWriteSeek> VerifyingWriter<'a, S> {
+    pub(crate) fn new(inner: &'a mut S, enabled: bool) -> Self {
+        Self { inner, enabled }
     }
 }
 
-impl<IO: ReadWriteSeek, TP, OCC> Write for FsIoAdapter<'_, IO, TP, OCC> {
+impl<S: IoBase> IoBase for VerifyingWriter<'_, S> {
+    type Error = Error<S::Error>;
+}
+
+impl<S: ReadWriteSeek> Write for VerifyingWriter<'_, S> {
     fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
-        let size = self.fs.disk.borrow_mut().write(buf)?;
-        if size > 0 {
-            self.fs.set_dirty_flag(true)?;
+        if !self.enabled {
+            return Ok(self.inner.write(buf)?);
         }
+        let offset = self.inner.seek(SeekFrom::Current(0))?;
+        let size = self.inner.write(buf)?;
+        verify_written(self.inner, offset, &buf[..size])?;
         Ok(size)
     }
 
     fn flush(&mut self) -> Result<(), Self::Error> {
-        self.fs.disk.borrow_mut().flush()
+        Ok(self.inner.flush()?)
     }
 }
 
-impl<IO: ReadWriteSeek, TP, OCC> Seek for FsIoAdapter<'_, IO, TP, OCC> {
-    fn seek(&mut self, pos: SeekFrom) -> Result<u64, Self::Error> {
-        self.fs.disk.borrow_mut().seek(pos)
+/// Returns the storage length and checks if the storage is big enough to contain a boot sector.
+///
+/// The storage is left at the start so it is also checked if it supports seeking backwards.
+fn storage_len<S: Seek>(storage: &mut S) -> Result<u64, Error<S::Error>> {
+    // size of the boot sector structure read before the geometry is known
+    const BOOT_SECTOR_SIZE: u64 = 512;
+    let seek_result = storage
+        .seek(SeekFrom::End(0))
+        .and_then(|len| Ok((len, storage.seek(SeekFrom::Start(0))?)));
+    let len = match seek_result {
+        Ok((len, 0)) => len,
+        Ok(_) => {
+            error!("storage does not support seeking backwards");
+            return Err(Error::InvalidInput);
+        }
+        Err(err) => {
+            error!("storage does not support seeking to the end and back");
+            return Err(Error::Io(err));
+        }
+    };
+    if len < BOOT_SECTOR_SIZE {
+        error!(
+            "storage is too small to contain a boot sector: {} bytes long but at least {} bytes are required",
+            len, BOOT_SECTOR_SIZE
+        );
+        return Err(Error::StorageTooSmall {
+            len,
+            min_len: BOOT_SECTOR_SIZE,
+        });
     }
+    Ok(len)
 }
 
-// Note: derive cannot be used because of invalid bounds. See: https://github.com/rust-lang/rust/issues/26925
-impl<IO: ReadWriteSeek, TP, OCC> Clone for FsIoAdapter<'_, IO, TP, OCC> {
-    fn clone(&self) -> Self {
-        FsIoAdapter { fs: self.fs }
+/// Positioned storage access used by `DiskSlice`.
+///
+/// `FsIoAdapter` implements it using the `FileSystem` storage access path shared with cluster IO. Other storage objects
+/// (used while formatting or mounting before the `FileSystem` object exists) are accessed directly.
+pub(crate) trait StorageIo {
+    type Error: IoError;
+
+    fn read_at(&mut self, offset: u64, buf: &mut [u8]) -> Result<usize, Error<Self::Error>>;
+
+    fn write_at(&mut self, offset: u64, buf: &[u8], verify: bool) -> Result<usize, Error<Self::Error>>;
+
+    fn flush(&mut self) -> Result<(), Error<Self::Error>>;
+}
+
+impl<S: ReadWriteSeek> StorageIo for S {
+    type Error = S::Error;
+
+    fn read_at(&mut self, offset: u64, buf: &mut [u8]) -> Result<usize, Error<Self::Error>> {
+        self.seek(SeekFrom::Start(offset))?;
+        Ok(self.read(buf)?)
+    }
+
+    fn write_at(&mut self, offset: u64, buf: &[u8], verify: bool) -> Result<usize, Error<Self::Error>> {
+        self.seek(SeekFrom::Start(offset))?;
+        let size = self.write(buf)?;
+        if verify {
+            verify_written(self, offset, &buf[..size])?;
+        }
+        Ok(size)
+    }
+
+    fn flush(&mut self) -> Result<(), Error<Self::Error>> {
+        Ok(Write::flush(self)?)
     }
 }
 
-fn fat_slice<S: ReadWriteSeek, B: BorrowMut<S>>(
-    io: B,
-    bpb: &BiosParameterBlock,
-) -> impl ReadWriteSeek<Error = Error<S::Error>> {
+fn fat_slice<S: StorageIo, B: BorrowMut<S>>(io: B, bpb: &BiosParameterBlock) -> DiskSlice<B, S> {
     let sectors_per_fat = bpb.sectors_per_fat();
     let mirroring_enabled = bpb.mirroring_enabled();
     let (fat_first_sector, mirrors) = if mirroring_enabled {
@@ -752,22 +2163,37 @@ pub(crate) struct DiskSlice<B, S = B> {
     size: u64,
     offset: u64,
     mirrors: u8,
+    verify: bool,
     inner: B,
     phantom: PhantomData<S>,
 }
 
-impl<B: BorrowMut<S>, S: ReadWriteSeek> DiskSlice<B, S> {
+impl<B: BorrowMut<S>, S: StorageIo> DiskSlice<B, S> {
     pub(crate) fn new(begin: u64, size: u64, mirrors: u8, inner: B) -> Self {
         Self {
             begin,
             size,
             mirrors,
+            verify: false,
             inner,
             offset: 0,
             phantom: PhantomData,
         }
     }
 
+    pub(crate) fn with_verification(mut self, verify: bool) -> Self {
+        self.verify = verify;
+        self
+    }
+
+    pub(crate) fn size(&self) -> u64 {
+        self.size
+    }
+
+    pub(crate) fn inner(&self) -> &B {
+        &self.inner
+    }
+
     fn from_sectors(first_sector: u32, sector_count: u32, mirrors: u8, bpb: &BiosParameterBlock, inner: B) -> Self {
         Self::new(
             bpb.bytes_from_sectors(first_sector),
@@ -790,6 +2216,7 @@ impl<B: Clone, S> Clone for DiskSlice<B, S> {
             size: self.size,
             offset: self.offset,
             mirrors: self.mirrors,
+            verify: self.verify,
             inner: self.inner.clone(),
             // phantom is needed to add type bounds on the storage type
             phantom: PhantomData,
@@ -797,22 +2224,21 @@ impl<B: Clone, S> Clone for DiskSlice<B, S> {
     }
 }
 
-impl<B, S: IoBase> IoBase for DiskSlice<B, S> {
+impl<B, S: StorageIo> IoBase for DiskSlice<B, S> {
     type Error = Error<S::Error>;
 }
 
-impl<B: BorrowMut<S>, S: Read + Seek> Read for DiskSlice<B, S> {
+impl<B: BorrowMut<S>, S: StorageIo> Read for DiskSlice<B, S> {
     fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
         let offset = self.begin + self.offset;
         let read_size = cmp::min(self.size - self.offset, buf.len() as u64) as usize;
-        self.inner.borrow_mut().seek(SeekFrom::Start(offset))?;
-        let size = self.inner.borrow_mut().read(&mut buf[..read_size])?;
+        let size = self.inner.borrow_mut().read_at(offset, &mut buf[..read_size])?;
         self.offset += size as u64;
         Ok(size)
     }
 }
 
-impl<B: BorrowMut<S>, S: Write + Seek> Write for DiskSlice<B, S> {
+impl<B: BorrowMut<S>, S: StorageIo> Write for DiskSlice<B, S> {
     fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
         let offset = self.begin + self.offset;
         let write_size = cmp::min(self.size - self.offset, buf.len() as u64) as usize;
@@ -822,19 +2248,27 @@ impl<B: BorrowMut<S>, S: Write + Seek> Write for DiskSlice<B, S> {
         // Write data
         let storage = self.inner.borrow_mut();
         for i in 0..self.mirrors {
-            storage.seek(SeekFrom::Start(offset + u64::from(i) * self.size))?;
-            storage.write_all(&buf[..write_size])?;
+            let mirror_offset = offset + u64::from(i) * self.size;
+            let mut written = 0;
+            while written < write_size {
+                let size = storage.write_at(mirror_offset + written as u64, &buf[written..write_size], self.verify)?;
+                if size == 0 {
+                    error!("failed to write whole buffer");
+                    return Err(Error::WriteZero);
+                }
+                written += size;
+            }
         }
         self.offset += write_size as u64;
         Ok(write_size)
     }
 
     fn flush(&mut self) -> Result<(), Self::Error> {
-        Ok(self.inner.borrow_mut().flush()?)
+        self.inner.borrow_mut().flush()
     }
 }
 
-impl<B, S: IoBase> Seek for DiskSlice<B, S> {
+impl<B, S: StorageIo> Seek for DiskSlice<B, S> {
     fn seek(&mut self, pos: SeekFrom) -> Result<u64, Self::Error> {
         let new_offset_opt: Option<u64> = match pos {
             SeekFrom::Current(x) => i64::try_from(self.offset)
@@ -862,6 +2296,80 @@ impl<B, S: IoBase> Seek for DiskSlice<B, S> {
     }
 }
 
+/// A FAT slice wrapper serving reads from the in-memory FAT copy filled by `FileSystem::prefetch_fat`.
+///
+/// Reads beyond the cached part fall back to the underlying slice. Writes always go to the underlying slice (so all
+/// FAT mirrors are updated) and the cached copy is updated afterwards.
+#[cfg(feature = "alloc")]
+struct FatCacheSlice<'a, S> {
+    inner: S,
+    cache: &'a RefCell<Vec<u8>>,
+    offset: u64,
+}
+
+#[cfg(feature = "alloc")]
+impl<'a, S> FatCacheSlice<'a, S> {
+    fn new(inner: S, cache: &'a RefCell<Vec<u8>>) -> Self {
+        Self {
+            inner,
+            cache,
+            offset: 0,
+        }
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<S: IoBase> IoBase for FatCacheSlice<'_, S> {
+    type Error = S::Error;
+}
+
+#[cfg(feature = "alloc")]
+impl<S: Read + Seek> Read for FatCacheSlice<'_, S> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        let cached_size = {
+            let cache = self.cache.borrow();
+            let cached = cache.get(self.offset as usize..).unwrap_or(&[]);
+            let size = cmp::min(cached.len(), buf.len());
+            buf[..size].copy_from_slice(&cached[..size]);
+            size
+        };
+        let size = if cached_size > 0 {
+            // keep the underlying slice position in sync (it does not access the storage)
+            self.inner.seek(SeekFrom::Start(self.offset + cached_size as u64))?;
+            cached_size
+        } else {
+            self.inner.read(buf)?
+        };
+        self.offset += size as u64;
+        Ok(size)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<S: Write> Write for FatCacheSlice<'_, S> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        let size = self.inner.write(buf)?;
+        let mut cache = self.cache.borrow_mut();
+        let start = cmp::min(self.offset, cache.len() as u64) as usize;
+        let end = cmp::min(self.offset + size as u64, cache.len() as u64) as usize;
+        cache[start..end].copy_from_slice(&buf[..end - start]);
+        self.offset += size as u64;
+        Ok(size)
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<S: Seek> Seek for FatCacheSlice<'_, S> {
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64, Self::Error> {
+        self.offset = self.inner.seek(pos)?;
+        Ok(self.offset)
+    }
+}
+
 /// An OEM code page encoder/decoder.
 ///
 /// Provides a custom implementation for a short name encoding/decoding.
@@ -1096,8 +2604,8 @@ impl FormatVolumeOptions {
 /// partition. Please use it with caution.
 /// Only quick formatting is supported. To achieve a full format zero entire partition before calling this function.
 /// Supplied `storage` parameter cannot be seeked (internal pointer must be on position 0).
-/// To format a fragment of a disk image (e.g. partition) library user should wrap the file struct in a struct
-/// limiting access to partition bytes only e.g. `fscommon::StreamSlice`.
+/// To format a fragment of a disk image (e.g. partition) wrap the storage in `StreamSlice` limiting access to the
+/// partition bytes.
 ///
 /// # Errors
 ///
@@ -1148,6 +2656,7 @@ pub fn format_volume<S: ReadWriteSeek>(storage: &mut S, options: FormatVolumeOpt
             free_cluster_count: None,
             next_free_cluster: None,
             dirty: false,
+            valid: true,
         };
         storage.seek(SeekFrom::Start(bpb.bytes_from_sectors(bpb.fs_info_sector())))?;
         fs_info_sector.serialize(storage)?;
@@ -1203,3 +2712,59 @@ pub fn format_volume<S: ReadWriteSeek>(storage: &mut S, options: FormatVolumeOpt
     trace!("format_volume end");
     Ok(())
 }
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use crate::io::StdIoWrapper;
+    use std::io::Cursor;
+
+    fn open_fs(path: &str) -> FileSystem<StdIoWrapper<Cursor<Vec<u8>>>, DefaultTimeProvider, LossyOemCpConverter> {
+        let img = std::fs::read(path).unwrap();
+        FileSystem::new(Cursor::new(img), FsOptions::new()).unwrap()
+    }
+
+    #[test]
+    fn default_options_are_equal_to_new() {
+        let default_options = FsOptions::<DefaultTimeProvider, LossyOemCpConverter>::default;
+        assert_eq!(format!("{:?}", default_options()), format!("{:?}", FsOptions::new()));
+        assert!(default_options().windows_name_rules);
+        assert!(default_options().update_modified_time);
+        assert!(default_options().update_archive_attribute);
+    }
+
+    #[test]
+    fn reentrant_access_returns_busy_error() {
+        // FAT32 root directory is accessed like a file
+        let fs = open_fs("resources/fat32.img");
+        let mut file = fs.root_dir().open_file("short.txt").unwrap();
+        {
+            // simulate a callback invoked by an operation holding the storage
+            let _disk = fs.disk.borrow_mut();
+            let err = fs.root_dir().open_file("long.txt").err().unwrap();
+            assert!(matches!(err, Error::Busy));
+            assert_eq!(err.to_string(), "Filesystem is busy - reentrant access detected");
+            assert!(matches!(file.read(&mut [0; 4]), Err(Error::Busy)));
+            assert!(matches!(file.write(b"data"), Err(Error::Busy)));
+            // directory cache is skipped
+            fs.dir_cache_insert(0, "a", 0);
+        }
+        // the filesystem is usable after the reentrant access
+        let mut buf = [0_u8; 4];
+        file.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"Rust");
+        assert!(fs.root_dir().open_file("long.txt").is_ok());
+    }
+
+    #[test]
+    fn reentrant_access_to_fat16_root_dir_returns_busy_error() {
+        // FAT16 root directory region is accessed through the same path as clusters
+        let fs = open_fs("resources/fat16.img");
+        {
+            let _disk = fs.disk.borrow_mut();
+            assert!(matches!(fs.root_dir().open_file("long.txt"), Err(Error::Busy)));
+            assert!(matches!(fs.root_dir().create_file("new.txt"), Err(Error::Busy)));
+        }
+        assert!(fs.root_dir().open_file("long.txt").is_ok());
+    }
+}
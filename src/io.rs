@@ -1,3 +1,6 @@
+use core::cmp;
+use core::convert::TryFrom;
+
 use crate::error::IoError;
 
 /// Provides IO error as an associated type.
@@ -151,6 +154,40 @@ pub trait Seek: IoBase {
     fn seek(&mut self, pos: SeekFrom) -> Result<u64, Self::Error>;
 }
 
+impl<T: IoBase + ?Sized> IoBase for &mut T {
+    type Error = T::Error;
+}
+
+impl<T: Read + ?Sized> Read for &mut T {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        (**self).read(buf)
+    }
+
+    fn read_exact(&mut self, buf: &mut [u8]) -> Result<(), Self::Error> {
+        (**self).read_exact(buf)
+    }
+}
+
+impl<T: Write + ?Sized> Write for &mut T {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        (**self).write(buf)
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> Result<(), Self::Error> {
+        (**self).write_all(buf)
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        (**self).flush()
+    }
+}
+
+impl<T: Seek + ?Sized> Seek for &mut T {
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64, Self::Error> {
+        (**self).seek(pos)
+    }
+}
+
 #[cfg(feature = "std")]
 impl From<SeekFrom> for std::io::SeekFrom {
     fn from(from: SeekFrom) -> Self {
@@ -239,6 +276,130 @@ impl<T> From<T> for StdIoWrapper<T> {
     }
 }
 
+/// A stream limited to a part of an underlying stream, e.g. a partition of a disk.
+///
+/// Positions are relative to the start of the slice. Reads and writes are truncated at the end of the slice and
+/// seeking beyond it fails, so bytes outside of the slice are never accessed. A write at the end of the slice
+/// returns zero which makes `write_all` fail with an error created by `IoError::new_write_zero_error`. Every seek is
+/// passed to the inner stream, so the inner stream should not be used directly while the slice exists.
+///
+/// It is the supported way to mount a filesystem placed at an offset in a bigger storage:
+///
+/// ```
+/// # fn main() -> Result<(), fatfs::Error<std::io::Error>> {
+/// // the volume starts at byte 1048576 of a disk image
+/// let mut disk = vec![0_u8; 1048576];
+/// disk.extend(std::fs::read("resources/fat16.img")?);
+/// let end = disk.len() as u64;
+/// let storage = fatfs::StdIoWrapper::new(std::io::Cursor::new(disk));
+/// let partition = fatfs::StreamSlice::new(storage, 1048576, end)?;
+/// let fs = fatfs::FileSystem::new(partition, fatfs::FsOptions::new())?;
+/// assert!(fs.root_dir().open_file("short.txt").is_ok());
+/// # Ok(())
+/// # }
+/// ```
+pub struct StreamSlice<T> {
+    inner: T,
+    start: u64,
+    len: u64,
+    pos: u64,
+}
+
+impl<T: Seek> StreamSlice<T> {
+    /// Creates a slice of `inner` containing bytes from `start` (inclusive) to `end` (exclusive).
+    ///
+    /// The inner stream is seeked to `start`.
+    ///
+    /// # Errors
+    ///
+    /// An error created by `IoError::new_invalid_input_error` will be returned if `start` is greater than `end`.
+    /// Errors returned by the inner stream are passed through.
+    pub fn new(mut inner: T, start: u64, end: u64) -> Result<Self, T::Error> {
+        if start > end {
+            debug!("invalid stream slice {}..{}", start, end);
+            return Err(T::Error::new_invalid_input_error());
+        }
+        inner.seek(SeekFrom::Start(start))?;
+        Ok(Self {
+            inner,
+            start,
+            len: end - start,
+            pos: 0,
+        })
+    }
+}
+
+impl<T> StreamSlice<T> {
+    /// Returns the inner stream.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    /// Returns the number of bytes of a buffer with `buf_len` bytes that fit before the end of the slice.
+    fn bytes_left(&self, buf_len: usize) -> usize {
+        cmp::min(buf_len as u64, self.len - self.pos) as usize
+    }
+}
+
+impl<T: IoBase> IoBase for StreamSlice<T> {
+    type Error = T::Error;
+}
+
+impl<T: Read> Read for StreamSlice<T> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        let n = self.bytes_left(buf.len());
+        if n == 0 {
+            return Ok(0);
+        }
+        let read = self.inner.read(&mut buf[..n])?;
+        self.pos += read as u64;
+        Ok(read)
+    }
+}
+
+impl<T: Write> Write for StreamSlice<T> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        let n = self.bytes_left(buf.len());
+        if n == 0 {
+            return Ok(0);
+        }
+        let written = self.inner.write(&buf[..n])?;
+        self.pos += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        self.inner.flush()
+    }
+}
+
+impl<T: Seek> Seek for StreamSlice<T> {
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64, Self::Error> {
+        let new_pos_opt: Option<u64> = match pos {
+            SeekFrom::Current(x) => i64::try_from(self.pos)
+                .ok()
+                .and_then(|n| n.checked_add(x))
+                .and_then(|n| u64::try_from(n).ok()),
+            SeekFrom::Start(x) => Some(x),
+            SeekFrom::End(o) => i64::try_from(self.len)
+                .ok()
+                .and_then(|len| len.checked_add(o))
+                .and_then(|n| u64::try_from(n).ok()),
+        };
+        match new_pos_opt {
+            Some(new_pos) if new_pos <= self.len => {
+                self.inner.seek(SeekFrom::Start(self.start + new_pos))?;
+                self.pos = new_pos;
+                Ok(new_pos)
+            }
+            _ => {
+                debug!("invalid seek {:?} in a stream slice of {} bytes", pos, self.len);
+                Err(T::Error::new_invalid_input_error())
+            }
+        }
+    }
+}
+
 pub(crate) trait ReadLeExt {
     type Error;
     fn read_u8(&mut self) -> Result<u8, Self::Error>;
@@ -58,19 +58,45 @@ extern crate alloc;
 mod log_macros;
 
 mod boot_sector;
+mod buf_stream;
+#[cfg(feature = "alloc")]
+mod check;
 mod dir;
+mod dir_cache;
 mod dir_entry;
+#[cfg(feature = "alloc")]
+mod dir_queue;
 mod error;
 mod file;
+pub mod fmt;
+#[cfg(feature = "alloc")]
+mod fragmentation;
 mod fs;
 mod io;
+mod mbr;
+mod open_files;
+#[cfg(feature = "alloc")]
+pub mod overlay;
+#[cfg(feature = "alloc")]
+mod scoped_dir;
 mod table;
 mod time;
 
+pub use crate::buf_stream::*;
+#[cfg(feature = "alloc")]
+pub use crate::check::*;
 pub use crate::dir::*;
+pub use crate::dir_cache::*;
 pub use crate::dir_entry::*;
+#[cfg(feature = "alloc")]
+pub use crate::dir_queue::*;
 pub use crate::error::*;
 pub use crate::file::*;
+#[cfg(feature = "alloc")]
+pub use crate::fragmentation::*;
 pub use crate::fs::*;
 pub use crate::io::*;
+pub use crate::mbr::*;
+#[cfg(feature = "alloc")]
+pub use crate::scoped_dir::*;
 pub use crate::time::*;
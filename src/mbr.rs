@@ -0,0 +1,188 @@
+use crate::error::Error;
+use crate::io::{Read, Seek, SeekFrom, StreamSlice};
+
+const MBR_SIZE: usize = 512;
+const PARTITION_TABLE_OFFSET: usize = 446;
+const PARTITION_ENTRY_SIZE: usize = 16;
+const NUM_PRIMARY_PARTITIONS: usize = 4;
+
+/// Size of a sector used by LBA addresses in the partition table.
+pub const MBR_SECTOR_SIZE: u64 = 512;
+
+/// Returns `true` if `partition_type` is one of the partition type bytes used for FAT volumes.
+///
+/// Recognized types are `0x01` (FAT12), `0x04`, `0x06` and `0x0E` (FAT16) and `0x0B` and `0x0C` (FAT32). The type
+/// is only a hint - the FAT type of a mounted volume is always determined from its boot sector.
+#[must_use]
+pub fn is_fat_partition_type(partition_type: u8) -> bool {
+    matches!(partition_type, 0x01 | 0x04 | 0x06 | 0x0B | 0x0C | 0x0E)
+}
+
+/// A primary partition entry of a Master Boot Record.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct PartitionEntry {
+    bootable: bool,
+    partition_type: u8,
+    start_lba: u32,
+    sector_count: u32,
+}
+
+impl PartitionEntry {
+    fn parse(raw: &[u8]) -> Option<Self> {
+        let bootable = match raw[0] {
+            0x00 => false,
+            0x80 => true,
+            _ => return None,
+        };
+        Some(Self {
+            bootable,
+            partition_type: raw[4],
+            start_lba: u32::from_le_bytes([raw[8], raw[9], raw[10], raw[11]]),
+            sector_count: u32::from_le_bytes([raw[12], raw[13], raw[14], raw[15]]),
+        })
+    }
+
+    /// Returns `true` if the partition is marked as active (bootable).
+    #[must_use]
+    pub fn is_bootable(&self) -> bool {
+        self.bootable
+    }
+
+    /// Returns the partition type byte.
+    #[must_use]
+    pub fn partition_type(&self) -> u8 {
+        self.partition_type
+    }
+
+    /// Returns the address of the first sector of the partition.
+    #[must_use]
+    pub fn start_lba(&self) -> u32 {
+        self.start_lba
+    }
+
+    /// Returns the number of sectors in the partition.
+    #[must_use]
+    pub fn sector_count(&self) -> u32 {
+        self.sector_count
+    }
+
+    /// Returns `true` if the entry does not describe a partition (it has zero type or zero size).
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.partition_type == 0 || self.sector_count == 0
+    }
+
+    /// Returns `true` if the partition type is one of the FAT types (see `is_fat_partition_type`).
+    #[must_use]
+    pub fn is_fat(&self) -> bool {
+        is_fat_partition_type(self.partition_type)
+    }
+
+    /// Returns `true` if the partition is an extended partition containing logical partitions.
+    ///
+    /// Logical partitions are not parsed by `Mbr::read`.
+    #[must_use]
+    pub fn is_extended(&self) -> bool {
+        matches!(self.partition_type, 0x05 | 0x0F | 0x85)
+    }
+
+    /// Returns offsets of the first byte and the byte after the last one of the partition.
+    #[must_use]
+    pub fn byte_range(&self) -> (u64, u64) {
+        let start = u64::from(self.start_lba) * MBR_SECTOR_SIZE;
+        (start, start + u64::from(self.sector_count) * MBR_SECTOR_SIZE)
+    }
+}
+
+/// A Master Boot Record partition table.
+///
+/// Only the four primary partition entries are read. Addresses are expressed in 512-byte sectors.
+///
+/// # Examples
+///
+/// ```
+/// # fn main() -> Result<(), fatfs::Error<std::io::Error>> {
+/// # let img = std::fs::read("resources/fat16.img")?;
+/// # let mut disk = vec![0_u8; 512];
+/// # disk[446..462].copy_from_slice(&[0x80, 0, 0, 0, 0x0E, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0]);
+/// # disk[458..462].copy_from_slice(&(img.len() as u32 / 512).to_le_bytes());
+/// # disk[510..].copy_from_slice(&[0x55, 0xAA]);
+/// # disk.extend(img);
+/// let mut disk = fatfs::StdIoWrapper::new(std::io::Cursor::new(disk));
+/// let mbr = fatfs::Mbr::read(&mut disk)?;
+/// let index = mbr.partitions().iter().position(|p| p.is_fat()).expect("no FAT partition");
+/// let partition = fatfs::Mbr::partition_slice(disk, index)?;
+/// let fs = fatfs::FileSystem::new(partition, fatfs::FsOptions::new())?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Mbr {
+    partitions: [PartitionEntry; NUM_PRIMARY_PARTITIONS],
+}
+
+impl Mbr {
+    /// Reads the partition table from the first sector of `disk`.
+    ///
+    /// # Errors
+    ///
+    /// Errors that can be returned:
+    ///
+    /// * `Error::CorruptedFileSystem` will be returned if the sector does not end with the `0x55AA` signature or
+    ///   a partition entry has an invalid status byte (e.g. the disk is not partitioned and starts with a FAT boot
+    ///   sector instead).
+    /// * `Error::Io` will be returned if the underlying storage object returned an I/O error (e.g. the disk is shorter
+    ///   than a sector).
+    pub fn read<T: Read + Seek>(disk: &mut T) -> Result<Self, Error<T::Error>> {
+        let mut sector = [0_u8; MBR_SIZE];
+        disk.seek(SeekFrom::Start(0))?;
+        disk.read_exact(&mut sector)?;
+        if sector[MBR_SIZE - 2..] != [0x55, 0xAA] {
+            error!(
+                "Invalid MBR signature: expected [0x55, 0xAA] but got {:?}",
+                &sector[MBR_SIZE - 2..]
+            );
+            return Err(Error::CorruptedFileSystem);
+        }
+        let mut partitions = [PartitionEntry::default(); NUM_PRIMARY_PARTITIONS];
+        for (i, partition) in partitions.iter_mut().enumerate() {
+            let offset = PARTITION_TABLE_OFFSET + i * PARTITION_ENTRY_SIZE;
+            let raw = &sector[offset..offset + PARTITION_ENTRY_SIZE];
+            *partition = PartitionEntry::parse(raw).ok_or_else(|| {
+                error!("Invalid status {:#x} of MBR partition entry {}", raw[0], i);
+                Error::CorruptedFileSystem
+            })?;
+        }
+        Ok(Self { partitions })
+    }
+
+    /// Returns the primary partition entries including empty ones.
+    #[must_use]
+    pub fn partitions(&self) -> &[PartitionEntry] {
+        &self.partitions
+    }
+
+    /// Reads the partition table from `disk` and returns a slice of `disk` covering the primary partition with
+    /// index `index`.
+    ///
+    /// The result can be passed to `FileSystem::new`.
+    ///
+    /// # Errors
+    ///
+    /// Errors that can be returned:
+    ///
+    /// * `Error::InvalidInput` will be returned if `index` is not lower than 4 or the entry is empty.
+    /// * Other errors are the same as for `Mbr::read`.
+    pub fn partition_slice<T: Read + Seek>(mut disk: T, index: usize) -> Result<StreamSlice<T>, Error<T::Error>> {
+        let mbr = Self::read(&mut disk)?;
+        let partition = match mbr.partitions.get(index) {
+            Some(partition) if !partition.is_empty() => partition,
+            _ => {
+                error!("MBR partition {} does not exist", index);
+                return Err(Error::InvalidInput);
+            }
+        };
+        let (start, end) = partition.byte_range();
+        Ok(StreamSlice::new(disk, start, end)?)
+    }
+}
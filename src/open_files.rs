@@ -0,0 +1,108 @@
+#[cfg(all(not(feature = "std"), feature = "alloc"))]
+use alloc::vec::Vec;
+
+/// Metadata of an open file shared by all its handles.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct SharedFileState {
+    pub(crate) size: u32,
+    pub(crate) first_cluster: Option<u32>,
+    // incremented every time clusters are freed - handles positioned in a freed cluster have to seek again
+    pub(crate) truncations: u32,
+}
+
+#[cfg(feature = "alloc")]
+#[derive(Debug)]
+struct OpenFile {
+    id: u32,
+    entry_pos: u64,
+    handles: u32,
+    state: SharedFileState,
+}
+
+/// A table of files having at least one `File` handle, used to keep handles of the same file coherent.
+///
+/// Files are identified by the position of their directory entry. Every registration gets an unique id so a handle
+/// of a removed file does not affect a file created later in the same entry slot. Without the `alloc` feature the
+/// table is always empty and handles do not share anything.
+#[derive(Debug, Default)]
+pub(crate) struct OpenFiles {
+    #[cfg(feature = "alloc")]
+    files: Vec<OpenFile>,
+    #[cfg(feature = "alloc")]
+    next_id: u32,
+}
+
+#[cfg(feature = "alloc")]
+impl OpenFiles {
+    /// Registers a new handle of the file with entry at `entry_pos`.
+    ///
+    /// Returns the registration id and the current state - `state` is used only if the file was not open.
+    pub(crate) fn open(&mut self, entry_pos: u64, state: SharedFileState) -> (Option<u32>, SharedFileState) {
+        if let Some(file) = self.files.iter_mut().find(|f| f.entry_pos == entry_pos) {
+            file.handles += 1;
+            return (Some(file.id), file.state);
+        }
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1);
+        self.files.push(OpenFile {
+            id,
+            entry_pos,
+            handles: 1,
+            state,
+        });
+        (Some(id), state)
+    }
+
+    /// Registers another handle of an already open file.
+    pub(crate) fn reopen(&mut self, id: u32) {
+        if let Some(file) = self.files.iter_mut().find(|f| f.id == id) {
+            file.handles += 1;
+        }
+    }
+
+    /// Unregisters a handle. The file is removed from the table when its last handle is closed.
+    pub(crate) fn close(&mut self, id: u32) {
+        if let Some(index) = self.files.iter().position(|f| f.id == id) {
+            self.files[index].handles -= 1;
+            if self.files[index].handles == 0 {
+                self.files.swap_remove(index);
+            }
+        }
+    }
+
+    /// Forgets the file with entry at `entry_pos` because the entry has been removed.
+    ///
+    /// Remaining handles of the file continue to work independently.
+    pub(crate) fn forget(&mut self, entry_pos: u64) {
+        self.files.retain(|f| f.entry_pos != entry_pos);
+    }
+
+    pub(crate) fn get(&self, id: u32) -> Option<SharedFileState> {
+        self.files.iter().find(|f| f.id == id).map(|f| f.state)
+    }
+
+    pub(crate) fn set(&mut self, id: u32, state: SharedFileState) {
+        if let Some(file) = self.files.iter_mut().find(|f| f.id == id) {
+            file.state = state;
+        }
+    }
+}
+
+#[cfg(not(feature = "alloc"))]
+impl OpenFiles {
+    pub(crate) fn open(&mut self, _entry_pos: u64, state: SharedFileState) -> (Option<u32>, SharedFileState) {
+        (None, state)
+    }
+
+    pub(crate) fn reopen(&mut self, _id: u32) {}
+
+    pub(crate) fn close(&mut self, _id: u32) {}
+
+    pub(crate) fn forget(&mut self, _entry_pos: u64) {}
+
+    pub(crate) fn get(&self, _id: u32) -> Option<SharedFileState> {
+        None
+    }
+
+    pub(crate) fn set(&mut self, _id: u32, _state: SharedFileState) {}
+}
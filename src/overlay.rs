@@ -0,0 +1,322 @@
+//! Copy-on-write storage overlay.
+//!
+//! `CowStream` keeps all writes in memory so a filesystem can be mounted and modified without touching the
+//! underlying storage. It makes dry runs easy: wrap the storage, mount it, perform an operation, inspect the result
+//! and `discard` the changes (or `commit` them).
+//!
+//! # Examples
+//!
+//! ```rust
+//! # fn main() -> std::io::Result<()> {
+//! # std::fs::copy("resources/fat16.img", "tmp/overlay.img")?;
+//! let img_file = std::fs::OpenOptions::new().read(true).write(true).open("tmp/overlay.img")?;
+//! let mut cow = fatfs::overlay::CowStream::new(fatfs::StdIoWrapper::from(img_file));
+//! {
+//!     let fs = fatfs::FileSystem::new(&mut cow, fatfs::FsOptions::new())?;
+//!     fs.root_dir().remove("short.txt")?;
+//! }
+//! println!("removing the file modifies {} sectors", cow.modified_pages());
+//! cow.discard();
+//! # std::fs::remove_file("tmp/overlay.img")?;
+//! # Ok(())
+//! # }
+//! ```
+
+#[cfg(not(feature = "std"))]
+use alloc::boxed::Box;
+#[cfg(not(feature = "std"))]
+use alloc::collections::btree_map::{BTreeMap, Entry};
+#[cfg(not(feature = "std"))]
+use alloc::vec;
+use core::cmp;
+use core::convert::TryFrom;
+#[cfg(feature = "std")]
+use std::collections::btree_map::{BTreeMap, Entry};
+
+use crate::error::IoError;
+use crate::io::{IoBase, Read, Seek, SeekFrom, Write};
+
+/// Default page size - a typical sector size.
+const DEFAULT_PAGE_SIZE: usize = 512;
+
+/// A stream wrapper keeping all writes in an in-memory page map.
+///
+/// Reads are served from the modified pages if present and from the inner stream otherwise. The inner stream is
+/// only written by `commit`.
+pub struct CowStream<T> {
+    inner: T,
+    pages: BTreeMap<u64, Box<[u8]>>,
+    page_size: usize,
+    pos: u64,
+    len: Option<u64>,
+}
+
+impl<T: Read + Write + Seek> CowStream<T> {
+    /// Creates an overlay over `inner` using 512 bytes pages.
+    pub fn new(inner: T) -> Self {
+        Self::with_page_size(inner, DEFAULT_PAGE_SIZE)
+    }
+
+    /// Creates an overlay over `inner` using pages of the given size.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `page_size` is zero.
+    pub fn with_page_size(inner: T, page_size: usize) -> Self {
+        assert!(page_size > 0, "page size must not be zero");
+        Self {
+            inner,
+            pages: BTreeMap::new(),
+            page_size,
+            pos: 0,
+            len: None,
+        }
+    }
+
+    /// Returns the number of pages modified since the overlay was created or last committed or discarded.
+    pub fn modified_pages(&self) -> usize {
+        self.pages.len()
+    }
+
+    /// Returns true if the overlay contains modifications not written to the inner stream.
+    pub fn is_modified(&self) -> bool {
+        !self.pages.is_empty()
+    }
+
+    /// Drops all modifications. The inner stream is left untouched.
+    pub fn discard(&mut self) {
+        self.pages.clear();
+        self.len = None;
+    }
+
+    /// Writes all modified pages to the inner stream and flushes it.
+    ///
+    /// Pages are written in the order of their offsets. The filesystem does not tell which pages contain data and
+    /// which contain metadata, so commit is not crash-safe: if it is interrupted the inner stream can contain a
+    /// mix of old and new pages.
+    ///
+    /// # Errors
+    ///
+    /// Errors returned by the inner stream are passed through. Pages are kept in the overlay if an error occurs.
+    pub fn commit(&mut self) -> Result<(), T::Error> {
+        let len = self.len()?;
+        for (&index, page) in &self.pages {
+            let page_start = index * self.page_size as u64;
+            if page_start >= len {
+                continue;
+            }
+            let size = cmp::min(self.page_size as u64, len - page_start) as usize;
+            self.inner.seek(SeekFrom::Start(page_start))?;
+            self.inner.write_all(&page[..size])?;
+        }
+        self.inner.flush()?;
+        self.discard();
+        Ok(())
+    }
+
+    /// Returns a reference to the inner stream.
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+
+    /// Drops all modifications and returns the inner stream.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    fn len(&mut self) -> Result<u64, T::Error> {
+        if let Some(len) = self.len {
+            return Ok(len);
+        }
+        let len = self.inner.seek(SeekFrom::End(0))?;
+        self.len = Some(len);
+        Ok(len)
+    }
+
+    fn load_page(&mut self, index: u64) -> Result<&mut [u8], T::Error> {
+        match self.pages.entry(index) {
+            Entry::Occupied(entry) => Ok(entry.into_mut()),
+            Entry::Vacant(entry) => {
+                let mut data = vec![0_u8; self.page_size].into_boxed_slice();
+                self.inner.seek(SeekFrom::Start(index * self.page_size as u64))?;
+                let mut filled = 0;
+                // part of the page after the end of the inner stream is zero-filled
+                while filled < data.len() {
+                    match self.inner.read(&mut data[filled..]) {
+                        Ok(0) => break,
+                        Ok(n) => filled += n,
+                        Err(ref e) if e.is_interrupted() => {}
+                        Err(e) => return Err(e),
+                    }
+                }
+                Ok(entry.insert(data))
+            }
+        }
+    }
+}
+
+impl<T: IoBase> IoBase for CowStream<T> {
+    type Error = T::Error;
+}
+
+impl<T: Read + Write + Seek> Read for CowStream<T> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        let len = self.len()?;
+        if self.pos >= len || buf.is_empty() {
+            return Ok(0);
+        }
+        let index = self.pos / self.page_size as u64;
+        let offset_in_page = (self.pos % self.page_size as u64) as usize;
+        let max_size = cmp::min(buf.len(), self.page_size - offset_in_page);
+        let size = cmp::min(max_size as u64, len - self.pos) as usize;
+        let size = if let Some(page) = self.pages.get(&index) {
+            buf[..size].copy_from_slice(&page[offset_in_page..offset_in_page + size]);
+            size
+        } else {
+            self.inner.seek(SeekFrom::Start(self.pos))?;
+            match self.inner.read(&mut buf[..size])? {
+                // a hole created by writing after the end of the inner stream
+                0 => {
+                    buf[..size].iter_mut().for_each(|b| *b = 0);
+                    size
+                }
+                n => n,
+            }
+        };
+        self.pos += size as u64;
+        Ok(size)
+    }
+}
+
+impl<T: Read + Write + Seek> Write for CowStream<T> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let len = self.len()?;
+        let index = self.pos / self.page_size as u64;
+        let offset_in_page = (self.pos % self.page_size as u64) as usize;
+        let size = cmp::min(buf.len(), self.page_size - offset_in_page);
+        let page = self.load_page(index)?;
+        page[offset_in_page..offset_in_page + size].copy_from_slice(&buf[..size]);
+        self.pos += size as u64;
+        self.len = Some(cmp::max(len, self.pos));
+        Ok(size)
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        // nothing is written to the inner stream before commit
+        Ok(())
+    }
+}
+
+impl<T: Read + Write + Seek> Seek for CowStream<T> {
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64, Self::Error> {
+        let offset_from = |base: u64, offset: i64| {
+            i64::try_from(base)
+                .ok()
+                .and_then(|n| n.checked_add(offset))
+                .and_then(|n| u64::try_from(n).ok())
+        };
+        let new_pos = match pos {
+            SeekFrom::Start(n) => Some(n),
+            SeekFrom::Current(offset) => offset_from(self.pos, offset),
+            SeekFrom::End(offset) => offset_from(self.len()?, offset),
+        };
+        if let Some(new_pos) = new_pos {
+            self.pos = new_pos;
+            Ok(new_pos)
+        } else {
+            error!("Invalid seek offset");
+            Err(T::Error::new_invalid_input_error())
+        }
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use crate::io::StdIoWrapper;
+    use std::io::Cursor;
+
+    fn cow_over(data: &[u8], page_size: usize) -> CowStream<StdIoWrapper<Cursor<Vec<u8>>>> {
+        CowStream::with_page_size(StdIoWrapper::from(Cursor::new(data.to_vec())), page_size)
+    }
+
+    fn read_all<T: Read + Write + Seek>(cow: &mut CowStream<T>) -> Vec<u8> {
+        cow.seek(SeekFrom::Start(0)).unwrap();
+        let mut result = Vec::new();
+        let mut buf = [0_u8; 7];
+        loop {
+            let n = cow.read(&mut buf).unwrap();
+            if n == 0 {
+                return result;
+            }
+            result.extend_from_slice(&buf[..n]);
+        }
+    }
+
+    fn inner_data(cow: CowStream<StdIoWrapper<Cursor<Vec<u8>>>>) -> Vec<u8> {
+        cow.into_inner().into_inner().into_inner()
+    }
+
+    #[test]
+    fn overlay_keeps_writes_until_commit() {
+        let data: Vec<u8> = (0..100).collect();
+        let mut expected = data.clone();
+        expected[10..30].copy_from_slice(&[0xAA; 20]);
+        let write = |cow: &mut CowStream<_>| {
+            cow.seek(SeekFrom::Start(10)).unwrap();
+            cow.write_all(&[0xAA; 20]).unwrap();
+        };
+
+        let mut cow = cow_over(&data, 16);
+        write(&mut cow);
+        assert_eq!(read_all(&mut cow), expected);
+        assert_eq!(cow.modified_pages(), 2);
+        assert_eq!(inner_data(cow), data);
+
+        let mut cow = cow_over(&data, 16);
+        write(&mut cow);
+        cow.commit().unwrap();
+        assert!(!cow.is_modified());
+        assert_eq!(read_all(&mut cow), expected);
+        assert_eq!(inner_data(cow), expected);
+    }
+
+    #[test]
+    fn discard_drops_writes() {
+        let data = [1_u8; 40];
+        let mut cow = cow_over(&data, 16);
+        cow.write_all(&[2; 40]).unwrap();
+        assert!(cow.is_modified());
+        cow.discard();
+        assert!(!cow.is_modified());
+        assert_eq!(read_all(&mut cow), data);
+        cow.commit().unwrap();
+        assert_eq!(inner_data(cow), data);
+    }
+
+    #[test]
+    fn write_after_end_extends_stream() {
+        let data = [1_u8; 10];
+        let mut cow = cow_over(&data, 16);
+        assert_eq!(cow.seek(SeekFrom::End(30)).unwrap(), 40);
+        cow.write_all(&[2; 5]).unwrap();
+        let mut expected = vec![1_u8; 10];
+        expected.resize(40, 0);
+        expected.extend_from_slice(&[2; 5]);
+        assert_eq!(read_all(&mut cow), expected);
+        assert_eq!(cow.seek(SeekFrom::End(0)).unwrap(), 45);
+        cow.commit().unwrap();
+        assert_eq!(inner_data(cow), expected);
+    }
+
+    #[test]
+    fn seek_before_start_fails() {
+        let mut cow = cow_over(&[0; 10], 16);
+        assert!(cow.seek(SeekFrom::Current(-1)).is_err());
+        assert!(cow.seek(SeekFrom::End(-11)).is_err());
+        assert_eq!(cow.seek(SeekFrom::End(-10)).unwrap(), 0);
+    }
+}
@@ -0,0 +1,352 @@
+#[cfg(not(feature = "std"))]
+use alloc::string::String;
+#[cfg(not(feature = "std"))]
+use alloc::vec::Vec;
+
+use crate::dir::{Dir, DirIter};
+use crate::dir_entry::{DirEntry, FileAttributes};
+use crate::error::Error;
+use crate::file::File;
+use crate::fs::{OemCpConverter, ReadWriteSeek};
+use crate::time::{Date, DateTime, TimeProvider};
+
+/// A directory handle confined to a subtree of the filesystem (similar to `chroot`).
+///
+/// It is created by the `scoped_root` method on `FileSystem` or the `into_scoped` method on `Dir`. The directory it
+/// was created from is the scope root.
+///
+/// Paths passed to its methods are resolved lexically: `.` components are ignored, `..` goes to the parent directory
+/// but never above the scope root and paths starting with `/` are relative to the scope root. `.` and `..` entries
+/// stored in directories are never used for the lookup and are skipped by `iter`, and all returned directories are
+/// scoped too, so code holding only a `ScopedDir` cannot access anything outside of the scope.
+pub struct ScopedDir<'a, IO: ReadWriteSeek, TP, OCC> {
+    root: Dir<'a, IO, TP, OCC>,
+    dir: Dir<'a, IO, TP, OCC>,
+    /// Path of `dir` relative to `root` without `.` and `..` components
+    path: String,
+}
+
+impl<'a, IO: ReadWriteSeek, TP: TimeProvider, OCC: OemCpConverter> ScopedDir<'a, IO, TP, OCC> {
+    pub(crate) fn new(root: Dir<'a, IO, TP, OCC>) -> Self {
+        Self {
+            dir: root.clone(),
+            root,
+            path: String::new(),
+        }
+    }
+
+    /// Path of this directory relative to the scope root (empty for the scope root itself).
+    #[must_use]
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Creates directory entries iterator.
+    ///
+    /// `.` and `..` entries are skipped.
+    #[must_use]
+    pub fn iter(&self) -> ScopedDirIter<'a, IO, TP, OCC> {
+        ScopedDirIter {
+            inner: self.dir.iter(),
+            root: self.root.clone(),
+            path: self.path.clone(),
+        }
+    }
+
+    /// Opens existing subdirectory.
+    ///
+    /// An empty path, `/` and `.` refer to the scope root.
+    ///
+    /// # Errors
+    ///
+    /// Errors are the same as for `Dir::open_dir`.
+    pub fn open_dir(&self, path: &str) -> Result<Self, Error<IO::Error>> {
+        trace!("ScopedDir::open_dir {}", path);
+        let (base, rel_path, full_path) = self.resolve(path);
+        let dir = base.open_dir(&rel_path)?;
+        Ok(self.subdir(dir, full_path))
+    }
+
+    /// Opens existing file.
+    ///
+    /// # Errors
+    ///
+    /// Errors are the same as for `Dir::open_file`. `Error::InvalidInput` is returned if `path` resolves to the scope
+    /// root.
+    pub fn open_file(&self, path: &str) -> Result<File<'a, IO, TP, OCC>, Error<IO::Error>> {
+        trace!("ScopedDir::open_file {}", path);
+        let (base, rel_path, _) = self.resolve(path);
+        base.open_file(&rel_path)
+    }
+
+    /// Checks if `path` points to an existing file or directory.
+    ///
+    /// # Errors
+    ///
+    /// Errors are the same as for `Dir::exists`.
+    pub fn exists(&self, path: &str) -> Result<bool, Error<IO::Error>> {
+        let (base, rel_path, _) = self.resolve(path);
+        base.exists(&rel_path)
+    }
+
+    /// Creates new or opens existing file.
+    ///
+    /// # Errors
+    ///
+    /// Errors are the same as for `Dir::create_file`. `Error::InvalidInput` is returned if `path` resolves to the
+    /// scope root.
+    pub fn create_file(&self, path: &str) -> Result<File<'a, IO, TP, OCC>, Error<IO::Error>> {
+        trace!("ScopedDir::create_file {}", path);
+        let (base, rel_path, _) = self.resolve(path);
+        base.create_file(&rel_path)
+    }
+
+    /// Creates new directory or opens existing.
+    ///
+    /// # Errors
+    ///
+    /// Errors are the same as for `Dir::create_dir`. `Error::InvalidInput` is returned if `path` resolves to the
+    /// scope root.
+    pub fn create_dir(&self, path: &str) -> Result<Self, Error<IO::Error>> {
+        trace!("ScopedDir::create_dir {}", path);
+        let (base, rel_path, full_path) = self.resolve(path);
+        let dir = base.create_dir(&rel_path)?;
+        Ok(self.subdir(dir, full_path))
+    }
+
+    /// Removes existing file or directory.
+    ///
+    /// # Errors
+    ///
+    /// Errors are the same as for `Dir::remove`. `Error::InvalidInput` is returned if `path` resolves to the scope
+    /// root so the scope root cannot be removed.
+    pub fn remove(&self, path: &str) -> Result<(), Error<IO::Error>> {
+        trace!("ScopedDir::remove {}", path);
+        let (base, rel_path, _) = self.resolve(path);
+        base.remove(&rel_path)
+    }
+
+    /// Renames or moves existing file or directory.
+    ///
+    /// `dst_path` is resolved in the scope of `dst_dir`.
+    ///
+    /// # Errors
+    ///
+    /// Errors are the same as for `Dir::rename`. `Error::InvalidInput` is returned if any of the paths resolves to
+    /// the scope root.
+    pub fn rename(&self, src_path: &str, dst_dir: &Self, dst_path: &str) -> Result<(), Error<IO::Error>> {
+        trace!("ScopedDir::rename {} {}", src_path, dst_path);
+        let (src_base, src_rel_path, _) = self.resolve(src_path);
+        let (dst_base, dst_rel_path, _) = dst_dir.resolve(dst_path);
+        src_base.rename(&src_rel_path, dst_base, &dst_rel_path)
+    }
+
+    fn subdir(&self, dir: Dir<'a, IO, TP, OCC>, path: String) -> Self {
+        Self {
+            root: self.root.clone(),
+            dir,
+            path,
+        }
+    }
+
+    /// Resolves `path` lexically.
+    ///
+    /// Returns the directory the lookup should start from, the path relative to it and the path relative to the
+    /// scope root. The lookup starts from this directory unless `path` starts with `/` or leaves this directory.
+    fn resolve(&self, path: &str) -> (&Dir<'a, IO, TP, OCC>, String, String) {
+        let separators = self.root.path_separators();
+        let is_absolute = path.starts_with(separators);
+        if !is_absolute {
+            if let Some(components) = normalize_path(Vec::new(), path, separators, false) {
+                let rel_path = components.join("/");
+                let full_path = join_path(&self.path, &rel_path);
+                return (&self.dir, rel_path, full_path);
+            }
+        }
+        let start = if is_absolute {
+            Vec::new()
+        } else {
+            self.path.split('/').filter(|c| !c.is_empty()).collect()
+        };
+        // `..` components are clamped at the scope root so normalization cannot fail
+        let full_path = normalize_path(start, path, separators, true)
+            .unwrap_or_default()
+            .join("/");
+        (&self.root, full_path.clone(), full_path)
+    }
+}
+
+impl<IO: ReadWriteSeek, TP: TimeProvider, OCC: OemCpConverter> Clone for ScopedDir<'_, IO, TP, OCC> {
+    fn clone(&self) -> Self {
+        Self {
+            root: self.root.clone(),
+            dir: self.dir.clone(),
+            path: self.path.clone(),
+        }
+    }
+}
+
+impl<'a, IO: ReadWriteSeek, TP: TimeProvider, OCC: OemCpConverter> IntoIterator for &ScopedDir<'a, IO, TP, OCC> {
+    type Item = Result<ScopedDirEntry<'a, IO, TP, OCC>, Error<IO::Error>>;
+    type IntoIter = ScopedDirIter<'a, IO, TP, OCC>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Appends components of `path` to `components` removing `.` and resolving `..`.
+///
+/// If `..` leaves the directory `components` are relative to, `None` is returned or, if `clamp` is set, the `..` is
+/// ignored.
+fn normalize_path<'b>(
+    mut components: Vec<&'b str>,
+    path: &'b str,
+    separators: &[char],
+    clamp: bool,
+) -> Option<Vec<&'b str>> {
+    for component in path.split(separators) {
+        match component {
+            "" | "." => {}
+            ".." => {
+                if components.pop().is_none() && !clamp {
+                    return None;
+                }
+            }
+            _ => components.push(component),
+        }
+    }
+    Some(components)
+}
+
+fn join_path(parent: &str, name: &str) -> String {
+    if parent.is_empty() {
+        name.into()
+    } else if name.is_empty() {
+        parent.into()
+    } else {
+        [parent, name].join("/")
+    }
+}
+
+/// An iterator over entries of a `ScopedDir`.
+///
+/// It is created by the `iter` method on `ScopedDir`.
+pub struct ScopedDirIter<'a, IO: ReadWriteSeek, TP, OCC> {
+    inner: DirIter<'a, IO, TP, OCC>,
+    root: Dir<'a, IO, TP, OCC>,
+    path: String,
+}
+
+impl<'a, IO: ReadWriteSeek, TP: TimeProvider, OCC: OemCpConverter> Iterator for ScopedDirIter<'a, IO, TP, OCC> {
+    type Item = Result<ScopedDirEntry<'a, IO, TP, OCC>, Error<IO::Error>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let entry = match self.inner.next()? {
+                Ok(e) => e,
+                Err(err) => return Some(Err(err)),
+            };
+            // `..` would give access to the directory outside of the scope
+            if entry.is_dot_entry() {
+                continue;
+            }
+            return Some(Ok(ScopedDirEntry {
+                entry,
+                root: self.root.clone(),
+                path: self.path.clone(),
+            }));
+        }
+    }
+}
+
+/// A directory entry returned by `ScopedDirIter`.
+///
+/// It provides a subset of `DirEntry` methods. `to_dir` returns a directory confined to the same scope.
+pub struct ScopedDirEntry<'a, IO: ReadWriteSeek, TP, OCC> {
+    entry: DirEntry<'a, IO, TP, OCC>,
+    root: Dir<'a, IO, TP, OCC>,
+    /// Path of the parent directory relative to `root`
+    path: String,
+}
+
+#[allow(clippy::len_without_is_empty)]
+impl<'a, IO: ReadWriteSeek, TP: TimeProvider, OCC: OemCpConverter> ScopedDirEntry<'a, IO, TP, OCC> {
+    /// Returns long file name or if it doesn't exist fallbacks to short file name.
+    #[must_use]
+    pub fn file_name(&self) -> String {
+        self.entry.file_name()
+    }
+
+    /// Returns short file name.
+    #[must_use]
+    pub fn short_file_name(&self) -> String {
+        self.entry.short_file_name()
+    }
+
+    /// Returns file attributes.
+    #[must_use]
+    pub fn attributes(&self) -> FileAttributes {
+        self.entry.attributes()
+    }
+
+    /// Checks if entry belongs to directory.
+    #[must_use]
+    pub fn is_dir(&self) -> bool {
+        self.entry.is_dir()
+    }
+
+    /// Checks if entry belongs to regular file.
+    #[must_use]
+    pub fn is_file(&self) -> bool {
+        self.entry.is_file()
+    }
+
+    /// Returns `File` struct for this entry.
+    ///
+    /// # Panics
+    ///
+    /// Will panic if this is not a file.
+    #[must_use]
+    pub fn to_file(&self) -> File<'a, IO, TP, OCC> {
+        self.entry.to_file()
+    }
+
+    /// Returns `ScopedDir` struct for this entry.
+    ///
+    /// # Panics
+    ///
+    /// Will panic if this is not a directory.
+    #[must_use]
+    pub fn to_dir(&self) -> ScopedDir<'a, IO, TP, OCC> {
+        ScopedDir {
+            root: self.root.clone(),
+            dir: self.entry.to_dir(),
+            path: join_path(&self.path, &self.entry.file_name()),
+        }
+    }
+
+    /// Returns file size or 0 for directory.
+    #[must_use]
+    pub fn len(&self) -> u64 {
+        self.entry.len()
+    }
+
+    /// Returns file creation date and time or `None` if it is not set.
+    #[must_use]
+    pub fn creation_time(&self) -> Option<DateTime> {
+        self.entry.creation_time()
+    }
+
+    /// Returns file last access date or `None` if it is not set.
+    #[must_use]
+    pub fn access_date(&self) -> Option<Date> {
+        self.entry.access_date()
+    }
+
+    /// Returns file last modification date and time or `None` if it is not set.
+    #[must_use]
+    pub fn modification_time(&self) -> Option<DateTime> {
+        self.entry.modification_time()
+    }
+}
@@ -1,10 +1,11 @@
 use core::borrow::BorrowMut;
 use core::cmp;
+use core::convert::TryFrom;
 use core::marker::PhantomData;
 
 use crate::error::{Error, IoError};
 use crate::fs::{FatType, FsStatusFlags};
-use crate::io::{self, Read, ReadLeExt, Seek, Write, WriteLeExt};
+use crate::io::{self, IoBase, Read, ReadLeExt, Seek, SeekFrom, Write, WriteLeExt};
 
 struct Fat<S> {
     phantom: PhantomData<S>,
@@ -17,7 +18,7 @@ type Fat32 = Fat<u32>;
 pub const RESERVED_FAT_ENTRIES: u32 = 2;
 
 #[derive(Copy, Clone, Eq, PartialEq, Debug)]
-enum FatValue {
+pub(crate) enum FatValue {
     Free,
     Data(u32),
     Bad,
@@ -62,7 +63,7 @@ trait FatTrait {
         Error<E>: From<S::Error>;
 }
 
-fn read_fat<S, E>(fat: &mut S, fat_type: FatType, cluster: u32) -> Result<FatValue, Error<E>>
+pub(crate) fn read_fat<S, E>(fat: &mut S, fat_type: FatType, cluster: u32) -> Result<FatValue, Error<E>>
 where
     S: Read + Seek,
     E: IoError,
@@ -144,14 +145,117 @@ where
         }
         Err(e) => return Err(e),
     };
-    write_fat(fat, fat_type, new_cluster, FatValue::EndOfChain)?;
+    // entries of sequentially allocated clusters usually lie in the same FAT sector - write them together
+    let mut batch = FatWriteBatch::new(fat);
+    write_fat(&mut batch, fat_type, new_cluster, FatValue::EndOfChain)?;
     if let Some(n) = prev_cluster {
-        write_fat(fat, fat_type, n, FatValue::Data(new_cluster))?;
+        write_fat(&mut batch, fat_type, n, FatValue::Data(new_cluster))?;
     }
+    batch.flush_window()?;
     trace!("allocated cluster {}", new_cluster);
     Ok(new_cluster)
 }
 
+/// Finds `count` consecutive free clusters and returns the first of them or `None` if there is no such run.
+///
+/// The run starting at `preferred_cluster` is checked first, then the FAT is scanned starting at `hint` (the next
+/// free cluster hint) and wrapping around once.
+pub(crate) fn find_free_cluster_run<S, E>(
+    fat: &mut S,
+    fat_type: FatType,
+    preferred_cluster: Option<u32>,
+    hint: Option<u32>,
+    count: u32,
+    total_clusters: u32,
+) -> Result<Option<u32>, Error<E>>
+where
+    S: Read + Seek,
+    E: IoError,
+    Error<E>: From<S::Error>,
+{
+    let end_cluster = total_clusters + RESERVED_FAT_ENTRIES;
+    // returns the end of the run of free clusters starting at `first` limited to `count` clusters
+    let free_run_end = |fat: &mut S, first: u32| -> Result<u32, Error<E>> {
+        let mut cluster = first;
+        while cluster < end_cluster && cluster - first < count && read_fat(fat, fat_type, cluster)? == FatValue::Free {
+            cluster += 1;
+        }
+        Ok(cluster)
+    };
+    if let Some(first) = preferred_cluster.filter(|n| (RESERVED_FAT_ENTRIES..end_cluster).contains(n)) {
+        if free_run_end(fat, first)? - first == count {
+            return Ok(Some(first));
+        }
+    }
+    let hint_cluster = match hint {
+        Some(n) if n > RESERVED_FAT_ENTRIES && n < end_cluster => n,
+        _ => RESERVED_FAT_ENTRIES,
+    };
+    // runs starting before the hint are checked after wrapping around - they can extend past the hint
+    for &(mut start_cluster, scan_end) in &[(hint_cluster, end_cluster), (RESERVED_FAT_ENTRIES, hint_cluster)] {
+        while start_cluster < scan_end {
+            let first = match find_free_cluster(fat, fat_type, start_cluster, scan_end) {
+                Ok(n) => n,
+                Err(Error::NotEnoughSpace) => break,
+                Err(e) => return Err(e),
+            };
+            let end = free_run_end(fat, first)?;
+            if end - first == count {
+                return Ok(Some(first));
+            }
+            // the cluster at `end` is not free
+            start_cluster = end + 1;
+        }
+    }
+    Ok(None)
+}
+
+/// Allocates `count` consecutive clusters starting at `first_cluster` as a single cluster chain.
+///
+/// If `prev_cluster` is given the chain is appended to it. The FAT is not modified if any of the clusters is not
+/// free.
+pub(crate) fn alloc_cluster_range<S, E>(
+    fat: &mut S,
+    fat_type: FatType,
+    prev_cluster: Option<u32>,
+    first_cluster: u32,
+    count: u32,
+    total_clusters: u32,
+) -> Result<(), Error<E>>
+where
+    S: Read + Write + Seek,
+    E: IoError,
+    Error<E>: From<S::Error>,
+{
+    let end_cluster = match first_cluster.checked_add(count) {
+        Some(n) if first_cluster >= RESERVED_FAT_ENTRIES && n <= total_clusters + RESERVED_FAT_ENTRIES => n,
+        _ => {
+            error!("cluster range {}+{} is outside of the volume", first_cluster, count);
+            return Err(Error::CorruptedFileSystem);
+        }
+    };
+    for cluster in first_cluster..end_cluster {
+        if read_fat(fat, fat_type, cluster)? != FatValue::Free {
+            return Err(Error::ClusterInUse { cluster });
+        }
+    }
+    let mut batch = FatWriteBatch::new(fat);
+    for cluster in first_cluster..end_cluster {
+        let value = if cluster + 1 == end_cluster {
+            FatValue::EndOfChain
+        } else {
+            FatValue::Data(cluster + 1)
+        };
+        write_fat(&mut batch, fat_type, cluster, value)?;
+    }
+    if let Some(n) = prev_cluster {
+        write_fat(&mut batch, fat_type, n, FatValue::Data(first_cluster))?;
+    }
+    batch.flush_window()?;
+    trace!("allocated clusters {}..{}", first_cluster, end_cluster);
+    Ok(())
+}
+
 pub(crate) fn read_fat_flags<S, E>(fat: &mut S, fat_type: FatType) -> Result<FsStatusFlags, Error<E>>
 where
     S: Read + Seek,
@@ -177,6 +281,26 @@ where
     Ok(FsStatusFlags { dirty, io_error })
 }
 
+/// Returns the raw value of the second FAT entry with status flags bits set according to `flags`.
+///
+/// Flags are stored in the two most significant bits of the entry (not counting the reserved bits on FAT32). A bit
+/// equal to 1 means the volume is clean / has no IO errors. FAT12 has no flag bits so `raw` is returned unchanged.
+pub(crate) fn encode_fat_flags(fat_type: FatType, raw: u32, flags: FsStatusFlags) -> u32 {
+    let (clean_bit, no_io_error_bit) = match fat_type {
+        FatType::Fat12 => return raw,
+        FatType::Fat16 => (1 << 15, 1 << 14),
+        FatType::Fat32 => (1 << 27, 1 << 26),
+    };
+    let mut val = raw | clean_bit | no_io_error_bit;
+    if flags.dirty {
+        val &= !clean_bit;
+    }
+    if flags.io_error {
+        val &= !no_io_error_bit;
+    }
+    val
+}
+
 pub(crate) fn count_free_clusters<S, E>(fat: &mut S, fat_type: FatType, total_clusters: u32) -> Result<u32, Error<E>>
 where
     S: Read + Seek,
@@ -581,6 +705,117 @@ impl FatTrait for Fat32 {
     }
 }
 
+/// Size of the FAT window buffered by `FatWriteBatch` - the smallest supported sector size.
+const FAT_WRITE_BATCH_SIZE: usize = 512;
+
+/// A FAT stream wrapper combining writes of nearby FAT entries.
+///
+/// One aligned window of the FAT is kept in memory. Writes modify the window and it is written back at once when an
+/// offset outside of it is accessed or `flush_window` is called. Without it every FAT entry update is a separate
+/// write (for every FAT copy) and storage with bigger blocks has to do a read-modify-write cycle for each of them.
+struct FatWriteBatch<'a, S> {
+    inner: &'a mut S,
+    data: [u8; FAT_WRITE_BATCH_SIZE],
+    window_start: Option<u64>,
+    window_len: usize,
+    dirty: bool,
+    offset: u64,
+}
+
+impl<'a, S: Read + Write + Seek> FatWriteBatch<'a, S> {
+    fn new(inner: &'a mut S) -> Self {
+        Self {
+            inner,
+            data: [0; FAT_WRITE_BATCH_SIZE],
+            window_start: None,
+            window_len: 0,
+            dirty: false,
+            offset: 0,
+        }
+    }
+
+    /// Writes the buffered window if it has been modified.
+    fn flush_window(&mut self) -> Result<(), S::Error> {
+        if let (true, Some(start)) = (self.dirty, self.window_start) {
+            self.inner.seek(SeekFrom::Start(start))?;
+            self.inner.write_all(&self.data[..self.window_len])?;
+            self.dirty = false;
+        }
+        Ok(())
+    }
+
+    /// Makes sure the window containing the current offset is buffered and returns the offset in the window.
+    fn load_window(&mut self) -> Result<usize, S::Error> {
+        let start = self.offset - self.offset % FAT_WRITE_BATCH_SIZE as u64;
+        if self.window_start != Some(start) {
+            self.flush_window()?;
+            self.inner.seek(SeekFrom::Start(start))?;
+            let mut len = 0;
+            while len < self.data.len() {
+                match self.inner.read(&mut self.data[len..]) {
+                    Ok(0) => break,
+                    Ok(n) => len += n,
+                    Err(ref e) if e.is_interrupted() => {}
+                    Err(e) => return Err(e),
+                }
+            }
+            self.window_start = Some(start);
+            self.window_len = len;
+        }
+        Ok((self.offset - start) as usize)
+    }
+}
+
+impl<S: IoBase> IoBase for FatWriteBatch<'_, S> {
+    type Error = S::Error;
+}
+
+impl<S: Read + Write + Seek> Read for FatWriteBatch<'_, S> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        let pos = self.load_window()?;
+        let size = cmp::min(buf.len(), self.window_len.saturating_sub(pos));
+        buf[..size].copy_from_slice(&self.data[pos..pos + size]);
+        self.offset += size as u64;
+        Ok(size)
+    }
+}
+
+impl<S: Read + Write + Seek> Write for FatWriteBatch<'_, S> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        let pos = self.load_window()?;
+        let size = cmp::min(buf.len(), self.window_len.saturating_sub(pos));
+        self.data[pos..pos + size].copy_from_slice(&buf[..size]);
+        self.dirty |= size > 0;
+        self.offset += size as u64;
+        Ok(size)
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        self.flush_window()?;
+        self.inner.flush()
+    }
+}
+
+impl<S: Read + Write + Seek> Seek for FatWriteBatch<'_, S> {
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64, Self::Error> {
+        let new_offset = match pos {
+            SeekFrom::Start(n) => Some(n),
+            SeekFrom::Current(n) => i64::try_from(self.offset)
+                .ok()
+                .and_then(|offset| offset.checked_add(n))
+                .and_then(|offset| u64::try_from(offset).ok()),
+            SeekFrom::End(_) => Some(self.inner.seek(pos)?),
+        };
+        if let Some(n) = new_offset {
+            self.offset = n;
+            Ok(n)
+        } else {
+            error!("Invalid seek offset");
+            Err(S::Error::new_invalid_input_error())
+        }
+    }
+}
+
 pub(crate) struct ClusterIterator<B, E, S = B> {
     fat: B,
     fat_type: FatType,
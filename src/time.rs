@@ -1,6 +1,6 @@
 #[cfg(feature = "chrono")]
 use core::convert::TryFrom;
-use core::fmt::Debug;
+use core::fmt::{self, Debug};
 
 #[cfg(feature = "chrono")]
 use chrono::{self, Datelike, Local, TimeZone, Timelike};
@@ -52,6 +52,45 @@ impl Date {
     pub(crate) fn encode(self) -> u16 {
         ((self.year - MIN_YEAR) << 9) | (self.month << 5) | self.day
     }
+
+    /// Checks if all fields are in ranges accepted by `Date::new`.
+    pub(crate) fn is_valid(self) -> bool {
+        (MIN_YEAR..=MAX_YEAR).contains(&self.year)
+            && (MIN_MONTH..=MAX_MONTH).contains(&self.month)
+            && (MIN_DAY..=MAX_DAY).contains(&self.day)
+    }
+
+    /// Moves fields of a decoded date into their valid ranges (e.g. month 0 becomes 1 and February 30 becomes
+    /// February 28 or 29), so a corrupted entry never produces an impossible date.
+    pub(crate) fn clamped(self) -> Self {
+        let month = match self.month {
+            m if m < MIN_MONTH => MIN_MONTH,
+            m => m.min(MAX_MONTH),
+        };
+        let day = match self.day {
+            d if d < MIN_DAY => MIN_DAY,
+            d => d.min(days_in_month(self.year, month)),
+        };
+        Self {
+            year: self.year,
+            month,
+            day,
+        }
+    }
+}
+
+fn days_in_month(year: u16, month: u16) -> u16 {
+    let is_leap_year = match (year % 4, year % 100, year % 400) {
+        (_, _, 0) => true,
+        (_, 0, _) => false,
+        (rem, _, _) => rem == 0,
+    };
+    match month {
+        2 if is_leap_year => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => MAX_DAY,
+    }
 }
 
 /// A DOS compatible time.
@@ -105,6 +144,21 @@ impl Time {
         #[allow(clippy::cast_possible_truncation)]
         (dos_time, dos_time_hi_res as u8)
     }
+
+    /// Checks if all fields are in ranges accepted by `Time::new`.
+    pub(crate) fn is_valid(self) -> bool {
+        self.hour <= 23 && self.min <= 59 && self.sec <= 59 && self.millis <= 999
+    }
+
+    /// Moves fields of a decoded time into their valid ranges (e.g. hour 31 becomes 23).
+    pub(crate) fn clamped(self) -> Self {
+        Self {
+            hour: self.hour.min(23),
+            min: self.min.min(59),
+            sec: self.sec.min(59),
+            millis: self.millis.min(999),
+        }
+    }
 }
 
 /// A DOS compatible date and time.
@@ -128,6 +182,46 @@ impl DateTime {
     pub(crate) fn decode(dos_date: u16, dos_time: u16, dos_time_hi_res: u8) -> Self {
         Self::new(Date::decode(dos_date), Time::decode(dos_time, dos_time_hi_res))
     }
+
+    pub(crate) fn is_valid(self) -> bool {
+        self.date.is_valid() && self.time.is_valid()
+    }
+
+    pub(crate) fn clamped(self) -> Self {
+        Self::new(self.date.clamped(), self.time.clamped())
+    }
+}
+
+/// Formats the date as `YYYY-MM-DD`.
+impl fmt::Display for Date {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
+
+/// Formats the time as `HH:MM:SS`.
+///
+/// Precision (e.g. `{:.3}`) specifies the number of digits of the fraction of a second (at most 3).
+impl fmt::Display for Time {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:02}:{:02}:{:02}", self.hour, self.min, self.sec)?;
+        match f.precision() {
+            Some(1) => write!(f, ".{:01}", self.millis / 100),
+            Some(2) => write!(f, ".{:02}", self.millis / 10),
+            Some(n) if n >= 3 => write!(f, ".{:03}", self.millis),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Formats the date and time as `YYYY-MM-DD HH:MM:SS`.
+///
+/// Precision is applied to the time part.
+impl fmt::Display for DateTime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} ", self.date)?;
+        fmt::Display::fmt(&self.time, f)
+    }
 }
 
 #[cfg(feature = "chrono")]
@@ -296,6 +390,74 @@ mod tests {
         assert_eq!(t3, Time::decode(x3, y3));
     }
 
+    #[test]
+    fn date_decode_clamped() {
+        // month 0 and day 0
+        assert_eq!(Date::decode(0).clamped(), Date::new(1980, 1, 1));
+        // month 15 and day 31
+        assert_eq!(
+            Date::decode((20 << 9) | (15 << 5) | 31).clamped(),
+            Date::new(2000, 12, 31)
+        );
+        // February 31 in leap and non-leap years
+        assert_eq!(
+            Date::decode((20 << 9) | (2 << 5) | 31).clamped(),
+            Date::new(2000, 2, 29)
+        );
+        assert_eq!(
+            Date::decode((120 << 9) | (2 << 5) | 31).clamped(),
+            Date::new(2100, 2, 28)
+        );
+        assert_eq!(
+            Date::decode((21 << 9) | (4 << 5) | 31).clamped(),
+            Date::new(2001, 4, 30)
+        );
+        // valid dates are not changed
+        let d = Date::new(2055, 7, 23);
+        assert_eq!(Date::decode(d.encode()).clamped(), d);
+    }
+
+    #[test]
+    fn time_decode_clamped() {
+        // all bits set - hour 31, minute 63, second 62 + 2 from the high resolution part
+        assert_eq!(Time::decode(0xFFFF, 0xFF).clamped(), Time::new(23, 59, 59, 550));
+        let t = Time::new(15, 3, 29, 990);
+        let (x, y) = t.encode();
+        assert_eq!(Time::decode(x, y).clamped(), t);
+    }
+
+    #[test]
+    fn date_display() {
+        assert_eq!(Date::new(1980, 1, 1).to_string(), "1980-01-01");
+        assert_eq!(Date::new(2024, 2, 29).to_string(), "2024-02-29");
+        assert_eq!(Date::new(2107, 12, 31).to_string(), "2107-12-31");
+    }
+
+    #[test]
+    fn time_display() {
+        let t = Time::new(14, 2, 5, 987);
+        assert_eq!(t.to_string(), "14:02:05");
+        assert_eq!(format!("{:.0}", t), "14:02:05");
+        assert_eq!(format!("{:.1}", t), "14:02:05.9");
+        assert_eq!(format!("{:.2}", t), "14:02:05.98");
+        assert_eq!(format!("{:.3}", t), "14:02:05.987");
+        assert_eq!(format!("{:.9}", t), "14:02:05.987");
+        assert_eq!(format!("{:.3}", Time::new(0, 0, 0, 5)), "00:00:00.005");
+        assert_eq!(Time::new(23, 59, 59, 999).to_string(), "23:59:59");
+    }
+
+    #[test]
+    fn date_time_display() {
+        let midnight = DateTime::new(Date::new(2023, 7, 1), Time::new(0, 0, 0, 0));
+        assert_eq!(midnight.to_string(), "2023-07-01 00:00:00");
+        assert_eq!(format!("{:.2}", midnight), "2023-07-01 00:00:00.00");
+        let leap_day = DateTime::new(Date::new(2000, 2, 29), Time::new(14, 2, 58, 10));
+        assert_eq!(leap_day.to_string(), "2000-02-29 14:02:58");
+        assert_eq!(format!("{:.3}", leap_day), "2000-02-29 14:02:58.010");
+        // DOS epoch used by `NullTimeProvider`
+        assert_eq!(DateTime::decode(0, 0, 0).to_string(), "1980-00-00 00:00:00");
+    }
+
     #[test]
     fn date_time_from_chrono_leap_second() {
         use super::TimeZone;
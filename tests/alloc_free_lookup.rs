@@ -0,0 +1,68 @@
+//! Checks that looking up missing entries does not allocate memory.
+//!
+//! This file contains a single test because the allocation counter is global.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::fs;
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use fatfs::FsOptions;
+
+mod common;
+use common::FAT16_IMG;
+
+const PROBES: usize = 10_000;
+
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::SeqCst);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::SeqCst);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+#[test]
+fn test_exists_misses_do_not_allocate() {
+    let mut img = fs::read(FAT16_IMG).unwrap();
+    let fs = fatfs::FileSystem::new(io::Cursor::new(&mut img), FsOptions::new()).unwrap();
+    let dir = fs.root_dir().create_dir("probe").unwrap();
+    for i in 0..10 {
+        dir.create_file(&format!("existing file {}.txt", i)).unwrap();
+    }
+    dir.create_dir("sub").unwrap();
+    let paths: Vec<String> = (0..PROBES)
+        .map(|i| match i % 4 {
+            0 => format!("missing file {}.txt", i),
+            1 => format!("M{}.TXT", i),
+            2 => format!("sub/missing-{}", i),
+            _ => format!("missing-{}/existing file 1.txt", i),
+        })
+        .collect();
+    assert!(dir.exists("sub").unwrap());
+    assert!(dir.exists("existing file 1.txt").unwrap());
+
+    let before = ALLOCATIONS.load(Ordering::SeqCst);
+    for path in &paths {
+        assert!(!dir.exists(path).unwrap());
+    }
+    // paths resolved from the root directory containing long names too
+    assert!(!fs.root_dir().exists("very/long/path/missing.txt").unwrap());
+    let allocations = ALLOCATIONS.load(Ordering::SeqCst) - before;
+    assert_eq!(allocations, 0);
+}
@@ -0,0 +1,120 @@
+use std::io;
+use std::io::prelude::*;
+
+mod common;
+use common::{open_fs, Dir, FAT12_IMG, FAT16_IMG, FAT32_IMG};
+
+const TEST_STR: &str = "Rust is cool!\n";
+
+fn read_to_string(dir: &Dir, path: &str) -> String {
+    let mut content = String::new();
+    dir.open_file(path).unwrap().read_to_string(&mut content).unwrap();
+    content
+}
+
+#[test]
+fn test_append_interleaved_with_reads() {
+    let fs = open_fs(FAT16_IMG);
+    let root_dir = fs.root_dir();
+    let mut file = root_dir.open_file("short.txt").unwrap();
+    file.set_append(true);
+    let mut buf = [0; 4];
+    file.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"Rust");
+    // the write is done at the end and leaves the position there
+    file.write_all(b"A").unwrap();
+    assert_eq!(file.stream_position().unwrap(), TEST_STR.len() as u64 + 1);
+    // seeks affect only reads
+    file.seek(io::SeekFrom::Start(4)).unwrap();
+    let mut content = String::new();
+    file.read_to_string(&mut content).unwrap();
+    assert_eq!(content, " is cool!\nA");
+    file.seek(io::SeekFrom::Start(0)).unwrap();
+    file.write_all(b"B").unwrap();
+    assert_eq!(file.read(&mut buf).unwrap(), 0);
+    // the append mode can be disabled
+    file.set_append(false);
+    file.seek(io::SeekFrom::Start(0)).unwrap();
+    file.write_all(b"T").unwrap();
+    drop(file);
+    assert_eq!(read_to_string(&root_dir, "short.txt"), "Tust is cool!\nAB");
+}
+
+fn test_append_to_new_file(img_path: &str) {
+    let fs = open_fs(img_path);
+    let root_dir = fs.root_dir();
+    let mut file = root_dir
+        .open_options()
+        .read(true)
+        .append(true)
+        .create(true)
+        .open("app.log")
+        .unwrap();
+    // no cluster is allocated for an empty file
+    assert_eq!(file.extents().count(), 0);
+    let mut expected = String::new();
+    let mut line = String::new();
+    for i in 0..100 {
+        let entry = format!("entry {:03}\n", i);
+        file.write_all(entry.as_bytes()).unwrap();
+        expected.push_str(&entry);
+        // the first line can be read between appends
+        file.seek(io::SeekFrom::Start(0)).unwrap();
+        line.clear();
+        let mut buf = [0; 10];
+        file.read_exact(&mut buf).unwrap();
+        line.push_str(std::str::from_utf8(&buf).unwrap());
+        assert_eq!(line, "entry 000\n");
+    }
+    let len: u64 = file.extents().map(|e| e.unwrap().len).sum();
+    assert_eq!(len, 1000);
+    drop(file);
+    assert_eq!(read_to_string(&root_dir, "app.log"), expected);
+    assert_eq!(root_dir.entry("app.log").unwrap().len(), 1000);
+    assert!(fs.check().unwrap().is_clean());
+}
+
+#[test]
+fn test_append_to_new_file_fat12() {
+    test_append_to_new_file(FAT12_IMG)
+}
+
+#[test]
+fn test_append_to_new_file_fat16() {
+    test_append_to_new_file(FAT16_IMG)
+}
+
+#[test]
+fn test_append_to_new_file_fat32() {
+    test_append_to_new_file(FAT32_IMG)
+}
+
+#[test]
+fn test_append_after_truncate() {
+    let fs = open_fs(FAT16_IMG);
+    let root_dir = fs.root_dir();
+    let mut file = root_dir.open_options().append(true).open("short.txt").unwrap();
+    file.seek(io::SeekFrom::Start(4)).unwrap();
+    file.truncate().unwrap();
+    file.write_all(b"!").unwrap();
+    // set_len does not move the position to the end of file but writes do
+    file.set_len(2).unwrap();
+    assert_eq!(file.stream_position().unwrap(), 2);
+    file.write_all(b"st").unwrap();
+    drop(file);
+    assert_eq!(read_to_string(&root_dir, "short.txt"), "Rust");
+}
+
+#[test]
+fn test_set_append_without_write_access() {
+    let fs = open_fs(FAT16_IMG);
+    let root_dir = fs.root_dir();
+    let mut file = root_dir.open_options().read(true).open("short.txt").unwrap();
+    file.set_append(true);
+    assert!(matches!(
+        fatfs::Write::write(&mut file, b"x"),
+        Err(fatfs::Error::PermissionDenied)
+    ));
+    drop(file);
+    assert_eq!(read_to_string(&root_dir, "short.txt"), TEST_STR);
+}
@@ -0,0 +1,206 @@
+use std::fs;
+use std::io;
+use std::io::prelude::*;
+use std::rc::Rc;
+
+use fatfs::{BufStream, FsOptions, StdIoWrapper};
+
+mod common;
+use common::{init_logger, InstrumentedStream, StreamState, FAT12_IMG, FAT16_IMG, FAT32_IMG};
+
+type Stream = BufStream<StdIoWrapper<InstrumentedStream>, Vec<u8>>;
+
+fn buffered(state: &Rc<StreamState>, capacity: usize) -> Stream {
+    BufStream::with_capacity(StdIoWrapper::new(InstrumentedStream::new(state)), capacity)
+}
+
+fn counting_stream(data: Vec<u8>, capacity: usize) -> (Stream, Rc<StreamState>) {
+    let state = StreamState::new(data);
+    (buffered(&state, capacity), state)
+}
+
+fn into_data(stream: Stream, state: &StreamState) -> Vec<u8> {
+    stream.into_inner().unwrap();
+    state.data()
+}
+
+#[test]
+fn test_dir_iteration_reads_each_sector_once() {
+    let state = StreamState::from_img(FAT16_IMG);
+    {
+        let fs = common::remount(&state);
+        let dir = fs.root_dir().create_dir("big").unwrap();
+        for i in 0..100 {
+            dir.create_file(&format!("F{:03}.TXT", i)).unwrap();
+        }
+        drop(dir);
+        fs.unmount().unwrap();
+    }
+
+    let fs = fatfs::FileSystem::new(buffered(&state, 512), FsOptions::new()).unwrap();
+    // FAT lookups between directory clusters would evict the buffered directory sector
+    fs.prefetch_fat().unwrap();
+    let dir = fs.root_dir().open_dir("big").unwrap();
+    state.clear_ops();
+    // 100 files together with `.` and `..` entries fill 7 sectors
+    assert_eq!(dir.iter().count(), 102);
+    let reads = state.reads();
+    assert_eq!(reads.len(), 7);
+    let mut offsets = reads.iter().map(|(offset, _)| *offset).collect::<Vec<_>>();
+    offsets.sort_unstable();
+    offsets.dedup();
+    assert_eq!(offsets.len(), 7);
+    assert!(reads.iter().all(|(offset, len)| offset % 512 == 0 && *len == 512));
+    assert_eq!(state.writes(), []);
+}
+
+#[test]
+fn test_write_back() {
+    let data = (0..4096).map(|i| i as u8).collect::<Vec<_>>();
+    let (mut stream, state) = counting_stream(data.clone(), 1024);
+    fatfs::Seek::seek(&mut stream, fatfs::SeekFrom::Start(100)).unwrap();
+    fatfs::Write::write_all(&mut stream, b"abc").unwrap();
+    assert_eq!(state.reads(), [(0, 1024)]);
+    let seeks = state.seeks(|_| true);
+
+    // writes and seeks within the buffered block do not access the storage
+    for pos in &[200, 1000, 0] {
+        fatfs::Seek::seek(&mut stream, fatfs::SeekFrom::Start(*pos)).unwrap();
+        fatfs::Write::write_all(&mut stream, b"xy").unwrap();
+    }
+    fatfs::Seek::seek(&mut stream, fatfs::SeekFrom::Current(20)).unwrap();
+    let mut buf = [0; 4];
+    fatfs::Read::read_exact(&mut stream, &mut buf).unwrap();
+    assert_eq!(buf, [22, 23, 24, 25]);
+    assert_eq!(state.reads().len(), 1);
+    assert_eq!(state.writes(), []);
+    assert_eq!(state.seeks(|_| true), seeks);
+
+    // the block is written back when another one is loaded
+    fatfs::Seek::seek(&mut stream, fatfs::SeekFrom::Start(3000)).unwrap();
+    fatfs::Read::read_exact(&mut stream, &mut buf).unwrap();
+    assert_eq!(state.writes(), [(0, 1024)]);
+    assert_eq!(state.reads()[1..], [(2048, 1024)]);
+    fatfs::Write::write_all(&mut stream, b"d").unwrap();
+    fatfs::Write::flush(&mut stream).unwrap();
+    assert_eq!(state.writes()[1..], [(2048, 1024)]);
+    // flushing clean data does not write
+    fatfs::Write::flush(&mut stream).unwrap();
+    assert_eq!(state.writes().len(), 2);
+
+    let mut expected = data;
+    expected[100..103].copy_from_slice(b"abc");
+    for pos in &[200, 1000, 0] {
+        expected[*pos..*pos + 2].copy_from_slice(b"xy");
+    }
+    expected[3004] = b'd';
+    assert_eq!(into_data(stream, &state), expected);
+}
+
+#[test]
+fn test_large_access_bypasses_buffer() {
+    let data = vec![0x11; 8192];
+    let (mut stream, state) = counting_stream(data, 1024);
+    fatfs::Seek::seek(&mut stream, fatfs::SeekFrom::Start(10)).unwrap();
+    fatfs::Write::write_all(&mut stream, b"small").unwrap();
+    // a large write covering the buffered block
+    fatfs::Seek::seek(&mut stream, fatfs::SeekFrom::Start(0)).unwrap();
+    assert_eq!(fatfs::Write::write(&mut stream, &[0x22; 3000]).unwrap(), 3000);
+    let mut buf = vec![0; 4000];
+    fatfs::Seek::seek(&mut stream, fatfs::SeekFrom::Start(0)).unwrap();
+    fatfs::Read::read_exact(&mut stream, &mut buf).unwrap();
+    assert_eq!(&buf[..3000], &[0x22; 3000][..]);
+    assert_eq!(&buf[3000..], &[0x11; 1000][..]);
+    assert_eq!(state.writes(), [(0, 1024), (0, 3000)]);
+}
+
+#[test]
+fn test_stream_end() {
+    let (mut stream, state) = counting_stream(vec![0x11; 2500], 1024);
+    assert_eq!(fatfs::Seek::seek(&mut stream, fatfs::SeekFrom::End(-4)).unwrap(), 2496);
+    let mut buf = [0; 10];
+    assert_eq!(fatfs::Read::read(&mut stream, &mut buf).unwrap(), 4);
+    assert_eq!(fatfs::Read::read(&mut stream, &mut buf).unwrap(), 0);
+    // the buffered block extends the stream
+    fatfs::Write::write_all(&mut stream, b"more").unwrap();
+    assert_eq!(fatfs::Seek::seek(&mut stream, fatfs::SeekFrom::End(0)).unwrap(), 2504);
+    let data = into_data(stream, &state);
+    assert_eq!(data.len(), 2504);
+    assert_eq!(&data[2500..], b"more");
+}
+
+fn test_filesystem(img_path: &str, capacity: usize) {
+    let state = StreamState::from_img(img_path);
+    let mut stream = buffered(&state, capacity);
+    {
+        let fs = fatfs::FileSystem::new(&mut stream, FsOptions::new()).unwrap();
+        let root_dir = fs.root_dir();
+        let dir = root_dir.create_dir("dir").unwrap();
+        for i in 0..20 {
+            let mut file = dir.create_file(&format!("file{}.txt", i)).unwrap();
+            file.write_all(content(i).as_bytes()).unwrap();
+        }
+        drop(dir);
+        root_dir.remove("short.txt").unwrap();
+        drop(root_dir);
+        fs.unmount().unwrap();
+    }
+    // buffered writes are aligned to the buffer size
+    assert!(state
+        .writes()
+        .iter()
+        .all(|(offset, len)| offset % capacity as u64 == 0 && *len % 512 == 0));
+
+    stream.into_inner().unwrap();
+    let fs = common::remount(&state);
+    for i in 0..20 {
+        let mut read_content = String::new();
+        fs.root_dir()
+            .open_file(&format!("dir/file{}.txt", i))
+            .unwrap()
+            .read_to_string(&mut read_content)
+            .unwrap();
+        assert_eq!(read_content, content(i));
+    }
+    assert!(!fs.root_dir().exists("short.txt").unwrap());
+    assert!(fs.check().unwrap().is_clean());
+}
+
+fn content(i: usize) -> String {
+    format!("content of file {}\n", i).repeat(i * 10)
+}
+
+#[test]
+fn test_filesystem_fat12() {
+    test_filesystem(FAT12_IMG, 512);
+    test_filesystem(FAT12_IMG, 4096);
+}
+
+#[test]
+fn test_filesystem_fat16() {
+    test_filesystem(FAT16_IMG, 512);
+    test_filesystem(FAT16_IMG, 4096);
+}
+
+#[test]
+fn test_filesystem_fat32() {
+    test_filesystem(FAT32_IMG, 512);
+    test_filesystem(FAT32_IMG, 4096);
+}
+
+#[test]
+fn test_caller_provided_buffer() {
+    init_logger();
+    let mut buf = [0_u8; 1024];
+    let storage = StdIoWrapper::new(io::Cursor::new(fs::read(FAT16_IMG).unwrap()));
+    let stream = BufStream::with_buffer(storage, &mut buf[..]);
+    assert_eq!(stream.capacity(), 1024);
+    let fs = fatfs::FileSystem::new(stream, FsOptions::new()).unwrap();
+    let mut content = String::new();
+    fs.root_dir()
+        .open_file("short.txt")
+        .unwrap()
+        .read_to_string(&mut content)
+        .unwrap();
+    assert_eq!(content, "Rust is cool!\n");
+}
@@ -0,0 +1,249 @@
+use std::fs;
+use std::io;
+use std::io::prelude::*;
+
+use fatfs::{CheckBudget, CheckFinding, CheckProgress, CheckReport, CheckState, FsOptions, StdIoWrapper};
+
+mod common;
+use common::{mount, open_fs, FileSystem, FAT12_IMG, FAT16_IMG, FAT32_IMG};
+
+fn read_u16(img: &[u8], offset: usize) -> usize {
+    usize::from(u16::from_le_bytes([img[offset], img[offset + 1]]))
+}
+
+/// Raw access to the FAT16 image.
+struct Fat16Image {
+    img: Vec<u8>,
+    fat_offsets: Vec<usize>,
+}
+
+impl Fat16Image {
+    fn new(img: Vec<u8>) -> Self {
+        let bytes_per_sector = read_u16(&img, 11);
+        let reserved_sectors = read_u16(&img, 14);
+        let fats = usize::from(img[16]);
+        let sectors_per_fat = read_u16(&img, 22);
+        let fat_offsets = (0..fats)
+            .map(|i| (reserved_sectors + i * sectors_per_fat) * bytes_per_sector)
+            .collect();
+        Self { img, fat_offsets }
+    }
+
+    fn total_clusters(&self) -> u16 {
+        let bytes_per_sector = read_u16(&self.img, 11);
+        let root_dir_sectors = read_u16(&self.img, 17) * 32 / bytes_per_sector;
+        let first_data_sector = self.fat_offsets[self.fat_offsets.len() - 1] / bytes_per_sector
+            + read_u16(&self.img, 22)
+            + root_dir_sectors;
+        (read_u16(&self.img, 19) - first_data_sector) as u16
+    }
+
+    /// Returns the offset of the short name entry.
+    fn find_entry(&self, short_name: &[u8; 11]) -> usize {
+        (0..self.img.len())
+            .step_by(32)
+            .find(|&i| &self.img[i..i + 11] == short_name)
+            .unwrap()
+    }
+
+    fn first_cluster(&self, short_name: &[u8; 11]) -> u16 {
+        read_u16(&self.img, self.find_entry(short_name) + 26) as u16
+    }
+
+    fn set_size(&mut self, short_name: &[u8; 11], size: u32) {
+        let offset = self.find_entry(short_name) + 28;
+        self.img[offset..offset + 4].copy_from_slice(&size.to_le_bytes());
+    }
+
+    fn fat_entry(&self, cluster: u16) -> u16 {
+        read_u16(&self.img, self.fat_offsets[0] + usize::from(cluster) * 2) as u16
+    }
+
+    fn set_fat_entry(&mut self, cluster: u16, value: u16) {
+        for &fat_offset in &self.fat_offsets {
+            let offset = fat_offset + usize::from(cluster) * 2;
+            self.img[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+        }
+    }
+}
+
+fn path(s: &str) -> String {
+    s.to_string()
+}
+
+/// Returns a FAT16 image with known problems and the expected findings.
+fn corrupted_fat16_img() -> (Vec<u8>, Vec<CheckFinding>) {
+    let mut img = fs::read(FAT16_IMG).unwrap();
+    {
+        let fs = fatfs::FileSystem::new(io::Cursor::new(&mut img), FsOptions::new()).unwrap();
+        let root_dir = fs.root_dir();
+        let dir = root_dir.create_dir("dir").unwrap();
+        for name in &["cross1.txt", "cross2.txt", "broken.txt", "size.txt"] {
+            dir.create_file(name).unwrap().write_all(&[b'x'; 1024]).unwrap();
+        }
+        root_dir.create_file("empty.txt").unwrap();
+    }
+    let mut img = Fat16Image::new(img);
+    // second chain joins the first one: its own second cluster becomes lost
+    let cross1 = img.first_cluster(b"CROSS1  TXT");
+    let cross2 = img.first_cluster(b"CROSS2  TXT");
+    let cross1_second = img.fat_entry(cross1);
+    let cross2_second = img.fat_entry(cross2);
+    img.set_fat_entry(cross2, cross1_second);
+    // chain leads to a free cluster
+    let broken = img.first_cluster(b"BROKEN  TXT");
+    let broken_second = img.fat_entry(broken);
+    img.set_fat_entry(broken_second, 0);
+    img.set_size(b"SIZE    TXT", 5000);
+    img.set_size(b"EMPTY   TXT", 10);
+    // a lost chain at the end of the volume
+    let total_clusters = img.total_clusters();
+    let last = total_clusters + 1;
+    img.set_fat_entry(last - 2, last - 1);
+    img.set_fat_entry(last - 1, last);
+    img.set_fat_entry(last, 0xFFFF);
+    let mut lost = vec![(u32::from(cross2_second), 1), (u32::from(last - 2), 3)];
+    lost.sort();
+    let mut expected = vec![
+        CheckFinding::CrossLinkedCluster {
+            path: path("dir/cross2.txt"),
+            cluster: u32::from(cross1_second),
+        },
+        CheckFinding::BrokenChain {
+            path: path("dir/broken.txt"),
+            cluster: u32::from(broken_second),
+        },
+        CheckFinding::SizeMismatch {
+            path: path("dir/size.txt"),
+            size: 5000,
+            clusters: 2,
+        },
+        CheckFinding::SizeMismatch {
+            path: path("empty.txt"),
+            size: 10,
+            clusters: 0,
+        },
+    ];
+    expected.extend(
+        lost.into_iter()
+            .map(|(first_cluster, count)| CheckFinding::LostClusters { first_cluster, count }),
+    );
+    (img.img, expected)
+}
+
+fn assert_same_findings(report: &CheckReport, expected: &[CheckFinding]) {
+    for finding in expected {
+        assert!(
+            report.findings().contains(finding),
+            "{:?} not found in {:?}",
+            finding,
+            report
+        );
+    }
+    assert_eq!(report.findings().len(), expected.len(), "{:?}", report);
+}
+
+/// Runs the incremental check, optionally saving and restoring the state between calls.
+fn check_incrementally(fs: &FileSystem, budget: CheckBudget, serialize: bool) -> (CheckReport, u32) {
+    let mut state = CheckState::new();
+    let mut calls = 0;
+    let mut last_fraction = 0.0;
+    loop {
+        calls += 1;
+        match fs.check_incremental(&mut state, budget).unwrap() {
+            CheckProgress::InProgress { fraction } => {
+                assert!(
+                    fraction >= last_fraction && fraction <= 1.0,
+                    "{} after {}",
+                    fraction,
+                    last_fraction
+                );
+                last_fraction = fraction;
+            }
+            CheckProgress::Done(report) => {
+                assert!(state.is_done());
+                return (report, calls);
+            }
+        }
+        if serialize {
+            let mut buf = Vec::new();
+            state.serialize(&mut StdIoWrapper::from(&mut buf)).unwrap();
+            let restored = CheckState::deserialize(&mut StdIoWrapper::from(io::Cursor::new(&buf))).unwrap();
+            assert_eq!(restored, state);
+            state = restored;
+        }
+    }
+}
+
+#[test]
+fn test_check_clean_images() {
+    for img_path in &[FAT12_IMG, FAT16_IMG, FAT32_IMG] {
+        let fs = open_fs(img_path);
+        let report = fs.check().unwrap();
+        assert!(report.is_clean(), "{}: {:?}", img_path, report);
+        let summary = fs.usage_summary().unwrap();
+        assert_eq!(report.files(), summary.files());
+        assert_eq!(report.dirs(), summary.dirs());
+    }
+}
+
+#[test]
+fn test_check_corrupted_image() {
+    let (img, expected) = corrupted_fat16_img();
+    let report = mount(img).check().unwrap();
+    assert_same_findings(&report, &expected);
+}
+
+#[test]
+fn test_check_incremental_matches_one_shot() {
+    let (img, expected) = corrupted_fat16_img();
+    let fs = mount(img);
+    let report = fs.check().unwrap();
+    assert_same_findings(&report, &expected);
+    let budgets = [
+        CheckBudget::unlimited().max_entries(1).max_clusters(1),
+        CheckBudget::unlimited().max_entries(2).max_clusters(3),
+        CheckBudget::unlimited().max_entries(1),
+        CheckBudget::unlimited().max_clusters(1),
+        CheckBudget::unlimited().max_entries(0).max_clusters(0),
+    ];
+    for &budget in &budgets {
+        for &serialize in &[false, true] {
+            let (incremental_report, calls) = check_incrementally(&fs, budget, serialize);
+            assert_eq!(incremental_report, report, "{:?}", budget);
+            assert!(calls > 10);
+        }
+    }
+}
+
+#[test]
+fn test_check_incremental_fat32() {
+    let fs = open_fs(FAT32_IMG);
+    let report = fs.check().unwrap();
+    let budget = CheckBudget::unlimited().max_entries(1).max_clusters(1000);
+    assert_eq!(check_incrementally(&fs, budget, true).0, report);
+}
+
+#[test]
+fn test_check_state_for_other_fs() {
+    let fs = open_fs(FAT16_IMG);
+    let mut state = CheckState::new();
+    let budget = CheckBudget::unlimited().max_entries(1);
+    fs.check_incremental(&mut state, budget).unwrap();
+    let other_fs = open_fs(FAT32_IMG);
+    assert!(matches!(
+        other_fs.check_incremental(&mut state, budget),
+        Err(fatfs::Error::InvalidInput)
+    ));
+}
+
+#[test]
+fn test_check_state_invalid_data() {
+    let mut buf = Vec::new();
+    CheckState::new().serialize(&mut StdIoWrapper::from(&mut buf)).unwrap();
+    buf[0] ^= 1;
+    assert!(matches!(
+        CheckState::deserialize(&mut StdIoWrapper::from(io::Cursor::new(&buf))),
+        Err(fatfs::Error::InvalidInput)
+    ));
+}
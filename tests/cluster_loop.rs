@@ -0,0 +1,175 @@
+use std::fs;
+use std::io;
+use std::io::prelude::*;
+
+use fatfs::{FatType, FsOptions};
+
+mod common;
+use common::{init_logger, mount, mount_with_options, FAT16_IMG, FAT32_IMG};
+
+const LOOP_DIR_SFN: &[u8; 11] = b"LOOP       ";
+
+fn read_u16(img: &[u8], offset: usize) -> u32 {
+    u32::from(u16::from_le_bytes([img[offset], img[offset + 1]]))
+}
+
+/// Raw access to the FAT of a FAT16 or FAT32 image.
+struct FatImage {
+    img: Vec<u8>,
+    fat_type: FatType,
+    fat_offsets: Vec<usize>,
+}
+
+impl FatImage {
+    fn new(img: Vec<u8>, fat_type: FatType) -> Self {
+        let bytes_per_sector = read_u16(&img, 11) as usize;
+        let reserved_sectors = read_u16(&img, 14) as usize;
+        let fats = usize::from(img[16]);
+        let sectors_per_fat = if fat_type == FatType::Fat32 {
+            (read_u16(&img, 36) | read_u16(&img, 38) << 16) as usize
+        } else {
+            read_u16(&img, 22) as usize
+        };
+        let fat_offsets = (0..fats)
+            .map(|i| (reserved_sectors + i * sectors_per_fat) * bytes_per_sector)
+            .collect();
+        Self {
+            img,
+            fat_type,
+            fat_offsets,
+        }
+    }
+
+    fn entry_size(&self) -> usize {
+        if self.fat_type == FatType::Fat32 {
+            4
+        } else {
+            2
+        }
+    }
+
+    fn first_cluster(&self, short_name: &[u8; 11]) -> u32 {
+        let offset = (0..self.img.len())
+            .step_by(32)
+            .find(|&i| &self.img[i..i + 11] == short_name)
+            .unwrap();
+        read_u16(&self.img, offset + 26) | read_u16(&self.img, offset + 20) << 16
+    }
+
+    fn fat_entry(&self, cluster: u32) -> u32 {
+        let offset = self.fat_offsets[0] + cluster as usize * self.entry_size();
+        let mut bytes = [0_u8; 4];
+        bytes[..self.entry_size()].copy_from_slice(&self.img[offset..offset + self.entry_size()]);
+        u32::from_le_bytes(bytes) & 0x0FFF_FFFF
+    }
+
+    fn set_fat_entry(&mut self, cluster: u32, value: u32) {
+        let size = self.entry_size();
+        for &fat_offset in &self.fat_offsets {
+            let offset = fat_offset + cluster as usize * size;
+            self.img[offset..offset + size].copy_from_slice(&value.to_le_bytes()[..size]);
+        }
+    }
+}
+
+/// Returns an image with directory "loop" filling exactly two clusters without an end marker and the second cluster
+/// linked back to the first one.
+fn loop_dir_image(img_path: &str) -> (Vec<u8>, FatType) {
+    init_logger();
+    let mut img = fs::read(img_path).unwrap();
+    let fat_type = {
+        let fs = fatfs::FileSystem::new(io::Cursor::new(&mut img), FsOptions::new()).unwrap();
+        let dir = fs.root_dir().create_dir("loop").unwrap();
+        // "." and ".." take one entry and every file takes a LFN and a SFN entry (the space requires a long name)
+        let num_files = fs.cluster_size() * 2 / 32 / 2 - 1;
+        for i in 0..num_files {
+            dir.create_file(&format!("F {}", i)).unwrap();
+        }
+        fs.fat_type()
+    };
+    let mut image = FatImage::new(img, fat_type);
+    let first = image.first_cluster(LOOP_DIR_SFN);
+    let second = image.fat_entry(first);
+    // the directory is full - no third cluster has been allocated for an end marker
+    assert!(image.fat_entry(second) >= 0xFFF8);
+    image.set_fat_entry(second, first);
+    (image.img, fat_type)
+}
+
+fn assert_corrupted<T>(result: Result<T, fatfs::Error<io::Error>>) {
+    match result {
+        Err(fatfs::Error::CorruptedFileSystem) => {}
+        Err(err) => panic!("unexpected error {:?}", err),
+        Ok(_) => panic!("cluster loop has not been detected"),
+    }
+}
+
+fn test_dir_cluster_loop(img_path: &str) {
+    let (img, _) = loop_dir_image(img_path);
+    let fs = mount(img);
+    let root_dir = fs.root_dir();
+    let dir = root_dir.open_dir("loop").unwrap();
+    // entries of both clusters are returned once before the loop is detected
+    let mut names = Vec::new();
+    let mut result = Ok(());
+    for r in dir.iter() {
+        match r {
+            Ok(e) => names.push(e.file_name()),
+            Err(err) => {
+                result = Err(err);
+                break;
+            }
+        }
+    }
+    assert_corrupted(result);
+    assert_eq!(names.len() as u32, fs.cluster_size() * 2 / 32 / 2 + 1);
+    // lookups and free entries search read the whole directory
+    assert_corrupted(dir.open_file("missing"));
+    assert_corrupted(dir.create_file("new"));
+    assert_corrupted(root_dir.create_dir("loop/new"));
+    assert_corrupted(dir.entry_count());
+}
+
+#[test]
+fn test_dir_cluster_loop_fat16() {
+    test_dir_cluster_loop(FAT16_IMG)
+}
+
+#[test]
+fn test_dir_cluster_loop_fat32() {
+    test_dir_cluster_loop(FAT32_IMG)
+}
+
+#[test]
+fn test_dir_cluster_loop_with_raised_entries_limit() {
+    let (img, _) = loop_dir_image(FAT32_IMG);
+    let limits = fatfs::FsLimits {
+        max_dir_entries: u32::MAX,
+        ..fatfs::FsLimits::default()
+    };
+    let fs = mount_with_options(img, FsOptions::new().limits(limits));
+    let dir = fs.root_dir().open_dir("loop").unwrap();
+    assert_corrupted(dir.iter().find(Result::is_err).unwrap());
+    assert_corrupted(dir.create_file("new"));
+}
+
+#[test]
+fn test_file_cluster_self_loop() {
+    init_logger();
+    let mut img = fs::read(FAT16_IMG).unwrap();
+    {
+        let fs = fatfs::FileSystem::new(io::Cursor::new(&mut img), FsOptions::new()).unwrap();
+        let mut file = fs.root_dir().create_file("loop.bin").unwrap();
+        file.write_all(&vec![1; fs.cluster_size() as usize * 3]).unwrap();
+    }
+    let mut image = FatImage::new(img, FatType::Fat16);
+    let first = image.first_cluster(b"LOOP    BIN");
+    let second = image.fat_entry(first);
+    image.set_fat_entry(second, second);
+    let fs = mount(image.img);
+    let mut file = fs.root_dir().open_file("loop.bin").unwrap();
+    let mut buf = Vec::new();
+    let err = file.read_to_end(&mut buf).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    assert_eq!(buf.len() as u32, fs.cluster_size() * 2);
+}
@@ -0,0 +1,355 @@
+//! Helpers shared by integration tests.
+// every test crate includes this module but uses only some of the helpers
+#![allow(dead_code)]
+// `div_ceil` and `io::Error::other` are not available in the minimal supported Rust version
+#![allow(unknown_lints, clippy::manual_div_ceil, clippy::io_other_error)]
+
+use std::cell::{Cell, RefCell};
+use std::fs;
+use std::io;
+use std::io::prelude::*;
+use std::ops::Range;
+use std::rc::Rc;
+
+use fatfs::{DefaultTimeProvider, FsOptions, LossyOemCpConverter, StdIoWrapper};
+
+pub const FAT12_IMG: &str = "resources/fat12.img";
+pub const FAT16_IMG: &str = "resources/fat16.img";
+pub const FAT32_IMG: &str = "resources/fat32.img";
+
+/// An in-memory copy of an image.
+pub type Storage = StdIoWrapper<io::Cursor<Vec<u8>>>;
+pub type FileSystem = fatfs::FileSystem<Storage, DefaultTimeProvider, LossyOemCpConverter>;
+pub type Dir<'a> = fatfs::Dir<'a, Storage, DefaultTimeProvider, LossyOemCpConverter>;
+
+pub fn init_logger() {
+    let _ = env_logger::builder().is_test(true).try_init();
+}
+
+/// Mounts an in-memory copy of the image so the image file is never modified.
+pub fn open_fs(img_path: &str) -> FileSystem {
+    open_fs_with_options(img_path, FsOptions::new())
+}
+
+pub fn open_fs_with_options(
+    img_path: &str,
+    options: FsOptions<DefaultTimeProvider, LossyOemCpConverter>,
+) -> FileSystem {
+    mount_with_options(fs::read(img_path).unwrap(), options)
+}
+
+/// Mounts an image modified by the test.
+pub fn mount(img: Vec<u8>) -> FileSystem {
+    mount_with_options(img, FsOptions::new())
+}
+
+pub fn mount_with_options(img: Vec<u8>, options: FsOptions<DefaultTimeProvider, LossyOemCpConverter>) -> FileSystem {
+    init_logger();
+    FileSystem::new(io::Cursor::new(img), options).unwrap()
+}
+
+/// Offsets of the filesystem regions of an image.
+pub struct Layout {
+    pub bytes_per_sector: u64,
+    pub fat_start: u64,
+    /// Size of a single FAT copy.
+    pub fat_size: u64,
+    pub fats: u64,
+    pub root_dir_start: u64,
+    pub data_start: u64,
+}
+
+impl Layout {
+    /// Reads the layout from the Boot Sector of an image.
+    pub fn read(img: &[u8]) -> Self {
+        let u16_at = |pos: usize| u64::from(u16::from_le_bytes([img[pos], img[pos + 1]]));
+        let bytes_per_sector = u16_at(11);
+        let reserved_sectors = u16_at(14);
+        let fats = u64::from(img[16]);
+        let root_entries = u16_at(17);
+        let sectors_per_fat = match u16_at(22) {
+            0 => u64::from(u32::from_le_bytes([img[36], img[37], img[38], img[39]])),
+            n => n,
+        };
+        let root_dir_sectors = (root_entries * 32 + bytes_per_sector - 1) / bytes_per_sector;
+        let root_dir_start = (reserved_sectors + fats * sectors_per_fat) * bytes_per_sector;
+        Self {
+            bytes_per_sector,
+            fat_start: reserved_sectors * bytes_per_sector,
+            fat_size: sectors_per_fat * bytes_per_sector,
+            fats,
+            root_dir_start,
+            data_start: root_dir_start + root_dir_sectors * bytes_per_sector,
+        }
+    }
+
+    /// Returns the range of offsets occupied by all FAT copies.
+    pub fn fat_region(&self) -> Range<u64> {
+        self.fat_start..self.fat_start + self.fats * self.fat_size
+    }
+}
+
+/// A storage call recorded by `InstrumentedStream`.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Op {
+    /// A read call with the stream position and the buffer length.
+    Read { offset: u64, len: usize },
+    /// A write call with the stream position and the buffer length.
+    Write { offset: u64, len: usize },
+    /// A seek call with its target.
+    Seek(io::SeekFrom),
+}
+
+/// A write failure injected by `InstrumentedStream`.
+#[derive(Clone, Debug)]
+pub enum WriteFault {
+    /// All writes succeed.
+    None,
+    /// Writes fail with an I/O error after the given number of successful write calls.
+    FailAfterCalls(u32),
+    /// Writes fail with an I/O error after the given number of bytes has been written. The last write before the
+    /// failure can be partial.
+    FailAfterBytes(usize),
+    /// Writes are silently dropped after the given number of successful write calls (like after a power cut).
+    DropAfterCalls(u32),
+    /// The first write touching the range is silently dropped (like by a counterfeit SD card).
+    DropOnce(Range<u64>),
+    /// Writes panic (for storage which must never be written).
+    Panic,
+}
+
+/// State of `InstrumentedStream` objects shared with the test.
+pub struct StreamState {
+    /// The storage content. It can be modified while a filesystem is mounted (e.g. truncated).
+    pub data: RefCell<Vec<u8>>,
+    ops: RefCell<Vec<Op>>,
+    dropped_writes: RefCell<Vec<Range<u64>>>,
+    write_fault: RefCell<WriteFault>,
+    forward_only: Cell<bool>,
+}
+
+impl StreamState {
+    pub fn new(data: Vec<u8>) -> Rc<Self> {
+        init_logger();
+        Rc::new(Self {
+            data: RefCell::new(data),
+            ops: RefCell::new(Vec::new()),
+            dropped_writes: RefCell::new(Vec::new()),
+            write_fault: RefCell::new(WriteFault::None),
+            forward_only: Cell::new(false),
+        })
+    }
+
+    /// Creates a state containing an in-memory copy of the image.
+    pub fn from_img(img_path: &str) -> Rc<Self> {
+        Self::new(fs::read(img_path).unwrap())
+    }
+
+    pub fn set_write_fault(&self, fault: WriteFault) {
+        *self.write_fault.borrow_mut() = fault;
+    }
+
+    /// If enabled seeks fail unless they only query the current position.
+    pub fn set_forward_only(&self, enabled: bool) {
+        self.forward_only.set(enabled);
+    }
+
+    /// Returns a copy of the storage content.
+    pub fn data(&self) -> Vec<u8> {
+        self.data.borrow().clone()
+    }
+
+    pub fn ops(&self) -> Vec<Op> {
+        self.ops.borrow().clone()
+    }
+
+    pub fn clear_ops(&self) {
+        self.ops.borrow_mut().clear();
+    }
+
+    /// Returns `(offset, len)` pairs of all read calls.
+    pub fn reads(&self) -> Vec<(u64, usize)> {
+        let ops = self.ops.borrow();
+        ops.iter()
+            .filter_map(|op| match *op {
+                Op::Read { offset, len } => Some((offset, len)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Returns `(offset, len)` pairs of all write calls including failed and dropped ones.
+    pub fn writes(&self) -> Vec<(u64, usize)> {
+        let ops = self.ops.borrow();
+        ops.iter()
+            .filter_map(|op| match *op {
+                Op::Write { offset, len } => Some((offset, len)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Returns the number of seek calls matching `filter`.
+    pub fn seeks(&self, filter: impl Fn(io::SeekFrom) -> bool) -> usize {
+        let ops = self.ops.borrow();
+        ops.iter()
+            .filter(|op| match **op {
+                Op::Seek(pos) => filter(pos),
+                _ => false,
+            })
+            .count()
+    }
+
+    /// Returns the number of bytes requested by all read calls.
+    pub fn bytes_read(&self) -> u64 {
+        self.reads().iter().map(|(_, len)| *len as u64).sum()
+    }
+
+    /// Returns the number of read calls touching `range`.
+    pub fn reads_in(&self, range: &Range<u64>) -> usize {
+        self.reads().into_iter().filter(|call| overlaps(*call, range)).count()
+    }
+
+    /// Returns the number of write calls touching `range`.
+    pub fn writes_in(&self, range: &Range<u64>) -> usize {
+        self.writes().into_iter().filter(|call| overlaps(*call, range)).count()
+    }
+
+    /// Returns ranges of writes dropped because of `WriteFault::DropAfterCalls` or `WriteFault::DropOnce`.
+    pub fn dropped_writes(&self) -> Vec<Range<u64>> {
+        self.dropped_writes.borrow().clone()
+    }
+}
+
+fn overlaps((offset, len): (u64, usize), range: &Range<u64>) -> bool {
+    offset < range.end && offset + len as u64 > range.start
+}
+
+/// An in-memory storage recording all calls and optionally injecting write failures.
+///
+/// All streams created from the same `StreamState` share the content so a volume can be remounted.
+pub struct InstrumentedStream {
+    state: Rc<StreamState>,
+    pos: u64,
+}
+
+impl InstrumentedStream {
+    pub fn new(state: &Rc<StreamState>) -> Self {
+        Self {
+            state: Rc::clone(state),
+            pos: 0,
+        }
+    }
+
+    fn io_error(msg: &str) -> io::Error {
+        io::Error::new(io::ErrorKind::Other, msg)
+    }
+
+    /// Applies the write fault and returns the number of bytes which should be written.
+    fn check_write_fault(&self, len: usize) -> io::Result<Option<usize>> {
+        let mut fault = self.state.write_fault.borrow_mut();
+        let write_len = match *fault {
+            WriteFault::None => Some(len),
+            WriteFault::FailAfterCalls(0) | WriteFault::FailAfterBytes(0) => {
+                return Err(Self::io_error("injected write failure"))
+            }
+            WriteFault::FailAfterCalls(ref mut n) => {
+                *n -= 1;
+                Some(len)
+            }
+            WriteFault::FailAfterBytes(ref mut n) => {
+                let write_len = len.min(*n);
+                *n -= write_len;
+                Some(write_len)
+            }
+            WriteFault::DropAfterCalls(0) => None,
+            WriteFault::DropAfterCalls(ref mut n) => {
+                *n -= 1;
+                Some(len)
+            }
+            WriteFault::DropOnce(ref range) if overlaps((self.pos, len), range) => {
+                *fault = WriteFault::None;
+                None
+            }
+            WriteFault::DropOnce(_) => Some(len),
+            WriteFault::Panic => panic!("write to protected storage at offset {}", self.pos),
+        };
+        Ok(write_len)
+    }
+}
+
+impl Read for InstrumentedStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.state.ops.borrow_mut().push(Op::Read {
+            offset: self.pos,
+            len: buf.len(),
+        });
+        let data = self.state.data.borrow();
+        let mut cursor = io::Cursor::new(&data[..]);
+        cursor.set_position(self.pos);
+        let n = cursor.read(buf)?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl Write for InstrumentedStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.state.ops.borrow_mut().push(Op::Write {
+            offset: self.pos,
+            len: buf.len(),
+        });
+        let len = match self.check_write_fault(buf.len())? {
+            Some(len) => len,
+            None => {
+                let end = self.pos + buf.len() as u64;
+                self.state.dropped_writes.borrow_mut().push(self.pos..end);
+                self.pos = end;
+                return Ok(buf.len());
+            }
+        };
+        let mut data = self.state.data.borrow_mut();
+        let mut cursor = io::Cursor::new(&mut *data);
+        cursor.set_position(self.pos);
+        let n = cursor.write(&buf[..len])?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Seek for InstrumentedStream {
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        self.state.ops.borrow_mut().push(Op::Seek(pos));
+        if self.state.forward_only.get() && pos != io::SeekFrom::Current(0) {
+            return Err(Self::io_error("stream is not seekable"));
+        }
+        let data = self.state.data.borrow();
+        let mut cursor = io::Cursor::new(&data[..]);
+        cursor.set_position(self.pos);
+        self.pos = cursor.seek(pos)?;
+        Ok(self.pos)
+    }
+}
+
+pub type InstrumentedStorage = StdIoWrapper<InstrumentedStream>;
+pub type InstrumentedFs = fatfs::FileSystem<InstrumentedStorage, DefaultTimeProvider, LossyOemCpConverter>;
+pub type InstrumentedDir<'a> = fatfs::Dir<'a, InstrumentedStorage, DefaultTimeProvider, LossyOemCpConverter>;
+
+/// Mounts an instrumented in-memory copy of the image.
+pub fn open_instrumented_fs(
+    img_path: &str,
+    options: FsOptions<DefaultTimeProvider, LossyOemCpConverter>,
+) -> (InstrumentedFs, Rc<StreamState>) {
+    let state = StreamState::from_img(img_path);
+    let fs = InstrumentedFs::new(InstrumentedStream::new(&state), options).unwrap();
+    (fs, state)
+}
+
+/// Mounts the filesystem stored in `state` again.
+pub fn remount(state: &Rc<StreamState>) -> InstrumentedFs {
+    InstrumentedFs::new(InstrumentedStream::new(state), FsOptions::new()).unwrap()
+}
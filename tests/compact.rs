@@ -0,0 +1,125 @@
+use std::io::prelude::*;
+
+use fatfs::FileAttributes;
+
+mod common;
+use common::{open_fs, Dir, FAT12_IMG, FAT16_IMG, FAT32_IMG};
+
+/// Returns everything stored in entries of `dir` except their positions.
+fn entries_data(dir: &Dir) -> Vec<(String, String, u64, FileAttributes, Option<fatfs::DateTime>, u32)> {
+    dir.iter()
+        .map(|r| r.unwrap())
+        .map(|e| {
+            (
+                e.file_name(),
+                e.short_file_name(),
+                e.len(),
+                e.attributes(),
+                e.modification_time(),
+                e.slot_count(),
+            )
+        })
+        .collect()
+}
+
+fn read_to_string(dir: &Dir, path: &str) -> String {
+    let mut content = String::new();
+    dir.open_file(path).unwrap().read_to_string(&mut content).unwrap();
+    content
+}
+
+fn file_name(i: usize) -> String {
+    format!("Long File Name {}.txt", i)
+}
+
+fn test_compact(img_path: &str) {
+    let fs = open_fs(img_path);
+    let root_dir = fs.root_dir();
+    let dir = root_dir.create_dir("churn").unwrap();
+    // 60 entries taking 3 slots each need 12 clusters
+    for i in 0..60 {
+        dir.create_file(&file_name(i))
+            .unwrap()
+            .write_all(file_name(i).as_bytes())
+            .unwrap();
+    }
+    dir.create_dir("sub").unwrap().create_file("nested.txt").unwrap();
+    dir.create_file("short.txt").unwrap();
+    for i in 0..60 {
+        if i % 20 != 7 {
+            dir.remove(&file_name(i)).unwrap();
+        }
+    }
+    let free_before = fs.stats().unwrap().free_clusters();
+    let data_before = entries_data(&dir);
+    let positions_before: Vec<_> = dir.iter().map(|r| r.unwrap().entry_pos()).collect();
+
+    dir.compact().unwrap();
+
+    assert_eq!(entries_data(&dir), data_before);
+    // `.` and `..` are not moved, other entries are moved towards the beginning
+    let positions: Vec<_> = dir.iter().map(|r| r.unwrap().entry_pos()).collect();
+    assert_eq!(positions[..2], positions_before[..2]);
+    assert!(positions[2..].iter().zip(&positions_before[2..]).all(|(a, b)| a < b));
+    // 2 + 3 * 3 + 2 + 1 slots fit in one cluster
+    assert_eq!(fs.stats().unwrap().free_clusters(), free_before + 11);
+    for i in &[7, 27, 47] {
+        assert_eq!(read_to_string(&dir, &file_name(*i)), file_name(*i));
+    }
+    assert_eq!(read_to_string(&dir, "sub/../short.txt"), "");
+    assert!(dir.open_file("sub/nested.txt").is_ok());
+    assert!(fs.check().unwrap().is_clean());
+
+    // the directory can grow again
+    dir.create_file(&file_name(100)).unwrap();
+    assert_eq!(dir.iter().count(), data_before.len() + 1);
+    assert!(fs.check().unwrap().is_clean());
+}
+
+#[test]
+fn test_compact_fat12() {
+    test_compact(FAT12_IMG)
+}
+
+#[test]
+fn test_compact_fat16() {
+    test_compact(FAT16_IMG)
+}
+
+#[test]
+fn test_compact_fat32() {
+    test_compact(FAT32_IMG)
+}
+
+fn test_compact_root_dir(img_path: &str) {
+    let fs = open_fs(img_path);
+    let root_dir = fs.root_dir();
+    root_dir.remove("short.txt").unwrap();
+    let data_before = entries_data(&root_dir);
+    root_dir.compact().unwrap();
+    assert_eq!(entries_data(&root_dir), data_before);
+    assert_eq!(read_to_string(&root_dir, "very/long/path/test.txt"), "Rust is cool!\n");
+    assert!(fs.check().unwrap().is_clean());
+}
+
+#[test]
+fn test_compact_root_dir_fat16() {
+    test_compact_root_dir(FAT16_IMG)
+}
+
+#[test]
+fn test_compact_root_dir_fat32() {
+    test_compact_root_dir(FAT32_IMG)
+}
+
+#[test]
+fn test_compact_without_deleted_entries() {
+    let fs = open_fs(FAT16_IMG);
+    let dir = fs.root_dir().open_dir("very").unwrap();
+    let data_before = entries_data(&dir);
+    let free_before = fs.stats().unwrap().free_clusters();
+    dir.compact().unwrap();
+    assert_eq!(entries_data(&dir), data_before);
+    assert_eq!(fs.stats().unwrap().free_clusters(), free_before);
+    assert!(fs.check().unwrap().is_clean());
+}
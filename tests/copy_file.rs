@@ -0,0 +1,117 @@
+use std::io::prelude::*;
+
+mod common;
+use common::{open_fs, Dir, FAT12_IMG, FAT16_IMG, FAT32_IMG};
+
+fn read_file(dir: &Dir, path: &str) -> Vec<u8> {
+    let mut content = Vec::new();
+    dir.open_file(path).unwrap().read_to_end(&mut content).unwrap();
+    content
+}
+
+fn test_copy_file(img_path: &str) {
+    let fs = open_fs(img_path);
+    let root_dir = fs.root_dir();
+    let long = read_file(&root_dir, "long.txt");
+    assert!(long.len() > fs.cluster_size() as usize);
+
+    assert_eq!(
+        root_dir.copy_file("long.txt", &root_dir, "long copy.txt").unwrap(),
+        long.len() as u64
+    );
+    assert_eq!(read_file(&root_dir, "long copy.txt"), long);
+    assert_eq!(root_dir.entry("long copy.txt").unwrap().len(), long.len() as u64);
+
+    // copy to another directory
+    let dst_dir = root_dir.open_dir("very/long").unwrap();
+    assert_eq!(
+        root_dir
+            .copy_file("very/long/path/test.txt", &dst_dir, "copy.txt")
+            .unwrap(),
+        14
+    );
+    assert_eq!(read_file(&dst_dir, "copy.txt"), b"Rust is cool!\n");
+
+    // zero-length file
+    root_dir.create_file("empty.txt").unwrap();
+    assert_eq!(
+        root_dir
+            .copy_file("empty.txt", &dst_dir, "path/empty copy.txt")
+            .unwrap(),
+        0
+    );
+    assert_eq!(read_file(&root_dir, "very/long/path/empty copy.txt"), b"");
+
+    // source is not modified and both files have separate clusters
+    assert_eq!(read_file(&root_dir, "long.txt"), long);
+    assert!(fs.check().unwrap().is_clean());
+}
+
+#[test]
+fn test_copy_file_fat12() {
+    test_copy_file(FAT12_IMG)
+}
+
+#[test]
+fn test_copy_file_fat16() {
+    test_copy_file(FAT16_IMG)
+}
+
+#[test]
+fn test_copy_file_fat32() {
+    test_copy_file(FAT32_IMG)
+}
+
+#[test]
+fn test_copy_file_errors() {
+    let fs = open_fs(FAT16_IMG);
+    let root_dir = fs.root_dir();
+    assert!(matches!(
+        root_dir.copy_file("short.txt", &root_dir, "LONG.TXT"),
+        Err(fatfs::Error::AlreadyExists)
+    ));
+    assert!(matches!(
+        root_dir.copy_file("short.txt", &root_dir, "very"),
+        Err(fatfs::Error::AlreadyExists)
+    ));
+    assert!(matches!(
+        root_dir.copy_file("very", &root_dir, "very copy"),
+        Err(fatfs::Error::InvalidInput)
+    ));
+    assert!(matches!(
+        root_dir.copy_file("missing.txt", &root_dir, "copy.txt"),
+        Err(fatfs::Error::NotFound { .. })
+    ));
+    assert!(matches!(
+        root_dir.copy_file("short.txt", &root_dir, "missing/copy.txt"),
+        Err(fatfs::Error::NotFound { .. })
+    ));
+    assert!(matches!(
+        root_dir.copy_file("short.txt", &root_dir, "/"),
+        Err(fatfs::Error::InvalidInput)
+    ));
+    assert_eq!(read_file(&root_dir, "long.txt").len(), 14000);
+    assert!(!root_dir.exists("very copy").unwrap());
+    assert!(!root_dir.exists("copy.txt").unwrap());
+    assert!(fs.check().unwrap().is_clean());
+}
+
+#[test]
+fn test_copy_file_not_enough_space() {
+    let fs = open_fs(FAT12_IMG);
+    let root_dir = fs.root_dir();
+    let cluster_size = fs.cluster_size() as usize;
+    let free_clusters = fs.stats().unwrap().free_clusters() as usize;
+    let data = vec![0xC3_u8; (free_clusters / 2 + 1) * cluster_size];
+    root_dir.create_file("big.bin").unwrap().write_all(&data).unwrap();
+    let free_clusters = fs.stats().unwrap().free_clusters();
+
+    assert!(matches!(
+        root_dir.copy_file("big.bin", &root_dir, "big copy.bin"),
+        Err(fatfs::Error::NotEnoughSpace)
+    ));
+    // the partial copy has been removed
+    assert!(!root_dir.exists("big copy.bin").unwrap());
+    assert_eq!(fs.stats().unwrap().free_clusters(), free_clusters);
+    assert!(fs.check().unwrap().is_clean());
+}
@@ -0,0 +1,256 @@
+use std::fs;
+use std::io;
+
+use fatfs::FsOptions;
+
+mod common;
+use common::{
+    init_logger, open_fs, InstrumentedFs, InstrumentedStream, StreamState, WriteFault, FAT12_IMG, FAT16_IMG, FAT32_IMG,
+};
+
+const DOT_ENTRY: &[u8] = b".          \x10";
+const DOTDOT_ENTRY: &[u8] = b"..         \x10";
+
+fn names_in_dir<IO: fatfs::ReadWriteSeek, TP: fatfs::TimeProvider, OCC: fatfs::OemCpConverter>(
+    dir: &fatfs::Dir<IO, TP, OCC>,
+) -> Vec<String> {
+    dir.iter().map(|r| r.unwrap().file_name()).collect()
+}
+
+fn assert_valid_new_dir(img: Vec<u8>) {
+    let fs = fatfs::FileSystem::new(io::Cursor::new(img), FsOptions::new()).unwrap();
+    let root_dir = fs.root_dir();
+    let root_names = names_in_dir(&root_dir);
+    assert_eq!(root_names.iter().filter(|n| *n == "new").count(), 1);
+    assert_eq!(names_in_dir(&root_dir.open_dir("new").unwrap()), [".", ".."]);
+    assert_eq!(names_in_dir(&root_dir.open_dir("new/.").unwrap()), [".", ".."]);
+    assert_eq!(names_in_dir(&root_dir.open_dir("new/..").unwrap()), root_names);
+}
+
+fn test_create_dir_retry(img_path: &str) {
+    for failing_write in 0.. {
+        let state = StreamState::from_img(img_path);
+        let completed = {
+            let fs = InstrumentedFs::new(InstrumentedStream::new(&state), FsOptions::new()).unwrap();
+            let root_dir = fs.root_dir();
+            state.set_write_fault(WriteFault::FailAfterCalls(failing_write));
+            let completed = root_dir.create_dir("new").is_ok();
+            // retry on a healthy device
+            state.set_write_fault(WriteFault::None);
+            root_dir.create_dir("new").unwrap();
+            completed
+        };
+        assert_valid_new_dir(state.data());
+        if completed {
+            break;
+        }
+    }
+}
+
+#[test]
+fn test_create_dir_retry_fat12() {
+    test_create_dir_retry(FAT12_IMG)
+}
+
+#[test]
+fn test_create_dir_retry_fat16() {
+    test_create_dir_retry(FAT16_IMG)
+}
+
+#[test]
+fn test_create_dir_retry_fat32() {
+    test_create_dir_retry(FAT32_IMG)
+}
+
+fn find_entries(img: &[u8], entry: &[u8]) -> Vec<usize> {
+    (0..img.len())
+        .step_by(32)
+        .filter(|&i| img[i..].starts_with(entry))
+        .collect()
+}
+
+/// Creates "new" directory and returns the image with offsets of its "." and ".." entries.
+fn create_new_dir(img_path: &str) -> (Vec<u8>, usize, usize) {
+    let img = fs::read(img_path).unwrap();
+    let mut new_img = img.clone();
+    {
+        let fs = fatfs::FileSystem::new(io::Cursor::new(&mut new_img), FsOptions::new()).unwrap();
+        fs.root_dir().create_dir("new").unwrap();
+    }
+    let new_entry = |entry| {
+        let old_offsets = find_entries(&img, entry);
+        let new_offsets = find_entries(&new_img, entry);
+        *new_offsets.iter().find(|o| !old_offsets.contains(o)).unwrap()
+    };
+    let dot = new_entry(DOT_ENTRY);
+    let dotdot = new_entry(DOTDOT_ENTRY);
+    (new_img, dot, dotdot)
+}
+
+fn create_dir_again(img: &[u8], repair: bool) -> Result<Vec<u8>, fatfs::Error<io::Error>> {
+    let mut img = img.to_vec();
+    {
+        let fs = fatfs::FileSystem::new(io::Cursor::new(&mut img), FsOptions::new().repair(repair)).unwrap();
+        fs.root_dir().create_dir("new")?;
+    }
+    Ok(img)
+}
+
+fn assert_corrupted<T>(result: Result<T, fatfs::Error<io::Error>>) {
+    match result {
+        Err(fatfs::Error::CorruptedFileSystem) => {}
+        Err(err) => panic!("unexpected error {:?}", err),
+        Ok(_) => panic!("corruption has not been detected"),
+    }
+}
+
+fn test_create_dir_invalid_dotdot_cluster(img_path: &str) {
+    let (mut img, _, dotdot) = create_new_dir(img_path);
+    img[dotdot + 26..dotdot + 28].copy_from_slice(&[0x34, 0x12]);
+    assert_corrupted(create_dir_again(&img, false));
+    assert_valid_new_dir(create_dir_again(&img, true).unwrap());
+}
+
+fn test_create_dir_duplicated_dot_entry(img_path: &str) {
+    let (mut img, dot, dotdot) = create_new_dir(img_path);
+    let dot_entry = img[dot..dot + 32].to_vec();
+    img[dotdot + 32..dotdot + 64].copy_from_slice(&dot_entry);
+    assert_corrupted(create_dir_again(&img, false));
+    assert_valid_new_dir(create_dir_again(&img, true).unwrap());
+}
+
+#[test]
+fn test_create_dir_invalid_dotdot_cluster_fat12() {
+    test_create_dir_invalid_dotdot_cluster(FAT12_IMG)
+}
+
+#[test]
+fn test_create_dir_invalid_dotdot_cluster_fat32() {
+    test_create_dir_invalid_dotdot_cluster(FAT32_IMG)
+}
+
+#[test]
+fn test_create_dir_duplicated_dot_entry_fat16() {
+    test_create_dir_duplicated_dot_entry(FAT16_IMG)
+}
+
+#[test]
+fn test_create_dir_duplicated_dot_entry_fat32() {
+    test_create_dir_duplicated_dot_entry(FAT32_IMG)
+}
+
+fn read_cluster(entry: &[u8]) -> u32 {
+    let hi = u16::from_le_bytes([entry[20], entry[21]]);
+    let lo = u16::from_le_bytes([entry[26], entry[27]]);
+    (u32::from(hi) << 16) | u32::from(lo)
+}
+
+fn test_create_dir_dot_entries(img_path: &str) {
+    init_logger();
+    let (img, dot, dotdot) = create_new_dir(img_path);
+    let dir_entry = find_entries(&img, b"NEW        \x10")[0];
+    let dir_entry = &img[dir_entry..dir_entry + 32];
+    // plain short entries at the beginning of the directory
+    assert_eq!(dotdot, dot + 32);
+    assert_eq!(read_cluster(&img[dot..]), read_cluster(dir_entry));
+    // ".." entry of a subdirectory of the root directory points to cluster 0 (also on FAT32)
+    assert_eq!(read_cluster(&img[dotdot..]), 0);
+    // timestamps are copied from the directory entry
+    for &entry in &[dot, dotdot] {
+        assert_eq!(img[entry + 13..entry + 20], dir_entry[13..20]);
+        assert_eq!(img[entry + 22..entry + 26], dir_entry[22..26]);
+    }
+    assert_eq!(img[dotdot + 32], 0);
+
+    let fs = fatfs::FileSystem::new(io::Cursor::new(img), FsOptions::new()).unwrap();
+    let dir = fs.root_dir().open_dir("new").unwrap();
+    let sub = dir.create_dir("sub").unwrap();
+    let entries: Vec<_> = sub.iter().map(|r| r.unwrap()).collect();
+    assert_eq!(entries.len(), 2);
+    assert!(entries.iter().all(|e| !e.has_lfn()));
+    assert_eq!(entries[1].entry_pos(), entries[0].entry_pos() + 32);
+    // ".." entry of a nested directory points to its parent
+    assert_eq!(names_in_dir(&dir.open_dir("sub/..").unwrap()), [".", "..", "sub"]);
+    assert_eq!(sub.parent().unwrap().unwrap().iter().count(), 3);
+    assert!(fs.check().unwrap().is_clean());
+}
+
+#[test]
+fn test_create_dir_dot_entries_fat12() {
+    test_create_dir_dot_entries(FAT12_IMG)
+}
+
+#[test]
+fn test_create_dir_dot_entries_fat16() {
+    test_create_dir_dot_entries(FAT16_IMG)
+}
+
+#[test]
+fn test_create_dir_dot_entries_fat32() {
+    test_create_dir_dot_entries(FAT32_IMG)
+}
+
+fn test_create_dir_all(img_path: &str) {
+    let fs = open_fs(img_path);
+    let root_dir = fs.root_dir();
+    assert!(matches!(
+        root_dir.create_dir("a/b/c"),
+        Err(fatfs::Error::NotFound { .. })
+    ));
+    let c = root_dir.create_dir_all("a/b/c").unwrap();
+    assert_eq!(names_in_dir(&c), [".", ".."]);
+    assert_eq!(names_in_dir(&root_dir.open_dir("a").unwrap()), [".", "..", "b"]);
+    assert_eq!(names_in_dir(&root_dir.open_dir("a/b/c/..").unwrap()), [".", "..", "c"]);
+    assert_eq!(names_in_dir(&root_dir.open_dir("a/b/..").unwrap()), [".", "..", "b"]);
+    // `.` and `..` entries of every level are valid
+    root_dir.create_dir("a").unwrap();
+    root_dir.create_dir("a/b").unwrap();
+    root_dir.create_dir("a/b/c").unwrap();
+
+    // existing directories are opened
+    root_dir.open_dir("a/b").unwrap().create_file("file.txt").unwrap();
+    root_dir.create_dir_all("/a/b/").unwrap();
+    assert_eq!(
+        names_in_dir(&root_dir.open_dir("a/b").unwrap()),
+        [".", "..", "c", "file.txt"]
+    );
+    root_dir.create_dir_all("very/long/new/dir").unwrap();
+    assert_eq!(
+        names_in_dir(&root_dir.open_dir("very/long/new").unwrap()),
+        [".", "..", "dir"]
+    );
+    assert!(fs.check().unwrap().is_clean());
+}
+
+#[test]
+fn test_create_dir_all_fat12() {
+    test_create_dir_all(FAT12_IMG)
+}
+
+#[test]
+fn test_create_dir_all_fat32() {
+    test_create_dir_all(FAT32_IMG)
+}
+
+#[test]
+fn test_create_dir_all_file_component() {
+    let fs = open_fs(FAT16_IMG);
+    let root_dir = fs.root_dir();
+    let root_names = names_in_dir(&root_dir);
+    for &path in &["short.txt", "short.txt/new", "very/long/path/test.txt/new/dir"] {
+        assert!(
+            matches!(root_dir.create_dir_all(path), Err(fatfs::Error::InvalidInput)),
+            "{}",
+            path
+        );
+    }
+    for &path in &["", "/", "."] {
+        assert!(matches!(root_dir.create_dir_all(path), Err(fatfs::Error::InvalidInput)));
+    }
+    assert_eq!(names_in_dir(&root_dir), root_names);
+    assert_eq!(
+        names_in_dir(&root_dir.open_dir("very/long/path").unwrap()),
+        [".", "..", "test.txt"]
+    );
+    assert!(fs.check().unwrap().is_clean());
+}
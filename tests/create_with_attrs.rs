@@ -0,0 +1,116 @@
+use fatfs::FileAttributes;
+
+mod common;
+use common::{open_fs, Dir, FAT16_IMG, FAT32_IMG};
+
+fn attributes_of(dir: &Dir, name: &str) -> FileAttributes {
+    dir.iter()
+        .map(|r| r.unwrap())
+        .find(|e| e.file_name() == name)
+        .unwrap()
+        .attributes()
+}
+
+fn test_create_file_with_attrs(img_path: &str) {
+    let fs = open_fs(img_path);
+    let root_dir = fs.root_dir();
+    let attrs = FileAttributes::HIDDEN | FileAttributes::SYSTEM | FileAttributes::READ_ONLY;
+    root_dir.create_file_with_attrs("very/boot.ini", attrs).unwrap();
+    let dir = root_dir.open_dir("very").unwrap();
+    assert_eq!(attributes_of(&dir, "boot.ini"), attrs);
+    // hidden from the default filter right away
+    assert!(dir.files().all(|r| r.unwrap().file_name() != "boot.ini"));
+    // an existing file is opened and its attributes are not changed
+    root_dir
+        .create_file_with_attrs("very/boot.ini", FileAttributes::ARCHIVE)
+        .unwrap();
+    root_dir.create_file("very/boot.ini").unwrap();
+    assert_eq!(attributes_of(&dir, "boot.ini"), attrs);
+    // create_file still creates files without attributes
+    root_dir.create_file("plain.txt").unwrap();
+    assert_eq!(attributes_of(&root_dir, "plain.txt"), FileAttributes::empty());
+    assert!(fs.check().unwrap().is_clean());
+}
+
+#[test]
+fn test_create_file_with_attrs_fat16() {
+    test_create_file_with_attrs(FAT16_IMG)
+}
+
+#[test]
+fn test_create_file_with_attrs_fat32() {
+    test_create_file_with_attrs(FAT32_IMG)
+}
+
+fn test_create_dir_with_attrs(img_path: &str) {
+    let fs = open_fs(img_path);
+    let root_dir = fs.root_dir();
+    let attrs = FileAttributes::HIDDEN | FileAttributes::SYSTEM;
+    let dir = root_dir
+        .create_dir_with_attrs("System Volume Information", attrs)
+        .unwrap();
+    assert_eq!(
+        attributes_of(&root_dir, "System Volume Information"),
+        attrs | FileAttributes::DIRECTORY
+    );
+    // "." and ".." are plain directory entries
+    assert_eq!(attributes_of(&dir, "."), FileAttributes::DIRECTORY);
+    assert_eq!(attributes_of(&dir, ".."), FileAttributes::DIRECTORY);
+    // the new directory is usable
+    dir.create_file("IndexerVolumeGuid").unwrap();
+    assert!(root_dir.exists("System Volume Information/IndexerVolumeGuid").unwrap());
+    // only the last path component gets the attributes
+    root_dir
+        .create_dir_with_attrs("very/hidden", FileAttributes::HIDDEN | FileAttributes::DIRECTORY)
+        .unwrap();
+    assert_eq!(
+        attributes_of(&root_dir.open_dir("very").unwrap(), "hidden"),
+        FileAttributes::HIDDEN | FileAttributes::DIRECTORY
+    );
+    assert_eq!(attributes_of(&root_dir, "very"), FileAttributes::DIRECTORY);
+    // an existing directory is opened and its attributes are not changed
+    root_dir
+        .create_dir_with_attrs("System Volume Information", FileAttributes::READ_ONLY)
+        .unwrap();
+    assert_eq!(
+        attributes_of(&root_dir, "System Volume Information"),
+        attrs | FileAttributes::DIRECTORY
+    );
+    assert!(fs.check().unwrap().is_clean());
+}
+
+#[test]
+fn test_create_dir_with_attrs_fat16() {
+    test_create_dir_with_attrs(FAT16_IMG)
+}
+
+#[test]
+fn test_create_dir_with_attrs_fat32() {
+    test_create_dir_with_attrs(FAT32_IMG)
+}
+
+#[test]
+fn test_create_with_invalid_attrs() {
+    let fs = open_fs(FAT16_IMG);
+    let root_dir = fs.root_dir();
+    let names = || root_dir.iter().map(|r| r.unwrap().file_name()).collect::<Vec<_>>();
+    let before = names();
+    for &attrs in &[
+        FileAttributes::DIRECTORY,
+        FileAttributes::VOLUME_ID,
+        FileAttributes::LFN,
+        FileAttributes::HIDDEN | FileAttributes::DIRECTORY,
+    ] {
+        assert!(matches!(
+            root_dir.create_file_with_attrs("new.txt", attrs),
+            Err(fatfs::Error::InvalidInput)
+        ));
+    }
+    for &attrs in &[FileAttributes::VOLUME_ID, FileAttributes::LFN] {
+        assert!(matches!(
+            root_dir.create_dir_with_attrs("new-dir", attrs),
+            Err(fatfs::Error::InvalidInput)
+        ));
+    }
+    assert_eq!(names(), before);
+}
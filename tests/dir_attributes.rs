@@ -0,0 +1,204 @@
+use std::fs;
+use std::io;
+
+use fatfs::{DefaultTimeProvider, FileAttributes, FsOptions, LossyOemCpConverter, Read, StdIoWrapper, Write};
+
+mod common;
+use common::{init_logger, mount, open_fs, open_fs_with_options, FAT16_IMG};
+
+type File<'a> = fatfs::File<'a, StdIoWrapper<io::Cursor<Vec<u8>>>, DefaultTimeProvider, LossyOemCpConverter>;
+type Options = FsOptions<DefaultTimeProvider, LossyOemCpConverter>;
+
+const VERY_SFN: &[u8; 11] = b"VERY       ";
+
+fn sfn_offset(img: &[u8], short_name: &[u8; 11]) -> usize {
+    (0..img.len())
+        .step_by(32)
+        .find(|&i| img[i..].starts_with(short_name))
+        .unwrap()
+}
+
+/// Returns fat16 image with directory "very" marked as hidden and system (like "System Volume Information").
+fn hidden_system_dir_image() -> Vec<u8> {
+    init_logger();
+    let mut img = fs::read(FAT16_IMG).unwrap();
+    let offset = sfn_offset(&img, VERY_SFN);
+    img[offset + 11] |= (FileAttributes::HIDDEN | FileAttributes::SYSTEM).bits();
+    img
+}
+
+fn dir_attributes(img: &[u8]) -> FileAttributes {
+    FileAttributes::from_bits_truncate(img[sfn_offset(img, VERY_SFN) + 11])
+}
+
+#[test]
+fn test_hidden_system_dir_is_listed_and_usable() {
+    let img = hidden_system_dir_image();
+    let fs = mount(img);
+    let root_dir = fs.root_dir();
+    let entry = root_dir
+        .iter()
+        .map(|r| r.unwrap())
+        .find(|e| e.file_name() == "very")
+        .unwrap();
+    assert!(entry.is_dir());
+    assert_eq!(
+        entry.attributes(),
+        FileAttributes::DIRECTORY | FileAttributes::HIDDEN | FileAttributes::SYSTEM
+    );
+    assert!(root_dir.open_file("very/long/path/test.txt").is_ok());
+}
+
+#[test]
+fn test_modifying_dir_content_keeps_dir_attributes() {
+    let img = hidden_system_dir_image();
+    let expected = dir_attributes(&img);
+    let mut stream = io::Cursor::new(img);
+    {
+        let fs = fatfs::FileSystem::new(&mut stream, FsOptions::new()).unwrap();
+        let dir = fs.root_dir().open_dir("very").unwrap();
+        let mut file = dir.create_file("new.txt").unwrap();
+        file.write_all(b"data").unwrap();
+        drop(file);
+        dir.create_dir("new-dir").unwrap();
+        dir.rename("new.txt", &dir, "renamed.txt").unwrap();
+        dir.remove("renamed.txt").unwrap();
+        dir.remove("new-dir").unwrap();
+    }
+    // ARCHIVE is never set on a directory
+    assert_eq!(dir_attributes(&stream.into_inner()), expected);
+}
+
+#[test]
+fn test_archive_attribute_is_preserved_on_dir() {
+    let mut img = hidden_system_dir_image();
+    let offset = sfn_offset(&img, VERY_SFN);
+    img[offset + 11] |= FileAttributes::ARCHIVE.bits();
+    let expected = dir_attributes(&img);
+    let mut stream = io::Cursor::new(img);
+    {
+        let fs = fatfs::FileSystem::new(&mut stream, FsOptions::new()).unwrap();
+        fs.root_dir().create_file("very/new.txt").unwrap();
+    }
+    assert_eq!(dir_attributes(&stream.into_inner()), expected);
+}
+
+#[test]
+fn test_filtered_iter_skips_hidden_dir() {
+    let img = hidden_system_dir_image();
+    let fs = mount(img);
+    let root_dir = fs.root_dir();
+    let names = |iter: fatfs::DirIter<_, _, _>| iter.map(|r| r.unwrap().file_name()).collect::<Vec<_>>();
+    assert_eq!(names(root_dir.dirs()), ["very-long-dir-name"]);
+    assert_eq!(names(root_dir.files()), ["long.txt", "short.txt"]);
+    assert_eq!(
+        names(root_dir.iter_filtered(fatfs::EntryFilter::dirs().include_hidden(true))),
+        ["very", "very-long-dir-name"]
+    );
+    assert_eq!(
+        names(root_dir.iter_filtered(fatfs::EntryFilter::all())),
+        ["long.txt", "short.txt", "very-long-dir-name"]
+    );
+}
+
+#[test]
+fn test_set_attributes() {
+    let mut stream = io::Cursor::new(fs::read(FAT16_IMG).unwrap());
+    {
+        let fs = fatfs::FileSystem::new(&mut stream, FsOptions::new()).unwrap();
+        let root_dir = fs.root_dir();
+        let mut file_entry = root_dir.entry("long.txt").unwrap();
+        let attrs = FileAttributes::READ_ONLY | FileAttributes::HIDDEN | FileAttributes::SYSTEM;
+        file_entry.set_attributes(attrs).unwrap();
+        assert_eq!(file_entry.attributes(), attrs);
+        let mut dir_entry = root_dir.entry("very").unwrap();
+        dir_entry
+            .set_attributes(FileAttributes::DIRECTORY | FileAttributes::HIDDEN)
+            .unwrap();
+        // the iterator reads the updated entries
+        let attrs_in_dir: Vec<_> = root_dir
+            .iter()
+            .map(|r| r.unwrap())
+            .filter(|e| e.file_name() == "long.txt" || e.file_name() == "very")
+            .map(|e| e.attributes())
+            .collect();
+        assert_eq!(
+            attrs_in_dir,
+            [attrs, FileAttributes::DIRECTORY | FileAttributes::HIDDEN]
+        );
+        // and the file is still readable
+        assert!(root_dir.open_file("long.txt").is_ok());
+        assert!(fs.check().unwrap().is_clean());
+    }
+    let img = stream.into_inner();
+    assert_eq!(dir_attributes(&img), FileAttributes::DIRECTORY | FileAttributes::HIDDEN);
+}
+
+#[test]
+fn test_set_attributes_cannot_change_entry_type() {
+    let fs = open_fs(FAT16_IMG);
+    let root_dir = fs.root_dir();
+    let mut file_entry = root_dir.entry("short.txt").unwrap();
+    let mut dir_entry = root_dir.entry("very").unwrap();
+    let file_attrs = file_entry.attributes();
+    let dir_attrs = dir_entry.attributes();
+    for &attrs in &[
+        FileAttributes::DIRECTORY,
+        FileAttributes::VOLUME_ID,
+        FileAttributes::LFN,
+    ] {
+        assert!(matches!(
+            file_entry.set_attributes(attrs),
+            Err(fatfs::Error::InvalidInput)
+        ));
+    }
+    for &attrs in &[
+        FileAttributes::ARCHIVE,
+        FileAttributes::DIRECTORY | FileAttributes::VOLUME_ID,
+    ] {
+        assert!(matches!(
+            dir_entry.set_attributes(attrs),
+            Err(fatfs::Error::InvalidInput)
+        ));
+    }
+    assert_eq!(root_dir.entry("short.txt").unwrap().attributes(), file_attrs);
+    assert_eq!(file_entry.attributes(), file_attrs);
+    assert_eq!(root_dir.entry("very").unwrap().attributes(), dir_attrs);
+}
+
+fn file_attributes_after<F: Fn(&mut File)>(options: Options, f: F) -> FileAttributes {
+    let fs = open_fs_with_options(FAT16_IMG, options);
+    let root_dir = fs.root_dir();
+    let mut file = root_dir.create_file("new.txt").unwrap();
+    file.write_all(b"data").unwrap();
+    drop(file);
+    let mut entry = root_dir.entry("new.txt").unwrap();
+    entry.set_attributes(FileAttributes::empty()).unwrap();
+    let mut file = entry.to_file();
+    f(&mut file);
+    file.flush().unwrap();
+    drop(file);
+    root_dir.entry("new.txt").unwrap().attributes()
+}
+
+#[test]
+fn test_write_sets_archive_attribute() {
+    let attrs = file_attributes_after(FsOptions::new(), |file| file.write_all(b"more data").unwrap());
+    assert_eq!(attrs, FileAttributes::ARCHIVE);
+    let attrs = file_attributes_after(FsOptions::new(), |file| file.truncate().unwrap());
+    assert_eq!(attrs, FileAttributes::ARCHIVE);
+    // reading does not modify the file
+    let attrs = file_attributes_after(FsOptions::new(), |file| {
+        file.read_exact(&mut [0; 4]).unwrap();
+    });
+    assert_eq!(attrs, FileAttributes::empty());
+}
+
+#[test]
+fn test_archive_attribute_update_disabled() {
+    let options = FsOptions::new().update_archive_attribute(false);
+    let attrs = file_attributes_after(options, |file| file.write_all(b"more data").unwrap());
+    assert_eq!(attrs, FileAttributes::empty());
+    let attrs = file_attributes_after(options, |file| file.truncate().unwrap());
+    assert_eq!(attrs, FileAttributes::empty());
+}
@@ -0,0 +1,123 @@
+use std::fs;
+use std::io;
+
+use fatfs::{FsOptions, Write};
+
+mod common;
+use common::{init_logger, open_fs, Dir, FAT16_IMG, FAT32_IMG};
+
+/// Fills all free clusters with non-zero bytes by writing and removing a big file.
+fn fill_free_clusters_with_garbage<IO: fatfs::ReadWriteSeek, TP: fatfs::TimeProvider, OCC: fatfs::OemCpConverter>(
+    fs: &fatfs::FileSystem<IO, TP, OCC>,
+) {
+    let root_dir = fs.root_dir();
+    let size = fs.stats().unwrap().free_clusters() as usize * fs.cluster_size() as usize;
+    root_dir
+        .create_file("garbage")
+        .unwrap()
+        .write_all(&vec![b'A'; size])
+        .unwrap();
+    root_dir.remove("garbage").unwrap();
+}
+
+fn names_in_dir(dir: &Dir) -> Vec<String> {
+    dir.iter().map(|r| r.unwrap().file_name()).collect()
+}
+
+/// Creates files `<prefix><n>` in `dir` until it occupies more than one cluster.
+///
+/// Checks that only these files are listed and returns the short name of the last one.
+fn fill_dir_past_cluster<IO: fatfs::ReadWriteSeek, TP: fatfs::TimeProvider, OCC: fatfs::OemCpConverter>(
+    dir: &fatfs::Dir<IO, TP, OCC>,
+    cluster_size: u32,
+    prefix: &str,
+) -> String {
+    // every file takes a single SFN entry so the files together with `.` and `..` do not fit in one cluster
+    let num_files = cluster_size / 32;
+    let mut expected = vec![".".to_string(), "..".to_string()];
+    for i in 0..num_files {
+        let name = format!("{}{}", prefix, i);
+        dir.create_file(&name).unwrap();
+        expected.push(name);
+    }
+    let names: Vec<_> = dir.iter().map(|r| r.unwrap().file_name()).collect();
+    assert_eq!(names, expected);
+    format!("{:<11}", expected.last().unwrap().to_uppercase())
+}
+
+/// Checks that the entries following the short name entry `short_name` are zeroed.
+fn assert_zeroed_after(img: &[u8], short_name: &str) {
+    let offset = (0..img.len())
+        .step_by(32)
+        .find(|&i| img[i..].starts_with(short_name.as_bytes()))
+        .unwrap();
+    assert!(img[offset + 32..offset + 96].iter().all(|&b| b == 0));
+}
+
+fn test_dir_growth(img_path: &str) {
+    init_logger();
+    let mut img = fs::read(img_path).unwrap();
+    let (last_in_dir, last_in_other) = {
+        let fs = fatfs::FileSystem::new(io::Cursor::new(&mut img), FsOptions::new()).unwrap();
+        fill_free_clusters_with_garbage(&fs);
+        let cluster_size = fs.cluster_size();
+        let root_dir = fs.root_dir();
+        let last_in_dir = fill_dir_past_cluster(&root_dir.create_dir("dir").unwrap(), cluster_size, "d");
+        // a directory opened without its entry (using `parent`) grows the same way
+        let sub = root_dir.create_dir("other").unwrap().create_dir("sub").unwrap();
+        let other = sub.parent().unwrap().unwrap();
+        other.remove("sub").unwrap();
+        let last_in_other = fill_dir_past_cluster(&other, cluster_size, "o");
+        assert!(fs.check().unwrap().is_clean());
+        (last_in_dir, last_in_other)
+    };
+    // the remaining part of the new cluster does not contain stale data
+    assert_zeroed_after(&img, &last_in_dir);
+    assert_zeroed_after(&img, &last_in_other);
+}
+
+#[test]
+fn test_dir_growth_fat16() {
+    test_dir_growth(FAT16_IMG)
+}
+
+#[test]
+fn test_dir_growth_fat32() {
+    test_dir_growth(FAT32_IMG)
+}
+
+#[test]
+fn test_fat32_root_dir_growth() {
+    let fs = open_fs(FAT32_IMG);
+    fill_free_clusters_with_garbage(&fs);
+    let root_dir = fs.root_dir();
+    let root_names = names_in_dir(&root_dir);
+    let num_files = fs.cluster_size() / 32;
+    for i in 0..num_files {
+        root_dir.create_file(&format!("f{}", i)).unwrap();
+    }
+    assert_eq!(names_in_dir(&root_dir).len(), root_names.len() + num_files as usize);
+    assert!(fs.check().unwrap().is_clean());
+}
+
+#[test]
+fn test_fat16_root_dir_is_not_growing() {
+    let fs = open_fs(FAT16_IMG);
+    let root_dir = fs.root_dir();
+    let mut created = 0;
+    for i in 0.. {
+        match root_dir.create_file(&format!("File{}", i)) {
+            Ok(_) => created += 1,
+            Err(fatfs::Error::NotEnoughSpace) => break,
+            Err(err) => panic!("unexpected error {:?}", err),
+        }
+    }
+    // every file takes 2 of 512 root directory entries (a mixed case name needs a LFN entry)
+    assert!(created > 200 && created < 256);
+    // the cluster allocated for the directory is freed
+    let free_clusters = fs.stats().unwrap().free_clusters();
+    assert!(matches!(root_dir.create_dir("dir"), Err(fatfs::Error::NotEnoughSpace)));
+    assert_eq!(fs.stats().unwrap().free_clusters(), free_clusters);
+    assert_eq!(names_in_dir(&root_dir).len(), 4 + created);
+    assert!(fs.check().unwrap().is_clean());
+}
@@ -0,0 +1,82 @@
+mod common;
+use common::{open_fs, Dir, FAT16_IMG, FAT32_IMG};
+
+fn entry_paths(dir: &Dir) -> Vec<String> {
+    dir.iter().map(|r| r.unwrap().path()).collect()
+}
+
+fn test_paths_of_opened_dirs(img_path: &str) {
+    let fs = open_fs(img_path);
+    let root_dir = fs.root_dir();
+    assert_eq!(root_dir.path(), "");
+    assert_eq!(
+        entry_paths(&root_dir),
+        ["long.txt", "short.txt", "very", "very-long-dir-name"]
+    );
+    let dir = root_dir.open_dir("very/./long/../long//path").unwrap();
+    assert_eq!(dir.path(), "very/long/path");
+    assert_eq!(
+        entry_paths(&dir),
+        ["very/long/path", "very/long", "very/long/path/test.txt"]
+    );
+    assert_eq!(dir.open_dir("..").unwrap().path(), "very/long");
+    assert_eq!(dir.parent().unwrap().unwrap().path(), "very/long");
+    assert_eq!(dir.open_dir("../..").unwrap().open_dir("..").unwrap().path(), "");
+    assert_eq!(dir.clone().path(), "very/long/path");
+    assert_eq!(
+        root_dir.entry("very/long/path/test.txt").unwrap().path(),
+        "very/long/path/test.txt"
+    );
+    // directories returned by an entry keep the path
+    let long = root_dir.open_dir("very").unwrap().iter().nth(2).unwrap().unwrap();
+    assert_eq!(long.to_dir().path(), "very/long");
+    assert_eq!(entry_paths(&long.to_dir())[2], "very/long/path");
+}
+
+#[test]
+fn test_paths_of_opened_dirs_fat16() {
+    test_paths_of_opened_dirs(FAT16_IMG)
+}
+
+#[test]
+fn test_paths_of_opened_dirs_fat32() {
+    test_paths_of_opened_dirs(FAT32_IMG)
+}
+
+#[test]
+fn test_paths_of_created_dirs() {
+    let fs = open_fs(FAT16_IMG);
+    let root_dir = fs.root_dir();
+    let dir = root_dir.create_dir("Some Dir").unwrap();
+    assert_eq!(dir.path(), "Some Dir");
+    let sub = dir.create_dir_all("a/b/../c").unwrap();
+    assert_eq!(sub.path(), "Some Dir/a/c");
+    assert_eq!(dir.create_new_dir("d").unwrap().path(), "Some Dir/d");
+    sub.create_file("file.txt").unwrap();
+    assert_eq!(entry_paths(&sub)[2], "Some Dir/a/c/file.txt");
+    // the path used to open a directory is kept, including the case of the names
+    assert_eq!(root_dir.open_dir("SOME DIR/A").unwrap().path(), "SOME DIR/A");
+    let paths: Vec<_> = fs
+        .dir_queue_iter()
+        .map(|r| {
+            let entry = r.unwrap();
+            assert_eq!(entry.dir().path(), entry.path());
+            entry.path().to_string()
+        })
+        .collect();
+    assert!(paths.iter().any(|p| p == "Some Dir/a/b"));
+}
+
+#[test]
+fn test_paths_after_rename() {
+    let fs = open_fs(FAT16_IMG);
+    let root_dir = fs.root_dir();
+    let dir = root_dir.open_dir("very/long").unwrap();
+    root_dir.rename("very", &root_dir, "renamed").unwrap();
+    // existing handles are not updated
+    assert_eq!(dir.path(), "very/long");
+    assert_eq!(entry_paths(&dir)[2], "very/long/path");
+    let dir = root_dir.open_dir("renamed/long").unwrap();
+    assert_eq!(dir.path(), "renamed/long");
+    assert_eq!(entry_paths(&dir)[2], "renamed/long/path");
+}
@@ -0,0 +1,115 @@
+use std::fs;
+use std::io;
+use std::thread;
+
+use fatfs::{FsLimits, FsOptions};
+
+mod common;
+use common::{mount, open_fs, open_fs_with_options, FileSystem, FAT16_IMG, FAT32_IMG};
+
+fn list_dirs(fs: &FileSystem) -> Vec<(String, u32)> {
+    fs.dir_queue_iter()
+        .map(|r| r.map(|e| (e.path().to_string(), e.depth())))
+        .collect::<Result<_, _>>()
+        .unwrap()
+}
+
+/// Returns the offset of the short name entry.
+fn find_entry(img: &[u8], short_name: &[u8; 11]) -> usize {
+    (0..img.len())
+        .step_by(32)
+        .find(|&i| &img[i..i + 11] == short_name)
+        .unwrap()
+}
+
+fn create_chain(mut img: Vec<u8>, depth: usize) -> Vec<u8> {
+    let fs = fatfs::FileSystem::new(io::Cursor::new(&mut img), FsOptions::new()).unwrap();
+    let mut dir = fs.root_dir();
+    for _ in 0..depth {
+        dir = dir.create_dir("D").unwrap();
+    }
+    drop(dir);
+    fs.unmount().unwrap();
+    img
+}
+
+#[test]
+fn test_dir_queue_iter_order() {
+    let fs = open_fs(FAT16_IMG);
+    let dirs = list_dirs(&fs);
+    let expected = [
+        ("", 0),
+        ("very", 1),
+        ("very/long", 2),
+        ("very/long/path", 3),
+        ("very-long-dir-name", 1),
+    ];
+    let expected: Vec<_> = expected.iter().map(|&(p, d)| (p.to_string(), d)).collect();
+    assert_eq!(dirs, expected);
+}
+
+#[test]
+fn test_dir_queue_iter_fat32() {
+    let fs = open_fs(FAT32_IMG);
+    let mut iter = fs.dir_queue_iter();
+    let root = iter.next().unwrap().unwrap();
+    assert_eq!(root.path(), "");
+    assert_eq!(root.depth(), 0);
+    assert_eq!(root.dir().iter().count(), 4);
+    assert_eq!(iter.count(), 4);
+}
+
+#[test]
+fn test_dir_queue_iter_skips_loops() {
+    let mut img = fs::read(FAT16_IMG).unwrap();
+    {
+        let fs = fatfs::FileSystem::new(io::Cursor::new(&mut img), FsOptions::new()).unwrap();
+        let loop_a = fs.root_dir().create_dir("LOOPA").unwrap();
+        loop_a.create_dir("LOOPB").unwrap().create_dir("LOOPC").unwrap();
+        drop(loop_a);
+        fs.unmount().unwrap();
+    }
+    // make LOOPC point to its grandparent directory
+    let loop_a = find_entry(&img, b"LOOPA      ");
+    let loop_c = find_entry(&img, b"LOOPC      ");
+    let cluster = [img[loop_a + 26], img[loop_a + 27]];
+    img[loop_c + 26..loop_c + 28].copy_from_slice(&cluster);
+
+    let fs = mount(img);
+    let paths: Vec<_> = list_dirs(&fs).into_iter().map(|(p, _)| p).collect();
+    assert_eq!(paths.iter().filter(|p| p.starts_with("LOOPA")).count(), 2);
+    assert!(paths.contains(&"LOOPA/LOOPB".to_string()));
+    let summary = fs.usage_summary().unwrap();
+    assert_eq!(summary.max_depth(), 4);
+}
+
+#[test]
+fn test_dir_queue_iter_depth_limit() {
+    let limits = FsLimits {
+        max_dir_depth: 2,
+        ..FsLimits::default()
+    };
+    let fs = open_fs_with_options(FAT16_IMG, FsOptions::new().limits(limits));
+    let results: Vec<_> = fs.dir_queue_iter().collect();
+    assert!(matches!(results.last(), Some(Err(fatfs::Error::LimitExceeded))));
+    assert!(results[..results.len() - 1].iter().all(Result::is_ok));
+}
+
+#[test]
+fn test_dir_queue_iter_deep_tree_small_stack() {
+    let img = create_chain(fs::read(FAT16_IMG).unwrap(), 500);
+    let handle = thread::Builder::new()
+        .stack_size(256 * 1024)
+        .spawn(move || {
+            let fs = mount(img);
+            let dirs = list_dirs(&fs);
+            assert_eq!(dirs.len(), 5 + 500);
+            let deepest = dirs.iter().max_by_key(|(_, depth)| *depth).unwrap();
+            assert_eq!(deepest.1, 500);
+            assert_eq!(deepest.0.len(), 500 * 2 - 1);
+            let summary = fs.usage_summary().unwrap();
+            assert_eq!(summary.max_depth(), 500);
+        })
+        .unwrap();
+    handle.join().unwrap();
+}
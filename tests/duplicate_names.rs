@@ -0,0 +1,136 @@
+use std::fs;
+use std::io;
+use std::io::prelude::*;
+
+use fatfs::{CheckFinding, FsOptions, StdIoWrapper};
+
+mod common;
+use common::{mount, FileSystem, FAT16_IMG};
+
+fn lfn_checksum(short_name: &[u8]) -> u8 {
+    short_name
+        .iter()
+        .fold(0_u8, |sum, &c| ((sum & 1) << 7).wrapping_add(sum >> 1).wrapping_add(c))
+}
+
+/// Creates `Dupa.txt` and `Dupb.txt` in `dir_path` and renames the second one to `Dupa.txt` by patching its long
+/// and short name entries directly, so the directory contains two entries with the same name.
+fn create_duplicates(img: &mut Vec<u8>, dir_path: &str) {
+    {
+        let fs = fatfs::FileSystem::new(io::Cursor::new(&mut *img), FsOptions::new()).unwrap();
+        {
+            let dir = fs.root_dir().open_dir(dir_path).unwrap();
+            dir.create_file("Dupa.txt").unwrap().write_all(b"first").unwrap();
+            dir.create_file("Dupb.txt").unwrap().write_all(b"second").unwrap();
+        }
+        fs.unmount().unwrap();
+    }
+    let sfn_pos = img.windows(11).position(|w| w == b"DUPB    TXT").unwrap();
+    img[sfn_pos + 3] = b'A';
+    // the mixed case long name is stored in a single LFN entry preceding the short name entry: 'b' is its 4th
+    // character
+    let lfn_pos = sfn_pos - 32;
+    assert_eq!(img[lfn_pos + 7], b'b');
+    img[lfn_pos + 7] = b'a';
+    img[lfn_pos + 13] = lfn_checksum(&img[sfn_pos..sfn_pos + 11]);
+}
+
+fn open_fs_with_duplicates(dir_path: &str) -> FileSystem {
+    let mut img = fs::read(FAT16_IMG).unwrap();
+    create_duplicates(&mut img, dir_path);
+    mount(img)
+}
+
+fn read_to_string(mut file: impl Read) -> String {
+    let mut content = String::new();
+    file.read_to_string(&mut content).unwrap();
+    content
+}
+
+#[test]
+fn test_lookup_returns_first_duplicate() {
+    let fs = open_fs_with_duplicates("/");
+    let root_dir = fs.root_dir();
+    let entries = root_dir.find_all("DUPA.TXT").unwrap();
+    assert_eq!(entries.len(), 2);
+    assert!(entries[0].entry_pos() < entries[1].entry_pos());
+    assert_eq!(entries[0].file_name(), "Dupa.txt");
+    assert_eq!(entries[1].file_name(), "Dupa.txt");
+    assert_eq!(read_to_string(entries[0].to_file()), "first");
+    assert_eq!(read_to_string(entries[1].to_file()), "second");
+
+    assert_eq!(read_to_string(root_dir.open_file("Dupa.txt").unwrap()), "first");
+    assert_eq!(read_to_string(root_dir.create_file("DUPA.TXT").unwrap()), "first");
+    assert!(!root_dir.exists("Dupb.txt").unwrap());
+    assert!(root_dir.find_all("Dupb.txt").unwrap().is_empty());
+}
+
+#[test]
+fn test_check_reports_duplicates() {
+    for &dir_path in &["/", "very/long"] {
+        let fs = open_fs_with_duplicates(dir_path);
+        let entries = fs.root_dir().open_dir(dir_path).unwrap().find_all("Dupa.txt").unwrap();
+        let path = format!("{}/Dupa.txt", dir_path).trim_start_matches('/').to_string();
+        let expected = [CheckFinding::DuplicateName {
+            path,
+            entry_pos: entries[1].entry_pos(),
+        }];
+        assert_eq!(fs.check().unwrap().findings(), expected);
+
+        // the finding survives interrupting the check in the middle of the directory
+        let mut state = fatfs::CheckState::new();
+        let budget = fatfs::CheckBudget::unlimited().max_entries(1).max_clusters(1);
+        let report = loop {
+            if let fatfs::CheckProgress::Done(report) = fs.check_incremental(&mut state, budget).unwrap() {
+                break report;
+            }
+            let mut buf = Vec::new();
+            state.serialize(&mut StdIoWrapper::from(&mut buf)).unwrap();
+            state = fatfs::CheckState::deserialize(&mut StdIoWrapper::from(io::Cursor::new(&buf))).unwrap();
+        };
+        assert_eq!(report.findings(), expected);
+    }
+}
+
+#[test]
+fn test_remove_second_duplicate() {
+    let fs = open_fs_with_duplicates("/");
+    let root_dir = fs.root_dir();
+    let entries = root_dir.find_all("Dupa.txt").unwrap();
+    root_dir.remove_entry_at(entries[1].entry_pos()).unwrap();
+
+    let entries = root_dir.find_all("Dupa.txt").unwrap();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(read_to_string(entries[0].to_file()), "first");
+    assert_eq!(read_to_string(root_dir.open_file("Dupa.txt").unwrap()), "first");
+    // the clusters of the removed entry have been freed
+    assert!(fs.check().unwrap().is_clean());
+}
+
+#[test]
+fn test_remove_entry_at_invalid_position() {
+    let fs = open_fs_with_duplicates("/");
+    let root_dir = fs.root_dir();
+    let entry_pos = root_dir.find_all("Dupa.txt").unwrap()[1].entry_pos();
+    // position of another directory
+    let subdir = root_dir.open_dir("very").unwrap();
+    assert!(matches!(
+        subdir.remove_entry_at(entry_pos),
+        Err(fatfs::Error::InvalidInput)
+    ));
+    // position in the middle of an entry
+    assert!(matches!(
+        root_dir.remove_entry_at(entry_pos + 1),
+        Err(fatfs::Error::InvalidInput)
+    ));
+    // "." and ".." entries
+    for e in subdir.iter().take(2) {
+        let e = e.unwrap();
+        assert!(matches!(
+            subdir.remove_entry_at(e.entry_pos()),
+            Err(fatfs::Error::InvalidInput)
+        ));
+    }
+    assert_eq!(root_dir.find_all("Dupa.txt").unwrap().len(), 2);
+    assert!(subdir.exists("long").unwrap());
+}
@@ -0,0 +1,142 @@
+mod common;
+use common::{remount, InstrumentedDir, StreamState, FAT16_IMG};
+
+const DOT_ENTRY: &[u8] = b".          \x10";
+
+fn find_entries(img: &[u8], entry: &[u8]) -> Vec<usize> {
+    (0..img.len())
+        .step_by(32)
+        .filter(|&i| img[i..].starts_with(entry))
+        .collect()
+}
+
+fn names_in_dir(dir: &InstrumentedDir) -> Vec<String> {
+    dir.iter().map(|r| r.unwrap().file_name()).collect()
+}
+
+/// Creates directory "d" with the given files and returns the image with the offset of the directory data.
+fn create_dir_with_files(names: &[&str]) -> (Vec<u8>, usize) {
+    let state = StreamState::from_img(FAT16_IMG);
+    let img = state.data();
+    {
+        let fs = remount(&state);
+        let dir = fs.root_dir().create_dir("d").unwrap();
+        for name in names {
+            dir.create_file(name).unwrap();
+        }
+    }
+    let new_img = state.data();
+    let old_dots = find_entries(&img, DOT_ENTRY);
+    let dot = *find_entries(&new_img, DOT_ENTRY)
+        .iter()
+        .find(|o| !old_dots.contains(o))
+        .unwrap();
+    // "." entry is the first entry of the directory
+    (new_img, dot)
+}
+
+/// Returns the offset of the end marker relative to the directory start.
+fn end_marker_offset(img: &[u8], dir_start: usize) -> usize {
+    (dir_start..img.len()).step_by(32).find(|&i| img[i] == 0).unwrap() - dir_start
+}
+
+/// Mounts the image, runs `f` on directory "d" and returns the modified image.
+fn with_dir<F: FnOnce(&InstrumentedDir)>(img: Vec<u8>, f: F) -> Vec<u8> {
+    let state = StreamState::new(img);
+    {
+        let fs = remount(&state);
+        f(&fs.root_dir().open_dir("d").unwrap());
+    }
+    state.data()
+}
+
+/// Checks that all entries before the end marker are live or deleted and that every live entry is listed.
+fn assert_end_marker(img: Vec<u8>, dir_start: usize, expected_names: &[&str]) {
+    let marker = end_marker_offset(&img, dir_start);
+    let deleted = (dir_start..dir_start + marker)
+        .step_by(32)
+        .filter(|&i| img[i] == 0xE5)
+        .count();
+    // lowercase 8.3 names do not need LFN entries so every name takes a single SFN entry
+    assert_eq!(marker, 32 * (expected_names.len() + deleted));
+    with_dir(img, |dir| assert_eq!(names_in_dir(dir), expected_names));
+}
+
+#[test]
+fn test_append_after_early_end_marker() {
+    let (mut img, dir_start) = create_dir_with_files(&["a", "b", "c", "e"]);
+    assert_eq!(end_marker_offset(&img, dir_start), 6 * 32);
+    // turn entries of "a" and all following entries into stale data after the end marker
+    img[dir_start + 2 * 32] = 0;
+    let img = with_dir(img, |dir| {
+        assert_eq!(names_in_dir(dir), [".", ".."]);
+        dir.create_file("new").unwrap();
+        assert_eq!(names_in_dir(dir), [".", "..", "new"]);
+    });
+    assert_eq!(end_marker_offset(&img, dir_start), 3 * 32);
+    assert_end_marker(img, dir_start, &[".", "..", "new"]);
+}
+
+#[test]
+fn test_append_moves_end_marker() {
+    let (img, dir_start) = create_dir_with_files(&["a", "b"]);
+    assert_end_marker(img.clone(), dir_start, &[".", "..", "a", "b"]);
+    let img = with_dir(img, |dir| {
+        dir.create_file("c").unwrap();
+    });
+    assert_eq!(end_marker_offset(&img, dir_start), 5 * 32);
+    assert_end_marker(img, dir_start, &[".", "..", "a", "b", "c"]);
+}
+
+#[test]
+fn test_reuse_of_deleted_entries_keeps_end_marker() {
+    let (img, dir_start) = create_dir_with_files(&["a", "b", "c"]);
+    let img = with_dir(img, |dir| dir.remove("b").unwrap());
+    assert_eq!(end_marker_offset(&img, dir_start), 5 * 32);
+    assert_end_marker(img.clone(), dir_start, &[".", "..", "a", "c"]);
+    let img = with_dir(img, |dir| {
+        dir.create_file("x").unwrap();
+    });
+    assert_eq!(end_marker_offset(&img, dir_start), 5 * 32);
+    assert_end_marker(img, dir_start, &[".", "..", "a", "x", "c"]);
+}
+
+#[test]
+fn test_append_filling_cluster() {
+    // 512 bytes cluster holds 16 entries: dot entries take 2 and 14 files take 14 slots
+    let names = [
+        "f0", "f1", "f2", "f3", "f4", "f5", "f6", "f7", "f8", "f9", "f10", "f11", "f12", "f13",
+    ];
+    let (img, dir_start) = create_dir_with_files(&names);
+    let mut expected_names = vec![".", ".."];
+    expected_names.extend_from_slice(&names);
+    // directory cluster is full so there is no end marker in it
+    assert!(img[dir_start..dir_start + 512].chunks(32).all(|e| e[0] != 0));
+    with_dir(img, |dir| assert_eq!(names_in_dir(dir), expected_names));
+}
+
+#[test]
+fn test_entry_count_stops_at_end_marker() {
+    let (mut img, dir_start) = create_dir_with_files(&["a", "b", "c"]);
+    let img2 = with_dir(img.clone(), |dir| {
+        dir.create_dir("sub").unwrap();
+        assert_eq!(dir.entry_count().unwrap(), (3, 1));
+    });
+    // stale entries after the end marker are not counted
+    img[dir_start + 3 * 32] = 0;
+    with_dir(img, |dir| assert_eq!(dir.entry_count().unwrap(), (1, 0)));
+    with_dir(img2, |dir| {
+        dir.remove("b").unwrap();
+        assert_eq!(dir.entry_count().unwrap(), (2, 1));
+    });
+}
+
+#[test]
+fn test_entry_count_in_full_cluster() {
+    let names = [
+        "f0", "f1", "f2", "f3", "f4", "f5", "f6", "f7", "f8", "f9", "f10", "f11", "f12", "f13",
+    ];
+    let (img, dir_start) = create_dir_with_files(&names);
+    assert!(img[dir_start..dir_start + 512].chunks(32).all(|e| e[0] != 0));
+    with_dir(img, |dir| assert_eq!(dir.entry_count().unwrap(), (14, 0)));
+}
@@ -0,0 +1,144 @@
+use std::io;
+use std::io::prelude::*;
+
+use fatfs::FsOptions;
+
+mod common;
+use common::{open_instrumented_fs, StreamState, FAT12_IMG, FAT16_IMG, FAT32_IMG};
+
+fn write_offsets(state: &StreamState) -> Vec<u64> {
+    state.writes().iter().map(|(offset, _)| *offset).collect()
+}
+
+fn test_read_does_not_write(img_path: &str) {
+    let (fs, state) = open_instrumented_fs(img_path, FsOptions::new());
+    let root_dir = fs.root_dir();
+    for path in &["short.txt", "long.txt", "very/long/path/test.txt"] {
+        let mut file = root_dir.open_file(path).unwrap();
+        let mut content = Vec::new();
+        file.read_to_end(&mut content).unwrap();
+        file.flush().unwrap();
+        file.seek(io::SeekFrom::Start(1)).unwrap();
+        file.read_exact(&mut [0; 4]).unwrap();
+        // a clone is flushed on drop too
+        drop(file.clone());
+    }
+    assert_eq!(write_offsets(&state), []);
+}
+
+#[test]
+fn test_read_does_not_write_fat12() {
+    test_read_does_not_write(FAT12_IMG)
+}
+
+#[test]
+fn test_read_does_not_write_fat16() {
+    test_read_does_not_write(FAT16_IMG)
+}
+
+#[test]
+fn test_read_does_not_write_fat32() {
+    test_read_does_not_write(FAT32_IMG)
+}
+
+#[test]
+fn test_read_writes_accessed_date_if_enabled() {
+    let (fs, state) = open_instrumented_fs(FAT16_IMG, FsOptions::new().update_accessed_date(true));
+    let root_dir = fs.root_dir();
+    let entry_pos = root_dir.entry("short.txt").unwrap().entry_pos();
+    let mut file = root_dir.open_file("short.txt").unwrap();
+    file.read_to_end(&mut Vec::new()).unwrap();
+    file.flush().unwrap();
+    // the date has not changed since the first flush
+    file.seek(io::SeekFrom::Start(0)).unwrap();
+    file.read_to_end(&mut Vec::new()).unwrap();
+    drop(file);
+    // apart from the entry only the volume dirty flag is written
+    let entry_writes: Vec<u64> = write_offsets(&state)
+        .into_iter()
+        .filter(|offset| offset / 512 == entry_pos / 512)
+        .collect();
+    assert_eq!(entry_writes, [entry_pos]);
+}
+
+#[test]
+fn test_flush_without_changes_does_not_write() {
+    let (fs, state) = open_instrumented_fs(FAT16_IMG, FsOptions::new());
+    let root_dir = fs.root_dir();
+    let entry_pos = root_dir.entry("short.txt").unwrap().entry_pos();
+    let mut file = root_dir.open_file("short.txt").unwrap();
+    file.seek(io::SeekFrom::End(0)).unwrap();
+    file.write_all(b"more").unwrap();
+    file.flush().unwrap();
+    let num_writes = state.writes().len();
+    assert_eq!(write_offsets(&state).last(), Some(&entry_pos));
+    file.flush().unwrap();
+    file.seek(io::SeekFrom::Start(0)).unwrap();
+    file.read_to_end(&mut Vec::new()).unwrap();
+    drop(file);
+    assert_eq!(state.writes().len(), num_writes);
+}
+
+fn entry_writes(state: &StreamState, entry_pos: u64) -> usize {
+    write_offsets(state)
+        .iter()
+        .filter(|offset| **offset == entry_pos)
+        .count()
+}
+
+fn test_sync_data(img_path: &str) {
+    let (fs, state) = open_instrumented_fs(img_path, FsOptions::new());
+    let root_dir = fs.root_dir();
+    let entry_pos = root_dir.entry("short.txt").unwrap().entry_pos();
+    let mut file = root_dir.open_file("short.txt").unwrap();
+    file.seek(io::SeekFrom::End(0)).unwrap();
+    for _ in 0..3 {
+        file.write_all(b"log\n").unwrap();
+        file.sync_data().unwrap();
+    }
+    // the data is on the storage but the entry still has the old size
+    assert_eq!(entry_writes(&state, entry_pos), 0);
+    assert_eq!(root_dir.entry("short.txt").unwrap().len(), 14);
+    file.sync_all().unwrap();
+    assert_eq!(entry_writes(&state, entry_pos), 1);
+    assert_eq!(root_dir.entry("short.txt").unwrap().len(), 14 + 3 * 4);
+    drop(file);
+    assert_eq!(entry_writes(&state, entry_pos), 1);
+    assert!(fs.check().unwrap().is_clean());
+}
+
+#[test]
+fn test_sync_data_fat12() {
+    test_sync_data(FAT12_IMG)
+}
+
+#[test]
+fn test_sync_data_fat16() {
+    test_sync_data(FAT16_IMG)
+}
+
+#[test]
+fn test_sync_data_fat32() {
+    test_sync_data(FAT32_IMG)
+}
+
+#[test]
+fn test_sync_data_writes_first_cluster() {
+    let (fs, state) = open_instrumented_fs(FAT16_IMG, FsOptions::new());
+    let root_dir = fs.root_dir();
+    let mut file = root_dir.create_file("new.log").unwrap();
+    let entry_pos = root_dir.entry("new.log").unwrap().entry_pos();
+    let num_writes = entry_writes(&state, entry_pos);
+    // the data could not be found without the first cluster stored in the entry
+    file.write_all(b"log\n").unwrap();
+    file.sync_data().unwrap();
+    assert_eq!(entry_writes(&state, entry_pos), num_writes + 1);
+    file.write_all(b"log\n").unwrap();
+    file.sync_data().unwrap();
+    assert_eq!(entry_writes(&state, entry_pos), num_writes + 1);
+    assert_eq!(root_dir.entry("new.log").unwrap().len(), 4);
+    // flush is the same as sync_all
+    file.flush().unwrap();
+    assert_eq!(entry_writes(&state, entry_pos), num_writes + 2);
+    assert_eq!(root_dir.entry("new.log").unwrap().len(), 8);
+}
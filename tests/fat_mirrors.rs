@@ -0,0 +1,139 @@
+use std::fs;
+use std::io;
+use std::io::prelude::*;
+
+use fatfs::FsOptions;
+
+mod common;
+use common::{init_logger, Layout, FAT12_IMG, FAT16_IMG, FAT32_IMG};
+
+/// Returns byte ranges of all FAT copies.
+fn fat_ranges(img: &[u8]) -> Vec<(usize, usize)> {
+    let layout = Layout::read(img);
+    (0..layout.fats)
+        .map(|i| {
+            let begin = (layout.fat_start + i * layout.fat_size) as usize;
+            (begin, begin + layout.fat_size as usize)
+        })
+        .collect()
+}
+
+/// Creates fragmented files, frees some of them and truncates another one.
+fn modify_fs(img: &mut Vec<u8>) {
+    let fs = fatfs::FileSystem::new(io::Cursor::new(img), FsOptions::new()).unwrap();
+    let cluster_size = fs.cluster_size() as usize;
+    let root_dir = fs.root_dir();
+    let mut files = (0..3)
+        .map(|i| root_dir.create_file(&format!("{}.bin", i)).unwrap())
+        .collect::<Vec<_>>();
+    for _ in 0..4 {
+        for file in &mut files {
+            file.write_all(&vec![0xAB; cluster_size]).unwrap();
+        }
+    }
+    files[2].seek(io::SeekFrom::Start(cluster_size as u64)).unwrap();
+    files[2].truncate().unwrap();
+    drop(files);
+    root_dir.remove("1.bin").unwrap();
+    root_dir.remove("long.txt").unwrap();
+    root_dir.create_dir("new-dir").unwrap();
+    drop(root_dir);
+    fs.unmount().unwrap();
+}
+
+fn test_fats_are_equal(img_path: &str) {
+    init_logger();
+    let mut img = fs::read(img_path).unwrap();
+    let orig_img = img.clone();
+    modify_fs(&mut img);
+    let ranges = fat_ranges(&img);
+    assert_eq!(ranges.len(), 2);
+    let (fat1, fat2) = (ranges[0], ranges[1]);
+    assert_ne!(&img[fat1.0..fat1.1], &orig_img[fat1.0..fat1.1]);
+    assert_eq!(&img[fat1.0..fat1.1], &img[fat2.0..fat2.1]);
+}
+
+#[test]
+fn test_fats_are_equal_fat12() {
+    test_fats_are_equal(FAT12_IMG)
+}
+
+#[test]
+fn test_fats_are_equal_fat16() {
+    test_fats_are_equal(FAT16_IMG)
+}
+
+#[test]
+fn test_fats_are_equal_fat32() {
+    test_fats_are_equal(FAT32_IMG)
+}
+
+#[test]
+fn test_only_active_fat_is_written_without_mirroring() {
+    init_logger();
+    let mut img = fs::read(FAT32_IMG).unwrap();
+    // ExtFlags: mirroring disabled (bit 7), FAT #2 is active
+    img[40..42].copy_from_slice(&0x0081_u16.to_le_bytes());
+    let orig_img = img.clone();
+    modify_fs(&mut img);
+    let ranges = fat_ranges(&img);
+    let (fat1, fat2) = (ranges[0], ranges[1]);
+    assert_eq!(&img[fat1.0..fat1.1], &orig_img[fat1.0..fat1.1]);
+    assert_ne!(&img[fat2.0..fat2.1], &orig_img[fat2.0..fat2.1]);
+}
+
+/// Returns an image with mirroring disabled and the second FAT active. The first FAT is filled with garbage.
+fn active_fat1_img() -> Vec<u8> {
+    let mut img = fs::read(FAT32_IMG).unwrap();
+    img[40..42].copy_from_slice(&0x0081_u16.to_le_bytes());
+    let fat1 = fat_ranges(&img)[0];
+    for b in &mut img[fat1.0 + 8..fat1.1] {
+        *b = 0xFF;
+    }
+    img
+}
+
+#[test]
+fn test_active_fat_is_used() {
+    init_logger();
+    let mut img = active_fat1_img();
+    let orig_img = img.clone();
+    {
+        let fs = fatfs::FileSystem::new(io::Cursor::new(&mut img), FsOptions::new()).unwrap();
+        // long.txt spans multiple clusters
+        let mut content = String::new();
+        fs.root_dir()
+            .open_file("long.txt")
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        assert_eq!(content, "Rust is cool!\n".repeat(1000));
+        let mut file = fs.root_dir().create_file("new.txt").unwrap();
+        file.write_all(content.as_bytes()).unwrap();
+        drop(file);
+        assert!(fs.check().unwrap().is_clean());
+        fs.unmount().unwrap();
+    }
+    let fat1 = fat_ranges(&img)[0];
+    assert_eq!(&img[fat1.0..fat1.1], &orig_img[fat1.0..fat1.1]);
+    let fs = fatfs::FileSystem::new(io::Cursor::new(&mut img), FsOptions::new()).unwrap();
+    // the in-memory copy is read from the active FAT too
+    fs.prefetch_fat().unwrap();
+    let mut content = String::new();
+    fs.root_dir()
+        .open_file("new.txt")
+        .unwrap()
+        .read_to_string(&mut content)
+        .unwrap();
+    assert_eq!(content, "Rust is cool!\n".repeat(1000));
+}
+
+#[test]
+fn test_invalid_active_fat() {
+    init_logger();
+    let mut img = fs::read(FAT32_IMG).unwrap();
+    // the volume has only two FATs
+    img[40..42].copy_from_slice(&0x0082_u16.to_le_bytes());
+    let result = fatfs::FileSystem::new(io::Cursor::new(img), FsOptions::new());
+    assert!(matches!(result, Err(fatfs::Error::CorruptedFileSystem)));
+}
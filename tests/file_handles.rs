@@ -0,0 +1,157 @@
+use std::io;
+use std::io::prelude::*;
+
+mod common;
+use common::{open_fs, Dir, FAT12_IMG, FAT16_IMG, FAT32_IMG};
+
+const TEST_STR: &str = "Rust is cool!\n";
+
+fn read_to_string(dir: &Dir, path: &str) -> String {
+    let mut content = String::new();
+    dir.open_file(path).unwrap().read_to_string(&mut content).unwrap();
+    content
+}
+
+fn test_append_through_two_handles(img_path: &str, flush_a_first: bool) {
+    let fs = open_fs(img_path);
+    let root_dir = fs.root_dir();
+    let mut file_a = root_dir.open_file("short.txt").unwrap();
+    let mut file_b = root_dir.open_file("short.txt").unwrap();
+    file_a.seek(io::SeekFrom::End(0)).unwrap();
+    file_a.write_all(b"A").unwrap();
+    // the second handle sees the new size
+    assert_eq!(file_b.seek(io::SeekFrom::End(0)).unwrap(), TEST_STR.len() as u64 + 1);
+    // append more than a cluster so both handles allocate clusters
+    file_b.write_all(&[b'B'; 600]).unwrap();
+    file_a.seek(io::SeekFrom::End(0)).unwrap();
+    file_a.write_all(b"A").unwrap();
+    if flush_a_first {
+        drop(file_a);
+        drop(file_b);
+    } else {
+        drop(file_b);
+        drop(file_a);
+    }
+    let expected = format!("{}A{}A", TEST_STR, "B".repeat(600));
+    assert_eq!(read_to_string(&root_dir, "short.txt"), expected);
+    assert!(fs.check().unwrap().is_clean());
+}
+
+#[test]
+fn test_append_through_two_handles_fat12() {
+    test_append_through_two_handles(FAT12_IMG, true);
+    test_append_through_two_handles(FAT12_IMG, false);
+}
+
+#[test]
+fn test_append_through_two_handles_fat16() {
+    test_append_through_two_handles(FAT16_IMG, true);
+    test_append_through_two_handles(FAT16_IMG, false);
+}
+
+#[test]
+fn test_append_through_two_handles_fat32() {
+    test_append_through_two_handles(FAT32_IMG, true);
+    test_append_through_two_handles(FAT32_IMG, false);
+}
+
+#[test]
+fn test_append_mode_handles() {
+    let fs = open_fs(FAT16_IMG);
+    let root_dir = fs.root_dir();
+    let mut file_a = root_dir.open_options().append(true).open("short.txt").unwrap();
+    let mut file_b = file_a.clone();
+    for _ in 0..3 {
+        file_a.write_all(b"a").unwrap();
+        file_b.write_all(b"b").unwrap();
+    }
+    drop(file_a);
+    drop(file_b);
+    assert_eq!(read_to_string(&root_dir, "short.txt"), format!("{}ababab", TEST_STR));
+}
+
+#[test]
+fn test_read_through_stale_handle() {
+    let fs = open_fs(FAT16_IMG);
+    let root_dir = fs.root_dir();
+    let mut reader = root_dir.open_file("short.txt").unwrap();
+    let mut content = String::new();
+    reader.read_to_string(&mut content).unwrap();
+    assert_eq!(content, TEST_STR);
+    let mut writer = root_dir.open_file("short.txt").unwrap();
+    writer.seek(io::SeekFrom::End(0)).unwrap();
+    writer.write_all(b"more").unwrap();
+    // the reader does not stop at the old end of file
+    content.clear();
+    reader.read_to_string(&mut content).unwrap();
+    assert_eq!(content, "more");
+}
+
+#[test]
+fn test_new_file_written_through_two_handles() {
+    let fs = open_fs(FAT16_IMG);
+    let root_dir = fs.root_dir();
+    let mut file_a = root_dir.create_file("new.txt").unwrap();
+    let mut file_b = root_dir.open_file("new.txt").unwrap();
+    // the first cluster allocated through one handle is used by the other one
+    file_a.write_all(b"first ").unwrap();
+    file_b.seek(io::SeekFrom::End(0)).unwrap();
+    file_b.write_all(b"second").unwrap();
+    drop(file_b);
+    drop(file_a);
+    assert_eq!(read_to_string(&root_dir, "new.txt"), "first second");
+    assert!(fs.check().unwrap().is_clean());
+}
+
+#[test]
+fn test_truncate_through_other_handle() {
+    let fs = open_fs(FAT16_IMG);
+    let root_dir = fs.root_dir();
+    let mut file_a = root_dir.open_file("short.txt").unwrap();
+    file_a.write_all(&[b'a'; 2000]).unwrap();
+    let mut file_b = root_dir.open_file("short.txt").unwrap();
+    file_b.set_len(100).unwrap();
+    // the position of the first handle is moved to the new end
+    assert_eq!(file_a.stream_position().unwrap(), 100);
+    assert_eq!(file_a.read(&mut [0; 10]).unwrap(), 0);
+    file_a.write_all(b"end").unwrap();
+    drop(file_a);
+    drop(file_b);
+    assert_eq!(root_dir.entry("short.txt").unwrap().len(), 103);
+    assert!(fs.check().unwrap().is_clean());
+
+    // a file truncated to zero gets a new first cluster
+    let mut file_a = root_dir.open_file("short.txt").unwrap();
+    file_a.seek(io::SeekFrom::Start(50)).unwrap();
+    let mut file_b = root_dir.open_file("short.txt").unwrap();
+    file_b.truncate().unwrap();
+    file_b.write_all(b"new").unwrap();
+    assert_eq!(file_a.stream_position().unwrap(), 3);
+    file_a.seek(io::SeekFrom::Start(0)).unwrap();
+    let mut content = String::new();
+    file_a.read_to_string(&mut content).unwrap();
+    assert_eq!(content, "new");
+    drop(file_a);
+    drop(file_b);
+    assert_eq!(read_to_string(&root_dir, "short.txt"), "new");
+    assert!(fs.check().unwrap().is_clean());
+}
+
+#[test]
+fn test_removed_file_handle_does_not_affect_new_file() {
+    let fs = open_fs(FAT16_IMG);
+    let root_dir = fs.root_dir();
+    let mut old_file = root_dir.create_file("a.txt").unwrap();
+    old_file.write_all(b"old content").unwrap();
+    old_file.flush().unwrap();
+    let entry_pos = root_dir.entry("a.txt").unwrap().entry_pos();
+    root_dir.remove("a.txt").unwrap();
+    // the new file reuses the entry slot
+    let mut new_file = root_dir.create_file("b.txt").unwrap();
+    assert_eq!(root_dir.entry("b.txt").unwrap().entry_pos(), entry_pos);
+    assert_eq!(new_file.seek(io::SeekFrom::End(0)).unwrap(), 0);
+    new_file.write_all(b"new").unwrap();
+    drop(new_file);
+    drop(old_file);
+    assert_eq!(read_to_string(&root_dir, "b.txt"), "new");
+}
@@ -0,0 +1,190 @@
+use std::fs;
+use std::io;
+use std::io::prelude::*;
+
+use fatfs::{FilenamePolicy, FsOptions, WindowsNameRule};
+
+mod common;
+use common::{init_logger, mount_with_options, open_fs_with_options, FileSystem, FAT16_IMG};
+
+const TEST_STR: &str = "Rust is cool!\n";
+
+fn list_root_dir(fs: &FileSystem) -> Vec<String> {
+    fs.root_dir().iter().map(|r| r.unwrap().file_name()).collect()
+}
+
+/// Returns an image containing `bad*name.txt` - a name rejected by the strict policy.
+fn img_with_invalid_name() -> Vec<u8> {
+    let mut img = fs::read(FAT16_IMG).unwrap();
+    {
+        let fs = fatfs::FileSystem::new(io::Cursor::new(&mut img), FsOptions::new()).unwrap();
+        let mut file = fs.root_dir().create_file("badXname.txt").unwrap();
+        file.write_all(TEST_STR.as_bytes()).unwrap();
+        drop(file);
+        fs.unmount().unwrap();
+    }
+    // replace `X` by `*` in the LFN entry - the checksum only covers the short name so it stays valid
+    let pattern: Vec<u8> = "badXn".encode_utf16().flat_map(u16::to_le_bytes).collect();
+    let pos = img.windows(pattern.len()).position(|w| w == &pattern[..]).unwrap();
+    img[pos + 6] = b'*';
+    img
+}
+
+fn assert_unsupported_character<T>(result: Result<T, fatfs::Error<io::Error>>) {
+    match result {
+        Err(fatfs::Error::UnsupportedFileNameCharacter) => {}
+        Err(err) => panic!("unexpected error {:?}", err),
+        Ok(_) => panic!("invalid name has been accepted"),
+    }
+}
+
+#[test]
+fn test_existing_invalid_name_can_be_opened_renamed_and_removed() {
+    let fs = mount_with_options(
+        img_with_invalid_name(),
+        FsOptions::new().filename_policy(FilenamePolicy::Strict),
+    );
+    let root_dir = fs.root_dir();
+    assert!(list_root_dir(&fs).contains(&"bad*name.txt".to_string()));
+
+    let mut content = String::new();
+    root_dir
+        .open_file("bad*name.txt")
+        .unwrap()
+        .read_to_string(&mut content)
+        .unwrap();
+    assert_eq!(content, TEST_STR);
+
+    // destination name is still validated
+    assert_unsupported_character(root_dir.rename("bad*name.txt", &root_dir, "bad?name.txt"));
+    root_dir.rename("bad*name.txt", &root_dir, "good name.txt").unwrap();
+    assert!(!list_root_dir(&fs).contains(&"bad*name.txt".to_string()));
+    root_dir
+        .rename("good name.txt", &root_dir, "bad*name.txt")
+        .err()
+        .unwrap();
+    root_dir.remove("good name.txt").unwrap();
+    assert_eq!(list_root_dir(&fs).len(), 4);
+}
+
+#[test]
+fn test_existing_invalid_name_can_be_removed() {
+    let fs = mount_with_options(
+        img_with_invalid_name(),
+        FsOptions::new().filename_policy(FilenamePolicy::Strict),
+    );
+    fs.root_dir().remove("bad*name.txt").unwrap();
+    assert_eq!(list_root_dir(&fs).len(), 4);
+    assert!(fs.check().unwrap().is_clean());
+}
+
+#[test]
+fn test_strict_policy_rejects_invalid_characters() {
+    let fs = open_fs_with_options(FAT16_IMG, FsOptions::new().filename_policy(FilenamePolicy::Strict));
+    let root_dir = fs.root_dir();
+    assert_unsupported_character(root_dir.create_file("bad*name2.txt"));
+    assert_unsupported_character(root_dir.create_dir("what?"));
+    assert_unsupported_character(root_dir.create_file("a\"b<c>d|e:f\\g"));
+    assert_eq!(list_root_dir(&fs).len(), 4);
+}
+
+#[test]
+fn test_relaxed_policy_allows_posix_characters() {
+    let fs = open_fs_with_options(FAT16_IMG, FsOptions::new().filename_policy(FilenamePolicy::Relaxed));
+    let root_dir = fs.root_dir();
+    root_dir.create_file("bad*name2.txt").unwrap();
+    root_dir.create_dir("what?").unwrap();
+    root_dir.create_file("a\"b<c>d|e:f\\g").unwrap();
+    root_dir.create_file("emoji \u{1F980}.txt").unwrap();
+    assert_unsupported_character(root_dir.create_file("tab\tname"));
+    assert_unsupported_character(root_dir.create_file("nul\0name"));
+    let names = list_root_dir(&fs);
+    for name in &["bad*name2.txt", "what?", "a\"b<c>d|e:f\\g", "emoji \u{1F980}.txt"] {
+        assert!(names.contains(&name.to_string()), "{} not found", name);
+    }
+    root_dir.open_file("BAD*NAME2.TXT").unwrap();
+    root_dir.open_dir("what?").unwrap();
+}
+
+fn assert_windows_rule<T>(result: Result<T, fatfs::Error<io::Error>>, expected: WindowsNameRule) {
+    match result {
+        Err(fatfs::Error::UnsupportedWindowsFileName { rule }) => assert_eq!(rule, expected),
+        Err(err) => panic!("unexpected error {:?}", err),
+        Ok(_) => panic!("invalid name has been accepted"),
+    }
+}
+
+#[test]
+fn test_windows_name_rules() {
+    let fs = open_fs_with_options(FAT16_IMG, FsOptions::new().filename_policy(FilenamePolicy::Strict));
+    let root_dir = fs.root_dir();
+    for &name in &["CON", "prn", "Aux.txt", "nul.tar.gz", "nul .txt", "COM1", "lpt9.log"] {
+        assert_windows_rule(root_dir.create_file(name), WindowsNameRule::ReservedDeviceName);
+        assert_windows_rule(root_dir.create_dir(name), WindowsNameRule::ReservedDeviceName);
+        assert_windows_rule(
+            root_dir.rename("short.txt", &root_dir, name),
+            WindowsNameRule::ReservedDeviceName,
+        );
+    }
+    for &name in &["foo.", "bar ", "baz. ", "very/dir."] {
+        assert_windows_rule(root_dir.create_file(name), WindowsNameRule::TrailingDotOrSpace);
+        assert_windows_rule(root_dir.create_dir(name), WindowsNameRule::TrailingDotOrSpace);
+        assert_windows_rule(
+            root_dir.rename("short.txt", &root_dir, name),
+            WindowsNameRule::TrailingDotOrSpace,
+        );
+    }
+    assert_eq!(list_root_dir(&fs).len(), 4);
+    // names only resembling reserved ones are accepted
+    for &name in &[
+        "console",
+        "COM0",
+        "lpt10.txt",
+        "con1.txt",
+        "auxiliary",
+        "prn_.txt",
+        ".nul",
+        " foo",
+    ] {
+        root_dir.create_file(name).unwrap();
+    }
+    assert_eq!(list_root_dir(&fs).len(), 12);
+
+    let err: io::Error = root_dir.create_file("aux.txt").err().unwrap().into();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    assert_eq!(err.to_string(), "Unsupported file name: name is a reserved device name");
+}
+
+#[test]
+fn test_windows_name_rules_can_be_disabled() {
+    init_logger();
+    let mut img = fs::read(FAT16_IMG).unwrap();
+    {
+        let options = FsOptions::new().windows_name_rules(false);
+        let fs = fatfs::FileSystem::new(io::Cursor::new(&mut img), options).unwrap();
+        let root_dir = fs.root_dir();
+        let mut file = root_dir.create_file("aux.txt").unwrap();
+        file.write_all(TEST_STR.as_bytes()).unwrap();
+        drop(file);
+        root_dir.create_dir("dir.").unwrap();
+        root_dir.rename("short.txt", &root_dir, "short ").unwrap();
+    }
+    // existing entries can be used with the rules enabled
+    let fs = mount_with_options(img, FsOptions::new().filename_policy(FilenamePolicy::Strict));
+    let root_dir = fs.root_dir();
+    let names = list_root_dir(&fs);
+    for name in &["aux.txt", "dir.", "short "] {
+        assert!(names.contains(&name.to_string()), "{} not found", name);
+    }
+    let mut content = String::new();
+    root_dir
+        .open_file("aux.txt")
+        .unwrap()
+        .read_to_string(&mut content)
+        .unwrap();
+    assert_eq!(content, TEST_STR);
+    root_dir.rename("aux.txt", &root_dir, "aux2.txt").unwrap();
+    root_dir.remove("dir.").unwrap();
+    root_dir.remove("short ").unwrap();
+    assert!(fs.check().unwrap().is_clean());
+}
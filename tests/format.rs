@@ -58,6 +58,12 @@ fn basic_fs_test(fs: &FileSystem) {
 
     let filenames = root_dir.iter().map(|r| r.unwrap().file_name()).collect::<Vec<String>>();
     assert_eq!(filenames, ["subdir1", "new-name.txt"]);
+
+    drop(file);
+    drop(subdir1);
+    drop(subdir2);
+    drop(root_dir);
+    assert!(fs.check().expect("check").is_clean());
 }
 
 fn test_format_fs(opts: fatfs::FormatVolumeOptions, total_bytes: u64) -> FileSystem {
@@ -136,3 +142,80 @@ fn test_format_volume_label_and_id() {
     );
     assert_eq!(fs.volume_id(), 1234);
 }
+
+fn format_with_label(volume_label: [u8; 11], strict: bool) -> FileSystem {
+    let _ = env_logger::builder().is_test(true).try_init();
+    let storage_cur = io::Cursor::new(vec![0_u8; MB as usize]);
+    let mut buffered_stream = fatfs::StdIoWrapper::from(BufStream::new(storage_cur));
+    let opts = fatfs::FormatVolumeOptions::new().volume_label(volume_label);
+    fatfs::format_volume(&mut buffered_stream, opts).expect("format volume");
+    let options = fatfs::FsOptions::new().strict(strict);
+    fatfs::FileSystem::new(buffered_stream, options).expect("open fs")
+}
+
+#[test]
+fn test_file_named_like_volume_label() {
+    let fs = format_with_label(*b"DATA       ", false);
+    let root_dir = fs.root_dir();
+    assert!(matches!(root_dir.open_file("DATA"), Err(fatfs::Error::NotFound { .. })));
+    {
+        let mut file = root_dir.create_file("DATA").expect("create file");
+        file.write_all(TEST_STR.as_bytes()).unwrap();
+    }
+    let entries = root_dir.iter().map(|r| r.unwrap()).collect::<Vec<_>>();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].short_file_name(), "DATA");
+    assert!(entries[0].is_file());
+    assert_eq!(fs.read_volume_label_from_root_dir().unwrap(), Some("DATA".to_string()));
+    let mut content = String::new();
+    root_dir
+        .open_file("DATA")
+        .unwrap()
+        .read_to_string(&mut content)
+        .unwrap();
+    assert_eq!(content, TEST_STR);
+    root_dir.remove("DATA").expect("remove");
+    assert_eq!(fs.read_volume_label_from_root_dir().unwrap(), Some("DATA".to_string()));
+}
+
+#[test]
+fn test_file_named_like_volume_label_strict() {
+    let fs = format_with_label(*b"DATA       ", true);
+    let root_dir = fs.root_dir();
+    assert!(matches!(root_dir.create_file("data"), Err(fatfs::Error::AlreadyExists)));
+    assert!(matches!(root_dir.create_dir("DATA"), Err(fatfs::Error::AlreadyExists)));
+    root_dir.create_file("data.txt").expect("create file");
+    root_dir
+        .create_dir("sub")
+        .unwrap()
+        .create_file("DATA")
+        .expect("create file in subdir");
+    assert_eq!(fs.read_volume_label_from_root_dir().unwrap(), Some("DATA".to_string()));
+}
+
+#[test]
+fn test_format_fat32_backup_boot_sector() {
+    let _ = env_logger::builder().is_test(true).try_init();
+    let mut storage = fatfs::StdIoWrapper::from(io::Cursor::new(vec![0xD1_u8; 64 * MB as usize]));
+    let opts = fatfs::FormatVolumeOptions::new()
+        .fat_type(fatfs::FatType::Fat32)
+        .volume_id(0xCAFE);
+    fatfs::format_volume(&mut storage, opts).expect("format volume");
+    let img = storage.into_inner().into_inner();
+    // boot signature
+    assert_eq!(&img[510..512], &[0x55, 0xAA]);
+    // the backup boot sector is at the default sector 6 and FSInfo at sector 1
+    assert_eq!(&img[6 * 512..7 * 512], &img[..512]);
+    assert_eq!(&img[512..516], b"RRaA");
+    assert_eq!(&img[512 + 484..512 + 488], b"rrAa");
+
+    let fs = fatfs::FileSystem::new(io::Cursor::new(img), fatfs::FsOptions::new()).expect("open fs");
+    assert_eq!(fs.fat_type(), fatfs::FatType::Fat32);
+    assert_eq!(fs.volume_id(), 0xCAFE);
+    fs.root_dir()
+        .create_file("test.txt")
+        .unwrap()
+        .write_all(TEST_STR.as_bytes())
+        .unwrap();
+    assert!(fs.check().unwrap().is_clean());
+}
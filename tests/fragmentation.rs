@@ -0,0 +1,87 @@
+use std::io::prelude::*;
+
+mod common;
+use common::{open_fs, FileSystem, FAT16_IMG};
+
+/// Writes clusters to `a.bin` and `b.bin` alternately so both files are fragmented.
+///
+/// Allocation order is `a b a b a b a a` so `a.bin` has 4 extents and `b.bin` has 3 extents.
+fn create_fragmented_files(fs: &FileSystem) {
+    let cluster = vec![0xAB_u8; fs.cluster_size() as usize];
+    let root_dir = fs.root_dir();
+    let mut a = root_dir.create_file("a.bin").unwrap();
+    let mut b = root_dir.create_file("b.bin").unwrap();
+    for i in 0..5 {
+        a.write_all(&cluster).unwrap();
+        if i < 3 {
+            b.write_all(&cluster).unwrap();
+        }
+    }
+}
+
+fn file_fragmentation(fs: &FileSystem, path: &str) -> fatfs::FragInfo {
+    fs.root_dir().open_file(path).unwrap().fragmentation().unwrap()
+}
+
+#[test]
+fn test_file_fragmentation() {
+    let fs = open_fs(FAT16_IMG);
+    create_fragmented_files(&fs);
+    let cluster_size = u64::from(fs.cluster_size());
+
+    let a = file_fragmentation(&fs, "a.bin");
+    assert_eq!(a.extents(), 4);
+    assert_eq!(a.largest_extent(), 2 * cluster_size);
+    assert_eq!(a.smallest_extent(), cluster_size);
+    assert_eq!(a.allocated_bytes(), 5 * cluster_size);
+
+    let b = file_fragmentation(&fs, "b.bin");
+    assert_eq!(b.extents(), 3);
+    assert_eq!(b.largest_extent(), cluster_size);
+    assert_eq!(b.smallest_extent(), cluster_size);
+    let expected_score = 3.0 * 1_048_576.0 / (3 * cluster_size) as f64;
+    assert!((b.score() - expected_score).abs() < 1e-9);
+}
+
+#[test]
+fn test_file_fragmentation_contiguous_and_empty() {
+    let fs = open_fs(FAT16_IMG);
+    let long = file_fragmentation(&fs, "long.txt");
+    assert_eq!(long.extents(), 1);
+    assert_eq!(long.largest_extent(), long.allocated_bytes());
+    assert_eq!(long.smallest_extent(), long.allocated_bytes());
+
+    fs.root_dir().create_file("empty.txt").unwrap();
+    let empty = file_fragmentation(&fs, "empty.txt");
+    assert_eq!(empty, fatfs::FragInfo::default());
+    assert_eq!(empty.score(), 0.0);
+}
+
+#[test]
+fn test_fragmentation_report() {
+    let fs = open_fs(FAT16_IMG);
+    create_fragmented_files(&fs);
+    let report = fs.fragmentation_report(3).unwrap();
+    assert_eq!(report.files(), 6);
+    assert_eq!(report.fragmented_files(), 2);
+    assert_eq!(report.extents(), 4 + 3 + 4);
+    let top: Vec<_> = report
+        .most_fragmented()
+        .iter()
+        .map(|f| (f.path(), f.info().extents()))
+        .collect();
+    // files with a single extent are ordered by path
+    assert_eq!(top, [("a.bin", 4), ("b.bin", 3), ("long.txt", 1)]);
+}
+
+#[test]
+fn test_fragmentation_report_without_files_list() {
+    let fs = open_fs(FAT16_IMG);
+    let report = fs.fragmentation_report(0).unwrap();
+    assert_eq!(report.files(), 4);
+    assert_eq!(report.fragmented_files(), 0);
+    assert!(report.most_fragmented().is_empty());
+    let usage = fs.usage_summary().unwrap();
+    let dirs_bytes = usage.allocated_bytes() - report.allocated_bytes();
+    assert_eq!(dirs_bytes, usage.dirs() * u64::from(fs.cluster_size()));
+}
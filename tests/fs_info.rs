@@ -0,0 +1,250 @@
+use std::fs;
+use std::io;
+use std::io::prelude::*;
+use std::mem;
+
+use fatfs::FsOptions;
+
+mod common;
+use common::{init_logger, FAT32_IMG};
+
+const TEST_STR: &str = "Hi there Rust programmer!\n";
+
+/// FAT32 volume geometry read from the boot sector.
+struct Geometry {
+    fs_info_offset: usize,
+    fat_offset: usize,
+    total_clusters: u32,
+}
+
+fn read_u32(img: &[u8], offset: usize) -> u32 {
+    let mut buf = [0_u8; 4];
+    buf.copy_from_slice(&img[offset..offset + 4]);
+    u32::from_le_bytes(buf)
+}
+
+fn read_u16(img: &[u8], offset: usize) -> usize {
+    usize::from(u16::from_le_bytes([img[offset], img[offset + 1]]))
+}
+
+fn geometry(img: &[u8]) -> Geometry {
+    let bytes_per_sector = read_u16(img, 11);
+    let sectors_per_cluster = img[13];
+    let reserved_sectors = read_u16(img, 14);
+    let fats = img[16] as usize;
+    let total_sectors = read_u32(img, 32);
+    let sectors_per_fat = read_u32(img, 36) as usize;
+    let first_data_sector = reserved_sectors + fats * sectors_per_fat;
+    Geometry {
+        fs_info_offset: read_u16(img, 48) * bytes_per_sector,
+        fat_offset: reserved_sectors * bytes_per_sector,
+        total_clusters: (total_sectors - first_data_sector as u32) / u32::from(sectors_per_cluster),
+    }
+}
+
+/// Returns (free cluster count, next free cluster) stored in the FS Information Sector.
+fn stored_fs_info(img: &[u8]) -> (u32, u32) {
+    let offset = geometry(img).fs_info_offset;
+    (read_u32(img, offset + 488), read_u32(img, offset + 492))
+}
+
+fn set_fs_info(img: &mut [u8], free_cluster_count: u32, next_free_cluster: u32) {
+    let offset = geometry(img).fs_info_offset;
+    img[offset + 488..offset + 492].copy_from_slice(&free_cluster_count.to_le_bytes());
+    img[offset + 492..offset + 496].copy_from_slice(&next_free_cluster.to_le_bytes());
+}
+
+fn is_cluster_free(img: &[u8], cluster: u32) -> bool {
+    read_u32(img, geometry(img).fat_offset + cluster as usize * 4) & 0x0FFF_FFFF == 0
+}
+
+/// Counts free clusters by walking the FAT.
+fn count_free_clusters(img: &[u8]) -> u32 {
+    let total_clusters = geometry(img).total_clusters;
+    (2..total_clusters + 2).filter(|&c| is_cluster_free(img, c)).count() as u32
+}
+
+/// Creates a file spanning multiple clusters and removes another one.
+fn modify_fs(img: Vec<u8>) -> Vec<u8> {
+    init_logger();
+    let mut stream = io::Cursor::new(img);
+    {
+        let fs = fatfs::FileSystem::new(&mut stream, FsOptions::new()).unwrap();
+        {
+            let root_dir = fs.root_dir();
+            let mut file = root_dir.create_file("new.txt").unwrap();
+            for _ in 0..100 {
+                file.write_all(TEST_STR.as_bytes()).unwrap();
+            }
+            drop(file);
+            root_dir.remove("long.txt").unwrap();
+        }
+        // free cluster count is recalculated if it is unknown
+        fs.stats().unwrap();
+        fs.unmount().unwrap();
+    }
+    stream.into_inner()
+}
+
+fn check_fs(img: Vec<u8>) {
+    let fs = fatfs::FileSystem::new(io::Cursor::new(img), FsOptions::new()).unwrap();
+    let mut content = String::new();
+    fs.root_dir()
+        .open_file("new.txt")
+        .unwrap()
+        .read_to_string(&mut content)
+        .unwrap();
+    assert_eq!(content, TEST_STR.repeat(100));
+    let mut content = String::new();
+    fs.root_dir()
+        .open_file("short.txt")
+        .unwrap()
+        .read_to_string(&mut content)
+        .unwrap();
+    assert_eq!(content, "Rust is cool!\n");
+}
+
+/// Checks that the filesystem works with the given FSInfo values and that stored values are correct after
+/// modifications.
+fn test_bogus_fs_info(free_cluster_count: u32, next_free_cluster: u32) {
+    let mut img = fs::read(FAT32_IMG).unwrap();
+    set_fs_info(&mut img, free_cluster_count, next_free_cluster);
+    let img = modify_fs(img);
+    let (stored_free_cluster_count, stored_next_free_cluster) = stored_fs_info(&img);
+    assert_eq!(stored_free_cluster_count, count_free_clusters(&img));
+    assert!(stored_next_free_cluster >= 2 && stored_next_free_cluster < geometry(&img).total_clusters + 2);
+    check_fs(img);
+}
+
+#[test]
+fn test_next_free_cluster_out_of_range() {
+    let total_clusters = geometry(&fs::read(FAT32_IMG).unwrap()).total_clusters;
+    test_bogus_fs_info(0xFFFF_FFFF, total_clusters + 2);
+    test_bogus_fs_info(0xFFFF_FFFF, total_clusters + 100);
+    test_bogus_fs_info(0xFFFF_FFFF, 0x0FFF_FFF0);
+}
+
+#[test]
+fn test_next_free_cluster_reserved() {
+    test_bogus_fs_info(0xFFFF_FFFF, 0);
+    test_bogus_fs_info(0xFFFF_FFFF, 1);
+}
+
+#[test]
+fn test_next_free_cluster_allocated() {
+    let img = fs::read(FAT32_IMG).unwrap();
+    // root directory cluster
+    assert!(!is_cluster_free(&img, 2));
+    test_bogus_fs_info(0xFFFF_FFFF, 2);
+}
+
+#[test]
+fn test_next_free_cluster_at_end() {
+    let total_clusters = geometry(&fs::read(FAT32_IMG).unwrap()).total_clusters;
+    test_bogus_fs_info(0xFFFF_FFFF, total_clusters + 1);
+}
+
+#[test]
+fn test_free_cluster_count_out_of_range() {
+    let total_clusters = geometry(&fs::read(FAT32_IMG).unwrap()).total_clusters;
+    test_bogus_fs_info(total_clusters + 1, 0xFFFF_FFFF);
+    test_bogus_fs_info(0xFFFF_FFF0, 0xFFFF_FFFF);
+}
+
+#[test]
+fn test_free_cluster_count_too_small() {
+    test_bogus_fs_info(0, 0xFFFF_FFFF);
+    test_bogus_fs_info(1, 0xFFFF_FFFF);
+}
+
+#[test]
+fn test_free_cluster_count_too_big() {
+    let total_clusters = geometry(&fs::read(FAT32_IMG).unwrap()).total_clusters;
+    let mut img = fs::read(FAT32_IMG).unwrap();
+    set_fs_info(&mut img, total_clusters, 0xFFFF_FFFF);
+    let mut stream = io::Cursor::new(img);
+    {
+        let fs = fatfs::FileSystem::new(&mut stream, FsOptions::new()).unwrap();
+        let mut file = fs.root_dir().create_file("big.bin").unwrap();
+        let buf = vec![0xAB_u8; 64 * 1024];
+        loop {
+            match file.write(&buf) {
+                Ok(0) | Err(_) => break,
+                Ok(_) => {}
+            }
+        }
+        drop(file);
+        // a full volume proves the stored count wrong
+        assert_eq!(fs.stats().unwrap().free_clusters(), 0);
+        fs.unmount().unwrap();
+    }
+    let img = stream.into_inner();
+    assert_eq!(count_free_clusters(&img), 0);
+    assert_eq!(stored_fs_info(&img).0, 0);
+}
+
+#[test]
+fn test_flush_writes_fs_info() {
+    init_logger();
+    let mut stream = io::Cursor::new(fs::read(FAT32_IMG).unwrap());
+    let free_clusters = count_free_clusters(stream.get_ref());
+    {
+        let fs = fatfs::FileSystem::new(&mut stream, FsOptions::new()).unwrap();
+        let cluster_size = fs.cluster_size() as usize;
+        let mut file = fs.root_dir().create_file("new.bin").unwrap();
+        file.write_all(&vec![0xAB; 3 * cluster_size]).unwrap();
+        drop(file);
+        fs.flush().unwrap();
+        // the volume is not unmounted
+        mem::forget(fs);
+    }
+    let (stored_free_cluster_count, stored_next_free_cluster) = stored_fs_info(stream.get_ref());
+    assert_eq!(stored_free_cluster_count, free_clusters - 3);
+    assert_eq!(stored_free_cluster_count, count_free_clusters(stream.get_ref()));
+    assert!(!is_cluster_free(stream.get_ref(), stored_next_free_cluster - 1));
+
+    stream.set_position(0);
+    {
+        let fs = fatfs::FileSystem::new(&mut stream, FsOptions::new()).unwrap();
+        // the stored count is not trusted because the volume was not unmounted - it is counted again
+        assert!(fs.read_status_flags().unwrap().dirty());
+        assert_eq!(fs.stats().unwrap().free_clusters(), free_clusters - 3);
+        fs.root_dir().remove("new.bin").unwrap();
+        fs.flush().unwrap();
+        mem::forget(fs);
+    }
+    assert_eq!(stored_fs_info(stream.get_ref()).0, free_clusters);
+}
+
+#[test]
+fn test_corrupted_fs_info() {
+    init_logger();
+    let mut img = fs::read(FAT32_IMG).unwrap();
+    let offset = geometry(&img).fs_info_offset;
+    // invalid lead signature
+    img[offset] = b'X';
+    let fs_info_sector = img[offset..offset + 512].to_vec();
+    let free_clusters = count_free_clusters(&img);
+    let mut stream = io::Cursor::new(img);
+    {
+        let fs = fatfs::FileSystem::new(&mut stream, FsOptions::new()).unwrap();
+        let mut file = fs.root_dir().create_file("new.txt").unwrap();
+        file.write_all(TEST_STR.as_bytes()).unwrap();
+        drop(file);
+        // the free cluster count is computed from the FAT
+        assert_eq!(fs.stats().unwrap().free_clusters(), free_clusters - 1);
+        fs.unmount().unwrap();
+    }
+    // the corrupted sector is not overwritten
+    let img = stream.into_inner();
+    assert_eq!(&img[offset..offset + 512], &fs_info_sector[..]);
+    assert_eq!(count_free_clusters(&img), free_clusters - 1);
+    let fs = fatfs::FileSystem::new(io::Cursor::new(img), FsOptions::new()).unwrap();
+    let mut content = String::new();
+    fs.root_dir()
+        .open_file("new.txt")
+        .unwrap()
+        .read_to_string(&mut content)
+        .unwrap();
+    assert_eq!(content, TEST_STR);
+}
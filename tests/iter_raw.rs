@@ -0,0 +1,130 @@
+use std::io::prelude::*;
+
+use fatfs::RawDirEntry;
+
+mod common;
+use common::{open_fs, Dir, FAT12_IMG, FAT16_IMG, FAT32_IMG};
+
+fn names_in_dir(dir: &Dir) -> Vec<String> {
+    dir.iter().map(|r| r.unwrap().file_name()).collect()
+}
+
+fn test_iter_raw(img_path: &str) {
+    let fs = open_fs(img_path);
+    let dir = fs.root_dir().create_dir("dir").unwrap();
+    dir.create_file("First File.txt").unwrap();
+    dir.create_file("Second File.txt")
+        .unwrap()
+        .write_all(b"second")
+        .unwrap();
+    dir.create_file("THIRD.TXT").unwrap();
+    let second = dir.entry("Second File.txt").unwrap();
+    let second_pos = second.entry_pos();
+    let third_pos = dir.entry("THIRD.TXT").unwrap().entry_pos();
+    dir.remove("Second File.txt").unwrap();
+    dir.remove("THIRD.TXT").unwrap();
+    let names = names_in_dir(&dir);
+
+    let entries: Vec<_> = dir.iter_raw().map(|r| r.unwrap()).collect();
+    assert_eq!(entries.len(), 6);
+    let live_names: Vec<_> = entries
+        .iter()
+        .filter_map(|e| match e {
+            RawDirEntry::Live(e) => Some(e.file_name()),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(live_names, [".", "..", "First File.txt"]);
+
+    let second = match &entries[3] {
+        RawDirEntry::Deleted(info) => info,
+        e => panic!("unexpected entry {:?}", e),
+    };
+    assert_eq!(&second.short_name_tail(), b"ECOND~1TXT");
+    assert_eq!(second.recovered_first_byte(), Some(b'S'));
+    let lfn = String::from_utf16(second.long_file_name_as_ucs2_units().unwrap()).unwrap();
+    assert_eq!(lfn, "Second File.txt");
+    assert!(!second.is_dir());
+    assert_eq!(second.len(), 6);
+    assert!(second.first_cluster().is_some());
+    assert!(second.modification_time().is_some());
+    assert_eq!(second.entry_pos(), second_pos);
+    assert_eq!(second.slot_count(), 3);
+
+    // a short name without long name entries
+    let third = match &entries[4] {
+        RawDirEntry::Deleted(info) => info,
+        e => panic!("unexpected entry {:?}", e),
+    };
+    assert_eq!(&third.short_name_tail(), b"HIRD   TXT");
+    assert_eq!(third.recovered_first_byte(), None);
+    assert!(third.long_file_name_as_ucs2_units().is_none());
+    assert_eq!(third.first_cluster(), None);
+    assert_eq!(third.entry_pos(), third_pos);
+    assert_eq!(third.slot_count(), 1);
+
+    assert!(matches!(entries[5], RawDirEntry::End));
+    // the regular iterator is not affected
+    assert_eq!(names_in_dir(&dir), names);
+}
+
+#[test]
+fn test_iter_raw_fat12() {
+    test_iter_raw(FAT12_IMG)
+}
+
+#[test]
+fn test_iter_raw_fat16() {
+    test_iter_raw(FAT16_IMG)
+}
+
+#[test]
+fn test_iter_raw_fat32() {
+    test_iter_raw(FAT32_IMG)
+}
+
+#[test]
+fn test_iter_raw_without_deleted_entries() {
+    let fs = open_fs(FAT16_IMG);
+    let root_dir = fs.root_dir();
+    let mut iter = root_dir.iter_raw();
+    let mut names = Vec::new();
+    for r in &mut iter {
+        match r.unwrap() {
+            RawDirEntry::Live(e) => names.push(e.file_name()),
+            RawDirEntry::Deleted(info) => panic!("unexpected deleted entry {:?}", info),
+            RawDirEntry::End => break,
+        }
+    }
+    // the volume label is returned too
+    let expected: Vec<_> = root_dir
+        .iter_with_volume_labels()
+        .map(|r| r.unwrap().file_name())
+        .collect();
+    assert_eq!(names, expected);
+    assert!(iter.next().is_none());
+}
+
+#[test]
+fn test_iter_raw_reused_slots() {
+    let fs = open_fs(FAT16_IMG);
+    let dir = fs.root_dir().create_dir("dir").unwrap();
+    dir.create_file("A Much Longer File Name.txt").unwrap();
+    dir.remove("A Much Longer File Name.txt").unwrap();
+    // the new entry overwrites the first deleted long name entry
+    dir.create_file("NEW.TXT").unwrap();
+    let deleted: Vec<_> = dir
+        .iter_raw()
+        .filter_map(|r| match r.unwrap() {
+            RawDirEntry::Deleted(info) => Some(info),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(deleted.len(), 1);
+    // remaining long name entries do not form the whole name
+    assert_eq!(&deleted[0].short_name_tail(), b"MUCHL~1TXT");
+    let lfn = String::from_utf16(deleted[0].long_file_name_as_ucs2_units().unwrap()).unwrap();
+    assert_eq!(lfn, "A Much Longer File Name.tx");
+    assert_eq!(deleted[0].recovered_first_byte(), Some(b'A'));
+    assert_eq!(deleted[0].slot_count(), 3);
+}
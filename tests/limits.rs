@@ -0,0 +1,109 @@
+use std::io;
+
+use fatfs::{FsLimits, FsOptions};
+
+mod common;
+use common::{open_fs_with_options, FileSystem, FAT16_IMG};
+
+fn open_fs(limits: FsLimits) -> FileSystem {
+    open_fs_with_options(FAT16_IMG, FsOptions::new().limits(limits))
+}
+
+fn read_file(fs: &FileSystem, path: &str) -> Result<Vec<u8>, fatfs::Error<io::Error>> {
+    let mut file = fs.root_dir().open_file(path)?;
+    let mut content = Vec::new();
+    let mut buf = [0_u8; 1024];
+    loop {
+        let n = fatfs::Read::read(&mut file, &mut buf)?;
+        if n == 0 {
+            return Ok(content);
+        }
+        content.extend_from_slice(&buf[..n]);
+    }
+}
+
+fn list_root_dir(fs: &FileSystem) -> Result<Vec<String>, fatfs::Error<io::Error>> {
+    fs.root_dir().iter().map(|r| r.map(|e| e.file_name())).collect()
+}
+
+fn assert_limit_exceeded<T>(result: Result<T, fatfs::Error<io::Error>>) {
+    match result {
+        Err(fatfs::Error::LimitExceeded) => {}
+        Err(err) => panic!("unexpected error {:?}", err),
+        Ok(_) => panic!("limit has not been enforced"),
+    }
+}
+
+#[test]
+fn test_default_limits() {
+    let fs = open_fs(FsLimits::default());
+    assert_eq!(list_root_dir(&fs).unwrap().len(), 4);
+    assert_eq!(read_file(&fs, "long.txt").unwrap().len(), 14000);
+    assert_eq!(read_file(&fs, "very/long/path/test.txt").unwrap(), b"Rust is cool!\n");
+    let name = "a".repeat(255);
+    fs.root_dir().create_file(&name).unwrap();
+    assert_eq!(fs.usage_summary().unwrap().max_depth(), 4);
+}
+
+#[test]
+fn test_max_file_size_limit() {
+    let fs = open_fs(FsLimits {
+        max_file_size: 1000,
+        ..FsLimits::default()
+    });
+    assert_limit_exceeded(read_file(&fs, "long.txt"));
+    assert_eq!(read_file(&fs, "short.txt").unwrap(), b"Rust is cool!\n");
+    let mut file = fs.root_dir().create_file("new.txt").unwrap();
+    assert_limit_exceeded(fatfs::Write::write_all(&mut file, &[0xAB; 2000]));
+    drop(file);
+    assert_eq!(read_file(&fs, "new.txt").unwrap(), vec![0xAB; 1000]);
+    let mut file = fs.root_dir().open_file("new.txt").unwrap();
+    assert_limit_exceeded(file.set_len(1001));
+    assert_limit_exceeded(file.preallocate(1001, false));
+    file.set_len(500).unwrap();
+}
+
+#[test]
+fn test_max_dir_entries_limit() {
+    // root directory contains 9 LFN and SFN entries and a volume label
+    let fs = open_fs(FsLimits {
+        max_dir_entries: 5,
+        ..FsLimits::default()
+    });
+    assert_limit_exceeded(list_root_dir(&fs));
+    let fs = open_fs(FsLimits {
+        max_dir_entries: 12,
+        ..FsLimits::default()
+    });
+    assert_eq!(list_root_dir(&fs).unwrap().len(), 4);
+    // lowercase 8.3 names take a single entry
+    fs.root_dir().create_file("a.txt").unwrap();
+    fs.root_dir().create_file("b.txt").unwrap();
+    assert_limit_exceeded(fs.root_dir().create_file("c.txt"));
+}
+
+#[test]
+fn test_max_name_len_limit() {
+    let fs = open_fs(FsLimits {
+        max_name_len: 16,
+        ..FsLimits::default()
+    });
+    // "very-long-dir-name" is 18 characters long
+    assert_limit_exceeded(list_root_dir(&fs));
+    assert_limit_exceeded(fs.root_dir().create_file("very/seventeen-chars.x"));
+    fs.root_dir().create_file("very/sixteen-chars.xy").unwrap();
+}
+
+#[test]
+fn test_max_dir_depth_limit() {
+    let fs = open_fs(FsLimits {
+        max_dir_depth: 2,
+        ..FsLimits::default()
+    });
+    assert_limit_exceeded(read_file(&fs, "very/long/path/test.txt"));
+    assert_limit_exceeded(fs.root_dir().create_dir("very/long/path/new"));
+    assert_limit_exceeded(fs.usage_summary());
+    fs.root_dir().open_dir("very/long/path").unwrap();
+    let content = read_file(&fs, "very-long-dir-name/very-long-file-name.txt").unwrap();
+    assert_eq!(content, b"Rust is cool!\n");
+}
@@ -0,0 +1,233 @@
+use std::io;
+use std::io::prelude::*;
+
+use fatfs::{Dir, OemCpConverter, ReadWriteSeek, TimeProvider};
+
+mod common;
+use common::{remount, InstrumentedDir, StreamState, FAT12_IMG, FAT16_IMG, FAT32_IMG};
+
+const TEST_STR: &str = "Hi there Rust programmer!\n";
+
+fn open_dir<'a, IO: ReadWriteSeek, TP: TimeProvider, OCC: OemCpConverter>(
+    fs: &'a fatfs::FileSystem<IO, TP, OCC>,
+    path: &str,
+) -> Dir<'a, IO, TP, OCC> {
+    if path.is_empty() {
+        fs.root_dir()
+    } else {
+        fs.root_dir().open_dir(path).unwrap()
+    }
+}
+
+/// Returns a name with the given number of characters: "abcd...zabc...".
+fn make_name(len: usize, first: u8) -> String {
+    (0..len)
+        .map(|i| char::from(b'a' + (first - b'a' + (i % 26) as u8) % 26))
+        .collect()
+}
+
+fn names_in_dir<IO: ReadWriteSeek, TP: TimeProvider, OCC: OemCpConverter>(dir: &Dir<IO, TP, OCC>) -> Vec<String> {
+    dir.iter().map(|r| r.unwrap().file_name()).collect()
+}
+
+fn read_to_string(dir: &InstrumentedDir, name: &str) -> String {
+    let mut content = String::new();
+    dir.open_file(name).unwrap().read_to_string(&mut content).unwrap();
+    content
+}
+
+fn assert_invalid_name_length<T>(result: Result<T, fatfs::Error<io::Error>>) {
+    match result {
+        Err(fatfs::Error::InvalidFileNameLength) => {}
+        Err(err) => panic!("unexpected error {:?}", err),
+        Ok(_) => panic!("name length has not been validated"),
+    }
+}
+
+/// Creates, lists, opens, renames and removes a file and a directory with a name of the given length.
+fn test_name_lifecycle(img_path: &str, dir_path: &str, len: usize) {
+    let state = StreamState::from_img(img_path);
+    let file_name = make_name(len, b'a');
+    let dir_name = make_name(len, b'b');
+    let new_file_name = make_name(len, b'c');
+    let (orig_names, mut expected_names) = {
+        let fs = remount(&state);
+        let dir = open_dir(&fs, dir_path);
+        let orig_names = names_in_dir(&dir);
+        dir.create_file(&file_name)
+            .unwrap()
+            .write_all(TEST_STR.as_bytes())
+            .unwrap();
+        dir.create_dir(&dir_name).unwrap().create_file("x").unwrap();
+        let mut expected_names = orig_names.clone();
+        expected_names.push(file_name.clone());
+        expected_names.push(dir_name.clone());
+        assert_eq!(names_in_dir(&dir), expected_names);
+        assert_eq!(read_to_string(&dir, &file_name), TEST_STR);
+        assert_eq!(names_in_dir(&dir.open_dir(&dir_name).unwrap()), [".", "..", "x"]);
+
+        dir.rename(&file_name, &dir, &new_file_name).unwrap();
+        assert_eq!(read_to_string(&dir, &new_file_name), TEST_STR);
+        assert!(dir.open_file(&file_name).is_err());
+        (orig_names, expected_names)
+    };
+
+    // names are read back correctly after remounting
+    let fs = remount(&state);
+    let dir = open_dir(&fs, dir_path);
+    expected_names.retain(|n| *n != file_name);
+    expected_names.push(new_file_name.clone());
+    let mut names = names_in_dir(&dir);
+    names.sort();
+    expected_names.sort();
+    assert_eq!(names, expected_names);
+
+    dir.remove(&new_file_name).unwrap();
+    dir.remove(&format!("{}/x", dir_name)).unwrap();
+    dir.remove(&dir_name).unwrap();
+    assert_eq!(names_in_dir(&dir), orig_names);
+}
+
+/// Checks that operations using a too long name fail without modifying the filesystem.
+fn test_too_long_name(img_path: &str, dir_path: &str) {
+    let state = StreamState::from_img(img_path);
+    let orig_names = {
+        let fs = remount(&state);
+        let dir = open_dir(&fs, dir_path);
+        let orig_names = names_in_dir(&dir);
+        let name = make_name(256, b'a');
+        assert_invalid_name_length(dir.create_file(&name));
+        assert_invalid_name_length(dir.create_dir(&name));
+        dir.create_file("short").unwrap();
+        assert_invalid_name_length(dir.rename("short", &dir, &name));
+        dir.remove("short").unwrap();
+        // a name consisting of 255 UTF-16 code units is valid even if it takes more UTF-8 bytes
+        let unicode_name = "\u{00F3}".repeat(255);
+        dir.create_file(&unicode_name).unwrap();
+        assert_invalid_name_length(dir.create_file(&"\u{00F3}".repeat(256)));
+        dir.remove(&unicode_name).unwrap();
+        orig_names
+    };
+    // no partial entries are left behind
+    let fs = remount(&state);
+    assert_eq!(names_in_dir(&open_dir(&fs, dir_path)), orig_names);
+}
+
+fn test_long_names(img_path: &str) {
+    for &dir_path in &["", "very/long/path"] {
+        for &len in &[254, 255] {
+            test_name_lifecycle(img_path, dir_path, len);
+        }
+        test_too_long_name(img_path, dir_path);
+    }
+}
+
+#[test]
+fn test_long_names_fat12() {
+    test_long_names(FAT12_IMG)
+}
+
+#[test]
+fn test_long_names_fat16() {
+    test_long_names(FAT16_IMG)
+}
+
+#[test]
+fn test_long_names_fat32() {
+    test_long_names(FAT32_IMG)
+}
+
+#[test]
+fn test_long_name_in_fragmented_dir() {
+    // directory clusters are not contiguous so the entries run crosses a chain discontinuity
+    let state = StreamState::from_img(FAT16_IMG);
+    let name = make_name(255, b'a');
+    {
+        let fs = remount(&state);
+        let dir = fs.root_dir().create_dir("frag").unwrap();
+        for i in 0..10 {
+            dir.create_file(&format!("f{}", i)).unwrap();
+            // allocate a cluster after the directory cluster
+            fs.root_dir()
+                .create_file(&format!("filler{}", i))
+                .unwrap()
+                .write_all(&[0; 512])
+                .unwrap();
+        }
+        dir.create_file(&name).unwrap().write_all(TEST_STR.as_bytes()).unwrap();
+    }
+    let fs = remount(&state);
+    let dir = fs.root_dir().open_dir("frag").unwrap();
+    assert_eq!(names_in_dir(&dir).last().unwrap(), &name);
+    assert_eq!(read_to_string(&dir, &name), TEST_STR);
+}
+
+fn lfn_checksum(short_name: &[u8]) -> u8 {
+    short_name
+        .iter()
+        .fold(0_u8, |sum, &b| ((sum & 1) << 7).wrapping_add(sum >> 1).wrapping_add(b))
+}
+
+/// Builds the entries run for a 255 characters name as written by Windows: 20 LFN entries in the reversed order
+/// (the first one has the last entry flag 0x40 set), the name terminated by 0x0000 and padded with 0xFFFF.
+fn reference_lfn_entries(name: &str, short_name: &[u8; 11]) -> Vec<u8> {
+    let mut ucs2: Vec<u16> = name.encode_utf16().collect();
+    ucs2.push(0);
+    ucs2.resize(20 * 13, 0xFFFF);
+    let checksum = lfn_checksum(short_name);
+    let mut run = Vec::new();
+    for index in (1..=20_u8).rev() {
+        let part = &ucs2[usize::from(index - 1) * 13..usize::from(index) * 13];
+        let mut entry = [0_u8; 32];
+        entry[0] = if index == 20 { index | 0x40 } else { index };
+        entry[11] = 0x0F;
+        entry[13] = checksum;
+        let offsets = (1..11).step_by(2).chain((14..26).step_by(2)).chain((28..32).step_by(2));
+        for (offset, unit) in offsets.zip(part) {
+            entry[offset..offset + 2].copy_from_slice(&unit.to_le_bytes());
+        }
+        run.extend_from_slice(&entry);
+    }
+    run
+}
+
+#[test]
+fn test_max_long_name_golden_entries() {
+    let state = StreamState::from_img(FAT16_IMG);
+    let name = make_name(255, b'a');
+    remount(&state).root_dir().create_file(&name).unwrap();
+    let img = state.data();
+    let short_name = b"ABCDEF~1   ";
+    let run = reference_lfn_entries(&name, short_name);
+    assert_eq!(run[0], 0x54);
+    let pos = (0..img.len())
+        .step_by(32)
+        .find(|&i| img[i..].starts_with(&run[..32]))
+        .expect("LFN entries not found");
+    assert_eq!(&img[pos..pos + run.len()], &run[..]);
+    // SFN entry directly follows the LFN entries
+    assert_eq!(&img[pos + run.len()..pos + run.len() + 11], short_name);
+}
+
+#[test]
+fn test_long_name_in_full_root_dir() {
+    let state = StreamState::from_img(FAT16_IMG);
+    let created = {
+        let fs = remount(&state);
+        let root_dir = fs.root_dir();
+        let mut created = 0;
+        loop {
+            let name = format!("{}{}", created, make_name(250, b'a'));
+            match root_dir.create_file(&name) {
+                Ok(_) => created += 1,
+                Err(fatfs::Error::NotEnoughSpace) => break,
+                Err(err) => panic!("unexpected error {:?}", err),
+            }
+        }
+        // no partial entries run has been written so entries left at the end of the root directory can be used
+        root_dir.create_file("short").unwrap();
+        created
+    };
+    let fs = remount(&state);
+    assert_eq!(names_in_dir(&fs.root_dir()).len(), 4 + created + 1);
+}
@@ -0,0 +1,139 @@
+use std::fs;
+use std::io;
+
+use fatfs::{is_fat_partition_type, FsOptions, Mbr, StdIoWrapper};
+
+mod common;
+use common::{init_logger, Storage, FAT12_IMG, FAT16_IMG};
+
+fn partition_entry(status: u8, partition_type: u8, start_lba: u32, sector_count: u32) -> [u8; 16] {
+    let mut entry = [0; 16];
+    entry[0] = status;
+    entry[4] = partition_type;
+    entry[8..12].copy_from_slice(&start_lba.to_le_bytes());
+    entry[12..16].copy_from_slice(&sector_count.to_le_bytes());
+    entry
+}
+
+/// Creates a disk with an empty first entry, a FAT16 partition at 1 MiB and a FAT12 partition after it.
+fn create_disk() -> Vec<u8> {
+    let fat16 = fs::read(FAT16_IMG).unwrap();
+    let fat12 = fs::read(FAT12_IMG).unwrap();
+    let fat16_sectors = fat16.len() as u32 / 512;
+    let fat12_start = 2048 + fat16_sectors;
+    let mut disk = vec![0; 2048 * 512];
+    disk[462..478].copy_from_slice(&partition_entry(0x80, 0x0E, 2048, fat16_sectors));
+    disk[478..494].copy_from_slice(&partition_entry(0x00, 0x01, fat12_start, fat12.len() as u32 / 512));
+    disk[510..512].copy_from_slice(&[0x55, 0xAA]);
+    disk.extend_from_slice(&fat16);
+    disk.extend_from_slice(&fat12);
+    disk
+}
+
+fn wrap(disk: Vec<u8>) -> Storage {
+    StdIoWrapper::new(io::Cursor::new(disk))
+}
+
+#[test]
+fn test_read_mbr() {
+    init_logger();
+    let mut disk = wrap(create_disk());
+    let mbr = Mbr::read(&mut disk).unwrap();
+    let partitions = mbr.partitions();
+    assert_eq!(partitions.len(), 4);
+    // zeroed entries are tolerated
+    assert!(partitions[0].is_empty());
+    assert!(!partitions[0].is_fat());
+    assert!(partitions[3].is_empty());
+
+    let fat16 = &partitions[1];
+    assert!(!fat16.is_empty());
+    assert!(fat16.is_bootable());
+    assert!(fat16.is_fat());
+    assert!(!fat16.is_extended());
+    assert_eq!(fat16.partition_type(), 0x0E);
+    assert_eq!(fat16.start_lba(), 2048);
+    let fat16_len = fs::metadata(FAT16_IMG).unwrap().len();
+    assert_eq!(u64::from(fat16.sector_count()) * 512, fat16_len);
+    assert_eq!(fat16.byte_range(), (2048 * 512, 2048 * 512 + fat16_len));
+
+    let fat12 = &partitions[2];
+    assert!(!fat12.is_bootable());
+    assert_eq!(fat12.partition_type(), 0x01);
+    assert_eq!(fat12.start_lba(), 2048 + fat16.sector_count());
+}
+
+#[test]
+fn test_mount_partitions() {
+    init_logger();
+    let original = create_disk();
+    let mut disk = wrap(original.clone());
+    let mbr = Mbr::read(&mut disk).unwrap();
+    {
+        let partition = Mbr::partition_slice(&mut disk, 1).unwrap();
+        let fs = fatfs::FileSystem::new(partition, FsOptions::new()).unwrap();
+        assert_eq!(fs.fat_type(), fatfs::FatType::Fat16);
+        let mut file = fs.root_dir().create_file("new.txt").unwrap();
+        fatfs::Write::write_all(&mut file, b"partition 1").unwrap();
+        drop(file);
+        fs.unmount().unwrap();
+    }
+    {
+        let partition = Mbr::partition_slice(&mut disk, 2).unwrap();
+        let fs = fatfs::FileSystem::new(partition, FsOptions::new()).unwrap();
+        assert_eq!(fs.fat_type(), fatfs::FatType::Fat12);
+        let mut content = String::new();
+        std::io::Read::read_to_string(&mut fs.root_dir().open_file("short.txt").unwrap(), &mut content).unwrap();
+        assert_eq!(content, "Rust is cool!\n");
+    }
+    // only the first partition has been modified
+    let disk = disk.into_inner().into_inner();
+    let (start, end) = mbr.partitions()[1].byte_range();
+    let (start, end) = (start as usize, end as usize);
+    assert_eq!(disk[..start], original[..start]);
+    assert_ne!(disk[start..end], original[start..end]);
+    assert_eq!(disk[end..], original[end..]);
+}
+
+#[test]
+fn test_invalid_mbr() {
+    init_logger();
+    let mut disk = create_disk();
+    disk[511] = 0;
+    assert!(matches!(
+        Mbr::read(&mut wrap(disk)),
+        Err(fatfs::Error::CorruptedFileSystem)
+    ));
+    // an unpartitioned volume has the signature too but its boot code is not a valid partition table
+    let mut disk = create_disk();
+    disk[446 + 16] = 0x12;
+    assert!(matches!(
+        Mbr::read(&mut wrap(disk)),
+        Err(fatfs::Error::CorruptedFileSystem)
+    ));
+    assert!(matches!(
+        Mbr::read(&mut wrap(vec![0; 100])),
+        Err(fatfs::Error::Io(ref e)) if e.kind() == io::ErrorKind::UnexpectedEof
+    ));
+}
+
+#[test]
+fn test_missing_partition() {
+    init_logger();
+    for index in &[0, 3, 4] {
+        assert!(matches!(
+            Mbr::partition_slice(wrap(create_disk()), *index),
+            Err(fatfs::Error::InvalidInput)
+        ));
+    }
+}
+
+#[test]
+fn test_fat_partition_types() {
+    for partition_type in &[0x01, 0x04, 0x06, 0x0B, 0x0C, 0x0E] {
+        assert!(is_fat_partition_type(*partition_type));
+    }
+    for partition_type in &[0x00, 0x05, 0x07, 0x0F, 0x83, 0xEE] {
+        assert!(!is_fat_partition_type(*partition_type));
+    }
+}
@@ -0,0 +1,100 @@
+use std::io::prelude::*;
+
+mod common;
+use common::{open_fs, Dir, FileSystem, FAT16_IMG, FAT32_IMG};
+
+fn names_in_dir(dir: &Dir) -> Vec<String> {
+    dir.iter().map(|r| r.unwrap().file_name()).collect()
+}
+
+fn read_to_string(dir: &Dir, path: &str) -> String {
+    let mut content = String::new();
+    dir.open_file(path).unwrap().read_to_string(&mut content).unwrap();
+    content
+}
+
+/// Creates `LOGS_OLD` with two files and a subdirectory and `LOGS` with one file.
+fn create_dirs(fs: &FileSystem) -> (Dir<'_>, Dir<'_>) {
+    let root_dir = fs.root_dir();
+    let old = root_dir.create_dir("LOGS_OLD").unwrap();
+    old.create_file("first.log").unwrap().write_all(b"first").unwrap();
+    old.create_file("Second Log.txt").unwrap().write_all(b"second").unwrap();
+    let sub = old.create_dir("archive").unwrap();
+    sub.create_file("old.log").unwrap().write_all(b"old").unwrap();
+    let logs = root_dir.create_dir("LOGS").unwrap();
+    logs.create_file("current.log").unwrap().write_all(b"current").unwrap();
+    (old, logs)
+}
+
+fn test_move_contents(img_path: &str) {
+    let fs = open_fs(img_path);
+    let (old, logs) = create_dirs(&fs);
+    assert_eq!(old.move_contents(&logs).unwrap(), 3);
+    assert_eq!(names_in_dir(&old), [".", ".."]);
+    assert_eq!(
+        names_in_dir(&logs),
+        [".", "..", "current.log", "first.log", "Second Log.txt", "archive"]
+    );
+    assert_eq!(read_to_string(&logs, "first.log"), "first");
+    assert_eq!(read_to_string(&logs, "Second Log.txt"), "second");
+    assert_eq!(read_to_string(&logs, "archive/old.log"), "old");
+    // `..` entry of the moved directory points to the destination
+    assert_eq!(read_to_string(&logs, "archive/../current.log"), "current");
+    assert!(fs.check().unwrap().is_clean());
+
+    // moving into the root directory sets `..` to cluster 0
+    assert_eq!(logs.move_contents(&fs.root_dir()).unwrap(), 4);
+    let archive = fs.root_dir().open_dir("archive").unwrap();
+    assert_eq!(
+        names_in_dir(&archive.parent().unwrap().unwrap()),
+        names_in_dir(&fs.root_dir())
+    );
+    assert!(fs.check().unwrap().is_clean());
+}
+
+#[test]
+fn test_move_contents_fat16() {
+    test_move_contents(FAT16_IMG)
+}
+
+#[test]
+fn test_move_contents_fat32() {
+    test_move_contents(FAT32_IMG)
+}
+
+#[test]
+fn test_move_contents_collision() {
+    let fs = open_fs(FAT16_IMG);
+    let (old, logs) = create_dirs(&fs);
+    logs.create_file("SECOND LOG.TXT").unwrap();
+    let old_names = names_in_dir(&old);
+    let logs_names = names_in_dir(&logs);
+    assert!(matches!(old.move_contents(&logs), Err(fatfs::Error::AlreadyExists)));
+    // nothing has been moved
+    assert_eq!(names_in_dir(&old), old_names);
+    assert_eq!(names_in_dir(&logs), logs_names);
+
+    assert_eq!(old.move_contents_skip_existing(&logs).unwrap(), 2);
+    assert_eq!(names_in_dir(&old), [".", "..", "Second Log.txt"]);
+    assert_eq!(read_to_string(&old, "Second Log.txt"), "second");
+    assert_eq!(
+        names_in_dir(&logs),
+        [".", "..", "current.log", "SECOND LOG.TXT", "first.log", "archive"]
+    );
+    assert!(fs.check().unwrap().is_clean());
+}
+
+#[test]
+fn test_move_contents_into_itself() {
+    let fs = open_fs(FAT16_IMG);
+    let (old, _) = create_dirs(&fs);
+    let names = names_in_dir(&old);
+    for dst in &[old.clone(), old.open_dir("archive").unwrap()] {
+        assert!(matches!(old.move_contents(dst), Err(fatfs::Error::InvalidInput)));
+    }
+    assert!(matches!(
+        fs.root_dir().move_contents(&old),
+        Err(fatfs::Error::InvalidInput)
+    ));
+    assert_eq!(names_in_dir(&old), names);
+}
@@ -0,0 +1,162 @@
+use std::io;
+
+mod common;
+use common::{open_fs, FAT16_IMG};
+
+fn assert_not_found<T>(result: Result<T, fatfs::Error<io::Error>>, expected_prefix: &str, expected_missing: &str) {
+    match result {
+        Err(fatfs::Error::NotFound {
+            missing_component,
+            resolved_prefix,
+        }) => {
+            assert_eq!(missing_component, expected_missing);
+            assert_eq!(resolved_prefix, expected_prefix);
+        }
+        Err(err) => panic!("unexpected error {:?}", err),
+        Ok(_) => panic!("{}/{} has been found", expected_prefix, expected_missing),
+    }
+}
+
+/// Paths to "very/long/path/test.txt" with one missing component: (path, resolved prefix, missing component).
+const FILE_PATHS: &[(&str, &str, &str)] = &[
+    ("missing/long/path/test.txt", "", "missing"),
+    ("very/missing/path/test.txt", "very", "missing"),
+    ("very/long/missing/test.txt", "very/long", "missing"),
+    ("very/long/path/missing.txt", "very/long/path", "missing.txt"),
+    ("/very/long/missing/test.txt/", "very/long", "missing"),
+];
+
+#[test]
+fn test_open_file_not_found() {
+    let fs = open_fs(FAT16_IMG);
+    let root_dir = fs.root_dir();
+    assert!(root_dir.open_file("very/long/path/test.txt").is_ok());
+    for &(path, prefix, missing) in FILE_PATHS {
+        assert_not_found(root_dir.open_file(path), prefix, missing);
+    }
+    // prefix is relative to the directory used for the lookup
+    let dir = root_dir.open_dir("very").unwrap();
+    assert_not_found(dir.open_file("long/missing/test.txt"), "long", "missing");
+}
+
+#[test]
+fn test_entry_not_found() {
+    let fs = open_fs(FAT16_IMG);
+    let root_dir = fs.root_dir();
+    for &(path, prefix, missing) in FILE_PATHS {
+        assert_not_found(root_dir.entry(path), prefix, missing);
+    }
+    assert_not_found(root_dir.entry("very/long/missing"), "very/long", "missing");
+}
+
+#[test]
+fn test_open_dir_not_found() {
+    let fs = open_fs(FAT16_IMG);
+    let root_dir = fs.root_dir();
+    assert_not_found(root_dir.open_dir("missing/long/path"), "", "missing");
+    assert_not_found(root_dir.open_dir("very/missing/path"), "very", "missing");
+    assert_not_found(root_dir.open_dir("very/long/missing"), "very/long", "missing");
+}
+
+#[test]
+fn test_remove_not_found() {
+    let fs = open_fs(FAT16_IMG);
+    let root_dir = fs.root_dir();
+    for &(path, prefix, missing) in FILE_PATHS {
+        assert_not_found(root_dir.remove(path), prefix, missing);
+    }
+    root_dir.remove("very/long/path/test.txt").unwrap();
+}
+
+#[test]
+fn test_create_reports_missing_parent() {
+    let fs = open_fs(FAT16_IMG);
+    let root_dir = fs.root_dir();
+    for &(path, prefix, missing) in &FILE_PATHS[..3] {
+        assert_not_found(root_dir.create_file(path), prefix, missing);
+        assert_not_found(root_dir.create_dir(path), prefix, missing);
+    }
+    // the last component is created if the parent exists
+    root_dir.create_file("very/long/path/missing.txt").unwrap();
+    root_dir.create_dir("very/long/path/missing-dir").unwrap();
+}
+
+#[test]
+fn test_rename_not_found() {
+    let fs = open_fs(FAT16_IMG);
+    let root_dir = fs.root_dir();
+    for &(path, prefix, missing) in FILE_PATHS {
+        assert_not_found(root_dir.rename(path, &root_dir, "new.txt"), prefix, missing);
+    }
+    // missing parent of the destination
+    for &(path, prefix, missing) in &FILE_PATHS[..3] {
+        assert_not_found(root_dir.rename("short.txt", &root_dir, path), prefix, missing);
+    }
+    // destination prefix is relative to the destination directory
+    let dst_dir = root_dir.open_dir("very/long").unwrap();
+    assert_not_found(
+        root_dir.rename("short.txt", &dst_dir, "path/missing/x.txt"),
+        "path",
+        "missing",
+    );
+    root_dir.rename("short.txt", &dst_dir, "path/x.txt").unwrap();
+}
+
+#[test]
+fn test_not_found_message() {
+    let fs = open_fs(FAT16_IMG);
+    let root_dir = fs.root_dir();
+    let err = root_dir.open_file("very/long/missing/test.txt").err().unwrap();
+    assert_eq!(err.to_string(), "No such file or directory: very/long/missing");
+    let err = root_dir.open_file("missing.txt").err().unwrap();
+    assert_eq!(err.to_string(), "No such file or directory: missing.txt");
+    let io_err: io::Error = err.into();
+    assert_eq!(io_err.kind(), io::ErrorKind::NotFound);
+}
+
+#[test]
+fn test_exists() {
+    let fs = open_fs(FAT16_IMG);
+    let root_dir = fs.root_dir();
+    for path in &[
+        "short.txt",
+        "SHORT.TXT",
+        "very/long/path/test.txt",
+        "/very/long/",
+        "very-long-dir-name",
+        "",
+        ".",
+    ] {
+        assert!(root_dir.exists(path).unwrap(), "{}", path);
+    }
+    for &(path, _, _) in FILE_PATHS {
+        assert!(!root_dir.exists(path).unwrap(), "{}", path);
+    }
+    // a file in the middle of the path
+    assert!(!root_dir.exists("short.txt/test.txt").unwrap());
+    let dir = root_dir.open_dir("very/long").unwrap();
+    assert!(dir.exists("path/test.txt").unwrap());
+    assert!(!dir.exists("test.txt").unwrap());
+}
+
+#[test]
+fn test_remove_error_kinds() {
+    let fs = open_fs(FAT16_IMG);
+    let root_dir = fs.root_dir();
+    let err = root_dir.remove("missing").err().unwrap();
+    assert!(matches!(err, fatfs::Error::NotFound { .. }));
+    let io_err: io::Error = err.into();
+    assert_eq!(io_err.kind(), io::ErrorKind::NotFound);
+
+    let err = root_dir.remove("very").err().unwrap();
+    assert!(matches!(err, fatfs::Error::DirectoryIsNotEmpty));
+    let io_err: io::Error = err.into();
+    assert_eq!(io_err.kind(), io::ErrorKind::InvalidInput);
+    assert_eq!(io_err.to_string(), "Directory is not empty");
+    assert!(root_dir.exists("very/long/path/test.txt").unwrap());
+
+    root_dir.remove("very/long/path/test.txt").unwrap();
+    root_dir.remove("very/long/path").unwrap();
+    assert!(!root_dir.exists("very/long/path").unwrap());
+    assert_not_found(root_dir.remove("very/long/path"), "very/long", "path");
+}
@@ -0,0 +1,161 @@
+use std::io;
+use std::io::prelude::*;
+
+mod common;
+use common::{open_fs, Dir, FAT12_IMG, FAT16_IMG, FAT32_IMG};
+
+const TEST_STR: &str = "Rust is cool!\n";
+
+fn read_to_string(dir: &Dir, path: &str) -> String {
+    let mut content = String::new();
+    dir.open_file(path).unwrap().read_to_string(&mut content).unwrap();
+    content
+}
+
+fn test_open_options(img_path: &str) {
+    let fs = open_fs(img_path);
+    let dir = fs.root_dir().open_dir("very/long/path").unwrap();
+
+    // read only
+    let mut file = dir.open_options().read(true).open("test.txt").unwrap();
+    let mut content = String::new();
+    file.read_to_string(&mut content).unwrap();
+    assert_eq!(content, TEST_STR);
+    assert!(matches!(
+        fatfs::Write::write(&mut file, b"x"),
+        Err(fatfs::Error::PermissionDenied)
+    ));
+    file.seek(io::SeekFrom::Start(0)).unwrap();
+    assert!(matches!(file.truncate(), Err(fatfs::Error::PermissionDenied)));
+    drop(file);
+    assert_eq!(read_to_string(&dir, "test.txt"), TEST_STR);
+
+    // write only
+    let mut file = dir.open_options().write(true).open("test.txt").unwrap();
+    file.write_all(b"Java").unwrap();
+    let mut buf = [0_u8; 4];
+    assert!(matches!(
+        fatfs::Read::read(&mut file, &mut buf),
+        Err(fatfs::Error::PermissionDenied)
+    ));
+    drop(file);
+    assert_eq!(read_to_string(&dir, "test.txt"), "Java is cool!\n");
+
+    // append writes at the end regardless of the position
+    let mut file = dir.open_options().read(true).append(true).open("test.txt").unwrap();
+    file.write_all(b"1").unwrap();
+    file.seek(io::SeekFrom::Start(0)).unwrap();
+    file.write_all(b"2").unwrap();
+    file.seek(io::SeekFrom::Start(0)).unwrap();
+    let mut content = String::new();
+    file.read_to_string(&mut content).unwrap();
+    assert_eq!(content, "Java is cool!\n12");
+    drop(file);
+
+    // truncate frees the clusters right away
+    let free_clusters = fs.stats().unwrap().free_clusters();
+    let file = dir.open_options().write(true).truncate(true).open("test.txt").unwrap();
+    assert_eq!(fs.stats().unwrap().free_clusters(), free_clusters + 1);
+    drop(file);
+    assert_eq!(dir.entry("test.txt").unwrap().len(), 0);
+    assert_eq!(read_to_string(&dir, "test.txt"), "");
+
+    // create opens an existing file without truncating it and creates a missing one
+    dir.open_options()
+        .write(true)
+        .open("test.txt")
+        .unwrap()
+        .write_all(TEST_STR.as_bytes())
+        .unwrap();
+    dir.open_options().write(true).create(true).open("test.txt").unwrap();
+    assert_eq!(read_to_string(&dir, "test.txt"), TEST_STR);
+    assert!(matches!(
+        dir.open_options().read(true).write(true).open("new.txt"),
+        Err(fatfs::Error::NotFound { .. })
+    ));
+    let mut file = dir.open_options().write(true).create(true).open("new.txt").unwrap();
+    file.write_all(b"new").unwrap();
+    drop(file);
+    assert_eq!(read_to_string(&dir, "new.txt"), "new");
+
+    // create and truncate
+    dir.open_options()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open("new.txt")
+        .unwrap();
+    assert_eq!(read_to_string(&dir, "new.txt"), "");
+
+    // create_new fails for existing entries
+    assert!(matches!(
+        dir.open_options().write(true).create_new(true).open("test.txt"),
+        Err(fatfs::Error::AlreadyExists)
+    ));
+    assert!(matches!(
+        dir.open_options()
+            .write(true)
+            .create(true)
+            .create_new(true)
+            .open("NEW.TXT"),
+        Err(fatfs::Error::AlreadyExists)
+    ));
+    let mut file = dir
+        .open_options()
+        .append(true)
+        .create_new(true)
+        .open("newer.txt")
+        .unwrap();
+    file.write_all(b"newer").unwrap();
+    drop(file);
+    assert_eq!(read_to_string(&dir, "newer.txt"), "newer");
+    assert!(fs.check().unwrap().is_clean());
+}
+
+#[test]
+fn test_open_options_fat12() {
+    test_open_options(FAT12_IMG)
+}
+
+#[test]
+fn test_open_options_fat16() {
+    test_open_options(FAT16_IMG)
+}
+
+#[test]
+fn test_open_options_fat32() {
+    test_open_options(FAT32_IMG)
+}
+
+#[test]
+fn test_open_options_invalid_combinations() {
+    let fs = open_fs(FAT16_IMG);
+    let root_dir = fs.root_dir();
+    let invalid = [
+        // no access mode
+        root_dir.open_options(),
+        root_dir.open_options().create(true),
+        // creating or truncating requires write access
+        root_dir.open_options().read(true).truncate(true),
+        root_dir.open_options().read(true).create(true),
+        root_dir.open_options().read(true).create_new(true),
+        // truncate cannot be combined with append
+        root_dir.open_options().append(true).truncate(true),
+        root_dir.open_options().write(true).append(true).truncate(true),
+    ];
+    for options in &invalid {
+        assert!(matches!(options.open("short.txt"), Err(fatfs::Error::InvalidInput)));
+        assert!(matches!(options.open("missing.txt"), Err(fatfs::Error::InvalidInput)));
+    }
+    assert!(!root_dir.exists("missing.txt").unwrap());
+    assert_eq!(read_to_string(&root_dir, "short.txt"), "Rust is cool!\n");
+    // directories cannot be opened
+    assert!(matches!(
+        root_dir.open_options().read(true).open("very"),
+        Err(fatfs::Error::InvalidInput)
+    ));
+    assert!(matches!(
+        root_dir.open_options().write(true).create(true).open("very"),
+        Err(fatfs::Error::InvalidInput)
+    ));
+}
@@ -0,0 +1,85 @@
+use std::fs;
+
+mod common;
+use common::{mount, open_fs, Dir, FAT12_IMG, FAT16_IMG, FAT32_IMG};
+
+/// Returns names and positions of all entries - equal for handles of the same directory.
+fn dir_identity(dir: &Dir) -> Vec<(String, u64)> {
+    dir.iter()
+        .map(|r| {
+            let e = r.unwrap();
+            (e.file_name(), e.entry_pos())
+        })
+        .collect()
+}
+
+/// Climbs from `dir` to the root directory checking that every parent contains the directory it was reached from.
+fn check_parents(dir: &Dir, names: &[&str], root_dir: &Dir) {
+    let mut dir = dir.clone();
+    for name in names.iter().rev() {
+        let parent = dir.parent().unwrap().unwrap();
+        assert_eq!(dir_identity(&parent.open_dir(name).unwrap()), dir_identity(&dir));
+        dir = parent;
+    }
+    assert_eq!(dir_identity(&dir), dir_identity(root_dir));
+    assert!(dir.parent().unwrap().is_none());
+}
+
+fn test_parent(img_path: &str) {
+    let fs = open_fs(img_path);
+    let root_dir = fs.root_dir();
+    assert!(root_dir.parent().unwrap().is_none());
+
+    let dir = root_dir.open_dir("very/long/path").unwrap();
+    check_parents(&dir, &["very", "long", "path"], &root_dir);
+
+    let dir = root_dir.create_dir("new").unwrap().create_dir("sub").unwrap();
+    check_parents(&dir, &["new", "sub"], &root_dir);
+    let dir = root_dir.open_dir("new/sub").unwrap();
+    check_parents(&dir, &["new", "sub"], &root_dir);
+}
+
+#[test]
+fn test_parent_fat12() {
+    test_parent(FAT12_IMG)
+}
+
+#[test]
+fn test_parent_fat16() {
+    test_parent(FAT16_IMG)
+}
+
+#[test]
+fn test_parent_fat32() {
+    test_parent(FAT32_IMG)
+}
+
+/// Sets the cluster of the `..` entry of directory "very" in a FAT32 image.
+fn set_dotdot_cluster(img: &mut [u8], cluster: u32) {
+    let fs = mount(img.to_vec());
+    let dotdot_pos = fs
+        .root_dir()
+        .open_dir("very")
+        .unwrap()
+        .iter()
+        .nth(1)
+        .unwrap()
+        .unwrap()
+        .entry_pos() as usize;
+    drop(fs);
+    img[dotdot_pos + 20..dotdot_pos + 22].copy_from_slice(&((cluster >> 16) as u16).to_le_bytes());
+    img[dotdot_pos + 26..dotdot_pos + 28].copy_from_slice(&(cluster as u16).to_le_bytes());
+}
+
+#[test]
+fn test_parent_fat32_root_dotdot_cluster() {
+    // `..` entry pointing to the root directory should contain 0 but some implementations store the root cluster
+    for &cluster in &[0, 2] {
+        let mut img = fs::read(FAT32_IMG).unwrap();
+        set_dotdot_cluster(&mut img, cluster);
+        let fs = mount(img);
+        let root_dir = fs.root_dir();
+        let dir = root_dir.open_dir("very/long").unwrap();
+        check_parents(&dir, &["very", "long"], &root_dir);
+    }
+}
@@ -0,0 +1,179 @@
+use std::io;
+use std::io::prelude::*;
+
+mod common;
+use common::{open_fs, FAT12_IMG, FAT16_IMG, FAT32_IMG};
+
+const TEST_STR: &str = "Rust is cool!\n";
+const RECORD_SIZE: usize = 48;
+
+fn record(i: usize) -> Vec<u8> {
+    (0..RECORD_SIZE).map(|j| (i * 7 + j) as u8).collect()
+}
+
+fn test_records(img_path: &str) {
+    let fs = open_fs(img_path);
+    let root_dir = fs.root_dir();
+    let mut file = root_dir.create_file("records.db").unwrap();
+    file.write_all(b"header").unwrap();
+    // records are written in a shuffled order crossing cluster boundaries
+    let count = 100;
+    for k in 0..count {
+        let i = k * 37 % count;
+        file.write_all_at((i * RECORD_SIZE) as u64, &record(i)).unwrap();
+    }
+    assert_eq!(file.stream_position().unwrap(), 6);
+    let mut buf = vec![0; RECORD_SIZE];
+    for i in 0..count {
+        file.read_exact_at((i * RECORD_SIZE) as u64, &mut buf).unwrap();
+        assert_eq!(buf, record(i), "record {}", i);
+    }
+    for i in (0..count).rev() {
+        file.read_exact_at((i * RECORD_SIZE) as u64, &mut buf).unwrap();
+        assert_eq!(buf, record(i), "record {}", i);
+    }
+    // the position is not changed by positional I/O
+    file.write_all(b"!").unwrap();
+    drop(file);
+
+    let mut content = Vec::new();
+    root_dir
+        .open_file("records.db")
+        .unwrap()
+        .read_to_end(&mut content)
+        .unwrap();
+    assert_eq!(content.len(), count * RECORD_SIZE);
+    assert_eq!(content[6], b'!');
+    assert_eq!(&content[RECORD_SIZE..2 * RECORD_SIZE], &record(1)[..]);
+}
+
+#[test]
+fn test_records_fat12() {
+    test_records(FAT12_IMG)
+}
+
+#[test]
+fn test_records_fat16() {
+    test_records(FAT16_IMG)
+}
+
+#[test]
+fn test_records_fat32() {
+    test_records(FAT32_IMG)
+}
+
+#[test]
+fn test_read_exact_at_eof() {
+    let fs = open_fs(FAT16_IMG);
+    let mut file = fs.root_dir().open_file("short.txt").unwrap();
+    file.seek(io::SeekFrom::Start(2)).unwrap();
+    let mut buf = [0; 4];
+    file.read_exact_at(10, &mut buf).unwrap();
+    assert_eq!(&buf, b"ol!\n");
+    assert!(matches!(
+        file.read_exact_at(11, &mut buf),
+        Err(fatfs::Error::UnexpectedEof)
+    ));
+    assert!(matches!(
+        file.read_exact_at(100, &mut buf),
+        Err(fatfs::Error::UnexpectedEof)
+    ));
+    assert!(matches!(
+        file.read_exact_at(1 << 40, &mut buf),
+        Err(fatfs::Error::UnexpectedEof)
+    ));
+    // an empty buffer can be read anywhere
+    file.read_exact_at(100, &mut []).unwrap();
+    assert_eq!(file.stream_position().unwrap(), 2);
+    let mut rest = String::new();
+    file.read_to_string(&mut rest).unwrap();
+    assert_eq!(rest, &TEST_STR[2..]);
+}
+
+#[test]
+fn test_write_all_at_beyond_eof() {
+    let fs = open_fs(FAT16_IMG);
+    let root_dir = fs.root_dir();
+    let mut file = root_dir.open_file("short.txt").unwrap();
+    file.write_all_at(1000, b"end").unwrap();
+    assert_eq!(file.stream_position().unwrap(), 0);
+    drop(file);
+    let mut content = Vec::new();
+    root_dir
+        .open_file("short.txt")
+        .unwrap()
+        .read_to_end(&mut content)
+        .unwrap();
+    assert_eq!(content.len(), 1003);
+    assert_eq!(&content[..TEST_STR.len()], TEST_STR.as_bytes());
+    assert!(content[TEST_STR.len()..1000].iter().all(|b| *b == 0));
+    assert_eq!(&content[1000..], b"end");
+    assert!(fs.check().unwrap().is_clean());
+}
+
+#[test]
+fn test_write_all_at_empty_buffer() {
+    let fs = open_fs(FAT16_IMG);
+    let root_dir = fs.root_dir();
+    let mut file = root_dir.open_file("short.txt").unwrap();
+    // an empty write does not fill the gap up to the offset
+    file.write_all_at(0xFFFF_FFFF, b"").unwrap();
+    file.write_all_at(1000, b"").unwrap();
+    assert_eq!(file.stream_position().unwrap(), 0);
+    drop(file);
+    let mut content = String::new();
+    root_dir
+        .open_file("short.txt")
+        .unwrap()
+        .read_to_string(&mut content)
+        .unwrap();
+    assert_eq!(content, TEST_STR);
+    assert!(fs.check().unwrap().is_clean());
+}
+
+#[test]
+fn test_write_all_at_in_append_mode() {
+    let fs = open_fs(FAT16_IMG);
+    let root_dir = fs.root_dir();
+    let mut file = root_dir
+        .open_options()
+        .read(true)
+        .append(true)
+        .open("short.txt")
+        .unwrap();
+    file.write_all_at(0, b"D").unwrap();
+    file.write_all(b"?").unwrap();
+    drop(file);
+    let mut content = String::new();
+    root_dir
+        .open_file("short.txt")
+        .unwrap()
+        .read_to_string(&mut content)
+        .unwrap();
+    assert_eq!(content, "Dust is cool!\n?");
+}
+
+#[test]
+fn test_positional_io_errors() {
+    let fs = open_fs(FAT16_IMG);
+    let root_dir = fs.root_dir();
+    let mut file = root_dir.open_file("short.txt").unwrap();
+    assert!(matches!(
+        file.write_all_at(u64::from(u32::MAX), b"x"),
+        Err(fatfs::Error::FileTooLarge)
+    ));
+    assert!(matches!(
+        file.write_all_at(u64::MAX - 2, b"hello"),
+        Err(fatfs::Error::FileTooLarge)
+    ));
+    let mut file = root_dir.open_options().read(true).open("short.txt").unwrap();
+    assert!(matches!(
+        file.write_all_at(0, b"x"),
+        Err(fatfs::Error::PermissionDenied)
+    ));
+    let mut file = root_dir.open_options().write(true).open("short.txt").unwrap();
+    assert!(matches!(
+        file.read_exact_at(0, &mut [0; 1]),
+        Err(fatfs::Error::PermissionDenied)
+    ));
+}
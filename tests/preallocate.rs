@@ -0,0 +1,159 @@
+use std::io;
+use std::io::prelude::*;
+
+mod common;
+use common::{open_fs, FileSystem, FAT12_IMG, FAT16_IMG, FAT32_IMG};
+
+fn free_clusters(fs: &FileSystem) -> u32 {
+    fs.stats().unwrap().free_clusters()
+}
+
+fn test_content() -> Vec<u8> {
+    (0..5000_u32).map(|i| (i * 7 % 251) as u8).collect()
+}
+
+fn test_preallocate(img_path: &str) {
+    let fs = open_fs(img_path);
+    let root_dir = fs.root_dir();
+    let free = free_clusters(&fs);
+    let mut file = root_dir.create_file("video.bin").unwrap();
+    assert!(file.preallocate(5000, false).unwrap());
+    assert_eq!(free_clusters(&fs), free - 10);
+    assert_eq!(file.fragmentation().unwrap().extents(), 1);
+    assert_eq!(root_dir.entry("video.bin").unwrap().len(), 0);
+    // the chain is longer than the file until the data is written
+    assert!(!fs.check().unwrap().is_clean());
+
+    // writes use preallocated clusters
+    let content = test_content();
+    file.write_all(&content).unwrap();
+    assert_eq!(free_clusters(&fs), free - 10);
+    assert_eq!(file.fragmentation().unwrap().extents(), 1);
+    // preallocating less than the allocated size does nothing
+    assert!(file.preallocate(1000, false).unwrap());
+    assert_eq!(free_clusters(&fs), free - 10);
+    drop(file);
+    let mut read_content = Vec::new();
+    root_dir
+        .open_file("video.bin")
+        .unwrap()
+        .read_to_end(&mut read_content)
+        .unwrap();
+    assert_eq!(read_content, content);
+    assert!(fs.check().unwrap().is_clean());
+
+    // a non-empty file is extended
+    let mut file = root_dir.open_file("video.bin").unwrap();
+    assert!(file.preallocate(8192, false).unwrap());
+    assert_eq!(free_clusters(&fs), free - 16);
+    // unused clusters are freed by truncation
+    file.seek(io::SeekFrom::End(0)).unwrap();
+    file.truncate().unwrap();
+    assert_eq!(free_clusters(&fs), free - 10);
+    drop(file);
+    assert!(fs.check().unwrap().is_clean());
+}
+
+#[test]
+fn test_preallocate_fat12() {
+    test_preallocate(FAT12_IMG)
+}
+
+#[test]
+fn test_preallocate_fat16() {
+    test_preallocate(FAT16_IMG)
+}
+
+#[test]
+fn test_preallocate_fat32() {
+    test_preallocate(FAT32_IMG)
+}
+
+#[test]
+fn test_preallocate_update_len() {
+    let fs = open_fs(FAT16_IMG);
+    let root_dir = fs.root_dir();
+    let mut file = root_dir.create_file("video.bin").unwrap();
+    assert!(file.preallocate(1500, true).unwrap());
+    // the file can be overwritten without seeking to the end
+    file.write_all(b"header").unwrap();
+    drop(file);
+    assert_eq!(root_dir.entry("video.bin").unwrap().len(), 1500);
+    assert!(fs.check().unwrap().is_clean());
+    // the length is never decreased
+    let mut file = root_dir.open_file("video.bin").unwrap();
+    assert!(file.preallocate(100, true).unwrap());
+    drop(file);
+    assert_eq!(root_dir.entry("video.bin").unwrap().len(), 1500);
+}
+
+/// Fills the volume leaving `holes` free clusters that are not adjacent.
+fn fragment_free_space(fs: &FileSystem, holes: u32) {
+    let dir = fs.root_dir().create_dir("fill").unwrap();
+    for i in 0..2 * holes {
+        dir.create_file(&format!("{}.bin", i)).unwrap().write_all(b"x").unwrap();
+    }
+    let free = free_clusters(fs);
+    dir.create_file("rest.bin")
+        .unwrap()
+        .set_len(u64::from(free) * 512)
+        .unwrap();
+    for i in (0..2 * holes).step_by(2) {
+        dir.remove(&format!("{}.bin", i)).unwrap();
+    }
+    assert_eq!(free_clusters(fs), holes);
+}
+
+#[test]
+fn test_preallocate_fragmented() {
+    let fs = open_fs(FAT12_IMG);
+    fragment_free_space(&fs, 10);
+    let root_dir = fs.root_dir();
+    let mut file = root_dir.create_file("video.bin").unwrap();
+    assert!(!file.preallocate(3 * 512, false).unwrap());
+    assert_eq!(free_clusters(&fs), 7);
+    assert_eq!(file.fragmentation().unwrap().extents(), 3);
+    let content = test_content();
+    file.write_all(&content[..3 * 512]).unwrap();
+    assert_eq!(free_clusters(&fs), 7);
+    drop(file);
+    assert!(fs.check().unwrap().is_clean());
+}
+
+#[test]
+fn test_preallocate_not_enough_space() {
+    let fs = open_fs(FAT12_IMG);
+    fragment_free_space(&fs, 10);
+    let root_dir = fs.root_dir();
+    let mut file = root_dir.open_file("short.txt").unwrap();
+    assert!(matches!(
+        file.preallocate(12 * 512, false),
+        Err(fatfs::Error::NotEnoughSpace)
+    ));
+    let mut file = root_dir.create_file("video.bin").unwrap();
+    assert!(matches!(
+        file.preallocate(11 * 512, true),
+        Err(fatfs::Error::NotEnoughSpace)
+    ));
+    // clusters allocated before the failure are freed
+    assert_eq!(free_clusters(&fs), 10);
+    drop(file);
+    assert_eq!(root_dir.entry("video.bin").unwrap().len(), 0);
+    assert!(fs.check().unwrap().is_clean());
+}
+
+#[test]
+fn test_preallocate_errors() {
+    let fs = open_fs(FAT16_IMG);
+    let root_dir = fs.root_dir();
+    let mut file = root_dir.open_file("short.txt").unwrap();
+    assert!(matches!(
+        file.preallocate(1 << 32, false),
+        Err(fatfs::Error::FileTooLarge)
+    ));
+    let mut file = root_dir.open_options().read(true).open("short.txt").unwrap();
+    assert!(matches!(
+        file.preallocate(1000, false),
+        Err(fatfs::Error::PermissionDenied)
+    ));
+}
@@ -0,0 +1,122 @@
+use std::fs;
+use std::io;
+use std::io::prelude::*;
+use std::ops::Range;
+use std::rc::Rc;
+
+use fatfs::{DefaultTimeProvider, FsOptions, LossyOemCpConverter};
+
+mod common;
+use common::{InstrumentedFs, InstrumentedStream, Layout, StreamState, FAT16_IMG};
+
+const DEPTH: usize = 8;
+
+/// Creates an image with a deep directory tree containing a multi-cluster file on each level.
+fn create_deep_img() -> Vec<u8> {
+    let mut stream = io::Cursor::new(fs::read(FAT16_IMG).unwrap());
+    {
+        let fs = fatfs::FileSystem::new(&mut stream, FsOptions::new()).unwrap();
+        let mut dir = fs.root_dir();
+        for i in 0..DEPTH {
+            dir = dir.create_dir(&format!("dir{}", i)).unwrap();
+            let mut file = dir.create_file("file.bin").unwrap();
+            file.write_all(&vec![i as u8; 4000]).unwrap();
+        }
+    }
+    stream.into_inner()
+}
+
+/// Mounts the image and returns the range of the FAT region.
+fn mount(
+    img: &[u8],
+    options: FsOptions<DefaultTimeProvider, LossyOemCpConverter>,
+) -> (InstrumentedFs, Rc<StreamState>, Range<u64>) {
+    let state = StreamState::new(img.to_vec());
+    let fs = InstrumentedFs::new(InstrumentedStream::new(&state), options).unwrap();
+    state.clear_ops();
+    (fs, state, Layout::read(img).fat_region())
+}
+
+#[test]
+fn test_prefetch_fat_reduces_fat_reads() {
+    let img = create_deep_img();
+
+    let (fs, state, fat) = mount(&img, FsOptions::new());
+    let expected = fs.usage_summary().unwrap();
+    let reads_without_prefetch = state.reads_in(&fat);
+    assert_eq!(expected.max_depth(), DEPTH as u32 + 1);
+    assert!(reads_without_prefetch > 50, "{} FAT reads", reads_without_prefetch);
+
+    let (fs, state, fat) = mount(&img, FsOptions::new());
+    fs.prefetch_fat().unwrap();
+    assert!(
+        state.reads_in(&fat) <= 2,
+        "{} FAT reads during prefetch",
+        state.reads_in(&fat)
+    );
+    state.clear_ops();
+    assert_eq!(fs.usage_summary().unwrap(), expected);
+    assert_eq!(state.reads_in(&fat), 0);
+}
+
+#[test]
+fn test_prefetch_fat_write_through() {
+    let img = fs::read(FAT16_IMG).unwrap();
+    let data = vec![0xAB_u8; 10000];
+    let (state, free_before, free_after) = {
+        let (fs, state, _) = mount(&img, FsOptions::new());
+        fs.prefetch_fat().unwrap();
+        let free_before = fs.stats().unwrap().free_clusters();
+        fs.root_dir().create_file("new.bin").unwrap().write_all(&data).unwrap();
+        fs.root_dir().remove("long.txt").unwrap();
+        let free_after = fs.stats().unwrap().free_clusters();
+        (state, free_before, free_after)
+    };
+    // 10000 bytes need 20 clusters and 14000 bytes of removed file occupied 28 clusters
+    assert_eq!(free_after, free_before - 20 + 28);
+
+    // remount without the cache and check that the FAT changes have reached the storage
+    let fs = InstrumentedFs::new(InstrumentedStream::new(&state), FsOptions::new()).unwrap();
+    let mut content = Vec::new();
+    fs.root_dir()
+        .open_file("new.bin")
+        .unwrap()
+        .read_to_end(&mut content)
+        .unwrap();
+    assert_eq!(content, data);
+    assert!(fs.root_dir().open_file("long.txt").is_err());
+    assert_eq!(fs.stats().unwrap().free_clusters(), free_after);
+}
+
+#[test]
+fn test_prefetch_fat_respects_cache_budget() {
+    let img = create_deep_img();
+    let expected = {
+        let (fs, _, _) = mount(&img, FsOptions::new());
+        fs.usage_summary().unwrap()
+    };
+
+    let (fs, state, fat) = mount(&img, FsOptions::new().fat_cache_budget(64));
+    fs.prefetch_fat().unwrap();
+    state.clear_ops();
+    // chains of files created last lie beyond the prefetched part of the FAT
+    assert_eq!(fs.usage_summary().unwrap(), expected);
+    assert!(state.reads_in(&fat) > 0);
+}
+
+#[test]
+fn test_check_prefetches_fat() {
+    let img = create_deep_img();
+
+    let (fs, state, fat) = mount(&img, FsOptions::new());
+    assert!(fs.check().unwrap().is_clean());
+    assert!(state.reads_in(&fat) > 50, "{} FAT reads", state.reads_in(&fat));
+
+    let (fs, state, fat) = mount(&img, FsOptions::new().prefetch_fat(true));
+    assert!(fs.check().unwrap().is_clean());
+    assert!(state.reads_in(&fat) <= 2, "{} FAT reads", state.reads_in(&fat));
+    // the FAT is not read again by the next traversal
+    state.clear_ops();
+    fs.usage_summary().unwrap();
+    assert_eq!(state.reads_in(&fat), 0);
+}
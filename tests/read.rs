@@ -48,6 +48,39 @@ fn test_root_dir_fat32() {
     call_with_fs(test_root_dir, FAT32_IMG)
 }
 
+fn test_dir_entry_slots(fs: FileSystem) {
+    let root_dir = fs.root_dir();
+    let entries = root_dir.iter().map(|r| r.unwrap()).collect::<Vec<_>>();
+    let slot_counts = entries.iter().map(|e| e.slot_count()).collect::<Vec<_>>();
+    assert_eq!(slot_counts, [2, 2, 2, 3]);
+    assert!(entries.iter().all(|e| e.has_lfn()));
+    let lfn_matches_sfn = entries.iter().map(|e| e.lfn_matches_sfn()).collect::<Vec<_>>();
+    assert_eq!(lfn_matches_sfn, [false, false, false, false]);
+    let dir = root_dir.open_dir("very/long/path").unwrap();
+    let entries = dir.iter().map(|r| r.unwrap()).collect::<Vec<_>>();
+    let names = entries.iter().map(|e| e.file_name()).collect::<Vec<String>>();
+    assert_eq!(names, [".", "..", "test.txt"]);
+    let slot_counts = entries.iter().map(|e| e.slot_count()).collect::<Vec<_>>();
+    assert_eq!(slot_counts, [1, 1, 2]);
+    assert!(!entries[0].has_lfn());
+    assert!(entries[0].lfn_matches_sfn());
+}
+
+#[test]
+fn test_dir_entry_slots_fat12() {
+    call_with_fs(test_dir_entry_slots, FAT12_IMG)
+}
+
+#[test]
+fn test_dir_entry_slots_fat16() {
+    call_with_fs(test_dir_entry_slots, FAT16_IMG)
+}
+
+#[test]
+fn test_dir_entry_slots_fat32() {
+    call_with_fs(test_dir_entry_slots, FAT32_IMG)
+}
+
 fn test_read_seek_short_file(fs: FileSystem) {
     let root_dir = fs.root_dir();
     let mut short_file = root_dir.open_file("short.txt").unwrap();
@@ -290,3 +323,193 @@ fn test_multi_thread() {
         FAT32_IMG,
     )
 }
+
+fn test_usage_summary(fs: FileSystem) {
+    let summary = fs.usage_summary().unwrap();
+    // long.txt, short.txt, very/long/path/test.txt, very-long-dir-name/test.txt
+    assert_eq!(summary.files(), 4);
+    // very, very/long, very/long/path, very-long-dir-name
+    assert_eq!(summary.dirs(), 4);
+    assert_eq!(summary.logical_bytes(), 14000 + 3 * TEST_TEXT.len() as u64);
+    assert_eq!(summary.max_file_size(), 14000);
+    assert_eq!(summary.mean_file_size(), 14042 / 4);
+    assert_eq!(summary.max_depth(), 4);
+    // 28 clusters for long.txt, 1 cluster for every other file and directory and the FAT32 root directory
+    let root_dir_clusters = if fs.fat_type() == FatType::Fat32 { 1 } else { 0 };
+    assert_eq!(summary.allocated_bytes(), (28 + 3 + 4 + root_dir_clusters) * 512);
+}
+
+#[test]
+fn test_usage_summary_fat12() {
+    call_with_fs(test_usage_summary, FAT12_IMG)
+}
+
+#[test]
+fn test_usage_summary_fat16() {
+    call_with_fs(test_usage_summary, FAT16_IMG)
+}
+
+#[test]
+fn test_usage_summary_fat32() {
+    call_with_fs(test_usage_summary, FAT32_IMG)
+}
+
+fn test_entry(fs: FileSystem) {
+    let root_dir = fs.root_dir();
+    let e = root_dir.entry("long.txt").unwrap();
+    assert!(e.is_file());
+    assert_eq!(e.len(), 14000);
+    assert_eq!(e.short_file_name(), "LONG.TXT");
+    let e = root_dir.entry("/very/long/path/").unwrap();
+    assert!(e.is_dir());
+    assert_eq!(e.file_name(), "path");
+    assert_eq!(e.to_dir().entry("test.txt").unwrap().len(), TEST_TEXT.len() as u64);
+    let e = root_dir
+        .open_dir("very-long-dir-name")
+        .unwrap()
+        .entry("VERY-L~1.TXT")
+        .unwrap();
+    assert_eq!(e.file_name(), "very-long-file-name.txt");
+    assert!(matches!(
+        root_dir.entry("very/missing"),
+        Err(fatfs::Error::NotFound { .. })
+    ));
+    // a file in the middle of the path
+    assert!(matches!(
+        root_dir.entry("short.txt/test.txt"),
+        Err(fatfs::Error::InvalidInput)
+    ));
+    // the root directory has no entry
+    assert!(matches!(root_dir.entry("/"), Err(fatfs::Error::InvalidInput)));
+}
+
+#[test]
+fn test_entry_fat12() {
+    call_with_fs(test_entry, FAT12_IMG)
+}
+
+#[test]
+fn test_entry_fat16() {
+    call_with_fs(test_entry, FAT16_IMG)
+}
+
+#[test]
+fn test_entry_fat32() {
+    call_with_fs(test_entry, FAT32_IMG)
+}
+
+fn test_iter_with_volume_labels(fs: FileSystem) {
+    let root_dir = fs.root_dir();
+    let entries = root_dir
+        .iter_with_volume_labels()
+        .map(|r| r.unwrap())
+        .collect::<Vec<_>>();
+    let labels = entries.iter().filter(|e| e.is_volume()).collect::<Vec<_>>();
+    assert_eq!(labels.len(), 1);
+    assert_eq!(labels[0].raw_short_name(), b"Test!      ");
+    assert!(!labels[0].is_file());
+    assert!(!labels[0].is_dir());
+    // other entries are the same as returned by the normal iterator
+    let names = entries
+        .iter()
+        .filter(|e| !e.is_volume())
+        .map(|e| e.file_name())
+        .collect::<Vec<String>>();
+    assert_eq!(names, ["long.txt", "short.txt", "very", "very-long-dir-name"]);
+    assert!(root_dir.iter().all(|r| !r.unwrap().is_volume()));
+    // subdirectories have no volume label
+    let dir = root_dir.open_dir("very").unwrap();
+    assert!(dir.iter_with_volume_labels().all(|r| !r.unwrap().is_volume()));
+}
+
+#[test]
+fn test_iter_with_volume_labels_fat12() {
+    call_with_fs(test_iter_with_volume_labels, FAT12_IMG)
+}
+
+#[test]
+fn test_iter_with_volume_labels_fat16() {
+    call_with_fs(test_iter_with_volume_labels, FAT16_IMG)
+}
+
+#[test]
+fn test_iter_with_volume_labels_fat32() {
+    call_with_fs(test_iter_with_volume_labels, FAT32_IMG)
+}
+
+fn test_filtered_iter(fs: FileSystem) {
+    let root_dir = fs.root_dir();
+    for dir in [
+        root_dir.clone(),
+        root_dir.open_dir("very").unwrap(),
+        root_dir.open_dir("very/long/path").unwrap(),
+    ]
+    .iter()
+    {
+        let entries = dir.iter().map(|r| r.unwrap()).collect::<Vec<_>>();
+        let files = dir.files().map(|r| r.unwrap().file_name()).collect::<Vec<_>>();
+        let expected_files = entries
+            .iter()
+            .filter(|e| e.is_file())
+            .map(|e| e.file_name())
+            .collect::<Vec<_>>();
+        assert_eq!(files, expected_files);
+        let dirs = dir.dirs().map(|r| r.unwrap().file_name()).collect::<Vec<_>>();
+        let expected_dirs = entries
+            .iter()
+            .filter(|e| e.is_dir())
+            .map(|e| e.file_name())
+            .collect::<Vec<_>>();
+        assert_eq!(dirs, expected_dirs);
+        assert_eq!(files.len() + dirs.len(), entries.len());
+        assert_eq!(dir.iter_filtered(fatfs::EntryFilter::all()).count(), entries.len());
+    }
+    let files = root_dir.files().map(|r| r.unwrap().file_name()).collect::<Vec<_>>();
+    assert_eq!(files, ["long.txt", "short.txt"]);
+    let dirs = root_dir.dirs().map(|r| r.unwrap().file_name()).collect::<Vec<_>>();
+    assert_eq!(dirs, ["very", "very-long-dir-name"]);
+}
+
+#[test]
+fn test_filtered_iter_fat12() {
+    call_with_fs(test_filtered_iter, FAT12_IMG)
+}
+
+#[test]
+fn test_filtered_iter_fat16() {
+    call_with_fs(test_filtered_iter, FAT16_IMG)
+}
+
+#[test]
+fn test_filtered_iter_fat32() {
+    call_with_fs(test_filtered_iter, FAT32_IMG)
+}
+
+fn test_entry_count(fs: FileSystem) {
+    let root_dir = fs.root_dir();
+    assert_eq!(root_dir.entry_count().unwrap(), (2, 2));
+    assert_eq!(root_dir.open_dir("very").unwrap().entry_count().unwrap(), (0, 1));
+    assert_eq!(
+        root_dir.open_dir("very/long/path").unwrap().entry_count().unwrap(),
+        (1, 0)
+    );
+    assert_eq!(
+        root_dir.open_dir("very-long-dir-name").unwrap().entry_count().unwrap(),
+        (1, 0)
+    );
+}
+
+#[test]
+fn test_entry_count_fat12() {
+    call_with_fs(test_entry_count, FAT12_IMG)
+}
+
+#[test]
+fn test_entry_count_fat16() {
+    call_with_fs(test_entry_count, FAT16_IMG)
+}
+
+#[test]
+fn test_entry_count_fat32() {
+    call_with_fs(test_entry_count, FAT32_IMG)
+}
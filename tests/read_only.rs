@@ -0,0 +1,145 @@
+use std::io;
+use std::io::prelude::*;
+use std::rc::Rc;
+
+use fatfs::{DefaultTimeProvider, FileAttributes, FsOptions, LossyOemCpConverter};
+
+mod common;
+use common::{InstrumentedFs, InstrumentedStream, StreamState, WriteFault, FAT12_IMG, FAT16_IMG, FAT32_IMG};
+
+const TEST_STR: &str = "Rust is cool!\n";
+
+/// Mounts a read-only copy of the image stored in `state`. Writing to the storage panics.
+fn mount_read_only(
+    state: &Rc<StreamState>,
+    options: FsOptions<DefaultTimeProvider, LossyOemCpConverter>,
+) -> InstrumentedFs {
+    state.set_write_fault(WriteFault::Panic);
+    InstrumentedFs::new(InstrumentedStream::new(state), options.read_only(true)).unwrap()
+}
+
+fn open_fs(img_path: &str) -> InstrumentedFs {
+    let state = StreamState::from_img(img_path);
+    mount_read_only(&state, FsOptions::new().update_accessed_date(true))
+}
+
+fn assert_permission_denied<T>(result: Result<T, fatfs::Error<io::Error>>) {
+    match result {
+        Err(fatfs::Error::PermissionDenied) => {}
+        Err(e) => panic!("unexpected error {:?}", e),
+        Ok(_) => panic!("operation succeeded on a read-only filesystem"),
+    }
+}
+
+fn test_read_api(img_path: &str) {
+    let fs = open_fs(img_path);
+    assert!(fs.is_read_only());
+    let root_dir = fs.root_dir();
+    let names = root_dir.iter().map(|e| e.unwrap().file_name()).collect::<Vec<_>>();
+    assert_eq!(names, ["long.txt", "short.txt", "very", "very-long-dir-name"]);
+    let mut content = String::new();
+    root_dir
+        .open_file("very/long/path/test.txt")
+        .unwrap()
+        .read_to_string(&mut content)
+        .unwrap();
+    assert_eq!(content, TEST_STR);
+    let mut file = root_dir.open_file("long.txt").unwrap();
+    content.clear();
+    file.read_to_string(&mut content).unwrap();
+    assert_eq!(content, TEST_STR.repeat(1000));
+    file.seek(io::SeekFrom::Start(5)).unwrap();
+    file.read_exact(&mut [0; 2]).unwrap();
+    file.flush().unwrap();
+    drop(file);
+    assert_eq!(root_dir.entry("short.txt").unwrap().len(), TEST_STR.len() as u64);
+
+    let stats = fs.stats().unwrap();
+    assert!(stats.free_clusters() < stats.total_clusters());
+    assert!(fs.check().unwrap().is_clean());
+    assert!(!fs.read_status_flags().unwrap().dirty());
+    assert_eq!(fs.read_volume_label().unwrap().to_string(), "Test!");
+    fs.flush().unwrap();
+    drop(root_dir);
+    fs.unmount().unwrap();
+}
+
+#[test]
+fn test_read_api_fat12() {
+    test_read_api(FAT12_IMG)
+}
+
+#[test]
+fn test_read_api_fat16() {
+    test_read_api(FAT16_IMG)
+}
+
+#[test]
+fn test_read_api_fat32() {
+    test_read_api(FAT32_IMG)
+}
+
+fn test_modifications_denied(img_path: &str) {
+    let fs = open_fs(img_path);
+    let root_dir = fs.root_dir();
+    assert_permission_denied(root_dir.create_file("new.txt"));
+    assert_permission_denied(root_dir.create_file("short.txt"));
+    assert_permission_denied(root_dir.create_dir("new"));
+    assert_permission_denied(root_dir.remove("short.txt"));
+    assert_permission_denied(root_dir.remove("very-long-dir-name"));
+    assert_permission_denied(root_dir.rename("short.txt", &root_dir, "renamed.txt"));
+    assert_permission_denied(root_dir.open_options().write(true).open("short.txt"));
+    assert_permission_denied(root_dir.open_options().append(true).open("short.txt"));
+    let mut entry = root_dir.entry("short.txt").unwrap();
+    assert_permission_denied(entry.set_attributes(FileAttributes::READ_ONLY));
+    assert_permission_denied(fs.set_volume_label("NEW"));
+
+    let mut file = root_dir.open_file("short.txt").unwrap();
+    assert_permission_denied(fatfs::Write::write(&mut file, b"more"));
+    assert_permission_denied(file.truncate());
+    assert_permission_denied(file.set_len(1));
+    drop(file);
+
+    // nothing has changed
+    let mut content = String::new();
+    root_dir
+        .open_file("short.txt")
+        .unwrap()
+        .read_to_string(&mut content)
+        .unwrap();
+    assert_eq!(content, TEST_STR);
+    assert!(root_dir.entry("new.txt").is_err());
+    assert!(root_dir.entry("renamed.txt").is_err());
+    assert!(fs.check().unwrap().is_clean());
+}
+
+#[test]
+fn test_modifications_denied_fat12() {
+    test_modifications_denied(FAT12_IMG)
+}
+
+#[test]
+fn test_modifications_denied_fat16() {
+    test_modifications_denied(FAT16_IMG)
+}
+
+#[test]
+fn test_modifications_denied_fat32() {
+    test_modifications_denied(FAT32_IMG)
+}
+
+#[test]
+fn test_dirty_volume_stays_dirty() {
+    let state = StreamState::from_img(FAT32_IMG);
+    {
+        let fs = common::remount(&state);
+        fs.root_dir().create_file("new.txt").unwrap();
+        // the volume is not unmounted cleanly
+        std::mem::forget(fs);
+    }
+    let fs = mount_read_only(&state, FsOptions::new());
+    assert!(fs.read_status_flags().unwrap().dirty());
+    // the free cluster count is calculated but not stored
+    assert!(fs.stats().unwrap().free_clusters() > 0);
+    fs.unmount().unwrap();
+}
@@ -0,0 +1,137 @@
+use std::fs;
+use std::io;
+use std::io::prelude::*;
+
+use fatfs::{FileAttributes, FsOptions};
+
+mod common;
+use common::{mount, open_fs, FileSystem, FAT16_IMG, FAT32_IMG};
+
+/// Creates a tree of 3 levels with a long-named file and a long-named subdirectory on each level.
+///
+/// Returns the number of created files and directories including `top`.
+fn create_tree(fs: &FileSystem, top: &str) -> u64 {
+    let cluster = vec![0x5A_u8; fs.cluster_size() as usize + 1];
+    let mut dir = fs.root_dir().create_dir(top).unwrap();
+    let mut count = 1;
+    for level in 0..3 {
+        let name = format!("file with a long name on level {}.bin", level);
+        dir.create_file(&name).unwrap().write_all(&cluster).unwrap();
+        dir.create_file(&format!("empty-{}.txt", level)).unwrap();
+        dir = dir.create_dir(&format!("Subdirectory Level {}", level + 1)).unwrap();
+        count += 3;
+    }
+    count
+}
+
+fn test_remove_recursive(img_path: &str) {
+    let fs = open_fs(img_path);
+    let free_clusters = fs.stats().unwrap().free_clusters();
+    let created = create_tree(&fs, "a tree with long names");
+    assert!(fs.stats().unwrap().free_clusters() < free_clusters);
+
+    let root_dir = fs.root_dir();
+    assert!(matches!(
+        root_dir.remove("a tree with long names"),
+        Err(fatfs::Error::DirectoryIsNotEmpty)
+    ));
+    assert_eq!(root_dir.remove_recursive("a tree with long names").unwrap(), created);
+    assert!(!root_dir.exists("a tree with long names").unwrap());
+    assert_eq!(fs.stats().unwrap().free_clusters(), free_clusters);
+    assert!(fs.check().unwrap().is_clean());
+    // other entries are untouched
+    assert!(root_dir.exists("very/long/path/test.txt").unwrap());
+}
+
+#[test]
+fn test_remove_recursive_fat16() {
+    test_remove_recursive(FAT16_IMG)
+}
+
+#[test]
+fn test_remove_recursive_fat32() {
+    test_remove_recursive(FAT32_IMG)
+}
+
+#[test]
+fn test_remove_recursive_nested_path_and_file() {
+    let fs = open_fs(FAT16_IMG);
+    let root_dir = fs.root_dir();
+    assert_eq!(root_dir.remove_recursive("very/long").unwrap(), 3);
+    assert_eq!(
+        root_dir
+            .open_dir("very")
+            .unwrap()
+            .iter()
+            .map(|r| r.unwrap().file_name())
+            .collect::<Vec<_>>(),
+        [".", ".."]
+    );
+    assert_eq!(root_dir.remove_recursive("short.txt").unwrap(), 1);
+    assert!(!root_dir.exists("short.txt").unwrap());
+    assert!(fs.check().unwrap().is_clean());
+}
+
+#[test]
+fn test_remove_recursive_errors() {
+    let fs = open_fs(FAT16_IMG);
+    let root_dir = fs.root_dir();
+    assert!(matches!(
+        root_dir.remove_recursive("missing"),
+        Err(fatfs::Error::NotFound { .. })
+    ));
+    for &path in &["", "/", "."] {
+        assert!(matches!(
+            root_dir.remove_recursive(path),
+            Err(fatfs::Error::InvalidInput)
+        ));
+    }
+    assert!(root_dir.exists("very/long/path/test.txt").unwrap());
+}
+
+/// Creates a tree containing a directory "sys" marked with the `SYSTEM` attribute.
+fn system_dir_image() -> Vec<u8> {
+    let mut img = fs::read(FAT16_IMG).unwrap();
+    {
+        let fs = fatfs::FileSystem::new(io::Cursor::new(&mut img), FsOptions::new()).unwrap();
+        let dir = fs.root_dir().create_dir("tree").unwrap();
+        dir.create_file("file.txt").unwrap();
+        dir.create_dir("sys").unwrap().create_file("inside.txt").unwrap();
+        dir.create_dir("other").unwrap();
+    }
+    let offset = (0..img.len())
+        .step_by(32)
+        .find(|&i| img[i..].starts_with(b"SYS        "))
+        .unwrap();
+    img[offset + 11] |= FileAttributes::SYSTEM.bits();
+    img
+}
+
+#[test]
+fn test_remove_recursive_skips_system_dirs() {
+    let fs = mount(system_dir_image());
+    let root_dir = fs.root_dir();
+    // "file.txt" and "other" are removed, "sys" and its content are kept
+    assert_eq!(root_dir.remove_recursive("tree").unwrap(), 2);
+    let names: Vec<_> = root_dir
+        .open_dir("tree")
+        .unwrap()
+        .iter()
+        .map(|r| r.unwrap().file_name())
+        .collect();
+    assert_eq!(names, [".", "..", "sys"]);
+    assert!(root_dir.exists("tree/sys/inside.txt").unwrap());
+    // the system directory is removed if it is specified explicitly
+    assert_eq!(root_dir.remove_recursive("tree/sys").unwrap(), 2);
+    assert_eq!(root_dir.remove_recursive("tree").unwrap(), 1);
+    assert!(fs.check().unwrap().is_clean());
+}
+
+#[test]
+fn test_remove_recursive_forced_removes_system_dirs() {
+    let fs = mount(system_dir_image());
+    let root_dir = fs.root_dir();
+    assert_eq!(root_dir.remove_recursive_forced("tree").unwrap(), 5);
+    assert!(!root_dir.exists("tree").unwrap());
+    assert!(fs.check().unwrap().is_clean());
+}
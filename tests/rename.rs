@@ -0,0 +1,291 @@
+use std::fs;
+use std::io;
+use std::io::prelude::*;
+
+use fatfs::FsOptions;
+
+mod common;
+use common::{init_logger, open_fs, Dir, FAT16_IMG, FAT32_IMG};
+
+const LONG_NAME: &str = "a destination file with a name spanning three LFN slots.txt";
+
+fn write_file(dir: &Dir, path: &str, content: &[u8]) {
+    let mut file = dir.create_file(path).unwrap();
+    file.truncate().unwrap();
+    file.write_all(content).unwrap();
+}
+
+fn read_file(dir: &Dir, path: &str) -> Vec<u8> {
+    let mut content = Vec::new();
+    dir.open_file(path).unwrap().read_to_end(&mut content).unwrap();
+    content
+}
+
+/// Returns the number of used (not deleted) 32-byte slots in the directory.
+fn used_slots(dir: &Dir) -> u32 {
+    dir.iter().map(|r| r.unwrap().slot_count()).sum()
+}
+
+fn test_rename_replace(img_path: &str) {
+    let fs = open_fs(img_path);
+    let root_dir = fs.root_dir();
+    let cluster_size = fs.cluster_size() as usize;
+    let slots = used_slots(&root_dir);
+
+    write_file(&root_dir, "new content.tmp", &vec![b'N'; cluster_size + 1]);
+    write_file(&root_dir, LONG_NAME, &vec![b'O'; 3 * cluster_size]);
+    let free_clusters = fs.stats().unwrap().free_clusters();
+    assert_eq!(root_dir.entry(LONG_NAME).unwrap().slot_count(), 6);
+    assert!(matches!(
+        root_dir.rename("new content.tmp", &root_dir, LONG_NAME),
+        Err(fatfs::Error::AlreadyExists)
+    ));
+
+    root_dir
+        .rename_replace("new content.tmp", &root_dir, LONG_NAME)
+        .unwrap();
+    assert!(!root_dir.exists("new content.tmp").unwrap());
+    assert_eq!(read_file(&root_dir, LONG_NAME), vec![b'N'; cluster_size + 1]);
+    let names: Vec<_> = root_dir.iter().map(|r| r.unwrap().file_name()).collect();
+    assert_eq!(names.iter().filter(|n| *n == LONG_NAME).count(), 1);
+    // replaced entry and its LFN entries are deleted and its clusters are freed
+    assert_eq!(used_slots(&root_dir), slots + 6);
+    assert_eq!(fs.stats().unwrap().free_clusters(), free_clusters + 3);
+    assert!(fs.check().unwrap().is_clean());
+
+    // move between directories
+    let dst_dir = root_dir.open_dir("very/long/path").unwrap();
+    root_dir.rename_replace("short.txt", &dst_dir, "TEST.TXT").unwrap();
+    assert_eq!(read_file(&dst_dir, "test.txt"), b"Rust is cool!\n");
+    // the uppercase 8.3 name does not need a LFN entry
+    assert_eq!(used_slots(&dst_dir), 2 + 1);
+    assert!(fs.check().unwrap().is_clean());
+}
+
+#[test]
+fn test_rename_replace_fat16() {
+    test_rename_replace(FAT16_IMG)
+}
+
+#[test]
+fn test_rename_replace_fat32() {
+    test_rename_replace(FAT32_IMG)
+}
+
+#[test]
+fn test_rename_replace_without_destination() {
+    let fs = open_fs(FAT16_IMG);
+    let root_dir = fs.root_dir();
+    root_dir.rename_replace("short.txt", &root_dir, "renamed.txt").unwrap();
+    assert_eq!(read_file(&root_dir, "renamed.txt"), b"Rust is cool!\n");
+    // renaming an entry to itself
+    root_dir
+        .rename_replace("renamed.txt", &root_dir, "RENAMED.TXT")
+        .unwrap();
+    assert_eq!(read_file(&root_dir, "renamed.txt"), b"Rust is cool!\n");
+    assert!(fs.check().unwrap().is_clean());
+}
+
+#[test]
+fn test_rename_replace_dirs() {
+    let fs = open_fs(FAT16_IMG);
+    let root_dir = fs.root_dir();
+    root_dir.create_dir("empty").unwrap();
+    root_dir.create_dir("other").unwrap();
+    let free_clusters = fs.stats().unwrap().free_clusters();
+    // a non-empty directory is never replaced
+    assert!(matches!(
+        root_dir.rename_replace("other", &root_dir, "very"),
+        Err(fatfs::Error::DirectoryIsNotEmpty)
+    ));
+    // files and directories cannot replace each other
+    assert!(matches!(
+        root_dir.rename_replace("short.txt", &root_dir, "empty"),
+        Err(fatfs::Error::InvalidInput)
+    ));
+    assert!(matches!(
+        root_dir.rename_replace("other", &root_dir, "long.txt"),
+        Err(fatfs::Error::InvalidInput)
+    ));
+    assert!(root_dir.exists("very/long/path/test.txt").unwrap());
+    assert!(root_dir.exists("short.txt").unwrap());
+    assert!(root_dir.exists("long.txt").unwrap());
+
+    root_dir.rename_replace("very", &root_dir, "empty").unwrap();
+    assert!(!root_dir.exists("very").unwrap());
+    assert!(root_dir.exists("empty/long/path/test.txt").unwrap());
+    assert_eq!(fs.stats().unwrap().free_clusters(), free_clusters + 1);
+    assert!(fs.check().unwrap().is_clean());
+}
+
+fn read_u16(img: &[u8], offset: usize) -> usize {
+    usize::from(u16::from_le_bytes([img[offset], img[offset + 1]]))
+}
+
+#[test]
+fn test_rename_replace_leaves_no_orphaned_lfn_entries() {
+    init_logger();
+    let mut img = fs::read(FAT16_IMG).unwrap();
+    let mut live_lfn_slots = 0;
+    {
+        let fs = fatfs::FileSystem::new(io::Cursor::new(&mut img), FsOptions::new()).unwrap();
+        let root_dir = fs.root_dir();
+        root_dir.create_file(LONG_NAME).unwrap().write_all(b"old").unwrap();
+        root_dir.create_file("new.txt").unwrap().write_all(b"new").unwrap();
+        root_dir.rename_replace("new.txt", &root_dir, LONG_NAME).unwrap();
+        for r in root_dir.iter() {
+            live_lfn_slots += r.unwrap().slot_count() - 1;
+        }
+        drop(root_dir);
+        fs.unmount().unwrap();
+    }
+    // FAT16 root directory region follows the FATs
+    let bytes_per_sector = read_u16(&img, 11);
+    let root_dir_start = (read_u16(&img, 14) + usize::from(img[16]) * read_u16(&img, 22)) * bytes_per_sector;
+    let root_dir_len = read_u16(&img, 17) * 32;
+    let lfn_slots = img[root_dir_start..root_dir_start + root_dir_len]
+        .chunks(32)
+        .filter(|slot| slot[0] != 0 && slot[0] != 0xE5 && slot[11] == 0x0F)
+        .count();
+    assert_eq!(lfn_slots as u32, live_lfn_slots);
+}
+
+fn names(dir: &Dir) -> Vec<String> {
+    dir.iter().map(|r| r.unwrap().file_name()).collect()
+}
+
+#[test]
+fn test_rename_case_only() {
+    let fs = open_fs(FAT16_IMG);
+    let root_dir = fs.root_dir();
+    write_file(&root_dir, "README.md", b"readme");
+    let short_name = root_dir.entry("README.md").unwrap().short_file_name();
+    let slots = used_slots(&root_dir);
+
+    root_dir.rename("README.md", &root_dir, "readme.md").unwrap();
+    assert_eq!(
+        names(&root_dir)
+            .iter()
+            .filter(|n| n.eq_ignore_ascii_case("readme.md"))
+            .count(),
+        1
+    );
+    assert!(names(&root_dir).contains(&"readme.md".to_string()));
+    let e = root_dir.entry("readme.md").unwrap();
+    assert_eq!(e.file_name(), "readme.md");
+    assert_eq!(e.short_file_name(), short_name);
+    assert_eq!(read_file(&root_dir, "README.MD"), b"readme");
+    assert_eq!(used_slots(&root_dir), slots);
+
+    // renaming to the same name is a no-op
+    root_dir.rename("README.MD", &root_dir, "readme.md").unwrap();
+    assert_eq!(root_dir.entry("readme.md").unwrap().entry_pos(), e.entry_pos());
+    assert!(fs.check().unwrap().is_clean());
+}
+
+#[test]
+fn test_rename_extension_case_only() {
+    let fs = open_fs(FAT32_IMG);
+    let root_dir = fs.root_dir();
+    root_dir.create_dir("photos").unwrap();
+    let dir = root_dir.open_dir("photos").unwrap();
+    write_file(&dir, "holiday picture.JPG", b"jpeg");
+    // a name without a long name entry in the fixture
+    root_dir.rename("short.txt", &root_dir, "short.TXT").unwrap();
+    root_dir
+        .rename("photos/holiday picture.JPG", &root_dir, "photos/holiday picture.jpg")
+        .unwrap();
+    assert_eq!(names(&dir), [".", "..", "holiday picture.jpg"]);
+    assert_eq!(read_file(&dir, "holiday picture.jpg"), b"jpeg");
+    assert!(names(&root_dir).contains(&"short.TXT".to_string()));
+    assert_eq!(read_file(&root_dir, "short.txt"), b"Rust is cool!\n");
+    assert!(fs.check().unwrap().is_clean());
+}
+
+#[test]
+fn test_rename_onto_existing_name_keeps_source() {
+    let fs = open_fs(FAT16_IMG);
+    let root_dir = fs.root_dir();
+    let slots = used_slots(&root_dir);
+    assert!(matches!(
+        root_dir.rename("very/long/path/test.txt", &root_dir, "SHORT.TXT"),
+        Err(fatfs::Error::AlreadyExists)
+    ));
+    assert_eq!(read_file(&root_dir, "very/long/path/test.txt"), b"Rust is cool!\n");
+    assert_eq!(read_file(&root_dir, "short.txt"), b"Rust is cool!\n");
+    assert_eq!(used_slots(&root_dir), slots);
+    assert!(fs.check().unwrap().is_clean());
+}
+
+#[test]
+fn test_rename_into_full_root_dir_keeps_source() {
+    let fs = open_fs(FAT16_IMG);
+    let root_dir = fs.root_dir();
+    // fill the fixed size root directory
+    for i in 0.. {
+        match root_dir.create_file(&format!("f{}", i)) {
+            Ok(_) => {}
+            Err(fatfs::Error::NotEnoughSpace) => break,
+            Err(err) => panic!("unexpected error {:?}", err),
+        }
+    }
+    assert!(matches!(
+        root_dir.rename("very/long/path/test.txt", &root_dir, LONG_NAME),
+        Err(fatfs::Error::NotEnoughSpace)
+    ));
+    assert_eq!(read_file(&root_dir, "very/long/path/test.txt"), b"Rust is cool!\n");
+    assert!(!root_dir.exists(LONG_NAME).unwrap());
+    assert!(fs.check().unwrap().is_clean());
+}
+
+/// Returns the cluster stored in the directory entry at `pos`.
+fn entry_cluster(img: &[u8], pos: u64) -> usize {
+    let pos = pos as usize;
+    (read_u16(img, pos + 20) << 16) | read_u16(img, pos + 26)
+}
+
+fn test_rename_dir_updates_dotdot(img_path: &str) {
+    init_logger();
+    let mut stream = io::Cursor::new(fs::read(img_path).unwrap());
+    // positions of the `.` entry of the destination directory and the `..` entry of the moved directory
+    let (dst_dot_pos, dotdot_pos) = {
+        let fs = fatfs::FileSystem::new(&mut stream, FsOptions::new()).unwrap();
+        let root_dir = fs.root_dir();
+        let sub = root_dir.create_dir("src").unwrap().create_dir("moved dir").unwrap();
+        sub.create_file("file.txt").unwrap().write_all(b"data").unwrap();
+        let dst = root_dir.create_dir("dst").unwrap();
+        root_dir.rename("src/moved dir", &root_dir, "dst/moved dir").unwrap();
+        let moved = dst.open_dir("moved dir").unwrap();
+        let parent_entries = moved.parent().unwrap().unwrap().iter().count();
+        assert_eq!(parent_entries, dst.iter().count());
+        assert!(root_dir.open_file("dst/moved dir/../moved dir/file.txt").is_ok());
+        assert!(fs.check().unwrap().is_clean());
+        let dst_dot_pos = dst.iter().next().unwrap().unwrap().entry_pos();
+        let dotdot_pos = moved.iter().nth(1).unwrap().unwrap().entry_pos();
+        (dst_dot_pos, dotdot_pos)
+    };
+    let dst_cluster = entry_cluster(stream.get_ref(), dst_dot_pos);
+    assert_ne!(dst_cluster, 0);
+    assert_eq!(entry_cluster(stream.get_ref(), dotdot_pos), dst_cluster);
+    // moving to the root directory sets cluster 0
+    {
+        stream.set_position(0);
+        let fs = fatfs::FileSystem::new(&mut stream, FsOptions::new()).unwrap();
+        let root_dir = fs.root_dir();
+        root_dir.rename("dst/moved dir", &root_dir, "moved dir").unwrap();
+        let parent_entries = root_dir.open_dir("moved dir/..").unwrap().iter().count();
+        assert_eq!(parent_entries, root_dir.iter().count());
+        assert!(fs.check().unwrap().is_clean());
+    }
+    assert_eq!(entry_cluster(stream.get_ref(), dotdot_pos), 0);
+}
+
+#[test]
+fn test_rename_dir_updates_dotdot_fat16() {
+    test_rename_dir_updates_dotdot(FAT16_IMG)
+}
+
+#[test]
+fn test_rename_dir_updates_dotdot_fat32() {
+    test_rename_dir_updates_dotdot(FAT32_IMG)
+}
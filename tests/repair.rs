@@ -0,0 +1,140 @@
+#![cfg(feature = "repair")]
+
+use std::fs;
+use std::io;
+
+use fatfs::{FileAttributes, FsOptions, Read};
+
+mod common;
+use common::{init_logger, mount, FileSystem, Storage, FAT16_IMG, FAT32_IMG};
+
+const TEST_TEXT: &str = "Rust is cool!\n";
+
+type DirEntry<'a> = fatfs::DirEntry<'a, Storage, fatfs::DefaultTimeProvider, fatfs::LossyOemCpConverter>;
+
+/// Loads the image and toggles the `DIRECTORY` attribute of the root directory entry with the given short name.
+fn corrupt_image(img_path: &str, short_name: &[u8; 11]) -> Vec<u8> {
+    init_logger();
+    let mut img = fs::read(img_path).unwrap();
+    let offset = (0..img.len())
+        .step_by(32)
+        .find(|&i| img[i..].starts_with(short_name))
+        .unwrap();
+    img[offset + 11] ^= FileAttributes::DIRECTORY.bits();
+    img
+}
+
+fn root_entry<'a>(fs: &'a FileSystem, name: &str) -> DirEntry<'a> {
+    fs.root_dir()
+        .iter()
+        .map(|r| r.unwrap())
+        .find(|e| e.file_name() == name)
+        .unwrap()
+}
+
+fn read_to_string<IO: fatfs::ReadWriteSeek, TP: fatfs::TimeProvider, OCC: fatfs::OemCpConverter>(
+    mut file: fatfs::File<IO, TP, OCC>,
+) -> String {
+    let mut buf = Vec::new();
+    let mut chunk = [0_u8; 64];
+    loop {
+        let n = file.read(&mut chunk).ok().unwrap();
+        if n == 0 {
+            return String::from_utf8(buf).unwrap();
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    }
+}
+
+fn test_clear_directory_attribute(img_path: &str) {
+    let img = corrupt_image(img_path, b"SHORT   TXT");
+    let fs = mount(img);
+    let entry = root_entry(&fs, "short.txt");
+    assert!(entry.is_dir());
+    assert!(matches!(
+        entry.set_directory_attribute(),
+        Err(fatfs::Error::InvalidInput)
+    ));
+    let entry = entry.clear_directory_attribute().unwrap();
+    assert!(entry.is_file());
+    assert_eq!(read_to_string(entry.to_file()), TEST_TEXT);
+    // the change is persistent
+    assert_eq!(read_to_string(fs.root_dir().open_file("short.txt").unwrap()), TEST_TEXT);
+    assert!(fs.check().unwrap().is_clean());
+}
+
+#[test]
+fn test_clear_directory_attribute_fat16() {
+    test_clear_directory_attribute(FAT16_IMG)
+}
+
+#[test]
+fn test_clear_directory_attribute_fat32() {
+    test_clear_directory_attribute(FAT32_IMG)
+}
+
+fn test_set_directory_attribute(img_path: &str) {
+    let img = corrupt_image(img_path, b"VERY       ");
+    let fs = mount(img);
+    let entry = root_entry(&fs, "very");
+    assert!(entry.is_file());
+    assert!(matches!(
+        entry.clear_directory_attribute(),
+        Err(fatfs::Error::InvalidInput)
+    ));
+    let entry = entry.set_directory_attribute().unwrap();
+    assert!(entry.is_dir());
+    assert!(entry.to_dir().open_dir("long/path").is_ok());
+    assert_eq!(
+        read_to_string(fs.root_dir().open_file("very/long/path/test.txt").unwrap()),
+        TEST_TEXT
+    );
+    assert!(fs.check().unwrap().is_clean());
+}
+
+#[test]
+fn test_set_directory_attribute_fat16() {
+    test_set_directory_attribute(FAT16_IMG)
+}
+
+#[test]
+fn test_set_directory_attribute_fat32() {
+    test_set_directory_attribute(FAT32_IMG)
+}
+
+#[test]
+fn test_refuse_obviously_wrong_conversion() {
+    let img = fs::read(FAT16_IMG).unwrap();
+    let mut stream = io::Cursor::new(img.clone());
+    {
+        let fs = fatfs::FileSystem::new(&mut stream, FsOptions::new()).unwrap();
+        let root_dir = fs.root_dir();
+        let find = |name: &str| {
+            root_dir
+                .iter()
+                .map(|r| r.unwrap())
+                .find(|e| e.file_name() == name)
+                .unwrap()
+        };
+        // a valid directory with dot entries
+        let dir_entry = find("very");
+        assert!(matches!(
+            dir_entry.clear_directory_attribute(),
+            Err(fatfs::Error::InvalidInput)
+        ));
+        // a file with data not looking like a directory
+        let file_entry = find("short.txt");
+        assert!(matches!(
+            file_entry.set_directory_attribute(),
+            Err(fatfs::Error::InvalidInput)
+        ));
+        // dot entries themselves
+        for e in dir_entry.to_dir().iter() {
+            assert!(matches!(
+                e.unwrap().clear_directory_attribute(),
+                Err(fatfs::Error::InvalidInput)
+            ));
+        }
+    }
+    assert!(stream.into_inner() == img, "image has been modified");
+}
@@ -0,0 +1,86 @@
+use std::io;
+
+use fatfs::FsOptions;
+
+mod common;
+use common::{open_instrumented_fs, InstrumentedFs, Layout, FAT16_IMG};
+
+fn data_start(img: &[u8]) -> usize {
+    Layout::read(img).data_start as usize
+}
+
+fn read_file(fs: &InstrumentedFs, path: &str) -> Result<Vec<u8>, fatfs::Error<io::Error>> {
+    let mut file = fs.root_dir().open_file(path)?;
+    let mut content = Vec::new();
+    let mut buf = [0_u8; 1024];
+    loop {
+        let n = fatfs::Read::read(&mut file, &mut buf)?;
+        if n == 0 {
+            return Ok(content);
+        }
+        content.extend_from_slice(&buf[..n]);
+    }
+}
+
+fn assert_storage_size_changed<T: std::fmt::Debug>(result: Result<T, fatfs::Error<io::Error>>) {
+    match result {
+        Err(fatfs::Error::StorageSizeChanged) => {}
+        r => panic!("unexpected result {:?}", r),
+    }
+}
+
+#[test]
+fn test_read_from_shrunk_storage() {
+    let (fs, state) = open_instrumented_fs(FAT16_IMG, FsOptions::new());
+    let data = &state.data;
+    let orig = data.borrow().clone();
+    assert_eq!(read_file(&fs, "long.txt").unwrap().len(), 14000);
+    data.borrow_mut().truncate(data_start(&orig));
+    assert_storage_size_changed(read_file(&fs, "long.txt"));
+    // truncated storage cannot be accepted
+    assert_storage_size_changed(fs.refresh_geometry());
+    // restoring the original content makes the filesystem usable again
+    *data.borrow_mut() = orig;
+    assert_eq!(read_file(&fs, "long.txt").unwrap().len(), 14000);
+}
+
+#[test]
+fn test_write_does_not_query_storage_size() {
+    const CLUSTERS: u64 = 32;
+    let (fs, state) = open_instrumented_fs(FAT16_IMG, FsOptions::new());
+    let cluster_size = u64::from(fs.cluster_size());
+    let mut file = fs.root_dir().create_file("new.txt").unwrap();
+    let buf = vec![0xAA_u8; cluster_size as usize];
+    state.clear_ops();
+    for _ in 0..CLUSTERS {
+        fatfs::Write::write_all(&mut file, &buf).unwrap();
+    }
+    // the size is checked lazily - only accesses past the known storage size query it
+    assert_eq!(state.seeks(|pos| matches!(pos, io::SeekFrom::End(_))), 0);
+    drop(file);
+    assert_eq!(read_file(&fs, "new.txt").unwrap().len() as u64, CLUSTERS * cluster_size);
+}
+
+#[test]
+fn test_refresh_geometry_after_grow() {
+    let (fs, state) = open_instrumented_fs(FAT16_IMG, FsOptions::new());
+    let data = &state.data;
+    let len = data.borrow().len();
+    let data_start = data_start(&data.borrow());
+    data.borrow_mut().resize(len * 2, 0);
+    fs.refresh_geometry().unwrap();
+    assert_eq!(read_file(&fs, "long.txt").unwrap().len(), 14000);
+    // shrinking the storage after accepting the bigger size is detected
+    data.borrow_mut().truncate(data_start);
+    assert_storage_size_changed(read_file(&fs, "long.txt"));
+    // shrinking back to the volume size is accepted
+    data.borrow_mut().resize(len, 0);
+    fs.refresh_geometry().unwrap();
+}
+
+#[test]
+fn test_refresh_geometry_without_resize() {
+    let (fs, _) = open_instrumented_fs(FAT16_IMG, FsOptions::new());
+    fs.refresh_geometry().unwrap();
+    assert_eq!(read_file(&fs, "short.txt").unwrap(), b"Rust is cool!\n");
+}
@@ -0,0 +1,174 @@
+use std::io;
+use std::io::prelude::*;
+
+use fatfs::{DefaultTimeProvider, LossyOemCpConverter};
+
+mod common;
+use common::{open_fs, FileSystem, Storage, FAT16_IMG, FAT32_IMG};
+
+type ScopedDir<'a> = fatfs::ScopedDir<'a, Storage, DefaultTimeProvider, LossyOemCpConverter>;
+
+/// Creates `/ETC/SECRET` outside and `/DATA/APP1/ETC/SECRET` inside of the scope.
+fn open_fs_with_dirs(img_path: &str) -> FileSystem {
+    let fs = open_fs(img_path);
+    {
+        let root_dir = fs.root_dir();
+        for dir in &[
+            "ETC",
+            "DATA",
+            "DATA/APP1",
+            "DATA/APP1/ETC",
+            "DATA/APP1/sub",
+            "DATA/APP2",
+        ] {
+            root_dir.create_dir(dir).unwrap();
+        }
+        write_file(root_dir.create_file("ETC/SECRET").unwrap(), "outside");
+        write_file(root_dir.create_file("DATA/APP1/ETC/SECRET").unwrap(), "inside");
+        write_file(root_dir.create_file("DATA/APP2/other.txt").unwrap(), "app2");
+    }
+    fs
+}
+
+fn write_file<T: Write>(mut file: T, content: &str) {
+    file.write_all(content.as_bytes()).unwrap();
+}
+
+fn read_file<T: Read>(mut file: T) -> String {
+    let mut content = String::new();
+    file.read_to_string(&mut content).unwrap();
+    content
+}
+
+fn list(dir: &ScopedDir) -> Vec<String> {
+    dir.iter().map(|r| r.unwrap().file_name()).collect()
+}
+
+fn assert_not_found<T>(result: Result<T, fatfs::Error<io::Error>>, path: &str) {
+    match result {
+        Err(fatfs::Error::NotFound { .. }) => {}
+        Err(err) => panic!("unexpected error {:?} for path {:?}", err, path),
+        Ok(_) => panic!("path {:?} has been found", path),
+    }
+}
+
+const ESCAPING_SECRET_PATHS: [&str; 6] = [
+    "../../ETC/SECRET",
+    "/ETC/SECRET",
+    "../../../../../../../../ETC/SECRET",
+    "sub/../../ETC/SECRET",
+    "./sub/./../.././../ETC/./SECRET",
+    "//..//..//ETC//SECRET",
+];
+
+fn test_escape_attempts(img_path: &str) {
+    let fs = open_fs_with_dirs(img_path);
+    let scope = fs.scoped_root("DATA/APP1").unwrap();
+    for path in &ESCAPING_SECRET_PATHS {
+        assert_eq!(read_file(scope.open_file(path).unwrap()), "inside", "{}", path);
+        assert!(scope.exists(path).unwrap());
+    }
+    // a subdirectory is confined to the same scope
+    let sub = scope.open_dir("sub").unwrap();
+    assert_eq!(sub.path(), "sub");
+    for path in &ESCAPING_SECRET_PATHS {
+        assert_eq!(read_file(sub.open_file(path).unwrap()), "inside", "{}", path);
+    }
+    assert_eq!(read_file(sub.open_file("../ETC/SECRET").unwrap()), "inside");
+    assert_not_found(sub.open_file("ETC/SECRET"), "ETC/SECRET");
+    // other directories of the volume are not reachable
+    assert_not_found(scope.open_file("../APP2/other.txt"), "../APP2/other.txt");
+    assert_not_found(scope.open_dir("../../DATA/APP2"), "../../DATA/APP2");
+    assert!(!scope.exists("../../very/long/path/test.txt").unwrap());
+}
+
+#[test]
+fn test_escape_attempts_fat16() {
+    test_escape_attempts(FAT16_IMG)
+}
+
+#[test]
+fn test_escape_attempts_fat32() {
+    test_escape_attempts(FAT32_IMG)
+}
+
+#[test]
+fn test_dot_dot_clamped_at_scope_root() {
+    let fs = open_fs_with_dirs(FAT16_IMG);
+    let scope = fs.scoped_root("/DATA/APP1/").unwrap();
+    let entries = list(&scope);
+    assert_eq!(entries, ["ETC", "sub"]);
+    for path in &["..", "../..", "sub/../..", "/", "", "."] {
+        let dir = scope.open_dir(path).unwrap();
+        assert_eq!(dir.path(), "");
+        assert_eq!(list(&dir), entries, "{}", path);
+    }
+    let sub = scope.open_dir("ETC/../sub/.").unwrap();
+    assert_eq!(sub.path(), "sub");
+    assert!(list(&sub).is_empty());
+    assert_eq!(list(&sub.open_dir("..").unwrap()), entries);
+    // the scope root itself cannot be removed or renamed
+    for path in &["..", "sub/../..", "/"] {
+        assert!(matches!(scope.remove(path), Err(fatfs::Error::InvalidInput)));
+        assert!(matches!(sub.rename(path, &sub, "x"), Err(fatfs::Error::InvalidInput)));
+    }
+}
+
+#[test]
+fn test_dir_entries_are_scoped() {
+    let fs = open_fs_with_dirs(FAT16_IMG);
+    let scope = fs.scoped_root("DATA/APP1").unwrap();
+    let sub_entry = scope
+        .iter()
+        .map(|r| r.unwrap())
+        .find(|e| e.file_name() == "sub")
+        .unwrap();
+    assert!(sub_entry.is_dir());
+    let sub = sub_entry.to_dir();
+    assert_eq!(sub.path(), "sub");
+    // "." and ".." entries are not returned so a parent directory cannot be obtained from them
+    assert!(sub.iter().next().is_none());
+    assert_eq!(read_file(sub.open_file("../../ETC/SECRET").unwrap()), "inside");
+    let etc = scope.open_dir("ETC").unwrap();
+    let secret = etc.iter().map(|r| r.unwrap()).next().unwrap();
+    assert_eq!(secret.file_name(), "SECRET");
+    assert_eq!(secret.len(), 6);
+    assert_eq!(read_file(secret.to_file()), "inside");
+}
+
+#[test]
+fn test_mutations_are_scoped() {
+    let fs = open_fs_with_dirs(FAT16_IMG);
+    let root_dir = fs.root_dir();
+    let root_entries: Vec<_> = root_dir.iter().map(|r| r.unwrap().file_name()).collect();
+    let scope = fs.scoped_root("DATA/APP1").unwrap();
+    let sub = scope.open_dir("sub").unwrap();
+
+    write_file(sub.create_file("../../../new.txt").unwrap(), "new");
+    let dir = sub.create_dir("../../../ETC/../new-dir").unwrap();
+    assert_eq!(dir.path(), "new-dir");
+    sub.rename("/new.txt", &dir, "../../moved.txt").unwrap();
+    scope.remove("../../ETC/SECRET").unwrap();
+    sub.remove("../../new-dir").unwrap();
+    assert_eq!(list(&scope), ["ETC", "sub", "moved.txt"]);
+    assert!(list(&scope.open_dir("ETC").unwrap()).is_empty());
+
+    // nothing has changed outside of the scope
+    assert_eq!(
+        root_dir.iter().map(|r| r.unwrap().file_name()).collect::<Vec<_>>(),
+        root_entries
+    );
+    assert_eq!(read_file(root_dir.open_file("ETC/SECRET").unwrap()), "outside");
+    assert_eq!(read_file(root_dir.open_file("DATA/APP1/moved.txt").unwrap()), "new");
+    assert!(fs.check().unwrap().is_clean());
+}
+
+#[test]
+fn test_into_scoped() {
+    let fs = open_fs_with_dirs(FAT16_IMG);
+    let scope = fs.root_dir().open_dir("DATA").unwrap().into_scoped();
+    assert_eq!(read_file(scope.open_file("../APP1/ETC/SECRET").unwrap()), "inside");
+    assert_eq!(read_file(scope.open_file("../../APP2/other.txt").unwrap()), "app2");
+    assert_not_found(scope.open_file("../ETC/SECRET"), "../ETC/SECRET");
+    assert!(matches!(fs.scoped_root("ETC/SECRET"), Err(fatfs::Error::InvalidInput)));
+}
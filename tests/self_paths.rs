@@ -0,0 +1,190 @@
+use std::io;
+
+use fatfs::FsOptions;
+
+mod common;
+use common::{open_fs, open_fs_with_options, Dir, FAT16_IMG, FAT32_IMG};
+
+const SELF_PATHS: [&str; 5] = ["", "/", "//", ".", "/./"];
+
+fn list(dir: &Dir) -> Vec<String> {
+    dir.iter().map(|r| r.unwrap().file_name()).collect()
+}
+
+fn assert_invalid_input<T>(result: Result<T, fatfs::Error<io::Error>>, path: &str) {
+    match result {
+        Err(fatfs::Error::InvalidInput) => {}
+        Err(err) => panic!("unexpected error {:?} for path {:?}", err, path),
+        Ok(_) => panic!("path {:?} has been accepted", path),
+    }
+}
+
+fn check_self_paths(dir: &Dir, root_dir: &Dir) {
+    let entries = list(dir);
+    for &path in &SELF_PATHS {
+        assert_eq!(list(&dir.open_dir(path).unwrap()), entries, "open_dir({:?})", path);
+        assert_invalid_input(dir.open_file(path), path);
+        assert_invalid_input(dir.create_file(path), path);
+        assert_invalid_input(dir.create_dir(path), path);
+        assert_invalid_input(dir.remove(path), path);
+        assert_invalid_input(dir.rename(path, root_dir, "renamed"), path);
+        assert_invalid_input(dir.rename("short.txt", root_dir, path), path);
+    }
+    assert_eq!(list(dir), entries);
+}
+
+fn test_self_paths(img_path: &str) {
+    let fs = open_fs(img_path);
+    let root_dir = fs.root_dir();
+    check_self_paths(&root_dir, &root_dir);
+    let subdir = root_dir.open_dir("very/long").unwrap();
+    check_self_paths(&subdir, &root_dir);
+    assert_eq!(list(&subdir.open_dir(".").unwrap()), [".", "..", "path"]);
+    // nothing has been modified
+    assert!(fs.check().unwrap().is_clean());
+}
+
+#[test]
+fn test_self_paths_fat16() {
+    test_self_paths(FAT16_IMG)
+}
+
+#[test]
+fn test_self_paths_fat32() {
+    test_self_paths(FAT32_IMG)
+}
+
+#[test]
+fn test_trailing_slashes_still_accepted() {
+    let fs = open_fs(FAT16_IMG);
+    let root_dir = fs.root_dir();
+    assert_eq!(list(&root_dir.open_dir("/very/").unwrap()), [".", "..", "long"]);
+    root_dir.open_file("/short.txt").unwrap();
+}
+
+fn read_to_string(dir: &Dir, path: &str) -> String {
+    let mut content = String::new();
+    io::Read::read_to_string(&mut dir.open_file(path).unwrap(), &mut content).unwrap();
+    content
+}
+
+fn test_dot_components(img_path: &str) {
+    let fs = open_fs(img_path);
+    let root_dir = fs.root_dir();
+    let text = "Rust is cool!\n";
+    assert_eq!(read_to_string(&root_dir, "./short.txt"), text);
+    assert_eq!(read_to_string(&root_dir, "very/../short.txt"), text);
+    assert_eq!(read_to_string(&root_dir, "very/./long//path/../../../short.txt"), text);
+    assert_eq!(read_to_string(&root_dir, "very//long/./path/test.txt"), text);
+    assert_eq!(list(&root_dir.open_dir("very/long/..").unwrap()), [".", "..", "long"]);
+    assert_eq!(
+        list(&root_dir.open_dir("very/../very-long-dir-name/").unwrap()).len(),
+        3
+    );
+    let subdir = root_dir.open_dir("very/long/path").unwrap();
+    assert_eq!(list(&subdir.open_dir("../..").unwrap()), [".", "..", "long"]);
+    assert_eq!(list(&subdir.open_dir("../../..").unwrap()), list(&root_dir));
+    assert_eq!(read_to_string(&subdir, "../../../short.txt"), text);
+    assert!(subdir.exists("../../../very-long-dir-name").unwrap());
+    assert!(subdir.exists("../..").unwrap());
+    assert!(!subdir.exists("../../../..").unwrap());
+    assert!(!root_dir.exists("../short.txt").unwrap());
+
+    // ".." above the root directory
+    assert_invalid_input(root_dir.open_dir(".."), "..");
+    assert_invalid_input(root_dir.open_file("very/../../short.txt"), "very/../../short.txt");
+    // a path cannot end with "." or ".." if it should point to an entry
+    for &path in &["very/.", "very/..", "very/long/.."] {
+        assert_invalid_input(root_dir.open_file(path), path);
+        assert_invalid_input(root_dir.create_file(path), path);
+        assert_invalid_input(root_dir.create_dir(path), path);
+        assert_invalid_input(root_dir.remove(path), path);
+        assert_invalid_input(root_dir.rename(path, &root_dir, "renamed"), path);
+        assert_invalid_input(root_dir.rename("short.txt", &root_dir, path), path);
+    }
+
+    // modifications
+    root_dir.create_dir("very/../new-dir").unwrap();
+    root_dir.create_file("./new-dir//./new.txt").unwrap();
+    assert_eq!(list(&root_dir.open_dir("new-dir").unwrap()), [".", "..", "new.txt"]);
+    subdir
+        .rename("../../../new-dir/new.txt", &subdir, "./moved.txt")
+        .unwrap();
+    assert_eq!(list(&subdir), [".", "..", "test.txt", "moved.txt"]);
+    subdir.remove("../path/moved.txt").unwrap();
+    root_dir.remove("very/../new-dir").unwrap();
+    assert!(!root_dir.exists("new-dir").unwrap());
+    assert_eq!(list(&subdir), [".", "..", "test.txt"]);
+    assert!(fs.check().unwrap().is_clean());
+}
+
+#[test]
+fn test_dot_components_fat16() {
+    test_dot_components(FAT16_IMG)
+}
+
+#[test]
+fn test_dot_components_fat32() {
+    test_dot_components(FAT32_IMG)
+}
+
+fn test_backslash_separator(img_path: &str) {
+    let fs = open_fs_with_options(img_path, FsOptions::new().backslash_separator(true));
+    let root_dir = fs.root_dir();
+    let text = "Rust is cool!\n";
+    assert_eq!(read_to_string(&root_dir, "very\\long/path\\test.txt"), text);
+    assert_eq!(read_to_string(&root_dir, "\\very/long\\\\path/test.txt"), text);
+    assert_eq!(read_to_string(&root_dir, "very\\..\\short.txt"), text);
+    assert_eq!(list(&root_dir.open_dir("very\\long\\").unwrap()), [".", "..", "path"]);
+    assert!(root_dir.exists("very-long-dir-name\\very-long-file-name.txt").unwrap());
+    // a lone backslash refers to self directory
+    assert_eq!(list(&root_dir.open_dir("\\").unwrap()), list(&root_dir));
+    assert_eq!(list(&root_dir.open_dir("/\\.\\").unwrap()), list(&root_dir));
+    assert_invalid_input(root_dir.open_file("\\"), "\\");
+    assert_invalid_input(root_dir.create_file("\\"), "\\");
+    assert_invalid_input(root_dir.create_dir("very\\."), "very\\.");
+
+    // modifications
+    root_dir.create_dir("very\\new-dir\\").unwrap();
+    root_dir.create_file("very/new-dir\\new.txt").unwrap();
+    assert_eq!(
+        list(&root_dir.open_dir("very/new-dir").unwrap()),
+        [".", "..", "new.txt"]
+    );
+    root_dir
+        .rename("very\\new-dir/new.txt", &root_dir, "\\moved.txt")
+        .unwrap();
+    assert!(root_dir.exists("moved.txt").unwrap());
+    root_dir.remove("very\\new-dir").unwrap();
+    assert_eq!(list(&root_dir.open_dir("very").unwrap()), [".", "..", "long"]);
+
+    // a scoped directory resolves backslashes too
+    let scoped = fs.scoped_root("very").unwrap().open_dir("\\long\\").unwrap();
+    assert_eq!(scoped.path(), "long");
+    assert!(scoped.exists("path\\test.txt").unwrap());
+    // `..` is clamped at the scope root
+    assert!(scoped.exists("..\\..\\long/path").unwrap());
+    assert!(fs.check().unwrap().is_clean());
+}
+
+#[test]
+fn test_backslash_separator_fat16() {
+    test_backslash_separator(FAT16_IMG)
+}
+
+#[test]
+fn test_backslash_separator_fat32() {
+    test_backslash_separator(FAT32_IMG)
+}
+
+#[test]
+fn test_backslash_is_not_a_separator_by_default() {
+    let fs = open_fs(FAT16_IMG);
+    let root_dir = fs.root_dir();
+    assert!(!root_dir.exists("very\\long").unwrap());
+    assert!(matches!(root_dir.open_dir("\\"), Err(fatfs::Error::NotFound { .. })));
+    assert!(matches!(
+        root_dir.create_file("very\\new.txt"),
+        Err(fatfs::Error::UnsupportedFileNameCharacter)
+    ));
+}
@@ -0,0 +1,128 @@
+use std::io;
+use std::io::prelude::*;
+
+mod common;
+use common::{open_fs, Dir, FileSystem, FAT12_IMG, FAT16_IMG, FAT32_IMG};
+
+const TEST_STR: &str = "Rust is cool!\n";
+
+fn read_to_vec(dir: &Dir, path: &str) -> Vec<u8> {
+    let mut content = Vec::new();
+    dir.open_file(path).unwrap().read_to_end(&mut content).unwrap();
+    content
+}
+
+fn free_clusters(fs: &FileSystem) -> u32 {
+    fs.stats().unwrap().free_clusters()
+}
+
+fn test_set_len(img_path: &str) {
+    let fs = open_fs(img_path);
+    let root_dir = fs.root_dir();
+    let free = free_clusters(&fs);
+    let mut file = root_dir.open_file("short.txt").unwrap();
+    file.seek(io::SeekFrom::Start(4)).unwrap();
+
+    // extend the file to 6 clusters
+    file.set_len(3000).unwrap();
+    assert_eq!(file.stream_position().unwrap(), 4);
+    assert_eq!(file.fragmentation().unwrap().allocated_bytes(), 6 * 512);
+    assert_eq!(free_clusters(&fs), free - 5);
+    drop(file);
+    let content = read_to_vec(&root_dir, "short.txt");
+    assert_eq!(content.len(), 3000);
+    assert_eq!(&content[..TEST_STR.len()], TEST_STR.as_bytes());
+    assert!(content[TEST_STR.len()..].iter().all(|b| *b == 0));
+
+    // shrink the file to a cluster boundary
+    let mut file = root_dir.open_file("short.txt").unwrap();
+    file.seek(io::SeekFrom::Start(2000)).unwrap();
+    file.set_len(1024).unwrap();
+    assert_eq!(file.stream_position().unwrap(), 1024);
+    assert_eq!(file.fragmentation().unwrap().allocated_bytes(), 2 * 512);
+    assert_eq!(free_clusters(&fs), free - 1);
+    // the file can be extended by writing after shrinking
+    file.write_all(b"end").unwrap();
+    drop(file);
+    assert_eq!(root_dir.entry("short.txt").unwrap().len(), 1027);
+    assert_eq!(free_clusters(&fs), free - 2);
+
+    // shrink the file inside a cluster - the partially used cluster is kept
+    let mut file = root_dir.open_file("short.txt").unwrap();
+    file.set_len(5).unwrap();
+    assert_eq!(file.stream_position().unwrap(), 0);
+    assert_eq!(free_clusters(&fs), free);
+    // extending overwrites old data in the last cluster with zeros
+    file.set_len(TEST_STR.len() as u64).unwrap();
+    drop(file);
+    assert_eq!(read_to_vec(&root_dir, "short.txt"), b"Rust \0\0\0\0\0\0\0\0\0");
+
+    // shrinking to 0 frees the whole chain
+    let mut file = root_dir.open_file("short.txt").unwrap();
+    file.set_len(0).unwrap();
+    assert_eq!(file.fragmentation().unwrap().extents(), 0);
+    assert_eq!(free_clusters(&fs), free + 1);
+    // setting the same length changes nothing
+    file.set_len(0).unwrap();
+    // an empty file gets the first cluster
+    file.set_len(512).unwrap();
+    drop(file);
+    assert_eq!(read_to_vec(&root_dir, "short.txt"), vec![0; 512]);
+    assert_eq!(free_clusters(&fs), free);
+    assert!(fs.check().unwrap().is_clean());
+}
+
+#[test]
+fn test_set_len_fat12() {
+    test_set_len(FAT12_IMG)
+}
+
+#[test]
+fn test_set_len_fat16() {
+    test_set_len(FAT16_IMG)
+}
+
+#[test]
+fn test_set_len_fat32() {
+    test_set_len(FAT32_IMG)
+}
+
+#[test]
+fn test_set_len_not_enough_space() {
+    let fs = open_fs(FAT12_IMG);
+    let root_dir = fs.root_dir();
+    let free = free_clusters(&fs);
+    root_dir
+        .create_file("filler.bin")
+        .unwrap()
+        .set_len(u64::from(free - 3) * 512)
+        .unwrap();
+    assert_eq!(free_clusters(&fs), 3);
+
+    let mut file = root_dir.open_file("short.txt").unwrap();
+    file.seek(io::SeekFrom::End(0)).unwrap();
+    assert!(matches!(file.set_len(10 * 512), Err(fatfs::Error::NotEnoughSpace)));
+    // allocated clusters are freed and the length is not changed
+    assert_eq!(free_clusters(&fs), 3);
+    assert_eq!(file.stream_position().unwrap(), TEST_STR.len() as u64);
+    drop(file);
+    assert_eq!(read_to_vec(&root_dir, "short.txt"), TEST_STR.as_bytes());
+    assert!(fs.check().unwrap().is_clean());
+
+    // the remaining space can still be used
+    root_dir.open_file("short.txt").unwrap().set_len(4 * 512).unwrap();
+    assert_eq!(free_clusters(&fs), 0);
+    assert!(fs.check().unwrap().is_clean());
+}
+
+#[test]
+fn test_set_len_errors() {
+    let fs = open_fs(FAT16_IMG);
+    let root_dir = fs.root_dir();
+    let mut file = root_dir.open_file("short.txt").unwrap();
+    assert!(matches!(file.set_len(1 << 32), Err(fatfs::Error::FileTooLarge)));
+    let mut file = root_dir.open_options().read(true).open("short.txt").unwrap();
+    assert!(matches!(file.set_len(0), Err(fatfs::Error::PermissionDenied)));
+    drop(file);
+    assert_eq!(read_to_vec(&root_dir, "short.txt"), TEST_STR.as_bytes());
+}
@@ -0,0 +1,27 @@
+use std::fs;
+use std::io;
+
+use fatfs::FsOptions;
+
+#[allow(dead_code)]
+#[path = "../examples/shell.rs"]
+mod shell;
+
+mod common;
+use common::{init_logger, FAT16_IMG};
+
+const SCRIPT: &str = "resources/shell-script.txt";
+const EXPECTED_OUTPUT: &str = "resources/shell-output.txt";
+
+#[test]
+fn test_shell_script() {
+    init_logger();
+    let img = fs::read(FAT16_IMG).unwrap();
+    let options = FsOptions::new().time_provider(shell::FixedTimeProvider);
+    let fs = fatfs::FileSystem::new(io::Cursor::new(img), options).unwrap();
+    let script = fs::read(SCRIPT).unwrap();
+    let mut output = Vec::new();
+    shell::run(&fs, &script[..], &mut output, true).unwrap();
+    let expected = fs::read_to_string(EXPECTED_OUTPUT).unwrap();
+    assert_eq!(String::from_utf8(output).unwrap(), expected);
+}
@@ -0,0 +1,85 @@
+use fatfs::{FsOptions, ShortNameAlgorithm};
+
+mod common;
+use common::{open_fs_with_options, FAT16_IMG, FAT32_IMG};
+
+/// Creates colliding names using all methods creating a name and returns their short names.
+fn create_colliding_names(img_path: &str, algorithm: ShortNameAlgorithm) -> Vec<String> {
+    let fs = open_fs_with_options(img_path, FsOptions::new().short_name_algorithm(algorithm));
+    let root_dir = fs.root_dir();
+    let dir = root_dir.create_dir("names").unwrap();
+    for i in 1..7 {
+        dir.create_file(&format!("Long File Name {}.txt", i)).unwrap();
+    }
+    dir.create_dir("Long File Name 7.txt").unwrap();
+    root_dir.rename("short.txt", &dir, "Long File Name 8.txt").unwrap();
+    let short_names = dir.iter().skip(2).map(|r| r.unwrap().short_file_name()).collect();
+    assert!(fs.check().unwrap().is_clean());
+    short_names
+}
+
+fn test_checksum_algorithm(img_path: &str) {
+    let short_names = create_colliding_names(img_path, ShortNameAlgorithm::Checksum);
+    let expected = [
+        "LONGFI~1.TXT",
+        "LONGFI~2.TXT",
+        "LONGFI~3.TXT",
+        "LONGFI~4.TXT",
+        "LO679D~1.TXT",
+        "LO779D~1.TXT",
+        "LO879D~1.TXT",
+        "LO979D~1.TXT",
+    ];
+    assert_eq!(short_names, expected);
+}
+
+#[test]
+fn test_checksum_algorithm_fat16() {
+    test_checksum_algorithm(FAT16_IMG)
+}
+
+#[test]
+fn test_checksum_algorithm_fat32() {
+    test_checksum_algorithm(FAT32_IMG)
+}
+
+fn test_windows_algorithm(img_path: &str) {
+    let short_names = create_colliding_names(img_path, ShortNameAlgorithm::Windows);
+    let expected = [
+        "LONGFI~1.TXT",
+        "LONGFI~2.TXT",
+        "LONGFI~3.TXT",
+        "LONGFI~4.TXT",
+        "LO8FE5~1.TXT",
+        "LO3AB1~1.TXT",
+        "LO2B72~1.TXT",
+        "LO001A~1.TXT",
+    ];
+    assert_eq!(short_names, expected);
+}
+
+#[test]
+fn test_windows_algorithm_fat16() {
+    test_windows_algorithm(FAT16_IMG)
+}
+
+#[test]
+fn test_windows_algorithm_fat32() {
+    test_windows_algorithm(FAT32_IMG)
+}
+
+fn test_numeric_tail_algorithm(img_path: &str) {
+    let short_names = create_colliding_names(img_path, ShortNameAlgorithm::NumericTail);
+    let expected: Vec<_> = (1..9).map(|i| format!("LONGFI~{}.TXT", i)).collect();
+    assert_eq!(short_names, expected);
+}
+
+#[test]
+fn test_numeric_tail_algorithm_fat16() {
+    test_numeric_tail_algorithm(FAT16_IMG)
+}
+
+#[test]
+fn test_numeric_tail_algorithm_fat32() {
+    test_numeric_tail_algorithm(FAT32_IMG)
+}
@@ -0,0 +1,89 @@
+use std::fs;
+use std::io;
+
+use fatfs::FsOptions;
+
+mod common;
+use common::{init_logger, InstrumentedStream, StreamState, FAT16_IMG, FAT32_IMG};
+
+fn mount(data: Vec<u8>) -> fatfs::Error<io::Error> {
+    init_logger();
+    match fatfs::FileSystem::new(io::Cursor::new(data), FsOptions::new()) {
+        Ok(_) => panic!("mounting should fail"),
+        Err(err) => err,
+    }
+}
+
+fn image_prefix(img_path: &str, len: usize) -> Vec<u8> {
+    let mut img = fs::read(img_path).unwrap();
+    img.truncate(len);
+    img
+}
+
+fn assert_too_small(err: &fatfs::Error<io::Error>, expected_len: u64, expected_min_len: u64) {
+    match *err {
+        fatfs::Error::StorageTooSmall { len, min_len } => assert_eq!((len, min_len), (expected_len, expected_min_len)),
+        _ => panic!("unexpected error {:?}", err),
+    }
+}
+
+#[test]
+fn test_mount_empty_storage() {
+    let err = mount(Vec::new());
+    assert_too_small(&err, 0, 512);
+    assert_eq!(
+        err.to_string(),
+        "Storage is too small: 0 bytes long but at least 512 bytes are required"
+    );
+    let io_err: io::Error = err.into();
+    assert_eq!(io_err.kind(), io::ErrorKind::UnexpectedEof);
+}
+
+#[test]
+fn test_mount_storage_shorter_than_sector() {
+    let err = mount(image_prefix(FAT16_IMG, 100));
+    assert_too_small(&err, 100, 512);
+    assert_eq!(
+        err.to_string(),
+        "Storage is too small: 100 bytes long but at least 512 bytes are required"
+    );
+}
+
+#[test]
+fn test_mount_single_sector_storage() {
+    // reserved sector, 2 FATs of 20 sectors, 32 root directory sectors and a 512 bytes cluster
+    let err = mount(image_prefix(FAT16_IMG, 512));
+    assert_too_small(&err, 512, 37888);
+    assert_eq!(
+        err.to_string(),
+        "Storage is too small: 512 bytes long but at least 37888 bytes are required"
+    );
+    // geometry cannot be read from an empty sector
+    assert!(matches!(mount(vec![0; 512]), fatfs::Error::CorruptedFileSystem));
+}
+
+#[test]
+fn test_mount_truncated_storage() {
+    assert_too_small(&mount(image_prefix(FAT16_IMG, 37887)), 37887, 37888);
+    let fs = fatfs::FileSystem::new(io::Cursor::new(image_prefix(FAT16_IMG, 37888)), FsOptions::new());
+    assert!(fs.is_ok());
+    let fat32_img = fs::read(FAT32_IMG).unwrap();
+    let err = mount(image_prefix(FAT32_IMG, 4096));
+    match err {
+        fatfs::Error::StorageTooSmall { len: 4096, min_len } => {
+            assert!(min_len > 4096 && min_len < fat32_img.len() as u64)
+        }
+        _ => panic!("unexpected error {:?}", err),
+    }
+}
+
+#[test]
+fn test_mount_forward_only_stream() {
+    let state = StreamState::from_img(FAT16_IMG);
+    state.set_forward_only(true);
+    match fatfs::FileSystem::new(InstrumentedStream::new(&state), FsOptions::new()) {
+        Err(fatfs::Error::Io(err)) => assert_eq!(err.to_string(), "stream is not seekable"),
+        Err(err) => panic!("unexpected error {:?}", err),
+        Ok(_) => panic!("mounting should fail"),
+    }
+}
@@ -0,0 +1,218 @@
+use std::fs;
+use std::io;
+use std::io::prelude::*;
+use std::mem;
+
+use fatfs::{FsOptions, FsStatusFlagSources};
+
+mod common;
+use common::{init_logger, FAT12_IMG, FAT16_IMG, FAT32_IMG};
+
+const TEST_STR: &str = "Hi there Rust programmer!\n";
+
+fn read_u16(img: &[u8], offset: usize) -> usize {
+    usize::from(u16::from_le_bytes([img[offset], img[offset + 1]]))
+}
+
+fn read_u32(img: &[u8], offset: usize) -> usize {
+    u32::from_le_bytes([img[offset], img[offset + 1], img[offset + 2], img[offset + 3]]) as usize
+}
+
+/// Returns offsets of the second FAT entry in every FAT.
+fn fat1_offsets(img: &[u8], entry_size: usize) -> Vec<usize> {
+    let bytes_per_sector = read_u16(img, 11);
+    let reserved_sectors = read_u16(img, 14);
+    let fats = usize::from(img[16]);
+    let sectors_per_fat = match read_u16(img, 22) {
+        0 => read_u32(img, 36),
+        n => n,
+    };
+    (0..fats)
+        .map(|i| (reserved_sectors + i * sectors_per_fat) * bytes_per_sector + entry_size)
+        .collect()
+}
+
+/// Returns offsets of all bytes that differ between the images.
+fn changed_bytes(old: &[u8], new: &[u8]) -> Vec<usize> {
+    (0..old.len()).filter(|&i| old[i] != new[i]).collect()
+}
+
+fn read_sources(img: &mut Vec<u8>) -> (FsStatusFlagSources, bool) {
+    init_logger();
+    let fs = fatfs::FileSystem::new(io::Cursor::new(img), FsOptions::new()).unwrap();
+    let sources = fs.read_status_flag_sources().unwrap();
+    let dirty = fs.read_status_flags().unwrap().dirty();
+    // do not write anything
+    mem::forget(fs);
+    (sources, dirty)
+}
+
+/// Writes a file and forgets the filesystem so it is not unmounted.
+fn write_without_unmount(img: &mut Vec<u8>) {
+    let fs = fatfs::FileSystem::new(io::Cursor::new(img), FsOptions::new()).unwrap();
+    let mut file = fs.root_dir().create_file("new.txt").unwrap();
+    file.write_all(TEST_STR.as_bytes()).unwrap();
+    file.flush().unwrap();
+    drop(file);
+    mem::forget(fs);
+}
+
+fn write_and_unmount(img: &mut Vec<u8>) {
+    let fs = fatfs::FileSystem::new(io::Cursor::new(img), FsOptions::new()).unwrap();
+    let mut file = fs.root_dir().create_file("new2.txt").unwrap();
+    file.write_all(TEST_STR.as_bytes()).unwrap();
+    drop(file);
+    fs.unmount().unwrap();
+}
+
+/// Checks that exactly the flag bytes in the boot sector and in every FAT are written when the volume becomes
+/// dirty and that they are restored by unmount.
+fn check_flag_bytes(img_path: &str, flags_offset: usize, fat_flags: Option<(usize, usize, u8)>) {
+    let orig = fs::read(img_path).unwrap();
+    let (sources, dirty) = read_sources(&mut orig.clone());
+    assert!(!dirty);
+    assert!(!sources.boot_sector().dirty());
+    assert_eq!(sources.fat().map(|f| f.dirty()), fat_flags.map(|_| false));
+
+    let mut img = orig.clone();
+    write_without_unmount(&mut img);
+    let mut expected_offsets = vec![flags_offset];
+    assert_eq!(img[flags_offset], orig[flags_offset] | 1);
+    if let Some((entry_size, msb_offset, dirty_mask)) = fat_flags {
+        for offset in fat1_offsets(&orig, entry_size) {
+            assert_eq!(img[offset + msb_offset], orig[offset + msb_offset] & !dirty_mask);
+            expected_offsets.push(offset + msb_offset);
+        }
+    }
+    // only compare metadata in front of the root directory / data region touched by the file creation
+    let metadata_end = fat1_offsets(&orig, 0)[0];
+    let changed_metadata: Vec<_> = changed_bytes(&orig, &img)
+        .into_iter()
+        .filter(|&i| i < metadata_end || expected_offsets.contains(&i))
+        .collect();
+    assert_eq!(changed_metadata, expected_offsets);
+    let (sources, dirty) = read_sources(&mut img.clone());
+    assert!(dirty);
+    assert!(sources.boot_sector().dirty());
+    assert_eq!(sources.fat().map(|f| f.dirty()), fat_flags.map(|_| true));
+
+    let mut img = orig.clone();
+    write_and_unmount(&mut img);
+    for &offset in &expected_offsets {
+        assert_eq!(img[offset], orig[offset]);
+    }
+    assert!(!read_sources(&mut img).1);
+}
+
+#[test]
+fn test_flag_bytes_fat12() {
+    check_flag_bytes(FAT12_IMG, 0x25, None);
+}
+
+#[test]
+fn test_flag_bytes_fat16() {
+    // bit 15 of FAT[1]
+    check_flag_bytes(FAT16_IMG, 0x25, Some((2, 1, 0x80)));
+}
+
+#[test]
+fn test_flag_bytes_fat32() {
+    // bit 27 of FAT[1]
+    check_flag_bytes(FAT32_IMG, 0x41, Some((4, 3, 0x08)));
+}
+
+#[test]
+fn test_dirty_flag_in_fat_only() {
+    // Windows marks volumes dirty in the FAT only
+    let mut img = fs::read(FAT16_IMG).unwrap();
+    for offset in fat1_offsets(&img.clone(), 2) {
+        img[offset + 1] &= !0x80;
+    }
+    let (sources, dirty) = read_sources(&mut img);
+    assert!(dirty);
+    assert!(!sources.boot_sector().dirty());
+    assert!(sources.fat().unwrap().dirty());
+
+    // the flag is preserved and written to the boot sector too
+    write_and_unmount(&mut img);
+    let (sources, dirty) = read_sources(&mut img);
+    assert!(dirty);
+    assert!(sources.boot_sector().dirty());
+    assert!(sources.fat().unwrap().dirty());
+}
+
+#[test]
+fn test_dirty_flag_in_boot_sector_only() {
+    let mut img = fs::read(FAT16_IMG).unwrap();
+    img[0x25] |= 1;
+    let (sources, dirty) = read_sources(&mut img);
+    assert!(dirty);
+    assert!(sources.boot_sector().dirty());
+    assert!(!sources.fat().unwrap().dirty());
+
+    write_and_unmount(&mut img);
+    let (sources, _) = read_sources(&mut img);
+    assert!(sources.boot_sector().dirty());
+    assert!(sources.fat().unwrap().dirty());
+}
+
+#[test]
+fn test_read_only_session_does_not_sync_flags() {
+    let mut img = fs::read(FAT16_IMG).unwrap();
+    img[0x25] |= 1;
+    let orig = img.clone();
+    {
+        let fs = fatfs::FileSystem::new(io::Cursor::new(&mut img), FsOptions::new()).unwrap();
+        for r in fs.root_dir().iter() {
+            r.unwrap();
+        }
+        fs.unmount().unwrap();
+    }
+    assert!(img == orig, "image has been modified");
+}
+
+#[test]
+fn test_io_error_flag_in_fat() {
+    let mut img = fs::read(FAT16_IMG).unwrap();
+    for offset in fat1_offsets(&img.clone(), 2) {
+        img[offset + 1] &= !0x40;
+    }
+    let fs = fatfs::FileSystem::new(io::Cursor::new(&mut img), FsOptions::new()).unwrap();
+    assert!(fs.read_status_flags().unwrap().io_error());
+    let sources = fs.read_status_flag_sources().unwrap();
+    assert!(!sources.boot_sector().io_error());
+    assert!(sources.fat().unwrap().io_error());
+    mem::forget(fs);
+}
+
+fn test_flush_keeps_dirty_flag(img_path: &str) {
+    let mut img = fs::read(img_path).unwrap();
+    assert!(!read_sources(&mut img).1);
+    {
+        let fs = fatfs::FileSystem::new(io::Cursor::new(&mut img), FsOptions::new()).unwrap();
+        fs.root_dir().create_dir("new-dir").unwrap();
+        fs.flush().unwrap();
+        assert!(fs.read_status_flags().unwrap().dirty());
+        // power loss
+        mem::forget(fs);
+    }
+    assert!(read_sources(&mut img).1);
+    write_and_unmount(&mut img);
+    // flags read on mount are never cleared
+    assert!(read_sources(&mut img).1);
+}
+
+#[test]
+fn test_flush_keeps_dirty_flag_fat12() {
+    test_flush_keeps_dirty_flag(FAT12_IMG)
+}
+
+#[test]
+fn test_flush_keeps_dirty_flag_fat16() {
+    test_flush_keeps_dirty_flag(FAT16_IMG)
+}
+
+#[test]
+fn test_flush_keeps_dirty_flag_fat32() {
+    test_flush_keeps_dirty_flag(FAT32_IMG)
+}
@@ -0,0 +1,134 @@
+use std::fs;
+use std::io;
+
+use fatfs::{FsOptions, Read, Seek, SeekFrom, StdIoWrapper, StreamSlice, Write};
+
+mod common;
+use common::{init_logger, FAT12_IMG, FAT16_IMG, FAT32_IMG};
+
+const PARTITION_START: usize = 1024 * 1024;
+const TRAILER_LEN: usize = 4096;
+
+type Slice<'a> = StreamSlice<StdIoWrapper<io::Cursor<&'a mut Vec<u8>>>>;
+
+fn slice(disk: &mut Vec<u8>, start: usize, end: usize) -> Slice<'_> {
+    StreamSlice::new(StdIoWrapper::new(io::Cursor::new(disk)), start as u64, end as u64).unwrap()
+}
+
+fn test_mount_partition(img_path: &str) {
+    init_logger();
+    let img = fs::read(img_path).unwrap();
+    let mut disk = vec![0xAA; PARTITION_START];
+    disk.extend_from_slice(&img);
+    disk.extend_from_slice(&[0x55; TRAILER_LEN]);
+    let end = PARTITION_START + img.len();
+
+    {
+        let fs = fatfs::FileSystem::new(slice(&mut disk, PARTITION_START, end), FsOptions::new()).unwrap();
+        let root_dir = fs.root_dir();
+        let mut content = Vec::new();
+        std::io::Read::read_to_end(&mut root_dir.open_file("short.txt").unwrap(), &mut content).unwrap();
+        assert_eq!(content, b"Rust is cool!\n");
+        root_dir.create_dir("new").unwrap();
+        let mut file = root_dir.create_file("new/file.bin").unwrap();
+        file.write_all(&[0x33; 3000]).unwrap();
+        drop(file);
+        drop(root_dir);
+        fs.unmount().unwrap();
+    }
+
+    // bytes outside of the partition are not touched
+    assert!(disk[..PARTITION_START].iter().all(|b| *b == 0xAA));
+    assert!(disk[end..].iter().all(|b| *b == 0x55));
+    // the same volume is found at the beginning of the unwrapped image
+    let fs = common::mount(disk[PARTITION_START..end].to_vec());
+    let mut content = Vec::new();
+    std::io::Read::read_to_end(&mut fs.root_dir().open_file("new/file.bin").unwrap(), &mut content).unwrap();
+    assert_eq!(content, vec![0x33; 3000]);
+    assert!(fs.check().unwrap().is_clean());
+}
+
+#[test]
+fn test_mount_partition_fat12() {
+    test_mount_partition(FAT12_IMG)
+}
+
+#[test]
+fn test_mount_partition_fat16() {
+    test_mount_partition(FAT16_IMG)
+}
+
+#[test]
+fn test_mount_partition_fat32() {
+    test_mount_partition(FAT32_IMG)
+}
+
+fn test_disk() -> Vec<u8> {
+    (0..100).collect()
+}
+
+#[test]
+fn test_read_straddling_end() {
+    let mut disk = test_disk();
+    let mut slice = slice(&mut disk, 10, 50);
+    assert_eq!(slice.seek(SeekFrom::Start(35)).unwrap(), 35);
+    let mut buf = [0; 10];
+    // the read is truncated at the end of the slice
+    assert_eq!(slice.read(&mut buf).unwrap(), 5);
+    assert_eq!(&buf[..5], &[45, 46, 47, 48, 49]);
+    assert_eq!(slice.read(&mut buf).unwrap(), 0);
+    slice.seek(SeekFrom::Start(38)).unwrap();
+    let err = slice.read_exact(&mut buf).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    // positions are relative to the slice start
+    slice.seek(SeekFrom::Start(0)).unwrap();
+    slice.read_exact(&mut buf).unwrap();
+    assert_eq!(buf, [10, 11, 12, 13, 14, 15, 16, 17, 18, 19]);
+}
+
+#[test]
+fn test_write_straddling_end() {
+    let mut disk = test_disk();
+    {
+        let mut slice = slice(&mut disk, 10, 50);
+        slice.seek(SeekFrom::End(-5)).unwrap();
+        // the write is truncated at the end of the slice
+        assert_eq!(slice.write(&[0xFF; 10]).unwrap(), 5);
+        assert_eq!(slice.write(&[0xFF; 10]).unwrap(), 0);
+        slice.seek(SeekFrom::Current(-2)).unwrap();
+        let err = slice.write_all(&[0xEE; 10]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::WriteZero);
+        slice.seek(SeekFrom::Start(0)).unwrap();
+        slice.write_all(&[0xDD; 2]).unwrap();
+    }
+    let mut expected = test_disk();
+    expected[10..12].copy_from_slice(&[0xDD; 2]);
+    expected[45..48].copy_from_slice(&[0xFF; 3]);
+    expected[48..50].copy_from_slice(&[0xEE; 2]);
+    assert_eq!(disk, expected);
+}
+
+#[test]
+fn test_seek_out_of_bounds() {
+    let mut disk = test_disk();
+    let mut slice = slice(&mut disk, 10, 50);
+    assert_eq!(slice.seek(SeekFrom::End(0)).unwrap(), 40);
+    for pos in &[
+        SeekFrom::Start(41),
+        SeekFrom::End(1),
+        SeekFrom::Current(1),
+        SeekFrom::Current(-41),
+    ] {
+        let err = slice.seek(*pos).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+    // the position is not changed by a failed seek
+    assert_eq!(slice.seek(SeekFrom::Current(0)).unwrap(), 40);
+    let inner = slice.into_inner().into_inner();
+    assert_eq!(inner.position(), 50);
+
+    let err = StreamSlice::new(StdIoWrapper::new(io::Cursor::new(&mut disk)), 20, 10)
+        .err()
+        .unwrap();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+}
@@ -0,0 +1,225 @@
+use std::fs;
+use std::io;
+use std::io::prelude::*;
+
+use fatfs::{Date, DateTime, DefaultTimeProvider, FsOptions, LossyOemCpConverter, StdIoWrapper, Time, TimeProvider};
+
+mod common;
+use common::{init_logger, FAT16_IMG};
+
+const TEST_STR: &str = "Hi there Rust programmer!\n";
+
+type DirEntry<'a, 'b> =
+    fatfs::DirEntry<'a, StdIoWrapper<io::Cursor<&'b mut Vec<u8>>>, DefaultTimeProvider, LossyOemCpConverter>;
+
+#[derive(Debug)]
+struct FixedTimeProvider;
+
+impl TimeProvider for FixedTimeProvider {
+    fn get_current_date(&self) -> Date {
+        Date::new(2024, 2, 29)
+    }
+
+    fn get_current_date_time(&self) -> DateTime {
+        DateTime::new(Date::new(2024, 2, 29), Time::new(23, 58, 57, 450))
+    }
+}
+
+/// Returns the offset of the short name entry.
+fn find_entry(img: &[u8], short_name: &[u8; 11]) -> usize {
+    (0..img.len())
+        .step_by(32)
+        .find(|&i| &img[i..i + 11] == short_name)
+        .unwrap()
+}
+
+/// Returns the image with zeroed creation date/time and access date of `SHORT.TXT`.
+fn img_without_timestamps() -> Vec<u8> {
+    let mut img = fs::read(FAT16_IMG).unwrap();
+    let offset = find_entry(&img, b"SHORT   TXT");
+    // create_time_0, create_time_1, create_date, access_date
+    for b in &mut img[offset + 13..offset + 20] {
+        *b = 0;
+    }
+    img
+}
+
+fn with_short_txt<F: FnOnce(DirEntry)>(
+    img: &mut Vec<u8>,
+    options: FsOptions<DefaultTimeProvider, LossyOemCpConverter>,
+    f: F,
+) {
+    init_logger();
+    let fs = fatfs::FileSystem::new(io::Cursor::new(img), options).unwrap();
+    let e = fs
+        .root_dir()
+        .iter()
+        .map(|r| r.unwrap())
+        .find(|e| e.file_name() == "short.txt")
+        .unwrap();
+    f(e);
+}
+
+#[test]
+fn test_set_timestamps() {
+    let mut img = fs::read(FAT16_IMG).unwrap();
+    with_short_txt(&mut img, FsOptions::new(), |e| {
+        let date = Date::new(2017, 9, 24);
+        assert_eq!(e.creation_time(), Some(DateTime::new(date, Time::new(19, 59, 4, 0))));
+        assert_eq!(e.access_date(), Some(date));
+        assert_eq!(
+            e.modification_time(),
+            Some(DateTime::new(date, Time::new(19, 59, 4, 0)))
+        );
+    });
+}
+
+#[test]
+fn test_zeroed_timestamps_are_not_set() {
+    let mut img = img_without_timestamps();
+    with_short_txt(&mut img, FsOptions::new(), |e| {
+        assert_eq!(e.creation_time(), None);
+        assert_eq!(e.access_date(), None);
+        assert!(e.modification_time().is_some());
+    });
+}
+
+#[test]
+fn test_zeroed_timestamps_preserved_on_write() {
+    let mut img = img_without_timestamps();
+    let offset = find_entry(&img, b"SHORT   TXT");
+    with_short_txt(&mut img, FsOptions::new(), |e| {
+        let mut file = e.to_file();
+        file.seek(io::SeekFrom::End(0)).unwrap();
+        file.write_all(TEST_STR.as_bytes()).unwrap();
+        file.seek(io::SeekFrom::Start(0)).unwrap();
+        let mut buf = Vec::new();
+        file.read_to_end(&mut buf).unwrap();
+        file.flush().unwrap();
+    });
+    assert_eq!(&img[offset + 13..offset + 20], &[0; 7]);
+    with_short_txt(&mut img, FsOptions::new(), |e| {
+        assert_eq!(e.len(), 14 + TEST_STR.len() as u64);
+        assert_eq!(e.creation_time(), None);
+        assert_eq!(e.access_date(), None);
+        assert!(e.modification_time().is_some());
+    });
+}
+
+#[test]
+fn test_zeroed_access_date_updated_if_enabled() {
+    let mut img = img_without_timestamps();
+    with_short_txt(&mut img, FsOptions::new().update_accessed_date(true), |e| {
+        let mut buf = Vec::new();
+        e.to_file().read_to_end(&mut buf).unwrap();
+    });
+    with_short_txt(&mut img, FsOptions::new(), |e| {
+        assert!(e.access_date().is_some());
+        assert_eq!(e.creation_time(), None);
+    });
+}
+
+#[test]
+fn test_timestamps_of_created_entries() {
+    init_logger();
+    let img = fs::read(FAT16_IMG).unwrap();
+    let options = FsOptions::new().time_provider(FixedTimeProvider);
+    let fs = fatfs::FileSystem::new(io::Cursor::new(img), options).unwrap();
+    let root_dir = fs.root_dir();
+    root_dir.create_file("new.txt").unwrap();
+    root_dir.create_dir("new dir").unwrap();
+    let now = FixedTimeProvider.get_current_date_time();
+    for name in &["new.txt", "new dir"] {
+        let e = root_dir.entry(name).unwrap();
+        // creation time keeps 1/100s, modification time only 2s
+        assert_eq!(e.creation_time(), Some(now));
+        assert_eq!(e.access_date(), Some(now.date));
+        assert_eq!(
+            e.modification_time(),
+            Some(DateTime::new(now.date, Time::new(23, 58, 56, 0)))
+        );
+    }
+}
+
+#[test]
+fn test_invalid_timestamps_are_clamped() {
+    let mut img = fs::read(FAT16_IMG).unwrap();
+    let offset = find_entry(&img, b"SHORT   TXT");
+    // creation: 2000-00-00 31:63:62 + 2.55s, access: 2001-15-31, modification: 2001-02-31 24:00:00
+    img[offset + 13] = 0xFF;
+    img[offset + 14..offset + 16].copy_from_slice(&0xFFFF_u16.to_le_bytes());
+    img[offset + 16..offset + 18].copy_from_slice(&(20_u16 << 9).to_le_bytes());
+    img[offset + 18..offset + 20].copy_from_slice(&((21_u16 << 9) | (15 << 5) | 31).to_le_bytes());
+    img[offset + 22..offset + 24].copy_from_slice(&(24_u16 << 11).to_le_bytes());
+    img[offset + 24..offset + 26].copy_from_slice(&((21_u16 << 9) | (2 << 5) | 31).to_le_bytes());
+    with_short_txt(&mut img, FsOptions::new(), |e| {
+        assert_eq!(
+            e.creation_time(),
+            Some(DateTime::new(Date::new(2000, 1, 1), Time::new(23, 59, 59, 550)))
+        );
+        assert_eq!(e.access_date(), Some(Date::new(2001, 12, 31)));
+        assert_eq!(
+            e.modification_time(),
+            Some(DateTime::new(Date::new(2001, 2, 28), Time::new(23, 0, 0, 0)))
+        );
+    });
+}
+
+#[test]
+fn test_set_entry_timestamps() {
+    init_logger();
+    let mut img = fs::read(FAT16_IMG).unwrap();
+    let orig_img = img.clone();
+    let created = DateTime::new(Date::new(1999, 12, 31), Time::new(23, 59, 58, 990));
+    let accessed = Date::new(2107, 12, 31);
+    let modified = DateTime::new(Date::new(1980, 1, 1), Time::new(1, 2, 3, 400));
+    let entry_pos = {
+        let fs = fatfs::FileSystem::new(io::Cursor::new(&mut img), FsOptions::new()).unwrap();
+        let mut e = fs.root_dir().entry("long.txt").unwrap();
+        assert!(e.has_lfn());
+        e.set_created(created).unwrap();
+        e.set_accessed(accessed).unwrap();
+        e.set_modified(modified).unwrap();
+        assert_eq!(e.creation_time(), Some(created));
+        e.entry_pos()
+    };
+    // only the short name entry is changed
+    let changed: Vec<_> = (0..img.len()).filter(|&i| img[i] != orig_img[i]).collect();
+    assert!(!changed.is_empty());
+    assert!(changed
+        .iter()
+        .all(|&i| (entry_pos..entry_pos + 32).contains(&(i as u64))));
+    let fs = fatfs::FileSystem::new(io::Cursor::new(&mut img), FsOptions::new()).unwrap();
+    let e = fs.root_dir().entry("long.txt").unwrap();
+    assert_eq!(e.creation_time(), Some(created));
+    assert_eq!(e.access_date(), Some(accessed));
+    // modification time has 2s resolution
+    assert_eq!(
+        e.modification_time(),
+        Some(DateTime::new(modified.date, Time::new(1, 2, 2, 0)))
+    );
+    assert!(fs.check().unwrap().is_clean());
+}
+
+#[test]
+fn test_set_invalid_timestamps() {
+    let mut img = fs::read(FAT16_IMG).unwrap();
+    with_short_txt(&mut img, FsOptions::new(), |mut e| {
+        let valid = DateTime::new(Date::new(2000, 1, 1), Time::new(0, 0, 0, 0));
+        let mut before_1980 = valid;
+        before_1980.date.year = 1979;
+        let mut after_2107 = valid;
+        after_2107.date.year = 2108;
+        let mut invalid_hour = valid;
+        invalid_hour.time.hour = 24;
+        for &date_time in &[before_1980, after_2107, invalid_hour] {
+            assert!(matches!(e.set_created(date_time), Err(fatfs::Error::InvalidInput)));
+            assert!(matches!(e.set_modified(date_time), Err(fatfs::Error::InvalidInput)));
+        }
+        assert!(matches!(
+            e.set_accessed(before_1980.date),
+            Err(fatfs::Error::InvalidInput)
+        ));
+        assert_eq!(e.modification_time().unwrap().date, Date::new(2017, 9, 24));
+    });
+}
@@ -0,0 +1,164 @@
+use std::fs;
+use std::io;
+use std::io::prelude::*;
+
+use fatfs::{DeletedEntryInfo, FsOptions, RawDirEntry};
+
+mod common;
+use common::{open_fs, Dir, FAT12_IMG, FAT16_IMG, FAT32_IMG};
+
+fn deleted_entries(dir: &Dir) -> Vec<DeletedEntryInfo> {
+    dir.iter_raw()
+        .filter_map(|r| match r.unwrap() {
+            RawDirEntry::Deleted(info) => Some(info),
+            _ => None,
+        })
+        .collect()
+}
+
+fn read_to_vec(dir: &Dir, path: &str) -> Vec<u8> {
+    let mut content = Vec::new();
+    dir.open_file(path).unwrap().read_to_end(&mut content).unwrap();
+    content
+}
+
+/// Content spanning 6 clusters of 512 bytes.
+fn test_content() -> Vec<u8> {
+    (0..3000_u32).map(|i| (i * 7 % 251) as u8).collect()
+}
+
+fn test_undelete(img_path: &str) {
+    let fs = open_fs(img_path);
+    let dir = fs.root_dir().create_dir("dir").unwrap();
+    let content = test_content();
+    dir.create_file("Deleted File.bin")
+        .unwrap()
+        .write_all(&content)
+        .unwrap();
+    let free_clusters = fs.stats().unwrap().free_clusters();
+    dir.remove("Deleted File.bin").unwrap();
+    assert_eq!(fs.stats().unwrap().free_clusters(), free_clusters + 6);
+
+    let deleted = deleted_entries(&dir);
+    assert_eq!(deleted.len(), 1);
+    let first_char = deleted[0].recovered_first_byte().unwrap();
+    let e = dir.undelete(&deleted[0], first_char).unwrap();
+    assert_eq!(e.file_name(), "Deleted File.bin");
+    assert_eq!(e.len(), content.len() as u64);
+    assert_eq!(read_to_vec(&dir, "Deleted File.bin"), content);
+    assert_eq!(fs.stats().unwrap().free_clusters(), free_clusters);
+    assert!(deleted_entries(&dir).is_empty());
+    assert!(fs.check().unwrap().is_clean());
+}
+
+#[test]
+fn test_undelete_fat12() {
+    test_undelete(FAT12_IMG)
+}
+
+#[test]
+fn test_undelete_fat16() {
+    test_undelete(FAT16_IMG)
+}
+
+#[test]
+fn test_undelete_fat32() {
+    test_undelete(FAT32_IMG)
+}
+
+#[test]
+fn test_undelete_reallocated_cluster() {
+    let mut stream = io::Cursor::new(fs::read(FAT16_IMG).unwrap());
+    {
+        let fs = fatfs::FileSystem::new(&mut stream, FsOptions::new()).unwrap();
+        let dir = fs.root_dir().create_dir("dir").unwrap();
+        dir.create_file("Deleted File.bin")
+            .unwrap()
+            .write_all(&test_content())
+            .unwrap();
+        dir.remove("Deleted File.bin").unwrap();
+    }
+    stream.set_position(0);
+    // the volume has no next free cluster hint so the new file gets the first freed cluster
+    let fs = fatfs::FileSystem::new(&mut stream, FsOptions::new()).unwrap();
+    let dir = fs.root_dir().open_dir("dir").unwrap();
+    dir.create_file("other.bin").unwrap().write_all(b"other").unwrap();
+    let deleted: Vec<_> = dir
+        .iter_raw()
+        .filter_map(|r| match r.unwrap() {
+            RawDirEntry::Deleted(info) => Some(info),
+            _ => None,
+        })
+        .collect();
+    let free_clusters = fs.stats().unwrap().free_clusters();
+    assert!(matches!(
+        dir.undelete(&deleted[0], b'D'),
+        Err(fatfs::Error::ClusterInUse { .. })
+    ));
+    // nothing has been modified
+    assert_eq!(fs.stats().unwrap().free_clusters(), free_clusters);
+    assert_eq!(dir.iter_raw().count(), deleted.len() + 4);
+    assert_eq!(dir.iter().count(), 3);
+    assert!(fs.check().unwrap().is_clean());
+}
+
+#[test]
+fn test_undelete_with_other_first_char() {
+    let fs = open_fs(FAT16_IMG);
+    let root_dir = fs.root_dir();
+    root_dir.remove("long.txt").unwrap();
+    let deleted = deleted_entries(&root_dir);
+    assert_eq!(deleted[0].recovered_first_byte(), Some(b'L'));
+    // the long name is not restored if the checksum does not match
+    let e = root_dir.undelete(&deleted[0], b'X').unwrap();
+    assert_eq!(e.file_name(), "XONG.TXT");
+    assert_eq!(e.slot_count(), 1);
+    assert_eq!(
+        read_to_vec(&root_dir, "XONG.TXT"),
+        read_to_vec(&open_fs(FAT16_IMG).root_dir(), "long.txt")
+    );
+    assert!(fs.check().unwrap().is_clean());
+}
+
+#[test]
+fn test_undelete_dir() {
+    let fs = open_fs(FAT32_IMG);
+    let root_dir = fs.root_dir();
+    root_dir.create_dir("Empty Directory").unwrap();
+    root_dir.remove("Empty Directory").unwrap();
+    let deleted = deleted_entries(&root_dir);
+    assert!(deleted[0].is_dir());
+    let e = root_dir.undelete(&deleted[0], b'E').unwrap();
+    assert!(e.is_dir());
+    let names: Vec<_> = e.to_dir().iter().map(|r| r.unwrap().file_name()).collect();
+    assert_eq!(names, [".", ".."]);
+    assert!(fs.check().unwrap().is_clean());
+}
+
+#[test]
+fn test_undelete_errors() {
+    let fs = open_fs(FAT16_IMG);
+    let root_dir = fs.root_dir();
+    root_dir.remove("short.txt").unwrap();
+    let deleted = deleted_entries(&root_dir);
+    for &c in &[0, b' ', 0xE5] {
+        assert!(matches!(
+            root_dir.undelete(&deleted[0], c),
+            Err(fatfs::Error::InvalidInput)
+        ));
+    }
+    // the deleted entry belongs to another directory
+    let other_dir = root_dir.open_dir("very").unwrap();
+    assert!(matches!(
+        other_dir.undelete(&deleted[0], b'S'),
+        Err(fatfs::Error::InvalidInput)
+    ));
+    // a file with the same name has been created in the meantime
+    root_dir.create_file("short.txt").unwrap();
+    let deleted = deleted_entries(&root_dir);
+    assert!(matches!(
+        root_dir.undelete(&deleted[0], b'S'),
+        Err(fatfs::Error::AlreadyExists)
+    ));
+    assert!(fs.check().unwrap().is_clean());
+}
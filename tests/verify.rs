@@ -0,0 +1,102 @@
+use std::io;
+use std::ops::Range;
+use std::rc::Rc;
+
+use fatfs::{FsOptions, VerifyPolicy};
+
+mod common;
+use common::{open_instrumented_fs, InstrumentedFs, Layout, StreamState, WriteFault, FAT16_IMG};
+
+const TEST_STR: &str = "Hi there Rust programmer!\n";
+
+/// Mounts a storage that silently drops the first write touching `drop_range` (like a counterfeit SD card).
+fn open_lying_fs(
+    policy: VerifyPolicy,
+    drop_range: impl Fn(&Layout) -> Range<u64>,
+) -> (InstrumentedFs, Rc<StreamState>) {
+    let (fs, state) = open_instrumented_fs(FAT16_IMG, FsOptions::new().verify_writes(policy));
+    let layout = Layout::read(&state.data());
+    state.set_write_fault(WriteFault::DropOnce(drop_range(&layout)));
+    (fs, state)
+}
+
+fn write_new_file(fs: &InstrumentedFs) -> Result<(), fatfs::Error<io::Error>> {
+    let root_dir = fs.root_dir();
+    let mut file = root_dir.create_file("new.txt")?;
+    fatfs::Write::write_all(&mut file, TEST_STR.as_bytes())?;
+    fatfs::Write::flush(&mut file)
+}
+
+fn assert_verification_failed(result: Result<(), fatfs::Error<io::Error>>, state: &StreamState) {
+    let dropped = state.dropped_writes().pop().expect("no write has been dropped");
+    match result {
+        Err(fatfs::Error::WriteVerificationFailed { offset }) => {
+            assert!(dropped.contains(&offset), "offset {} not in {:?}", offset, dropped);
+        }
+        r => panic!("unexpected result {:?}", r),
+    }
+}
+
+#[test]
+fn test_verify_none_ignores_dropped_fat_write() {
+    let (fs, state) = open_lying_fs(VerifyPolicy::None, |l| l.fat_start..l.fat_start + l.fat_size);
+    write_new_file(&fs).unwrap();
+    assert_eq!(state.dropped_writes().len(), 1);
+    assert!(!fs.read_status_flags().unwrap().io_error());
+}
+
+#[test]
+fn test_verify_metadata_detects_dropped_fat_write() {
+    // skip reserved FAT entries - the second one contains status flags written when the volume becomes dirty
+    let (fs, state) = open_lying_fs(VerifyPolicy::MetadataOnly, |l| {
+        l.fat_start + 4..l.fat_start + l.fat_size
+    });
+    assert!(!fs.read_status_flags().unwrap().io_error());
+    assert_verification_failed(write_new_file(&fs), &state);
+    assert!(fs.read_status_flags().unwrap().io_error());
+}
+
+#[test]
+fn test_verify_metadata_ignores_dropped_data_write() {
+    let (fs, state) = open_lying_fs(VerifyPolicy::MetadataOnly, |l| l.data_start..u64::MAX);
+    write_new_file(&fs).unwrap();
+    assert_eq!(state.dropped_writes().len(), 1);
+    assert!(!fs.read_status_flags().unwrap().io_error());
+}
+
+#[test]
+fn test_verify_all_detects_dropped_data_write() {
+    let (fs, state) = open_lying_fs(VerifyPolicy::All, |l| l.data_start..u64::MAX);
+    assert_verification_failed(write_new_file(&fs), &state);
+    assert!(fs.read_status_flags().unwrap().io_error());
+}
+
+#[test]
+fn test_verify_metadata_detects_dropped_root_dir_write() {
+    let (fs, state) = open_lying_fs(VerifyPolicy::MetadataOnly, |l| l.root_dir_start..l.data_start);
+    assert_verification_failed(write_new_file(&fs), &state);
+    assert!(fs.read_status_flags().unwrap().io_error());
+}
+
+#[test]
+fn test_verify_metadata_detects_dropped_boot_sector_status_write() {
+    // `BS_Reserved1` field of a FAT16 Boot Sector containing status flags
+    let (fs, state) = open_lying_fs(VerifyPolicy::MetadataOnly, |_| 0x25..0x26);
+    assert_verification_failed(write_new_file(&fs), &state);
+    assert!(fs.read_status_flags().unwrap().io_error());
+}
+
+#[test]
+fn test_verify_metadata_detects_dropped_fat_status_write() {
+    // the second FAT16 entry containing status flags
+    let (fs, state) = open_lying_fs(VerifyPolicy::MetadataOnly, |l| l.fat_start + 2..l.fat_start + 4);
+    assert_verification_failed(write_new_file(&fs), &state);
+    assert!(fs.read_status_flags().unwrap().io_error());
+}
+
+#[test]
+fn test_verify_none_ignores_dropped_boot_sector_status_write() {
+    let (fs, state) = open_lying_fs(VerifyPolicy::None, |_| 0x25..0x26);
+    write_new_file(&fs).unwrap();
+    assert_eq!(state.dropped_writes().len(), 1);
+}
@@ -0,0 +1,195 @@
+use std::fs;
+use std::io;
+
+use fatfs::{DefaultTimeProvider, FatType, FsOptions, LossyOemCpConverter, StdIoWrapper};
+
+mod common;
+use common::{init_logger, FAT12_IMG, FAT16_IMG, FAT32_IMG};
+
+const LABEL_SIZE: usize = 11;
+
+/// Returns the image and positions of the label in the BPB block and of the volume entry in the root directory.
+fn load_img(img_path: &str) -> (Vec<u8>, usize, usize) {
+    init_logger();
+    let img = fs::read(img_path).unwrap();
+    let (bpb_label_pos, entry_pos) = {
+        let fs = fatfs::FileSystem::new(io::Cursor::new(img.clone()), FsOptions::new()).unwrap();
+        let bpb_label_pos = if fs.fat_type() == FatType::Fat32 { 71 } else { 43 };
+        let entry = fs
+            .root_dir()
+            .iter_with_volume_labels()
+            .map(|r| r.unwrap())
+            .find(|e| e.is_volume())
+            .unwrap();
+        (bpb_label_pos, entry.entry_pos() as usize)
+    };
+    assert_eq!(&img[bpb_label_pos..bpb_label_pos + LABEL_SIZE], b"Test!      ");
+    assert_eq!(&img[entry_pos..entry_pos + LABEL_SIZE], b"Test!      ");
+    (img, bpb_label_pos, entry_pos)
+}
+
+fn volume_label(img: Vec<u8>) -> fatfs::VolumeLabel {
+    common::mount(img).read_volume_label().unwrap()
+}
+
+fn test_volume_label(img_path: &str) {
+    // label stored in both places by mkfs.vfat
+    let (img, bpb_label_pos, entry_pos) = load_img(img_path);
+    let label = volume_label(img.clone());
+    assert_eq!(label.to_string(), "Test!");
+    assert_eq!(label.as_bytes(), b"Test!");
+
+    // Windows changes only the root directory entry
+    let mut windows_img = img.clone();
+    windows_img[bpb_label_pos..bpb_label_pos + LABEL_SIZE].copy_from_slice(b"NO NAME    ");
+    windows_img[entry_pos..entry_pos + LABEL_SIZE].copy_from_slice(b"WIN LABEL  ");
+    assert_eq!(volume_label(windows_img.clone()).to_string(), "WIN LABEL");
+    // the infallible variant returns the BPB label only
+    assert_eq!(common::mount(windows_img).volume_label(), "NO NAME");
+
+    // without the root directory entry the BPB label is used
+    let mut bpb_only_img = img;
+    bpb_only_img[bpb_label_pos..bpb_label_pos + LABEL_SIZE].copy_from_slice(b"BPB LABEL  ");
+    bpb_only_img[entry_pos] = 0xE5;
+    let label = volume_label(bpb_only_img);
+    assert_eq!(label.to_string(), "BPB LABEL");
+    assert!(!label.is_empty());
+}
+
+#[test]
+fn test_volume_label_fat12() {
+    test_volume_label(FAT12_IMG)
+}
+
+#[test]
+fn test_volume_label_fat16() {
+    test_volume_label(FAT16_IMG)
+}
+
+#[test]
+fn test_volume_label_fat32() {
+    test_volume_label(FAT32_IMG)
+}
+
+#[test]
+fn test_volume_label_decoding() {
+    let (mut img, _, entry_pos) = load_img(FAT16_IMG);
+    img[entry_pos..entry_pos + LABEL_SIZE].copy_from_slice(b"A\xC4B        ");
+    let label = volume_label(img);
+    // non-ASCII characters are decoded by the OEM codepage converter
+    assert_eq!(label.to_string(), "A\u{FFFD}B");
+    assert_eq!(label.as_bytes(), b"A\xC4B");
+}
+
+#[test]
+fn test_empty_volume_label() {
+    let (mut img, _, entry_pos) = load_img(FAT16_IMG);
+    img[entry_pos..entry_pos + LABEL_SIZE].copy_from_slice(&[b' '; LABEL_SIZE]);
+    let label = volume_label(img);
+    assert!(label.is_empty());
+    assert_eq!(label.to_string(), "");
+}
+
+type FileSystem<'a> =
+    fatfs::FileSystem<StdIoWrapper<io::Cursor<&'a mut Vec<u8>>>, DefaultTimeProvider, LossyOemCpConverter>;
+
+fn mount(img: &mut Vec<u8>) -> FileSystem<'_> {
+    fatfs::FileSystem::new(io::Cursor::new(img), FsOptions::new()).unwrap()
+}
+
+fn root_dir_names(img: &mut Vec<u8>) -> Vec<String> {
+    let fs = mount(img);
+    let names = fs.root_dir().iter().map(|r| r.unwrap().file_name()).collect();
+    names
+}
+
+fn test_set_volume_label(img_path: &str) {
+    let (mut img, bpb_label_pos, entry_pos) = load_img(img_path);
+    let names = root_dir_names(&mut img);
+    {
+        let fs = mount(&mut img);
+        fs.set_volume_label("New_Label1").unwrap();
+        assert_eq!(fs.read_volume_label().unwrap().to_string(), "NEW_LABEL1");
+        assert!(fs.check().unwrap().is_clean());
+        fs.unmount().unwrap();
+    }
+    // the existing entry is updated
+    assert_eq!(&img[bpb_label_pos..bpb_label_pos + LABEL_SIZE], b"NEW_LABEL1 ");
+    assert_eq!(&img[entry_pos..entry_pos + LABEL_SIZE], b"NEW_LABEL1 ");
+    if bpb_label_pos == 71 {
+        // backup boot sector
+        assert_eq!(&img[6 * 512 + 71..6 * 512 + 71 + LABEL_SIZE], b"NEW_LABEL1 ");
+    }
+    assert_eq!(mount(&mut img).read_volume_label().unwrap().to_string(), "NEW_LABEL1");
+    assert_eq!(root_dir_names(&mut img), names);
+}
+
+#[test]
+fn test_set_volume_label_fat12() {
+    test_set_volume_label(FAT12_IMG)
+}
+
+#[test]
+fn test_set_volume_label_fat16() {
+    test_set_volume_label(FAT16_IMG)
+}
+
+#[test]
+fn test_set_volume_label_fat32() {
+    test_set_volume_label(FAT32_IMG)
+}
+
+#[test]
+fn test_set_volume_label_creates_entry() {
+    let (mut img, _, entry_pos) = load_img(FAT16_IMG);
+    img[entry_pos] = 0xE5;
+    let names = root_dir_names(&mut img);
+    {
+        let fs = mount(&mut img);
+        fs.set_volume_label("DATA 2").unwrap();
+        fs.root_dir().create_file("after.txt").unwrap();
+        assert!(fs.check().unwrap().is_clean());
+    }
+    // the deleted entry is reused
+    assert_eq!(&img[entry_pos..entry_pos + LABEL_SIZE], b"DATA 2     ");
+    assert_eq!(img[entry_pos + 11], 0x08);
+    assert_eq!(mount(&mut img).read_volume_label().unwrap().to_string(), "DATA 2");
+    let mut expected_names = names;
+    expected_names.push("after.txt".to_string());
+    assert_eq!(root_dir_names(&mut img), expected_names);
+}
+
+#[test]
+fn test_set_empty_volume_label() {
+    let (mut img, bpb_label_pos, _) = load_img(FAT16_IMG);
+    {
+        let fs = mount(&mut img);
+        fs.set_volume_label("").unwrap();
+        assert_eq!(fs.read_volume_label().unwrap().to_string(), "NO NAME");
+        assert_eq!(fs.read_volume_label_from_root_dir().unwrap(), None);
+    }
+    assert_eq!(&img[bpb_label_pos..bpb_label_pos + LABEL_SIZE], b"NO NAME    ");
+    let fs = mount(&mut img);
+    assert_eq!(fs.read_volume_label_from_root_dir().unwrap(), None);
+    // the label can be set again
+    fs.set_volume_label("again").unwrap();
+    assert_eq!(fs.read_volume_label_from_root_dir().unwrap(), Some("AGAIN".to_string()));
+}
+
+#[test]
+fn test_set_invalid_volume_label() {
+    let (mut img, _, _) = load_img(FAT16_IMG);
+    let orig_img = img.clone();
+    {
+        let fs = mount(&mut img);
+        for label in &["TOO LONG LABEL", "A.B", "A*", "A\\B", " LEADING", "\u{0141}"] {
+            assert!(
+                matches!(fs.set_volume_label(label), Err(fatfs::Error::InvalidInput)),
+                "{}",
+                label
+            );
+        }
+        assert_eq!(fs.read_volume_label().unwrap().to_string(), "Test!");
+    }
+    assert_eq!(img, orig_img);
+}
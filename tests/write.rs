@@ -1,12 +1,22 @@
+use std::cell::Cell;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs;
 use std::io;
 use std::io::prelude::*;
 use std::mem;
+use std::rc::Rc;
 use std::str;
 
-use fatfs::{DefaultTimeProvider, FsOptions, LossyOemCpConverter, StdIoWrapper};
+use fatfs::overlay::CowStream;
+use fatfs::{
+    Date, DateTime, DefaultTimeProvider, DirCacheStorage, Extent, FatType, FormatVolumeOptions, FsOptions,
+    LinearDirCache, LossyOemCpConverter, ReadWriteSeek, StdIoWrapper, Time, TimeProvider,
+};
 use fscommon::BufStream;
 
+mod common;
+use common::{InstrumentedStream, Layout, StreamState, WriteFault};
+
 const FAT12_IMG: &str = "fat12.img";
 const FAT16_IMG: &str = "fat16.img";
 const FAT32_IMG: &str = "fat32.img";
@@ -134,6 +144,57 @@ fn test_remove_fat32() {
     call_with_fs(test_remove, FAT32_IMG, 3)
 }
 
+fn test_remove_file_and_dir(fs: FileSystem) {
+    let root_dir = fs.root_dir();
+    // type mismatch does not remove anything
+    assert!(matches!(
+        root_dir.remove_file("very-long-dir-name"),
+        Err(fatfs::Error::InvalidInput)
+    ));
+    assert!(matches!(
+        root_dir.remove_dir("short.txt"),
+        Err(fatfs::Error::InvalidInput)
+    ));
+    assert!(matches!(
+        root_dir.remove_file("missing.txt"),
+        Err(fatfs::Error::NotFound { .. })
+    ));
+    assert!(matches!(
+        root_dir.remove_dir("very/missing"),
+        Err(fatfs::Error::NotFound { .. })
+    ));
+    assert!(matches!(
+        root_dir.remove_dir("very/long"),
+        Err(fatfs::Error::DirectoryIsNotEmpty)
+    ));
+    assert!(matches!(root_dir.remove_dir("/"), Err(fatfs::Error::InvalidInput)));
+    let names = root_dir.iter().map(|r| r.unwrap().file_name()).collect::<Vec<String>>();
+    assert_eq!(names, ["long.txt", "short.txt", "very", "very-long-dir-name"]);
+
+    root_dir.remove_file("short.txt").unwrap();
+    root_dir.remove_file("very/long/path/test.txt").unwrap();
+    root_dir.remove_dir("very/long/path").unwrap();
+    root_dir.remove_dir("very/long").unwrap();
+    let names = root_dir.iter().map(|r| r.unwrap().file_name()).collect::<Vec<String>>();
+    assert_eq!(names, ["long.txt", "very", "very-long-dir-name"]);
+    assert!(!root_dir.exists("very/long").unwrap());
+}
+
+#[test]
+fn test_remove_file_and_dir_fat12() {
+    call_with_fs(test_remove_file_and_dir, FAT12_IMG, 10)
+}
+
+#[test]
+fn test_remove_file_and_dir_fat16() {
+    call_with_fs(test_remove_file_and_dir, FAT16_IMG, 10)
+}
+
+#[test]
+fn test_remove_file_and_dir_fat32() {
+    call_with_fs(test_remove_file_and_dir, FAT32_IMG, 10)
+}
+
 fn test_create_file(fs: FileSystem) {
     let root_dir = fs.root_dir();
     let dir = root_dir.open_dir("very/long/path").unwrap();
@@ -201,6 +262,66 @@ fn test_create_file_fat32() {
     call_with_fs(test_create_file, FAT32_IMG, 4)
 }
 
+fn test_create_new_file(fs: FileSystem) {
+    let root_dir = fs.root_dir();
+    {
+        let mut file = root_dir.create_new_file("very/long/path/lock").unwrap();
+        file.write_all(TEST_STR.as_bytes()).unwrap();
+    }
+    // existing long names, short names and directories are reported regardless of case
+    for &path in &[
+        "very/long/path/lock",
+        "very/long/path/LOCK",
+        "short.txt",
+        "SHORT.TXT",
+        "VERY-L~1",
+        "very-long-dir-name",
+        "Very",
+    ] {
+        match root_dir.create_new_file(path) {
+            Err(fatfs::Error::AlreadyExists) => {}
+            Err(err) => panic!("unexpected error {:?} for {}", err, path),
+            Ok(_) => panic!("existing entry {} has been opened", path),
+        }
+    }
+    // the existing file is untouched
+    let mut content = String::new();
+    root_dir
+        .open_file("very/long/path/lock")
+        .unwrap()
+        .read_to_string(&mut content)
+        .unwrap();
+    assert_eq!(&content, &TEST_STR);
+    let names = root_dir
+        .open_dir("very/long/path")
+        .unwrap()
+        .iter()
+        .map(|r| r.unwrap().file_name())
+        .collect::<Vec<String>>();
+    assert_eq!(names, [".", "..", "test.txt", "lock"]);
+    // errors of create_file are kept
+    assert!(matches!(
+        root_dir.create_new_file("missing/lock"),
+        Err(fatfs::Error::NotFound { .. })
+    ));
+    assert!(matches!(root_dir.create_new_file("/"), Err(fatfs::Error::InvalidInput)));
+}
+
+#[test]
+fn test_create_new_file_fat12() {
+    call_with_fs(test_create_new_file, FAT12_IMG, 11)
+}
+
+#[test]
+fn test_create_new_file_fat16() {
+    call_with_fs(test_create_new_file, FAT16_IMG, 11)
+}
+
+#[test]
+fn test_create_new_file_fat32() {
+    call_with_fs(test_create_new_file, FAT32_IMG, 11)
+}
+
 fn test_create_dir(fs: FileSystem) {
     let root_dir = fs.root_dir();
     let parent_dir = root_dir.open_dir("very/long/path").unwrap();
@@ -273,6 +394,128 @@ fn test_create_dir_fat32() {
     call_with_fs(test_create_dir, FAT32_IMG, 5)
 }
 
+fn test_create_new_dir(fs: FileSystem) {
+    let root_dir = fs.root_dir();
+    let dir = root_dir.create_new_dir("very/long/new-dir").unwrap();
+    dir.create_file("old.txt").unwrap();
+    let names = dir.iter().map(|r| r.unwrap().file_name()).collect::<Vec<String>>();
+    assert_eq!(names, [".", "..", "old.txt"]);
+    // create_dir opens the existing directory
+    let dir = root_dir.create_dir("very/long/new-dir").unwrap();
+    assert_eq!(dir.iter().count(), 3);
+    // create_new_dir fails for an existing directory or file regardless of case
+    for &path in &[
+        "very/long/new-dir",
+        "very/long/NEW-DIR",
+        "very/long/path/test.txt",
+        "short.txt",
+    ] {
+        match root_dir.create_new_dir(path) {
+            Err(fatfs::Error::AlreadyExists) => {}
+            Err(err) => panic!("unexpected error {:?} for {}", err, path),
+            Ok(_) => panic!("existing entry {} has been opened", path),
+        }
+    }
+    let names = dir.iter().map(|r| r.unwrap().file_name()).collect::<Vec<String>>();
+    assert_eq!(names, [".", "..", "old.txt"]);
+    // parents are not created
+    assert!(matches!(
+        root_dir.create_new_dir("missing/new-dir"),
+        Err(fatfs::Error::NotFound { .. })
+    ));
+}
+
+#[test]
+fn test_create_new_dir_fat12() {
+    call_with_fs(test_create_new_dir, FAT12_IMG, 12)
+}
+
+#[test]
+fn test_create_new_dir_fat16() {
+    call_with_fs(test_create_new_dir, FAT16_IMG, 12)
+}
+
+#[test]
+fn test_create_new_dir_fat32() {
+    call_with_fs(test_create_new_dir, FAT32_IMG, 12)
+}
+
+fn test_create_many_colliding_names(fs: FileSystem) {
+    let root_dir = fs.root_dir();
+    let dir = root_dir.create_dir("collisions").unwrap();
+    // names with characters `a + 2 * i` and `b - i` have the same checksum so they only differ in numeric tails
+    for i in 0..200 {
+        let name = format!(
+            "Colliding File {}{}.txt",
+            char::from_u32(0x4E00 + 2 * i).unwrap(),
+            char::from_u32(0x8000 - i).unwrap()
+        );
+        dir.create_file(&name).unwrap().write_all(name.as_bytes()).unwrap();
+    }
+    let entries = dir.iter().skip(2).map(|r| r.unwrap()).collect::<Vec<_>>();
+    assert_eq!(entries.len(), 200);
+    let short_names = entries.iter().map(|e| e.short_file_name()).collect::<HashSet<_>>();
+    assert_eq!(short_names.len(), 200);
+    assert!(short_names.contains("COLLID~4.TXT"));
+    assert!(short_names.contains("COLLID~9.TXT"));
+    assert!(short_names.contains("COLLI~10.TXT"));
+    assert!(short_names.contains("COLL~100.TXT"));
+    // every file can be opened by its short name
+    for e in &entries {
+        let mut content = String::new();
+        dir.open_file(&e.short_file_name())
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        assert_eq!(content, e.file_name());
+    }
+    assert!(fs.check().unwrap().is_clean());
+}
+
+#[test]
+fn test_create_many_colliding_names_fat16() {
+    call_with_fs(test_create_many_colliding_names, FAT16_IMG, 13)
+}
+
+#[test]
+fn test_create_many_colliding_names_fat32() {
+    call_with_fs(test_create_many_colliding_names, FAT32_IMG, 13)
+}
+
+fn test_dir_is_empty(fs: FileSystem) {
+    let root_dir = fs.root_dir();
+    let dir = root_dir.create_dir("empty").unwrap();
+    assert!(dir.is_empty().unwrap());
+    // hidden files are not ignored
+    dir.create_file("hidden.txt").unwrap();
+    let mut entry = dir.entry("hidden.txt").unwrap();
+    entry.set_attributes(fatfs::FileAttributes::HIDDEN).unwrap();
+    assert!(!dir.is_empty().unwrap());
+    dir.remove("hidden.txt").unwrap();
+    assert!(dir.is_empty().unwrap());
+    // the root directory has no dot entries and the volume label is ignored
+    assert!(!root_dir.is_empty().unwrap());
+    for name in &["short.txt", "long.txt", "very", "very-long-dir-name", "empty"] {
+        root_dir.remove_recursive(name).unwrap();
+    }
+    assert!(root_dir.is_empty().unwrap());
+}
+
+#[test]
+fn test_dir_is_empty_fat12() {
+    call_with_fs(test_dir_is_empty, FAT12_IMG, 14)
+}
+
+#[test]
+fn test_dir_is_empty_fat16() {
+    call_with_fs(test_dir_is_empty, FAT16_IMG, 14)
+}
+
+#[test]
+fn test_dir_is_empty_fat32() {
+    call_with_fs(test_dir_is_empty, FAT32_IMG, 14)
+}
+
 fn test_rename_file(fs: FileSystem) {
     let root_dir = fs.root_dir();
     let parent_dir = root_dir.open_dir("very/long/path").unwrap();
@@ -405,3 +648,1448 @@ fn test_multiple_files_in_directory_fat16() {
 fn test_multiple_files_in_directory_fat32() {
     call_with_fs(&test_multiple_files_in_directory, FAT32_IMG, 8)
 }
+
+fn test_mount_borrowed_storage(tmp_path: &str) {
+    let file = fs::OpenOptions::new().read(true).write(true).open(tmp_path).unwrap();
+    let mut storage = StdIoWrapper::new(BufStream::new(file));
+    {
+        let fs = fatfs::FileSystem::new(&mut storage, FsOptions::new()).unwrap();
+        let mut file = fs.root_dir().create_file("borrowed.txt").unwrap();
+        file.write_all(TEST_STR.as_bytes()).unwrap();
+    }
+    // Storage is usable directly after the filesystem has been dropped
+    let mut boot_sig = [0_u8; 2];
+    fatfs::Seek::seek(&mut storage, fatfs::SeekFrom::Start(510)).unwrap();
+    fatfs::Read::read_exact(&mut storage, &mut boot_sig).unwrap();
+    assert_eq!(boot_sig, [0x55, 0xAA]);
+    fatfs::Seek::seek(&mut storage, fatfs::SeekFrom::Start(0)).unwrap();
+    // Mount again using the same storage object
+    {
+        let fs = fatfs::FileSystem::new(&mut storage, FsOptions::new()).unwrap();
+        let mut file = fs.root_dir().open_file("borrowed.txt").unwrap();
+        let mut content = String::new();
+        file.read_to_string(&mut content).unwrap();
+        assert_eq!(content, TEST_STR);
+        assert!(!fs.read_status_flags().unwrap().dirty());
+    }
+    // Storage implementing std::io traits can be borrowed too
+    let mut buf_file = storage.into_inner();
+    buf_file.seek(io::SeekFrom::Start(0)).unwrap();
+    let fs = fatfs::FileSystem::new(&mut buf_file, FsOptions::new()).unwrap();
+    assert!(fs.root_dir().open_file("borrowed.txt").is_ok());
+}
+
+#[test]
+fn test_mount_borrowed_storage_fat12() {
+    call_with_tmp_img(test_mount_borrowed_storage, FAT12_IMG, 9)
+}
+
+#[test]
+fn test_mount_borrowed_storage_fat16() {
+    call_with_tmp_img(test_mount_borrowed_storage, FAT16_IMG, 9)
+}
+
+#[test]
+fn test_mount_borrowed_storage_fat32() {
+    call_with_tmp_img(test_mount_borrowed_storage, FAT32_IMG, 9)
+}
+
+fn test_stats(tmp_path: &str) {
+    let fs = open_filesystem_rw(tmp_path);
+    let stats = fs.stats().unwrap();
+    let cluster_size = u64::from(stats.cluster_size());
+    assert_eq!(stats.cluster_size(), fs.cluster_size());
+    assert_eq!(stats.total_bytes(), u64::from(stats.total_clusters()) * cluster_size);
+    assert_eq!(stats.free_bytes(), u64::from(stats.free_clusters()) * cluster_size);
+    assert!(stats.free_clusters() < stats.total_clusters());
+
+    // a file uses its size rounded up to whole clusters
+    let root_dir = fs.root_dir();
+    let size = 5 * cluster_size + 1;
+    let mut file = root_dir.create_file("stats.bin").unwrap();
+    file.write_all(&vec![0xAB; size as usize]).unwrap();
+    drop(file);
+    drop(root_dir);
+    let new_stats = fs.stats().unwrap();
+    assert_eq!(new_stats.free_clusters(), stats.free_clusters() - 6);
+    assert_eq!(new_stats.free_bytes(), stats.free_bytes() - 6 * cluster_size);
+    assert_eq!(new_stats.total_clusters(), stats.total_clusters());
+
+    // the cached count matches a recount after remounting
+    fs.unmount().unwrap();
+    let fs = open_filesystem_rw(tmp_path);
+    assert_eq!(fs.stats().unwrap(), new_stats);
+
+    fs.root_dir().remove("stats.bin").unwrap();
+    assert_eq!(fs.stats().unwrap(), stats);
+}
+
+#[test]
+fn test_stats_fat12() {
+    call_with_tmp_img(test_stats, FAT12_IMG, 15)
+}
+
+#[test]
+fn test_stats_fat16() {
+    call_with_tmp_img(test_stats, FAT16_IMG, 15)
+}
+
+#[test]
+fn test_stats_fat32() {
+    call_with_tmp_img(test_stats, FAT32_IMG, 15)
+}
+
+#[derive(Debug, Clone, Copy)]
+struct FixedTimeProvider;
+
+impl TimeProvider for FixedTimeProvider {
+    fn get_current_date(&self) -> Date {
+        Date::new(2020, 1, 1)
+    }
+
+    fn get_current_date_time(&self) -> DateTime {
+        DateTime::new(Date::new(2020, 1, 1), Time::new(12, 0, 0, 0))
+    }
+}
+
+fn read_size_and_mtime(img: Vec<u8>, name: &str) -> (u64, DateTime) {
+    let fs = fatfs::FileSystem::new(io::Cursor::new(img), FsOptions::new()).unwrap();
+    let e = fs
+        .root_dir()
+        .iter()
+        .map(|r| r.unwrap())
+        .find(|e| e.file_name() == name)
+        .unwrap();
+    (e.len(), e.modification_time().unwrap())
+}
+
+#[test]
+fn test_size_and_mtime_updated_atomically() {
+    let img = fs::read(common::FAT16_IMG).unwrap();
+    let old = read_size_and_mtime(img.clone(), "short.txt");
+    let new_size = old.0 + TEST_STR.len() as u64;
+    let new_mtime = FixedTimeProvider.get_current_date_time();
+    for writes_left in 0.. {
+        // all writes after a power cut are lost
+        let state = StreamState::new(img.clone());
+        state.set_write_fault(WriteFault::DropAfterCalls(writes_left));
+        {
+            let options = FsOptions::new().time_provider(FixedTimeProvider);
+            let fs = fatfs::FileSystem::new(InstrumentedStream::new(&state), options).unwrap();
+            let mut file = fs.root_dir().open_file("short.txt").unwrap();
+            file.seek(io::SeekFrom::End(0)).unwrap();
+            file.write_all(TEST_STR.as_bytes()).unwrap();
+            file.flush().unwrap();
+        }
+        let completed = state.dropped_writes().is_empty();
+        let entry = read_size_and_mtime(state.data(), "short.txt");
+        assert!(
+            entry == old || entry == (new_size, new_mtime),
+            "mixed size and mtime {:?} after {} writes",
+            entry,
+            writes_left
+        );
+        if completed {
+            assert_eq!(entry, (new_size, new_mtime));
+            break;
+        }
+    }
+}
+
+/// A fixed-capacity storage implemented outside of the crate.
+#[derive(Debug, Default)]
+struct ArrayDirCache {
+    entries: [Option<ArrayDirCacheEntry>; 4],
+    next: usize,
+}
+
+#[derive(Debug)]
+struct ArrayDirCacheEntry {
+    dir_cluster: u32,
+    name: [u8; 16],
+    name_len: usize,
+    offset: u64,
+}
+
+impl DirCacheStorage for ArrayDirCache {
+    fn get(&self, dir_cluster: u32, name: &str) -> Option<u64> {
+        self.entries
+            .iter()
+            .flatten()
+            .find(|e| e.dir_cluster == dir_cluster && &e.name[..e.name_len] == name.as_bytes())
+            .map(|e| e.offset)
+    }
+
+    fn insert(&mut self, dir_cluster: u32, name: &str, offset: u64) {
+        if name.len() > 16 {
+            return;
+        }
+        let mut entry = ArrayDirCacheEntry {
+            dir_cluster,
+            name: [0_u8; 16],
+            name_len: name.len(),
+            offset,
+        };
+        entry.name[..name.len()].copy_from_slice(name.as_bytes());
+        self.entries[self.next] = Some(entry);
+        self.next = (self.next + 1) % self.entries.len();
+    }
+
+    fn clear(&mut self) {
+        self.entries = Default::default();
+    }
+}
+
+fn read_file_to_string(dir: &common::Dir, path: &str) -> String {
+    let mut content = String::new();
+    dir.open_file(path).unwrap().read_to_string(&mut content).unwrap();
+    content
+}
+
+/// Runs lookups mixed with operations invalidating cached positions and checks results are the same as without
+/// the cache.
+fn check_dir_cache_correctness(img_path: &str, storage: Option<Box<dyn DirCacheStorage + Send>>) -> Vec<String> {
+    let fs = common::open_fs(img_path);
+    fs.set_dir_cache(storage);
+    let root_dir = fs.root_dir();
+    let mut log = Vec::new();
+    for _ in 0..2 {
+        log.push(read_file_to_string(&root_dir, "short.txt"));
+        log.push(read_file_to_string(&root_dir, "very/long/path/test.txt"));
+        log.push(read_file_to_string(&root_dir, "VERY/LONG/PATH/TEST.TXT"));
+    }
+    // cached position of a removed entry
+    root_dir.remove("short.txt").unwrap();
+    log.push(format!("{:?}", root_dir.open_file("short.txt").err()));
+    // a new entry with the same name at a different position
+    let dir = root_dir.open_dir("very/long/path").unwrap();
+    dir.create_file("a.txt").unwrap();
+    dir.rename("test.txt", &dir, "b.txt").unwrap();
+    dir.create_file("test.txt").unwrap().write_all(b"new").unwrap();
+    log.push(read_file_to_string(&root_dir, "very/long/path/test.txt"));
+    log.push(read_file_to_string(&root_dir, "very/long/path/b.txt"));
+    // a file in place of a cached directory
+    root_dir.rename("very/long/path", &root_dir, "path").unwrap();
+    log.push(format!("{:?}", root_dir.open_dir("very/long/path").err()));
+    root_dir.create_file("very/long/path").unwrap();
+    log.push(format!("{:?}", root_dir.open_dir("very/long/path").err()));
+    log.push(read_file_to_string(&root_dir, "path/b.txt"));
+    log
+}
+
+fn test_dir_cache_correctness(img_path: &str) {
+    let expected = check_dir_cache_correctness(img_path, None);
+    assert_eq!(expected[0], TEST_STR2);
+    assert_eq!(
+        check_dir_cache_correctness(img_path, Some(Box::new(LinearDirCache::default()))),
+        expected
+    );
+    assert_eq!(
+        check_dir_cache_correctness(img_path, Some(Box::new(LinearDirCache::new(1)))),
+        expected
+    );
+    assert_eq!(
+        check_dir_cache_correctness(img_path, Some(Box::new(ArrayDirCache::default()))),
+        expected
+    );
+    #[cfg(feature = "hashbrown")]
+    {
+        use fatfs::HashbrownDirCache;
+        assert_eq!(
+            check_dir_cache_correctness(img_path, Some(Box::new(HashbrownDirCache::default()))),
+            expected
+        );
+        assert_eq!(
+            check_dir_cache_correctness(img_path, Some(Box::new(HashbrownDirCache::new(1)))),
+            expected
+        );
+    }
+}
+
+#[test]
+fn test_dir_cache_correctness_fat16() {
+    test_dir_cache_correctness(common::FAT16_IMG)
+}
+
+#[test]
+fn test_dir_cache_correctness_fat32() {
+    test_dir_cache_correctness(common::FAT32_IMG)
+}
+
+fn count_lookup_reads(storage: Option<Box<dyn DirCacheStorage + Send>>) -> usize {
+    let state = StreamState::from_img(common::FAT16_IMG);
+    let fs = common::remount(&state);
+    for i in 0..30 {
+        fs.root_dir().create_file(&format!("very/file{}.txt", i)).unwrap();
+    }
+    fs.set_dir_cache(storage);
+    fs.root_dir().open_file("very/file29.txt").unwrap();
+    state.clear_ops();
+    for _ in 0..10 {
+        fs.root_dir().open_file("very/file29.txt").unwrap();
+    }
+    state.reads().len()
+}
+
+#[test]
+fn test_dir_cache_reduces_reads() {
+    let reads_without_cache = count_lookup_reads(None);
+    let reads_with_cache = count_lookup_reads(Some(Box::new(LinearDirCache::default())));
+    assert!(
+        reads_with_cache * 4 < reads_without_cache,
+        "{} reads with cache, {} without",
+        reads_with_cache,
+        reads_without_cache
+    );
+    #[cfg(feature = "hashbrown")]
+    assert_eq!(
+        count_lookup_reads(Some(Box::new(fatfs::HashbrownDirCache::default()))),
+        reads_with_cache
+    );
+}
+
+/// Modifies the filesystem: writes a file, creates a directory and removes a file.
+fn modify_fs<IO: ReadWriteSeek>(storage: IO) {
+    let options = FsOptions::new().time_provider(FixedTimeProvider);
+    let fs = fatfs::FileSystem::new(storage, options).unwrap();
+    {
+        let root_dir = fs.root_dir();
+        let mut file = root_dir.create_file("new.txt").unwrap();
+        for _ in 0..100 {
+            fatfs::Write::write_all(&mut file, b"Hello overlay!\n").unwrap();
+        }
+        drop(file);
+        root_dir.create_dir("new-dir").unwrap().create_file("x").unwrap();
+        root_dir.remove("short.txt").unwrap();
+    }
+    fs.unmount().unwrap();
+}
+
+fn test_overlay(img_path: &str) {
+    let orig = fs::read(img_path).unwrap();
+
+    let mut direct = io::Cursor::new(orig.clone());
+    modify_fs(StdIoWrapper::from(&mut direct));
+    let direct = direct.into_inner();
+    assert_ne!(direct, orig);
+
+    // discarded changes leave the storage untouched
+    let mut cow = CowStream::new(StdIoWrapper::from(io::Cursor::new(orig.clone())));
+    modify_fs(&mut cow);
+    assert!(cow.is_modified());
+    cow.discard();
+    assert!(!cow.is_modified());
+    let mut cow = CowStream::new(cow.into_inner());
+    modify_fs(&mut cow);
+    let mut content = Vec::new();
+    fatfs::Seek::seek(&mut cow, fatfs::SeekFrom::Start(0)).unwrap();
+    let mut buf = [0_u8; 4096];
+    loop {
+        let n = fatfs::Read::read(&mut cow, &mut buf).unwrap();
+        if n == 0 {
+            break;
+        }
+        content.extend_from_slice(&buf[..n]);
+    }
+    // the overlay sees the same image as a direct run
+    assert_eq!(content, direct);
+    assert_eq!(cow.into_inner().into_inner().into_inner(), orig);
+
+    // committed changes are identical to a direct run
+    let mut cow = CowStream::new(StdIoWrapper::from(io::Cursor::new(orig.clone())));
+    modify_fs(&mut cow);
+    cow.commit().unwrap();
+    assert!(!cow.is_modified());
+    assert_eq!(cow.into_inner().into_inner().into_inner(), direct);
+}
+
+#[test]
+fn test_overlay_fat12() {
+    test_overlay(common::FAT12_IMG)
+}
+
+#[test]
+fn test_overlay_fat16() {
+    test_overlay(common::FAT16_IMG)
+}
+
+#[test]
+fn test_overlay_fat32() {
+    test_overlay(common::FAT32_IMG)
+}
+
+/// Appends `clusters` clusters to a new file one cluster at a time and returns the number of FAT writes and copies.
+fn count_fat_writes(img_path: &str, clusters: u32) -> (usize, u32) {
+    let (fs, state) = common::open_instrumented_fs(img_path, FsOptions::new());
+    let layout = Layout::read(&state.data());
+    let cluster_size = fs.cluster_size() as usize;
+    let mut file = fs.root_dir().create_file("append.bin").unwrap();
+    // the volume is marked dirty by the first write - do not count it
+    file.write_all(&[1]).unwrap();
+    file.flush().unwrap();
+    state.clear_ops();
+    let data = vec![0xAB_u8; cluster_size];
+    file.write_all(&data[1..]).unwrap();
+    for _ in 1..clusters {
+        file.write_all(&data).unwrap();
+    }
+    drop(file);
+    let count = state.writes_in(&layout.fat_region());
+    fs.unmount().unwrap();
+    (count, layout.fats as u32)
+}
+
+fn test_sequential_append_fat_writes(img_path: &str) {
+    let clusters = 64;
+    let (writes, fats) = count_fat_writes(img_path, clusters);
+    // every allocation after the first one has to be written to every FAT copy at least once - linking the previous
+    // cluster is combined with it unless their entries lie in different FAT sectors
+    let min_writes = ((clusters - 1) * fats) as usize;
+    assert!(writes >= min_writes);
+    assert!(
+        writes <= min_writes + 2 * fats as usize,
+        "{} FAT writes for {} clusters and {} FAT copies",
+        writes,
+        clusters,
+        fats
+    );
+}
+
+#[test]
+fn test_sequential_append_fat_writes_fat12() {
+    test_sequential_append_fat_writes(common::FAT12_IMG)
+}
+
+#[test]
+fn test_sequential_append_fat_writes_fat16() {
+    test_sequential_append_fat_writes(common::FAT16_IMG)
+}
+
+#[test]
+fn test_sequential_append_fat_writes_fat32() {
+    test_sequential_append_fat_writes(common::FAT32_IMG)
+}
+
+#[test]
+fn test_sequential_append_keeps_chain() {
+    let state = StreamState::from_img(common::FAT12_IMG);
+    let data: Vec<u8> = (0..64 * 512).map(|i| (i % 251) as u8).collect();
+    {
+        let fs = common::remount(&state);
+        let mut file = fs.root_dir().create_file("append.bin").unwrap();
+        for chunk in data.chunks(512) {
+            file.write_all(chunk).unwrap();
+        }
+    }
+    let fs = common::remount(&state);
+    let mut file = fs.root_dir().open_file("append.bin").unwrap();
+    let mut read_data = Vec::new();
+    file.read_to_end(&mut read_data).unwrap();
+    assert!(read_data == data);
+    assert!(fs.check().unwrap().is_clean());
+}
+
+type Model = BTreeMap<String, Vec<u8>>;
+
+fn file_content(i: usize) -> Vec<u8> {
+    // some files span multiple clusters
+    (0..i * 300).map(|n| (n % 251) as u8 ^ i as u8).collect()
+}
+
+fn read_file<T: Read>(mut file: T) -> Vec<u8> {
+    let mut buf = Vec::new();
+    file.read_to_end(&mut buf).unwrap();
+    buf
+}
+
+fn check_contents(root_dir: &common::InstrumentedDir, model: &Model) {
+    let names: Vec<_> = root_dir
+        .iter()
+        .map(|r| r.unwrap().file_name())
+        .filter(|name| model.contains_key(name))
+        .collect();
+    assert_eq!(names.len(), model.len());
+    for (name, content) in model {
+        assert_eq!(&read_file(root_dir.open_file(name).unwrap()), content, "{}", name);
+    }
+}
+
+/// Modifies the FAT16 root directory region while FAT reads are served from the prefetched copy and lookups from the
+/// directory cache.
+fn run_cached_root_dir_session(state: &Rc<StreamState>) -> Model {
+    let fs = common::remount(state);
+    fs.prefetch_fat().unwrap();
+    fs.set_dir_cache(Some(Box::new(LinearDirCache::new(64))));
+    let mut model = Model::new();
+    let root_dir = fs.root_dir();
+    for i in 0..40 {
+        let name = format!("file{}.txt", i);
+        let content = file_content(i);
+        root_dir.create_file(&name).unwrap().write_all(&content).unwrap();
+        model.insert(name.clone(), content);
+        if i % 3 == 0 && i > 0 {
+            // append to an earlier file found using the cache
+            let earlier = format!("file{}.txt", i - 2);
+            if let Some(content) = model.get_mut(&earlier) {
+                let mut file = root_dir.open_file(&earlier).unwrap();
+                file.seek(io::SeekFrom::End(0)).unwrap();
+                file.write_all(b"appended").unwrap();
+                content.extend_from_slice(b"appended");
+            }
+        }
+        if i % 5 == 0 {
+            let new_name = format!("renamed{}.txt", i);
+            root_dir.rename(&name, &root_dir, &new_name).unwrap();
+            let content = model.remove(&name).unwrap();
+            model.insert(new_name, content);
+        }
+        if i % 7 == 0 {
+            let removed = model.keys().next().unwrap().clone();
+            root_dir.remove(&removed).unwrap();
+            model.remove(&removed);
+        }
+        check_contents(&root_dir, &model);
+    }
+    drop(root_dir);
+    fs.unmount().unwrap();
+    model
+}
+
+#[test]
+fn test_root_dir_writes_coherent_with_caches() {
+    let state = StreamState::from_img(common::FAT16_IMG);
+    let model = run_cached_root_dir_session(&state);
+
+    let fs = common::remount(&state);
+    assert!(!fs.read_status_flags().unwrap().dirty());
+    check_contents(&fs.root_dir(), &model);
+    assert!(fs.check().unwrap().is_clean());
+    // the original entries are not affected
+    assert_eq!(
+        read_file(fs.root_dir().open_file("short.txt").unwrap()),
+        TEST_STR2.as_bytes()
+    );
+}
+
+/// Mounts fat16 image with an additional file `Foo.txt`.
+fn open_fs_with_foo(case_sensitive: bool) -> common::FileSystem {
+    let options = FsOptions::new().case_sensitive_lookup(case_sensitive);
+    let fs = common::open_fs_with_options(common::FAT16_IMG, options);
+    fs.root_dir()
+        .create_file("Foo.txt")
+        .unwrap()
+        .write_all(b"upper")
+        .unwrap();
+    fs
+}
+
+fn long_and_short_names(dir: &common::Dir) -> Vec<(String, String)> {
+    dir.iter()
+        .map(|r| {
+            let e = r.unwrap();
+            (e.file_name(), e.short_file_name())
+        })
+        .collect()
+}
+
+#[test]
+fn test_case_insensitive_lookup_by_default() {
+    let fs = open_fs_with_foo(false);
+    let root_dir = fs.root_dir();
+    assert_eq!(read_file_to_string(&root_dir, "foo.txt"), "upper");
+    root_dir.create_file("foo.txt").unwrap().write_all(b"lower").unwrap();
+    assert_eq!(read_file_to_string(&root_dir, "Foo.txt"), "lower");
+    assert!(root_dir.exists("VERY/LONG").unwrap());
+    let names = long_and_short_names(&root_dir);
+    assert_eq!(names.iter().filter(|(n, _)| n == "Foo.txt").count(), 1);
+}
+
+#[test]
+fn test_case_sensitive_lookup() {
+    let fs = open_fs_with_foo(true);
+    let root_dir = fs.root_dir();
+    assert_eq!(read_file_to_string(&root_dir, "Foo.txt"), "upper");
+    assert!(matches!(
+        root_dir.open_file("foo.txt"),
+        Err(fatfs::Error::NotFound { .. })
+    ));
+    assert!(!root_dir.exists("FOO.TXT").unwrap());
+    assert!(!root_dir.exists("VERY/long").unwrap());
+    assert!(root_dir.exists("very/long").unwrap());
+
+    // a file differing only in case is a separate file with a unique short name
+    root_dir.create_file("foo.txt").unwrap().write_all(b"lower").unwrap();
+    assert_eq!(read_file_to_string(&root_dir, "Foo.txt"), "upper");
+    assert_eq!(read_file_to_string(&root_dir, "foo.txt"), "lower");
+    let entries = long_and_short_names(&root_dir);
+    assert!(entries.contains(&("Foo.txt".into(), "FOO.TXT".into())));
+    assert!(entries.contains(&("foo.txt".into(), "FOO~1.TXT".into())));
+    // short names are still compared case-insensitively
+    assert_eq!(read_file_to_string(&root_dir, "foo~1.txt"), "lower");
+
+    root_dir.create_dir("Dir").unwrap();
+    root_dir.create_dir("dir").unwrap().create_file("inner.txt").unwrap();
+    assert!(!root_dir.exists("Dir/inner.txt").unwrap());
+    assert!(root_dir.exists("dir/inner.txt").unwrap());
+
+    root_dir.rename("foo.txt", &root_dir, "bar.txt").unwrap();
+    assert_eq!(read_file_to_string(&root_dir, "Foo.txt"), "upper");
+    assert_eq!(read_file_to_string(&root_dir, "bar.txt"), "lower");
+    root_dir.create_file("foo.txt").unwrap();
+    root_dir.remove("foo.txt").unwrap();
+    assert_eq!(read_file_to_string(&root_dir, "Foo.txt"), "upper");
+    assert!(!root_dir.exists("foo.txt").unwrap());
+    root_dir.remove("Foo.txt").unwrap();
+    assert!(!root_dir.exists("Foo.txt").unwrap());
+    assert!(fs.check().unwrap().is_clean());
+}
+
+/// Returns the NT reserved byte of the short name entry `short_name`.
+fn nt_flags(img: &[u8], short_name: &[u8; 11]) -> u8 {
+    let offset = (0..img.len())
+        .step_by(32)
+        .find(|&i| img[i..].starts_with(short_name))
+        .unwrap();
+    img[offset + 12]
+}
+
+fn test_lowercase_names(img_path: &str) {
+    let state = StreamState::from_img(img_path);
+    {
+        let fs = common::remount(&state);
+        let dir = fs.root_dir().create_dir("d").unwrap();
+        // (name, short name, number of slots)
+        let cases = [
+            ("readme.txt", "README.TXT", 1),
+            ("LOGFILE.txt", "LOGFILE.TXT", 1),
+            ("makefile", "MAKEFILE", 1),
+            ("NOTES.TXT", "NOTES.TXT", 1),
+            ("1st-log.bin", "1ST-LOG.BIN", 1),
+            ("MixCase.txt", "MIXCASE.TXT", 2),
+            ("other.Txt", "OTHER.TXT", 2),
+        ];
+        for &(name, short_name, slots) in &cases {
+            let file = dir.create_file(name).unwrap();
+            drop(file);
+            let entry = dir.entry(name).unwrap();
+            assert_eq!(entry.file_name(), name);
+            assert_eq!(entry.short_file_name(), short_name);
+            assert_eq!(entry.slot_count(), slots, "{}", name);
+        }
+        assert!(fs.check().unwrap().is_clean());
+    }
+    let img = state.data();
+    assert_eq!(nt_flags(&img, b"README  TXT"), 0x18);
+    assert_eq!(nt_flags(&img, b"LOGFILE TXT"), 0x10);
+    assert_eq!(nt_flags(&img, b"MAKEFILE   "), 0x08);
+    assert_eq!(nt_flags(&img, b"NOTES   TXT"), 0);
+    let fs = common::remount(&state);
+    let dir = fs.root_dir().open_dir("d").unwrap();
+    let names: Vec<_> = dir.iter().skip(2).map(|r| r.unwrap().file_name()).collect();
+    assert_eq!(
+        names,
+        [
+            "readme.txt",
+            "LOGFILE.txt",
+            "makefile",
+            "NOTES.TXT",
+            "1st-log.bin",
+            "MixCase.txt",
+            "other.Txt"
+        ]
+    );
+}
+
+#[test]
+fn test_lowercase_names_fat16() {
+    test_lowercase_names(common::FAT16_IMG)
+}
+
+#[test]
+fn test_lowercase_names_fat32() {
+    test_lowercase_names(common::FAT32_IMG)
+}
+
+#[test]
+fn test_case_only_rename_updates_flags() {
+    let fs = common::open_fs(common::FAT16_IMG);
+    let root_dir = fs.root_dir();
+    root_dir.rename("short.txt", &root_dir, "SHORT.TXT").unwrap();
+    let entry = root_dir.entry("short.txt").unwrap();
+    assert_eq!(entry.file_name(), "SHORT.TXT");
+    assert_eq!(entry.slot_count(), 1);
+    root_dir.rename("SHORT.TXT", &root_dir, "short.txt").unwrap();
+    let entry = root_dir.entry("short.txt").unwrap();
+    assert_eq!(entry.file_name(), "short.txt");
+    assert_eq!(entry.slot_count(), 1);
+    // a name with mixed case needs a long name again
+    root_dir.rename("short.txt", &root_dir, "Short.txt").unwrap();
+    let entry = root_dir.entry("short.txt").unwrap();
+    assert_eq!(entry.file_name(), "Short.txt");
+    assert_eq!(entry.slot_count(), 2);
+    assert!(fs.check().unwrap().is_clean());
+}
+
+/// Checks that creating an entry in a big directory reads the directory only once.
+fn test_create_reads_dir_once(img_path: &str) {
+    let (fs, state) = common::open_instrumented_fs(img_path, FsOptions::new());
+    let dir = fs.root_dir().create_dir("big").unwrap();
+    let num_files = 200;
+    for i in 0..num_files {
+        dir.create_file(&format!("File {}.txt", i)).unwrap();
+    }
+    // every file takes a LFN and a SFN entry
+    let dir_size = (2 + 2 * num_files) * 32;
+    let check_reads = |num_scans: u64, f: &dyn Fn()| {
+        state.clear_ops();
+        f();
+        let read = state.bytes_read();
+        // directory scans and some reads of FAT structures
+        let scans_size = num_scans * dir_size;
+        assert!(
+            read >= scans_size && read < scans_size + dir_size / 2,
+            "{} bytes read",
+            read
+        );
+    };
+    check_reads(1, &|| {
+        dir.create_file("New File.txt").unwrap();
+    });
+    check_reads(1, &|| {
+        dir.create_dir("New Dir").unwrap();
+    });
+    // rename looks up the source entry first, the destination lookup finds free entries too
+    check_reads(2, &|| dir.rename("New File.txt", &dir, "Renamed File.txt").unwrap());
+    assert_eq!(dir.iter().count(), 2 + num_files as usize + 2);
+    assert!(fs.check().unwrap().is_clean());
+}
+
+#[test]
+fn test_create_reads_dir_once_fat16() {
+    test_create_reads_dir_once(common::FAT16_IMG)
+}
+
+#[test]
+fn test_create_reads_dir_once_fat32() {
+    test_create_reads_dir_once(common::FAT32_IMG)
+}
+
+/// Checks that the entries found during the scan are reused - a deleted entry is filled first.
+#[test]
+fn test_create_reuses_deleted_entries() {
+    let fs = common::open_fs(common::FAT16_IMG);
+    let dir = fs.root_dir().create_dir("dir").unwrap();
+    for name in &["First File.txt", "Second File.txt", "Third File.txt"] {
+        dir.create_file(name).unwrap();
+    }
+    let pos = dir.entry("Second File.txt").unwrap().entry_pos();
+    dir.remove("Second File.txt").unwrap();
+    // a name needing more entries does not fit in the freed slots
+    dir.create_file("A Much Longer File Name.txt").unwrap();
+    dir.create_file("Other File.txt").unwrap();
+    assert_eq!(dir.entry("Other File.txt").unwrap().entry_pos(), pos);
+    let names: Vec<_> = dir.iter().map(|r| r.unwrap().file_name()).collect();
+    assert_eq!(
+        names,
+        [
+            ".",
+            "..",
+            "First File.txt",
+            "Other File.txt",
+            "Third File.txt",
+            "A Much Longer File Name.txt"
+        ]
+    );
+    assert!(fs.check().unwrap().is_clean());
+}
+
+/// Replaces the UCS-2 unit `old` following `prefix` in a long name entry with `new`.
+fn patch_lfn_unit(img: &mut [u8], prefix: char, old: char, new: u16) {
+    let mut pattern = Vec::new();
+    for c in &[prefix, old] {
+        pattern.extend_from_slice(&(*c as u16).to_le_bytes());
+    }
+    let pos = img.windows(pattern.len()).position(|w| w == &pattern[..]).unwrap();
+    img[pos + 2..pos + 4].copy_from_slice(&new.to_le_bytes());
+}
+
+/// Returns an image with two files with long names containing unpaired surrogates in the `dir` directory.
+///
+/// The names differ only in the surrogate, so their lossy decoded names are the same.
+fn prepare_img_with_invalid_utf16_names(img_path: &str) -> Vec<u8> {
+    let state = StreamState::from_img(img_path);
+    {
+        let fs = common::remount(&state);
+        let dir = fs.root_dir().create_dir("dir").unwrap();
+        dir.create_file("Q1z Name.txt").unwrap().write_all(b"first").unwrap();
+        dir.create_file("Q2z Name.txt").unwrap().write_all(b"second").unwrap();
+    }
+    let mut img = state.data();
+    patch_lfn_unit(&mut img, 'Q', '1', 0xD800);
+    patch_lfn_unit(&mut img, 'Q', '2', 0xDC00);
+    img
+}
+
+fn file_to_string(mut file: impl Read) -> String {
+    let mut content = String::new();
+    file.read_to_string(&mut content).unwrap();
+    content
+}
+
+fn ucs2_names(dir: &common::Dir) -> Vec<Vec<u16>> {
+    dir.iter()
+        .filter_map(|r| r.unwrap().long_file_name_as_ucs2_units().map(<[u16]>::to_vec))
+        .collect()
+}
+
+fn test_invalid_utf16_names(img_path: &str) {
+    let fs = common::mount(prepare_img_with_invalid_utf16_names(img_path));
+    let dir = fs.root_dir().open_dir("dir").unwrap();
+    let names: Vec<_> = dir.iter().map(|r| r.unwrap().file_name()).collect();
+    assert_eq!(names, [".", "..", "Q\u{FFFD}z Name.txt", "Q\u{FFFD}z Name.txt"]);
+    let ucs2_names = ucs2_names(&dir);
+    assert_eq!(ucs2_names[0][..3], [u16::from(b'Q'), 0xD800, u16::from(b'z')]);
+    assert_eq!(ucs2_names[1][..3], [u16::from(b'Q'), 0xDC00, u16::from(b'z')]);
+
+    // the lossy name can be used in paths - it matches the first entry
+    assert_eq!(file_to_string(dir.open_file("Q\u{FFFD}z Name.txt").unwrap()), "first");
+    assert_eq!(
+        file_to_string(fs.root_dir().open_file("dir/q\u{FFFD}Z NAME.TXT").unwrap()),
+        "first"
+    );
+    // the raw name matches exactly one entry
+    let e = dir.entry_by_ucs2_name(&ucs2_names[1]).unwrap();
+    assert_eq!(e.path(), "dir/Q\u{FFFD}z Name.txt");
+    assert_eq!(file_to_string(e.to_file()), "second");
+    assert_eq!(
+        file_to_string(dir.entry_by_ucs2_name(&ucs2_names[0]).unwrap().to_file()),
+        "first"
+    );
+    let mut other_name = ucs2_names[0].clone();
+    other_name[1] = 0xDBFF;
+    assert!(matches!(
+        dir.entry_by_ucs2_name(&other_name),
+        Err(fatfs::Error::NotFound { .. })
+    ));
+
+    // the second file can be renamed using its short name
+    let short_name = e.short_file_name();
+    dir.rename(&short_name, &dir, "Renamed.txt").unwrap();
+    assert_eq!(file_to_string(dir.open_file("renamed.txt").unwrap()), "second");
+    dir.remove("Q\u{FFFD}z Name.txt").unwrap();
+    assert!(dir.entry_by_ucs2_name(&ucs2_names[0]).is_err());
+    assert!(fs.check().unwrap().is_clean());
+}
+
+#[test]
+fn test_invalid_utf16_names_fat16() {
+    test_invalid_utf16_names(common::FAT16_IMG)
+}
+
+#[test]
+fn test_invalid_utf16_names_fat32() {
+    test_invalid_utf16_names(common::FAT32_IMG)
+}
+
+#[test]
+fn test_entry_by_valid_ucs2_name() {
+    let fs = common::open_fs(common::FAT16_IMG);
+    let root_dir = fs.root_dir();
+    let name: Vec<u16> = "LONG.txt".encode_utf16().collect();
+    // valid names are looked up ignoring case and short names
+    assert_eq!(root_dir.entry_by_ucs2_name(&name).unwrap().file_name(), "long.txt");
+    let name: Vec<u16> = "VERY-L~1".encode_utf16().collect();
+    assert_eq!(
+        root_dir.entry_by_ucs2_name(&name).unwrap().file_name(),
+        "very-long-dir-name"
+    );
+    let name: Vec<u16> = "missing.txt".encode_utf16().collect();
+    assert!(matches!(
+        root_dir.entry_by_ucs2_name(&name),
+        Err(fatfs::Error::NotFound { .. })
+    ));
+    assert!(matches!(
+        root_dir.entry_by_ucs2_name(&[]),
+        Err(fatfs::Error::InvalidFileNameLength)
+    ));
+}
+
+const BLOCK_SIZE: u64 = 4096;
+const MAX_FILE_SIZE: u64 = 0xFFFF_FFFF;
+
+/// An in-memory storage allocating memory only for blocks that have been written.
+struct SparseStorage {
+    blocks: HashMap<u64, Vec<u8>>,
+    len: u64,
+    pos: u64,
+}
+
+impl SparseStorage {
+    fn new(len: u64) -> Self {
+        Self {
+            blocks: HashMap::new(),
+            len,
+            pos: 0,
+        }
+    }
+}
+
+impl Read for SparseStorage {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let offset = (self.pos % BLOCK_SIZE) as usize;
+        let n = buf
+            .len()
+            .min(BLOCK_SIZE as usize - offset)
+            .min((self.len - self.pos) as usize);
+        match self.blocks.get(&(self.pos / BLOCK_SIZE)) {
+            Some(block) => buf[..n].copy_from_slice(&block[offset..offset + n]),
+            None => buf[..n].iter_mut().for_each(|b| *b = 0),
+        }
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl Write for SparseStorage {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let offset = (self.pos % BLOCK_SIZE) as usize;
+        let n = buf
+            .len()
+            .min(BLOCK_SIZE as usize - offset)
+            .min((self.len - self.pos) as usize);
+        let block = self
+            .blocks
+            .entry(self.pos / BLOCK_SIZE)
+            .or_insert_with(|| vec![0; BLOCK_SIZE as usize]);
+        block[offset..offset + n].copy_from_slice(&buf[..n]);
+        self.pos += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Seek for SparseStorage {
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        let new_pos = match pos {
+            io::SeekFrom::Start(n) => Some(n),
+            io::SeekFrom::End(n) => (self.len as i64).checked_add(n).map(|n| n as u64),
+            io::SeekFrom::Current(n) => (self.pos as i64).checked_add(n).map(|n| n as u64),
+        };
+        match new_pos {
+            Some(n) if n <= self.len => {
+                self.pos = n;
+                Ok(n)
+            }
+            _ => Err(io::Error::new(io::ErrorKind::InvalidInput, "invalid seek")),
+        }
+    }
+}
+
+type SparseFileSystem = fatfs::FileSystem<StdIoWrapper<SparseStorage>, DefaultTimeProvider, LossyOemCpConverter>;
+
+/// Creates a FAT32 volume bigger than the maximal file size.
+fn create_sparse_fs() -> SparseFileSystem {
+    common::init_logger();
+    let total_sectors = 9 * 1024 * 1024;
+    let mut storage = StdIoWrapper::from(SparseStorage::new(u64::from(total_sectors) * 512));
+    let options = FormatVolumeOptions::new()
+        .total_sectors(total_sectors)
+        .bytes_per_cluster(32 * 1024);
+    fatfs::format_volume(&mut storage, options).unwrap();
+    SparseFileSystem::new(storage.into_inner(), FsOptions::new()).unwrap()
+}
+
+#[test]
+fn test_file_size_limit() {
+    let fs = create_sparse_fs();
+    assert_eq!(fs.fat_type(), FatType::Fat32);
+    let root_dir = fs.root_dir();
+    let mut file = root_dir.create_file("big.bin").unwrap();
+    // make the file 4 GiB minus 2 bytes long without writing the data
+    file.preallocate(MAX_FILE_SIZE - 1, true).unwrap();
+    assert_eq!(file.seek(io::SeekFrom::End(0)).unwrap(), MAX_FILE_SIZE - 1);
+
+    // only the byte that fits is written
+    assert_eq!(fatfs::Write::write(&mut file, &[1, 2, 3]).unwrap(), 1);
+    assert_eq!(file.stream_position().unwrap(), MAX_FILE_SIZE);
+    assert!(matches!(
+        fatfs::Write::write(&mut file, &[2]),
+        Err(fatfs::Error::FileTooLarge)
+    ));
+    let err = file.write_all(&[2]).unwrap_err();
+    assert_eq!(err.to_string(), "File too large");
+    // an empty write is fine
+    assert_eq!(fatfs::Write::write(&mut file, &[]).unwrap(), 0);
+
+    // seeking beyond the maximal size is rejected
+    assert!(file.seek(io::SeekFrom::Start(MAX_FILE_SIZE + 1)).is_err());
+    assert!(file.seek(io::SeekFrom::Current(1)).is_err());
+    assert_eq!(file.stream_position().unwrap(), MAX_FILE_SIZE);
+
+    assert!(matches!(
+        file.set_len(MAX_FILE_SIZE + 1),
+        Err(fatfs::Error::FileTooLarge)
+    ));
+    assert!(matches!(
+        file.preallocate(MAX_FILE_SIZE + 1, true),
+        Err(fatfs::Error::FileTooLarge)
+    ));
+    file.set_len(MAX_FILE_SIZE).unwrap();
+    drop(file);
+
+    let e = root_dir.entry("big.bin").unwrap();
+    assert_eq!(e.len(), MAX_FILE_SIZE);
+    let mut file = e.to_file();
+    file.seek(io::SeekFrom::End(-2)).unwrap();
+    let mut buf = Vec::new();
+    file.read_to_end(&mut buf).unwrap();
+    assert_eq!(buf, [0, 1]);
+
+    // the file can be shrunk below the limit and extended again
+    file.set_len(MAX_FILE_SIZE - 1).unwrap();
+    file.seek(io::SeekFrom::End(0)).unwrap();
+    assert_eq!(fatfs::Write::write(&mut file, &[4, 5]).unwrap(), 1);
+    drop(file);
+    assert_eq!(root_dir.entry("big.bin").unwrap().len(), MAX_FILE_SIZE);
+}
+
+/// A time provider advancing the time by 2 seconds every time it is queried.
+#[derive(Debug, Default)]
+struct TickingTimeProvider {
+    seconds: Cell<u16>,
+}
+
+impl TimeProvider for TickingTimeProvider {
+    fn get_current_date(&self) -> Date {
+        Date::new(2024, 1, 1)
+    }
+
+    fn get_current_date_time(&self) -> DateTime {
+        let seconds = self.seconds.get() + 2;
+        self.seconds.set(seconds);
+        DateTime::new(Date::new(2024, 1, 1), Time::new(12, seconds / 60 % 60, seconds % 60, 0))
+    }
+}
+
+/// Overwrites the beginning of `short.txt` and appends to it a few times flushing after every write.
+///
+/// Returns the number of writes to the sector containing the directory entry of the file, its modification time and
+/// length.
+fn count_entry_sector_writes(img_path: &str, update_modified_time: bool) -> (usize, Option<DateTime>, u64) {
+    let state = StreamState::from_img(img_path);
+    let options = FsOptions::new()
+        .update_modified_time(update_modified_time)
+        .update_archive_attribute(false)
+        .time_provider(TickingTimeProvider::default());
+    let fs = fatfs::FileSystem::new(InstrumentedStream::new(&state), options).unwrap();
+    let root_dir = fs.root_dir();
+    let entry_sector = root_dir.entry("short.txt").unwrap().entry_pos() / 512;
+    let mut file = root_dir.open_file("short.txt").unwrap();
+    for _ in 0..5 {
+        file.seek(io::SeekFrom::Start(0)).unwrap();
+        file.write_all(b"R").unwrap();
+        file.flush().unwrap();
+    }
+    for _ in 0..3 {
+        file.seek(io::SeekFrom::End(0)).unwrap();
+        file.write_all(b"!").unwrap();
+        file.flush().unwrap();
+    }
+    drop(file);
+    let count = state
+        .writes()
+        .iter()
+        .filter(|(offset, _)| offset / 512 == entry_sector)
+        .count();
+    let e = root_dir.entry("short.txt").unwrap();
+    (count, e.modification_time(), e.len())
+}
+
+fn test_update_modified_time(img_path: &str) {
+    let (count_enabled, modified_enabled, len_enabled) = count_entry_sector_writes(img_path, true);
+    let (count_disabled, modified_disabled, len_disabled) = count_entry_sector_writes(img_path, false);
+    // every flush writes the entry with a new modification time
+    assert_eq!(count_enabled, 8);
+    // the entry is written only when the size changes
+    assert_eq!(count_disabled, 3);
+    assert_eq!(len_enabled, 14 + 3);
+    assert_eq!(len_disabled, 14 + 3);
+    assert_eq!(modified_enabled.unwrap().time.min, 0);
+    assert_eq!(modified_enabled.unwrap().time.sec, 16);
+    // the original modification time of the file is kept
+    let fs = common::open_fs(img_path);
+    assert_eq!(
+        modified_disabled,
+        fs.root_dir().entry("short.txt").unwrap().modification_time()
+    );
+}
+
+#[test]
+fn test_update_modified_time_fat16() {
+    test_update_modified_time(common::FAT16_IMG)
+}
+
+#[test]
+fn test_update_modified_time_fat32() {
+    test_update_modified_time(common::FAT32_IMG)
+}
+
+fn extent_test_content(seed: u8, len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i % 251) as u8 ^ seed).collect()
+}
+
+/// Concatenates the extents read directly from the image.
+fn read_extents(img: &[u8], extents: &[Extent]) -> Vec<u8> {
+    let mut data = Vec::new();
+    for e in extents {
+        data.extend_from_slice(&img[e.offset as usize..(e.offset + e.len) as usize]);
+    }
+    data
+}
+
+fn test_extents(img_path: &str) {
+    let state = StreamState::from_img(img_path);
+    let fs = common::remount(&state);
+    let cluster_size = fs.cluster_size() as usize;
+    let root_dir = fs.root_dir();
+    // allocation order is `a b a b a b a a a` and the last cluster of `a.bin` is partially used
+    let content_a = extent_test_content(0x5A, 6 * cluster_size + 100);
+    let content_b = extent_test_content(0xA5, 3 * cluster_size);
+    let mut a = root_dir.create_file("a.bin").unwrap();
+    let mut b = root_dir.create_file("b.bin").unwrap();
+    for i in 0..3 {
+        a.write_all(&content_a[i * cluster_size..(i + 1) * cluster_size])
+            .unwrap();
+        b.write_all(&content_b[i * cluster_size..(i + 1) * cluster_size])
+            .unwrap();
+    }
+    a.write_all(&content_a[3 * cluster_size..]).unwrap();
+    drop(b);
+
+    let extents_a = a.extents().collect::<Result<Vec<_>, _>>().unwrap();
+    let lens = extents_a.iter().map(|e| e.len as usize).collect::<Vec<_>>();
+    assert_eq!(lens, [cluster_size, cluster_size, cluster_size, 3 * cluster_size + 100]);
+    // extents are not adjacent
+    for w in extents_a.windows(2) {
+        assert_ne!(w[0].offset + w[0].len, w[1].offset);
+    }
+    let extents_b = root_dir
+        .open_file("b.bin")
+        .unwrap()
+        .extents()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    assert_eq!(extents_b.len(), 3);
+
+    // clusters allocated beyond the file size are not included
+    a.seek(io::SeekFrom::Start(0)).unwrap();
+    a.truncate().unwrap();
+    a.write_all(&content_a[..10]).unwrap();
+    assert!(a.preallocate(5 * cluster_size as u64, false).unwrap());
+    let extents_short = a.extents().collect::<Result<Vec<_>, _>>().unwrap();
+    assert_eq!(extents_short.len(), 1);
+    assert_eq!(extents_short[0].len, 10);
+    a.seek(io::SeekFrom::Start(0)).unwrap();
+    a.truncate().unwrap();
+    a.write_all(&content_a).unwrap();
+    let extents_a = a.extents().collect::<Result<Vec<_>, _>>().unwrap();
+    drop(a);
+
+    // an empty file has no extents
+    let empty = root_dir.create_file("empty.bin").unwrap();
+    assert_eq!(empty.extents().count(), 0);
+    drop(empty);
+    drop(root_dir);
+    fs.unmount().unwrap();
+
+    let img = state.data();
+    assert_eq!(read_extents(&img, &extents_a), content_a);
+    assert_eq!(read_extents(&img, &extents_b), content_b);
+}
+
+#[test]
+fn test_extents_fat12() {
+    test_extents(common::FAT12_IMG)
+}
+
+#[test]
+fn test_extents_fat16() {
+    test_extents(common::FAT16_IMG)
+}
+
+#[test]
+fn test_extents_fat32() {
+    test_extents(common::FAT32_IMG)
+}
+
+fn multi_cluster_content(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i * 13 % 251) as u8).collect()
+}
+
+/// Returns the number of storage calls transferring more than a cluster.
+fn big_calls(calls: &[(u64, usize)], cluster_size: usize) -> usize {
+    calls.iter().filter(|(_, len)| *len > cluster_size).count()
+}
+
+fn test_contiguous_io(img_path: &str) {
+    let (fs, state) = common::open_instrumented_fs(img_path, FsOptions::new());
+    let cluster_size = fs.cluster_size() as usize;
+    let root_dir = fs.root_dir();
+    let content = multi_cluster_content(16 * cluster_size + 100);
+    let mut file = root_dir.create_file("big.bin").unwrap();
+    assert!(file.preallocate(content.len() as u64, false).unwrap());
+
+    // the data is written in a single call
+    state.clear_ops();
+    assert_eq!(fatfs::Write::write(&mut file, &content).unwrap(), content.len());
+    let write_lens: Vec<_> = state.writes().iter().map(|(_, len)| *len).collect();
+    assert_eq!(write_lens, [content.len()]);
+
+    // the data is read in a single call starting in the middle of a cluster
+    file.seek(io::SeekFrom::Start(10)).unwrap();
+    state.clear_ops();
+    let mut buf = vec![0; 64 * 1024];
+    assert_eq!(fatfs::Read::read(&mut file, &mut buf).unwrap(), content.len() - 10);
+    assert_eq!(&buf[..content.len() - 10], &content[10..]);
+    assert_eq!(big_calls(&state.reads(), cluster_size), 1);
+    assert_eq!(fatfs::Read::read(&mut file, &mut buf).unwrap(), 0);
+
+    // the position is correct after a multi-cluster transfer
+    file.seek(io::SeekFrom::Start(3 * cluster_size as u64 - 1)).unwrap();
+    assert_eq!(
+        fatfs::Read::read(&mut file, &mut buf[..2 * cluster_size + 2]).unwrap(),
+        2 * cluster_size + 2
+    );
+    assert_eq!(
+        &buf[..2 * cluster_size + 2],
+        &content[3 * cluster_size - 1..5 * cluster_size + 1]
+    );
+    file.write_all(b"xyz").unwrap();
+    let mut rest = Vec::new();
+    file.read_to_end(&mut rest).unwrap();
+    assert_eq!(rest, &content[5 * cluster_size + 4..]);
+    drop(file);
+
+    let mut expected = content.clone();
+    expected[5 * cluster_size + 1..5 * cluster_size + 4].copy_from_slice(b"xyz");
+    let mut read_content = Vec::new();
+    root_dir
+        .open_file("big.bin")
+        .unwrap()
+        .read_to_end(&mut read_content)
+        .unwrap();
+    assert_eq!(read_content, expected);
+}
+
+#[test]
+fn test_contiguous_io_fat12() {
+    test_contiguous_io(common::FAT12_IMG)
+}
+
+#[test]
+fn test_contiguous_io_fat16() {
+    test_contiguous_io(common::FAT16_IMG)
+}
+
+#[test]
+fn test_contiguous_io_fat32() {
+    test_contiguous_io(common::FAT32_IMG)
+}
+
+#[test]
+fn test_fragmented_io_stops_at_extent_end() {
+    let fs = common::open_fs(common::FAT16_IMG);
+    let cluster_size = fs.cluster_size() as usize;
+    let root_dir = fs.root_dir();
+    let content = multi_cluster_content(6 * cluster_size);
+    // clusters are allocated for both files alternately
+    let mut a = root_dir.create_file("a.bin").unwrap();
+    let mut b = root_dir.create_file("b.bin").unwrap();
+    for chunk in content.chunks(cluster_size) {
+        a.write_all(chunk).unwrap();
+        b.write_all(chunk).unwrap();
+    }
+    let extents = a.extents().collect::<Result<Vec<_>, _>>().unwrap();
+    assert!(extents.len() > 1);
+
+    // every read returns one extent
+    a.seek(io::SeekFrom::Start(0)).unwrap();
+    let mut buf = vec![0; content.len()];
+    let mut pos = 0;
+    for e in &extents {
+        assert_eq!(fatfs::Read::read(&mut a, &mut buf[pos..]).unwrap(), e.len as usize);
+        pos += e.len as usize;
+    }
+    assert_eq!(buf, content);
+
+    // a write is split at the extent end too
+    a.seek(io::SeekFrom::Start(1)).unwrap();
+    assert_eq!(
+        fatfs::Write::write(&mut a, &content).unwrap(),
+        extents[0].len as usize - 1
+    );
+    drop(a);
+    drop(b);
+    assert!(fs.check().unwrap().is_clean());
+}
+
+#[test]
+fn test_close_reports_flush_error() {
+    let (fs, state) = common::open_instrumented_fs(common::FAT16_IMG, FsOptions::new());
+    let mut file = fs.root_dir().open_file("short.txt").unwrap();
+    file.seek(io::SeekFrom::End(0)).unwrap();
+    file.write_all(b"more data").unwrap();
+    // the data is on the storage but writing the directory entry fails
+    state.set_write_fault(WriteFault::FailAfterBytes(0));
+    state.clear_ops();
+    assert!(matches!(file.close(), Err(fatfs::Error::Io(_))));
+    // the failed write is not retried when the handle is dropped
+    assert_eq!(state.writes().len(), 1);
+}
+
+#[test]
+fn test_drop_ignores_flush_error() {
+    let (fs, state) = common::open_instrumented_fs(common::FAT16_IMG, FsOptions::new());
+    let mut file = fs.root_dir().open_file("short.txt").unwrap();
+    file.seek(io::SeekFrom::End(0)).unwrap();
+    file.write_all(b"more data").unwrap();
+    state.set_write_fault(WriteFault::FailAfterBytes(0));
+    state.clear_ops();
+    drop(file);
+    assert_eq!(state.writes().len(), 1);
+}
+
+#[test]
+fn test_close_reports_partial_write() {
+    let (fs, state) = common::open_instrumented_fs(common::FAT16_IMG, FsOptions::new());
+    let mut file = fs.root_dir().open_file("short.txt").unwrap();
+    file.seek(io::SeekFrom::End(0)).unwrap();
+    file.write_all(b"more data").unwrap();
+    // the entry is written only partially
+    state.set_write_fault(WriteFault::FailAfterBytes(10));
+    assert!(matches!(file.close(), Err(fatfs::Error::Io(_))));
+}
+
+#[test]
+fn test_close_after_flush() {
+    let (fs, state) = common::open_instrumented_fs(common::FAT16_IMG, FsOptions::new());
+    let root_dir = fs.root_dir();
+    let mut file = root_dir.open_file("short.txt").unwrap();
+    file.seek(io::SeekFrom::End(0)).unwrap();
+    file.write_all(b"more data").unwrap();
+    file.flush().unwrap();
+    state.clear_ops();
+    file.close().unwrap();
+    assert_eq!(state.writes().len(), 0);
+
+    // a handle without changes is closed without writing anything
+    let file = root_dir.open_file("short.txt").unwrap();
+    file.close().unwrap();
+    assert_eq!(state.writes().len(), 0);
+    assert_eq!(root_dir.entry("short.txt").unwrap().len(), 14 + 9);
+}
+
+#[test]
+fn test_close_does_not_affect_other_handles() {
+    let fs = common::open_fs(common::FAT16_IMG);
+    let root_dir = fs.root_dir();
+    let mut file = root_dir.open_file("short.txt").unwrap();
+    let mut other = file.clone();
+    file.seek(io::SeekFrom::End(0)).unwrap();
+    file.write_all(b"!").unwrap();
+    file.close().unwrap();
+    other.seek(io::SeekFrom::End(0)).unwrap();
+    other.write_all(b"?").unwrap();
+    other.close().unwrap();
+    let mut content = String::new();
+    root_dir
+        .open_file("short.txt")
+        .unwrap()
+        .read_to_string(&mut content)
+        .unwrap();
+    assert_eq!(content, "Rust is cool!\n!?");
+}
+
+type InstrumentedFile<'a> = fatfs::File<'a, common::InstrumentedStorage, DefaultTimeProvider, LossyOemCpConverter>;
+
+/// Formats a 64 MB volume with the smallest cluster size keeping the cluster count in the range of the FAT type.
+fn format_alloc_hint_volume(fat_type: FatType) -> Rc<StreamState> {
+    let mut storage = io::Cursor::new(vec![0_u8; 64 * 1024 * 1024]);
+    let bytes_per_cluster = if fat_type == FatType::Fat32 { 512 } else { 2048 };
+    let options = FormatVolumeOptions::new()
+        .fat_type(fat_type)
+        .bytes_per_cluster(bytes_per_cluster);
+    fatfs::format_volume(&mut StdIoWrapper::new(&mut storage), options).unwrap();
+    StreamState::new(storage.into_inner())
+}
+
+/// Appends `count` clusters to `file` one by one and returns the number of bytes read from the FATs.
+fn append_clusters(file: &mut InstrumentedFile, cluster_size: u32, count: usize, state: &StreamState) -> u64 {
+    let fat_region = Layout::read(&state.data.borrow()).fat_region();
+    let cluster = vec![0x5A; cluster_size as usize];
+    state.clear_ops();
+    for _ in 0..count {
+        file.write_all(&cluster).unwrap();
+    }
+    state
+        .reads()
+        .iter()
+        .filter(|(offset, _)| fat_region.contains(offset))
+        .map(|(_, len)| *len as u64)
+        .sum()
+}
+
+fn test_alloc_reads_are_linear(fat_type: FatType) {
+    const BATCH: usize = 1000;
+    let state = format_alloc_hint_volume(fat_type);
+    let fs = common::remount(&state);
+    assert_eq!(fs.fat_type(), fat_type);
+    let mut file = fs.root_dir().create_file("big.bin").unwrap();
+    let first = append_clusters(&mut file, fs.cluster_size(), BATCH, &state);
+    let second = append_clusters(&mut file, fs.cluster_size(), BATCH, &state);
+    let third = append_clusters(&mut file, fs.cluster_size(), BATCH, &state);
+    // searching from the beginning of the FAT would make every batch read more than the previous one
+    assert!(first > 0);
+    assert!(second <= first + first / 10, "{} bytes read after {}", second, first);
+    assert!(third <= first + first / 10, "{} bytes read after {}", third, first);
+    drop(file);
+    fs.unmount().unwrap();
+}
+
+#[test]
+fn test_alloc_reads_are_linear_fat16() {
+    test_alloc_reads_are_linear(FatType::Fat16)
+}
+
+#[test]
+fn test_alloc_reads_are_linear_fat32() {
+    test_alloc_reads_are_linear(FatType::Fat32)
+}
+
+#[test]
+fn test_alloc_hint_is_loaded_from_fs_info() {
+    const COUNT: usize = 2000;
+    let state = format_alloc_hint_volume(FatType::Fat32);
+    {
+        let fs = common::remount(&state);
+        let mut file = fs.root_dir().create_file("big.bin").unwrap();
+        append_clusters(&mut file, fs.cluster_size(), COUNT, &state);
+        drop(file);
+        fs.unmount().unwrap();
+    }
+
+    // after remounting the search starts at the stored hint instead of skipping all allocated clusters
+    let fs = common::remount(&state);
+    let mut file = fs.root_dir().create_file("next.bin").unwrap();
+    let read_bytes = append_clusters(&mut file, fs.cluster_size(), 1, &state);
+    assert!(
+        read_bytes < COUNT as u64 * 4,
+        "{} bytes read for a single allocation",
+        read_bytes
+    );
+    drop(file);
+    fs.unmount().unwrap();
+}